@@ -0,0 +1,73 @@
+//! The cost of the handle system and of dispatching events to handlers, on
+//! a compositor running on the headless backend.
+//!
+//! Run with `cargo bench --features testing --bench dispatch`.
+
+extern crate criterion;
+extern crate wlroots;
+
+use std::{sync::atomic::{AtomicUsize, Ordering}, time::Duration};
+
+use criterion::{black_box, Criterion};
+use wlroots::{backend::Backend, compositor, output, testing::{self, Harness}};
+
+/// How many frame events the output handler got.
+static FRAMES: AtomicUsize = AtomicUsize::new(0);
+
+struct BenchOutput;
+
+impl output::Handler for BenchOutput {
+    fn on_frame(&mut self, _: compositor::Handle, _: output::Handle) {
+        FRAMES.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+fn output_added<'output>(_: compositor::Handle,
+                         builder: output::Builder<'output>)
+                         -> Option<output::BuilderResult<'output>> {
+    Some(builder.build_best_mode(BenchOutput))
+}
+
+fn handle_upgrades(c: &mut Criterion, harness: &mut Harness) {
+    let output = harness.add_output(800, 600).expect("Could not add an output");
+    c.bench_function("upgrade a handle", move |b| {
+        b.iter(|| output.run(|output| black_box(output.scale())))
+    });
+    let removed = harness.add_output(800, 600).expect("Could not add an output");
+    compositor::handle().expect("The compositor is not running")
+                        .run(|compositor| match *compositor.backend() {
+                                 Backend::Headless(ref headless) => {
+                                     headless.remove_output(&removed)
+                                 },
+                                 _ => false
+                             })
+                        .expect("Could not remove the output");
+    harness.dispatch(Duration::from_millis(0));
+    c.bench_function("upgrade a handle to a destroyed output", move |b| {
+        b.iter(|| black_box(removed.run(|output| output.scale()).is_err()))
+    });
+}
+
+fn listener_dispatch(c: &mut Criterion, harness: &mut Harness) {
+    let output = harness.add_output(800, 600).expect("Could not add an output");
+    c.bench_function("dispatch a frame event", |b| {
+        b.iter(|| {
+                   let frames = FRAMES.load(Ordering::Relaxed);
+                   while FRAMES.load(Ordering::Relaxed) == frames {
+                       output.run(|output| output.schedule_frame()).unwrap();
+                       harness.dispatch(Duration::from_millis(0));
+                   }
+               })
+    });
+}
+
+fn main() {
+    let output_builder = output::manager::Builder::default().output_added(output_added);
+    let builder = compositor::Builder::new().output_manager(output_builder);
+    testing::run(builder, (), |harness| {
+        let mut c = Criterion::default().configure_from_args();
+        handle_upgrades(&mut c, harness);
+        listener_dispatch(&mut c, harness);
+        c.final_summary();
+    });
+}
@@ -0,0 +1,42 @@
+//! Allocating listener structs in bursts, as when a client opens hundreds of
+//! tooltips at once, with and without a `utils::Pool`.
+
+#[macro_use]
+extern crate criterion;
+extern crate wlroots;
+
+use criterion::{black_box, Criterion};
+use wlroots::utils::Pool;
+
+/// About as big as the listeners of an XDG shell surface: a few pointers of
+/// data, and twelve `wl_listener`s of three pointers each.
+type Listeners = [usize; 40];
+
+/// How many surfaces come and go at once.
+const BURST: usize = 500;
+
+fn boxed(c: &mut Criterion) {
+    c.bench_function("box a burst of listeners", |b| {
+        b.iter(|| {
+                   let burst: Vec<Box<Listeners>> =
+                       (0..BURST).map(|i| Box::new([i; 40])).collect();
+                   drop(black_box(burst))
+               })
+    });
+}
+
+fn pooled(c: &mut Criterion) {
+    let pool = Pool::new(BURST);
+    c.bench_function("pool a burst of listeners", move |b| {
+        b.iter(|| {
+                   let burst: Vec<Box<Listeners>> =
+                       (0..BURST).map(|i| pool.boxed([i; 40])).collect();
+                   for listeners in black_box(burst) {
+                       pool.release(listeners)
+                   }
+               })
+    });
+}
+
+criterion_group!(benches, boxed, pooled);
+criterion_main!(benches);
@@ -0,0 +1,87 @@
+//! The matrix math and damage region operations done for every rendered
+//! frame.
+
+#[macro_use]
+extern crate criterion;
+extern crate wlroots;
+
+use criterion::{black_box, Criterion};
+use wlroots::{area::{Area, Origin, Size},
+              render::{matrix, PixmanRegion},
+              wlroots_sys::wl_output_transform::{self, *}};
+
+/// The transform of a rotated output, so the transform isn't a no-op.
+const TRANSFORM: wl_output_transform = WL_OUTPUT_TRANSFORM_90;
+
+fn matrix_math(c: &mut Criterion) {
+    let projection = matrix::projection([0.0; 9], 1920, 1080, TRANSFORM);
+    let area = Area::new(Origin::new(100, 200), Size::new(640, 480));
+    c.bench_function("project a box", move |b| {
+        b.iter(|| matrix::project_box(black_box(area), TRANSFORM, 0.5, projection))
+    });
+    c.bench_function("multiply matrices", move |b| {
+        let translate = matrix::translate(100.0, 200.0);
+        let scale = matrix::scale(2.0, 2.0);
+        b.iter(|| matrix::multiply(black_box(translate), black_box(scale)))
+    });
+    c.bench_function("compute an output projection", |b| {
+        b.iter(|| matrix::projection([0.0; 9], black_box(1920), black_box(1080), TRANSFORM))
+    });
+}
+
+/// Damage as it comes in from a busy frame: a grid of small rectangles that
+/// overlap a little, e.g. from a terminal redrawing its cells.
+fn scattered_damage() -> PixmanRegion {
+    let mut region = PixmanRegion::new();
+    for row in 0..30 {
+        for column in 0..40 {
+            region.rectangle(column * 48, row * 36, 50, 38);
+        }
+    }
+    region
+}
+
+fn damage_regions(c: &mut Criterion) {
+    c.bench_function("accumulate damage", |b| b.iter(|| black_box(scattered_damage())));
+    c.bench_function("merge damage", |b| {
+        let damage = scattered_damage();
+        let mut previous = PixmanRegion::new();
+        previous.rectangle(200, 100, 800, 600);
+        b.iter(|| {
+                   let mut region = PixmanRegion::new();
+                   region.union(&damage);
+                   region.union(&previous);
+                   black_box(region)
+               })
+    });
+    c.bench_function("clip damage to an output", |b| {
+        let damage = scattered_damage();
+        let mut output = PixmanRegion::new();
+        output.rectangle(960, 0, 960, 1080);
+        b.iter(|| {
+                   let mut region = PixmanRegion::new();
+                   region.union(&damage);
+                   region.intersect(&output);
+                   region.translate(-960, 0);
+                   black_box(region)
+               })
+    });
+    c.bench_function("subtract opaque areas from damage", |b| {
+        let damage = scattered_damage();
+        let mut opaque = PixmanRegion::new();
+        opaque.rectangle(0, 0, 1280, 720);
+        b.iter(|| {
+                   let mut region = PixmanRegion::new();
+                   region.union(&damage);
+                   region.subtract(&opaque);
+                   black_box(region.is_empty())
+               })
+    });
+    c.bench_function("list damaged rectangles", |b| {
+        let damage = scattered_damage();
+        b.iter(|| black_box(damage.rectangles()))
+    });
+}
+
+criterion_group!(benches, matrix_math, damage_regions);
+criterion_main!(benches);
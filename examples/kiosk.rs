@@ -0,0 +1,144 @@
+//! A kiosk compositor, which shows a single fullscreen shell client on
+//! every output.
+//!
+//! Run a client that speaks the fullscreen shell protocol against it,
+//! e.g. `weston-simple-shm` or a kiosk browser.
+
+#[macro_use]
+extern crate wlroots;
+
+use wlroots::{area::{Area, Origin, Size},
+              compositor,
+              extensions::fullscreen_shell::{self, PresentMethod},
+              input::{self, keyboard},
+              output,
+              render::matrix,
+              surface,
+              utils::{current_time, log::{init_logging, WLR_DEBUG}}};
+use wlroots::xkbcommon::xkb::keysyms::KEY_Escape;
+use wlroots::wlroots_dehandle;
+
+/// A surface a client asked to present.
+struct Presented {
+    surface: surface::Handle,
+    method: PresentMethod,
+    /// The output to present on, or `None` for all of them.
+    output: Option<output::Handle>
+}
+
+#[derive(Default)]
+struct State {
+    presented: Vec<Presented>,
+    shell: Option<fullscreen_shell::Manager>
+}
+
+struct FullscreenShell;
+
+impl fullscreen_shell::Handler for FullscreenShell {
+    #[wlroots_dehandle(compositor)]
+    fn present_surface(&mut self,
+                       compositor_handle: compositor::Handle,
+                       surface: Option<surface::Handle>,
+                       method: PresentMethod,
+                       output: Option<output::Handle>) {
+        use compositor_handle as compositor;
+        let state: &mut State = compositor.downcast();
+        // NOTE Presenting replaces whatever was on the same outputs.
+        state.presented.retain(|presented| {
+                                   output.is_some() && presented.output.is_some() &&
+                                   presented.output != output
+                               });
+        if let Some(surface) = surface {
+            wlr_log!(WLR_DEBUG, "Presenting {:?} with {:?}", surface, method);
+            state.presented.push(Presented { surface, method, output });
+        }
+    }
+}
+
+struct KioskOutput;
+
+impl output::Handler for KioskOutput {
+    #[wlroots_dehandle(compositor, output)]
+    fn on_frame(&mut self, compositor_handle: compositor::Handle, output_handle: output::Handle) {
+        let this_output = Some(output_handle.clone());
+        use compositor_handle as compositor;
+        use output_handle as output;
+        let state: &mut State = compositor.data.downcast_mut().unwrap();
+        let renderer = compositor.renderer
+                                 .as_mut()
+                                 .expect("Compositor was not loaded with a renderer");
+        let mut renderer = renderer.render(output, None).expect("Could not render to the output");
+        renderer.clear([0.0, 0.0, 0.0, 1.0]);
+        let (output_width, output_height) = renderer.output.effective_resolution();
+        for presented in &state.presented {
+            if presented.output.is_some() && presented.output != this_output {
+                continue
+            }
+            let surface_handle = &presented.surface;
+            with_handles!([(surface: {surface_handle})] => {
+                let (width, height) = surface.current_state().size();
+                let render_box = match presented.method {
+                    PresentMethod::Default | PresentMethod::Center => {
+                        Area::new(Origin::new((output_width - width) / 2,
+                                              (output_height - height) / 2),
+                                  Size::new(width, height))
+                    },
+                    // NOTE For brevity the zoom methods don't keep the
+                    // aspect ratio of the surface.
+                    _ => Area::new(Origin::new(0, 0), Size::new(output_width, output_height))
+                };
+                let scale = renderer.output.scale() as i32;
+                let render_box = Area::new(Origin::new(render_box.origin.x * scale,
+                                                       render_box.origin.y * scale),
+                                           Size::new(render_box.size.width * scale,
+                                                     render_box.size.height * scale));
+                let transform = renderer.output.get_transform().invert();
+                let matrix = matrix::project_box(render_box,
+                                                 transform,
+                                                 0.0,
+                                                 renderer.output.transform_matrix());
+                renderer.render_surface(surface, matrix);
+                surface.send_frame_done(current_time());
+            }).ok();
+        }
+    }
+}
+
+fn output_added<'output>(_: compositor::Handle,
+                         builder: output::Builder<'output>)
+                         -> Option<output::BuilderResult<'output>> {
+    Some(builder.build_best_mode(KioskOutput))
+}
+
+struct KioskKeyboard;
+
+impl keyboard::Handler for KioskKeyboard {
+    fn on_key(&mut self, _: compositor::Handle, _: keyboard::Handle, key: &keyboard::event::Key) {
+        if key.pressed_keys().contains(&KEY_Escape) {
+            compositor::terminate()
+        }
+    }
+}
+
+fn keyboard_added(_: compositor::Handle,
+                  _: keyboard::Handle)
+                  -> Option<Box<keyboard::Handler>> {
+    Some(Box::new(KioskKeyboard))
+}
+
+fn main() {
+    init_logging(WLR_DEBUG, None);
+    let output_builder = output::manager::Builder::default().output_added(output_added);
+    let input_builder = input::manager::Builder::default().keyboard_added(keyboard_added);
+    let mut compositor = compositor::Builder::new().gles2(true)
+                                                   .wl_shm(true)
+                                                   .input_manager(input_builder)
+                                                   .output_manager(output_builder)
+                                                   .build_auto(State::default());
+    let shell = fullscreen_shell::Manager::create(&mut compositor, Box::new(FullscreenShell));
+    {
+        let state: &mut State = compositor.downcast();
+        state.shell = Some(shell);
+    }
+    compositor.run();
+}
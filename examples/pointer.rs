@@ -53,7 +53,7 @@ fn output_added<'output>(compositor_handle: compositor::Handle,
                         (output: {&mut result.output})] => {
             layout.add_auto(output);
             cursor.attach_output_layout(layout);
-            xcursor_manager.load(output.scale());
+            xcursor_manager.load(output.scale()).ok();
             xcursor_manager.set_cursor_image("left_ptr".to_string(), cursor);
             let (x, y) = cursor.coords();
             // https://en.wikipedia.org/wiki/Mouse_warping
@@ -151,7 +151,8 @@ impl output::Handler for ExOutput {
             let compositor_state: &mut CompositorState = compositor.data.downcast_mut().unwrap();
             let renderer = compositor.renderer.as_mut()
                 .expect("Compositor was not loaded with a renderer");
-            let mut render_context = renderer.render(output, None);
+            let mut render_context = renderer.render(output, None)
+                .expect("Could not render to the output");
             render_context.clear(compositor_state.color);
         }).unwrap();
     }
@@ -176,11 +177,11 @@ fn keyboard_added(_compositor_handle: compositor::Handle,
 }
 
 fn load_xcursor() -> (xcursor::Manager, cursor::Handle) {
-    let cursor_handle = Cursor::create(Box::new(ExCursor));
+    let cursor_handle = Cursor::create_with(ExCursor).expect("Could not create cursor");
     let mut xcursor_manager =
         xcursor::Manager::create("default".to_string(), 24).expect("Could not create xcursor \
                                                                   manager");
-    xcursor_manager.load(1.0);
+    xcursor_manager.load(1.0).expect("Could not load the cursor theme");
     cursor_handle.run(|c| xcursor_manager.set_cursor_image("left_ptr".to_string(), c))
           .unwrap();
     (xcursor_manager, cursor_handle)
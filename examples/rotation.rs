@@ -87,7 +87,8 @@ impl output::Handler for ExOutput {
                 .unwrap();
             let delta_time_in_seconds = compositor_state.register_frame();
             let transform_matrix = output.transform_matrix();
-            let mut renderer = renderer.render(output, None);
+            let mut renderer = renderer.render(output, None)
+                .expect("Could not render to the output");
             let cat_texture = compositor_state.cat_texture.as_ref().unwrap();
             let (max_width, max_height) = (CAT_TEXTURE_WIDTH as i32, CAT_TEXTURE_HEIGHT as i32);
             for y in (-max_height + compositor_state.offset.y as i32..output_height).step_by(max_height as usize) {
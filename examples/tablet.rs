@@ -193,7 +193,8 @@ impl output::Handler for OutputEx {
             let renderer = compositor.renderer
                 .as_mut()
                 .expect("Compositor was not loaded with a renderer");
-            let mut renderer = renderer.render(output, None);
+            let mut renderer = renderer.render(output, None)
+                .expect("Could not render to the output");
             renderer.clear([0.25, 0.25, 0.25, 1.0]);
             let tool_color: [f32; 4] = if state.button {
                 state.tool_color.clone()
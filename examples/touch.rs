@@ -64,7 +64,8 @@ impl output::Handler for ExOutput {
             // NOTE gl functions will probably always be unsafe.
             let (width, height) = output.effective_resolution();
             let transform_matrix = output.transform_matrix();
-            let mut renderer = renderer.render(output, None);
+            let mut renderer = renderer.render(output, None)
+                .expect("Could not render to the output");
             renderer.clear([0.25, 0.25, 0.25, 1.0]);
             let cat_texture = state.cat_texture.as_mut().unwrap();
             let (cat_width, cat_height) = cat_texture.size();
@@ -1,8 +1,6 @@
 #[macro_use]
 extern crate wlroots;
 
-use std::{thread, process::Command};
-
 use wlroots::{area::{Area, Origin, Size},
               compositor,
               cursor::{self, Cursor, xcursor},
@@ -118,7 +116,7 @@ fn output_added<'output>(compositor: compositor::Handle,
         use cursor as cursor;
         layout.add_auto(output);
         cursor.attach_output_layout(layout);
-        xcursor_manager.load(output.scale());
+        xcursor_manager.load(output.scale()).ok();
         xcursor_manager.set_cursor_image("left_ptr".to_string(), cursor);
         let (x, y) = cursor.coords();
         // https://en.wikipedia.org/wiki/Mouse_warping
@@ -138,9 +136,7 @@ impl keyboard::Handler for ExKeyboardHandler {
                 compositor::terminate();
             } else if key_event.key_state() == WLR_KEY_PRESSED {
                 if key == KEY_F1 {
-                    thread::spawn(move || {
-                        Command::new("weston-terminal").output().unwrap();
-                    });
+                    compositor::spawn("weston-terminal").ok();
                     return
                 }
             }
@@ -219,7 +215,8 @@ impl output::Handler for ExOutput {
         let renderer = compositor.renderer
             .as_mut()
             .expect("Compositor was not loaded with a renderer");
-        let mut render_context = renderer.render(output, None);
+        let mut render_context = renderer.render(output, None)
+            .expect("Could not render to the output");
         render_context.clear([0.25, 0.25, 0.25, 1.0]);
         render_shells(state, &mut render_context)
     }
@@ -249,11 +246,11 @@ fn keyboard_added(compositor: compositor::Handle,
 
 fn main() {
     init_logging(WLR_DEBUG, None);
-    let cursor = Cursor::create(Box::new(CursorEx));
+    let cursor = Cursor::create(Box::new(CursorEx)).expect("Could not create cursor");
     let mut xcursor_manager =
         xcursor::Manager::create("default".to_string(), 24).expect("Could not create xcursor \
                                                                     manager");
-    xcursor_manager.load(1.0);
+    xcursor_manager.load(1.0).expect("Could not load the cursor theme");
     cursor.run(|c| xcursor_manager.set_cursor_image("left_ptr".to_string(), c))
         .unwrap();
     let layout = Layout::create(Box::new(OutputLayoutEx));
@@ -274,7 +271,7 @@ fn main() {
 
     {
         let seat_handle =
-            Seat::create(&mut compositor, "seat0".into(), Box::new(SeatHandlerEx));
+            Seat::create_with(&mut compositor, "seat0".into(), SeatHandlerEx);
         seat_handle.run(|seat| {
                             seat.set_capabilities(seat::Capability::all());
                         })
@@ -310,9 +307,7 @@ fn render_shells(state: &mut State, renderer: &mut Renderer) {
                                              0.0,
                                              renderer.output
                                              .transform_matrix());
-            if let Some(texture) = surface.texture().as_ref() {
-                renderer.render_texture_with_matrix(texture, matrix);
-            }
+            renderer.render_surface(surface, matrix);
             surface.send_frame_done(current_time());
         }
     }
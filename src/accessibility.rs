@@ -0,0 +1,110 @@
+//! Events about what the compositor shows, for assistive technologies such as
+//! screen readers to narrate.
+//!
+//! Anything can subscribe to the events, independent of how they reach the
+//! tool: over IPC (see the `subscribe` request of `ipc::Request`), over
+//! D-Bus, or from the compositor itself.
+//!
+//! Focusing a view with `View::activate` sends `Event::ViewFocused` on its
+//! own. Only the compositor knows about the rest, e.g. which surfaces are
+//! notifications, so it sends those with `publish`:
+//!
+//! ```rust,no_run,ignore
+//! let subscription = accessibility::subscribe(|event| speak(event));
+//!
+//! accessibility::publish(Event::WorkspaceChanged { name: "2".into(),
+//!                                                  output: Some("DP-1".into()) });
+//! ```
+
+use std::{cell::{Cell, RefCell}, rc::Rc};
+
+use {utils::HandleResult, view::View};
+
+/// Something about the state of the compositor that changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "ipc", derive(Serialize))]
+#[cfg_attr(feature = "ipc", serde(tag = "event", rename_all = "snake_case"))]
+pub enum Event {
+    /// A view got the keyboard focus.
+    ViewFocused {
+        title: Option<String>,
+        app_id: Option<String>
+    },
+    /// Another workspace is shown, on the output if it's known.
+    WorkspaceChanged {
+        name: String,
+        output: Option<String>
+    },
+    /// A notification was shown.
+    NotificationMapped {
+        /// The application the notification is from.
+        app_id: Option<String>,
+        /// The text of the notification, if the compositor knows it.
+        text: Option<String>
+    }
+}
+
+impl Event {
+    /// Make the event for the view getting the focus.
+    pub fn view_focused(view: &View) -> HandleResult<Self> {
+        Ok(Event::ViewFocused { title: view.title()?,
+                                app_id: view.app_id()? })
+    }
+}
+
+type Subscriber = Rc<RefCell<Box<FnMut(&Event)>>>;
+
+thread_local! {
+    static SUBSCRIBERS: RefCell<Vec<(u64, Subscriber)>> = RefCell::new(Vec::new());
+    static NEXT_ID: Cell<u64> = Cell::new(0);
+}
+
+/// Receives the events until it's dropped.
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct Subscription {
+    id: u64
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        let id = self.id;
+        SUBSCRIBERS.with(|subscribers| {
+                             subscribers.borrow_mut().retain(|&(other, _)| other != id)
+                         })
+    }
+}
+
+/// Call the function with every event that's published from now on, until
+/// the subscription is dropped.
+pub fn subscribe<F>(subscriber: F) -> Subscription
+    where F: FnMut(&Event) + 'static
+{
+    let id = NEXT_ID.with(|next_id| {
+                              let id = next_id.get();
+                              next_id.set(id + 1);
+                              id
+                          });
+    let subscriber: Box<FnMut(&Event)> = Box::new(subscriber);
+    SUBSCRIBERS.with(|subscribers| {
+                         subscribers.borrow_mut().push((id, Rc::new(RefCell::new(subscriber))))
+                     });
+    Subscription { id }
+}
+
+/// Send the event to every subscriber, in the order they subscribed.
+///
+/// Subscribers may subscribe and unsubscribe while the event is sent, but
+/// an event published by a subscriber itself is not sent to it again.
+pub fn publish(event: Event) {
+    let subscribers = SUBSCRIBERS.with(|subscribers| {
+        subscribers.borrow()
+                   .iter()
+                   .map(|&(_, ref subscriber)| subscriber.clone())
+                   .collect::<Vec<_>>()
+    });
+    for subscriber in subscribers {
+        if let Ok(mut subscriber) = subscriber.try_borrow_mut() {
+            (&mut **subscriber)(&event)
+        }
+    }
+}
@@ -0,0 +1,430 @@
+//! Animating values (e.g. the position of a view or the opacity of a closed
+//! window) from the frame events of the outputs they're shown on.
+//!
+//! An `Animator` keeps the running animations. Tick it when an output renders
+//! a frame, then read the values to draw that frame with. As long as an
+//! animation on the output is running another frame is scheduled on it, so
+//! the output only keeps rendering while something moves:
+//!
+//! ```rust,no_run,ignore
+//! let fade = animator.start(&[output_handle], Duration::from_millis(200), Ease::OutCubic);
+//!
+//! fn on_frame(&mut self, compositor: compositor::Handle, output: output::Handle) {
+//!     with_handles!([(output: {output})] => {
+//!         self.animator.tick(output);
+//!         let alpha = self.animator.value(fade, 1.0, 0.0).unwrap_or(0.0);
+//!         // Render with alpha...
+//!         for finished in self.animator.take_finished() {
+//!             // Forget about the window...
+//!         }
+//!     }).unwrap();
+//! }
+//! ```
+//!
+//! All animations share one clock: the time of the frame event of the output
+//! that's ticked, so every output shows the value for when its frame started
+//! rather than for when it got around to rendering. An animation starts at
+//! its first frame, so its first frame always shows the start value.
+//!
+//! An animation whose outputs were all destroyed is finished right away, so
+//! it still shows up in `take_finished`.
+
+use std::time::{Duration, Instant};
+
+use {area::{Area, Origin, Size}, output::{self, Output}, utils::{self, HandleErr}};
+
+/// How the progress of an animation speeds up and slows down over its
+/// duration.
+#[derive(Debug, Clone, Copy)]
+pub enum Ease {
+    Linear,
+    InQuad,
+    OutQuad,
+    InOutQuad,
+    InCubic,
+    OutCubic,
+    InOutCubic,
+    /// A cubic Bézier curve from (0, 0) to (1, 1) with the two control
+    /// points (x1, y1) and (x2, y2), like `cubic-bezier` in CSS.
+    CubicBezier(f64, f64, f64, f64),
+    /// Maps the elapsed part of the duration (from 0 to 1) to the progress.
+    Custom(fn(f64) -> f64)
+}
+
+impl Ease {
+    /// Get the progress of the animation once the part `t` (from 0 to 1) of
+    /// its duration elapsed.
+    pub fn apply(&self, t: f64) -> f64 {
+        let t = t.max(0.0).min(1.0);
+        match *self {
+            Ease::Linear => t,
+            Ease::InQuad => t * t,
+            Ease::OutQuad => t * (2.0 - t),
+            Ease::InOutQuad => {
+                if t < 0.5 {
+                    2.0 * t * t
+                } else {
+                    -1.0 + (4.0 - 2.0 * t) * t
+                }
+            },
+            Ease::InCubic => t * t * t,
+            Ease::OutCubic => {
+                let t = t - 1.0;
+                t * t * t + 1.0
+            },
+            Ease::InOutCubic => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    let t = 2.0 * t - 2.0;
+                    t * t * t / 2.0 + 1.0
+                }
+            },
+            Ease::CubicBezier(x1, y1, x2, y2) => cubic_bezier(x1, y1, x2, y2, t),
+            Ease::Custom(f) => f(t)
+        }
+    }
+}
+
+impl Default for Ease {
+    fn default() -> Self {
+        Ease::Linear
+    }
+}
+
+/// Find the y of the curve at x = `t`, by solving for the parameter of the
+/// curve with Newton's method and falling back to bisection.
+fn cubic_bezier(x1: f64, y1: f64, x2: f64, y2: f64, t: f64) -> f64 {
+    let bezier = |a: f64, b: f64, s: f64| {
+        3.0 * a * s * (1.0 - s) * (1.0 - s) + 3.0 * b * s * s * (1.0 - s) + s * s * s
+    };
+    let slope = |a: f64, b: f64, s: f64| {
+        3.0 * a * (1.0 - s) * (1.0 - s) + 6.0 * (b - a) * s * (1.0 - s) + 3.0 * (1.0 - b) * s * s
+    };
+    let mut s = t;
+    for _ in 0..8 {
+        let error = bezier(x1, x2, s) - t;
+        if error.abs() < 1e-6 {
+            return bezier(y1, y2, s)
+        }
+        let d = slope(x1, x2, s);
+        if d.abs() < 1e-6 {
+            break
+        }
+        s -= error / d;
+    }
+    let (mut low, mut high) = (0.0, 1.0);
+    s = t;
+    for _ in 0..32 {
+        let x = bezier(x1, x2, s);
+        if (x - t).abs() < 1e-6 {
+            break
+        }
+        if x < t {
+            low = s;
+        } else {
+            high = s;
+        }
+        s = (low + high) / 2.0;
+    }
+    bezier(y1, y2, s)
+}
+
+/// Values that can be animated, by blending between two of them.
+pub trait Interpolate: Sized {
+    /// Get the value the part `t` of the way from `self` to `to`.
+    ///
+    /// `t` can be outside of 0 to 1 for eases that overshoot.
+    fn interpolate(&self, to: &Self, t: f64) -> Self;
+}
+
+impl Interpolate for f64 {
+    fn interpolate(&self, to: &Self, t: f64) -> Self {
+        self + (to - self) * t
+    }
+}
+
+impl Interpolate for f32 {
+    fn interpolate(&self, to: &Self, t: f64) -> Self {
+        self + (to - self) * t as f32
+    }
+}
+
+impl Interpolate for i32 {
+    fn interpolate(&self, to: &Self, t: f64) -> Self {
+        (*self as f64).interpolate(&(*to as f64), t).round() as i32
+    }
+}
+
+/// Colors, as given to the renderer.
+impl Interpolate for [f32; 4] {
+    fn interpolate(&self, to: &Self, t: f64) -> Self {
+        [self[0].interpolate(&to[0], t),
+         self[1].interpolate(&to[1], t),
+         self[2].interpolate(&to[2], t),
+         self[3].interpolate(&to[3], t)]
+    }
+}
+
+impl Interpolate for Origin {
+    fn interpolate(&self, to: &Self, t: f64) -> Self {
+        Origin::new(self.x.interpolate(&to.x, t), self.y.interpolate(&to.y, t))
+    }
+}
+
+impl Interpolate for Size {
+    fn interpolate(&self, to: &Self, t: f64) -> Self {
+        Size::new(self.width.interpolate(&to.width, t),
+                  self.height.interpolate(&to.height, t))
+    }
+}
+
+impl Interpolate for Area {
+    fn interpolate(&self, to: &Self, t: f64) -> Self {
+        Area::new(self.origin.interpolate(&to.origin, t),
+                  self.size.interpolate(&to.size, t))
+    }
+}
+
+/// Identifies an animation of an `Animator`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AnimationId(u64);
+
+#[derive(Debug)]
+struct Animation {
+    id: AnimationId,
+    /// The outputs the animation is shown on.
+    outputs: Vec<output::Handle>,
+    duration: Duration,
+    ease: Ease,
+    /// When the first frame of the animation was rendered.
+    start: Option<Instant>,
+    elapsed: Duration
+}
+
+impl Animation {
+    fn is_finished(&self) -> bool {
+        self.elapsed >= self.duration || self.is_orphaned()
+    }
+
+    /// Determines if all the outputs of the animation were destroyed, so it
+    /// can't be ticked anymore.
+    fn is_orphaned(&self) -> bool {
+        self.outputs.iter().all(|output| match output.run(|_| ()) {
+                                    Err(HandleErr::AlreadyDropped) => true,
+                                    _ => false
+                                })
+    }
+
+    fn progress(&self) -> f64 {
+        if self.is_finished() {
+            return self.ease.apply(1.0)
+        }
+        self.ease.apply(as_secs_f64(self.elapsed) / as_secs_f64(self.duration))
+    }
+}
+
+/// Keeps the running animations, and the outputs rendering while they run.
+#[derive(Debug, Default)]
+pub struct Animator {
+    animations: Vec<Animation>,
+    next_id: u64
+}
+
+impl Animator {
+    pub fn new() -> Self {
+        Animator::default()
+    }
+
+    /// Start an animation shown on the outputs, and schedule a frame on them
+    /// so it gets going.
+    pub fn start(&mut self, outputs: &[output::Handle], duration: Duration, ease: Ease)
+                 -> AnimationId {
+        let id = AnimationId(self.next_id);
+        self.next_id += 1;
+        for output in outputs {
+            // NOTE An output that's borrowed right now is probably rendering,
+            // and it's scheduled at the end of its tick.
+            output.run(|output| output.schedule_frame()).ok();
+        }
+        self.animations.push(Animation { id,
+                                         outputs: outputs.to_vec(),
+                                         duration,
+                                         ease,
+                                         start: None,
+                                         elapsed: Duration::from_secs(0) });
+        id
+    }
+
+    /// Stop the animation, without it showing up in `take_finished`.
+    pub fn cancel(&mut self, id: AnimationId) {
+        self.animations.retain(|animation| animation.id != id)
+    }
+
+    /// Advance the animations shown on the output to the time of this frame.
+    ///
+    /// Call this from the frame event of every output the animations are
+    /// shown on, before rendering. Another frame is scheduled on the output
+    /// as long as any of them still runs.
+    pub fn tick(&mut self, output: &mut Output) {
+        let now = output.frame_time().unwrap_or_else(utils::now);
+        let handle = output.weak_reference();
+        let mut running = false;
+        for animation in &mut self.animations {
+            if !animation.outputs.contains(&handle) {
+                continue
+            }
+            // NOTE The frame events of different outputs aren't in order, so
+            // an animation never goes back to an earlier time.
+            let start = *animation.start.get_or_insert(now);
+            if now > start {
+                animation.elapsed = animation.elapsed.max(now.duration_since(start));
+            }
+            running |= !animation.is_finished();
+        }
+        if running {
+            output.schedule_frame();
+        }
+    }
+
+    /// Get the eased progress of the animation, from 0 to 1 (eases can
+    /// overshoot).
+    ///
+    /// Finished animations stay at their end until they're taken with
+    /// `take_finished`. Returns `None` for animations that are gone.
+    pub fn progress(&self, id: AnimationId) -> Option<f64> {
+        self.animations
+            .iter()
+            .find(|animation| animation.id == id)
+            .map(Animation::progress)
+    }
+
+    /// Get the value of the animation for the current frame, between `from`
+    /// and `to`.
+    pub fn value<T: Interpolate>(&self, id: AnimationId, from: T, to: T) -> Option<T> {
+        self.progress(id).map(|t| from.interpolate(&to, t))
+    }
+
+    /// Determines if the animation is still running.
+    pub fn is_running(&self, id: AnimationId) -> bool {
+        self.animations.iter().any(|animation| animation.id == id && !animation.is_finished())
+    }
+
+    /// Determines if any animation is running, on any output.
+    pub fn is_active(&self) -> bool {
+        self.animations.iter().any(|animation| !animation.is_finished())
+    }
+
+    /// Remove the animations that finished, returning them.
+    pub fn take_finished(&mut self) -> Vec<AnimationId> {
+        let finished = self.animations
+                           .iter()
+                           .filter(|animation| animation.is_finished())
+                           .map(|animation| animation.id)
+                           .collect();
+        self.animations.retain(|animation| !animation.is_finished());
+        finished
+    }
+}
+
+fn as_secs_f64(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1_000_000_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EASES: [Ease; 8] = [Ease::Linear,
+                              Ease::InQuad,
+                              Ease::OutQuad,
+                              Ease::InOutQuad,
+                              Ease::InCubic,
+                              Ease::OutCubic,
+                              Ease::InOutCubic,
+                              Ease::CubicBezier(0.25, 0.1, 0.25, 1.0)];
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-3
+    }
+
+    #[test]
+    fn eases_go_from_start_to_end() {
+        for ease in &EASES {
+            assert!(close(ease.apply(0.0), 0.0), "{:?} doesn't start at 0", ease);
+            assert!(close(ease.apply(1.0), 1.0), "{:?} doesn't end at 1", ease);
+            // NOTE Out of range parts of the duration are clamped.
+            assert!(close(ease.apply(-1.0), 0.0));
+            assert!(close(ease.apply(2.0), 1.0));
+        }
+    }
+
+    #[test]
+    fn eases_never_go_back() {
+        for ease in &EASES {
+            let mut previous = ease.apply(0.0);
+            for step in 1..=100 {
+                let progress = ease.apply(step as f64 / 100.0);
+                assert!(progress >= previous - 1e-9, "{:?} goes back at {}", ease, step);
+                previous = progress;
+            }
+        }
+    }
+
+    #[test]
+    fn in_out_eases_are_halfway_at_the_middle() {
+        assert!(close(Ease::InOutQuad.apply(0.5), 0.5));
+        assert!(close(Ease::InOutCubic.apply(0.5), 0.5));
+        assert!(close(Ease::InQuad.apply(0.5), 0.25));
+        assert!(close(Ease::OutCubic.apply(0.5), 0.875));
+    }
+
+    #[test]
+    fn cubic_bezier_matches_css() {
+        // NOTE The control points of a straight line make it linear.
+        for step in 0..=10 {
+            let t = step as f64 / 10.0;
+            assert!(close(Ease::CubicBezier(0.0, 0.0, 1.0, 1.0).apply(t), t));
+        }
+        // NOTE `ease` in CSS.
+        assert!(close(Ease::CubicBezier(0.25, 0.1, 0.25, 1.0).apply(0.5), 0.8024));
+    }
+
+    #[test]
+    fn custom_ease() {
+        fn step(t: f64) -> f64 {
+            if t < 0.5 { 0.0 } else { 1.0 }
+        }
+        assert_eq!(Ease::Custom(step).apply(0.25), 0.0);
+        assert_eq!(Ease::Custom(step).apply(0.75), 1.0);
+    }
+
+    #[test]
+    fn interpolate() {
+        assert_eq!(2.0f64.interpolate(&4.0, 0.5), 3.0);
+        assert_eq!(10i32.interpolate(&0, 0.25), 8);
+        assert_eq!(0i32.interpolate(&10, 1.1), 11);
+        assert_eq!([0.0f32, 1.0, 0.0, 1.0].interpolate(&[1.0, 1.0, 0.0, 0.0], 0.5),
+                   [0.5, 1.0, 0.0, 0.5]);
+        let from = Area::new(Origin::new(0, 0), Size::new(100, 100));
+        let to = Area::new(Origin::new(100, 50), Size::new(200, 0));
+        assert_eq!(from.interpolate(&to, 0.5),
+                   Area::new(Origin::new(50, 25), Size::new(150, 50)));
+    }
+
+    #[test]
+    fn animations_without_outputs_finish_right_away() {
+        let mut animator = Animator::new();
+        let first = animator.start(&[], Duration::from_secs(1), Ease::Linear);
+        let second = animator.start(&[], Duration::from_secs(1), Ease::InQuad);
+        assert_ne!(first, second);
+        assert!(!animator.is_running(first));
+        assert!(!animator.is_active());
+        assert_eq!(animator.progress(first), Some(1.0));
+        assert_eq!(animator.value(first, 0, 10), Some(10));
+        animator.cancel(second);
+        assert_eq!(animator.take_finished(), vec![first]);
+        assert_eq!(animator.progress(first), None);
+        assert_eq!(animator.take_finished(), Vec::<AnimationId>::new());
+    }
+}
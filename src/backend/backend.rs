@@ -12,12 +12,14 @@
 //!
 //! On the multi backend multiple backends could be running at the same time.
 
+use std::{error::Error, fmt};
+
 use libc;
 use wlroots_sys::{self, wlr_backend, wlr_backend_is_wl, wlr_backend_is_x11,
                   wlr_backend_is_drm, wlr_backend_is_headless, wlr_backend_is_multi,
                   wlr_backend_is_libinput, wlr_backend_get_session};
 
-use backend;
+use {backend::{self, SessionKind}, output};
 
 /// A custom function to set up the renderer.
 pub type UnsafeRenderSetupFunction = unsafe extern "C" fn(egl: *mut wlroots_sys::wlr_egl,
@@ -28,6 +30,35 @@ pub type UnsafeRenderSetupFunction = unsafe extern "C" fn(egl: *mut wlroots_sys:
                                                           -> *mut wlroots_sys::wlr_renderer;
 
 
+/// The ways setting up a backend can fail.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum BackendError {
+    /// The session could not be opened, either the one that was asked for or
+    /// (if `None`) any session at all.
+    Session(Option<SessionKind>),
+    /// The backend with this name could not be created.
+    Backend(&'static str)
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BackendError::Session(Some(kind)) => write!(f, "could not open a {} session", kind),
+            BackendError::Session(None) => write!(f, "could not open a session"),
+            BackendError::Backend(name) => write!(f, "could not construct {} backend", name)
+        }
+    }
+}
+
+impl Error for BackendError {
+    fn description(&self) -> &str {
+        match *self {
+            BackendError::Session(_) => "Could not open session",
+            BackendError::Backend(_) => "Could not construct backend"
+        }
+    }
+}
+
 #[derive(Debug, Hash, Eq, PartialEq)]
 pub enum Backend {
     Wayland(backend::Wayland),
@@ -39,6 +70,30 @@ pub enum Backend {
 }
 
 impl Backend {
+    /// Create an output while the compositor is running, see
+    /// `Headless::add_virtual_output`.
+    ///
+    /// Returns `None` if this isn't the headless backend.
+    pub fn create_virtual_output(&self,
+                                 width: libc::c_uint,
+                                 height: libc::c_uint,
+                                 refresh: i32)
+                                 -> Option<output::Handle> {
+        match *self {
+            Backend::Headless(ref headless) => headless.add_virtual_output(width, height, refresh),
+            _ => None
+        }
+    }
+
+    /// Remove an output created with `create_virtual_output`, see
+    /// `Headless::remove_output`.
+    pub fn remove_virtual_output(&self, output: &output::Handle) -> bool {
+        match *self {
+            Backend::Headless(ref headless) => headless.remove_output(output),
+            _ => false
+        }
+    }
+
     /// Obtains the wlr_session reference from this backend if there is any.
     ///
     /// Might return None for backends that don't use a session.
@@ -53,6 +108,13 @@ impl Backend {
         }
     }
 
+    /// Get the kind of session the backend is using.
+    ///
+    /// Nested and headless backends don't use a session, so they are `Noop`.
+    pub fn session_kind(&self) -> SessionKind {
+        unsafe { SessionKind::from_ptr(wlr_backend_get_session(self.as_ptr())) }
+    }
+
     /// Create a backend from a `*mut wlr_backend`.
     pub unsafe fn from_backend(backend: *mut wlr_backend) -> Self {
         if wlr_backend_is_wl(backend) {
@@ -4,7 +4,7 @@ use libc::c_int;
 use wlroots_sys::{wlr_backend, wl_display, wlr_drm_backend_create, wlr_output_is_drm};
 
 use {output::Output,
-     backend::{UnsafeRenderSetupFunction, Session},
+     backend::{BackendError, UnsafeRenderSetupFunction, Session},
      utils::Handleable};
 
 /// When the compositor is ran on a TTY and has full control of the system resources.
@@ -35,7 +35,7 @@ impl Drm {
                       gpu_fd: c_int,
                       parent: Option<Drm>,
                       render_setup_func: Option<UnsafeRenderSetupFunction>)
-                      -> Self {
+                      -> Result<Self, BackendError> {
         let parent_ptr = parent.map(|backend| backend.as_ptr()).unwrap_or_else(ptr::null_mut);
         let backend = wlr_drm_backend_create(display,
                                              session.as_ptr(),
@@ -43,9 +43,9 @@ impl Drm {
                                              parent_ptr,
                                              render_setup_func);
         if backend.is_null() {
-            panic!("Could not construct X11 backend");
+            return Err(BackendError::Backend("DRM"))
         }
-        Drm { backend }
+        Ok(Drm { backend })
     }
 
     pub fn output_is_drm(&self, output: &Output) -> bool {
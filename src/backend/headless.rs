@@ -1,10 +1,13 @@
 use libc;
+use wayland_sys::server::WAYLAND_SERVER_HANDLE;
 use wlroots_sys::{wlr_backend, wlr_headless_backend_create, wlr_headless_add_output,
                   wlr_headless_add_input_device, wlr_input_device_is_headless,
-                  wlr_output_is_headless, wlr_input_device_type, wl_display};
+                  wlr_output_destroy, wlr_output_is_headless, wlr_input_device_type, wl_display};
 
-use {backend::UnsafeRenderSetupFunction,
+use {backend::{BackendError, UnsafeRenderSetupFunction},
+     compositor,
      input,
+     area::Size,
      output::{self, Output},
      utils::Handleable};
 
@@ -24,11 +27,21 @@ impl Headless {
     pub unsafe fn new(display: *mut wl_display,
                       render_setup_func: Option<UnsafeRenderSetupFunction>)
                       -> Self {
+        match Headless::try_new(display, render_setup_func) {
+            Ok(headless) => headless,
+            Err(_) => panic!("Could not construct Headless backend")
+        }
+    }
+
+    /// Creates a headless backend, like `new`.
+    pub unsafe fn try_new(display: *mut wl_display,
+                          render_setup_func: Option<UnsafeRenderSetupFunction>)
+                          -> Result<Self, BackendError> {
         let backend = wlr_headless_backend_create(display, render_setup_func);
         if backend.is_null() {
-            panic!("Could not construct Headless backend");
+            return Err(BackendError::Backend("headless"))
         }
-        Headless { backend }
+        Ok(Headless { backend })
     }
 
 
@@ -47,6 +60,56 @@ impl Headless {
         }
     }
 
+    /// Create an output while the compositor is running, e.g. one dedicated
+    /// to remote streaming or VNC.
+    ///
+    /// `refresh` is in mHz, like `Output::set_custom_mode`. The output is
+    /// announced through the output manager like any other, so it is added
+    /// to the output layout there.
+    pub fn add_virtual_output(&self,
+                              width: libc::c_uint,
+                              height: libc::c_uint,
+                              refresh: i32)
+                              -> Option<output::Handle> {
+        let handle = self.add_output(width, height)?;
+        let size = Size::new(width as i32, height as i32);
+        if refresh > 0 {
+            handle.run(|output| output.set_custom_mode(size, refresh)).ok();
+        }
+        Some(handle)
+    }
+
+    /// Remove an output of this backend, as if it was unplugged.
+    ///
+    /// The output is destroyed once control returns to the event loop, so
+    /// this is safe to call from the callbacks of the output itself. The
+    /// `destroyed` callback of its handler and the `output_removed` callback
+    /// of the output manager are called then, and it is removed from its
+    /// layout.
+    ///
+    /// Returns false if the output isn't from the headless backend.
+    pub fn remove_output(&self, output: &output::Handle) -> bool {
+        // NOTE The output isn't upgraded, so this works from within its own
+        // callbacks.
+        if output.handle.upgrade().is_none() {
+            return false
+        }
+        let is_headless = unsafe { wlr_output_is_headless(output.as_ptr()) };
+        if !is_headless || unsafe { compositor::COMPOSITOR_PTR.is_null() } {
+            return false
+        }
+        unsafe {
+            let event_loop = (*compositor::COMPOSITOR_PTR).event_loop;
+            let output = Box::into_raw(Box::new(output.clone()));
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_event_loop_add_idle,
+                          event_loop,
+                          remove_output_idle,
+                          output as *mut libc::c_void);
+        }
+        true
+    }
+
     /// Creates a new input device.
     ///
     /// The caller is responsible for manually raising any event signals on the
@@ -78,3 +141,11 @@ impl Headless {
         self.backend
     }
 }
+
+unsafe extern "C" fn remove_output_idle(data: *mut libc::c_void) {
+    let output = Box::from_raw(data as *mut output::Handle);
+    // NOTE The output could have been unplugged in the meantime.
+    if output.handle.upgrade().is_some() {
+        wlr_output_destroy(output.as_ptr())
+    }
+}
@@ -1,7 +1,18 @@
+use libc::c_double;
 use wlroots_sys::{wlr_backend, wl_display, wlr_libinput_backend_create, libinput_device,
-                  wlr_libinput_get_device_handle, wlr_input_device_is_libinput};
+                  wlr_libinput_get_device_handle, wlr_input_device_is_libinput,
+                  libinput_config_accel_profile, libinput_config_status,
+                  libinput_config_tap_state,
+                  libinput_device_config_accel_get_profile,
+                  libinput_device_config_accel_get_speed,
+                  libinput_device_config_accel_is_available,
+                  libinput_device_config_accel_set_profile,
+                  libinput_device_config_accel_set_speed,
+                  libinput_device_config_tap_get_enabled,
+                  libinput_device_config_tap_get_finger_count,
+                  libinput_device_config_tap_set_enabled};
 
-use {backend::Session, input};
+use {backend::{BackendError, Session}, input};
 
 #[derive(Debug, Hash, Eq, PartialEq)]
 pub struct Libinput {
@@ -9,12 +20,12 @@ pub struct Libinput {
 }
 
 impl Libinput {
-    pub unsafe fn new(display: *mut wl_display, session: Session) -> Self {
+    pub unsafe fn new(display: *mut wl_display, session: Session) -> Result<Self, BackendError> {
         let backend = wlr_libinput_backend_create(display, session.as_ptr());
         if backend.is_null() {
-            panic!("Could not construct Wayland backend");
+            return Err(BackendError::Backend("libinput"))
         }
-        Libinput { backend }
+        Ok(Libinput { backend })
     }
 
     /// Get the underlying libinput_device handle for the given input device.
@@ -25,4 +36,137 @@ impl Libinput {
     pub fn is_libinput_input_device(&self, input_device: &input::Device) -> bool {
         unsafe { wlr_input_device_is_libinput(input_device.as_ptr()) }
     }
+
+    /// Determines if the device supports pointer acceleration.
+    ///
+    /// Returns false if the device isn't a libinput device.
+    pub fn accel_available(&self, input_device: &input::Device) -> bool {
+        unsafe {
+            match Libinput::libinput_device(input_device) {
+                Some(device) => libinput_device_config_accel_is_available(device) != 0,
+                None => false
+            }
+        }
+    }
+
+    /// Set the pointer acceleration speed of the device.
+    ///
+    /// The speed is normalized to the range [-1, 1], where 0 is the device's
+    /// default speed.
+    pub fn set_accel_speed(&mut self,
+                           input_device: &input::Device,
+                           speed: c_double)
+                           -> Result<(), libinput_config_status> {
+        unsafe { Libinput::set_accel_speed_unchecked(input_device, speed) }
+    }
+
+    /// Get the pointer acceleration speed of the device, if it's a libinput
+    /// device.
+    pub fn accel_speed(&self, input_device: &input::Device) -> Option<c_double> {
+        unsafe {
+            Libinput::libinput_device(input_device)
+                .map(|device| libinput_device_config_accel_get_speed(device))
+        }
+    }
+
+    /// Set the pointer acceleration profile of the device.
+    pub fn set_accel_profile(&mut self,
+                             input_device: &input::Device,
+                             profile: libinput_config_accel_profile)
+                             -> Result<(), libinput_config_status> {
+        unsafe { Libinput::set_accel_profile_unchecked(input_device, profile) }
+    }
+
+    /// Get the pointer acceleration profile of the device, if it's a libinput
+    /// device.
+    pub fn accel_profile(&self,
+                         input_device: &input::Device)
+                         -> Option<libinput_config_accel_profile> {
+        unsafe {
+            Libinput::libinput_device(input_device)
+                .map(|device| libinput_device_config_accel_get_profile(device))
+        }
+    }
+
+    /// Determines if the device supports tap-to-click.
+    ///
+    /// Returns false if the device isn't a libinput device.
+    pub fn tap_available(&self, input_device: &input::Device) -> bool {
+        unsafe {
+            match Libinput::libinput_device(input_device) {
+                Some(device) => libinput_device_config_tap_get_finger_count(device) > 0,
+                None => false
+            }
+        }
+    }
+
+    /// Enable or disable tap-to-click on the device.
+    pub fn set_tap_enabled(&mut self,
+                           input_device: &input::Device,
+                           enabled: bool)
+                           -> Result<(), libinput_config_status> {
+        unsafe { Libinput::set_tap_enabled_unchecked(input_device, enabled) }
+    }
+
+    /// Determines if tap-to-click is enabled on the device, if it's a libinput
+    /// device.
+    pub fn tap_enabled(&self, input_device: &input::Device) -> Option<bool> {
+        use self::libinput_config_tap_state::*;
+        unsafe {
+            Libinput::libinput_device(input_device)
+                .map(|device| libinput_device_config_tap_get_enabled(device)
+                              == LIBINPUT_CONFIG_TAP_ENABLED)
+        }
+    }
+
+    pub(crate) unsafe fn set_accel_speed_unchecked(input_device: &input::Device,
+                                                   speed: c_double)
+                                                   -> Result<(), libinput_config_status> {
+        let device = Libinput::libinput_device(input_device)
+            .ok_or(libinput_config_status::LIBINPUT_CONFIG_STATUS_UNSUPPORTED)?;
+        match libinput_device_config_accel_set_speed(device, speed) {
+            libinput_config_status::LIBINPUT_CONFIG_STATUS_SUCCESS => Ok(()),
+            err => Err(err)
+        }
+    }
+
+    pub(crate) unsafe fn set_accel_profile_unchecked(input_device: &input::Device,
+                                                     profile: libinput_config_accel_profile)
+                                                     -> Result<(), libinput_config_status> {
+        let device = Libinput::libinput_device(input_device)
+            .ok_or(libinput_config_status::LIBINPUT_CONFIG_STATUS_UNSUPPORTED)?;
+        match libinput_device_config_accel_set_profile(device, profile) {
+            libinput_config_status::LIBINPUT_CONFIG_STATUS_SUCCESS => Ok(()),
+            err => Err(err)
+        }
+    }
+
+    pub(crate) unsafe fn set_tap_enabled_unchecked(input_device: &input::Device,
+                                                   enabled: bool)
+                                                   -> Result<(), libinput_config_status> {
+        use self::libinput_config_tap_state::*;
+        let device = Libinput::libinput_device(input_device)
+            .ok_or(libinput_config_status::LIBINPUT_CONFIG_STATUS_UNSUPPORTED)?;
+        let state = if enabled {
+            LIBINPUT_CONFIG_TAP_ENABLED
+        } else {
+            LIBINPUT_CONFIG_TAP_DISABLED
+        };
+        match libinput_device_config_tap_set_enabled(device, state) {
+            libinput_config_status::LIBINPUT_CONFIG_STATUS_SUCCESS => Ok(()),
+            err => Err(err)
+        }
+    }
+
+    unsafe fn libinput_device(input_device: &input::Device) -> Option<*mut libinput_device> {
+        if !wlr_input_device_is_libinput(input_device.as_ptr()) {
+            return None
+        }
+        let device = Libinput::device_handle(input_device);
+        if device.is_null() {
+            None
+        } else {
+            Some(device)
+        }
+    }
 }
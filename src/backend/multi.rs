@@ -1,7 +1,8 @@
-use wlroots_sys::{wlr_backend, wlr_backend_autocreate, wl_display, wlr_multi_backend_add,
+use wlroots_sys::{wlr_backend, wlr_backend_autocreate,
+                  wlr_backend_get_session, wl_display, wlr_multi_backend_add,
                   wlr_multi_backend_remove, wlr_multi_is_empty};
 
-use backend::UnsafeRenderSetupFunction;
+use backend::{BackendError, SessionKind, UnsafeRenderSetupFunction};
 
 /// When multiple backends are running or when the compositor writer doesn't care and
 /// just used the auto create option in the `CompositorBuilder`.
@@ -15,13 +16,46 @@ impl Multi {
     pub unsafe fn auto_create(display: *mut wl_display,
                               render_setup_func: Option<UnsafeRenderSetupFunction>)
                               -> Self {
+        let kind = SessionKind::from_env();
         let backend = wlr_backend_autocreate(display, render_setup_func);
         if backend.is_null() {
             panic!("Could not auto construct backend");
         }
+        SessionKind::record(wlr_backend_get_session(backend), kind);
         Multi { backend }
     }
 
+    /// Auto create a backend based on the environment, like `auto_create`.
+    ///
+    /// If it needs a session it will be of the given kind, failing
+    /// instead of falling back to another kind of session.
+    ///
+    /// `WLR_SESSION` is set while the backend is created, and restored once
+    /// it is, so the environment mustn't be used by another thread meanwhile.
+    pub unsafe fn try_auto_create(display: *mut wl_display,
+                                  session: Option<SessionKind>,
+                                  render_setup_func: Option<UnsafeRenderSetupFunction>)
+                                  -> Result<Self, BackendError> {
+        let create = || {
+            let kind = SessionKind::from_env();
+            (wlr_backend_autocreate(display, render_setup_func), kind)
+        };
+        let (backend, kind) = match session {
+            Some(session) => session.forced(create),
+            None => create()
+        };
+        if backend.is_null() {
+            return Err(match session {
+                           Some(session) => BackendError::Session(Some(session)),
+                           None => BackendError::Backend("multi")
+                       })
+        }
+        // NOTE With `WLR_SESSION` set wlroots doesn't fall back to another
+        // kind of session, so a forced session is always of that kind.
+        SessionKind::record(wlr_backend_get_session(backend), kind);
+        Ok(Multi { backend })
+    }
+
     /// Adds the given backend to the multi backend.
     ///
     /// # Safety
@@ -1,3 +1,4 @@
+use std::{cell::RefCell, collections::HashMap, env, ffi::OsString, fmt};
 use std::marker::PhantomData;
 use std::path::Path;
 
@@ -7,7 +8,136 @@ use wlroots_sys::{wl_display, wlr_session, wlr_session_create, wlr_session_destr
                   wlr_session_change_vt, wl_listener, wl_signal, udev, udev_monitor, wlr_device,
                   dev_t};
 
-use utils::safe_as_cstring;
+use {backend::BackendError, utils::safe_as_cstring};
+
+/// Environment variable the wlroots session code reads to pick a session.
+const SESSION_ENV: &str = "WLR_SESSION";
+
+// NOTE From sd-login, which wlroots links against for its logind session.
+#[cfg(any(feature = "systemd", feature = "elogind"))]
+extern "C" {
+    fn sd_pid_get_session(pid: libc::pid_t, session: *mut *mut c_char) -> c_int;
+}
+
+thread_local! {
+    /// The kind of the sessions that were created, by their address.
+    static KINDS: RefCell<HashMap<usize, SessionKind>> = RefCell::new(HashMap::new());
+}
+
+/// The ways the compositor can get access to the devices of the seat.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum SessionKind {
+    /// Opening the devices directly, which requires root or `CAP_SYS_ADMIN`.
+    Direct,
+    /// Asking systemd-logind (or elogind) for the devices.
+    Logind,
+    /// No session at all, e.g. when running headless or nested.
+    Noop
+}
+
+impl SessionKind {
+    fn env_name(self) -> Option<&'static str> {
+        match self {
+            SessionKind::Direct => Some("direct"),
+            SessionKind::Logind => Some("logind"),
+            SessionKind::Noop => None
+        }
+    }
+
+    /// Run `create` with the sessions it creates being of this kind.
+    ///
+    /// wlroots only takes the kind of session from `WLR_SESSION`, so the
+    /// variable is set for as long as `create` runs, and restored right after
+    /// (even if it panics). The environment is shared by the whole process,
+    /// so no other thread may read or change it in the meantime.
+    pub(crate) fn forced<F, R>(self, create: F) -> R
+        where F: FnOnce() -> R
+    {
+        let _forced = ForcedSession { previous: env::var_os(SESSION_ENV) };
+        if let Some(name) = self.env_name() {
+            env::set_var(SESSION_ENV, name);
+        }
+        create()
+    }
+
+    /// Get the kind of the session backing the pointer.
+    ///
+    /// A null pointer is a `Noop` session.
+    pub(crate) unsafe fn from_ptr(session: *mut wlr_session) -> Self {
+        if session.is_null() {
+            return SessionKind::Noop
+        }
+        KINDS.with(|kinds| kinds.borrow().get(&(session as usize)).cloned())
+             .unwrap_or_else(SessionKind::from_env)
+    }
+
+    /// Remember the kind of the session that was just created.
+    pub(crate) fn record(session: *mut wlr_session, kind: SessionKind) {
+        if !session.is_null() {
+            KINDS.with(|kinds| kinds.borrow_mut().insert(session as usize, kind));
+        }
+    }
+
+    /// Determine the kind of session `wlr_session_create` opens right now,
+    /// the same way it picks one: `WLR_SESSION` if it's set, otherwise
+    /// logind if the process is part of a logind session.
+    ///
+    /// NOTE If taking control of the logind session fails wlroots falls back
+    /// to a direct session, which can't be told apart from the outside.
+    pub(crate) fn from_env() -> Self {
+        match env::var(SESSION_ENV).as_ref().map(String::as_str) {
+            Ok("direct") => return SessionKind::Direct,
+            Ok("logind") => return SessionKind::Logind,
+            _ => {}
+        }
+        if in_logind_session() {
+            SessionKind::Logind
+        } else {
+            SessionKind::Direct
+        }
+    }
+}
+
+#[cfg(any(feature = "systemd", feature = "elogind"))]
+fn in_logind_session() -> bool {
+    unsafe {
+        let mut session = ::std::ptr::null_mut();
+        if sd_pid_get_session(0, &mut session) < 0 {
+            return false
+        }
+        libc::free(session as *mut libc::c_void);
+        true
+    }
+}
+
+#[cfg(not(any(feature = "systemd", feature = "elogind")))]
+fn in_logind_session() -> bool {
+    false
+}
+
+impl fmt::Display for SessionKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SessionKind::Direct => write!(f, "direct"),
+            SessionKind::Logind => write!(f, "logind"),
+            SessionKind::Noop => write!(f, "noop")
+        }
+    }
+}
+
+/// Restores `WLR_SESSION` when dropped, see `SessionKind::forced`.
+pub(crate) struct ForcedSession {
+    previous: Option<OsString>
+}
+
+impl Drop for ForcedSession {
+    fn drop(&mut self) {
+        match self.previous.take() {
+            Some(previous) => env::set_var(SESSION_ENV, previous),
+            None => env::remove_var(SESSION_ENV)
+        }
+    }
+}
 
 pub struct Device<'session> {
     device: *mut wlr_device,
@@ -40,6 +170,11 @@ impl <'session> Session<'session> {
         unsafe { (*self.session).session_signal }
     }
 
+    /// Get which kind of session this is.
+    pub fn kind(&self) -> SessionKind {
+        unsafe { SessionKind::from_ptr(self.session) }
+    }
+
     pub fn active(&self) -> bool {
         unsafe { (*self.session).active }
     }
@@ -88,10 +223,12 @@ impl <'session> Session<'session> {
     ///
     /// Returns `None` on error.
     pub unsafe fn new(display: *mut wl_display) -> Option<Self> {
+        let kind = SessionKind::from_env();
         let session = wlr_session_create(display);
         if session.is_null() {
             None
         } else {
+            SessionKind::record(session, kind);
             Some(Session {
                 session,
                 phantom: PhantomData
@@ -99,10 +236,34 @@ impl <'session> Session<'session> {
         }
     }
 
+    /// Opens a session of the given kind, like `Session::new`.
+    ///
+    /// Fails if that kind of session could not be opened, instead of falling
+    /// back to another one. A `Noop` session can't be opened, as there's
+    /// nothing to open.
+    ///
+    /// `WLR_SESSION` is set while the session is opened, and restored once
+    /// it is, so the environment mustn't be used by another thread meanwhile.
+    pub unsafe fn with_kind(display: *mut wl_display,
+                            kind: SessionKind)
+                            -> Result<Self, BackendError> {
+        if kind == SessionKind::Noop {
+            return Err(BackendError::Session(Some(kind)))
+        }
+        let session = kind.forced(|| wlr_session_create(display));
+        if session.is_null() {
+            return Err(BackendError::Session(Some(kind)))
+        }
+        // NOTE With `WLR_SESSION` set wlroots doesn't fall back to another kind.
+        SessionKind::record(session, kind);
+        Ok(Session { session, phantom: PhantomData })
+    }
+
     /// Closes a previously opened session and restores the virtual terminal.
     /// You should call Session::close_file on each files you opened
     /// with Session::open_file before you call this.
     pub unsafe fn destroy(self) {
+        KINDS.with(|kinds| kinds.borrow_mut().remove(&(self.session as usize)));
         wlr_session_destroy(self.session)
     }
 
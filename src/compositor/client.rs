@@ -0,0 +1,480 @@
+//! Wayland clients connected to the compositor.
+//!
+//! Every client that connects to the compositor is tracked, so that the
+//! compositor can query information about it (e.g. for security policies) or
+//! forcibly disconnect it.
+
+use std::{cell::{Cell, RefCell}, collections::HashMap, ffi::CStr, fmt, mem, ops::Add, ptr,
+          rc::{Rc, Weak}};
+
+use libc::{self, c_char, c_int, c_void, gid_t, pid_t, uid_t};
+use wayland_sys::server::{wl_client, wl_display, wl_listener, wl_resource,
+                          WAYLAND_SERVER_HANDLE};
+use wlroots_sys::{wlr_buffer, wlr_surface, wlr_surface_from_resource, wlr_texture};
+
+use {render::Texture, surface};
+
+// NOTE These are not exposed by wayland-sys, but libwayland-server is
+// always linked by wlroots-sys.
+extern "C" {
+    fn wl_display_add_client_created_listener(display: *mut wl_display,
+                                              listener: *mut wl_listener);
+    fn wl_client_for_each_resource(client: *mut wl_client,
+                                   iterator: unsafe extern "C" fn(*mut wl_resource,
+                                                                  *mut c_void)
+                                                                  -> c_int,
+                                   user_data: *mut c_void);
+    fn wl_resource_get_class(resource: *mut wl_resource) -> *const c_char;
+}
+
+/// Value for `wl_iterator_result` to keep iterating.
+const WL_ITERATOR_CONTINUE: c_int = 1;
+
+thread_local! {
+    /// All of the clients currently connected to the compositor.
+    static CLIENTS: RefCell<HashMap<usize, *mut ClientState>> = RefCell::new(HashMap::new());
+    /// The textures the compositor holds on to for the clients.
+    static RETAINED: RefCell<Vec<Weak<Retained>>> = RefCell::new(Vec::new());
+}
+
+/// A texture the compositor holds on to for a client (a snapshot, or a
+/// locked buffer), which is counted by `Client::memory_stats` while it's
+/// alive.
+#[derive(Debug)]
+pub(crate) struct Retained {
+    client: Client,
+    /// The locked buffer, whose texture is counted, or null.
+    buffer: *mut wlr_buffer,
+    /// The texture of a snapshot, or null.
+    texture: *mut wlr_texture,
+    /// Whether the renderer the texture belongs to is still around.
+    renderer: Rc<Cell<bool>>
+}
+
+impl Retained {
+    /// Count the texture, or the texture of the buffer, for the client of the
+    /// surface while the returned value is kept.
+    ///
+    /// Returns `None` if the surface has no client anymore.
+    pub(crate) unsafe fn track(surface: *mut wlr_surface,
+                               buffer: *mut wlr_buffer,
+                               texture: *mut wlr_texture,
+                               renderer: Rc<Cell<bool>>)
+                               -> Option<Rc<Retained>> {
+        let client = surface::surface_client(surface);
+        if client.is_null() {
+            return None
+        }
+        let retained = Rc::new(Retained { client: Client::from_ptr(client),
+                                          buffer,
+                                          texture,
+                                          renderer });
+        RETAINED.with(|all| {
+                          let mut all = all.borrow_mut();
+                          all.retain(|retained| retained.upgrade().is_some());
+                          all.push(Rc::downgrade(&retained))
+                      });
+        Some(retained)
+    }
+
+    unsafe fn texture(&self) -> *mut wlr_texture {
+        if !self.renderer.get() {
+            ptr::null_mut()
+        } else if !self.buffer.is_null() {
+            (*self.buffer).texture
+        } else {
+            self.texture
+        }
+    }
+}
+
+/// Callback that's triggered when a client disconnects.
+pub type ClientDestroyed = Box<FnMut(Client)>;
+
+/// The credentials of a client, as reported by the socket it connected with.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct Credentials {
+    pub pid: pid_t,
+    pub uid: uid_t,
+    pub gid: gid_t
+}
+
+/// How much memory the buffers of a client take, see `Client::memory_stats`.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct MemoryStats {
+    /// How many shared memory buffers the client has created.
+    pub shm_buffers: usize,
+    /// Estimate of the size of those buffers in bytes, from their stride and
+    /// height.
+    ///
+    /// libwayland doesn't tell the size of the pools the buffers are in, so
+    /// pool memory that no buffer uses isn't counted, and buffers that
+    /// overlap in their pool are counted twice.
+    pub shm_bytes: u64,
+    /// How many of the surfaces of the client have a texture uploaded.
+    pub textures: usize,
+    /// Estimate of the GPU memory those textures take in bytes, see
+    /// `Texture::memory_size`.
+    pub texture_bytes: u64,
+    /// How many other textures the compositor holds on to for the client,
+    /// i.e. snapshots of its surfaces and locked buffers that aren't
+    /// committed anymore.
+    pub retained_textures: usize,
+    /// Estimate of the GPU memory those textures take in bytes.
+    pub retained_bytes: u64
+}
+
+impl Add for MemoryStats {
+    type Output = MemoryStats;
+
+    fn add(self, other: MemoryStats) -> MemoryStats {
+        MemoryStats { shm_buffers: self.shm_buffers + other.shm_buffers,
+                      shm_bytes: self.shm_bytes + other.shm_bytes,
+                      textures: self.textures + other.textures,
+                      texture_bytes: self.texture_bytes + other.texture_bytes,
+                      retained_textures: self.retained_textures + other.retained_textures,
+                      retained_bytes: self.retained_bytes + other.retained_bytes }
+    }
+}
+
+/// A handle to a Wayland client connected to the compositor.
+///
+/// Once the client disconnects the handle is no longer valid, and all
+/// operations on it do nothing.
+#[derive(Clone)]
+pub struct Client {
+    client: *mut wl_client,
+    handle: Weak<Cell<bool>>
+}
+
+/// A resource (i.e. an instance of a Wayland object) owned by a client.
+///
+/// Its id, version and interface are read while the resource is alive, so
+/// they can still be looked at once it's destroyed. Only the pointer to it
+/// may dangle then.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct Resource {
+    resource: *mut wl_resource,
+    id: u32,
+    version: c_int,
+    /// The name of the interface, which is static data of the library
+    /// implementing the protocol, so it outlives the resource.
+    class: *const c_char
+}
+
+wayland_listener!(pub(crate) ClientState, (*mut wl_client, Rc<Cell<bool>>, Vec<ClientDestroyed>), [
+    destroy_listener => destroy_notify: |this: &mut ClientState, _data: *mut libc::c_void,|
+    unsafe {
+        let client_ptr = this.data.0;
+        let client = Client { client: client_ptr,
+                              handle: Rc::downgrade(&this.data.1) };
+        let callbacks = mem::replace(&mut this.data.2, Vec::new());
+        for mut callback in callbacks {
+            callback(client.clone());
+        }
+        CLIENTS.with(|clients| clients.borrow_mut().remove(&(client_ptr as usize)));
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_list_remove,
+                      &mut (*this.destroy_listener()).link as *mut _ as _);
+        Box::from_raw(this as *mut ClientState);
+    };
+]);
+
+wayland_listener!(pub(crate) ClientManager, (), [
+    client_created_listener => client_created_notify: |_this: &mut ClientManager,
+                                                       data: *mut libc::c_void,|
+    unsafe {
+        track_client(data as *mut wl_client);
+    };
+]);
+
+impl ClientManager {
+    /// Start tracking the clients connected to the display.
+    pub(crate) unsafe fn create(display: *mut wl_display) -> Box<ClientManager> {
+        let mut manager = ClientManager::new(());
+        wl_display_add_client_created_listener(display,
+                                               manager.client_created_listener() as *mut _ as _);
+        manager
+    }
+}
+
+impl Drop for ClientManager {
+    fn drop(&mut self) {
+        unsafe {
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_list_remove,
+                          &mut (*self.client_created_listener()).link as *mut _ as _);
+        }
+    }
+}
+
+/// Start tracking the client, if it isn't being tracked already.
+unsafe fn track_client(client_ptr: *mut wl_client) -> *mut ClientState {
+    if let Some(state) = CLIENTS.with(|clients| clients.borrow().get(&(client_ptr as usize))
+                                                       .cloned()) {
+        return state
+    }
+    let mut state = ClientState::new((client_ptr, Rc::new(Cell::new(false)), Vec::new()));
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_client_add_destroy_listener,
+                  client_ptr,
+                  state.destroy_listener() as *mut _ as _);
+    let state = Box::into_raw(state);
+    CLIENTS.with(|clients| clients.borrow_mut().insert(client_ptr as usize, state));
+    state
+}
+
+/// Get all of the clients currently connected to the compositor.
+pub fn clients() -> Vec<Client> {
+    CLIENTS.with(|clients| {
+        clients.borrow()
+               .values()
+               .map(|state| unsafe { Client::from_state(*state) })
+               .collect()
+    })
+}
+
+impl Client {
+    /// Get the `Client` for the raw `wl_client`.
+    ///
+    /// # Safety
+    /// The pointer must point to a live `wl_client` of this compositor.
+    pub unsafe fn from_ptr(client: *mut wl_client) -> Self {
+        Client::from_state(track_client(client))
+    }
+
+    unsafe fn from_state(state: *mut ClientState) -> Self {
+        Client { client: (*state).data.0,
+                 handle: Rc::downgrade(&(*state).data.1) }
+    }
+
+    /// Get the raw pointer to the client.
+    ///
+    /// # Safety
+    /// There's no guarantees that this pointer is not dangling.
+    pub unsafe fn as_ptr(&self) -> *mut wl_client {
+        self.client
+    }
+
+    /// Determines if the client is still connected.
+    pub fn is_alive(&self) -> bool {
+        self.handle.upgrade().is_some()
+    }
+
+    /// Get the credentials of the process that connected this client.
+    ///
+    /// Returns `None` if the client has disconnected.
+    ///
+    /// Note that the process could have been replaced (e.g. with `exec`) since
+    /// it connected, so these should only be used for advisory policies.
+    pub fn credentials(&self) -> Option<Credentials> {
+        if !self.is_alive() {
+            return None
+        }
+        unsafe {
+            let (mut pid, mut uid, mut gid) = (0, 0, 0);
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_client_get_credentials,
+                          self.client,
+                          &mut pid,
+                          &mut uid,
+                          &mut gid);
+            Some(Credentials { pid, uid, gid })
+        }
+    }
+
+    /// Get the pid of the process that connected this client.
+    pub fn pid(&self) -> Option<pid_t> {
+        self.credentials().map(|credentials| credentials.pid)
+    }
+
+    /// Get the uid of the process that connected this client.
+    pub fn uid(&self) -> Option<uid_t> {
+        self.credentials().map(|credentials| credentials.uid)
+    }
+
+    /// Get the gid of the process that connected this client.
+    pub fn gid(&self) -> Option<gid_t> {
+        self.credentials().map(|credentials| credentials.gid)
+    }
+
+    /// Get the resources the client currently owns.
+    pub fn resources(&self) -> Vec<Resource> {
+        let mut resources = Vec::new();
+        if !self.is_alive() {
+            return resources
+        }
+        unsafe extern "C" fn iterator(resource: *mut wl_resource, data: *mut c_void) -> c_int {
+            let resources = &mut *(data as *mut Vec<Resource>);
+            resources.push(Resource::from_ptr(resource));
+            WL_ITERATOR_CONTINUE
+        }
+        unsafe {
+            wl_client_for_each_resource(self.client,
+                                        iterator,
+                                        &mut resources as *mut _ as *mut c_void);
+        }
+        resources
+    }
+
+    /// Get how much memory the buffers of the client take, e.g. to point out
+    /// applications using too much of it.
+    ///
+    /// Shared memory buffers are counted whether they're attached or not,
+    /// and textures are those of the buffers currently committed to the
+    /// surfaces of the client. Snapshots of its surfaces and buffers locked
+    /// with `Surface::lock_current_buffer` are counted as retained textures
+    /// while they're kept, unless they're the texture of a committed buffer.
+    ///
+    /// Returns the default (i.e. nothing) if the client has disconnected.
+    pub fn memory_stats(&self) -> MemoryStats {
+        let mut stats = MemoryStats::default();
+        let mut committed = Vec::new();
+        for resource in self.resources() {
+            match resource.interface_name().as_str() {
+                "wl_buffer" => unsafe {
+                    let shm_buffer = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                                   wl_shm_buffer_get,
+                                                   resource.resource);
+                    if shm_buffer.is_null() {
+                        continue
+                    }
+                    let stride = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                               wl_shm_buffer_get_stride,
+                                               shm_buffer);
+                    let height = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                               wl_shm_buffer_get_height,
+                                               shm_buffer);
+                    stats.shm_buffers += 1;
+                    stats.shm_bytes += stride.max(0) as u64 * height.max(0) as u64;
+                },
+                "wl_surface" => unsafe {
+                    let surface = wlr_surface_from_resource(resource.resource as *mut _);
+                    if surface.is_null() || (*surface).buffer.is_null() {
+                        continue
+                    }
+                    let texture = (*(*surface).buffer).texture;
+                    if texture.is_null() {
+                        continue
+                    }
+                    committed.push(texture);
+                    stats.textures += 1;
+                    stats.texture_bytes += Texture::from_ptr(texture).memory_size();
+                },
+                _ => {}
+            }
+        }
+        if !self.is_alive() {
+            return stats
+        }
+        let retained = RETAINED.with(|all| {
+            all.borrow()
+               .iter()
+               .filter_map(|retained| retained.upgrade())
+               .filter(|retained| retained.client == *self && retained.client.is_alive())
+               .collect::<Vec<_>>()
+        });
+        let mut counted = Vec::new();
+        for retained in retained {
+            let texture = unsafe { retained.texture() };
+            if texture.is_null() || committed.contains(&texture) || counted.contains(&texture) {
+                continue
+            }
+            counted.push(texture);
+            stats.retained_textures += 1;
+            stats.retained_bytes += unsafe { Texture::from_ptr(texture).memory_size() };
+        }
+        stats
+    }
+
+    /// Call the function when the client disconnects.
+    ///
+    /// If this client has already disconnected, this does nothing.
+    pub fn on_destroy<F>(&self, callback: F)
+        where F: FnMut(Client) + 'static
+    {
+        if !self.is_alive() {
+            return
+        }
+        unsafe {
+            if let Some(state) = CLIENTS.with(|clients| {
+                                                  clients.borrow()
+                                                         .get(&(self.client as usize))
+                                                         .cloned()
+                                              }) {
+                (*state).data.2.push(Box::new(callback));
+            }
+        }
+    }
+
+    /// Flush any pending events to the client.
+    pub fn flush(&self) {
+        if !self.is_alive() {
+            return
+        }
+        unsafe { ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_client_flush, self.client) }
+    }
+
+    /// Disconnect the client, destroying all of its resources.
+    ///
+    /// This is useful to get rid of misbehaving (e.g. hung) clients.
+    pub fn kill(self) {
+        if !self.is_alive() {
+            return
+        }
+        unsafe { ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_client_destroy, self.client) }
+    }
+}
+
+impl fmt::Debug for Client {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Client {:p}", self.client)
+    }
+}
+
+impl PartialEq for Client {
+    fn eq(&self, other: &Client) -> bool {
+        self.client == other.client
+    }
+}
+
+impl Eq for Client {}
+
+impl Resource {
+    /// Get the `Resource` for the raw `wl_resource`.
+    ///
+    /// # Safety
+    /// The pointer must point to a live `wl_resource` of this compositor.
+    pub unsafe fn from_ptr(resource: *mut wl_resource) -> Self {
+        Resource { resource,
+                   id: ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_id, resource),
+                   version: ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_version, resource),
+                   class: wl_resource_get_class(resource) }
+    }
+
+    /// Get the raw pointer to the resource.
+    ///
+    /// # Safety
+    /// There's no guarantees that this pointer is not dangling.
+    pub unsafe fn as_ptr(&self) -> *mut wl_resource {
+        self.resource
+    }
+
+    /// Get the protocol id of the resource.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Get the version of the interface the resource implements.
+    pub fn version(&self) -> c_int {
+        self.version
+    }
+
+    /// Get the name of the interface the resource implements (e.g. `wl_surface`).
+    pub fn interface_name(&self) -> String {
+        if self.class.is_null() {
+            return String::new()
+        }
+        unsafe { CStr::from_ptr(self.class).to_string_lossy().into_owned() }
+    }
+}
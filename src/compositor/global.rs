@@ -0,0 +1,369 @@
+//! Filtering which Wayland globals are advertised to which clients.
+//!
+//! Privileged globals (e.g. screencopy, output management, or virtual
+//! keyboards) should usually not be handed out to every client. A global
+//! filter is consulted whenever a client would see a global, and can hide it
+//! based on the `Client` (e.g. its `Credentials`).
+//!
+//! The compositor also keeps an internal client bound to the registry, so that
+//! the globals it advertises can be listed with `Compositor::globals` (e.g. to
+//! check a compositor implements the expected protocols in a test suite).
+
+use std::{collections::BTreeMap, ffi::CStr, marker::PhantomData, panic, ptr};
+
+use libc::{self, c_char, c_int, c_void};
+use wayland_sys::{common::wl_interface,
+                  server::{wl_client, wl_display, wl_global, WAYLAND_SERVER_HANDLE}};
+use wlroots_sys::wayland_server::{Interface, NewResource};
+
+use super::Client;
+use utils;
+
+// NOTE These are not exposed by wayland-sys, but libwayland-server is
+// always linked by wlroots-sys.
+extern "C" {
+    fn wl_display_set_global_filter(display: *mut wl_display,
+                                    filter: Option<unsafe extern "C" fn(*const wl_client,
+                                                                        *const wl_global,
+                                                                        *mut c_void)
+                                                                        -> bool>,
+                                    data: *mut c_void);
+    fn wl_global_get_interface(global: *const wl_global) -> *const wl_interface;
+}
+
+/// The object id the registry of the internal client is created with.
+const REGISTRY_ID: u32 = 2;
+/// Opcode of `wl_display.get_registry`.
+const GET_REGISTRY: u32 = 1;
+/// Opcode of `wl_registry.global`.
+const REGISTRY_GLOBAL: u32 = 0;
+/// Opcode of `wl_registry.global_remove`.
+const REGISTRY_GLOBAL_REMOVE: u32 = 1;
+
+/// Callback that decides if the client can see and bind to the global.
+///
+/// Return `true` to advertise the global to the client, `false` to hide it.
+pub type GlobalFilter = Box<FnMut(Client, Global) -> bool>;
+
+/// A global advertised by the compositor.
+///
+/// This can be kept around after the global is destroyed, only the pointer
+/// to it may dangle then. Its interface is looked up while it's alive.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct Global {
+    global: *const wl_global,
+    /// The interface of the global, which is static data of the library
+    /// implementing the protocol, so it outlives the global.
+    interface: *const wl_interface
+}
+
+impl Global {
+    /// Make the `Global` for a global that's alive.
+    unsafe fn from_ptr(global: *const wl_global) -> Self {
+        Global { global,
+                 interface: wl_global_get_interface(global) }
+    }
+
+    /// Get the raw pointer to the global.
+    ///
+    /// # Safety
+    /// There's no guarantees that this pointer is not dangling.
+    pub unsafe fn as_ptr(&self) -> *const wl_global {
+        self.global
+    }
+
+    /// Get the name of the interface the global implements
+    /// (e.g. `zwlr_screencopy_manager_v1`).
+    pub fn interface_name(&self) -> String {
+        unsafe {
+            let interface = self.interface;
+            if interface.is_null() || (*interface).name.is_null() {
+                return String::new()
+            }
+            CStr::from_ptr((*interface).name as *const c_char).to_string_lossy()
+                                                                .into_owned()
+        }
+    }
+
+    /// Get the highest version of the interface the global supports.
+    pub fn version(&self) -> i32 {
+        unsafe {
+            let interface = self.interface;
+            if interface.is_null() {
+                return 0
+            }
+            (*interface).version
+        }
+    }
+}
+
+/// A global created with `Compositor::create_global`.
+///
+/// The global is removed when this is dropped.
+#[must_use = "the global is removed again when it's dropped"]
+pub struct CustomGlobal<I: Interface> {
+    global: *mut wl_global,
+    bind: *mut BindData<I>
+}
+
+struct BindData<I: Interface> {
+    bind: Box<FnMut(NewResource<I>, u32)>,
+    _interface: PhantomData<I>
+}
+
+impl<I: Interface> CustomGlobal<I> {
+    /// Create the global on the display, calling `bind` whenever a client
+    /// binds it.
+    ///
+    /// Returns `None` if libwayland could not create the global.
+    pub(crate) unsafe fn create(display: *mut wl_display,
+                                version: u32,
+                                bind: Box<FnMut(NewResource<I>, u32)>)
+                                -> Option<Self> {
+        let bind = Box::into_raw(Box::new(BindData { bind, _interface: PhantomData }));
+        let global = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                   wl_global_create,
+                                   display,
+                                   I::c_interface(),
+                                   version as c_int,
+                                   bind as *mut c_void,
+                                   custom_global_bind::<I>);
+        if global.is_null() {
+            Box::from_raw(bind);
+            return None
+        }
+        Some(CustomGlobal { global, bind })
+    }
+
+    /// Get the `Global` to compare it with the ones the global filter is
+    /// called with.
+    pub fn global(&self) -> Global {
+        unsafe { Global::from_ptr(self.global) }
+    }
+
+    /// Remove the global from the registry, so no more clients can bind it.
+    ///
+    /// This is the same as dropping it. The resources clients already bound
+    /// stay alive.
+    pub fn destroy(self) {}
+}
+
+impl<I: Interface> Drop for CustomGlobal<I> {
+    fn drop(&mut self) {
+        unsafe {
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_global_destroy, self.global);
+            Box::from_raw(self.bind);
+        }
+    }
+}
+
+unsafe extern "C" fn custom_global_bind<I: Interface>(client: *mut wl_client,
+                                                      data: *mut c_void,
+                                                      version: u32,
+                                                      id: u32) {
+    let data = &mut *(data as *mut BindData<I>);
+    let resource = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                 wl_resource_create,
+                                 client,
+                                 I::c_interface(),
+                                 version as c_int,
+                                 id);
+    if resource.is_null() {
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_client_post_no_memory, client);
+        return
+    }
+    let resource = NewResource::<I>::from_c_ptr(resource);
+    wlr_span!("compositor::custom_global_bind");
+    let res = panic::catch_unwind(panic::AssertUnwindSafe(|| (data.bind)(resource, version)));
+    utils::handle_unwind(res);
+}
+
+/// A global as it's advertised in the registry.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct RegistryEntry {
+    /// The numeric name clients bind the global with.
+    pub name: u32,
+    /// The name of the interface (e.g. `wl_seat`).
+    pub interface: String,
+    /// The version the global is advertised with.
+    pub version: u32
+}
+
+/// Decides which globals are visible, and observes the registry.
+pub(crate) struct Registry {
+    filter: Option<GlobalFilter>,
+    /// Interfaces that are never advertised.
+    hidden: Vec<String>,
+    /// The internal client bound to the registry.
+    client: *mut wl_client,
+    /// The other end of the socket of the internal client.
+    socket: c_int,
+    /// Data read from the socket that isn't a complete message yet.
+    buffer: Vec<u8>,
+    globals: BTreeMap<u32, RegistryEntry>
+}
+
+impl Registry {
+    /// Install the filter on the display and bind the internal client to the
+    /// registry.
+    ///
+    /// This must happen before the client tracking is set up, so that the
+    /// internal client isn't listed as connected.
+    pub(crate) unsafe fn create(display: *mut wl_display,
+                                filter: Option<GlobalFilter>,
+                                hidden: Vec<String>)
+                                -> Box<Registry> {
+        let mut registry = Box::new(Registry { filter,
+                                               hidden,
+                                               client: ptr::null_mut(),
+                                               socket: -1,
+                                               buffer: Vec::new(),
+                                               globals: BTreeMap::new() });
+        wl_display_set_global_filter(display,
+                                     Some(global_filter_notify),
+                                     &mut *registry as *mut Registry as *mut c_void);
+        let mut fds = [-1; 2];
+        if libc::socketpair(libc::AF_UNIX,
+                            libc::SOCK_STREAM | libc::SOCK_CLOEXEC,
+                            0,
+                            fds.as_mut_ptr()) != 0 {
+            wlr_log!(WLR_ERROR, "Could not create the socket to observe the registry");
+            return registry
+        }
+        let client = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_client_create, display, fds[0]);
+        if client.is_null() {
+            wlr_log!(WLR_ERROR, "Could not create the client to observe the registry");
+            libc::close(fds[0]);
+            libc::close(fds[1]);
+            return registry
+        }
+        // NOTE The request is handled on the next dispatch of the event loop.
+        let request = [1, 12 << 16 | GET_REGISTRY, REGISTRY_ID];
+        let size = ::std::mem::size_of_val(&request);
+        if libc::send(fds[1], request.as_ptr() as *const c_void, size, 0) != size as isize {
+            wlr_log!(WLR_ERROR, "Could not bind to the registry");
+        }
+        registry.client = client;
+        registry.socket = fds[1];
+        registry
+    }
+
+    /// Get the globals currently advertised by the compositor.
+    ///
+    /// The internal client only learns about globals as the event loop runs,
+    /// so globals created since it last ran are not listed yet.
+    pub(crate) fn globals(&mut self) -> Vec<RegistryEntry> {
+        if self.socket < 0 {
+            return Vec::new()
+        }
+        unsafe {
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_client_flush, self.client);
+            let mut chunk = [0u8; 4096];
+            loop {
+                let read = libc::recv(self.socket,
+                                      chunk.as_mut_ptr() as *mut c_void,
+                                      chunk.len(),
+                                      libc::MSG_DONTWAIT);
+                if read <= 0 {
+                    break
+                }
+                self.buffer.extend_from_slice(&chunk[..read as usize]);
+            }
+        }
+        self.parse_events();
+        self.globals.values().cloned().collect()
+    }
+
+    fn parse_events(&mut self) {
+        let mut offset = 0;
+        while let (Some(object), Some(header)) = (read_u32(&self.buffer, offset),
+                                                  read_u32(&self.buffer, offset + 4)) {
+            let size = (header >> 16) as usize;
+            if size < 8 || self.buffer.len() < offset + size {
+                break
+            }
+            let message = &self.buffer[offset + 8..offset + size];
+            offset += size;
+            if object != REGISTRY_ID {
+                continue
+            }
+            match header & 0xffff {
+                REGISTRY_GLOBAL => {
+                    if let Some(entry) = parse_global(message) {
+                        self.globals.insert(entry.name, entry);
+                    }
+                },
+                REGISTRY_GLOBAL_REMOVE => {
+                    if let Some(name) = read_u32(message, 0) {
+                        self.globals.remove(&name);
+                    }
+                },
+                _ => {}
+            }
+        }
+        self.buffer.drain(..offset);
+    }
+
+    /// Remove the filter, which must happen before the registry is dropped.
+    ///
+    /// The internal client is destroyed along with the others.
+    pub(crate) unsafe fn remove(&mut self, display: *mut wl_display) {
+        wl_display_set_global_filter(display, None, ptr::null_mut());
+        if self.socket >= 0 {
+            libc::close(self.socket);
+            self.socket = -1;
+        }
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    let mut value = [0; 4];
+    value.copy_from_slice(bytes);
+    Some(u32::from_ne_bytes(value))
+}
+
+/// Parse the arguments of a `wl_registry.global` event.
+fn parse_global(message: &[u8]) -> Option<RegistryEntry> {
+    let name = read_u32(message, 0)?;
+    let length = read_u32(message, 4)? as usize;
+    // NOTE Strings are null terminated and padded to 32 bits.
+    let padded = (length + 3) & !3;
+    let interface = message.get(8..8 + length.checked_sub(1)?)?;
+    let version = read_u32(message, 8 + padded)?;
+    Some(RegistryEntry { name,
+                         interface: String::from_utf8_lossy(interface).into_owned(),
+                         version })
+}
+
+unsafe extern "C" fn global_filter_notify(client: *const wl_client,
+                                          global: *const wl_global,
+                                          data: *mut c_void)
+                                          -> bool {
+    let registry = &mut *(data as *mut Registry);
+    let global = Global::from_ptr(global);
+    if !registry.hidden.is_empty() {
+        let interface = global.interface_name();
+        if registry.hidden.iter().any(|hidden| *hidden == interface) {
+            return false
+        }
+    }
+    if client as *mut wl_client == registry.client {
+        return true
+    }
+    let filter = match registry.filter {
+        Some(ref mut filter) => filter,
+        None => return true
+    };
+    let client = Client::from_ptr(client as *mut wl_client);
+    wlr_span!("compositor::global_filter");
+    let res = panic::catch_unwind(panic::AssertUnwindSafe(|| filter(client, global)));
+    match res {
+        Ok(visible) => visible,
+        Err(err) => {
+            // NOTE Hide the global, it's better to fail closed.
+            utils::handle_unwind::<()>(Err(err));
+            false
+        }
+    }
+}
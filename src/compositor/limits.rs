@@ -0,0 +1,264 @@
+//! Limiting the resources each client can allocate.
+//!
+//! A buggy or malicious client can create surfaces in a loop, or attach
+//! buffers so large that uploading them exhausts the memory of the GPU,
+//! taking the whole session down with it. `ResourceLimits` caps what a
+//! single client gets, and an `AllocationFilter` can deny allocations based
+//! on anything else (e.g. the `Credentials` of the client).
+//!
+//! Wayland has no way to refuse a request, so a client that's denied an
+//! allocation is sent a `no_memory` error, which disconnects it. The
+//! compositor is told with the `LimitExceeded` callback, e.g. to notify the
+//! user.
+//!
+//! Buffers are checked when they're committed, before wlroots applies the
+//! commit, so a buffer that's denied is never uploaded.
+
+use std::{collections::HashMap, panic};
+
+use libc;
+use wayland_sys::server::{signal::wl_signal_add, wl_client, WAYLAND_SERVER_HANDLE};
+use wlroots_sys::{wlr_buffer_get_resource_size, wlr_compositor, wlr_surface,
+                  wlr_surface_from_resource,
+                  wlr_surface_state_field::WLR_SURFACE_STATE_BUFFER};
+
+use super::Client;
+use {debug::protocol_log::{self, Direction, Message, Watch}, surface, utils};
+
+/// The most resources a single client can allocate.
+///
+/// Each limit is off when it's `None`, which is the default.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct ResourceLimits {
+    /// How many surfaces (including subsurfaces and cursors) a client can
+    /// have at once.
+    pub max_surfaces: Option<usize>,
+    /// The largest buffer a client can commit, as its width and height in
+    /// pixels.
+    pub max_buffer_size: Option<(i32, i32)>
+}
+
+/// An allocation a client makes, as it's handed to the `AllocationFilter`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Allocation {
+    /// A new surface, which brings the surfaces of the client to `count`.
+    Surface { count: usize },
+    /// A buffer committed to a surface, with its size in pixels.
+    ///
+    /// This is checked when a surface commits a newly attached buffer,
+    /// before the buffer is applied.
+    Buffer { width: i32, height: i32 }
+}
+
+/// Callback that decides if the client can make the allocation.
+///
+/// It's only consulted for allocations within the `ResourceLimits`. Return
+/// `false` to deny the allocation, which disconnects the client.
+pub type AllocationFilter = Box<FnMut(Client, Allocation) -> bool>;
+
+/// Callback that's triggered when a client is disconnected for exceeding its
+/// limits, with the allocation that was denied.
+///
+/// It's called while the request of the client is being dispatched, after
+/// the client was sent the error. The client must not be used from within
+/// the callback (e.g. to send it events or destroy it), it's disconnected
+/// once the request is done.
+pub type LimitExceeded = Box<FnMut(Client, Allocation)>;
+
+/// Enforces the limits on the surfaces of every client.
+pub(crate) struct Limiter {
+    limits: ResourceLimits,
+    filter: Option<AllocationFilter>,
+    on_exceeded: Option<LimitExceeded>,
+    /// How many surfaces each client has.
+    surfaces: HashMap<usize, usize>,
+    /// Intercepts the commits of the surfaces before they're applied.
+    commits: Option<Watch>
+}
+
+wayland_listener!(pub(crate) LimiterListener, Limiter, [
+    new_surface_listener => new_surface_notify: |this: &mut LimiterListener,
+                                                 data: *mut libc::c_void,|
+    unsafe {
+        let surface = data as *mut wlr_surface;
+        let client = surface::surface_client(surface);
+        if client.is_null() {
+            return
+        }
+        let limiter = &mut this.data as *mut Limiter;
+        let count = {
+            let count = (*limiter).surfaces.entry(client as usize).or_insert(0);
+            *count += 1;
+            *count
+        };
+        let mut limited = LimitedSurface::new((limiter, client));
+        wl_signal_add(&mut (*surface).events.destroy as *mut _ as _,
+                      limited.destroy_listener() as _);
+        Box::into_raw(limited);
+        (*limiter).allocate(client, surface, Allocation::Surface { count });
+    };
+]);
+
+wayland_listener!(pub(crate) LimitedSurface, (*mut Limiter, *mut wl_client), [
+    destroy_listener => destroy_notify: |this: &mut LimitedSurface, _data: *mut libc::c_void,|
+    unsafe {
+        let (limiter, client) = this.data;
+        let empty = match (*limiter).surfaces.get_mut(&(client as usize)) {
+            Some(count) => {
+                *count = count.saturating_sub(1);
+                *count == 0
+            },
+            None => false
+        };
+        if empty {
+            (*limiter).surfaces.remove(&(client as usize));
+        }
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_list_remove,
+                      &mut (*this.destroy_listener()).link as *mut _ as _);
+        Box::from_raw(this as *mut LimitedSurface);
+    };
+]);
+
+impl LimiterListener {
+    /// Start enforcing the limits on the surfaces of the compositor.
+    pub(crate) unsafe fn create(compositor: *mut wlr_compositor,
+                                limits: ResourceLimits,
+                                filter: Option<AllocationFilter>,
+                                on_exceeded: Option<LimitExceeded>)
+                                -> Box<LimiterListener> {
+        let mut listener = LimiterListener::new(Limiter { limits,
+                                                          filter,
+                                                          on_exceeded,
+                                                          surfaces: HashMap::new(),
+                                                          commits: None });
+        wl_signal_add(&mut (*compositor).events.new_surface as *mut _ as _,
+                      listener.new_surface_listener() as _);
+        // NOTE The protocol logger sees the request before it's dispatched,
+        // and the limiter is boxed so it stays where it is.
+        let limiter = &mut listener.data as *mut Limiter;
+        let commits = protocol_log::watch(move |message| (*limiter).commit_requested(message));
+        listener.data.commits = Some(commits);
+        listener
+    }
+}
+
+impl Drop for LimiterListener {
+    fn drop(&mut self) {
+        unsafe {
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_list_remove,
+                          &mut (*self.new_surface_listener()).link as *mut _ as _);
+        }
+    }
+}
+
+impl ResourceLimits {
+    /// Determine if the allocation is within the limits.
+    pub fn allows(&self, allocation: Allocation) -> bool {
+        match allocation {
+            Allocation::Surface { count } => self.max_surfaces.map_or(true, |max| count <= max),
+            Allocation::Buffer { width, height } => {
+                self.max_buffer_size
+                    .map_or(true, |(max_width, max_height)| {
+                        width <= max_width && height <= max_height
+                    })
+            }
+        }
+    }
+}
+
+impl Limiter {
+    /// Check the buffer a surface is about to commit, dropping it from the
+    /// pending state if it's denied so it's never applied.
+    unsafe fn commit_requested(&mut self, message: &Message) {
+        if message.direction() != Direction::Request
+           || message.name() != "commit"
+           || message.interface_name() != "wl_surface"
+        {
+            return
+        }
+        let surface = wlr_surface_from_resource(message.resource().as_ptr() as *mut _);
+        if surface.is_null() {
+            return
+        }
+        let pending = &mut (*surface).pending;
+        let buffer_committed = WLR_SURFACE_STATE_BUFFER as u32;
+        if pending.committed & buffer_committed == 0 || pending.buffer_resource.is_null() {
+            return
+        }
+        let (mut width, mut height) = (0, 0);
+        wlr_buffer_get_resource_size(pending.buffer_resource,
+                                     (*surface).renderer,
+                                     &mut width,
+                                     &mut height);
+        if width <= 0 || height <= 0 {
+            return
+        }
+        let client = surface::surface_client(surface);
+        if client.is_null() {
+            return
+        }
+        if !self.allocate(client, surface, Allocation::Buffer { width, height }) {
+            // NOTE The buffer stays pending until the client is destroyed,
+            // which releases it.
+            (*surface).pending.committed &= !buffer_committed;
+        }
+    }
+
+    /// Check the allocation the client made for the surface, disconnecting
+    /// the client if it's denied.
+    ///
+    /// Returns whether the allocation is allowed.
+    unsafe fn allocate(&mut self,
+                       client: *mut wl_client,
+                       surface: *mut wlr_surface,
+                       allocation: Allocation)
+                       -> bool {
+        if self.allowed(client, allocation) {
+            return true
+        }
+        wlr_log!(WLR_INFO,
+                 "Disconnecting client {:p}, which was denied {:?}",
+                 client,
+                 allocation);
+        // NOTE The client is destroyed by libwayland once the request is
+        // dispatched, so it's still alive for the callback.
+        if (*surface).resource.is_null() {
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_client_post_no_memory, client);
+        } else {
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_resource_post_no_memory,
+                          (*surface).resource as *mut _);
+        }
+        if let Some(ref mut on_exceeded) = self.on_exceeded {
+            let client = Client::from_ptr(client);
+            wlr_span!("compositor::limit_exceeded");
+            let res =
+                panic::catch_unwind(panic::AssertUnwindSafe(|| on_exceeded(client, allocation)));
+            utils::handle_unwind(res);
+        }
+        false
+    }
+
+    unsafe fn allowed(&mut self, client: *mut wl_client, allocation: Allocation) -> bool {
+        if !self.limits.allows(allocation) {
+            return false
+        }
+        let filter = match self.filter {
+            Some(ref mut filter) => filter,
+            None => return true
+        };
+        let client = Client::from_ptr(client);
+        wlr_span!("compositor::allocation_filter");
+        let res = panic::catch_unwind(panic::AssertUnwindSafe(|| filter(client, allocation)));
+        match res {
+            Ok(allowed) => allowed,
+            Err(err) => {
+                // NOTE Deny the allocation, it's better to fail closed.
+                utils::handle_unwind::<()>(Err(err));
+                false
+            }
+        }
+    }
+}
@@ -0,0 +1,1544 @@
+//! Main entry point to the library.
+//! See examples for documentation on how to use this struct.
+
+mod client;
+mod global;
+mod limits;
+mod socket;
+mod spawn;
+
+pub use self::client::*;
+pub use self::global::{CustomGlobal, Global, GlobalFilter, RegistryEntry};
+pub use self::limits::{Allocation, AllocationFilter, LimitExceeded, ResourceLimits};
+pub use self::spawn::{spawn, spawn_command, ChildExited};
+
+use std::{env, panic::{self, Location}, ptr, any::Any, cell::{Cell, UnsafeCell}, os::unix::io::RawFd,
+          rc::{Rc, Weak}, sync::atomic::{AtomicBool, Ordering}, thread,
+          time::{Duration, Instant}};
+
+use libc;
+use wayland_sys::server::{wl_display, wl_event_loop, wl_event_source, signal::wl_signal_add,
+                          WAYLAND_SERVER_HANDLE};
+use wlroots_sys::{wlr_backend_destroy, wlr_backend_start,
+                  wlr_compositor, wlr_compositor_create, wlr_compositor_destroy,
+                  wlr_xdg_shell_v6, wlr_xdg_shell_v6_create,
+                  wlr_xdg_shell, wlr_xdg_shell_create, wayland_server};
+
+
+use {backend::{self, UnsafeRenderSetupFunction, Backend, BackendError, Session, SessionKind},
+     data_device, debug,
+     error::Error,
+     surface::{self, Surface, InternalSurface},
+     input,
+     output,
+     render::{pixman, GenericRenderer, RenderError},
+     shell::{xdg_shell, xdg_shell_v6},
+     utils::{self, watchdog, HandleErr, HandleResult, Handleable, PanicPolicy, Watchdog}};
+#[cfg(feature = "idle")]
+use extensions::idle;
+#[cfg(feature = "layer_shell")]
+use extensions::layer_shell;
+#[cfg(feature = "screencopy")]
+use extensions::screenshooter;
+#[cfg(feature = "server_decoration")]
+use extensions::server_decoration;
+#[cfg(feature = "xwayland")]
+use xwayland;
+
+/// Global compositor pointer, used to refer to the compositor state unsafely.
+pub(crate) static mut COMPOSITOR_PTR: *mut Compositor = 0 as *mut _;
+
+/// Callback that's triggered when a surface is provided to the compositor.
+pub type NewSurface = fn(compositor_handle: Handle,
+                            surface_handle: surface::Handle);
+
+/// Callback that's triggered when the compositor starts shutting down,
+/// before any clients are disconnected.
+///
+/// The compositor is still fully usable, e.g. to ask the clients to exit by
+/// closing their toplevels.
+pub type OnShutdown = fn(compositor: &mut Compositor);
+
+/// A check to ensure that we only have one builder at a time.
+/// This is necessary because it uses global state to keep track
+/// of callback pointers.
+///
+/// Once the builder has been built with `Build` then this will
+/// only be set to false once the `Compositor` is dropped.
+static mut BUILDER_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+wayland_listener_static!{
+    static mut INTERNAL_COMPOSITOR;
+    (InternalCompositor, EventBuilder): [
+        (NewSurface, new_surface_listener, surface_added) => (add_notify, surface_added):
+        |handler: &mut InternalCompositor, data: *mut libc::c_void,| unsafe {
+            let surface_ptr = data as _;
+            let compositor = (&mut *COMPOSITOR_PTR).weak_reference();
+            let surface = Surface::new(surface_ptr);
+            handler.surface_added.map(|f| f(compositor.clone(), surface.weak_reference()));
+            let mut internal_surface = InternalSurface::new((surface, Box::new(())));
+            wl_signal_add(&mut (*surface_ptr).events.commit as *mut _ as _,
+                          internal_surface.on_commit_listener() as _);
+            wl_signal_add(&mut (*surface_ptr).events.new_subsurface as *mut _ as _,
+                          internal_surface.new_subsurface_listener() as _);
+            wl_signal_add(&mut (*surface_ptr).events.destroy as *mut _ as _,
+                          internal_surface.on_destroy_listener() as _);
+            let surface_data = (*surface_ptr).data as *mut surface::InternalState;
+            (*surface_data).surface = Box::into_raw(internal_surface);
+        };
+
+        (OnShutdown, shutdown_listener, on_shutdown) => (shutdown_notify, on_shutdown):
+        |handler: &mut InternalCompositor, _data: *mut libc::c_void,| unsafe {
+            if let Some(on_shutdown) = handler.on_shutdown {
+                if !COMPOSITOR_PTR.is_null() {
+                    on_shutdown(&mut *COMPOSITOR_PTR)
+                }
+            }
+        };
+    ]
+}
+
+// NOTE This handle is handled differently from the others, so we can't use
+// the generic `utils::Handle` implementation. This is due to how we need
+// to be able to return a "full" `Compositor` for `upgrade` but that's
+// impossible.
+#[derive(Debug, Clone)]
+pub struct Handle {
+    /// This ensures that this handle is still alive and not already borrowed.
+    handle: Weak<Cell<bool>>
+}
+
+#[allow(dead_code)]
+pub struct Compositor {
+    /// User data.
+    pub data: Box<Any>,
+    /// Internal compositor handler
+    compositor_handler: Option<&'static mut InternalCompositor>,
+    /// Manager for the inputs.
+    input_manager: Option<&'static mut input::Manager>,
+    /// Manager for the outputs.
+    output_manager: Option<&'static mut output::Manager>,
+    /// Manager for stable XDG shells.
+    xdg_shell_manager: Option<&'static mut xdg_shell::Manager>,
+    /// Manager for XDG shells v6.
+    xdg_v6_shell_manager: Option<&'static mut xdg_shell_v6::Manager>,
+    /// Pointer to the xdg_shell global.
+    /// If xdg_shell_manager is `None`, this value will be `NULL`.
+    xdg_shell_global: *mut wlr_xdg_shell,
+    /// Pointer to the xdg_shell_v6 global.
+    /// If xdg_v6_shell_manager is `None`, this value will be `NULL`.
+    xdg_v6_shell_global: *mut wlr_xdg_shell_v6,
+    /// Pointer to the wlr_compositor.
+    compositor: *mut wlr_compositor,
+    /// Pointer to the wlroots backend in use.
+    backend: Backend,
+    /// Pointer to the wayland display.
+    pub display: *mut wl_display,
+    /// Pointer to the event loop.
+    pub event_loop: *mut wl_event_loop,
+    /// Shared memory buffer file descriptor. If the feature was not activated,
+    /// this will be None.
+    wl_shm_fd: Option<i32>,
+    /// Name of the Wayland socket that we are binding to.
+    socket_name: String,
+    /// Optional decoration manager extension.
+    #[cfg(feature = "server_decoration")]
+    pub server_decoration_manager: Option<server_decoration::Manager>,
+    /// Optional idle manager extension.
+    #[cfg(feature = "idle")]
+    pub idle_manager: Option<idle::Manager>,
+    /// Layer shell, only Some if a handler was provided.
+    #[cfg(feature = "layer_shell")]
+    pub layer_shell: Option<layer_shell::Manager>,
+    /// Optional screenshooter extension.
+    #[cfg(feature = "screencopy")]
+    screenshooter: Option<screenshooter::Manager>,
+    /// IPC server, only Some if a handler was provided.
+    #[cfg(feature = "ipc")]
+    pub ipc: Option<::ipc::Server>,
+    /// D-Bus connections, only Some if a handler was provided.
+    #[cfg(feature = "dbus")]
+    pub dbus: Option<::dbus::Dbus>,
+    /// The renderer used to draw things to the screen.
+    pub renderer: Option<GenericRenderer>,
+    /// XWayland server, only Some if it is enabled
+    #[cfg(feature = "xwayland")]
+    pub xwayland: Option<xwayland::Server>,
+    /// The DnD manager
+    data_device_manager: Option<data_device::Manager>,
+    /// The error from the panic, if there was one.
+    panic_error: Option<Box<Any + Send>>,
+    /// Custom function to run at shutdown (or when a panic occurs).
+    user_terminate: Option<fn()>,
+    /// What to do when a callback panics.
+    pub(crate) panic_policy: PanicPolicy,
+    /// Tracks the clients connected to the display.
+    client_manager: Box<ClientManager>,
+    /// Decides which globals are advertised to which clients.
+    registry: Box<global::Registry>,
+    /// Enforces the resource limits of clients, if there are any.
+    limiter: Option<Box<limits::LimiterListener>>,
+    /// Reaps the processes started with `compositor::spawn`.
+    child_reaper: *mut wl_event_source,
+    /// How long to wait for clients to disconnect on their own when shutting down.
+    shutdown_grace_period: Option<Duration>,
+    /// Watches for callbacks that block, if it was turned on.
+    watchdog: Option<watchdog::Thread>,
+    /// Lock used to borrow the compositor globally.
+    /// Should always be set before passing a reference to the compositor
+    /// in a callback.
+    pub(crate) lock: Rc<Cell<bool>>
+}
+
+/// The renderer the compositor draws with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RendererKind {
+    /// No renderer, for compositors that don't draw anything themselves
+    /// (e.g. in tests).
+    None,
+    /// The GLES2 renderer of the backend.
+    Gles2,
+    /// A renderer that draws on the CPU with pixman, so every machine draws
+    /// the same pixels (e.g. for comparing screenshots in tests).
+    ///
+    /// It's only available on the headless backend: the frames stay in
+    /// memory and are only seen by reading them back (e.g. with
+    /// `Renderer::read_pixels` or the screenshooter). The headless outputs
+    /// still need EGL, but no GPU (Mesa's software drivers are enough), and
+    /// `Renderer::with_gl` fails.
+    ///
+    /// This replaces the render setup function of the builder.
+    Pixman
+}
+
+impl Default for RendererKind {
+    fn default() -> Self {
+        RendererKind::None
+    }
+}
+
+/// The backend `Builder::build` makes the compositor on.
+///
+/// The DRM backend needs a session and a GPU to be opened beforehand, so it
+/// has its own `Builder::build_drm` instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackendKind {
+    /// Whichever fits where the compositor is started, see `build_auto`.
+    Auto,
+    /// No outputs or inputs until they are added by hand.
+    Headless,
+    /// Windows on a running X11 server.
+    X11,
+    /// Windows on a running Wayland compositor.
+    Wayland
+}
+
+impl Default for BackendKind {
+    fn default() -> Self {
+        BackendKind::Auto
+    }
+}
+
+/// Builds a `Compositor`.
+///
+/// Every subsystem is off unless it's asked for, and `build` fails if one
+/// that was asked for can't be set up, with an `Error` saying which (e.g.
+/// `Error::Protocol` for a global, `Error::Service` for the IPC server or
+/// D-Bus, `Error::Create("wlr_xwayland")` for XWayland):
+///
+/// ```rust,no_run,ignore
+/// let compositor = compositor::Builder::new().renderer(RendererKind::Gles2)
+///                                            .backend(BackendKind::Wayland)
+///                                            .data_device(true)
+///                                            .xwayland(xwayland_builder)
+///                                            .layer_shell(Box::new(LayerShellHandler))
+///                                            .build(State::default())?;
+/// ```
+///
+/// The protocols and XWayland each have a cargo feature (e.g. `layer_shell`,
+/// `screencopy`, `xwayland`). Without it, the option to turn them on is gone
+/// too, so a compositor can't ask for one that isn't built in.
+#[derive(Default)]
+pub struct Builder {
+    compositor_event_builder: Option<EventBuilder>,
+    input_manager_builder: Option<input::manager::Builder>,
+    output_manager_builder: Option<output::manager::Builder>,
+    xdg_shell_manager_builder: Option<xdg_shell::manager::Builder>,
+    xdg_v6_shell_manager_builder: Option<xdg_shell_v6::manager::Builder>,
+    wl_shm: bool,
+    renderer: RendererKind,
+    backend: BackendKind,
+    render_setup_function: Option<UnsafeRenderSetupFunction>,
+    #[cfg(feature = "server_decoration")]
+    server_decoration_manager: bool,
+    #[cfg(feature = "idle")]
+    idle_manager: bool,
+    #[cfg(feature = "screencopy")]
+    screencopy: bool,
+    #[cfg(feature = "layer_shell")]
+    layer_shell_handler: Option<Box<layer_shell::Handler>>,
+    wayland_remote: Option<String>,
+    x11_display: Option<String>,
+    data_device_manager: bool,
+    #[cfg(feature = "xwayland")]
+    xwayland: Option<xwayland::manager::Builder>,
+    #[cfg(feature = "xwayland")]
+    xwayland_dnd: bool,
+    user_terminate: Option<fn()>,
+    global_filter: Option<GlobalFilter>,
+    hidden_globals: Vec<String>,
+    resource_limits: ResourceLimits,
+    allocation_filter: Option<AllocationFilter>,
+    on_limit_exceeded: Option<LimitExceeded>,
+    socket: socket::Socket,
+    shutdown_grace_period: Option<Duration>,
+    session: Option<SessionKind>,
+    panic_policy: PanicPolicy,
+    watchdog: Option<Watchdog>,
+    #[cfg(feature = "ipc")]
+    ipc_handler: Option<Box<::ipc::Handler>>,
+    #[cfg(feature = "dbus")]
+    dbus_handler: Option<Box<::dbus::Handler>>
+}
+
+/// What `Builder::setup_fallible` set up.
+struct FallibleSetup {
+    renderer: Option<GenericRenderer>,
+    socket_name: String,
+    #[cfg(feature = "layer_shell")]
+    layer_shell: Option<layer_shell::Manager>,
+    #[cfg(feature = "screencopy")]
+    screenshooter: Option<screenshooter::Manager>,
+    #[cfg(feature = "server_decoration")]
+    server_decoration_manager: Option<server_decoration::Manager>,
+    #[cfg(feature = "idle")]
+    idle_manager: Option<idle::Manager>,
+    data_device_manager: Option<data_device::Manager>,
+    #[cfg(feature = "ipc")]
+    ipc: Option<::ipc::Server>,
+    #[cfg(feature = "dbus")]
+    dbus: Option<::dbus::Dbus>
+}
+
+impl Builder {
+    /// Make a new compositor builder.
+    ///
+    /// Unless otherwise noted, each option is `false`/`None`.
+    ///
+    /// # Panicking
+    /// There can only be one `compositor::Builder` per process. If you construct
+    /// a `compositor::Builder` with any of the `build` operations then another
+    /// `compositor::Builder` cannot be constructed until the built `Compositor`
+    /// is dropped.
+    ///
+    /// This requirement is enforced by a check that will panic if this
+    /// constraint is broken. This applies across threads.
+    pub fn new() -> Self {
+        unsafe {
+            assert_eq!(BUILDER_ACTIVE.compare_and_swap(false, true, Ordering::AcqRel),
+                       false,
+                       "A compositor builder already exists or has already been built");
+        }
+        Builder::default()
+    }
+
+    /// Set callbacks for miscellaneous compositor events.
+    pub fn compositor_events(mut self, compositor_event_builder: EventBuilder) -> Self {
+        self.compositor_event_builder = Some(compositor_event_builder);
+        self
+    }
+
+    /// Set callbacks for managing input resources.
+    pub fn input_manager(mut self, input_manager_builder: input::manager::Builder) -> Self {
+        self.input_manager_builder = Some(input_manager_builder);
+        self
+    }
+
+    /// Set callbacks for managing output resources.
+    pub fn output_manager(mut self, output_manager_builder: output::manager::Builder) -> Self {
+        self.output_manager_builder = Some(output_manager_builder);
+        self
+    }
+
+    /// Set callbacks for managing XDG shell resources.
+    pub fn xdg_shell_manager(mut self,
+                             xdg_shell_manager_builder: xdg_shell::manager::Builder)
+                             -> Self {
+        self.xdg_shell_manager_builder = Some(xdg_shell_manager_builder);
+        self
+    }
+
+    /// Set callbacks for managing XDG shell v6 resources.
+    pub fn xdg_shell_v6_manager(mut self,
+                                xdg_v6_shell_manager_builder: xdg_shell_v6::manager::Builder)
+                                -> Self {
+        self.xdg_v6_shell_manager_builder = Some(xdg_v6_shell_manager_builder);
+        self
+    }
+
+    /// Decide whether or not to enable the wl_shm global.
+    ///
+    /// This is used to allocate shared memory between clients and the
+    /// compositor.
+    pub fn wl_shm(mut self, wl_shm: bool) -> Self {
+        self.wl_shm = wl_shm;
+        self
+    }
+
+    /// Decide whether or not to enable the data device manager.
+    ///
+    /// This is used to do DnD, or "drag 'n drop" copy paste.
+    pub fn data_device(mut self, data_device_manager: bool) -> Self {
+        self.data_device_manager = data_device_manager;
+        self
+    }
+
+    /// Decide whether or not to use the GLES2 renderer.
+    ///
+    /// This is the same as `renderer(RendererKind::Gles2)` or
+    /// `renderer(RendererKind::None)`.
+    pub fn gles2(mut self, gles2_renderer: bool) -> Self {
+        self.renderer = if gles2_renderer { RendererKind::Gles2 } else { RendererKind::None };
+        self
+    }
+
+    /// Pick the renderer the compositor draws with.
+    ///
+    /// By default there is none. Building fails if the backend can't provide
+    /// the renderer.
+    pub fn renderer(mut self, renderer: RendererKind) -> Self {
+        self.renderer = renderer;
+        self
+    }
+
+    /// Pick the backend `build` makes the compositor on.
+    ///
+    /// By default it's `BackendKind::Auto`.
+    pub fn backend(mut self, backend: BackendKind) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Decide whether or not to enable the server decoration manager protocol
+    /// extension.
+    #[cfg(feature = "server_decoration")]
+    pub fn server_decoration_manager(mut self, server_decoration_manager: bool) -> Self {
+        self.server_decoration_manager = server_decoration_manager;
+        self
+    }
+
+    /// Decide whether or not to enable the KDE idle protocol extension, used
+    /// by clients (e.g. screen lockers) to be told when the user is idle.
+    #[cfg(feature = "idle")]
+    pub fn idle_manager(mut self, idle_manager: bool) -> Self {
+        self.idle_manager = idle_manager;
+        self
+    }
+
+    /// Decide whether or not clients can take screenshots by themselves, see
+    /// `extensions::screenshooter`.
+    #[cfg(feature = "screencopy")]
+    pub fn screencopy(mut self, screencopy: bool) -> Self {
+        self.screencopy = screencopy;
+        self
+    }
+
+    /// Advertise the layer shell, used by panels, wallpapers and the like,
+    /// and tell the handler about their surfaces.
+    ///
+    /// The manager is then in `Compositor::layer_shell`.
+    #[cfg(feature = "layer_shell")]
+    pub fn layer_shell(mut self, handler: Box<layer_shell::Handler>) -> Self {
+        self.layer_shell_handler = Some(handler);
+        self
+    }
+
+    /// Set callbacks for managing XWayland resources.
+    ///
+    /// If this function is not called then the xwayland server does not run.
+    #[cfg(feature = "xwayland")]
+    pub fn xwayland(mut self, xwayland: xwayland::manager::Builder) -> Self {
+        self.xwayland = Some(xwayland);
+        self
+    }
+
+    /// Decide whether the selections and drag and drop of X11 clients are
+    /// bridged with Wayland clients.
+    ///
+    /// When enabled, the first seat that's created is given to XWayland (see
+    /// `xwayland::Server::set_seat`). This needs `xwayland` and, for drag and
+    /// drop, `data_device_manager`.
+    #[cfg(feature = "xwayland")]
+    pub fn xwayland_dnd(mut self, xwayland_dnd: bool) -> Self {
+        self.xwayland_dnd = xwayland_dnd;
+        self
+    }
+
+    /// Add a custom function to run when shutting down the compositor
+    /// or whenever a function in a callback panics.
+    pub fn custom_terminate(mut self, terminate: fn()) -> Self {
+        self.user_terminate = Some(terminate);
+        self
+    }
+
+    /// Give clients some time to disconnect on their own when shutting down,
+    /// before they are forcibly disconnected.
+    ///
+    /// Clients are only asked to exit by the compositor itself, e.g. by closing
+    /// their toplevels in the `on_shutdown` callback.
+    ///
+    /// By default clients are disconnected immediately.
+    pub fn shutdown_grace_period(mut self, grace_period: Duration) -> Self {
+        self.shutdown_grace_period = Some(grace_period);
+        self
+    }
+
+    /// Decide what happens when one of the callbacks panics.
+    ///
+    /// By default the compositor is terminated, see `PanicPolicy`.
+    pub fn panic_policy(mut self, policy: PanicPolicy) -> Self {
+        self.panic_policy = policy;
+        self
+    }
+
+    /// Watch for callbacks that block the compositor, logging them or
+    /// aborting when they run for longer than the threshold.
+    ///
+    /// See `utils::Watchdog`. By default callbacks aren't timed.
+    pub fn watchdog(mut self, watchdog: Watchdog) -> Self {
+        self.watchdog = Some(watchdog);
+        self
+    }
+
+    /// Start an IPC server, which dispatches the requests of IPC clients to
+    /// the handler.
+    ///
+    /// See the `ipc` module for the protocol.
+    #[cfg(feature = "ipc")]
+    pub fn ipc(mut self, handler: Box<::ipc::Handler>) -> Self {
+        self.ipc_handler = Some(handler);
+        self
+    }
+
+    /// Connect to D-Bus, to delay system sleep until the handler is done
+    /// preparing for it and to let applications inhibit the screen saver.
+    ///
+    /// See the `dbus` module for the services that are used.
+    #[cfg(feature = "dbus")]
+    pub fn dbus(mut self, handler: Box<::dbus::Handler>) -> Self {
+        self.dbus_handler = Some(handler);
+        self
+    }
+
+    /// Decide which globals each client can see and bind to.
+    ///
+    /// The filter is called with the client and the global whenever the
+    /// global would be advertised to, or bound by, that client. Returning
+    /// `false` hides the global from the client.
+    ///
+    /// This is useful to restrict privileged protocols (e.g. screencopy or
+    /// virtual keyboards) to trusted clients, using `Client::credentials`.
+    ///
+    /// If the filter panics the global is hidden and the compositor shuts down.
+    pub fn global_filter<F>(mut self, filter: F) -> Self
+        where F: FnMut(Client, Global) -> bool + 'static
+    {
+        self.global_filter = Some(Box::new(filter));
+        self
+    }
+
+    /// Never advertise the global with the interface name (e.g.
+    /// `wl_data_device_manager`), as if the compositor didn't implement it.
+    ///
+    /// The global is still created, so this is mostly useful to check how
+    /// clients cope without a protocol in tests.
+    pub fn without_global<S: Into<String>>(mut self, interface: S) -> Self {
+        self.hidden_globals.push(interface.into());
+        self
+    }
+
+    /// Limit the resources each client can allocate, see `ResourceLimits`.
+    ///
+    /// A client that goes over a limit is disconnected. By default there are
+    /// no limits.
+    pub fn resource_limits(mut self, limits: ResourceLimits) -> Self {
+        self.resource_limits = limits;
+        self
+    }
+
+    /// Decide which allocations each client can make, on top of the
+    /// `resource_limits`.
+    ///
+    /// The filter is called with the client and the allocation whenever the
+    /// client creates a surface or commits a buffer. Returning `false`
+    /// disconnects the client.
+    ///
+    /// If the filter panics the allocation is denied and the compositor
+    /// shuts down.
+    pub fn allocation_filter<F>(mut self, filter: F) -> Self
+        where F: FnMut(Client, Allocation) -> bool + 'static
+    {
+        self.allocation_filter = Some(Box::new(filter));
+        self
+    }
+
+    /// Call the function when a client is disconnected for being denied an
+    /// allocation, e.g. to tell the user why the application went away.
+    ///
+    /// The function is called while the request of the client is being
+    /// dispatched, so it must not use the client, see `LimitExceeded`.
+    pub fn on_limit_exceeded<F>(mut self, callback: F) -> Self
+        where F: FnMut(Client, Allocation) + 'static
+    {
+        self.on_limit_exceeded = Some(Box::new(callback));
+        self
+    }
+
+    /// Give an unsafe function to setup the renderer instead of the default renderer.
+    pub unsafe fn render_setup_function(mut self, func: UnsafeRenderSetupFunction) -> Self {
+        self.render_setup_function = Some(func);
+        self
+    }
+
+    /// Force the kind of session `build_auto` uses when running on a TTY,
+    /// instead of picking the first one that works.
+    ///
+    /// A `Noop` session makes `build_auto` use the headless backend, as
+    /// there's no way to get at the devices without a session.
+    pub fn session(mut self, kind: SessionKind) -> Self {
+        self.session = Some(kind);
+        self
+    }
+
+    /// Makes a new compositor on the backend picked with `backend`.
+    ///
+    /// Also opens the socket for clients to communicate to the compositor
+    /// with.
+    ///
+    /// Returns an error if the backend, the renderer, the socket or one of the
+    /// protocols, services or XWayland that were asked for can't be set up.
+    pub fn build<D>(self, data: D) -> Result<Compositor, Error>
+        where D: Any + 'static
+    {
+        let backend = self.backend;
+        match backend {
+            BackendKind::Auto => self.try_build_auto(data),
+            BackendKind::Headless => self.try_build_headless(data),
+            BackendKind::X11 => self.build_x11(data),
+            BackendKind::Wayland => self.build_wayland(data)
+        }
+    }
+
+    /// Makes a new compositor that handles the setup of the graphical backend
+    /// (e.g, Wayland, X11, or DRM).
+    ///
+    /// Also automatically opens the socket for clients to communicate to the
+    /// compositor with.
+    ///
+    /// Panics if the compositor could not be built, see `try_build_auto`.
+    pub fn build_auto<D>(self, data: D) -> Compositor
+        where D: Any + 'static
+    {
+        match self.try_build_auto(data) {
+            Ok(compositor) => compositor,
+            Err(err) => panic!("Could not build the compositor: {}", err)
+        }
+    }
+
+    /// Makes a new compositor like `build_auto`, returning an error if it
+    /// could not be built.
+    pub fn try_build_auto<D>(self, data: D) -> Result<Compositor, Error>
+        where D: Any + 'static
+    {
+        if self.session == Some(SessionKind::Noop) {
+            return self.try_build_headless(data)
+        }
+        unsafe {
+            self.build_on_new_display(data, |builder, display| {
+                backend::Multi::try_auto_create(display as *mut _,
+                                                builder.session,
+                                                builder.render_setup_function)
+                    .map(Backend::Multi)
+            })
+        }
+    }
+
+    /// Makes a new compositor like `try_build_auto`, on a display that was
+    /// created elsewhere (e.g. a wayland-rs `Display` in `sys` mode), so
+    /// protocols can be implemented by hand on the same display.
+    ///
+    /// # Safety
+    /// The display must stay alive for as long as the compositor. The
+    /// compositor disconnects the clients of the display when it's dropped,
+    /// but leaves destroying the display to its owner.
+    ///
+    /// The event loop of the display must only be dispatched by the
+    /// compositor (or from within `Compositor::run_with`).
+    pub unsafe fn from_foreign_display<D>(mut self,
+                                          data: D,
+                                          display: *mut wl_display)
+                                          -> Result<Compositor, Error>
+        where D: Any + 'static
+    {
+        if self.renderer == RendererKind::Pixman {
+            self.render_setup_function = Some(pixman::setup);
+        }
+        let event_loop = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_display_get_event_loop, display);
+        let backend = if self.session == Some(SessionKind::Noop) {
+            Backend::Headless(backend::Headless::try_new(display as *mut _,
+                                                         self.render_setup_function)?)
+        } else {
+            Backend::Multi(backend::Multi::try_auto_create(display as *mut _,
+                                                           self.session,
+                                                           self.render_setup_function)?)
+        };
+        self.finish_build(data, display, event_loop, backend)
+    }
+
+    /// Set the name of the Wayland remote socket to connect to when using the Wayland backend.
+    ///
+    /// (e.g. `wayland-0`, which is usually the default).
+    pub fn wayland_remote(mut self, remote: String) -> Self {
+        self.wayland_remote = Some(remote);
+        self
+    }
+
+    /// Set the name of the X11 display socket to be used to connect to a running X11 instance for
+    /// the backend.
+    pub fn x11_display(mut self, remote: String) -> Self {
+        self.x11_display = Some(remote);
+        self
+    }
+
+    /// Set the name of the socket clients connect to (e.g. `wayland-5`).
+    ///
+    /// By default the first free `wayland-N` name is used.
+    pub fn socket_name(mut self, name: String) -> Self {
+        self.socket = socket::Socket::Named(name);
+        self
+    }
+
+    /// Accept clients on an already opened and listening socket instead of
+    /// creating one, e.g. for systemd socket activation.
+    ///
+    /// `name` is what `WAYLAND_DISPLAY` is set to for clients.
+    ///
+    /// The compositor takes ownership of the file descriptor.
+    pub fn socket_fd(mut self, fd: RawFd, name: String) -> Self {
+        self.socket = socket::Socket::Fd(fd, name);
+        self
+    }
+
+    /// Creates the compositor using a running X11 server as a backend.
+    pub fn build_x11<D>(self, data: D) -> Result<Compositor, Error>
+        where D: Any + 'static
+    {
+        unsafe {
+            self.build_on_new_display(data, |builder, display| {
+                backend::X11::new(display as *mut _,
+                                  builder.x11_display.take(),
+                                  builder.render_setup_function)
+                    .map(Backend::X11)
+            })
+        }
+    }
+
+    /// Creates the compositor using an already running Wayland instance as a backend.
+    ///
+    /// The instance starts with no outputs.
+    pub fn build_wayland<D>(self, data: D) -> Result<Compositor, Error>
+        where D: Any + 'static
+    {
+        unsafe {
+            self.build_on_new_display(data, |builder, display| {
+                backend::Wayland::new(display as *mut _,
+                                      builder.wayland_remote.take(),
+                                      builder.render_setup_function)
+                    .map(Backend::Wayland)
+            })
+        }
+    }
+
+    pub unsafe fn build_drm<D>(self,
+                               data: D,
+                               session: Session,
+                               gpu_fd: libc::c_int,
+                               parent: Option<backend::Drm>)
+                               -> Result<Compositor, Error>
+        where D: Any + 'static
+    {
+        self.build_on_new_display(data, |builder, display| {
+            backend::Drm::new(display as *mut _,
+                              session,
+                              gpu_fd,
+                              parent,
+                              builder.render_setup_function)
+                .map(Backend::DRM)
+        })
+    }
+
+    pub fn build_headless<D>(self, data: D) -> Compositor
+        where D: Any + 'static
+    {
+        match self.try_build_headless(data) {
+            Ok(compositor) => compositor,
+            Err(err) => panic!("Could not construct Headless backend: {}", err)
+        }
+    }
+
+    /// Makes a new compositor like `build_headless`, returning an error if it
+    /// could not be built.
+    pub fn try_build_headless<D>(self, data: D) -> Result<Compositor, Error>
+        where D: Any + 'static
+    {
+        unsafe {
+            self.build_on_new_display(data, |builder, display| {
+                backend::Headless::try_new(display as *mut _, builder.render_setup_function)
+                    .map(Backend::Headless)
+            })
+        }
+    }
+
+    /// Makes the compositor on a new display, with the backend made by
+    /// `create_backend`.
+    ///
+    /// The display is destroyed again if the compositor could not be built.
+    unsafe fn build_on_new_display<D, F>(mut self,
+                                         data: D,
+                                         create_backend: F)
+                                         -> Result<Compositor, Error>
+        where D: Any + 'static,
+              F: FnOnce(&mut Self, *mut wl_display) -> Result<Backend, BackendError>
+    {
+        if self.renderer == RendererKind::Pixman {
+            // NOTE The backends make their renderer themselves.
+            self.render_setup_function = Some(pixman::setup);
+        }
+        let display = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_display_create,) as *mut wl_display;
+        let event_loop = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_display_get_event_loop, display);
+        let res = match create_backend(&mut self, display) {
+            Ok(backend) => self.finish_build(data, display, event_loop, backend),
+            Err(err) => Err(err.into())
+        };
+        if res.is_err() {
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_display_destroy, display);
+        }
+        res
+    }
+
+    /// Set up what can fail when building, before anything else is set up.
+    unsafe fn setup_fallible(&mut self,
+                             display: *mut wl_display,
+                             backend: &Backend)
+                             -> Result<FallibleSetup, Error> {
+        let renderer = match (self.renderer, backend) {
+            (RendererKind::Gles2, _) => Some(GenericRenderer::gles2_renderer(backend.as_ptr())?),
+            (RendererKind::Pixman, &Backend::Headless(_)) => {
+                Some(GenericRenderer::pixman_renderer(backend.as_ptr())?)
+            },
+            (RendererKind::Pixman, _) => return Err(RenderError::Unsupported.into()),
+            (RendererKind::None, _) => None
+        };
+        // Open the socket to the Wayland server.
+        let socket_name = self.socket.add(display).ok_or(Error::Socket)?;
+        #[cfg(feature = "layer_shell")]
+        let layer_shell = match self.layer_shell_handler.take() {
+            Some(handler) => Some(layer_shell::Manager::new(display as *mut _, handler)?),
+            None => None
+        };
+        #[cfg(feature = "screencopy")]
+        let screenshooter = if self.screencopy {
+            Some(screenshooter::Manager::new(display)?)
+        } else {
+            None
+        };
+        #[cfg(feature = "server_decoration")]
+        let server_decoration_manager = if self.server_decoration_manager {
+            let manager = server_decoration::Manager::new(display);
+            Some(manager.ok_or(Error::Protocol("org_kde_kwin_server_decoration_manager"))?)
+        } else {
+            None
+        };
+        #[cfg(feature = "idle")]
+        let idle_manager = if self.idle_manager {
+            Some(idle::Manager::new(display).ok_or(Error::Protocol("org_kde_kwin_idle"))?)
+        } else {
+            None
+        };
+        let data_device_manager = if self.data_device_manager {
+            let manager = data_device::Manager::new(display as _);
+            Some(manager.ok_or(Error::Protocol("wl_data_device_manager"))?)
+        } else {
+            None
+        };
+        #[cfg(any(feature = "ipc", feature = "dbus"))]
+        let event_loop = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_display_get_event_loop, display);
+        #[cfg(feature = "ipc")]
+        let ipc = match self.ipc_handler.take() {
+            Some(handler) => match ::ipc::Server::new(event_loop, &socket_name, handler) {
+                Ok(server) => Some(server),
+                Err(err) => {
+                    wlr_log!(WLR_ERROR, "Could not start the IPC server: {}", err);
+                    return Err(Error::Service("IPC server"))
+                }
+            },
+            None => None
+        };
+        #[cfg(feature = "dbus")]
+        let dbus = match self.dbus_handler.take() {
+            Some(handler) => match ::dbus::Dbus::new(event_loop, handler) {
+                Ok(dbus) => Some(dbus),
+                Err(err) => {
+                    wlr_log!(WLR_ERROR, "Could not connect to D-Bus: {}", err);
+                    return Err(Error::Service("D-Bus connection"))
+                }
+            },
+            None => None
+        };
+        Ok(FallibleSetup { renderer,
+                           socket_name,
+                           #[cfg(feature = "layer_shell")]
+                           layer_shell,
+                           #[cfg(feature = "screencopy")]
+                           screenshooter,
+                           #[cfg(feature = "server_decoration")]
+                           server_decoration_manager,
+                           #[cfg(feature = "idle")]
+                           idle_manager,
+                           data_device_manager,
+                           #[cfg(feature = "ipc")]
+                           ipc,
+                           #[cfg(feature = "dbus")]
+                           dbus })
+    }
+
+    unsafe fn finish_build<D>(mut self,
+                              data: D,
+                              display: *mut wl_display,
+                              event_loop: *mut wl_event_loop,
+                              backend: Backend)
+                              -> Result<Compositor, Error>
+    where D: Any + 'static {
+        // NOTE Anything set up after this is undone by dropping the
+        // compositor, but it can't be built yet so the backend is destroyed
+        // by hand.
+        let setup = match self.setup_fallible(display, &backend) {
+            Ok(setup) => setup,
+            Err(err) => {
+                wlr_backend_destroy(backend.as_ptr());
+                return Err(err)
+            }
+        };
+        let FallibleSetup { renderer,
+                            socket_name,
+                            #[cfg(feature = "layer_shell")]
+                            layer_shell,
+                            #[cfg(feature = "screencopy")]
+                            screenshooter,
+                            #[cfg(feature = "server_decoration")]
+                            server_decoration_manager,
+                            #[cfg(feature = "idle")]
+                            idle_manager,
+                            data_device_manager,
+                            #[cfg(feature = "ipc")]
+                            ipc,
+                            #[cfg(feature = "dbus")]
+                            dbus } = setup;
+
+        // Set up the wl_compositor and wl_subcompositor globals,
+        // along with the renderer if there is one.
+        let compositor = wlr_compositor_create(display as *mut _,
+                                               renderer.as_ref()
+                                                       .map_or(ptr::null_mut(),
+                                                               |renderer| renderer.as_ptr()));
+
+        // Set up the XWayland server, if the user wants it. It's the last
+        // thing that can fail, so only the wl_compositor has to be undone.
+        #[cfg(feature = "xwayland")]
+        let xwayland = match self.xwayland.take() {
+            Some(builder) => {
+                match xwayland::Server::new(display as _,
+                                            compositor,
+                                            builder,
+                                            false,
+                                            self.xwayland_dnd) {
+                    Some(server) => Some(server),
+                    None => {
+                        wlr_compositor_destroy(compositor);
+                        // NOTE The renderer belongs to the backend.
+                        drop(renderer);
+                        wlr_backend_destroy(backend.as_ptr());
+                        return Err(Error::Create("wlr_xwayland"))
+                    }
+                }
+            },
+            None => None
+        };
+
+        // Set up shared memory buffer for Wayland clients.
+        let wl_shm_fd = if self.wl_shm {
+            Some(ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                               wl_display_init_shm,
+                               display as *mut _))
+        } else {
+            None
+        };
+
+        // Set up compositor event callbacks, if the user provided it.
+        let compositor_handler = self.compositor_event_builder.take()
+            // NOTE if it's not defined, we still need to have it execute
+            // the code above to properly set up wayland surfaces.
+            .or_else(|| Some(EventBuilder::default()))
+            .map(|mut builder| {
+                if builder.surface_added.is_none() {
+                    builder = builder.surface_added(|_,_|{});
+                }
+                let compositor_handler = InternalCompositor::build(builder);
+                wl_signal_add(&mut (*compositor).events.new_surface as *mut _ as _,
+                              (&mut compositor_handler.new_surface_listener) as *mut _ as _);
+                wl_signal_add(&mut (*compositor).events.destroy as *mut _ as _,
+                              (&mut compositor_handler.shutdown_listener) as *mut _ as _);
+                compositor_handler
+        });
+
+        // Set up input manager, if the user provided it.
+        let input_manager = self.input_manager_builder.take().map(|builder| {
+            let input_manager = input::Manager::build(builder);
+            wl_signal_add(&mut (*backend.as_ptr()).events.new_input as *mut _ as _,
+                          (&mut input_manager.add_listener) as *mut _ as _);
+            input_manager
+        });
+
+        // Set up output manager, if the user provided it.
+        let output_manager = self.output_manager_builder.take().map(|builder| {
+            let output_manager = output::Manager::build(builder);
+            wl_signal_add(&mut (*backend.as_ptr()).events.new_output as *mut _ as _,
+                          (&mut output_manager.add_listener) as *mut _ as _);
+            output_manager
+        });
+
+        // Set up the xdg_shell handler and associated Wayland global,
+        // if user provided a manager for it.
+        let mut xdg_shell_global = ptr::null_mut();
+        let xdg_shell_manager = self.xdg_shell_manager_builder.take().map(|builder| {
+            xdg_shell_global = wlr_xdg_shell_create(display as *mut _);
+            let xdg_shell_manager = xdg_shell::Manager::build(builder);
+            wl_signal_add(&mut (*xdg_shell_global).events.new_surface as *mut _ as _,
+                          (&mut xdg_shell_manager.add_listener) as *mut _ as _);
+            xdg_shell_manager
+        });
+
+        // Set up the xdg_shell_v6 handler and associated Wayland global,
+        // if user provided a manager for it.
+        let mut xdg_v6_shell_global = ptr::null_mut();
+        let xdg_v6_shell_manager = self.xdg_v6_shell_manager_builder.take().map(|builder| {
+            xdg_v6_shell_global = wlr_xdg_shell_v6_create(display as *mut _);
+            let xdg_v6_shell_manager = xdg_shell_v6::Manager::build(builder);
+            wl_signal_add(&mut (*xdg_v6_shell_global).events.new_surface as *mut _ as _,
+                          (&mut xdg_v6_shell_manager.add_listener) as *mut _ as _);
+            xdg_v6_shell_manager
+        });
+
+        let user_terminate = self.user_terminate;
+        let shutdown_grace_period = self.shutdown_grace_period;
+
+        // Install the global filter before any client is accepted, which only
+        // happens once the event loop runs.
+        let registry = global::Registry::create(display,
+                                                self.global_filter.take(),
+                                                std::mem::replace(&mut self.hidden_globals,
+                                                                  Vec::new()));
+
+        let client_manager = ClientManager::create(display);
+
+        let limiter = if self.resource_limits != ResourceLimits::default()
+                         || self.allocation_filter.is_some() {
+            Some(limits::LimiterListener::create(compositor,
+                                                 self.resource_limits,
+                                                 self.allocation_filter.take(),
+                                                 self.on_limit_exceeded.take()))
+        } else {
+            None
+        };
+        debug::protocol_log::attach(display);
+
+        let child_reaper = spawn::add_reaper(event_loop);
+
+        let watchdog = self.watchdog.map(watchdog::Thread::start);
+
+        wlr_log!(WLR_DEBUG,
+                 "Running compositor on wayland display {}",
+                 socket_name);
+        env::set_var("_WAYLAND_DISPLAY", socket_name.clone());
+        let compositor = Compositor { data: Box::new(data),
+                                      compositor_handler,
+                                      socket_name,
+                                      input_manager,
+                                      output_manager,
+                                      xdg_shell_manager,
+                                      xdg_shell_global,
+                                      xdg_v6_shell_manager,
+                                      xdg_v6_shell_global,
+                                      data_device_manager,
+                                      compositor,
+                                      backend,
+                                      display,
+                                      event_loop,
+                                      wl_shm_fd,
+                                      #[cfg(feature = "server_decoration")]
+                                      server_decoration_manager,
+                                      #[cfg(feature = "idle")]
+                                      idle_manager,
+                                      #[cfg(feature = "layer_shell")]
+                                      layer_shell,
+                                      #[cfg(feature = "screencopy")]
+                                      screenshooter,
+                                      #[cfg(feature = "ipc")]
+                                      ipc,
+                                      #[cfg(feature = "dbus")]
+                                      dbus,
+                                      renderer,
+                                      #[cfg(feature = "xwayland")]
+                                      xwayland,
+                                      user_terminate,
+                                      panic_policy: self.panic_policy,
+                                      client_manager,
+                                      registry,
+                                      limiter,
+                                      child_reaper,
+                                      shutdown_grace_period,
+                                      watchdog,
+                                      panic_error: None,
+                                      lock: Rc::new(Cell::new(false)) };
+        // Forget so we can't construct another builder.
+        std::mem::forget(self);
+        compositor.set_lock(true);
+        Ok(compositor)
+    }
+}
+
+impl Drop for Builder {
+    fn drop(&mut self) {
+        unsafe {
+            // NOTE This will only happen if dropped outside of `finish_build`,
+            // which mem::forgets(self) in order to not be able to use a builder
+            // while the compositor is running.
+            assert_eq!(BUILDER_ACTIVE.compare_and_swap(true, false, Ordering::AcqRel),
+                       true,
+                       "Builder was in improper state");
+        }
+    }
+}
+
+impl Compositor {
+    /// Attempts to get the state struct the compositor was constructed with.
+    ///
+    /// # Panicking
+    /// If the data was not of the type specified in the type arguments this
+    /// function will panic.
+    pub fn downcast<D: 'static>(&mut self) -> &mut D {
+        self.data.downcast_mut::<D>()
+            .unwrap_or_else(|| {
+                wlr_log!(WLR_ERROR, "Incorrect type given for compositor state");
+                panic!("Could not cast compositor state to provided type")
+            })
+    }
+    /// Creates a weak reference to the `Compositor`.
+    pub fn weak_reference(&self) -> Handle {
+        let handle = Rc::downgrade(&self.lock);
+        Handle { handle }
+    }
+
+    /// Enters the wayland event loop. Won't return until the compositor is
+    /// shut off.
+    pub fn run(self) {
+        self.run_unborrowed(|| unsafe {
+                                ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                              wl_display_run,
+                                              (*COMPOSITOR_PTR).display);
+                            })
+    }
+
+    /// Prepare to enter the wayland event loop. Instead of calling
+    /// `wl_display_run`, the provided callback function is invoked. Allows
+    /// integration with a different event loop.
+    pub fn run_with<F>(self, runner: F)
+        where F: FnOnce(&Compositor)
+    {
+        self.run_unborrowed(|| unsafe { runner(&*COMPOSITOR_PTR) })
+    }
+
+    /// Like `run_with`, but without handing a reference to the compositor to
+    /// the runner, so it can upgrade `compositor::handle()` without aliasing
+    /// it.
+    pub(crate) fn run_unborrowed<F>(self, runner: F)
+        where F: FnOnce()
+    {
+        unsafe {
+            self.set_lock(false);
+            let compositor = UnsafeCell::new(self);
+            if COMPOSITOR_PTR != 0 as _ {
+                // NOTE Rationale for panicking:
+                // * Nicer than an abort
+                // * Not yet in C land
+                panic!("A compositor is already running!")
+            }
+            COMPOSITOR_PTR = compositor.get();
+            wlr_log!(WLR_INFO, "Starting compositor");
+            if !wlr_backend_start((*compositor.get()).backend.as_ptr()) {
+                // NOTE The backend is destroyed when the compositor is dropped.
+                // NOTE Rationale for panicking:
+                // * Won't be in C land just yet, so it's safe to panic
+                // * Can always be returned in a Result instead, but for now
+                //   if you auto create it's assumed you can't recover.
+                panic!("Failed to start backend");
+            }
+            env::set_var("WAYLAND_DISPLAY", (*COMPOSITOR_PTR).socket_name.clone());
+            runner();
+            match (*compositor.get()).panic_error.take() {
+                None => {}
+                Some(err) => {
+                    // A panic occured, now we can re-throw it safely.
+                    ::std::panic::resume_unwind(err)
+                }
+            }
+        }
+    }
+
+    /// Shutdown the wayland server
+    fn terminate(&mut self) {
+        unsafe {
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_display_terminate, self.display);
+        }
+    }
+
+    /// Get the name of the socket clients connect to.
+    ///
+    /// This is what `WAYLAND_DISPLAY` is set to once the compositor runs.
+    pub fn socket_name(&self) -> &str {
+        &self.socket_name
+    }
+
+    /// Get the globals advertised to clients, which aren't hidden from them
+    /// with `Builder::without_global`.
+    ///
+    /// This reflects the registry as of the last time the event loop ran.
+    /// The global filter isn't taken into account, see `Client::resources`
+    /// for what a particular client actually bound.
+    pub fn globals(&mut self) -> Vec<RegistryEntry> {
+        self.registry.globals()
+    }
+
+    /// Create a global implemented with wayland-server, for protocols this
+    /// crate doesn't wrap (e.g. vendor extensions).
+    ///
+    /// `bind` is called with the new resource and its version whenever a
+    /// client binds the global, and should implement the resource. The global
+    /// goes through the global filter like any other, and is removed when the
+    /// returned `CustomGlobal` is dropped.
+    ///
+    /// # Panicking
+    /// Panics if libwayland could not create the global, i.e. if `version`
+    /// is higher than the interface supports or it's out of memory.
+    pub fn create_global<I, F>(&mut self, version: u32, bind: F) -> CustomGlobal<I>
+        where I: wayland_server::Interface,
+              F: FnMut(wayland_server::NewResource<I>, u32) + 'static
+    {
+        unsafe {
+            CustomGlobal::create(self.display, version, Box::new(bind))
+                .expect("Could not create the global")
+        }
+    }
+
+    /// Open an additional socket clients can connect to, with the first free
+    /// `wayland-N` name.
+    ///
+    /// Returns the chosen name, or `None` if no socket could be opened.
+    pub fn add_socket_auto(&mut self) -> Option<String> {
+        unsafe { socket::add_socket_auto(self.display) }
+    }
+
+    /// Open an additional socket clients can connect to with the given name.
+    ///
+    /// Returns `false` if the socket could not be opened.
+    pub fn add_socket(&mut self, name: &str) -> bool {
+        unsafe { socket::add_socket(self.display, name).is_some() }
+    }
+
+    /// Get a reference to the currently running backend.
+    pub fn backend(&self) -> &Backend {
+        &self.backend
+    }
+
+    /// Get the kind of session the backend is using.
+    pub fn session_kind(&self) -> SessionKind {
+        self.backend.session_kind()
+    }
+
+    /// Get a mutable reference to the currently running backend.
+
+    pub fn backend_mut(&mut self) -> &mut Backend {
+        &mut self.backend
+    }
+
+    /// Run the `on_shutdown` callback, while everything is still alive and
+    /// the compositor can still be reached.
+    ///
+    /// A panic of the callback is re-thrown once the compositor is torn down.
+    unsafe fn notify_shutdown(&mut self) {
+        let on_shutdown = match self.compositor_handler {
+            Some(ref mut handler) => {
+                // NOTE This is normally triggered by the wlr_compositor being
+                // destroyed, but by then the clients are already gone.
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                              wl_list_remove,
+                              &mut handler.shutdown_listener.link as *mut _ as _);
+                handler.on_shutdown
+            },
+            None => None
+        };
+        if let Some(on_shutdown) = on_shutdown {
+            self.set_lock(true);
+            let res = panic::catch_unwind(panic::AssertUnwindSafe(|| on_shutdown(self)));
+            self.set_lock(false);
+            if let Err(err) = res {
+                self.save_panic_error(err);
+            }
+        }
+    }
+
+    /// Tears down the compositor, after the `on_shutdown` callback ran.
+    ///
+    /// XWayland is stopped first, then the clients get the grace period to
+    /// disconnect on their own before being disconnected, and finally the
+    /// backend is destroyed.
+    unsafe fn shutdown(&mut self) {
+        #[cfg(feature = "xwayland")]
+        self.xwayland.take();
+        self.wait_for_clients();
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_display_destroy_clients,
+                      self.display);
+        // NOTE The surfaces are gone with the clients, but the listener for
+        // new ones must go before the wlr_compositor.
+        self.limiter.take();
+        #[cfg(feature = "session_lock")]
+        ::extensions::session_lock::reset();
+        #[cfg(feature = "layer_shell")]
+        self.layer_shell.take();
+        if !self.child_reaper.is_null() {
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_event_source_remove,
+                          self.child_reaper);
+            self.child_reaper = ptr::null_mut();
+        }
+        self.registry.remove(self.display);
+        debug::protocol_log::detach();
+        wlr_compositor_destroy(self.compositor);
+        // NOTE The renderer belongs to the backend, so it must go first.
+        self.renderer.take();
+        wlr_backend_destroy(self.backend.as_ptr());
+    }
+
+    /// Dispatch events until all clients disconnect, or the grace period is up.
+    unsafe fn wait_for_clients(&mut self) {
+        let deadline = match self.shutdown_grace_period {
+            Some(grace_period) => Instant::now() + grace_period,
+            None => return
+        };
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_display_flush_clients, self.display);
+        while !clients().is_empty() {
+            let now = Instant::now();
+            if now >= deadline {
+                wlr_log!(WLR_INFO,
+                         "Disconnecting {} clients that did not exit in time",
+                         clients().len());
+                break
+            }
+            let remaining = deadline - now;
+            let timeout = remaining.as_secs() * 1000
+                + (remaining.subsec_nanos() / 1_000_000) as u64 + 1;
+            if ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                             wl_event_loop_dispatch,
+                             self.event_loop,
+                             timeout as libc::c_int) < 0 {
+                break
+            }
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_display_flush_clients, self.display);
+        }
+    }
+
+    /// Saves the panic error information in the compositor, to be re-thrown
+    /// later when we are out of the C callback stack.
+    pub(crate) fn save_panic_error(&mut self, error: Box<Any + Send>) {
+        self.panic_error = Some(error);
+    }
+
+    /// Manually set hte lock used to determine if a double-borrow is occuring on this structure.
+    ///
+    /// # Panics
+    /// Panics when trying to set the lock on an upgraded handle.
+    unsafe fn set_lock(&self, val: bool) {
+        self.lock.set(val)
+    }
+}
+
+impl Drop for Compositor {
+    fn drop(&mut self) {
+        unsafe {
+            assert_eq!(BUILDER_ACTIVE.compare_and_swap(true, false, Ordering::AcqRel),
+                       true,
+                       "Builder was in improper state");
+            self.notify_shutdown();
+            // NOTE Shutting down dispatches the event loop, and the callbacks
+            // it runs must not get at the compositor that's being dropped.
+            self.set_lock(true);
+            if COMPOSITOR_PTR == self as *mut _ {
+                COMPOSITOR_PTR = ptr::null_mut();
+            }
+            utils::begin_shutdown(self.panic_policy);
+            self.shutdown();
+            let panic_error = utils::end_shutdown().or_else(|| self.panic_error.take());
+            if let Some(err) = panic_error {
+                // NOTE Re-throwing while unwinding already would abort.
+                if !thread::panicking() {
+                    panic::resume_unwind(err)
+                }
+            }
+        }
+    }
+}
+
+impl Handle {
+    /// Constructs a new `compositor::Handle` that is always invalid. Calling `run` on this
+    /// will always fail.
+    ///
+    /// This is useful for pre-filling a value before it's provided by the server, or
+    /// for mocking/testing.
+    pub fn new() -> Self {
+        Handle { handle: Weak::new() }
+    }
+
+    /// Upgrades the compositor handle to a reference to the backing `Compositor`.
+    ///
+    /// # Unsafety
+    /// To be honest this function is probably safe.
+    ///
+    /// However, the `compositor::Handle` will behave like the other handles in order
+    /// to reduce confusion.
+    #[track_caller]
+    unsafe fn upgrade(&self) -> HandleResult<&mut Compositor> {
+        let location = Location::caller();
+        self.handle.upgrade()
+            .ok_or(HandleErr::AlreadyDropped)
+            // NOTE
+            // We drop the Rc here because having two would allow a dangling
+            // pointer to exist!
+            .and_then(|check| {
+                if check.get() {
+                    return Err(utils::already_borrowed::<Compositor>(&check))
+                }
+                if COMPOSITOR_PTR.is_null() {
+                    return Err(HandleErr::AlreadyDropped)
+                }
+                utils::borrow_lock(&check, location);
+                Ok(&mut *COMPOSITOR_PTR)
+            })
+    }
+
+    /// Get the raw pointer to the display of the compositor, e.g. to
+    /// implement a protocol by hand with wayland-rs in `sys` mode.
+    ///
+    /// Returns `None` if the compositor is gone. This doesn't borrow the
+    /// compositor, so it can be called from within `run`.
+    ///
+    /// The pointer is valid until the compositor is dropped. Don't destroy
+    /// the display or dispatch its event loop yourself.
+    pub fn display_ptr(&self) -> Option<*mut wl_display> {
+        self.handle.upgrade()?;
+        unsafe {
+            if COMPOSITOR_PTR.is_null() {
+                return None
+            }
+            Some((*COMPOSITOR_PTR).display)
+        }
+    }
+
+    /// Get the raw pointer to the event loop of the compositor, e.g. to add
+    /// event sources to it.
+    ///
+    /// Returns `None` if the compositor is gone. The same caveats as with
+    /// `display_ptr` apply.
+    pub fn event_loop_ptr(&self) -> Option<*mut wl_event_loop> {
+        self.handle.upgrade()?;
+        unsafe {
+            if COMPOSITOR_PTR.is_null() {
+                return None
+            }
+            Some((*COMPOSITOR_PTR).event_loop)
+        }
+    }
+
+    /// Run a function on the referenced `Compositor`, if it still exists.
+    ///
+    /// Returns the result of the function, if successful.
+    ///
+    /// # Safety
+    /// By enforcing a rather harsh limit on the lifetime of the Compositor
+    /// to a short lived scope of an anonymous function,
+    /// this function ensures the Compositor does not live longer
+    /// than it exists.
+    ///
+    /// # Panics
+    /// This function will panic if multiple mutable borrows are detected.
+    /// This will happen if you call `upgrade` directly within this callback,
+    /// or if you run this function within the another run to the same `Output`.
+    ///
+    /// So don't nest `run` calls and everything will be ok :).
+    #[track_caller]
+    pub fn run<F, R>(&self, runner: F) -> HandleResult<R>
+        where F: FnOnce(&mut Compositor) -> R
+    {
+        let compositor = unsafe { self.upgrade()? };
+        let res = panic::catch_unwind(panic::AssertUnwindSafe(|| runner(compositor)));
+        self.handle.upgrade().map(|check| {
+                                      // Sanity check that it hasn't been tampered with.
+                                      if !check.get() {
+                                          wlr_log!(WLR_ERROR,
+                                                   "After running compositor callback, mutable \
+                                                    lock was false");
+                                          panic!("Compositor lock in incorrect state!");
+                                      }
+                                      utils::release_lock(&check)
+                                  });
+        match res {
+            Ok(res) => Ok(res),
+            Err(err) => panic::resume_unwind(err)
+        }
+    }
+}
+
+/// Terminates the compositor and execute any user clean up code.
+pub fn terminate() {
+    unsafe {
+        if COMPOSITOR_PTR != 0 as _ {
+            let compositor = &mut *COMPOSITOR_PTR;
+            compositor.terminate();
+            compositor.user_terminate.map(|f| f());
+        }
+    }
+}
+
+/// Gets a handle to the compositor.
+///
+/// If the compositor has not started running yet, or if it has stopped,
+/// then this function will return None.
+pub fn handle() -> Option<Handle> {
+    unsafe {
+        if COMPOSITOR_PTR.is_null() {
+            None
+        } else {
+            Some((&mut *COMPOSITOR_PTR).weak_reference())
+        }
+    }
+}
@@ -0,0 +1,76 @@
+//! Helpers for the listening sockets clients connect to.
+
+use std::{ffi::{CStr, CString}, os::unix::io::RawFd};
+
+use libc::c_int;
+use wayland_sys::server::{wl_display, WAYLAND_SERVER_HANDLE};
+
+// NOTE This is not exposed by wayland-sys, but libwayland-server is
+// always linked by wlroots-sys.
+extern "C" {
+    fn wl_display_add_socket_fd(display: *mut wl_display, sock_fd: c_int) -> c_int;
+}
+
+/// Where the compositor should listen for clients.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum Socket {
+    /// Pick the first free `wayland-N` name.
+    Auto,
+    /// Use the socket with this name in `$XDG_RUNTIME_DIR`.
+    Named(String),
+    /// Use an already opened and listening socket (e.g. from systemd socket
+    /// activation), advertised to clients under this name.
+    Fd(RawFd, String)
+}
+
+impl Default for Socket {
+    fn default() -> Self {
+        Socket::Auto
+    }
+}
+
+impl Socket {
+    /// Start listening on the socket, returning the name of the socket.
+    pub(crate) unsafe fn add(&self, display: *mut wl_display) -> Option<String> {
+        match *self {
+            Socket::Auto => add_socket_auto(display),
+            Socket::Named(ref name) => add_socket(display, name),
+            Socket::Fd(fd, ref name) => {
+                if wl_display_add_socket_fd(display, fd) != 0 {
+                    wlr_log!(WLR_ERROR, "Unable to listen on socket fd {}", fd);
+                    return None
+                }
+                Some(name.clone())
+            }
+        }
+    }
+}
+
+/// Open a new socket with the first free `wayland-N` name.
+pub(crate) unsafe fn add_socket_auto(display: *mut wl_display) -> Option<String> {
+    let socket = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_display_add_socket_auto, display);
+    if socket.is_null() {
+        wlr_log!(WLR_ERROR, "Unable to find a free wayland socket name");
+        return None
+    }
+    Some(CStr::from_ptr(socket).to_string_lossy().into_owned())
+}
+
+/// Open a new socket with the given name.
+pub(crate) unsafe fn add_socket(display: *mut wl_display, name: &str) -> Option<String> {
+    let name_c = match CString::new(name) {
+        Ok(name_c) => name_c,
+        Err(_) => {
+            wlr_log!(WLR_ERROR, "Wayland socket name {:?} contains a nul byte", name);
+            return None
+        }
+    };
+    if ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                     wl_display_add_socket,
+                     display,
+                     name_c.as_ptr()) != 0 {
+        wlr_log!(WLR_ERROR, "Unable to open wayland socket {}", name);
+        return None
+    }
+    Some(name.into())
+}
@@ -0,0 +1,95 @@
+//! Launching clients of the compositor.
+//!
+//! Spawned processes get `WAYLAND_DISPLAY` (and `DISPLAY`, when XWayland is
+//! running) pointing at this compositor, and are reaped from the event loop
+//! when they exit so they don't linger as zombies.
+
+use std::{cell::RefCell, collections::HashMap, io, process::Command, ptr};
+
+use libc::{self, c_int, c_void, pid_t};
+use wayland_sys::server::{wl_event_loop, wl_event_source, WAYLAND_SERVER_HANDLE};
+
+use super::COMPOSITOR_PTR;
+use utils;
+
+/// Callback that's triggered when a spawned process exits.
+///
+/// The argument is the raw status returned by `waitpid`.
+pub type ChildExited = Box<FnMut(pid_t, c_int)>;
+
+thread_local! {
+    /// The processes spawned by the compositor that haven't been reaped yet.
+    static CHILDREN: RefCell<HashMap<pid_t, Option<ChildExited>>> =
+        RefCell::new(HashMap::new());
+}
+
+/// Spawn the command with `sh -c`, so it can connect to the compositor.
+///
+/// Returns the pid of the new process.
+///
+/// This can only be called once the compositor is running.
+pub fn spawn(command: &str) -> io::Result<pid_t> {
+    let mut shell = Command::new("/bin/sh");
+    shell.arg("-c").arg(command);
+    spawn_command(shell, None)
+}
+
+/// Spawn the command, calling `on_exit` once it exits.
+///
+/// `WAYLAND_DISPLAY` and `DISPLAY` are overridden to refer to this compositor.
+///
+/// Returns the pid of the new process.
+///
+/// This can only be called once the compositor is running.
+pub fn spawn_command(mut command: Command, on_exit: Option<ChildExited>) -> io::Result<pid_t> {
+    unsafe {
+        if COMPOSITOR_PTR.is_null() {
+            return Err(io::Error::new(io::ErrorKind::Other, "The compositor is not running"))
+        }
+        let compositor = &*COMPOSITOR_PTR;
+        command.env("WAYLAND_DISPLAY", compositor.socket_name());
+        #[cfg(feature = "xwayland")]
+        match compositor.xwayland {
+            Some(ref xwayland) => command.env("DISPLAY", format!(":{}", xwayland.display())),
+            None => command.env_remove("DISPLAY")
+        };
+        #[cfg(not(feature = "xwayland"))]
+        command.env_remove("DISPLAY");
+    }
+    let child = command.spawn()?;
+    let pid = child.id() as pid_t;
+    // NOTE `Child` doesn't wait on drop, the reaper takes care of it.
+    CHILDREN.with(|children| children.borrow_mut().insert(pid, on_exit));
+    Ok(pid)
+}
+
+/// Start reaping spawned processes when they exit.
+pub(crate) unsafe fn add_reaper(event_loop: *mut wl_event_loop) -> *mut wl_event_source {
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_event_loop_add_signal,
+                  event_loop,
+                  libc::SIGCHLD,
+                  reaper_notify,
+                  ptr::null_mut())
+}
+
+unsafe extern "C" fn reaper_notify(_signal: c_int, _data: *mut c_void) -> c_int {
+    // NOTE Only our own children are waited on, other child processes
+    // (e.g. XWayland) are reaped by whoever spawned them.
+    let pids: Vec<pid_t> = CHILDREN.with(|children| children.borrow().keys().cloned().collect());
+    for pid in pids {
+        let mut status = 0;
+        if libc::waitpid(pid, &mut status, libc::WNOHANG) != pid {
+            continue
+        }
+        let on_exit = CHILDREN.with(|children| children.borrow_mut().remove(&pid));
+        if let Some(Some(mut on_exit)) = on_exit {
+            wlr_span!("compositor::child_exited");
+            let res = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+                                                      on_exit(pid, status)
+                                                  }));
+            utils::handle_unwind(res);
+        }
+    }
+    0
+}
@@ -0,0 +1,482 @@
+//! Declarative configuration of outputs and input devices.
+//!
+//! A `Config` can be deserialized with serde from any format (e.g. TOML or
+//! JSON). Once applied it is used for every output and input device, including
+//! the ones plugged in later.
+//!
+//! Outputs are matched by name (e.g. `DP-1`), input devices by their
+//! identifier (see `input::Device::identifier`). The `*` entry matches
+//! everything, and is overridden by the more specific entries.
+//!
+//! ```rust,ignore
+//! let config: Config = toml::from_str(r#"
+//!     [outputs.DP-1]
+//!     mode = { width = 2560, height = 1440, refresh = 144000 }
+//!     position = { x = 0, y = 0 }
+//!     scale = 1.5
+//!
+//!     [inputs."1739:52546:SYNA8008:00_06CB:CD42_Touchpad"]
+//!     tap = true
+//!     accel_speed = 0.3
+//! "#)?;
+//! config.output_layout(layout_handle).apply();
+//! ```
+
+use std::{cell::RefCell, collections::HashMap, mem, rc::Rc};
+
+use wlroots_sys::{libinput_config_accel_profile, wl_output_transform, wlr_keyboard_set_keymap,
+                  wlr_input_device_type::*};
+use xkbcommon::xkb;
+
+use {area::{Origin, Size},
+     backend::Libinput,
+     input,
+     output::{self, arrangement::Arrangement, layout, Output},
+     utils::{HandleErr, HandleResult, Handleable}};
+
+thread_local! {
+    /// The configuration that was applied last.
+    static CONFIG: RefCell<Option<Rc<Config>>> = RefCell::new(None);
+    /// The devices and outputs the configuration has been applied to, so it
+    /// can be applied to them again.
+    static DEVICES: RefCell<Vec<input::Handle>> = RefCell::new(Vec::new());
+    static OUTPUTS: RefCell<Vec<output::Handle>> = RefCell::new(Vec::new());
+}
+
+/// The entry that applies to every output or input device.
+const WILDCARD: &str = "*";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Settings for outputs, by output name.
+    pub outputs: HashMap<String, OutputConfig>,
+    /// Settings for input devices, by device identifier.
+    pub inputs: HashMap<String, InputConfig>,
+    /// The layout the outputs are positioned in.
+    #[serde(skip)]
+    layout: Option<layout::Handle>
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct OutputConfig {
+    pub enabled: Option<bool>,
+    pub mode: Option<ModeConfig>,
+    /// Where the output is placed in the output layout.
+    ///
+    /// Only used if the `Config` has an output layout.
+    pub position: Option<PositionConfig>,
+    pub scale: Option<f32>,
+    pub transform: Option<TransformConfig>
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize)]
+pub struct ModeConfig {
+    pub width: i32,
+    pub height: i32,
+    /// The refresh rate in mHz.
+    ///
+    /// If not set the mode with the highest refresh rate is picked.
+    #[serde(default)]
+    pub refresh: Option<i32>
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize)]
+pub struct PositionConfig {
+    pub x: i32,
+    pub y: i32
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize)]
+pub enum TransformConfig {
+    #[serde(rename = "normal")]
+    Normal,
+    #[serde(rename = "90")]
+    Rotate90,
+    #[serde(rename = "180")]
+    Rotate180,
+    #[serde(rename = "270")]
+    Rotate270,
+    #[serde(rename = "flipped")]
+    Flipped,
+    #[serde(rename = "flipped-90")]
+    Flipped90,
+    #[serde(rename = "flipped-180")]
+    Flipped180,
+    #[serde(rename = "flipped-270")]
+    Flipped270
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct InputConfig {
+    /// Pointer acceleration speed, in the range [-1, 1].
+    pub accel_speed: Option<f64>,
+    pub accel_profile: Option<AccelProfileConfig>,
+    /// Whether tap-to-click is enabled.
+    pub tap: Option<bool>,
+    /// How much faster (or slower, below 1) the device scrolls.
+    pub scroll_factor: Option<f64>,
+    /// The XKB layout of keyboards (e.g. `us,de`).
+    pub layout: Option<String>,
+    /// The XKB layout variant of keyboards (e.g. `dvorak`).
+    pub variant: Option<String>,
+    /// The XKB options of keyboards (e.g. `caps:escape`).
+    pub options: Option<String>
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize)]
+pub enum AccelProfileConfig {
+    #[serde(rename = "flat")]
+    Flat,
+    #[serde(rename = "adaptive")]
+    Adaptive
+}
+
+impl TransformConfig {
+    pub fn to_raw(self) -> output::Transform {
+        use self::wl_output_transform::*;
+        match self {
+            TransformConfig::Normal => WL_OUTPUT_TRANSFORM_NORMAL,
+            TransformConfig::Rotate90 => WL_OUTPUT_TRANSFORM_90,
+            TransformConfig::Rotate180 => WL_OUTPUT_TRANSFORM_180,
+            TransformConfig::Rotate270 => WL_OUTPUT_TRANSFORM_270,
+            TransformConfig::Flipped => WL_OUTPUT_TRANSFORM_FLIPPED,
+            TransformConfig::Flipped90 => WL_OUTPUT_TRANSFORM_FLIPPED_90,
+            TransformConfig::Flipped180 => WL_OUTPUT_TRANSFORM_FLIPPED_180,
+            TransformConfig::Flipped270 => WL_OUTPUT_TRANSFORM_FLIPPED_270
+        }
+    }
+}
+
+impl AccelProfileConfig {
+    pub fn to_raw(self) -> libinput_config_accel_profile {
+        use self::libinput_config_accel_profile::*;
+        match self {
+            AccelProfileConfig::Flat => LIBINPUT_CONFIG_ACCEL_PROFILE_FLAT,
+            AccelProfileConfig::Adaptive => LIBINPUT_CONFIG_ACCEL_PROFILE_ADAPTIVE
+        }
+    }
+}
+
+impl OutputConfig {
+    /// Fill in the unset options from the other configuration.
+    fn merge(&mut self, other: &OutputConfig) {
+        self.enabled = self.enabled.or(other.enabled);
+        self.mode = self.mode.or(other.mode);
+        self.position = self.position.or(other.position);
+        self.scale = self.scale.or(other.scale);
+        self.transform = self.transform.or(other.transform);
+    }
+}
+
+impl InputConfig {
+    /// Fill in the unset options from the other configuration.
+    fn merge(&mut self, other: &InputConfig) {
+        self.accel_speed = self.accel_speed.or(other.accel_speed);
+        self.accel_profile = self.accel_profile.or(other.accel_profile);
+        self.tap = self.tap.or(other.tap);
+        self.scroll_factor = self.scroll_factor.or(other.scroll_factor);
+        self.layout = self.layout.take().or_else(|| other.layout.clone());
+        self.variant = self.variant.take().or_else(|| other.variant.clone());
+        self.options = self.options.take().or_else(|| other.options.clone());
+    }
+}
+
+impl Config {
+    /// Position the outputs in this layout.
+    pub fn output_layout(mut self, layout: layout::Handle) -> Self {
+        self.layout = Some(layout);
+        self
+    }
+
+    /// Get the settings for the output with this name.
+    pub fn output(&self, name: &str) -> OutputConfig {
+        let mut config = self.outputs.get(name).cloned().unwrap_or_default();
+        if let Some(wildcard) = self.outputs.get(WILDCARD) {
+            config.merge(wildcard);
+        }
+        config
+    }
+
+    /// Get an arrangement that puts the outputs with a position at it.
+    ///
+    /// Use it for the layout so outputs without a position don't overlap
+    /// the others (see `output::layout::Layout::set_arrangement`).
+    pub fn arrangement(&self) -> Arrangement {
+        Arrangement::Remembered(self.outputs
+                                    .iter()
+                                    .filter(|&(name, _)| name != WILDCARD)
+                                    .filter_map(|(name, output)| {
+                                        output.position.map(|position| {
+                                            (name.clone(), Origin::new(position.x, position.y))
+                                        })
+                                    })
+                                    .collect())
+    }
+
+    /// Get the settings for the input device with this identifier.
+    pub fn input(&self, identifier: &str) -> InputConfig {
+        let mut config = self.inputs.get(identifier).cloned().unwrap_or_default();
+        if let Some(wildcard) = self.inputs.get(WILDCARD) {
+            config.merge(wildcard);
+        }
+        config
+    }
+
+    /// Apply the configuration to every output and input device, replacing
+    /// the previously applied configuration.
+    ///
+    /// The configuration is also applied to outputs and input devices
+    /// plugged in afterwards.
+    ///
+    /// Outputs and input devices that are in use (e.g. from the callback
+    /// this is called in) keep their previous settings until they're
+    /// configured again.
+    pub fn apply(self) {
+        CONFIG.with(|config| *config.borrow_mut() = Some(Rc::new(self)));
+        // NOTE The lists are taken out while the handles run, as applying
+        // the configuration can trigger callbacks that add to them.
+        let outputs = OUTPUTS.with(|outputs| mem::replace(&mut *outputs.borrow_mut(), Vec::new()));
+        let outputs = outputs.into_iter()
+                             .filter(|output| is_alive(configure_output(output)))
+                             .collect();
+        OUTPUTS.with(|added| {
+                         let mut added = added.borrow_mut();
+                         let added_since = mem::replace(&mut *added, outputs);
+                         added.extend(added_since)
+                     });
+        let devices = DEVICES.with(|devices| mem::replace(&mut *devices.borrow_mut(), Vec::new()));
+        let devices = devices.into_iter()
+                             .filter(|device| is_alive(configure_input(device)))
+                             .collect();
+        DEVICES.with(|added| {
+                         let mut added = added.borrow_mut();
+                         let added_since = mem::replace(&mut *added, devices);
+                         added.extend(added_since)
+                     });
+    }
+
+    fn apply_output(&self, output: &mut Output) {
+        let name = output.name();
+        let config = self.output(&name);
+        if let Some(enabled) = config.enabled {
+            output.enable(enabled);
+        }
+        if let Some(mode) = config.mode {
+            let best_mode = output.modes()
+                .into_iter()
+                .filter(|other| other.dimensions() == (mode.width, mode.height))
+                .filter(|other| mode.refresh.map(|refresh| other.refresh() == refresh)
+                                            .unwrap_or(true))
+                .max_by_key(|other| other.refresh());
+            let found = match best_mode {
+                Some(best_mode) => output.set_mode(best_mode),
+                None => false
+            };
+            if !found && !output.set_custom_mode(Size::new(mode.width, mode.height),
+                                                 mode.refresh.unwrap_or(0)) {
+                wlr_log!(WLR_ERROR, "Could not set mode {:?} on output {}", mode, name);
+            }
+        }
+        if let Some(scale) = config.scale {
+            output.set_scale(scale);
+        }
+        if let Some(transform) = config.transform {
+            output.transform(transform.to_raw());
+        }
+        if let (Some(position), Some(layout)) = (config.position, self.layout.as_ref()) {
+            let origin = Origin::new(position.x, position.y);
+            let res = layout.run(|layout| {
+                let handle = output.weak_reference();
+                let in_layout = layout.outputs()
+                                      .iter()
+                                      .any(|&(ref other, _)| *other == handle);
+                if in_layout {
+                    layout.move_output(output, origin);
+                } else {
+                    layout.add(output, origin);
+                }
+            });
+            if res.is_err() {
+                wlr_log!(WLR_ERROR, "Could not position output {}: {:?}", name, res);
+            }
+        }
+    }
+
+    fn apply_input(&self, device: &input::Device) {
+        let identifier = device.identifier();
+        let config = self.input(&identifier);
+        unsafe {
+            if let Some(speed) = config.accel_speed {
+                if let Err(err) = Libinput::set_accel_speed_unchecked(device, speed) {
+                    wlr_log!(WLR_DEBUG, "Could not set accel speed on {}: {:?}", identifier, err);
+                }
+            }
+            if let Some(profile) = config.accel_profile {
+                if let Err(err) = Libinput::set_accel_profile_unchecked(device, profile.to_raw()) {
+                    wlr_log!(WLR_DEBUG, "Could not set accel profile on {}: {:?}", identifier, err);
+                }
+            }
+            if let Some(tap) = config.tap {
+                if let Err(err) = Libinput::set_tap_enabled_unchecked(device, tap) {
+                    wlr_log!(WLR_DEBUG, "Could not set tapping on {}: {:?}", identifier, err);
+                }
+            }
+            if let Some(factor) = config.scroll_factor {
+                device.set_scroll_factor(factor);
+            }
+            let has_keymap = config.layout.is_some() || config.variant.is_some() ||
+                config.options.is_some();
+            if device.dev_type() == WLR_INPUT_DEVICE_KEYBOARD && has_keymap {
+                let layout = config.layout.unwrap_or_default();
+                let variant = config.variant.unwrap_or_default();
+                let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+                let keymap = xkb::Keymap::new_from_names(&context,
+                                                         "",
+                                                         "",
+                                                         layout.as_str(),
+                                                         variant.as_str(),
+                                                         config.options,
+                                                         xkb::KEYMAP_COMPILE_NO_FLAGS);
+                match keymap {
+                    Some(keymap) => wlr_keyboard_set_keymap(device.dev_union().keyboard,
+                                                            keymap.get_raw_ptr() as _),
+                    None => wlr_log!(WLR_ERROR, "Could not compile keymap for {}", identifier)
+                }
+            }
+        }
+    }
+}
+
+/// Run the function with the applied configuration, if there is one.
+///
+/// The configuration isn't borrowed while the function runs, so it can be
+/// replaced from the callbacks it triggers.
+fn with_config<F: FnOnce(&Config)>(func: F) {
+    let config = CONFIG.with(|config| config.borrow().clone());
+    if let Some(config) = config {
+        func(&config)
+    }
+}
+
+/// Apply the configuration to an output it was applied to before.
+fn configure_output(output: &output::Handle) -> HandleResult<()> {
+    output.run(|output| with_config(|config| config.apply_output(output)))
+}
+
+/// Apply the configuration to an input device it was applied to before.
+fn configure_input(device: &input::Handle) -> HandleResult<()> {
+    use input::Handle::*;
+    match *device {
+        Keyboard(ref handle) => handle.run(|keyboard| {
+            with_config(|config| config.apply_input(keyboard.input_device()))
+        }),
+        Pointer(ref handle) => handle.run(|pointer| {
+            with_config(|config| config.apply_input(pointer.input_device()))
+        }),
+        Touch(ref handle) => handle.run(|touch| {
+            with_config(|config| config.apply_input(touch.input_device()))
+        }),
+        TabletPad(ref handle) => handle.run(|pad| {
+            with_config(|config| config.apply_input(pad.input_device()))
+        }),
+        TabletTool(ref handle) => handle.run(|tool| {
+            with_config(|config| config.apply_input(tool.input_device()))
+        })
+    }
+}
+
+/// Whether the output or device should be kept, i.e. it wasn't dropped.
+///
+/// One that's in use right now is still kept.
+fn is_alive(res: HandleResult<()>) -> bool {
+    match res {
+        Err(HandleErr::AlreadyDropped) => false,
+        _ => true
+    }
+}
+
+/// Apply the configuration to a new output, and remember it for when the
+/// configuration is applied again.
+pub(crate) fn output_added(output: &mut Output) {
+    with_config(|config| config.apply_output(output));
+    OUTPUTS.with(|outputs| outputs.borrow_mut().push(output.weak_reference()));
+}
+
+/// Apply the configuration to a new input device, and remember it for when
+/// the configuration is applied again.
+pub(crate) fn input_added(device: &input::Device) {
+    with_config(|config| config.apply_input(device));
+    DEVICES.with(|devices| devices.borrow_mut().push(device.device()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        let mut config = Config::default();
+        config.outputs.insert(WILDCARD.into(),
+                              OutputConfig { scale: Some(2.0),
+                                             transform: Some(TransformConfig::Rotate90),
+                                             position: Some(PositionConfig { x: 5, y: 5 }),
+                                             ..OutputConfig::default() });
+        config.outputs.insert("DP-1".into(),
+                              OutputConfig { scale: Some(1.5),
+                                             position: Some(PositionConfig { x: 0, y: 0 }),
+                                             ..OutputConfig::default() });
+        config.outputs.insert("HDMI-A-1".into(),
+                              OutputConfig { position: Some(PositionConfig { x: 1920, y: 0 }),
+                                             ..OutputConfig::default() });
+        config.inputs.insert(WILDCARD.into(),
+                             InputConfig { tap: Some(true),
+                                           layout: Some("us".into()),
+                                           options: Some("caps:escape".into()),
+                                           ..InputConfig::default() });
+        config.inputs.insert("keyboard".into(),
+                             InputConfig { tap: Some(false),
+                                           layout: Some("de".into()),
+                                           ..InputConfig::default() });
+        config
+    }
+
+    #[test]
+    fn specific_outputs_override_the_wildcard() {
+        let output = config().output("DP-1");
+        assert_eq!(output.scale, Some(1.5));
+        assert_eq!(output.position, Some(PositionConfig { x: 0, y: 0 }));
+        assert_eq!(output.transform, Some(TransformConfig::Rotate90));
+        assert_eq!(output.enabled, None);
+        assert_eq!(output.mode, None);
+    }
+
+    #[test]
+    fn unknown_outputs_get_the_wildcard() {
+        let output = config().output("eDP-1");
+        assert_eq!(output.scale, Some(2.0));
+        assert_eq!(output.transform, Some(TransformConfig::Rotate90));
+        assert_eq!(Config::default().output("eDP-1").scale, None);
+    }
+
+    #[test]
+    fn specific_inputs_override_the_wildcard() {
+        let input = config().input("keyboard");
+        assert_eq!(input.tap, Some(false));
+        assert_eq!(input.layout, Some("de".into()));
+        assert_eq!(input.options, Some("caps:escape".into()));
+        assert_eq!(input.variant, None);
+        let input = config().input("touchpad");
+        assert_eq!(input.tap, Some(true));
+        assert_eq!(input.layout, Some("us".into()));
+    }
+
+    #[test]
+    fn arrangement_leaves_out_the_wildcard() {
+        let mut positions = HashMap::new();
+        positions.insert("DP-1".to_string(), Origin::new(0, 0));
+        positions.insert("HDMI-A-1".to_string(), Origin::new(1920, 0));
+        assert_eq!(config().arrangement(), Arrangement::Remembered(positions));
+    }
+}
@@ -0,0 +1,447 @@
+//! Integration with the D-Bus services compositors usually need.
+//!
+//! * The compositor takes a logind "delay" inhibitor lock for sleep, so it
+//!   gets a chance to prepare (e.g. lock the screen) in
+//!   `dbus::Handler::prepare_for_sleep` before the system suspends.
+//! * The compositor serves `org.freedesktop.ScreenSaver` on the session bus,
+//!   so applications (e.g. video players or browsers) can inhibit the idle
+//!   timers of the `idle` extension. The inhibitions of an application are
+//!   released when it leaves the bus (e.g. when it crashes).
+//!
+//! The connections are dispatched from the compositor's event loop, without
+//! ever blocking on them: method calls are sent without waiting for their
+//! replies, which are handled with the rest of the messages once they arrive.
+
+use std::{collections::HashMap, os::unix::io::{AsRawFd, RawFd}, panic};
+
+use libc::{self, c_int, c_void};
+use wayland_sys::server::{wl_event_loop, wl_event_source, WAYLAND_SERVER_HANDLE};
+use zbus::{self, Connection, Message, MessageType};
+use zvariant::Fd;
+
+use {compositor, utils::{self, WL_EVENT_READABLE}};
+
+const LOGIND_DESTINATION: &str = "org.freedesktop.login1";
+const LOGIND_PATH: &str = "/org/freedesktop/login1";
+const LOGIND_MANAGER: &str = "org.freedesktop.login1.Manager";
+const SCREENSAVER_NAME: &str = "org.freedesktop.ScreenSaver";
+const SCREENSAVER_PATH: &str = "/org/freedesktop/ScreenSaver";
+
+/// Handles the events from the D-Bus services.
+#[allow(unused_variables)]
+pub trait Handler {
+    /// Callback that is triggered right before the system goes to sleep
+    /// (`sleeping` is `true`), and after it wakes up again (`sleeping` is
+    /// `false`).
+    ///
+    /// The system doesn't go to sleep until this returns, so this is the
+    /// place to e.g. lock the screen.
+    fn prepare_for_sleep(&mut self, compositor_handle: compositor::Handle, sleeping: bool) {}
+
+    /// Callback that is triggered when an application starts or stops
+    /// inhibiting the screen saver.
+    ///
+    /// When inhibited the idle timers are already disabled.
+    fn screensaver_inhibited(&mut self,
+                             compositor_handle: compositor::Handle,
+                             inhibited: bool) {}
+}
+
+struct DbusState {
+    system: Connection,
+    session: Option<Connection>,
+    /// The logind inhibitor lock, released when it's closed.
+    sleep_inhibitor: Option<RawFd>,
+    /// The serial of the call taking the inhibitor lock, until it's replied.
+    pending_inhibit: Option<u32>,
+    /// The serial of the call owning the screen saver name, until it's
+    /// replied.
+    pending_name: Option<u32>,
+    /// Whether the screen saver name is ours.
+    serving_screensaver: bool,
+    /// The applications inhibiting the screen saver, with their unique name
+    /// on the bus, by cookie.
+    screensaver_inhibitors: HashMap<u32, (String, String)>,
+    next_cookie: u32,
+    handler: Box<Handler>
+}
+
+/// The connections to the system and session buses.
+pub struct Dbus {
+    sources: Vec<*mut wl_event_source>,
+    state: Box<DbusState>
+}
+
+impl Dbus {
+    /// Connect to the buses and dispatch them from the event loop.
+    ///
+    /// A session bus is optional, without one the screen saver can't be
+    /// inhibited.
+    pub(crate) unsafe fn new(event_loop: *mut wl_event_loop,
+                             handler: Box<Handler>)
+                             -> zbus::Result<Self> {
+        let system = Connection::new_system()?;
+        system.send_message(Message::method(None,
+                                            Some("org.freedesktop.DBus"),
+                                            "/org/freedesktop/DBus",
+                                            Some("org.freedesktop.DBus"),
+                                            "AddMatch",
+                                            &("type='signal',\
+                                               interface='org.freedesktop.login1.Manager',\
+                                               member='PrepareForSleep'"))?)?;
+        let (session, pending_name) = match Connection::new_session() {
+            Ok(session) => match serve_screensaver(&session) {
+                Ok(serial) => (Some(session), Some(serial)),
+                Err(err) => {
+                    wlr_log!(WLR_ERROR, "Could not serve {}: {}", SCREENSAVER_NAME, err);
+                    (None, None)
+                }
+            },
+            Err(err) => {
+                wlr_log!(WLR_INFO, "Not connecting to the session bus: {}", err);
+                (None, None)
+            }
+        };
+        let mut state = Box::new(DbusState { system,
+                                             session,
+                                             sleep_inhibitor: None,
+                                             pending_inhibit: None,
+                                             pending_name,
+                                             serving_screensaver: false,
+                                             screensaver_inhibitors: HashMap::new(),
+                                             next_cookie: 1,
+                                             handler });
+        state.inhibit_sleep();
+        let mut sources = vec![add_source(event_loop, state.system.as_raw_fd(), &mut state)];
+        if let Some(fd) = state.session.as_ref().map(|session| session.as_raw_fd()) {
+            sources.push(add_source(event_loop, fd, &mut state));
+        }
+        Ok(Dbus { sources, state })
+    }
+
+    /// Determines if an application is inhibiting the screen saver.
+    pub fn screensaver_inhibited(&self) -> bool {
+        !self.state.screensaver_inhibitors.is_empty()
+    }
+}
+
+impl Drop for Dbus {
+    fn drop(&mut self) {
+        unsafe {
+            for source in self.sources.drain(..) {
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_event_source_remove, source);
+            }
+        }
+        self.state.release_sleep();
+    }
+}
+
+impl DbusState {
+    /// Ask logind for the lock that delays sleep until we release it.
+    ///
+    /// The lock is taken once the reply arrives, in `inhibit_reply`.
+    fn inhibit_sleep(&mut self) {
+        if self.sleep_inhibitor.is_some() || self.pending_inhibit.is_some() {
+            return
+        }
+        let message = Message::method(None,
+                                      Some(LOGIND_DESTINATION),
+                                      LOGIND_PATH,
+                                      Some(LOGIND_MANAGER),
+                                      "Inhibit",
+                                      &("sleep",
+                                        "wlroots",
+                                        "Preparing the compositor for sleep",
+                                        "delay"));
+        match message.and_then(|message| self.system.send_message(message)) {
+            Ok(serial) => self.pending_inhibit = Some(serial),
+            Err(err) => wlr_log!(WLR_ERROR, "Could not take the logind sleep lock: {}", err)
+        }
+    }
+
+    fn inhibit_reply(&mut self, message: &Message, is_error: bool) -> zbus::Result<()> {
+        self.pending_inhibit = None;
+        if is_error {
+            let error: String = message.body().unwrap_or_default();
+            wlr_log!(WLR_ERROR, "Could not take the logind sleep lock: {}", error);
+            return Ok(())
+        }
+        let fd: Fd = message.body()?;
+        // NOTE The fd in the message is closed with it, so keep a copy.
+        self.sleep_inhibitor = Some(unsafe { libc::dup(fd.as_raw_fd()) });
+        Ok(())
+    }
+
+    fn name_reply(&mut self, message: &Message, is_error: bool) -> zbus::Result<()> {
+        self.pending_name = None;
+        // NOTE 1 is DBUS_REQUEST_NAME_REPLY_PRIMARY_OWNER.
+        if is_error || message.body::<u32>()? != 1 {
+            wlr_log!(WLR_ERROR, "Could not serve {}: the name is taken", SCREENSAVER_NAME);
+            return Ok(())
+        }
+        wlr_log!(WLR_DEBUG, "Serving {} on {}", SCREENSAVER_NAME, SCREENSAVER_PATH);
+        self.serving_screensaver = true;
+        Ok(())
+    }
+
+    fn release_sleep(&mut self) {
+        if let Some(fd) = self.sleep_inhibitor.take() {
+            unsafe { libc::close(fd) };
+        }
+    }
+
+    /// Handle all of the messages that arrived on the connection.
+    ///
+    /// Only as many messages are received as can be read without blocking,
+    /// the rest are handled once the connection is readable again.
+    ///
+    /// zbus only holds messages back in its own queue while it waits for the
+    /// reply to a blocking call, which is never made on these connections, so
+    /// every message that arrived is still waiting on the socket.
+    fn dispatch(&mut self, fd: RawFd) -> zbus::Result<()> {
+        let is_system = self.system.as_raw_fd() == fd;
+        while readable(fd) {
+            let message = if is_system {
+                self.system.receive_message()?
+            } else {
+                match self.session {
+                    Some(ref session) => session.receive_message()?,
+                    None => return Ok(())
+                }
+            };
+            // NOTE A message we can't make sense of mustn't keep the ones
+            // after it from being handled.
+            if let Err(err) = self.handle_message(is_system, &message) {
+                wlr_log!(WLR_ERROR, "Could not handle D-Bus message: {}", err);
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_message(&mut self, is_system: bool, message: &Message) -> zbus::Result<()> {
+        let header = message.header()?;
+        let member = header.member()?.unwrap_or("").to_string();
+        match (is_system, header.message_type()?) {
+            (_, MessageType::MethodReturn) => {
+                self.reply(is_system, header.reply_serial()?, message, false)?
+            },
+            (_, MessageType::Error) => {
+                self.reply(is_system, header.reply_serial()?, message, true)?
+            },
+            (true, MessageType::Signal) if member == "PrepareForSleep" => {
+                let sleeping: bool = message.body()?;
+                self.prepare_for_sleep(sleeping);
+            },
+            (false, MessageType::Signal) if member == "NameOwnerChanged" => {
+                let (name, _old_owner, new_owner): (String, String, String) = message.body()?;
+                if new_owner.is_empty() {
+                    self.name_lost(&name);
+                }
+            },
+            (false, MessageType::MethodCall) => {
+                let sender = header.sender()?.unwrap_or("").to_string();
+                let path = header.path()?.map(|path| path.as_str().to_string());
+                let interface = header.interface()?.map(|interface| interface.to_string());
+                self.screensaver_call(message, path, interface, &member, sender)?
+            },
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Handle the reply to one of our method calls.
+    fn reply(&mut self,
+             is_system: bool,
+             serial: Option<u32>,
+             message: &Message,
+             is_error: bool)
+             -> zbus::Result<()> {
+        if is_system && serial.is_some() && serial == self.pending_inhibit {
+            self.inhibit_reply(message, is_error)
+        } else if !is_system && serial.is_some() && serial == self.pending_name {
+            self.name_reply(message, is_error)
+        } else {
+            if is_error {
+                let error: String = message.body().unwrap_or_default();
+                wlr_log!(WLR_ERROR, "D-Bus call failed: {}", error);
+            }
+            Ok(())
+        }
+    }
+
+    fn prepare_for_sleep(&mut self, sleeping: bool) {
+        if let Some(compositor) = compositor::handle() {
+            self.handler.prepare_for_sleep(compositor, sleeping);
+        }
+        if sleeping {
+            // NOTE A lock that's still on its way is of no use anymore.
+            self.pending_inhibit = None;
+            self.release_sleep();
+        } else {
+            self.inhibit_sleep();
+        }
+    }
+
+    fn screensaver_call(&mut self,
+                        message: &Message,
+                        path: Option<String>,
+                        interface: Option<String>,
+                        member: &str,
+                        sender: String)
+                        -> zbus::Result<()> {
+        let was_inhibited = !self.screensaver_inhibitors.is_empty();
+        {
+            let session = match self.session {
+                Some(ref session) => session,
+                None => return Ok(())
+            };
+            let path = path.unwrap_or_default();
+            if !self.serving_screensaver || path != SCREENSAVER_PATH {
+                session.reply_error(message,
+                                    "org.freedesktop.DBus.Error.UnknownObject",
+                                    &format!("No such object {}", path))?;
+                return Ok(())
+            }
+            // NOTE The interface is optional in method calls, the member is
+            // then looked up in all of the interfaces of the object.
+            if let Some(interface) = interface {
+                if interface != SCREENSAVER_NAME {
+                    session.reply_error(message,
+                                        "org.freedesktop.DBus.Error.UnknownInterface",
+                                        &format!("No such interface {}", interface))?;
+                    return Ok(())
+                }
+            }
+            match member {
+                "Inhibit" => {
+                    let (application, _reason): (String, String) = match message.body() {
+                        Ok(body) => body,
+                        Err(_) => return invalid_args(session, message, "(ss)")
+                    };
+                    let cookie = self.next_cookie;
+                    self.next_cookie = self.next_cookie.wrapping_add(1).max(1);
+                    wlr_log!(WLR_DEBUG, "{} inhibited the screen saver", application);
+                    self.screensaver_inhibitors.insert(cookie, (application, sender));
+                    session.reply(message, &cookie)?;
+                },
+                "UnInhibit" => {
+                    let cookie: u32 = match message.body() {
+                        Ok(body) => body,
+                        Err(_) => return invalid_args(session, message, "(u)")
+                    };
+                    let owned = self.screensaver_inhibitors
+                                    .get(&cookie)
+                                    .map_or(false, |&(_, ref owner)| *owner == sender);
+                    if owned {
+                        self.screensaver_inhibitors.remove(&cookie);
+                    }
+                    session.reply(message, &())?;
+                },
+                _ => {
+                    session.reply_error(message,
+                                        "org.freedesktop.DBus.Error.UnknownMethod",
+                                        &format!("Unknown method {}", member))?;
+                }
+            }
+        }
+        self.inhibitors_changed(was_inhibited);
+        Ok(())
+    }
+
+    /// Release the inhibitions of an application that left the bus without
+    /// releasing them itself.
+    fn name_lost(&mut self, name: &str) {
+        let was_inhibited = !self.screensaver_inhibitors.is_empty();
+        self.screensaver_inhibitors.retain(|_, &mut (ref application, ref owner)| {
+            if owner == name {
+                wlr_log!(WLR_DEBUG, "{} left without uninhibiting the screen saver", application);
+                false
+            } else {
+                true
+            }
+        });
+        self.inhibitors_changed(was_inhibited);
+    }
+
+    fn inhibitors_changed(&mut self, was_inhibited: bool) {
+        let inhibited = !self.screensaver_inhibitors.is_empty();
+        if inhibited != was_inhibited {
+            unsafe { set_idle_inhibited(inhibited) };
+            if let Some(compositor) = compositor::handle() {
+                self.handler.screensaver_inhibited(compositor, inhibited);
+            }
+        }
+    }
+}
+
+/// Determines if the connection can be read from without blocking.
+fn readable(fd: RawFd) -> bool {
+    let mut poll_fd = libc::pollfd { fd, events: libc::POLLIN, revents: 0 };
+    unsafe { libc::poll(&mut poll_fd, 1, 0) > 0 && poll_fd.revents & libc::POLLIN != 0 }
+}
+
+/// Reply to a method call whose arguments aren't of the expected signature.
+fn invalid_args(session: &Connection, message: &Message, signature: &str) -> zbus::Result<()> {
+    session.reply_error(message,
+                        "org.freedesktop.DBus.Error.InvalidArgs",
+                        &format!("Expected arguments of signature {}", signature))?;
+    Ok(())
+}
+
+/// Ask for the screen saver name, so that its method calls are sent to us,
+/// and watch for the applications that leave the bus.
+///
+/// Returns the serial of the call asking for the name, the name is ours once
+/// it's replied to (see `DbusState::name_reply`).
+fn serve_screensaver(session: &Connection) -> zbus::Result<u32> {
+    session.send_message(Message::method(None,
+                                         Some("org.freedesktop.DBus"),
+                                         "/org/freedesktop/DBus",
+                                         Some("org.freedesktop.DBus"),
+                                         "AddMatch",
+                                         &("type='signal',sender='org.freedesktop.DBus',\
+                                            interface='org.freedesktop.DBus',\
+                                            member='NameOwnerChanged'"))?)?;
+    // NOTE Flags of 0x4 means do not queue, so we fail if it's already taken.
+    session.send_message(Message::method(None,
+                                         Some("org.freedesktop.DBus"),
+                                         "/org/freedesktop/DBus",
+                                         Some("org.freedesktop.DBus"),
+                                         "RequestName",
+                                         &(SCREENSAVER_NAME, 0x4u32))?)
+}
+
+/// Disable the idle timers, if the idle extension is enabled.
+unsafe fn set_idle_inhibited(inhibited: bool) {
+    if compositor::COMPOSITOR_PTR.is_null() {
+        return
+    }
+    if let Some(ref mut idle_manager) = (*compositor::COMPOSITOR_PTR).idle_manager {
+        idle_manager.set_inhibited(inhibited)
+    }
+}
+
+unsafe fn add_source(event_loop: *mut wl_event_loop,
+                     fd: RawFd,
+                     state: &mut DbusState)
+                     -> *mut wl_event_source {
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_event_loop_add_fd,
+                  event_loop,
+                  fd,
+                  WL_EVENT_READABLE,
+                  dbus_notify,
+                  state as *mut DbusState as *mut c_void)
+}
+
+unsafe extern "C" fn dbus_notify(fd: c_int, _mask: u32, data: *mut c_void) -> c_int {
+    let state = &mut *(data as *mut DbusState);
+    wlr_span!("dbus::dispatch");
+    let res = panic::catch_unwind(panic::AssertUnwindSafe(|| state.dispatch(fd)));
+    match res {
+        Ok(Ok(())) => {},
+        Ok(Err(err)) => wlr_log!(WLR_ERROR, "Could not handle D-Bus message: {}", err),
+        Err(err) => utils::handle_unwind::<()>(Err(err))
+    }
+    0
+}
@@ -0,0 +1,681 @@
+//! Recording the input of the compositor to a file, and replaying it.
+//!
+//! A recording holds the key, pointer, touch and tablet events of every
+//! device, with when they happened on the clock of the compositor. Attached to
+//! a bug report it makes issues with focus or gestures reproducible, and
+//! replayed in a test it checks how the input is handled:
+//!
+//! ```rust,no_run,ignore
+//! input_recording::start("/tmp/input.rec")?;
+//! // ... reproduce the bug, then
+//! input_recording::stop()?;
+//!
+//! let events = input_recording::load("/tmp/input.rec")?;
+//! let mut replayer = Replayer::new(Injector::new(&backend).unwrap(), events);
+//! replayer.start();
+//! ```
+//!
+//! Events are replayed through an `inject::Injector`, so they go through the
+//! handlers of the compositor like real input does. The events of all the
+//! devices are replayed on the devices of the injector, and the events of the
+//! devices of injectors aren't recorded, so replaying while recording doesn't
+//! record the events twice. The injector has no tablet devices, so tablet
+//! events are recorded but skipped when replaying.
+//!
+//! This version of wlroots has no switch devices and no pointer gesture
+//! events. The gestures of `input::gestures` are detected from the pointer
+//! and touch events, so replaying those replays the gestures.
+//!
+//! The recordings are text, one event a line (e.g. `1250 key 30 pressed`),
+//! with the milliseconds since the recording started first.
+
+use std::{cell::RefCell, fmt, fs::File, io::{self, BufRead, BufReader, BufWriter, Write},
+          path::Path, ptr, time::Duration};
+
+use libc;
+use wayland_sys::server::{wl_event_source, WAYLAND_SERVER_HANDLE};
+use wlroots_sys::{wlr_axis_orientation::{self, *}, wlr_axis_source::{self, *},
+                  wlr_button_state::{self, *}, wlr_event_keyboard_key, wlr_event_pointer_axis,
+                  wlr_event_pointer_button, wlr_event_pointer_motion,
+                  wlr_event_pointer_motion_absolute, wlr_event_tablet_pad_button,
+                  wlr_event_tablet_pad_ring, wlr_event_tablet_pad_strip,
+                  wlr_event_tablet_tool_axis, wlr_event_tablet_tool_button,
+                  wlr_event_tablet_tool_proximity, wlr_event_tablet_tool_tip,
+                  wlr_event_touch_down, wlr_event_touch_motion, wlr_event_touch_up,
+                  wlr_input_device, wlr_key_state::{self, *},
+                  wlr_tablet_pad_ring_source::{self, *},
+                  wlr_tablet_pad_strip_source::{self, *},
+                  wlr_tablet_tool_proximity_state::*, wlr_tablet_tool_tip_state::*};
+
+use {compositor, input::inject::{self, Injector}, utils::current_time};
+
+const HEADER: &str = "# wlroots-rs input recording 1";
+
+/// An input event, as it's recorded.
+///
+/// Positions of absolute motion and touch points are normalized to the
+/// device, between 0 and 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    Key { keycode: u32, state: wlr_key_state },
+    PointerMotion { dx: f64, dy: f64 },
+    PointerMotionAbsolute { x: f64, y: f64 },
+    PointerButton { button: u32, state: wlr_button_state },
+    PointerAxis {
+        orientation: wlr_axis_orientation,
+        delta: f64,
+        delta_discrete: i32,
+        source: wlr_axis_source
+    },
+    TouchDown { touch_id: i32, x: f64, y: f64 },
+    TouchUp { touch_id: i32 },
+    TouchMotion { touch_id: i32, x: f64, y: f64 },
+    /// The axes of a tablet tool changed, `axes` are the bits of the
+    /// `tablet_tool::Axis` that were updated.
+    TabletToolAxis {
+        axes: u32,
+        x: f64,
+        y: f64,
+        pressure: f64,
+        distance: f64,
+        tilt_x: f64,
+        tilt_y: f64,
+        rotation: f64,
+        slider: f64,
+        wheel_delta: f64
+    },
+    TabletToolProximity { x: f64, y: f64, near: bool },
+    TabletToolTip { x: f64, y: f64, down: bool },
+    TabletToolButton { button: u32, state: wlr_button_state },
+    TabletPadButton { button: u32, state: wlr_button_state, mode: u32 },
+    TabletPadRing {
+        ring: u32,
+        position: f64,
+        source: wlr_tablet_pad_ring_source,
+        mode: u32
+    },
+    TabletPadStrip {
+        strip: u32,
+        position: f64,
+        source: wlr_tablet_pad_strip_source,
+        mode: u32
+    }
+}
+
+/// An event of a recording, with when it happened after the recording
+/// started.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Recorded {
+    pub time: Duration,
+    pub event: Event
+}
+
+struct Recording {
+    file: BufWriter<File>,
+    /// When the recording started, on the monotonic clock.
+    start: Duration
+}
+
+thread_local! {
+    static RECORDING: RefCell<Option<Recording>> = RefCell::new(None);
+}
+
+/// Start recording the input to the file, replacing what's in it.
+///
+/// A recording that's already going is stopped first.
+pub fn start<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    stop()?;
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(file, "{}", HEADER)?;
+    RECORDING.with(|recording| {
+                       *recording.borrow_mut() = Some(Recording { file,
+                                                                  start: current_time() })
+                   });
+    Ok(())
+}
+
+/// Stop recording the input, writing out what's left of the recording.
+pub fn stop() -> io::Result<()> {
+    match RECORDING.with(|recording| recording.borrow_mut().take()) {
+        Some(mut recording) => recording.file.flush(),
+        None => Ok(())
+    }
+}
+
+/// Determine if the input is being recorded.
+pub fn is_recording() -> bool {
+    RECORDING.with(|recording| recording.borrow().is_some())
+}
+
+/// Add the event the device sent to the recording, if there is one.
+///
+/// The event is recorded at the current time of the compositor, the
+/// timestamps of the events are on the clock of the device they came from.
+fn record(device: *mut wlr_input_device, event: Event) {
+    if inject::is_injected(device) {
+        return
+    }
+    RECORDING.with(|recording| {
+        let mut recording = recording.borrow_mut();
+        let failed = match recording.as_mut() {
+            Some(recording) => {
+                let time = current_time();
+                let time = time.max(recording.start) - recording.start;
+                let recorded = Recorded { time, event };
+                writeln!(recording.file, "{}", recorded).is_err()
+            },
+            None => return
+        };
+        if failed {
+            wlr_log!(WLR_ERROR, "Could not write to the input recording, stopping it");
+            *recording = None;
+        }
+    })
+}
+
+pub(crate) unsafe fn record_key(device: *mut wlr_input_device,
+                                event: *mut wlr_event_keyboard_key) {
+    record(device,
+           Event::Key { keycode: (*event).keycode, state: (*event).state })
+}
+
+pub(crate) unsafe fn record_pointer_motion(event: *mut wlr_event_pointer_motion) {
+    record((*event).device,
+           Event::PointerMotion { dx: (*event).delta_x, dy: (*event).delta_y })
+}
+
+pub(crate) unsafe fn record_pointer_motion_absolute(event: *mut wlr_event_pointer_motion_absolute)
+{
+    record((*event).device,
+           Event::PointerMotionAbsolute { x: (*event).x, y: (*event).y })
+}
+
+pub(crate) unsafe fn record_pointer_button(event: *mut wlr_event_pointer_button) {
+    record((*event).device,
+           Event::PointerButton { button: (*event).button, state: (*event).state })
+}
+
+pub(crate) unsafe fn record_pointer_axis(event: *mut wlr_event_pointer_axis) {
+    record((*event).device,
+           Event::PointerAxis { orientation: (*event).orientation,
+                                delta: (*event).delta,
+                                delta_discrete: (*event).delta_discrete,
+                                source: (*event).source })
+}
+
+pub(crate) unsafe fn record_touch_down(event: *mut wlr_event_touch_down) {
+    record((*event).device,
+           Event::TouchDown { touch_id: (*event).touch_id, x: (*event).x, y: (*event).y })
+}
+
+pub(crate) unsafe fn record_touch_up(event: *mut wlr_event_touch_up) {
+    record((*event).device, Event::TouchUp { touch_id: (*event).touch_id })
+}
+
+pub(crate) unsafe fn record_touch_motion(event: *mut wlr_event_touch_motion) {
+    record((*event).device,
+           Event::TouchMotion { touch_id: (*event).touch_id, x: (*event).x, y: (*event).y })
+}
+
+pub(crate) unsafe fn record_tablet_tool_axis(event: *mut wlr_event_tablet_tool_axis) {
+    let event = &*event;
+    record(event.device,
+           Event::TabletToolAxis { axes: event.updated_axes,
+                                   x: event.x,
+                                   y: event.y,
+                                   pressure: event.pressure,
+                                   distance: event.distance,
+                                   tilt_x: event.tilt_x,
+                                   tilt_y: event.tilt_y,
+                                   rotation: event.rotation,
+                                   slider: event.slider,
+                                   wheel_delta: event.wheel_delta })
+}
+
+pub(crate) unsafe fn record_tablet_tool_proximity(event: *mut wlr_event_tablet_tool_proximity) {
+    record((*event).device,
+           Event::TabletToolProximity { x: (*event).x,
+                                        y: (*event).y,
+                                        near: (*event).state == WLR_TABLET_TOOL_PROXIMITY_IN })
+}
+
+pub(crate) unsafe fn record_tablet_tool_tip(event: *mut wlr_event_tablet_tool_tip) {
+    record((*event).device,
+           Event::TabletToolTip { x: (*event).x,
+                                  y: (*event).y,
+                                  down: (*event).state == WLR_TABLET_TOOL_TIP_DOWN })
+}
+
+pub(crate) unsafe fn record_tablet_tool_button(event: *mut wlr_event_tablet_tool_button) {
+    record((*event).device,
+           Event::TabletToolButton { button: (*event).button, state: (*event).state })
+}
+
+pub(crate) unsafe fn record_tablet_pad_button(device: *mut wlr_input_device,
+                                              event: *mut wlr_event_tablet_pad_button) {
+    record(device,
+           Event::TabletPadButton { button: (*event).button,
+                                    state: (*event).state,
+                                    mode: (*event).mode })
+}
+
+pub(crate) unsafe fn record_tablet_pad_ring(device: *mut wlr_input_device,
+                                            event: *mut wlr_event_tablet_pad_ring) {
+    record(device,
+           Event::TabletPadRing { ring: (*event).ring,
+                                  position: (*event).position,
+                                  source: (*event).source,
+                                  mode: (*event).mode })
+}
+
+pub(crate) unsafe fn record_tablet_pad_strip(device: *mut wlr_input_device,
+                                             event: *mut wlr_event_tablet_pad_strip) {
+    record(device,
+           Event::TabletPadStrip { strip: (*event).strip,
+                                   position: (*event).position,
+                                   source: (*event).source,
+                                   mode: (*event).mode })
+}
+
+impl fmt::Display for Recorded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let millis = self.time.as_secs() * 1000 + self.time.subsec_millis() as u64;
+        write!(f, "{} ", millis)?;
+        match self.event {
+            Event::Key { keycode, state } => {
+                let state = if state == WLR_KEY_PRESSED { "pressed" } else { "released" };
+                write!(f, "key {} {}", keycode, state)
+            },
+            Event::PointerMotion { dx, dy } => write!(f, "motion {} {}", dx, dy),
+            Event::PointerMotionAbsolute { x, y } => write!(f, "motion_absolute {} {}", x, y),
+            Event::PointerButton { button, state } => {
+                let state = if state == WLR_BUTTON_PRESSED { "pressed" } else { "released" };
+                write!(f, "button {} {}", button, state)
+            },
+            Event::PointerAxis { orientation, delta, delta_discrete, source } => {
+                let orientation = match orientation {
+                    WLR_AXIS_ORIENTATION_VERTICAL => "vertical",
+                    WLR_AXIS_ORIENTATION_HORIZONTAL => "horizontal"
+                };
+                let source = match source {
+                    WLR_AXIS_SOURCE_WHEEL => "wheel",
+                    WLR_AXIS_SOURCE_FINGER => "finger",
+                    WLR_AXIS_SOURCE_CONTINUOUS => "continuous",
+                    _ => "wheel_tilt"
+                };
+                write!(f, "axis {} {} {} {}", orientation, delta, delta_discrete, source)
+            },
+            Event::TouchDown { touch_id, x, y } => {
+                write!(f, "touch_down {} {} {}", touch_id, x, y)
+            },
+            Event::TouchUp { touch_id } => write!(f, "touch_up {}", touch_id),
+            Event::TouchMotion { touch_id, x, y } => {
+                write!(f, "touch_motion {} {} {}", touch_id, x, y)
+            },
+            Event::TabletToolAxis { axes,
+                                    x,
+                                    y,
+                                    pressure,
+                                    distance,
+                                    tilt_x,
+                                    tilt_y,
+                                    rotation,
+                                    slider,
+                                    wheel_delta } => {
+                write!(f,
+                       "tool_axis {} {} {} {} {} {} {} {} {} {}",
+                       axes,
+                       x,
+                       y,
+                       pressure,
+                       distance,
+                       tilt_x,
+                       tilt_y,
+                       rotation,
+                       slider,
+                       wheel_delta)
+            },
+            Event::TabletToolProximity { x, y, near } => {
+                let state = if near { "in" } else { "out" };
+                write!(f, "tool_proximity {} {} {}", x, y, state)
+            },
+            Event::TabletToolTip { x, y, down } => {
+                let state = if down { "down" } else { "up" };
+                write!(f, "tool_tip {} {} {}", x, y, state)
+            },
+            Event::TabletToolButton { button, state } => {
+                let state = if state == WLR_BUTTON_PRESSED { "pressed" } else { "released" };
+                write!(f, "tool_button {} {}", button, state)
+            },
+            Event::TabletPadButton { button, state, mode } => {
+                let state = if state == WLR_BUTTON_PRESSED { "pressed" } else { "released" };
+                write!(f, "pad_button {} {} {}", button, state, mode)
+            },
+            Event::TabletPadRing { ring, position, source, mode } => {
+                let source = match source {
+                    WLR_TABLET_PAD_RING_SOURCE_FINGER => "finger",
+                    WLR_TABLET_PAD_RING_SOURCE_UNKNOWN => "unknown"
+                };
+                write!(f, "pad_ring {} {} {} {}", ring, position, source, mode)
+            },
+            Event::TabletPadStrip { strip, position, source, mode } => {
+                let source = match source {
+                    WLR_TABLET_PAD_STRIP_SOURCE_FINGER => "finger",
+                    WLR_TABLET_PAD_STRIP_SOURCE_UNKNOWN => "unknown"
+                };
+                write!(f, "pad_strip {} {} {} {}", strip, position, source, mode)
+            }
+        }
+    }
+}
+
+impl Recorded {
+    /// Parse a line of a recording.
+    pub fn parse(line: &str) -> Option<Recorded> {
+        let mut words = line.split_whitespace();
+        let millis: u64 = words.next()?.parse().ok()?;
+        let kind = words.next()?;
+        let args: Vec<&str> = words.collect();
+        let pressed = |word: &str| match word {
+            "pressed" => Some(true),
+            "released" => Some(false),
+            _ => None
+        };
+        let event = match (kind, args.as_slice()) {
+            ("key", &[keycode, state]) => {
+                let state = if pressed(state)? { WLR_KEY_PRESSED } else { WLR_KEY_RELEASED };
+                Event::Key { keycode: keycode.parse().ok()?, state }
+            },
+            ("motion", &[dx, dy]) => {
+                Event::PointerMotion { dx: dx.parse().ok()?, dy: dy.parse().ok()? }
+            },
+            ("motion_absolute", &[x, y]) => {
+                Event::PointerMotionAbsolute { x: x.parse().ok()?, y: y.parse().ok()? }
+            },
+            ("button", &[button, state]) => {
+                let state = if pressed(state)? {
+                    WLR_BUTTON_PRESSED
+                } else {
+                    WLR_BUTTON_RELEASED
+                };
+                Event::PointerButton { button: button.parse().ok()?, state }
+            },
+            ("axis", &[orientation, delta, delta_discrete, source]) => {
+                let orientation = match orientation {
+                    "vertical" => WLR_AXIS_ORIENTATION_VERTICAL,
+                    "horizontal" => WLR_AXIS_ORIENTATION_HORIZONTAL,
+                    _ => return None
+                };
+                let source = match source {
+                    "wheel" => WLR_AXIS_SOURCE_WHEEL,
+                    "finger" => WLR_AXIS_SOURCE_FINGER,
+                    "continuous" => WLR_AXIS_SOURCE_CONTINUOUS,
+                    "wheel_tilt" => WLR_AXIS_SOURCE_WHEEL_TILT,
+                    _ => return None
+                };
+                Event::PointerAxis { orientation,
+                                     delta: delta.parse().ok()?,
+                                     delta_discrete: delta_discrete.parse().ok()?,
+                                     source }
+            },
+            ("touch_down", &[touch_id, x, y]) => {
+                Event::TouchDown { touch_id: touch_id.parse().ok()?,
+                                   x: x.parse().ok()?,
+                                   y: y.parse().ok()? }
+            },
+            ("touch_up", &[touch_id]) => Event::TouchUp { touch_id: touch_id.parse().ok()? },
+            ("touch_motion", &[touch_id, x, y]) => {
+                Event::TouchMotion { touch_id: touch_id.parse().ok()?,
+                                     x: x.parse().ok()?,
+                                     y: y.parse().ok()? }
+            },
+            ("tool_axis",
+             &[axes, x, y, pressure, distance, tilt_x, tilt_y, rotation, slider, wheel_delta]) => {
+                Event::TabletToolAxis { axes: axes.parse().ok()?,
+                                        x: x.parse().ok()?,
+                                        y: y.parse().ok()?,
+                                        pressure: pressure.parse().ok()?,
+                                        distance: distance.parse().ok()?,
+                                        tilt_x: tilt_x.parse().ok()?,
+                                        tilt_y: tilt_y.parse().ok()?,
+                                        rotation: rotation.parse().ok()?,
+                                        slider: slider.parse().ok()?,
+                                        wheel_delta: wheel_delta.parse().ok()? }
+            },
+            ("tool_proximity", &[x, y, state]) => {
+                let near = match state {
+                    "in" => true,
+                    "out" => false,
+                    _ => return None
+                };
+                Event::TabletToolProximity { x: x.parse().ok()?, y: y.parse().ok()?, near }
+            },
+            ("tool_tip", &[x, y, state]) => {
+                let down = match state {
+                    "down" => true,
+                    "up" => false,
+                    _ => return None
+                };
+                Event::TabletToolTip { x: x.parse().ok()?, y: y.parse().ok()?, down }
+            },
+            ("tool_button", &[button, state]) => {
+                let state = if pressed(state)? {
+                    WLR_BUTTON_PRESSED
+                } else {
+                    WLR_BUTTON_RELEASED
+                };
+                Event::TabletToolButton { button: button.parse().ok()?, state }
+            },
+            ("pad_button", &[button, state, mode]) => {
+                let state = if pressed(state)? {
+                    WLR_BUTTON_PRESSED
+                } else {
+                    WLR_BUTTON_RELEASED
+                };
+                Event::TabletPadButton { button: button.parse().ok()?,
+                                         state,
+                                         mode: mode.parse().ok()? }
+            },
+            ("pad_ring", &[ring, position, source, mode]) => {
+                let source = match source {
+                    "finger" => WLR_TABLET_PAD_RING_SOURCE_FINGER,
+                    "unknown" => WLR_TABLET_PAD_RING_SOURCE_UNKNOWN,
+                    _ => return None
+                };
+                Event::TabletPadRing { ring: ring.parse().ok()?,
+                                       position: position.parse().ok()?,
+                                       source,
+                                       mode: mode.parse().ok()? }
+            },
+            ("pad_strip", &[strip, position, source, mode]) => {
+                let source = match source {
+                    "finger" => WLR_TABLET_PAD_STRIP_SOURCE_FINGER,
+                    "unknown" => WLR_TABLET_PAD_STRIP_SOURCE_UNKNOWN,
+                    _ => return None
+                };
+                Event::TabletPadStrip { strip: strip.parse().ok()?,
+                                        position: position.parse().ok()?,
+                                        source,
+                                        mode: mode.parse().ok()? }
+            },
+            _ => return None
+        };
+        Some(Recorded { time: Duration::from_millis(millis), event })
+    }
+}
+
+/// Read the events of a recording.
+///
+/// Lines that aren't events are skipped, e.g. comments or the events of a
+/// later version of the format.
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Vec<Recorded>> {
+    let file = BufReader::new(File::open(path)?);
+    let mut events = Vec::new();
+    for line in file.lines() {
+        let line = line?;
+        if line.starts_with('#') {
+            continue
+        }
+        match Recorded::parse(&line) {
+            Some(event) => events.push(event),
+            None => wlr_log!(WLR_DEBUG, "Skipping input recording line {:?}", line)
+        }
+    }
+    Ok(events)
+}
+
+struct ReplayState {
+    injector: Injector,
+    events: Vec<Recorded>,
+    /// The index of the next event to replay.
+    next: usize,
+    /// When the replay started, on the monotonic clock, if it's going.
+    start: Option<Duration>,
+    timer: *mut wl_event_source
+}
+
+/// Replays recorded events through the devices of an injector.
+pub struct Replayer {
+    state: Box<ReplayState>
+}
+
+impl Replayer {
+    /// Get ready to replay the events, the earliest first.
+    ///
+    /// Touch events are only replayed if the injector has a touch device,
+    /// see `Injector::add_touch`. Tablet events are skipped.
+    pub fn new(injector: Injector, events: Vec<Recorded>) -> Self {
+        Replayer { state: Box::new(ReplayState { injector,
+                                                 events,
+                                                 next: 0,
+                                                 start: None,
+                                                 timer: ptr::null_mut() }) }
+    }
+
+    /// Replay the events as they happened, with the time between them, from
+    /// the event loop of the compositor.
+    pub fn start(&mut self) {
+        let next = self.state.events.get(self.state.next).map(|event| event.time);
+        let elapsed = next.unwrap_or_default();
+        self.state.start = Some(current_time().max(elapsed) - elapsed);
+        unsafe { self.state.schedule() }
+    }
+
+    /// Stop replaying the events as they happened, e.g. to step through them
+    /// instead.
+    pub fn pause(&mut self) {
+        self.state.start = None;
+        unsafe { self.state.schedule() }
+    }
+
+    /// Replay the next event right away.
+    ///
+    /// Returns false if all the events were replayed.
+    pub fn step(&mut self) -> bool {
+        self.state.replay_next()
+    }
+
+    /// Replay all the events that are left right away, e.g. to get to the
+    /// state at the end of a recording in a test.
+    pub fn replay_all(&mut self) {
+        while self.state.replay_next() {}
+    }
+
+    /// Determine if all the events were replayed.
+    pub fn is_done(&self) -> bool {
+        self.state.next >= self.state.events.len()
+    }
+}
+
+impl ReplayState {
+    fn replay_next(&mut self) -> bool {
+        let event = match self.events.get(self.next) {
+            Some(recorded) => recorded.event,
+            None => return false
+        };
+        self.next += 1;
+        let injector = &mut self.injector;
+        match event {
+            Event::Key { keycode, state } => injector.inject_key(keycode, state),
+            Event::PointerMotion { dx, dy } => injector.inject_pointer_motion(dx, dy),
+            Event::PointerMotionAbsolute { x, y } => {
+                injector.inject_pointer_motion_normalized(x, y)
+            },
+            Event::PointerButton { button, state } => injector.inject_button(button, state),
+            Event::PointerAxis { orientation, delta, delta_discrete, source } => {
+                injector.inject_axis(orientation, delta, delta_discrete, source)
+            },
+            Event::TouchDown { touch_id, x, y } => injector.inject_touch_down(touch_id, x, y),
+            Event::TouchUp { touch_id } => injector.inject_touch_up(touch_id),
+            Event::TouchMotion { touch_id, x, y } => {
+                injector.inject_touch_motion(touch_id, x, y)
+            },
+            Event::TabletToolAxis { .. }
+            | Event::TabletToolProximity { .. }
+            | Event::TabletToolTip { .. }
+            | Event::TabletToolButton { .. }
+            | Event::TabletPadButton { .. }
+            | Event::TabletPadRing { .. }
+            | Event::TabletPadStrip { .. } => {}
+        }
+        true
+    }
+
+    /// Replay the events that are due, and arm the timer for the next one.
+    unsafe fn schedule(&mut self) {
+        let start = match self.start {
+            Some(start) => start,
+            None => {
+                if !self.timer.is_null() {
+                    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                  wl_event_source_timer_update,
+                                  self.timer,
+                                  0);
+                }
+                return
+            }
+        };
+        let now = current_time();
+        while let Some(recorded) = self.events.get(self.next).cloned() {
+            let due = start + recorded.time;
+            if due > now {
+                if self.timer.is_null() {
+                    if compositor::COMPOSITOR_PTR.is_null() {
+                        return
+                    }
+                    let event_loop = (*compositor::COMPOSITOR_PTR).event_loop;
+                    self.timer = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                               wl_event_loop_add_timer,
+                                               event_loop,
+                                               replay_timer_notify,
+                                               self as *mut ReplayState as *mut libc::c_void);
+                }
+                let wait = due - now;
+                // NOTE A timeout of 0 would disarm the timer instead.
+                let millis = (wait.as_secs() * 1000 + wait.subsec_millis() as u64).max(1);
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                              wl_event_source_timer_update,
+                              self.timer,
+                              millis as libc::c_int);
+                return
+            }
+            self.replay_next();
+        }
+        self.start = None;
+    }
+}
+
+impl Drop for Replayer {
+    fn drop(&mut self) {
+        if !self.state.timer.is_null() {
+            unsafe {
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_event_source_remove, self.state.timer);
+            }
+        }
+    }
+}
+
+unsafe extern "C" fn replay_timer_notify(data: *mut libc::c_void) -> libc::c_int {
+    (*(data as *mut ReplayState)).schedule();
+    0
+}
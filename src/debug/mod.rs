@@ -0,0 +1,8 @@
+//! Tools for debugging compositors, and the clients running on them.
+//!
+//! None of these are active unless they're turned on, so they can be left in
+//! release builds and be enabled e.g. from an IPC command when a user reports
+//! a bug.
+
+pub mod input_recording;
+pub mod protocol_log;
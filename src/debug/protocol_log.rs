@@ -0,0 +1,382 @@
+//! Watching the protocol messages exchanged with clients, like
+//! `WAYLAND_DEBUG=1` does but from within the compositor.
+//!
+//! A hook is called with every request a client sends and every event it's
+//! sent, so an on-demand protocol logger only needs to be installed while a
+//! bug is being reproduced:
+//!
+//! ```rust,no_run,ignore
+//! let watch = protocol_log::watch(|message| wlr_log!(WLR_DEBUG, "{}", message));
+//! // ... reproduce the bug, then
+//! drop(watch);
+//! ```
+//!
+//! Hooks can also be limited to one client with `watch_client`, e.g. to count
+//! its requests and disconnect it with `Message::disconnect_client` when it
+//! floods the compositor.
+//!
+//! No messages are intercepted while there are no hooks, so this costs
+//! nothing unless it's used.
+
+use std::{cell::{Cell, RefCell}, ffi::CStr, fmt::{self, Write}, ptr, rc::Rc, slice};
+
+use libc::{c_int, c_void};
+use wayland_sys::{common::{wl_argument, wl_message},
+                  server::{wl_display, wl_resource, WAYLAND_SERVER_HANDLE}};
+
+use compositor::{self, Client, Resource};
+
+#[repr(C)]
+#[allow(non_camel_case_types, dead_code)]
+#[derive(Clone, Copy)]
+enum wl_protocol_logger_type {
+    WL_PROTOCOL_LOGGER_REQUEST,
+    WL_PROTOCOL_LOGGER_EVENT
+}
+
+#[repr(C)]
+#[allow(non_camel_case_types)]
+struct wl_protocol_logger_message {
+    resource: *mut wl_resource,
+    message_opcode: c_int,
+    message: *const wl_message,
+    arguments_count: c_int,
+    arguments: *const wl_argument
+}
+
+#[allow(non_camel_case_types)]
+enum wl_protocol_logger {}
+
+// NOTE These are not exposed by wayland-sys, but libwayland-server is
+// always linked by wlroots-sys.
+extern "C" {
+    fn wl_display_add_protocol_logger(display: *mut wl_display,
+                                      func: unsafe extern "C" fn(*mut c_void,
+                                                                 wl_protocol_logger_type,
+                                                                 *const wl_protocol_logger_message),
+                                      user_data: *mut c_void)
+                                      -> *mut wl_protocol_logger;
+    fn wl_protocol_logger_destroy(logger: *mut wl_protocol_logger);
+}
+
+/// Whether a message was sent by the client or to it.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Direction {
+    /// A request the client sent.
+    Request,
+    /// An event sent to the client.
+    Event
+}
+
+/// A protocol message, as it's handed to the hooks.
+///
+/// The message is only borrowed for the duration of the hook. Formatting it
+/// with `Display` gives the same line `WAYLAND_DEBUG=1` would print, e.g.
+/// `wl_surface@12.attach(wl_buffer@30, 0, 0)` or
+/// ` -> wl_callback@31.done(44093)`.
+pub struct Message<'message> {
+    direction: Direction,
+    resource: *mut wl_resource,
+    opcode: u32,
+    message: &'message wl_message,
+    arguments: &'message [wl_argument]
+}
+
+type Hook = Rc<RefCell<Box<FnMut(&Message)>>>;
+
+struct Logger {
+    display: *mut wl_display,
+    logger: *mut wl_protocol_logger,
+    /// The hooks, with the client they're limited to.
+    hooks: Vec<(u64, Option<Client>, Hook)>
+}
+
+thread_local! {
+    static LOGGER: RefCell<Logger> = RefCell::new(Logger { display: ptr::null_mut(),
+                                                           logger: ptr::null_mut(),
+                                                           hooks: Vec::new() });
+    static NEXT_ID: Cell<u64> = Cell::new(0);
+    /// Whether the hooks are being called, during which libwayland still
+    /// uses the logger.
+    static DISPATCHING: Cell<bool> = Cell::new(false);
+}
+
+/// Calls its hook with the protocol messages until it's dropped.
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct Watch {
+    id: u64
+}
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        let id = self.id;
+        LOGGER.with(|logger| {
+                        let mut logger = logger.borrow_mut();
+                        logger.hooks.retain(|&(other, _, _)| other != id);
+                        unsafe { logger.update() }
+                    })
+    }
+}
+
+/// Call the function with every protocol message of every client from now
+/// on, until the watch is dropped.
+pub fn watch<F>(hook: F) -> Watch
+    where F: FnMut(&Message) + 'static
+{
+    add_hook(None, Box::new(hook))
+}
+
+/// Call the function with every protocol message of the client from now on,
+/// until the watch is dropped or the client disconnects.
+pub fn watch_client<F>(client: &Client, hook: F) -> Watch
+    where F: FnMut(&Message) + 'static
+{
+    add_hook(Some(client.clone()), Box::new(hook))
+}
+
+fn add_hook(client: Option<Client>, hook: Box<FnMut(&Message)>) -> Watch {
+    let id = NEXT_ID.with(|next_id| {
+                              let id = next_id.get();
+                              next_id.set(id + 1);
+                              id
+                          });
+    LOGGER.with(|logger| {
+                    let mut logger = logger.borrow_mut();
+                    logger.hooks.push((id, client, Rc::new(RefCell::new(hook))));
+                    unsafe { logger.update() }
+                });
+    Watch { id }
+}
+
+/// Start handing the messages of the display to the hooks.
+pub(crate) unsafe fn attach(display: *mut wl_display) {
+    LOGGER.with(|logger| {
+                    let mut logger = logger.borrow_mut();
+                    logger.remove();
+                    logger.display = display;
+                    logger.update()
+                })
+}
+
+/// Stop intercepting the messages of the display, before it's destroyed.
+///
+/// The hooks are kept for the next display.
+pub(crate) unsafe fn detach() {
+    LOGGER.with(|logger| {
+                    let mut logger = logger.borrow_mut();
+                    logger.remove();
+                    logger.display = ptr::null_mut();
+                })
+}
+
+impl Logger {
+    /// Intercept the messages only while there's a hook for them.
+    unsafe fn update(&mut self) {
+        if self.hooks.is_empty() {
+            if !DISPATCHING.with(|dispatching| dispatching.get()) {
+                self.remove()
+            }
+        } else if self.logger.is_null() && !self.display.is_null() {
+            self.logger = wl_display_add_protocol_logger(self.display,
+                                                         protocol_logger_notify,
+                                                         ptr::null_mut());
+        }
+    }
+
+    unsafe fn remove(&mut self) {
+        if !self.logger.is_null() {
+            wl_protocol_logger_destroy(self.logger);
+            self.logger = ptr::null_mut();
+        }
+    }
+}
+
+unsafe extern "C" fn protocol_logger_notify(_data: *mut c_void,
+                                            direction: wl_protocol_logger_type,
+                                            message: *const wl_protocol_logger_message) {
+    let message = &*message;
+    if message.resource.is_null() || message.message.is_null() {
+        return
+    }
+    let client = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_client, message.resource);
+    let hooks = LOGGER.with(|logger| {
+        logger.borrow()
+              .hooks
+              .iter()
+              .filter(|&&(_, ref only, _)| match *only {
+                          Some(ref only) => only.is_alive() && only.as_ptr() == client,
+                          None => true
+                      })
+              .map(|&(_, _, ref hook)| hook.clone())
+              .collect::<Vec<_>>()
+    });
+    if hooks.is_empty() {
+        return
+    }
+    let arguments = if message.arguments.is_null() || message.arguments_count <= 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(message.arguments, message.arguments_count as usize)
+    };
+    let message = Message { direction: match direction {
+                                wl_protocol_logger_type::WL_PROTOCOL_LOGGER_REQUEST => {
+                                    Direction::Request
+                                },
+                                wl_protocol_logger_type::WL_PROTOCOL_LOGGER_EVENT => {
+                                    Direction::Event
+                                }
+                            },
+                            resource: message.resource,
+                            opcode: message.message_opcode as u32,
+                            message: &*message.message,
+                            arguments };
+    // NOTE Messages sent by a hook itself (e.g. a protocol error it posts)
+    // aren't handed to it again.
+    let nested = DISPATCHING.with(|dispatching| dispatching.replace(true));
+    for hook in hooks {
+        if let Ok(mut hook) = hook.try_borrow_mut() {
+            (&mut **hook)(&message)
+        }
+    }
+    if !nested {
+        DISPATCHING.with(|dispatching| dispatching.set(false));
+        // The last watch might have been dropped by a hook.
+        LOGGER.with(|logger| logger.borrow_mut().update());
+    }
+}
+
+impl<'message> Message<'message> {
+    /// Whether the client sent the message or was sent it.
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// Get the client that sent, or is sent, the message.
+    pub fn client(&self) -> Client {
+        unsafe {
+            Client::from_ptr(ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                           wl_resource_get_client,
+                                           self.resource))
+        }
+    }
+
+    /// Get the object the message is for.
+    ///
+    /// The resource is alive during the hook, a destructor request destroys
+    /// it right after. Its id and interface can still be looked at then.
+    pub fn resource(&self) -> Resource {
+        unsafe { Resource::from_ptr(self.resource) }
+    }
+
+    /// Get the name of the interface of the object the message is for
+    /// (e.g. `wl_surface`).
+    pub fn interface_name(&self) -> String {
+        self.resource().interface_name()
+    }
+
+    /// Get the opcode of the message, i.e. its index among the requests or
+    /// events of the interface.
+    pub fn opcode(&self) -> u32 {
+        self.opcode
+    }
+
+    /// Get the name of the message (e.g. `attach`).
+    pub fn name(&self) -> &'message str {
+        if self.message.name.is_null() {
+            return ""
+        }
+        unsafe { CStr::from_ptr(self.message.name).to_str().unwrap_or("") }
+    }
+
+    /// Get a summary of the arguments of the message, separated by commas
+    /// like `WAYLAND_DEBUG=1` prints them.
+    ///
+    /// Objects are shown as `interface@id`, strings are quoted, and the
+    /// contents of arrays and file descriptors are left out.
+    pub fn arguments(&self) -> String {
+        let mut summary = String::new();
+        if self.message.signature.is_null() {
+            return summary
+        }
+        let signature = unsafe { CStr::from_ptr(self.message.signature).to_bytes() };
+        let types = signature.iter().filter(|kind| !kind.is_ascii_digit() && **kind != b'?');
+        for (kind, argument) in types.zip(self.arguments) {
+            if !summary.is_empty() {
+                summary.push_str(", ");
+            }
+            let _ = unsafe { write_argument(&mut summary, *kind, argument) };
+        }
+        summary
+    }
+
+    /// Disconnect the client of the message once the message has been
+    /// handled, e.g. because it's sending too many of them.
+    ///
+    /// The client can't be killed from within the hook, as libwayland is
+    /// still using it.
+    pub fn disconnect_client(&self) {
+        unsafe {
+            if compositor::COMPOSITOR_PTR.is_null() {
+                return
+            }
+            let event_loop = (*compositor::COMPOSITOR_PTR).event_loop;
+            let client = Box::into_raw(Box::new(self.client()));
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_event_loop_add_idle,
+                          event_loop,
+                          disconnect_idle,
+                          client as *mut c_void);
+        }
+    }
+}
+
+unsafe extern "C" fn disconnect_idle(data: *mut c_void) {
+    let client = *Box::from_raw(data as *mut Client);
+    client.kill();
+}
+
+/// Write an argument of the type in the signature of a message to the summary.
+unsafe fn write_argument(summary: &mut String, kind: u8, argument: &wl_argument) -> fmt::Result {
+    match kind {
+        b'i' => write!(summary, "{}", argument.i),
+        b'u' => write!(summary, "{}", argument.u),
+        b'f' => write!(summary, "{}", argument.f as f64 / 256.0),
+        b's' if argument.s.is_null() => write!(summary, "nil"),
+        b's' => write!(summary, "{:?}", CStr::from_ptr(argument.s).to_string_lossy()),
+        b'o' if argument.o.is_null() => write!(summary, "nil"),
+        b'o' => {
+            let object = Resource::from_ptr(argument.o as *mut wl_resource);
+            write!(summary, "{}@{}", object.interface_name(), object.id())
+        },
+        b'n' => write!(summary, "new id {}", argument.n),
+        b'a' if argument.a.is_null() => write!(summary, "nil"),
+        b'a' => write!(summary, "array[{}]", (*argument.a).size),
+        b'h' => write!(summary, "fd {}", argument.h),
+        _ => write!(summary, "?")
+    }
+}
+
+impl<'message> fmt::Display for Message<'message> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.direction == Direction::Event {
+            write!(f, " -> ")?;
+        }
+        write!(f,
+               "{}@{}.{}({})",
+               self.interface_name(),
+               self.resource().id(),
+               self.name(),
+               self.arguments())
+    }
+}
+
+impl<'message> fmt::Debug for Message<'message> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Message")
+         .field("direction", &self.direction)
+         .field("resource", &self.resource)
+         .field("opcode", &self.opcode)
+         .field("name", &self.name())
+         .finish()
+    }
+}
@@ -0,0 +1,5 @@
+//! Helpers for the parts of a desktop compositor that aren't about Wayland
+//! itself, but that most compositors need.
+
+pub mod tiling;
+pub mod workspaces;
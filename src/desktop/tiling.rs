@@ -0,0 +1,210 @@
+//! Tiling the views in an area, with layout algorithms that can be swapped.
+//!
+//! A `Layout` only works out where the views go. `arrange` asks it, and
+//! configures the views to their new sizes. Wayland clients don't know where
+//! they are, so the positions are handed back for the compositor to keep
+//! (e.g. in its view list) and draw the views at:
+//!
+//! ```rust,no_run,ignore
+//! let layout = MasterStack::default();
+//! for (view, area) in tiling::arrange(&layout, output_area, &views) {
+//!     self.positions.insert(view, area.origin);
+//! }
+//! ```
+//!
+//! `MasterStack` and `Grid` cover the usual layouts, anything else can
+//! implement `Layout` itself.
+
+use std::cmp;
+
+use {area::{Area, Origin, Size}, view::View};
+
+/// A layout algorithm.
+pub trait Layout {
+    /// Work out where the views go in the area, in the order they're given
+    /// in.
+    ///
+    /// Returns an area for every view. Views that aren't shown (e.g. because
+    /// the layout only has room for so many) get an empty area.
+    fn geometries(&self, area: Area, views: &[View]) -> Vec<Area>;
+}
+
+/// Arrange the views in the area with the layout, and configure them to
+/// their new sizes.
+///
+/// Returns the views that are still alive with where they go. Views that were
+/// given an empty area aren't configured, and aren't returned.
+pub fn arrange<L>(layout: &L, area: Area, views: &[View]) -> Vec<(View, Area)>
+    where L: Layout + ?Sized
+{
+    let views: Vec<View> = views.iter().filter(|view| view.is_alive()).cloned().collect();
+    let geometries = layout.geometries(area, &views);
+    views.into_iter()
+         .zip(geometries)
+         .filter(|&(_, geometry)| !geometry.is_empty())
+         .filter(|&(ref view, geometry)| view.configure(geometry).is_ok())
+         .collect()
+}
+
+/// One or more master views on one side, the rest stacked on the other.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MasterStack {
+    /// How many views are masters.
+    pub masters: usize,
+    /// How much of the width the masters take up, between 0 and 1.
+    pub ratio: f32,
+    /// The space between the views, and around them.
+    pub gap: i32
+}
+
+impl Default for MasterStack {
+    fn default() -> Self {
+        MasterStack { masters: 1, ratio: 0.5, gap: 0 }
+    }
+}
+
+impl Layout for MasterStack {
+    fn geometries(&self, area: Area, views: &[View]) -> Vec<Area> {
+        self.areas(area, views.len())
+    }
+}
+
+impl MasterStack {
+    /// Where `count` views go in the area.
+    fn areas(&self, area: Area, count: usize) -> Vec<Area> {
+        let area = shrink(area, self.gap);
+        let masters = cmp::min(self.masters, count);
+        let stacked = count - masters;
+        if masters == 0 || stacked == 0 {
+            return column(area, count, self.gap)
+        }
+        let ratio = self.ratio.max(0.0).min(1.0);
+        let width = (area.size.width - self.gap).max(0);
+        let master_width = (width as f32 * ratio) as i32;
+        let stack_width = width - master_width;
+        let master_area = Area::new(area.origin, Size::new(master_width, area.size.height));
+        let stack_origin = Origin::new(area.origin.x + master_width + self.gap, area.origin.y);
+        let stack_area = Area::new(stack_origin, Size::new(stack_width, area.size.height));
+        let mut geometries = column(master_area, masters, self.gap);
+        geometries.extend(column(stack_area, stacked, self.gap));
+        geometries
+    }
+}
+
+/// The views in rows and columns of the same size, as close to square as they
+/// go.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct Grid {
+    /// The space between the views, and around them.
+    pub gap: i32
+}
+
+impl Layout for Grid {
+    fn geometries(&self, area: Area, views: &[View]) -> Vec<Area> {
+        self.areas(area, views.len())
+    }
+}
+
+impl Grid {
+    /// Where `count` views go in the area.
+    fn areas(&self, area: Area, count: usize) -> Vec<Area> {
+        if count == 0 {
+            return Vec::new()
+        }
+        let area = shrink(area, self.gap);
+        let count = count as i32;
+        let columns = (count as f64).sqrt().ceil() as i32;
+        let rows = (count + columns - 1) / columns;
+        let height = (area.size.height - self.gap * (rows - 1)) / rows;
+        (0..count).map(|index| {
+                          let (row, place) = (index / columns, index % columns);
+                          // NOTE The last row is spread out over the whole width.
+                          let in_row = cmp::min(columns, count - row * columns);
+                          let width = (area.size.width - self.gap * (in_row - 1)) / in_row;
+                          let origin = Origin::new(area.origin.x + place * (width + self.gap),
+                                                   area.origin.y + row * (height + self.gap));
+                          Area::new(origin, Size::new(width.max(0), height.max(0)))
+                      })
+                  .collect()
+    }
+}
+
+/// Take the gap off every side of the area.
+fn shrink(area: Area, gap: i32) -> Area {
+    let origin = Origin::new(area.origin.x + gap, area.origin.y + gap);
+    let size = Size::new((area.size.width - 2 * gap).max(0), (area.size.height - 2 * gap).max(0));
+    Area::new(origin, size)
+}
+
+/// Split the area into rows of the same height.
+fn column(area: Area, count: usize, gap: i32) -> Vec<Area> {
+    if count == 0 {
+        return Vec::new()
+    }
+    let count = count as i32;
+    let height = (area.size.height - gap * (count - 1)) / count;
+    (0..count).map(|index| {
+                      let origin = Origin::new(area.origin.x,
+                                               area.origin.y + index * (height + gap));
+                      Area::new(origin, Size::new(area.size.width, height.max(0)))
+                  })
+              .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(x: i32, y: i32, width: i32, height: i32) -> Area {
+        Area::new(Origin::new(x, y), Size::new(width, height))
+    }
+
+    #[test]
+    fn master_stack_splits_the_width() {
+        let layout = MasterStack::default();
+        assert_eq!(layout.areas(area(0, 0, 100, 60), 3),
+                   vec![area(0, 0, 50, 60), area(50, 0, 50, 30), area(50, 30, 50, 30)]);
+    }
+
+    #[test]
+    fn master_stack_without_a_stack_is_a_column() {
+        let layout = MasterStack { masters: 2, ratio: 0.7, gap: 0 };
+        assert_eq!(layout.areas(area(0, 0, 100, 60), 2),
+                   vec![area(0, 0, 100, 30), area(0, 30, 100, 30)]);
+        assert_eq!(layout.areas(area(0, 0, 100, 60), 0), Vec::<Area>::new());
+    }
+
+    #[test]
+    fn master_stack_gaps_and_ratio() {
+        let layout = MasterStack { masters: 1, ratio: 2.0, gap: 10 };
+        let areas = layout.areas(area(0, 0, 110, 100), 2);
+        // NOTE The ratio is clamped, so the stack gets no width at all.
+        assert_eq!(areas, vec![area(10, 10, 80, 80), area(100, 10, 0, 80)]);
+        let layout = MasterStack { masters: 1, ratio: 0.25, gap: 10 };
+        assert_eq!(layout.areas(area(0, 0, 110, 100), 3),
+                   vec![area(10, 10, 20, 80), area(40, 10, 60, 35), area(40, 55, 60, 35)]);
+    }
+
+    #[test]
+    fn grid_spreads_the_last_row() {
+        let layout = Grid::default();
+        assert_eq!(layout.areas(area(0, 0, 120, 60), 3),
+                   vec![area(0, 0, 60, 30), area(60, 0, 60, 30), area(0, 30, 120, 30)]);
+        assert_eq!(layout.areas(area(0, 0, 120, 60), 0), Vec::<Area>::new());
+    }
+
+    #[test]
+    fn grid_gaps() {
+        let layout = Grid { gap: 10 };
+        assert_eq!(layout.areas(area(0, 0, 130, 130), 4),
+                   vec![area(10, 10, 50, 50),
+                        area(70, 10, 50, 50),
+                        area(10, 70, 50, 50),
+                        area(70, 70, 50, 50)]);
+    }
+
+    #[test]
+    fn shrink_does_not_go_negative() {
+        assert_eq!(shrink(area(0, 0, 10, 30), 10), area(10, 10, 0, 10));
+    }
+}
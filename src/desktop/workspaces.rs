@@ -0,0 +1,404 @@
+//! The bookkeeping of workspaces: which views are on which workspace, and
+//! which workspace is shown.
+//!
+//! Workspaces are either global, or belong to an output, in which case every
+//! output shows one of its own. What to do with the views when another
+//! workspace is shown (e.g. hiding them, or animating them away) is up to the
+//! compositor, which is told in the activation callback:
+//!
+//! ```rust,no_run,ignore
+//! let mut workspaces = Workspaces::new();
+//! let main = workspaces.add("main".into(), None);
+//! let web = workspaces.add("web".into(), None);
+//! workspaces.set_activation_callback(|previous, workspace| {
+//!     previous.map(|previous| hide_views(previous.views()));
+//!     show_views(workspace.views());
+//! });
+//! workspaces.assign(view, web);
+//! workspaces.activate(web);
+//! ```
+//!
+//! Showing another workspace publishes `accessibility::Event::WorkspaceChanged`,
+//! which IPC clients get after subscribing. `info` is what the `list_workspaces`
+//! IPC request should be answered with.
+
+use std::{fmt, rc::Rc, cell::RefCell};
+
+use {accessibility::{self, Event}, output, view::View};
+
+/// Identifies a workspace of `Workspaces`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "ipc", derive(Serialize))]
+pub struct WorkspaceId(u64);
+
+/// Called with the workspace that was shown before, if there was one, and the
+/// workspace that is shown now.
+pub type ActivationCallback = Box<FnMut(Option<&Workspace>, &Workspace)>;
+
+/// A workspace and the views on it.
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    id: WorkspaceId,
+    name: String,
+    output: Option<output::Handle>,
+    views: Vec<View>
+}
+
+/// What's reported about a workspace, e.g. over IPC.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "ipc", derive(Serialize))]
+pub struct WorkspaceInfo {
+    pub id: WorkspaceId,
+    pub name: String,
+    /// The name of the output of the workspace, if it isn't global.
+    pub output: Option<String>,
+    /// Whether the workspace is shown.
+    pub active: bool,
+    /// How many views are on the workspace.
+    pub views: usize
+}
+
+impl Workspace {
+    /// Get the id of the workspace.
+    pub fn id(&self) -> WorkspaceId {
+        self.id
+    }
+
+    /// Get the name of the workspace.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the output the workspace is on, or `None` if it's global.
+    pub fn output(&self) -> Option<&output::Handle> {
+        self.output.as_ref()
+    }
+
+    /// Get the views on the workspace, in the order they were put there.
+    pub fn views(&self) -> Vec<View> {
+        self.views.iter().filter(|view| view.is_alive()).cloned().collect()
+    }
+
+    /// Determine if the view is on the workspace.
+    pub fn contains(&self, view: &View) -> bool {
+        self.views.contains(view)
+    }
+
+    fn output_name(&self) -> Option<String> {
+        self.output.as_ref().and_then(|output| output.run(|output| output.name()).ok())
+    }
+}
+
+/// The workspaces of the compositor.
+pub struct Workspaces {
+    workspaces: Vec<Workspace>,
+    /// The shown workspace of every output, or the one with no output for the
+    /// global workspaces.
+    active: Vec<(Option<output::Handle>, WorkspaceId)>,
+    next_id: u64,
+    callback: Option<Rc<RefCell<ActivationCallback>>>
+}
+
+impl fmt::Debug for Workspaces {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.debug_struct("Workspaces")
+                 .field("workspaces", &self.workspaces)
+                 .field("active", &self.active)
+                 .finish()
+    }
+}
+
+impl Default for Workspaces {
+    fn default() -> Self {
+        Workspaces::new()
+    }
+}
+
+impl Workspaces {
+    /// Start with no workspaces.
+    pub fn new() -> Self {
+        Workspaces { workspaces: Vec::new(),
+                     active: Vec::new(),
+                     next_id: 0,
+                     callback: None }
+    }
+
+    /// Set the function that's called whenever another workspace is shown.
+    pub fn set_activation_callback<F>(&mut self, callback: F)
+        where F: FnMut(Option<&Workspace>, &Workspace) + 'static
+    {
+        let callback: ActivationCallback = Box::new(callback);
+        self.callback = Some(Rc::new(RefCell::new(callback)))
+    }
+
+    /// Add a workspace, on the output or global.
+    ///
+    /// The first workspace of an output, or the first global one, is shown
+    /// straight away, without calling the activation callback.
+    pub fn add(&mut self, name: String, output: Option<output::Handle>) -> WorkspaceId {
+        let id = WorkspaceId(self.next_id);
+        self.next_id += 1;
+        if self.active_index(output.as_ref()).is_none() {
+            self.active.push((output.clone(), id));
+        }
+        self.workspaces.push(Workspace { id, name, output, views: Vec::new() });
+        id
+    }
+
+    /// Remove the workspace, giving back the views that were on it.
+    ///
+    /// If it was shown, its output (or the global workspaces) shows none
+    /// until another workspace is activated.
+    pub fn remove(&mut self, id: WorkspaceId) -> Option<Vec<View>> {
+        let index = self.workspaces.iter().position(|workspace| workspace.id == id)?;
+        self.active.retain(|&(_, active)| active != id);
+        let workspace = self.workspaces.remove(index);
+        Some(workspace.views())
+    }
+
+    /// Rename the workspace.
+    pub fn rename(&mut self, id: WorkspaceId, name: String) -> bool {
+        match self.get_mut(id) {
+            Some(workspace) => {
+                workspace.name = name;
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Get the workspace.
+    pub fn get(&self, id: WorkspaceId) -> Option<&Workspace> {
+        self.workspaces.iter().find(|workspace| workspace.id == id)
+    }
+
+    /// Find the workspace with the name.
+    pub fn by_name(&self, name: &str) -> Option<&Workspace> {
+        self.workspaces.iter().find(|workspace| workspace.name == name)
+    }
+
+    /// Get all of the workspaces, in the order they were added.
+    pub fn workspaces(&self) -> &[Workspace] {
+        &self.workspaces
+    }
+
+    /// Get the workspaces of the output, or the global ones.
+    pub fn workspaces_on(&self, output: Option<&output::Handle>) -> Vec<&Workspace> {
+        self.workspaces
+            .iter()
+            .filter(|workspace| workspace.output.as_ref() == output)
+            .collect()
+    }
+
+    /// Put the view on the workspace, taking it off the one it was on.
+    ///
+    /// Returns false if there's no such workspace.
+    pub fn assign(&mut self, view: View, id: WorkspaceId) -> bool {
+        if self.get(id).is_none() {
+            return false
+        }
+        self.unassign(&view);
+        if let Some(workspace) = self.get_mut(id) {
+            workspace.views.push(view)
+        }
+        true
+    }
+
+    /// Take the view off the workspace it's on, e.g. once it's destroyed.
+    pub fn unassign(&mut self, view: &View) -> Option<WorkspaceId> {
+        for workspace in &mut self.workspaces {
+            if let Some(index) = workspace.views.iter().position(|other| other == view) {
+                workspace.views.remove(index);
+                return Some(workspace.id)
+            }
+        }
+        None
+    }
+
+    /// Get the workspace the view is on.
+    pub fn workspace_of(&self, view: &View) -> Option<WorkspaceId> {
+        self.workspaces
+            .iter()
+            .find(|workspace| workspace.contains(view))
+            .map(|workspace| workspace.id)
+    }
+
+    /// Get the workspace that's shown on the output, or of the global ones.
+    pub fn active(&self, output: Option<&output::Handle>) -> Option<WorkspaceId> {
+        self.active_index(output).map(|index| self.active[index].1)
+    }
+
+    /// Determine if the workspace is shown.
+    pub fn is_active(&self, id: WorkspaceId) -> bool {
+        self.active.iter().any(|&(_, active)| active == id)
+    }
+
+    /// Determine if the view is on a workspace that's shown.
+    ///
+    /// Views that aren't on any workspace (e.g. sticky ones) are always
+    /// visible.
+    pub fn is_visible(&self, view: &View) -> bool {
+        self.workspace_of(view).map_or(true, |id| self.is_active(id))
+    }
+
+    /// Show the workspace on its output, or instead of the global workspace
+    /// that was shown.
+    ///
+    /// Calls the activation callback and publishes the change, unless the
+    /// workspace was already shown. Returns false if there's no such
+    /// workspace.
+    pub fn activate(&mut self, id: WorkspaceId) -> bool {
+        let output = match self.get(id) {
+            Some(workspace) => workspace.output.clone(),
+            None => return false
+        };
+        let previous = match self.active_index(output.as_ref()) {
+            Some(index) => {
+                let previous = self.active[index].1;
+                if previous == id {
+                    return true
+                }
+                self.active[index].1 = id;
+                Some(previous)
+            },
+            None => {
+                self.active.push((output, id));
+                None
+            }
+        };
+        self.prune();
+        let workspace = self.get(id).expect("Activated workspace missing");
+        if let Some(callback) = self.callback.clone() {
+            if let Ok(mut callback) = callback.try_borrow_mut() {
+                let previous = previous.and_then(|previous| self.get(previous));
+                (&mut **callback)(previous, workspace)
+            }
+        }
+        accessibility::publish(Event::WorkspaceChanged { name: workspace.name.clone(),
+                                                         output: workspace.output_name() });
+        true
+    }
+
+    /// Forget the workspaces of the output, e.g. once it's destroyed, giving
+    /// back the views that were on them.
+    pub fn remove_output(&mut self, output: &output::Handle) -> Vec<View> {
+        let mut views = Vec::new();
+        let ids: Vec<_> = self.workspaces_on(Some(output))
+                              .iter()
+                              .map(|workspace| workspace.id)
+                              .collect();
+        for id in ids {
+            views.extend(self.remove(id).unwrap_or_default())
+        }
+        self.active.retain(|&(ref other, _)| other.as_ref() != Some(output));
+        views
+    }
+
+    /// Describe the workspaces, e.g. for IPC.
+    pub fn info(&self) -> Vec<WorkspaceInfo> {
+        self.workspaces
+            .iter()
+            .map(|workspace| {
+                     WorkspaceInfo { id: workspace.id,
+                                     name: workspace.name.clone(),
+                                     output: workspace.output_name(),
+                                     active: self.is_active(workspace.id),
+                                     views: workspace.views().len() }
+                 })
+            .collect()
+    }
+
+    /// Forget the views that were destroyed.
+    pub fn prune(&mut self) {
+        for workspace in &mut self.workspaces {
+            workspace.views.retain(|view| view.is_alive())
+        }
+    }
+
+    fn get_mut(&mut self, id: WorkspaceId) -> Option<&mut Workspace> {
+        self.workspaces.iter_mut().find(|workspace| workspace.id == id)
+    }
+
+    fn active_index(&self, output: Option<&output::Handle>) -> Option<usize> {
+        self.active.iter().position(|&(ref other, _)| other.as_ref() == output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+
+    #[test]
+    fn first_workspace_is_shown() {
+        let mut workspaces = Workspaces::new();
+        assert_eq!(workspaces.active(None), None);
+        let main = workspaces.add("main".into(), None);
+        let web = workspaces.add("web".into(), None);
+        assert_eq!(workspaces.active(None), Some(main));
+        assert!(workspaces.is_active(main));
+        assert!(!workspaces.is_active(web));
+        assert_eq!(workspaces.by_name("web").map(Workspace::id), Some(web));
+        assert_eq!(workspaces.workspaces_on(None).len(), 2);
+    }
+
+    #[test]
+    fn activate_calls_back_with_the_previous_workspace() {
+        let mut workspaces = Workspaces::new();
+        let main = workspaces.add("main".into(), None);
+        let web = workspaces.add("web".into(), None);
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let recorded = calls.clone();
+        workspaces.set_activation_callback(move |previous, workspace| {
+            recorded.borrow_mut().push((previous.map(Workspace::id), workspace.id()))
+        });
+        assert!(workspaces.activate(web));
+        // NOTE Activating the shown workspace again is a no-op.
+        assert!(workspaces.activate(web));
+        assert!(workspaces.activate(main));
+        assert_eq!(*calls.borrow(), vec![(Some(main), web), (Some(web), main)]);
+        assert_eq!(workspaces.active(None), Some(main));
+    }
+
+    #[test]
+    fn removing_the_shown_workspace_shows_none() {
+        let mut workspaces = Workspaces::new();
+        let main = workspaces.add("main".into(), None);
+        let web = workspaces.add("web".into(), None);
+        assert_eq!(workspaces.remove(main).map(|views| views.len()), Some(0));
+        assert_eq!(workspaces.remove(main), None);
+        assert_eq!(workspaces.active(None), None);
+        assert!(workspaces.activate(web));
+        assert_eq!(workspaces.active(None), Some(web));
+    }
+
+    #[test]
+    fn unknown_workspaces() {
+        let mut workspaces = Workspaces::new();
+        let main = workspaces.add("main".into(), None);
+        workspaces.remove(main);
+        assert!(!workspaces.activate(main));
+        assert!(!workspaces.rename(main, "gone".into()));
+        assert!(workspaces.get(main).is_none());
+    }
+
+    #[test]
+    fn info_describes_the_workspaces() {
+        let mut workspaces = Workspaces::new();
+        let main = workspaces.add("main".into(), None);
+        let web = workspaces.add("web".into(), None);
+        assert!(workspaces.rename(web, "browser".into()));
+        assert_eq!(workspaces.info(),
+                   vec![WorkspaceInfo { id: main,
+                                        name: "main".into(),
+                                        output: None,
+                                        active: true,
+                                        views: 0 },
+                        WorkspaceInfo { id: web,
+                                        name: "browser".into(),
+                                        output: None,
+                                        active: false,
+                                        views: 0 }]);
+    }
+}
@@ -0,0 +1,142 @@
+//! The error type of the crate, for the functions that can fail for more than
+//! one reason.
+//!
+//! The more specific errors (`HandleErr`, `render::RenderError`,
+//! `backend::BackendError`, ...) convert into it, so they can be passed up
+//! with `?` from a function returning `wlroots::Error`:
+//!
+//! ```rust,no_run,ignore
+//! fn setup() -> Result<(), wlroots::Error> {
+//!     let cursor = Cursor::create(Box::new(CursorHandler))?;
+//!     let mut xcursor_manager = xcursor::Manager::create("default".to_string(), 24)?;
+//!     cursor.run(|cursor| xcursor_manager.set_cursor_image("left_ptr".into(), cursor))?;
+//!     Ok(())
+//! }
+//! ```
+
+use std::{error, fmt};
+
+#[cfg(feature = "unstable")]
+use {backend::BackendError, output::ConfigurationError, render::RenderError};
+use utils::HandleErr;
+
+/// The ways the functions of the crate can fail.
+///
+/// Some of the variants only exist with the `unstable` feature, and more may
+/// be added, so matches on it need a wildcard arm.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Error {
+    /// A handle couldn't be upgraded.
+    Handle(HandleErr),
+    /// Setting up rendering failed.
+    #[cfg(feature = "unstable")]
+    Render(RenderError),
+    /// Setting up a backend failed.
+    #[cfg(feature = "unstable")]
+    Backend(BackendError),
+    /// The outputs couldn't be configured.
+    #[cfg(feature = "unstable")]
+    OutputConfiguration(ConfigurationError),
+    /// wlroots couldn't make the object of this type, usually because it ran
+    /// out of memory.
+    Create(&'static str),
+    /// The cursor theme with this name couldn't be loaded, or the default
+    /// theme for `None`.
+    CursorTheme(Option<String>),
+    /// The global of the protocol with this name couldn't be created.
+    Protocol(&'static str),
+    /// The socket clients connect to couldn't be opened.
+    Socket,
+    /// The service with this name (e.g. the IPC server) couldn't be started,
+    /// why is logged.
+    Service(&'static str),
+    #[doc(hidden)]
+    __Nonexhaustive
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Handle(ref err) => write!(f, "handle error: {}", err),
+            #[cfg(feature = "unstable")]
+            Error::Render(ref err) => write!(f, "render error: {}", err),
+            #[cfg(feature = "unstable")]
+            Error::Backend(ref err) => write!(f, "backend error: {}", err),
+            #[cfg(feature = "unstable")]
+            Error::OutputConfiguration(ref err) => {
+                write!(f, "output configuration error: {}", err)
+            },
+            Error::Create(type_name) => write!(f, "could not create {}", type_name),
+            Error::CursorTheme(Some(ref name)) => {
+                write!(f, "could not load cursor theme {}", name)
+            },
+            Error::CursorTheme(None) => write!(f, "could not load the default cursor theme"),
+            Error::Protocol(name) => write!(f, "could not create the {} global", name),
+            Error::Socket => write!(f, "could not open the wayland socket"),
+            Error::Service(name) => write!(f, "could not start the {}", name),
+            Error::__Nonexhaustive => unreachable!()
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Handle(ref err) => error::Error::description(err),
+            #[cfg(feature = "unstable")]
+            Error::Render(ref err) => error::Error::description(err),
+            #[cfg(feature = "unstable")]
+            Error::Backend(ref err) => error::Error::description(err),
+            #[cfg(feature = "unstable")]
+            Error::OutputConfiguration(ref err) => error::Error::description(err),
+            Error::Create(_) => "Could not create object",
+            Error::CursorTheme(_) => "Could not load cursor theme",
+            Error::Protocol(_) => "Could not create protocol global",
+            Error::Socket => "Could not open the wayland socket",
+            Error::Service(_) => "Could not start a service",
+            Error::__Nonexhaustive => unreachable!()
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Handle(ref err) => Some(err),
+            #[cfg(feature = "unstable")]
+            Error::Render(ref err) => Some(err),
+            #[cfg(feature = "unstable")]
+            Error::Backend(ref err) => Some(err),
+            #[cfg(feature = "unstable")]
+            Error::OutputConfiguration(ref err) => Some(err),
+            Error::Create(_) | Error::CursorTheme(_) | Error::Protocol(_) | Error::Socket
+            | Error::Service(_) => None,
+            Error::__Nonexhaustive => unreachable!()
+        }
+    }
+}
+
+impl From<HandleErr> for Error {
+    fn from(err: HandleErr) -> Self {
+        Error::Handle(err)
+    }
+}
+
+#[cfg(feature = "unstable")]
+impl From<RenderError> for Error {
+    fn from(err: RenderError) -> Self {
+        Error::Render(err)
+    }
+}
+
+#[cfg(feature = "unstable")]
+impl From<BackendError> for Error {
+    fn from(err: BackendError) -> Self {
+        Error::Backend(err)
+    }
+}
+
+#[cfg(feature = "unstable")]
+impl From<ConfigurationError> for Error {
+    fn from(err: ConfigurationError) -> Self {
+        Error::OutputConfiguration(err)
+    }
+}
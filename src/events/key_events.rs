@@ -1,9 +1,9 @@
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use wlroots_sys::{wlr_event_keyboard_key, wlr_key_state, xkb_keysym_t, xkb_state,
                   xkb_state_key_get_syms};
 
-use input::keyboard;
+use {input::keyboard, utils::{event_instant, event_time}};
 
 #[derive(Debug)]
 pub struct Key {
@@ -30,6 +30,18 @@ impl Key {
         Duration::from_millis(unsafe { (*self.key).time_msec } as u64)
     }
 
+    /// Get the time on the monotonic clock the event happened at.
+    ///
+    /// Unlike the timestamp this doesn't wrap around (see `utils::event_time`).
+    pub fn time(&self) -> Duration {
+        event_time(unsafe { (*self.key).time_msec })
+    }
+
+    /// Get when the event happened.
+    pub fn instant(&self) -> Instant {
+        event_instant(unsafe { (*self.key).time_msec })
+    }
+
     /// TODO What is this?
     pub fn update_state(&self) -> bool {
         unsafe { (*self.key).update_state }
@@ -6,6 +6,7 @@ pub mod tablet_tool_events;
 pub mod touch_events;
 pub mod seat_events;
 pub mod tablet_pad_events;
+#[cfg(feature = "xwayland")]
 pub mod xwayland_events;
 
 pub use self::key_events::Key;
@@ -1,11 +1,13 @@
 //! Pointers and their events
 
+use std::time::{Duration, Instant};
+
 use libc::c_double;
 use wlroots_sys::{wlr_event_pointer_axis, wlr_event_pointer_button, wlr_event_pointer_motion,
                   wlr_event_pointer_motion_absolute, wlr_axis_orientation,
                   wlr_axis_source, wlr_button_state};
 
-use input;
+use {input, utils::{event_instant, event_time}};
 
 // NOTE Taken from linux/input-event-codes.h
 // TODO Find a way to automatically parse and fetch from there.
@@ -19,12 +21,19 @@ pub const BTN_FORWARD: u32 = 0x115;
 pub const BTN_BACK: u32 = 0x116;
 pub const BTN_TASK: u32 = 0x117;
 
+/// The high-resolution scroll value of one wheel detent.
+pub const V120_STEP: i32 = 120;
+
 /// Event that triggers when the pointer device scrolls (e.g using a wheel
 // or in the case of a touchpad when you use two fingers to scroll).
 #[derive(Debug)]
 pub struct Axis {
     event: *mut wlr_event_pointer_axis,
-    device: input::Device
+    device: input::Device,
+    /// See `input::Device::set_scroll_factor`.
+    scroll_factor: f64,
+    /// The discrete steps, scaled by the scroll factor.
+    delta_discrete: i32
 }
 
 /// Event that triggers when a button is pressed (e.g left click, right click,
@@ -74,6 +83,18 @@ impl Button {
         unsafe { (*self.event).time_msec }
     }
 
+    /// Get the time on the monotonic clock the event happened at.
+    ///
+    /// Unlike the timestamp this doesn't wrap around (see `utils::event_time`).
+    pub fn time(&self) -> Duration {
+        event_time(unsafe { (*self.event).time_msec })
+    }
+
+    /// Get when the event happened.
+    pub fn instant(&self) -> Instant {
+        event_instant(unsafe { (*self.event).time_msec })
+    }
+
     /// Get the value of the button pressed. This will generally be an atomically
     /// increasing value, with e.g left click being 1 and right click being 2...
     ///
@@ -87,8 +108,11 @@ impl Button {
 impl Axis {
     /// Constructs a `Axis` from a raw event pointer.
     pub(crate) unsafe fn from_ptr(event: *mut wlr_event_pointer_axis) -> Self {
-        Axis { device: input::Device::from_ptr((*event).device),
-                    event }
+        let device = input::Device::from_ptr((*event).device);
+        Axis { scroll_factor: device.scroll_factor(),
+               delta_discrete: device.scaled_discrete(event),
+               device,
+               event }
     }
 
     /// Get the device this event refers to.
@@ -101,19 +125,55 @@ impl Axis {
         unsafe { (*self.event).time_msec }
     }
 
+    /// Get the time on the monotonic clock the event happened at.
+    ///
+    /// Unlike the timestamp this doesn't wrap around (see `utils::event_time`).
+    pub fn time(&self) -> Duration {
+        event_time(unsafe { (*self.event).time_msec })
+    }
+
+    /// Get when the event happened.
+    pub fn instant(&self) -> Instant {
+        event_instant(unsafe { (*self.event).time_msec })
+    }
+
+    /// Get what caused the scroll (e.g. a wheel, or fingers on a touchpad).
+    ///
+    /// Clients use this to e.g. only scroll kinetically for finger scrolls.
     pub fn source(&self) -> wlr_axis_source {
         unsafe { (*self.event).source }
     }
 
+    /// Get whether this scrolls vertically or horizontally.
     pub fn orientation(&self) -> wlr_axis_orientation {
         unsafe { (*self.event).orientation }
     }
 
-    /// Get the change from the last axis value.
+    /// Get the change from the last axis value, scaled by the scroll factor
+    /// of the device.
     ///
     /// Useful to determine e.g how much to scroll.
     pub fn delta(&self) -> f64 {
-        unsafe { (*self.event).delta }
+        unsafe { (*self.event).delta * self.scroll_factor }
+    }
+
+    /// Get the change in discrete steps, e.g. the number of wheel detents,
+    /// scaled by the scroll factor of the device.
+    ///
+    /// This is 0 for sources that don't scroll in steps, like fingers, and
+    /// can be 0 for a detent when the steps are scaled down (see
+    /// `input::Device::set_scroll_factor`).
+    pub fn delta_discrete(&self) -> i32 {
+        self.delta_discrete
+    }
+
+    /// Get the change in fractions of a wheel detent, where `V120_STEP` is a
+    /// whole detent.
+    ///
+    /// This version of wlroots doesn't report high-resolution scrolling, so
+    /// this is always a multiple of `V120_STEP`.
+    pub fn delta_v120(&self) -> i32 {
+        self.delta_discrete() * V120_STEP
     }
 }
 
@@ -134,6 +194,18 @@ impl Motion {
         unsafe { (*self.event).time_msec }
     }
 
+    /// Get the time on the monotonic clock the event happened at.
+    ///
+    /// Unlike the timestamp this doesn't wrap around (see `utils::event_time`).
+    pub fn time(&self) -> Duration {
+        event_time(unsafe { (*self.event).time_msec })
+    }
+
+    /// Get when the event happened.
+    pub fn instant(&self) -> Instant {
+        event_instant(unsafe { (*self.event).time_msec })
+    }
+
     /// Get the change from the last positional value.
     ///
     /// Returned in (x, y) form.
@@ -158,6 +230,18 @@ impl AbsoluteMotion {
         unsafe { (*self.event).time_msec }
     }
 
+    /// Get the time on the monotonic clock the event happened at.
+    ///
+    /// Unlike the timestamp this doesn't wrap around (see `utils::event_time`).
+    pub fn time(&self) -> Duration {
+        event_time(unsafe { (*self.event).time_msec })
+    }
+
+    /// Get when the event happened.
+    pub fn instant(&self) -> Instant {
+        event_instant(unsafe { (*self.event).time_msec })
+    }
+
     /// Get the absolute position of the pointer from this event.
     pub fn pos(&self) -> (c_double, c_double) {
         unsafe { ((*self.event).x, (*self.event).y) }
@@ -1,5 +1,7 @@
 //! TODO Documentation
 
+use std::time::{Duration, Instant};
+
 use libc::{c_double, c_uint};
 
 use wlroots_sys::{wlr_event_tablet_pad_button, wlr_event_tablet_pad_ring,
@@ -7,6 +9,8 @@ use wlroots_sys::{wlr_event_tablet_pad_button, wlr_event_tablet_pad_ring,
 
 pub use wlroots_sys::{wlr_button_state, wlr_tablet_pad_ring_source, wlr_tablet_pad_strip_source};
 
+use utils::{event_instant, event_time};
+
 #[derive(Debug)]
 /// Event that is triggered when a tablet pad button event occurs.
 pub struct Button {
@@ -34,6 +38,18 @@ impl Button {
         unsafe { (*self.event).time_msec }
     }
 
+    /// Get the time on the monotonic clock the event happened at.
+    ///
+    /// Unlike the timestamp this doesn't wrap around (see `utils::event_time`).
+    pub fn time(&self) -> Duration {
+        event_time(unsafe { (*self.event).time_msec })
+    }
+
+    /// Get when the event happened.
+    pub fn instant(&self) -> Instant {
+        event_instant(unsafe { (*self.event).time_msec })
+    }
+
     pub fn button(&self) -> u32 {
         unsafe { (*self.event).button }
     }
@@ -56,6 +72,18 @@ impl Ring {
         unsafe { (*self.event).time_msec }
     }
 
+    /// Get the time on the monotonic clock the event happened at.
+    ///
+    /// Unlike the timestamp this doesn't wrap around (see `utils::event_time`).
+    pub fn time(&self) -> Duration {
+        event_time(unsafe { (*self.event).time_msec })
+    }
+
+    /// Get when the event happened.
+    pub fn instant(&self) -> Instant {
+        event_instant(unsafe { (*self.event).time_msec })
+    }
+
     pub fn source(&self) -> wlr_tablet_pad_ring_source {
         unsafe { (*self.event).source }
     }
@@ -82,6 +110,18 @@ impl Strip {
         unsafe { (*self.event).time_msec }
     }
 
+    /// Get the time on the monotonic clock the event happened at.
+    ///
+    /// Unlike the timestamp this doesn't wrap around (see `utils::event_time`).
+    pub fn time(&self) -> Duration {
+        event_time(unsafe { (*self.event).time_msec })
+    }
+
+    /// Get when the event happened.
+    pub fn instant(&self) -> Instant {
+        event_instant(unsafe { (*self.event).time_msec })
+    }
+
     pub fn source(&self) -> wlr_tablet_pad_strip_source {
         unsafe { (*self.event).source }
     }
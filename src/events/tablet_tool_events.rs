@@ -1,9 +1,16 @@
 //! TODO Documentation
 
+use std::time::{Duration, Instant};
+
 use wlroots_sys::{wlr_button_state, wlr_event_tablet_tool_axis, wlr_event_tablet_tool_button,
                   wlr_event_tablet_tool_proximity, wlr_event_tablet_tool_tip,
                   wlr_tablet_tool_proximity_state, wlr_tablet_tool_tip_state};
-use input::tablet_tool;
+use {input::tablet_tool, utils::{event_instant, event_time}};
+
+// NOTE Taken from linux/input-event-codes.h
+pub const BTN_TOOL_PEN: u32 = 0x140;
+pub const BTN_STYLUS: u32 = 0x14b;
+pub const BTN_STYLUS2: u32 = 0x14c;
 
 #[derive(Debug)]
 /// Event that is triggered when a tablet tool axis event occurs.
@@ -36,6 +43,18 @@ impl Axis {
         unsafe { (*self.event).time_msec }
     }
 
+    /// Get the time on the monotonic clock the event happened at.
+    ///
+    /// Unlike the timestamp this doesn't wrap around (see `utils::event_time`).
+    pub fn time(&self) -> Duration {
+        event_time(unsafe { (*self.event).time_msec })
+    }
+
+    /// Get when the event happened.
+    pub fn instant(&self) -> Instant {
+        event_instant(unsafe { (*self.event).time_msec })
+    }
+
     pub fn updated_axes(&self) -> tablet_tool::Axis {
         unsafe { tablet_tool::Axis::from_bits_truncate((*self.event).updated_axes) }
     }
@@ -80,6 +99,18 @@ impl Proximity {
         unsafe { (*self.event).time_msec }
     }
 
+    /// Get the time on the monotonic clock the event happened at.
+    ///
+    /// Unlike the timestamp this doesn't wrap around (see `utils::event_time`).
+    pub fn time(&self) -> Duration {
+        event_time(unsafe { (*self.event).time_msec })
+    }
+
+    /// Get when the event happened.
+    pub fn instant(&self) -> Instant {
+        event_instant(unsafe { (*self.event).time_msec })
+    }
+
     /// Gets the position of the event in mm.
     ///
     /// Return value is in (x, y) format.
@@ -101,6 +132,18 @@ impl Tip {
         unsafe { (*self.event).time_msec }
     }
 
+    /// Get the time on the monotonic clock the event happened at.
+    ///
+    /// Unlike the timestamp this doesn't wrap around (see `utils::event_time`).
+    pub fn time(&self) -> Duration {
+        event_time(unsafe { (*self.event).time_msec })
+    }
+
+    /// Get when the event happened.
+    pub fn instant(&self) -> Instant {
+        event_instant(unsafe { (*self.event).time_msec })
+    }
+
     /// Gets the position of the event in mm.
     ///
     /// Return value is in (x, y) format.
@@ -122,6 +165,18 @@ impl Button {
         unsafe { (*self.event).time_msec }
     }
 
+    /// Get the time on the monotonic clock the event happened at.
+    ///
+    /// Unlike the timestamp this doesn't wrap around (see `utils::event_time`).
+    pub fn time(&self) -> Duration {
+        event_time(unsafe { (*self.event).time_msec })
+    }
+
+    /// Get when the event happened.
+    pub fn instant(&self) -> Instant {
+        event_instant(unsafe { (*self.event).time_msec })
+    }
+
     pub fn button(&self) -> u32 {
         unsafe { (*self.event).button }
     }
@@ -1,8 +1,12 @@
 //! TODO Documentation
 
+use std::time::{Duration, Instant};
+
 use wlroots_sys::{wlr_event_touch_cancel, wlr_event_touch_down, wlr_event_touch_motion,
                   wlr_event_touch_up};
 
+use utils::{event_instant, event_time};
+
 #[derive(Debug)]
 /// Event that is triggered when a touch down event occurs.
 pub struct Down {
@@ -38,6 +42,18 @@ impl Down {
         unsafe { (*self.event).time_msec }
     }
 
+    /// Get the time on the monotonic clock the event happened at.
+    ///
+    /// Unlike the timestamp this doesn't wrap around (see `utils::event_time`).
+    pub fn time(&self) -> Duration {
+        event_time(unsafe { (*self.event).time_msec })
+    }
+
+    /// Get when the event happened.
+    pub fn instant(&self) -> Instant {
+        event_instant(unsafe { (*self.event).time_msec })
+    }
+
     /// Gets the touch id associated with this event.
     pub fn touch_id(&self) -> i32 {
         unsafe { (*self.event).touch_id }
@@ -61,6 +77,18 @@ impl Up {
         unsafe { (*self.event).time_msec }
     }
 
+    /// Get the time on the monotonic clock the event happened at.
+    ///
+    /// Unlike the timestamp this doesn't wrap around (see `utils::event_time`).
+    pub fn time(&self) -> Duration {
+        event_time(unsafe { (*self.event).time_msec })
+    }
+
+    /// Get when the event happened.
+    pub fn instant(&self) -> Instant {
+        event_instant(unsafe { (*self.event).time_msec })
+    }
+
     /// Gets the touch id associated with this event.
     pub fn touch_id(&self) -> i32 {
         unsafe { (*self.event).touch_id }
@@ -78,6 +106,18 @@ impl Motion {
         unsafe { (*self.event).time_msec }
     }
 
+    /// Get the time on the monotonic clock the event happened at.
+    ///
+    /// Unlike the timestamp this doesn't wrap around (see `utils::event_time`).
+    pub fn time(&self) -> Duration {
+        event_time(unsafe { (*self.event).time_msec })
+    }
+
+    /// Get when the event happened.
+    pub fn instant(&self) -> Instant {
+        event_instant(unsafe { (*self.event).time_msec })
+    }
+
     /// Gets the touch id associated with this event.
     pub fn touch_id(&self) -> i32 {
         unsafe { (*self.event).touch_id }
@@ -101,6 +141,18 @@ impl Cancel {
         unsafe { (*self.event).time_msec }
     }
 
+    /// Get the time on the monotonic clock the event happened at.
+    ///
+    /// Unlike the timestamp this doesn't wrap around (see `utils::event_time`).
+    pub fn time(&self) -> Duration {
+        event_time(unsafe { (*self.event).time_msec })
+    }
+
+    /// Get when the event happened.
+    pub fn instant(&self) -> Instant {
+        event_instant(unsafe { (*self.event).time_msec })
+    }
+
     /// Gets the touch id associated with this event.
     pub fn touch_id(&self) -> i32 {
         unsafe { (*self.event).touch_id }
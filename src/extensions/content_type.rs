@@ -0,0 +1,259 @@
+//! Support for the content type hint protocol (`wp_content_type_manager_v1`),
+//! with which clients tell what their surfaces show (a photo, a video or a
+//! game), e.g. to enable adaptive sync or skip animations for them.
+//!
+//! The protocol is implemented by this crate (see
+//! [`extensions`](../index.html)). The hint is double buffered like the rest
+//! of the state of the surface: it's applied when the surface commits, which
+//! is when `Handler::content_type_changed` is called. The current hint is
+//! given by `Surface::content_type`.
+
+use std::{cell::RefCell, ptr, rc::Rc};
+
+use libc;
+use wayland_sys::server::{signal::wl_signal_add, WAYLAND_SERVER_HANDLE};
+use wlroots_sys::{wayland_server::{NewResource, Resource, protocol::wl_surface::WlSurface},
+                  wlr_surface, wlr_surface_from_resource};
+use wlroots_sys::protocols::content_type::server::{
+    wp_content_type_manager_v1::{self as manager_v1, WpContentTypeManagerV1},
+    wp_content_type_v1::{self as content_type_v1, WpContentTypeV1}};
+pub use wlroots_sys::protocols::content_type::server::wp_content_type_v1::Type as ContentType;
+
+use {compositor::{self, Compositor, CustomGlobal}, surface};
+
+/// The version of the protocol that is implemented.
+const VERSION: u32 = 1;
+
+/// Handles the content type hints of the surfaces.
+#[allow(unused_variables)]
+pub trait Handler {
+    /// Called when the content type of the surface changed, once the surface
+    /// committed the new hint.
+    fn content_type_changed(&mut self,
+                            compositor_handle: compositor::Handle,
+                            surface: surface::Handle,
+                            content_type: ContentType) {
+    }
+}
+
+struct Tracked {
+    surface: *mut wlr_surface,
+    /// The object the client sets the hint with, until it's destroyed.
+    resource: Option<Resource<WpContentTypeV1>>,
+    /// The hint that's applied on the next commit, if it was set.
+    pending: Option<ContentType>,
+    current: ContentType,
+    listener: Option<Box<ContentTypeSurface>>
+}
+
+struct State {
+    /// Kept apart so the handler can be called without borrowing the rest.
+    handler: Option<Rc<RefCell<Box<Handler>>>>,
+    surfaces: Vec<Tracked>
+}
+
+thread_local! {
+    static STATE: RefCell<State> = RefCell::new(State { handler: None,
+                                                        surfaces: Vec::new() });
+}
+
+wayland_listener!(pub(crate) ContentTypeSurface, *mut wlr_surface, [
+    commit_listener => commit_notify: |this: &mut ContentTypeSurface, _data: *mut libc::c_void,|
+    unsafe {
+        let surface = this.data;
+        let (changed, done) = STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            let index = match state.surfaces.iter().position(|tracked| tracked.surface == surface) {
+                Some(index) => index,
+                None => return (None, None)
+            };
+            let changed = {
+                let tracked = &mut state.surfaces[index];
+                match tracked.pending.take() {
+                    Some(pending) if pending != tracked.current => {
+                        tracked.current = pending;
+                        Some(pending)
+                    },
+                    _ => None
+                }
+            };
+            // NOTE Once the object is gone and the reset to none is applied
+            // there's nothing left to track.
+            let done = if state.surfaces[index].resource.is_none() {
+                Some(state.surfaces.remove(index))
+            } else {
+                None
+            };
+            (changed, done)
+        });
+        if let Some(content_type) = changed {
+            content_type_changed(surface, content_type);
+        }
+        if let Some(tracked) = done {
+            // NOTE This frees the listener that's running, so nothing comes after.
+            forget(tracked);
+        }
+    };
+    destroy_listener => destroy_notify: |this: &mut ContentTypeSurface, _data: *mut libc::c_void,|
+    unsafe {
+        let surface = this.data;
+        let tracked = STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            let index = state.surfaces.iter().position(|tracked| tracked.surface == surface)?;
+            Some(state.surfaces.remove(index))
+        });
+        if let Some(tracked) = tracked {
+            // NOTE This frees the listener that's running, so nothing comes after.
+            forget(tracked);
+        }
+    };
+]);
+
+/// The `wp_content_type_manager_v1` global.
+///
+/// The global is removed when this is dropped.
+pub struct Manager {
+    global: Option<CustomGlobal<WpContentTypeManagerV1>>
+}
+
+impl Manager {
+    /// Advertise the global, telling the handler about the hints of the
+    /// surfaces.
+    pub fn create(compositor: &mut Compositor, handler: Box<Handler>) -> Self {
+        STATE.with(|state| state.borrow_mut().handler = Some(Rc::new(RefCell::new(handler))));
+        let global = compositor.create_global(VERSION, |resource, _| bind(resource));
+        Manager { global: Some(global) }
+    }
+
+    /// Advertise the global like `create`, without boxing the handler
+    /// yourself.
+    pub fn create_with<H: Handler + 'static>(compositor: &mut Compositor, handler: H) -> Self {
+        Manager::create(compositor, Box::new(handler))
+    }
+}
+
+impl Drop for Manager {
+    fn drop(&mut self) {
+        if let Some(global) = self.global.take() {
+            global.destroy()
+        }
+    }
+}
+
+/// Get the content type the surface last committed.
+pub(crate) fn current(surface: *mut wlr_surface) -> ContentType {
+    STATE.with(|state| {
+                   state.borrow()
+                        .surfaces
+                        .iter()
+                        .find(|tracked| tracked.surface == surface)
+                        .map_or(ContentType::None, |tracked| tracked.current)
+               })
+}
+
+fn bind(resource: NewResource<WpContentTypeManagerV1>) {
+    resource.implement(|request, manager: Resource<WpContentTypeManagerV1>| match request {
+                           manager_v1::Request::Destroy => {},
+                           manager_v1::Request::GetSurfaceContentType { id, surface } => {
+                               get_content_type(&manager, id, surface)
+                           }
+                       },
+                       None::<fn(_)>);
+}
+
+fn get_content_type(manager: &Resource<WpContentTypeManagerV1>,
+                    id: NewResource<WpContentTypeV1>,
+                    surface: Resource<WlSurface>) {
+    let resource = id.implement(|request, resource: Resource<WpContentTypeV1>| match request {
+                                    content_type_v1::Request::Destroy => {},
+                                    content_type_v1::Request::SetContentType { content_type } => {
+                                        set_pending(&resource, Some(content_type))
+                                    }
+                                },
+                                Some(|resource: Resource<WpContentTypeV1>| {
+                                    set_pending(&resource, None)
+                                }));
+    unsafe {
+        let surface = wlr_surface_from_resource(surface.c_ptr() as _);
+        if surface.is_null() {
+            return
+        }
+        let constructed = STATE.with(|state| {
+            state.borrow()
+                 .surfaces
+                 .iter()
+                 .any(|tracked| tracked.surface == surface && tracked.resource.is_some())
+        });
+        if constructed {
+            manager.post_error(manager_v1::Error::AlreadyConstructed as u32,
+                               "the surface already has a content type object".into());
+            return
+        }
+        STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            // NOTE The surface may still be tracked until the reset of its
+            // last object is committed.
+            if let Some(tracked) = state.surfaces.iter_mut().find(|tracked| tracked.surface == surface) {
+                tracked.resource = Some(resource);
+                return
+            }
+            let mut listener = ContentTypeSurface::new(surface);
+            wl_signal_add(&mut (*surface).events.commit as *mut _ as _,
+                          listener.commit_listener() as *mut _ as _);
+            wl_signal_add(&mut (*surface).events.destroy as *mut _ as _,
+                          listener.destroy_listener() as *mut _ as _);
+            state.surfaces.push(Tracked { surface,
+                                          resource: Some(resource),
+                                          pending: None,
+                                          current: ContentType::None,
+                                          listener: Some(listener) });
+        });
+    }
+}
+
+/// Set the hint applied on the next commit, or reset it to none once the
+/// object is destroyed (`content_type` is `None`).
+fn set_pending(resource: &Resource<WpContentTypeV1>, content_type: Option<ContentType>) {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let tracked = state.surfaces.iter_mut().find(|tracked| {
+            tracked.resource
+                   .as_ref()
+                   .map_or(false, |other| other.c_ptr() == resource.c_ptr())
+        });
+        if let Some(tracked) = tracked {
+            match content_type {
+                Some(content_type) => tracked.pending = Some(content_type),
+                None => {
+                    tracked.pending = Some(ContentType::None);
+                    tracked.resource = None;
+                }
+            }
+        }
+    })
+}
+
+unsafe fn content_type_changed(surface: *mut wlr_surface, content_type: ContentType) {
+    let handler = STATE.with(|state| state.borrow().handler.clone());
+    match (handler, compositor::handle()) {
+        (Some(handler), Some(compositor)) if !(*surface).data.is_null() => {
+            handler.borrow_mut().content_type_changed(compositor,
+                                                      surface::Handle::from_ptr(surface),
+                                                      content_type)
+        },
+        _ => {}
+    }
+}
+
+/// Stop listening to the surface of the entry, which is no longer tracked.
+unsafe fn forget(mut tracked: Tracked) {
+    tracked.surface = ptr::null_mut();
+    if let Some(mut listener) = tracked.listener.take() {
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_list_remove,
+                      &mut (*listener.commit_listener()).link as *mut _ as _);
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_list_remove,
+                      &mut (*listener.destroy_listener()).link as *mut _ as _);
+    }
+}
@@ -0,0 +1,67 @@
+//! Deciding who draws the decorations (title bar, borders) of windows.
+//!
+//! Clients negotiate the decorations of their windows with a decoration
+//! protocol, and the compositor has the last word. The decision is made by one
+//! `Policy` for all of the decoration protocols, set with `set_policy`, so it
+//! only has to be configured once.
+//!
+//! This version of wlroots only has the KDE server decoration protocol (see
+//! `server_decoration`); `xdg-decoration` needs a newer one. Its module should
+//! ask the same policy once it's added.
+
+use std::cell::Cell;
+
+use {compositor, surface};
+
+/// Who draws the decorations of a window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Mode {
+    /// The client draws them itself, or goes without.
+    ClientSide,
+    /// The compositor draws them.
+    ServerSide
+}
+
+/// Callback that decides how the window of the surface is decorated.
+///
+/// `requested` is the mode the client asked for, or `None` if it hasn't
+/// asked yet (e.g. when it started to negotiate).
+pub type Policy = fn(compositor_handle: compositor::Handle,
+                     surface: surface::Handle,
+                     requested: Option<Mode>)
+                     -> Mode;
+
+thread_local! {
+    static POLICY: Cell<Option<Policy>> = Cell::new(None);
+}
+
+/// Use the policy for the decorations of every protocol.
+///
+/// Without a policy the protocols fall back to their defaults, e.g. the
+/// default mode of the server decoration manager.
+pub fn set_policy<T: Into<Option<Policy>>>(policy: T) {
+    POLICY.with(|current| current.set(policy.into()))
+}
+
+/// Get the policy set with `set_policy`, if any.
+pub fn policy() -> Option<Policy> {
+    POLICY.with(|policy| policy.get())
+}
+
+/// Do what the client asks for, and have clients that don't ask decorate
+/// themselves.
+pub fn follow_client(_: compositor::Handle, _: surface::Handle, requested: Option<Mode>) -> Mode {
+    requested.unwrap_or(Mode::ClientSide)
+}
+
+/// Always draw the decorations in the compositor.
+pub fn server_side(_: compositor::Handle, _: surface::Handle, _: Option<Mode>) -> Mode {
+    Mode::ServerSide
+}
+
+/// Ask the policy how to decorate the surface, if there is one.
+pub(crate) fn decide(surface: surface::Handle, requested: Option<Mode>) -> Option<Mode> {
+    let policy = policy()?;
+    let compositor = compositor::handle()?;
+    Some(policy(compositor, surface, requested))
+}
@@ -0,0 +1,266 @@
+//! Support for the foreign toplevel list protocol
+//! (`ext_foreign_toplevel_list_v1`), which lets tools such as task bars,
+//! window switchers and screenshot tools list the windows of all clients.
+//!
+//! Every view is listed from the moment it's mapped until it's unmapped, along
+//! with its title and app id, including the views that were mapped before the
+//! global was advertised. Each listed view gets an identifier that stays the
+//! same while it's mapped, which other protocols use to refer to it (see
+//! `extensions::image_capture_source`).
+//!
+//! The protocol is read only, clients can't act on the windows with it. It's
+//! implemented by this crate (see [`extensions`](../index.html)).
+
+use std::{cell::RefCell, ptr};
+
+use libc::c_char;
+use wlroots_sys::wayland_server::{NewResource, Resource};
+use wlroots_sys::protocols::foreign_toplevel_list::server as protocol;
+use self::protocol::{ext_foreign_toplevel_handle_v1::{self, ExtForeignToplevelHandleV1},
+                     ext_foreign_toplevel_list_v1::{self, ExtForeignToplevelListV1}};
+
+use {compositor::{Compositor, CustomGlobal}, utils, view::View};
+
+/// The version of the protocol that is implemented.
+const VERSION: u32 = 1;
+
+/// A view that is listed.
+struct Toplevel {
+    view: View,
+    identifier: String,
+    title: Option<String>,
+    app_id: Option<String>,
+    /// Where wlroots stored the title and app id of XDG toplevels when they
+    /// were last checked.
+    names: (*const c_char, *const c_char),
+    handles: Vec<Resource<ExtForeignToplevelHandleV1>>
+}
+
+/// The views are tracked even when the global isn't advertised, so they can be
+/// listed once it is.
+struct State {
+    /// The lists clients are still listening on.
+    lists: Vec<Resource<ExtForeignToplevelListV1>>,
+    toplevels: Vec<Toplevel>
+}
+
+thread_local! {
+    static STATE: RefCell<State> = RefCell::new(State { lists: Vec::new(),
+                                                        toplevels: Vec::new() });
+}
+
+/// The `ext_foreign_toplevel_list_v1` global.
+///
+/// The global is removed when this is dropped.
+pub struct Manager {
+    global: Option<CustomGlobal<ExtForeignToplevelListV1>>
+}
+
+impl Manager {
+    /// Advertise the global.
+    pub fn create(compositor: &mut Compositor) -> Self {
+        let global = compositor.create_global(VERSION, |resource, _| bind(resource));
+        Manager { global: Some(global) }
+    }
+}
+
+impl Drop for Manager {
+    fn drop(&mut self) {
+        STATE.with(|state| {
+                       let mut state = state.borrow_mut();
+                       for list in state.lists.drain(..) {
+                           list.send(ext_foreign_toplevel_list_v1::Event::Finished)
+                       }
+                       for toplevel in &mut state.toplevels {
+                           toplevel.handles.clear()
+                       }
+                   });
+        if let Some(global) = self.global.take() {
+            global.destroy()
+        }
+    }
+}
+
+/// Get the identifier the view is listed with, if it is.
+pub fn identifier(view: &View) -> Option<String> {
+    STATE.with(|state| {
+                   state.borrow()
+                        .toplevels
+                        .iter()
+                        .find(|toplevel| toplevel.view == *view)
+                        .map(|toplevel| toplevel.identifier.clone())
+               })
+}
+
+/// Get the view a client refers to with a handle it got from the list.
+///
+/// Returns `None` once the view isn't listed anymore.
+pub(crate) fn view_of(handle: &Resource<ExtForeignToplevelHandleV1>) -> Option<View> {
+    STATE.with(|state| {
+                   state.borrow()
+                        .toplevels
+                        .iter()
+                        .find(|toplevel| toplevel.handles.iter().any(|other| other.equals(handle)))
+                        .map(|toplevel| toplevel.view.clone())
+               })
+}
+
+fn bind(resource: NewResource<ExtForeignToplevelListV1>) {
+    let list = resource.implement(handle_list_request,
+                                  Some(|list: Resource<ExtForeignToplevelListV1>| {
+                                      forget_list(&list)
+                                  }));
+    STATE.with(|state| {
+                   let mut state = state.borrow_mut();
+                   for toplevel in &mut state.toplevels {
+                       send_toplevel(&list, toplevel)
+                   }
+                   state.lists.push(list);
+               })
+}
+
+fn handle_list_request(request: ext_foreign_toplevel_list_v1::Request,
+                       list: Resource<ExtForeignToplevelListV1>) {
+    match request {
+        ext_foreign_toplevel_list_v1::Request::Stop => {
+            forget_list(&list);
+            list.send(ext_foreign_toplevel_list_v1::Event::Finished)
+        },
+        ext_foreign_toplevel_list_v1::Request::Destroy => {}
+    }
+}
+
+fn forget_list(list: &Resource<ExtForeignToplevelListV1>) {
+    STATE.with(|state| state.borrow_mut().lists.retain(|other| !other.equals(list)))
+}
+
+/// Tell the client of the list about the toplevel.
+fn send_toplevel(list: &Resource<ExtForeignToplevelListV1>, toplevel: &mut Toplevel) {
+    let client = match list.client() {
+        Some(client) => client,
+        None => return
+    };
+    let handle = match client.create_resource::<ExtForeignToplevelHandleV1>(list.version()) {
+        Some(handle) => handle,
+        None => return
+    };
+    let handle = handle.implement(|request, _| match request {
+                                      ext_foreign_toplevel_handle_v1::Request::Destroy => {}
+                                  },
+                                  Some(|handle: Resource<ExtForeignToplevelHandleV1>| {
+                                      forget_handle(&handle)
+                                  }));
+    list.send(ext_foreign_toplevel_list_v1::Event::Toplevel { toplevel: handle.clone() });
+    let identifier = toplevel.identifier.clone();
+    handle.send(ext_foreign_toplevel_handle_v1::Event::Identifier { identifier });
+    send_state(&handle, toplevel.title.clone(), toplevel.app_id.clone());
+    toplevel.handles.push(handle);
+}
+
+fn forget_handle(handle: &Resource<ExtForeignToplevelHandleV1>) {
+    STATE.with(|state| {
+                   for toplevel in &mut state.borrow_mut().toplevels {
+                       toplevel.handles.retain(|other| !other.equals(handle))
+                   }
+               })
+}
+
+/// Send what changed about the toplevel, and that that's all.
+fn send_state(handle: &Resource<ExtForeignToplevelHandleV1>,
+              title: Option<String>,
+              app_id: Option<String>) {
+    if let Some(title) = title {
+        handle.send(ext_foreign_toplevel_handle_v1::Event::Title { title })
+    }
+    if let Some(app_id) = app_id {
+        handle.send(ext_foreign_toplevel_handle_v1::Event::AppId { app_id })
+    }
+    handle.send(ext_foreign_toplevel_handle_v1::Event::Done)
+}
+
+/// List the view that was mapped.
+pub(crate) fn view_mapped(view: View) {
+    STATE.with(|state| {
+                   let mut state = state.borrow_mut();
+                   if state.toplevels.iter().any(|other| other.view == view) {
+                       return
+                   }
+                   let mut toplevel = Toplevel { title: view.title().ok().and_then(|title| title),
+                                                 app_id: view.app_id().ok().and_then(|id| id),
+                                                 view,
+                                                 identifier: utils::random_token(),
+                                                 names: (ptr::null(), ptr::null()),
+                                                 handles: Vec::new() };
+                   let lists = state.lists.clone();
+                   for list in &lists {
+                       send_toplevel(list, &mut toplevel)
+                   }
+                   state.toplevels.push(toplevel);
+               })
+}
+
+/// Tell the clients the title or app id of the view changed, if they did.
+pub(crate) fn view_changed(view: &View) {
+    STATE.with(|state| {
+                   let mut state = state.borrow_mut();
+                   if let Some(toplevel) = state.toplevels
+                                                .iter_mut()
+                                                .find(|toplevel| toplevel.view == *view) {
+                       update(toplevel)
+                   }
+               })
+}
+
+/// Tell the clients the title or app id of the XDG toplevel changed, given
+/// where wlroots stores them.
+///
+/// This version of wlroots doesn't say when they change for XDG toplevels, so
+/// this is called whenever their surface commits. wlroots copies the new
+/// strings before freeing the old ones when they're set, so they can only
+/// have changed if they moved.
+pub(crate) fn xdg_toplevel_committed(view: &View, title: *const c_char, app_id: *const c_char) {
+    STATE.with(|state| {
+                   let mut state = state.borrow_mut();
+                   let toplevel = match state.toplevels
+                                             .iter_mut()
+                                             .find(|toplevel| toplevel.view == *view) {
+                       Some(toplevel) => toplevel,
+                       None => return
+                   };
+                   if toplevel.names == (title, app_id) {
+                       return
+                   }
+                   toplevel.names = (title, app_id);
+                   update(toplevel)
+               })
+}
+
+/// Send the title and app id of the view, if they changed.
+fn update(toplevel: &mut Toplevel) {
+    let title = toplevel.view.title().ok().and_then(|title| title);
+    let app_id = toplevel.view.app_id().ok().and_then(|app_id| app_id);
+    if title == toplevel.title && app_id == toplevel.app_id {
+        return
+    }
+    let changed_title = if title != toplevel.title { title.clone() } else { None };
+    let changed_app_id = if app_id != toplevel.app_id { app_id.clone() } else { None };
+    toplevel.title = title;
+    toplevel.app_id = app_id;
+    for handle in &toplevel.handles {
+        send_state(handle, changed_title.clone(), changed_app_id.clone())
+    }
+}
+
+/// Tell the clients the view isn't there anymore.
+pub(crate) fn view_unmapped(view: &View) {
+    let toplevel = STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let index = state.toplevels.iter().position(|toplevel| toplevel.view == *view)?;
+        Some(state.toplevels.remove(index))
+    });
+    if let Some(toplevel) = toplevel {
+        for handle in toplevel.handles {
+            handle.send(ext_foreign_toplevel_handle_v1::Event::Closed)
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! Support for the fullscreen shell protocol (`zwp_fullscreen_shell_v1`), for
+//! kiosk compositors that show a single client surface on each output.
+//!
+//! The protocol is implemented by this crate (see
+//! [`extensions`](../index.html)). Clients ask for a surface to be
+//! presented and the `Handler` decides what to do with it; drawing the surface
+//! is up to the compositor like with any other surface.
+//!
+//! Mode switches requested with `present_surface_for_mode` aren't supported,
+//! and are always reported as failed to the client.
+
+use std::{cell::RefCell, rc::Rc};
+
+use wayland_sys::server::wl_resource;
+use wlroots_sys::{wayland_server::{NewResource, Resource},
+                  wlr_output_from_resource, wlr_surface_from_resource};
+use wlroots_sys::protocols::fullscreen_shell::server::{
+    zwp_fullscreen_shell_mode_feedback_v1::{self as mode_feedback,
+                                            ZwpFullscreenShellModeFeedbackV1},
+    zwp_fullscreen_shell_v1::{Request, ZwpFullscreenShellV1}};
+pub use wlroots_sys::protocols::fullscreen_shell::server::zwp_fullscreen_shell_v1::PresentMethod;
+
+use {compositor::{self, Compositor, CustomGlobal}, output, surface};
+
+/// The version of the protocol that is implemented.
+const VERSION: u32 = 1;
+
+/// Handles the requests of fullscreen shell clients.
+#[allow(unused_variables)]
+pub trait Handler {
+    /// Called when a client wants the surface shown on the output.
+    ///
+    /// `surface` is `None` when the client wants the output cleared, and
+    /// `output` is `None` when it leaves the choice of output to the
+    /// compositor. `method` is a hint of how the surface should be fit to the
+    /// output if the sizes don't match.
+    fn present_surface(&mut self,
+                       compositor_handle: compositor::Handle,
+                       surface: Option<surface::Handle>,
+                       method: PresentMethod,
+                       output: Option<output::Handle>) {
+    }
+}
+
+/// The fullscreen shell global.
+///
+/// The global is removed when this is dropped.
+pub struct Manager {
+    global: Option<CustomGlobal<ZwpFullscreenShellV1>>
+}
+
+impl Manager {
+    /// Advertise the fullscreen shell, sending the requests of the clients to
+    /// the handler.
+    pub fn create(compositor: &mut Compositor, handler: Box<Handler>) -> Self {
+        let handler = Rc::new(RefCell::new(handler));
+        let global = compositor.create_global(VERSION, move |resource, _version| {
+            bind(resource, handler.clone())
+        });
+        Manager { global: Some(global) }
+    }
+
+    /// Advertise the global like `create`, without boxing the handler
+    /// yourself.
+    pub fn create_with<H: Handler + 'static>(compositor: &mut Compositor, handler: H) -> Self {
+        Manager::create(compositor, Box::new(handler))
+    }
+}
+
+impl Drop for Manager {
+    fn drop(&mut self) {
+        if let Some(global) = self.global.take() {
+            global.destroy()
+        }
+    }
+}
+
+fn bind(resource: NewResource<ZwpFullscreenShellV1>, handler: Rc<RefCell<Box<Handler>>>) {
+    resource.implement(move |request, _shell: Resource<ZwpFullscreenShellV1>| {
+                           match request {
+                               Request::Release => {},
+                               Request::PresentSurface { surface, method, output } => {
+                                   let compositor = match compositor::handle() {
+                                       Some(compositor) => compositor,
+                                       None => return
+                                   };
+                                   let surface = surface.and_then(|surface| unsafe {
+                                       surface_handle(surface.c_ptr())
+                                   });
+                                   let output = output.and_then(|output| unsafe {
+                                       output_handle(output.c_ptr())
+                                   });
+                                   handler.borrow_mut()
+                                          .present_surface(compositor, surface, method, output)
+                               },
+                               Request::PresentSurfaceForMode { feedback, .. } => {
+                                   mode_failed(feedback)
+                               }
+                           }
+                       },
+                       None::<fn(_)>);
+}
+
+fn mode_failed(feedback: NewResource<ZwpFullscreenShellModeFeedbackV1>) {
+    let feedback = feedback.implement(|_, _| {}, None::<fn(_)>);
+    feedback.send(mode_feedback::Event::ModeFailed);
+}
+
+unsafe fn surface_handle(resource: *mut wl_resource) -> Option<surface::Handle> {
+    let surface = wlr_surface_from_resource(resource as *mut _);
+    if surface.is_null() || (*surface).data.is_null() {
+        return None
+    }
+    Some(surface::Handle::from_ptr(surface))
+}
+
+unsafe fn output_handle(resource: *mut wl_resource) -> Option<output::Handle> {
+    let output = wlr_output_from_resource(resource as *mut _);
+    // NOTE Outputs the output manager hasn't built have no handle.
+    if output.is_null() || (*output).data.is_null() {
+        return None
+    }
+    Some(output::Handle::from_ptr(output))
+}
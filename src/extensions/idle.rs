@@ -0,0 +1,425 @@
+//! Support for reporting user inactivity to clients (e.g. screen lockers).
+//!
+//! The input handlers record every bit of user activity in a single tracker,
+//! which is what `last_activity` and `idle_time` report and what the idle
+//! protocols are notified from.
+//!
+//! Two protocols are supported, both fed from that tracker and both following
+//! the same enabled and inhibited state of the seats:
+//!
+//! * The KDE idle protocol (`org_kde_kwin_idle`), provided by wlroots, which
+//!   is the `Manager` created by `compositor::Builder::idle_manager`.
+//! * The newer `ext_idle_notifier_v1` protocol, which is implemented by this
+//!   crate (see [`extensions`](../index.html)) and advertised by `Notifier`.
+
+use std::{cell::{Cell, RefCell}, ptr, time::{Duration, Instant}};
+
+use libc;
+use wayland_sys::server::{wl_display as wl_server_display, wl_event_source,
+                          WAYLAND_SERVER_HANDLE};
+use wlroots_sys::{wayland_server::{NewResource, Resource, protocol::wl_seat::WlSeat},
+                  wl_display, wlr_idle, wlr_idle_create, wlr_idle_destroy,
+                  wlr_idle_notify_activity, wlr_idle_set_enabled, wlr_seat,
+                  wlr_seat_client_from_resource};
+use wlroots_sys::protocols::idle_notify::server::{
+    ext_idle_notification_v1::{self as notification_v1, ExtIdleNotificationV1},
+    ext_idle_notifier_v1::{self as notifier_v1, ExtIdleNotifierV1}};
+
+use {compositor::{self, Compositor, CustomGlobal}, seat::Seat, utils};
+
+/// The version of `ext_idle_notifier_v1` that is implemented.
+const NOTIFIER_VERSION: u32 = 1;
+
+thread_local! {
+    /// When the user last interacted with any input device.
+    static LAST_ACTIVITY: Cell<Option<Instant>> = Cell::new(None);
+    /// The seats that idle timers can be attached to.
+    static SEATS: RefCell<Vec<*mut wlr_seat>> = RefCell::new(Vec::new());
+    /// The seats whose idle timers were disabled with `Manager::set_enabled`.
+    static DISABLED: RefCell<Vec<*mut wlr_seat>> = RefCell::new(Vec::new());
+    /// Whether the idle timers of every seat are inhibited.
+    static INHIBITED: Cell<bool> = Cell::new(false);
+    /// The `ext_idle_notification_v1` objects of the clients.
+    static NOTIFICATIONS: RefCell<Vec<Box<Notification>>> = RefCell::new(Vec::new());
+}
+
+#[derive(Debug)]
+pub struct Manager {
+    idle: *mut wlr_idle
+}
+
+impl Manager {
+    pub(crate) unsafe fn new(display: *mut wl_server_display) -> Option<Self> {
+        let idle = wlr_idle_create(display as *mut wl_display);
+        if !idle.is_null() {
+            Some(Manager { idle })
+        } else {
+            None
+        }
+    }
+
+    /// Enable or disable the idle timers of the seat.
+    ///
+    /// While disabled, clients are never told the user went idle on that seat
+    /// (e.g. while a video is playing). This is kept apart from
+    /// `set_inhibited`, the timers only run when they're enabled and not
+    /// inhibited.
+    pub fn set_enabled(&mut self, seat: &Seat, enabled: bool) {
+        let seat = unsafe { seat.as_ptr() };
+        set_enabled(seat, enabled);
+        unsafe { update(self.idle, seat) }
+    }
+
+    /// Inhibit the idle timers of every seat, e.g. while an application
+    /// inhibits the screen saver.
+    ///
+    /// Seats disabled with `set_enabled` stay disabled once this is lifted.
+    pub fn set_inhibited(&mut self, inhibited: bool) {
+        INHIBITED.with(|flag| flag.set(inhibited));
+        let seats = SEATS.with(|seats| seats.borrow().clone());
+        for seat in seats {
+            unsafe { update(self.idle, seat) }
+        }
+    }
+
+    /// Determines if the idle timers are inhibited.
+    pub fn inhibited(&self) -> bool {
+        inhibited()
+    }
+
+    /// Notify the clients that there was user activity on the seat, without
+    /// it coming from an input device (e.g. when an output is turned on).
+    pub fn notify_activity(&mut self, seat: &Seat) {
+        record_activity();
+        unsafe {
+            wlr_idle_notify_activity(self.idle, seat.as_ptr());
+            resume(Some(seat.as_ptr()))
+        }
+    }
+}
+
+impl Drop for Manager {
+    fn drop(&mut self) {
+        INHIBITED.with(|inhibited| inhibited.set(false));
+        DISABLED.with(|disabled| disabled.borrow_mut().clear());
+        unsafe { wlr_idle_destroy(self.idle) }
+    }
+}
+
+/// A notification the client asked for, which is sent `idled` once its seat
+/// had no activity for the timeout, and `resumed` on the next activity.
+struct Notification {
+    resource: Resource<ExtIdleNotificationV1>,
+    /// Null once the seat is destroyed, after which the notification is
+    /// inert.
+    seat: *mut wlr_seat,
+    /// The timeout in milliseconds.
+    timeout: u32,
+    timer: *mut wl_event_source,
+    idle: bool
+}
+
+/// The `ext_idle_notifier_v1` global.
+///
+/// Its notifications follow `Manager::set_enabled` and
+/// `Manager::set_inhibited`, which it has as well for compositors that don't
+/// advertise the KDE protocol. The global is removed when this is dropped.
+pub struct Notifier {
+    global: Option<CustomGlobal<ExtIdleNotifierV1>>
+}
+
+impl Notifier {
+    /// Advertise the global.
+    pub fn create(compositor: &mut Compositor) -> Self {
+        let global = compositor.create_global(NOTIFIER_VERSION, |resource, _| bind(resource));
+        Notifier { global: Some(global) }
+    }
+
+    /// Enable or disable the idle timers of the seat, the same as
+    /// `Manager::set_enabled`.
+    pub fn set_enabled(&mut self, seat: &Seat, enabled: bool) {
+        let seat = unsafe { seat.as_ptr() };
+        set_enabled(seat, enabled);
+        unsafe { update(kde_idle(), seat) }
+    }
+
+    /// Inhibit the idle timers of every seat, the same as
+    /// `Manager::set_inhibited`.
+    pub fn set_inhibited(&mut self, inhibited: bool) {
+        INHIBITED.with(|flag| flag.set(inhibited));
+        let seats = SEATS.with(|seats| seats.borrow().clone());
+        for seat in seats {
+            unsafe { update(kde_idle(), seat) }
+        }
+    }
+
+    /// Determines if the idle timers are inhibited.
+    pub fn inhibited(&self) -> bool {
+        inhibited()
+    }
+}
+
+impl Drop for Notifier {
+    fn drop(&mut self) {
+        if let Some(global) = self.global.take() {
+            global.destroy()
+        }
+        // NOTE The timers can't outlive the event loop, so the notifications
+        // that are left do nothing anymore.
+        NOTIFICATIONS.with(|notifications| {
+                               for notification in notifications.borrow_mut().iter_mut() {
+                                   unsafe { notification.stop() }
+                               }
+                           })
+    }
+}
+
+impl Notification {
+    /// Tell the client if the user went idle or resumed, unless it already
+    /// knows.
+    fn set_idle(&mut self, idle: bool) {
+        if self.idle == idle {
+            return
+        }
+        self.idle = idle;
+        if self.resource.is_alive() {
+            self.resource.send(if idle {
+                                   notification_v1::Event::Idled
+                               } else {
+                                   notification_v1::Event::Resumed
+                               });
+        }
+    }
+
+    /// Restart the timer from the timeout, or stop it while the timers of the
+    /// seat don't run.
+    ///
+    /// The notification stays idle until there's activity again.
+    unsafe fn reset_timer(&mut self) {
+        if self.seat.is_null() {
+            return
+        }
+        let running = enabled(self.seat);
+        if self.timer.is_null() {
+            // NOTE Only a timeout of zero has no timer, it's idle right away.
+            if running {
+                self.set_idle(true)
+            }
+            return
+        }
+        let timeout = if running {
+            self.timeout.min(libc::c_int::max_value() as u32) as libc::c_int
+        } else {
+            0
+        };
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_event_source_timer_update, self.timer, timeout);
+    }
+
+    /// Make the notification inert.
+    unsafe fn stop(&mut self) {
+        self.seat = ptr::null_mut();
+        if !self.timer.is_null() {
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_event_source_remove, self.timer);
+            self.timer = ptr::null_mut();
+        }
+    }
+}
+
+impl Drop for Notification {
+    fn drop(&mut self) {
+        unsafe { self.stop() }
+    }
+}
+
+fn bind(resource: NewResource<ExtIdleNotifierV1>) {
+    resource.implement(|request, _| match request {
+                           notifier_v1::Request::GetIdleNotification { id, timeout, seat } => {
+                               get_notification(id, timeout, seat)
+                           },
+                           _ => {}
+                       },
+                       None::<fn(_)>);
+}
+
+fn get_notification(id: NewResource<ExtIdleNotificationV1>,
+                    timeout: u32,
+                    seat: Resource<WlSeat>) {
+    let resource = id.implement(|request, _| match request {
+                                    notification_v1::Request::Destroy => {}
+                                },
+                                Some(|resource: Resource<ExtIdleNotificationV1>| {
+                                    forget(&resource)
+                                }));
+    unsafe {
+        let seat_client = wlr_seat_client_from_resource(seat.c_ptr() as _);
+        // NOTE The seat may already be gone, the notification is inert then.
+        let seat = if seat_client.is_null() {
+            ptr::null_mut()
+        } else {
+            (*seat_client).seat
+        };
+        let mut notification = Box::new(Notification { resource,
+                                                       seat,
+                                                       timeout,
+                                                       timer: ptr::null_mut(),
+                                                       idle: false });
+        if !seat.is_null() && timeout > 0 && !compositor::COMPOSITOR_PTR.is_null() {
+            let event_loop = (*compositor::COMPOSITOR_PTR).event_loop;
+            notification.timer = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                               wl_event_loop_add_timer,
+                                               event_loop,
+                                               idle_timer_notify,
+                                               &mut *notification as *mut Notification as
+                                               *mut libc::c_void);
+        }
+        notification.reset_timer();
+        NOTIFICATIONS.with(|notifications| notifications.borrow_mut().push(notification));
+    }
+}
+
+/// Forget the notification once its resource is destroyed.
+fn forget(resource: &Resource<ExtIdleNotificationV1>) {
+    let removed = NOTIFICATIONS.with(|notifications| {
+        let mut notifications = notifications.borrow_mut();
+        let index = notifications.iter()
+                                 .position(|notification| {
+                                               notification.resource.c_ptr() == resource.c_ptr()
+                                           })?;
+        Some(notifications.remove(index))
+    });
+    drop(removed);
+}
+
+unsafe extern "C" fn idle_timer_notify(data: *mut libc::c_void) -> libc::c_int {
+    let timed_out = data as *const Notification;
+    NOTIFICATIONS.with(|notifications| {
+        let mut notifications = notifications.borrow_mut();
+        if let Some(notification) = notifications.iter_mut()
+                                                 .find(|notification| {
+                                                           &***notification as *const _ ==
+                                                           timed_out
+                                                       }) {
+            notification.set_idle(true)
+        }
+    });
+    0
+}
+
+/// Tell the notifications (of the seat, if there is one) that the user is
+/// active, and restart their timers.
+unsafe fn resume(seat: Option<*mut wlr_seat>) {
+    NOTIFICATIONS.with(|notifications| {
+        for notification in notifications.borrow_mut()
+                                         .iter_mut()
+                                         .filter(|notification| !notification.seat.is_null())
+                                         .filter(|notification| {
+                                                     seat.map_or(true, |seat| {
+                                                         notification.seat == seat
+                                                     })
+                                                 }) {
+            notification.set_idle(false);
+            notification.reset_timer();
+        }
+    })
+}
+
+/// Disable or enable the timers of the seat.
+fn set_enabled(seat: *mut wlr_seat, enabled: bool) {
+    DISABLED.with(|disabled| {
+                      let mut disabled = disabled.borrow_mut();
+                      disabled.retain(|other| *other != seat);
+                      if !enabled {
+                          disabled.push(seat);
+                      }
+                  });
+}
+
+fn inhibited() -> bool {
+    INHIBITED.with(|inhibited| inhibited.get())
+}
+
+/// Determine if the timers of the seat run, i.e. they're neither disabled
+/// nor inhibited.
+fn enabled(seat: *mut wlr_seat) -> bool {
+    !DISABLED.with(|disabled| disabled.borrow().contains(&seat)) && !inhibited()
+}
+
+/// Get the KDE idle manager, or null if it isn't advertised.
+unsafe fn kde_idle() -> *mut wlr_idle {
+    if compositor::COMPOSITOR_PTR.is_null() {
+        return ptr::null_mut()
+    }
+    match (*compositor::COMPOSITOR_PTR).idle_manager {
+        Some(ref manager) => manager.idle,
+        None => ptr::null_mut()
+    }
+}
+
+/// Run the timers of the seat of both protocols unless they're disabled or
+/// inhibited.
+unsafe fn update(idle: *mut wlr_idle, seat: *mut wlr_seat) {
+    if !idle.is_null() {
+        wlr_idle_set_enabled(idle, seat, enabled(seat))
+    }
+    NOTIFICATIONS.with(|notifications| {
+                           for notification in notifications.borrow_mut()
+                                                            .iter_mut()
+                                                            .filter(|notification| {
+                                                                        notification.seat == seat
+                                                                    }) {
+                               notification.reset_timer()
+                           }
+                       })
+}
+
+/// Get when the user last interacted with an input device.
+///
+/// Returns `None` if there has not been any activity yet.
+pub fn last_activity() -> Option<Instant> {
+    LAST_ACTIVITY.with(|last_activity| last_activity.get())
+}
+
+/// Get how long the user has been idle for.
+///
+/// Returns `None` if there has not been any activity yet.
+pub fn idle_time() -> Option<Duration> {
+    last_activity().map(|last_activity| utils::now().duration_since(last_activity))
+}
+
+fn record_activity() {
+    LAST_ACTIVITY.with(|last_activity| last_activity.set(Some(utils::now())));
+}
+
+/// Record user activity from an input device, resetting the idle timers of
+/// every seat.
+pub(crate) unsafe fn notify_activity() {
+    record_activity();
+    let idle = kde_idle();
+    if !idle.is_null() {
+        SEATS.with(|seats| {
+                       for seat in seats.borrow().iter() {
+                           wlr_idle_notify_activity(idle, *seat)
+                       }
+                   })
+    }
+    resume(None)
+}
+
+pub(crate) fn add_seat(seat: *mut wlr_seat) {
+    SEATS.with(|seats| seats.borrow_mut().push(seat));
+    unsafe { update(kde_idle(), seat) }
+}
+
+pub(crate) fn remove_seat(seat: *mut wlr_seat) {
+    SEATS.with(|seats| seats.borrow_mut().retain(|other| *other != seat));
+    DISABLED.with(|disabled| disabled.borrow_mut().retain(|other| *other != seat));
+    NOTIFICATIONS.with(|notifications| {
+                           for notification in notifications.borrow_mut()
+                                                            .iter_mut()
+                                                            .filter(|notification| {
+                                                                        notification.seat == seat
+                                                                    }) {
+                               unsafe { notification.stop() }
+                           }
+                       })
+}
@@ -0,0 +1,172 @@
+//! Support for the image capture source protocol
+//! (`ext_image_capture_source_v1`), with which screenshot and screencast
+//! tools say what they want to capture: a whole output, or a single window
+//! they found with the foreign toplevel list
+//! (see `extensions::foreign_toplevel_list`).
+//!
+//! The protocol only names what is captured, the capturing itself is done by
+//! the compositor once a client asks for it (the protocol itself is handled
+//! by this crate, see [`extensions`](../index.html)), e.g. with:
+//!
+//! ```rust,no_run,ignore
+//! if let Some(source) = image_capture_source::lookup(&source_resource) {
+//!     let pixels = source.capture(renderer);
+//! }
+//! ```
+
+use std::cell::RefCell;
+
+use wlroots_sys::{wayland_server::{NewResource, Resource}, wlr_output_from_resource};
+use wlroots_sys::protocols::image_capture_source::server as protocol;
+use self::protocol::ext_foreign_toplevel_image_capture_source_manager_v1::{
+    self as toplevel_manager_v1, ExtForeignToplevelImageCaptureSourceManagerV1};
+use self::protocol::ext_image_capture_source_v1::{self as source_v1, ExtImageCaptureSourceV1};
+use self::protocol::ext_output_image_capture_source_manager_v1::{
+    self as output_manager_v1, ExtOutputImageCaptureSourceManagerV1};
+
+use {compositor::{Compositor, CustomGlobal},
+     extensions::foreign_toplevel_list,
+     output,
+     render::{GenericRenderer, Pixels},
+     view::View};
+
+/// The version of the protocol that is implemented.
+const VERSION: u32 = 1;
+
+/// What a client wants to capture.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CaptureSource {
+    /// Everything that is shown on the output.
+    Output(output::Handle),
+    /// A single window, wherever it is and even when it's covered.
+    Toplevel(View)
+}
+
+impl CaptureSource {
+    /// Read back what's shown of the window.
+    ///
+    /// Returns `None` for outputs, whose frames are only there while they're
+    /// rendered: capture them with a `screencast::Stream` instead. Also
+    /// returns `None` once the window is gone, or when it has nothing to
+    /// show.
+    pub fn capture(&self, renderer: &mut GenericRenderer) -> Option<Pixels> {
+        match *self {
+            CaptureSource::Output(_) => None,
+            CaptureSource::Toplevel(ref view) => {
+                let surface = view.surface().ok()??;
+                surface.run(|surface| surface.capture_pixels(renderer)).ok()?
+            }
+        }
+    }
+
+    /// Determine if there's still something to capture.
+    pub fn is_alive(&self) -> bool {
+        match *self {
+            CaptureSource::Output(ref output) => output.run(|_| ()).is_ok(),
+            CaptureSource::Toplevel(ref view) => view.is_alive()
+        }
+    }
+}
+
+thread_local! {
+    static SOURCES: RefCell<Vec<(Resource<ExtImageCaptureSourceV1>, CaptureSource)>> =
+        RefCell::new(Vec::new());
+}
+
+/// The globals that create capture sources for outputs and windows.
+///
+/// The globals are removed when this is dropped.
+pub struct Manager {
+    output_global: Option<CustomGlobal<ExtOutputImageCaptureSourceManagerV1>>,
+    toplevel_global: Option<CustomGlobal<ExtForeignToplevelImageCaptureSourceManagerV1>>
+}
+
+impl Manager {
+    /// Advertise the globals.
+    ///
+    /// Windows can only be captured once the foreign toplevel list is
+    /// advertised too, as that's where clients find them.
+    pub fn create(compositor: &mut Compositor) -> Self {
+        let output_global = compositor.create_global(VERSION, |resource, _| bind_output(resource));
+        let toplevel_global =
+            compositor.create_global(VERSION, |resource, _| bind_toplevel(resource));
+        Manager { output_global: Some(output_global),
+                  toplevel_global: Some(toplevel_global) }
+    }
+}
+
+impl Drop for Manager {
+    fn drop(&mut self) {
+        if let Some(global) = self.output_global.take() {
+            global.destroy()
+        }
+        if let Some(global) = self.toplevel_global.take() {
+            global.destroy()
+        }
+    }
+}
+
+/// Get what the capture source a client made refers to.
+///
+/// Returns `None` if the source wasn't made by this manager, or if what it
+/// referred to is gone.
+pub fn lookup(source: &Resource<ExtImageCaptureSourceV1>) -> Option<CaptureSource> {
+    SOURCES.with(|sources| {
+                     sources.borrow()
+                            .iter()
+                            .find(|&&(ref other, _)| other.equals(source))
+                            .map(|&(_, ref capture_source)| capture_source.clone())
+                            .filter(|capture_source| capture_source.is_alive())
+                 })
+}
+
+fn bind_output(resource: NewResource<ExtOutputImageCaptureSourceManagerV1>) {
+    resource.implement(|request, _| match request {
+                           output_manager_v1::Request::CreateSource { source, output } => {
+                               let output = unsafe {
+                                   let output = wlr_output_from_resource(output.c_ptr() as _);
+                                   // NOTE Outputs the output manager hasn't built have no handle.
+                                   if output.is_null() || (*output).data.is_null() {
+                                       None
+                                   } else {
+                                       Some(output::Handle::from_ptr(output))
+                                   }
+                               };
+                               create_source(source, output.map(CaptureSource::Output))
+                           },
+                           output_manager_v1::Request::Destroy => {}
+                       },
+                       None::<fn(_)>);
+}
+
+fn bind_toplevel(resource: NewResource<ExtForeignToplevelImageCaptureSourceManagerV1>) {
+    resource.implement(|request, _| match request {
+                           toplevel_manager_v1::Request::CreateSource { source,
+                                                                        toplevel_handle } => {
+                               let view = foreign_toplevel_list::view_of(&toplevel_handle);
+                               create_source(source, view.map(CaptureSource::Toplevel))
+                           },
+                           toplevel_manager_v1::Request::Destroy => {}
+                       },
+                       None::<fn(_)>);
+}
+
+/// Make the source, remembering what it refers to.
+///
+/// The protocol has no way to fail, so sources for outputs and windows that
+/// are already gone are made anyway, and can't be captured.
+fn create_source(source: NewResource<ExtImageCaptureSourceV1>,
+                 capture_source: Option<CaptureSource>) {
+    let source = source.implement(|request, _| match request {
+                                      source_v1::Request::Destroy => {}
+                                  },
+                                  Some(|source: Resource<ExtImageCaptureSourceV1>| {
+                                      SOURCES.with(|sources| {
+                                          sources.borrow_mut()
+                                                 .retain(|&(ref other, _)| !other.equals(&source))
+                                      })
+                                  }));
+    if let Some(capture_source) = capture_source {
+        SOURCES.with(|sources| sources.borrow_mut().push((source, capture_source)))
+    }
+}
@@ -0,0 +1,111 @@
+//! Support for the wlroots input inhibitor protocol
+//! (`zwlr_input_inhibit_manager_v1`), which lets a client (e.g. a screen
+//! locker) take all of the input for itself.
+//!
+//! While a client inhibits the input, the seats refuse to give pointer,
+//! keyboard or touch focus to the surfaces of any other client, and the
+//! focus is taken away from them as soon as the client asks. Lockers usually
+//! show their prompt with an exclusive layer surface on top of that (see
+//! `extensions::layer_shell`), which gets the keyboard.
+
+use std::{cell::Cell, ptr};
+
+use libc;
+use wayland_sys::server::{signal::wl_signal_add, wl_client, WAYLAND_SERVER_HANDLE};
+use wlroots_sys::{wl_display, wlr_input_inhibit_manager, wlr_input_inhibit_manager_create,
+                  wlr_input_inhibit_manager_destroy, wlr_seat_keyboard_clear_focus,
+                  wlr_seat_pointer_clear_focus, wlr_surface};
+
+use {compositor::{Client, Compositor}, error::Error, seat, surface};
+#[cfg(feature = "shortcuts_inhibit")]
+use extensions::shortcuts_inhibit;
+
+thread_local! {
+    /// The manager of the compositor, if it made one.
+    static MANAGER: Cell<*mut wlr_input_inhibit_manager> = Cell::new(ptr::null_mut());
+}
+
+wayland_listener!(pub(crate) InputInhibitor, (), [
+    activate_listener => activate_notify: |_this: &mut InputInhibitor, _data: *mut libc::c_void,|
+    unsafe {
+        for seat in seat::seat_ptrs() {
+            let keyboard_focus = (*seat).keyboard_state.focused_surface;
+            if !keyboard_focus.is_null() && blocks_input(keyboard_focus) {
+                wlr_seat_keyboard_clear_focus(seat);
+                #[cfg(feature = "shortcuts_inhibit")]
+                shortcuts_inhibit::focus_changed(seat);
+            }
+            let pointer_focus = (*seat).pointer_state.focused_surface;
+            if !pointer_focus.is_null() && blocks_input(pointer_focus) {
+                wlr_seat_pointer_clear_focus(seat);
+            }
+        }
+    };
+]);
+
+/// The `zwlr_input_inhibit_manager_v1` global.
+///
+/// The global is removed when this is dropped.
+pub struct Manager {
+    manager: *mut wlr_input_inhibit_manager,
+    listener: Box<InputInhibitor>
+}
+
+impl Manager {
+    /// Advertise the global.
+    ///
+    /// Returns `Error::Protocol` if wlroots couldn't make it.
+    pub fn create(compositor: &mut Compositor) -> Result<Self, Error> {
+        unsafe {
+            let manager = wlr_input_inhibit_manager_create(compositor.display as *mut wl_display);
+            if manager.is_null() {
+                return Err(Error::Protocol("zwlr_input_inhibit_manager_v1"))
+            }
+            let mut listener = InputInhibitor::new(());
+            wl_signal_add(&mut (*manager).events.activate as *mut _ as _,
+                          listener.activate_listener() as *mut _ as _);
+            MANAGER.with(|current| current.set(manager));
+            Ok(Manager { manager, listener })
+        }
+    }
+
+    /// Get the client that has all of the input, if one does.
+    pub fn active_client(&self) -> Option<Client> {
+        unsafe {
+            let client = (*self.manager).active_client;
+            if client.is_null() {
+                None
+            } else {
+                Some(Client::from_ptr(client as *mut wl_client))
+            }
+        }
+    }
+}
+
+impl Drop for Manager {
+    fn drop(&mut self) {
+        unsafe {
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_list_remove,
+                          &mut (*self.listener.activate_listener()).link as *mut _ as _);
+            MANAGER.with(|current| {
+                             if current.get() == self.manager {
+                                 current.set(ptr::null_mut())
+                             }
+                         });
+            wlr_input_inhibit_manager_destroy(self.manager)
+        }
+    }
+}
+
+/// Determine if a client has all of the input and the surface isn't one of
+/// its own.
+pub(crate) fn blocks_input(surface: *mut wlr_surface) -> bool {
+    let manager = MANAGER.with(|manager| manager.get());
+    unsafe {
+        if manager.is_null() || (*manager).active_client.is_null() {
+            return false
+        }
+        surface::surface_client(surface) != (*manager).active_client as *mut wl_client
+    }
+}
@@ -0,0 +1,580 @@
+//! Support for the wlroots layer shell protocol (`zwlr_layer_shell_v1`), for
+//! the surfaces that make up the desktop itself: backgrounds, panels,
+//! notifications, launchers and lock screens.
+//!
+//! wlroots only keeps the state of the layer surfaces, placing them is up to
+//! the compositor. The `Handler` is told about every new layer surface, which
+//! has to be configured with a size before its client draws anything.
+//!
+//! How a layer surface gets the keyboard depends on its
+//! `KeyboardInteractivity`:
+//!
+//! * `None` surfaces never get it from the crate,
+//! * `Exclusive` surfaces get it as soon as they are mapped, and keep it
+//!   while they are: the seats refuse to give the keyboard to the surfaces of
+//!   any other client,
+//! * `OnDemand` surfaces get it like any other surface, whenever the
+//!   compositor focuses them (e.g. on a click).
+//!
+//! When a layer surface with the keyboard focus is unmapped, the seat gets
+//! back the focus it had before the layer surface took it, e.g. the view
+//! that was focused before a launcher was opened.
+
+use std::{cell::RefCell, ptr, rc::{Rc, Weak}};
+
+use libc;
+use wayland_sys::server::{signal::wl_signal_add, WAYLAND_SERVER_HANDLE};
+use wlroots_sys::{wl_display, wlr_layer_shell, wlr_layer_shell_create, wlr_layer_shell_destroy,
+                  wlr_layer_surface, wlr_layer_surface_close, wlr_layer_surface_configure,
+                  wlr_seat, wlr_seat_keyboard_clear_focus, wlr_surface,
+                  wlr_surface_is_layer_surface, wlr_surface_surface_at, wlr_xdg_popup,
+                  wlr_xdg_surface_surface_at, zwlr_layer_shell_v1_layer};
+
+use {compositor::{self, Compositor},
+     error::Error,
+     output, seat, surface,
+     utils::{c_to_rust_string, Edges, HandleErr, HandleResult}};
+#[cfg(feature = "shortcuts_inhibit")]
+use extensions::shortcuts_inhibit;
+
+/// The layers of the desktop, from the bottom up.
+///
+/// Views are shown between `Bottom` and `Top`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Layer {
+    Background,
+    Bottom,
+    Top,
+    Overlay
+}
+
+/// How a layer surface wants to get keyboard input.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum KeyboardInteractivity {
+    /// It doesn't want the keyboard.
+    None,
+    /// It wants all of the keyboard input while it's mapped, e.g. a lock
+    /// screen or a launcher.
+    Exclusive,
+    /// It wants the keyboard when the user focuses it.
+    OnDemand
+}
+
+/// Handles the layer surfaces of the clients.
+#[allow(unused_variables)]
+pub trait Handler {
+    /// Called when a client committed a new layer surface for the first time.
+    ///
+    /// This is where it's configured with a size to draw itself at, and is
+    /// given an output if the client left that to the compositor.
+    fn new_surface(&mut self, compositor_handle: compositor::Handle, layer_surface: LayerSurface) {
+    }
+
+    /// Called when the layer surface is mapped, after it was given the
+    /// keyboard if it takes it exclusively.
+    fn surface_mapped(&mut self,
+                      compositor_handle: compositor::Handle,
+                      layer_surface: LayerSurface) {
+    }
+
+    /// Called when the layer surface is unmapped, before the keyboard focus
+    /// is given back.
+    fn surface_unmapped(&mut self,
+                        compositor_handle: compositor::Handle,
+                        layer_surface: LayerSurface) {
+    }
+
+    /// Called right before the layer surface is destroyed.
+    fn surface_destroyed(&mut self,
+                         compositor_handle: compositor::Handle,
+                         layer_surface: LayerSurface) {
+    }
+}
+
+/// A handle to a layer surface of a client.
+///
+/// Once the layer surface is destroyed the handle is no longer valid, and
+/// all operations on it return `HandleErr::AlreadyDropped`.
+#[derive(Debug, Clone)]
+pub struct LayerSurface {
+    layer_surface: *mut wlr_layer_surface,
+    handle: Weak<()>
+}
+
+struct Entry {
+    layer_surface: *mut wlr_layer_surface,
+    handle: Rc<()>,
+    listener: Box<LayerSurfaceListener>
+}
+
+struct State {
+    /// Kept apart so the handler can be called without borrowing the rest.
+    handler: Option<Rc<RefCell<Box<Handler>>>>,
+    /// The layer surfaces, in the order they were last mapped.
+    surfaces: Vec<Entry>,
+    /// The surface each seat had the keyboard focus on before a layer
+    /// surface took it.
+    previous_focus: Vec<(*mut wlr_seat, surface::Handle)>
+}
+
+thread_local! {
+    static STATE: RefCell<State> = RefCell::new(State { handler: None,
+                                                        surfaces: Vec::new(),
+                                                        previous_focus: Vec::new() });
+}
+
+wayland_listener!(pub(crate) LayerShell, (), [
+    new_surface_listener => new_surface_notify: |_this: &mut LayerShell, data: *mut libc::c_void,|
+    unsafe {
+        let layer_surface = data as *mut wlr_layer_surface;
+        let mut listener = LayerSurfaceListener::new(layer_surface);
+        wl_signal_add(&mut (*layer_surface).events.map as *mut _ as _,
+                      listener.map_listener() as *mut _ as _);
+        wl_signal_add(&mut (*layer_surface).events.unmap as *mut _ as _,
+                      listener.unmap_listener() as *mut _ as _);
+        wl_signal_add(&mut (*layer_surface).events.destroy as *mut _ as _,
+                      listener.destroy_listener() as *mut _ as _);
+        let handle = Rc::new(());
+        let layer_surface_handle = LayerSurface { layer_surface,
+                                                  handle: Rc::downgrade(&handle) };
+        STATE.with(|state| {
+                       state.borrow_mut().surfaces.push(Entry { layer_surface,
+                                                                handle,
+                                                                listener })
+                   });
+        notify(|handler, compositor| handler.new_surface(compositor, layer_surface_handle));
+    };
+]);
+
+wayland_listener!(pub(crate) LayerSurfaceListener, *mut wlr_layer_surface, [
+    map_listener => map_notify: |this: &mut LayerSurfaceListener, _data: *mut libc::c_void,|
+    unsafe {
+        let layer_surface = this.data;
+        STATE.with(|state| {
+                       let mut state = state.borrow_mut();
+                       let index = state.surfaces
+                                        .iter()
+                                        .position(|entry| entry.layer_surface == layer_surface);
+                       if let Some(index) = index {
+                           let entry = state.surfaces.remove(index);
+                           state.surfaces.push(entry);
+                       }
+                   });
+        if interactivity(layer_surface) == KeyboardInteractivity::Exclusive {
+            take_keyboard(layer_surface);
+        }
+        if let Some(layer_surface) = handle(layer_surface) {
+            notify(|handler, compositor| handler.surface_mapped(compositor, layer_surface));
+        }
+    };
+    unmap_listener => unmap_notify: |this: &mut LayerSurfaceListener, _data: *mut libc::c_void,|
+    unsafe {
+        let layer_surface = this.data;
+        if let Some(layer_surface) = handle(layer_surface) {
+            notify(|handler, compositor| handler.surface_unmapped(compositor, layer_surface));
+        }
+        give_keyboard_back(layer_surface);
+    };
+    destroy_listener => destroy_notify: |this: &mut LayerSurfaceListener,
+                                         _data: *mut libc::c_void,|
+    unsafe {
+        let layer_surface = this.data;
+        if let Some(layer_surface) = handle(layer_surface) {
+            notify(|handler, compositor| handler.surface_destroyed(compositor, layer_surface));
+        }
+        let entry = STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            let index = state.surfaces
+                             .iter()
+                             .position(|entry| entry.layer_surface == layer_surface)?;
+            Some(state.surfaces.remove(index))
+        });
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_list_remove,
+                      &mut (*this.map_listener()).link as *mut _ as _);
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_list_remove,
+                      &mut (*this.unmap_listener()).link as *mut _ as _);
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_list_remove,
+                      &mut (*this.destroy_listener()).link as *mut _ as _);
+        // NOTE This frees the listener that's running, so nothing comes after.
+        drop(entry);
+    };
+]);
+
+/// The `zwlr_layer_shell_v1` global.
+///
+/// The global is removed when this is dropped, destroying all of the layer
+/// surfaces.
+pub struct Manager {
+    shell: *mut wlr_layer_shell,
+    listener: Box<LayerShell>
+}
+
+impl Manager {
+    /// Advertise the global, telling the handler about the layer surfaces.
+    ///
+    /// Returns `Error::Protocol` if wlroots couldn't make it.
+    pub fn create(compositor: &mut Compositor, handler: Box<Handler>) -> Result<Self, Error> {
+        unsafe { Manager::new(compositor.display as *mut wl_display, handler) }
+    }
+
+    /// Advertise the global like `create`, without boxing the handler
+    /// yourself.
+    pub fn create_with<H: Handler + 'static>(compositor: &mut Compositor,
+                                             handler: H)
+                                             -> Result<Self, Error> {
+        Manager::create(compositor, Box::new(handler))
+    }
+
+    pub(crate) unsafe fn new(display: *mut wl_display,
+                             handler: Box<Handler>)
+                             -> Result<Self, Error> {
+        let shell = wlr_layer_shell_create(display);
+        if shell.is_null() {
+            return Err(Error::Protocol("zwlr_layer_shell_v1"))
+        }
+        let mut listener = LayerShell::new(());
+        wl_signal_add(&mut (*shell).events.new_surface as *mut _ as _,
+                      listener.new_surface_listener() as *mut _ as _);
+        STATE.with(|state| state.borrow_mut().handler = Some(Rc::new(RefCell::new(handler))));
+        Ok(Manager { shell, listener })
+    }
+
+    /// Get the layer surfaces, in the order they were last mapped.
+    pub fn surfaces(&self) -> Vec<LayerSurface> {
+        STATE.with(|state| {
+                       state.borrow()
+                            .surfaces
+                            .iter()
+                            .map(|entry| LayerSurface { layer_surface: entry.layer_surface,
+                                                        handle: Rc::downgrade(&entry.handle) })
+                            .collect()
+                   })
+    }
+}
+
+impl Drop for Manager {
+    fn drop(&mut self) {
+        unsafe {
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_list_remove,
+                          &mut (*self.listener.new_surface_listener()).link as *mut _ as _);
+            wlr_layer_shell_destroy(self.shell)
+        }
+        STATE.with(|state| state.borrow_mut().handler = None);
+    }
+}
+
+impl LayerSurface {
+    /// Determines if the layer surface is still around.
+    pub fn is_alive(&self) -> bool {
+        self.handle.upgrade().is_some()
+    }
+
+    fn ptr(&self) -> HandleResult<*mut wlr_layer_surface> {
+        if self.is_alive() {
+            Ok(self.layer_surface)
+        } else {
+            Err(HandleErr::AlreadyDropped)
+        }
+    }
+
+    /// Get the surface the layer surface shows.
+    pub fn surface(&self) -> HandleResult<surface::Handle> {
+        unsafe { Ok(surface::Handle::from_ptr((*self.ptr()?).surface)) }
+    }
+
+    /// Find the surface of the popups and subsurfaces of the layer surface
+    /// that takes input at the surface-local coordinates, the topmost first.
+    ///
+    /// Returns the surface with the coordinates relative to it, or `None` if
+    /// none accepts input there.
+    pub fn surface_at(&self,
+                      sx: f64,
+                      sy: f64)
+                      -> HandleResult<Option<(surface::Handle, f64, f64)>> {
+        let layer_surface = self.ptr()?;
+        let (mut sub_x, mut sub_y) = (0.0, 0.0);
+        unsafe {
+            let mut found = ptr::null_mut();
+            wl_list_for_each!((*layer_surface).popups, link, (popup: wlr_xdg_popup) => {
+                if found.is_null() {
+                    // NOTE The popup is placed by its window geometry.
+                    let (position, geometry) = ((*popup).geometry, (*(*popup).base).geometry);
+                    let popup_x = sx - (position.x - geometry.x) as f64;
+                    let popup_y = sy - (position.y - geometry.y) as f64;
+                    found = wlr_xdg_surface_surface_at((*popup).base,
+                                                       popup_x,
+                                                       popup_y,
+                                                       &mut sub_x,
+                                                       &mut sub_y);
+                }
+            });
+            if found.is_null() {
+                found = wlr_surface_surface_at((*layer_surface).surface,
+                                               sx,
+                                               sy,
+                                               &mut sub_x,
+                                               &mut sub_y);
+            }
+            if found.is_null() {
+                Ok(None)
+            } else {
+                Ok(Some((surface::Handle::from_ptr(found), sub_x, sub_y)))
+            }
+        }
+    }
+
+    /// Get the output the layer surface is shown on, if it has one yet.
+    pub fn output(&self) -> HandleResult<Option<output::Handle>> {
+        unsafe {
+            let output = (*self.ptr()?).output;
+            if output.is_null() {
+                Ok(None)
+            } else {
+                Ok(Some(output::Handle::from_ptr(output)))
+            }
+        }
+    }
+
+    /// Show the layer surface on the output, for clients that leave the
+    /// choice to the compositor.
+    ///
+    /// This should only be done in `Handler::new_surface`.
+    pub fn set_output(&self, output: &output::Handle) -> HandleResult<()> {
+        let layer_surface = self.ptr()?;
+        unsafe { (*layer_surface).output = output.as_ptr() }
+        Ok(())
+    }
+
+    /// Get what the client says the layer surface is for, e.g. "panel".
+    pub fn namespace(&self) -> HandleResult<Option<String>> {
+        unsafe { Ok(c_to_rust_string((*self.ptr()?).namespace)) }
+    }
+
+    pub fn layer(&self) -> HandleResult<Layer> {
+        unsafe { Ok(layer((*self.ptr()?).layer)) }
+    }
+
+    pub fn keyboard_interactivity(&self) -> HandleResult<KeyboardInteractivity> {
+        unsafe { Ok(interactivity(self.ptr()?)) }
+    }
+
+    /// Get the edges of the output the layer surface is attached to.
+    pub fn anchor(&self) -> HandleResult<Edges> {
+        unsafe { Ok(Edges::from_bits_truncate((*self.ptr()?).current.anchor)) }
+    }
+
+    /// Get how much of the output the layer surface wants to keep other
+    /// surfaces from covering, from the edge it's attached to.
+    ///
+    /// It's -1 if it wants to cover the space other layer surfaces keep
+    /// free too.
+    pub fn exclusive_zone(&self) -> HandleResult<i32> {
+        unsafe { Ok((*self.ptr()?).current.exclusive_zone) }
+    }
+
+    /// Get the margin the layer surface wants to the edges it's attached to,
+    /// as `(top, right, bottom, left)`.
+    pub fn margin(&self) -> HandleResult<(u32, u32, u32, u32)> {
+        let margin = unsafe { (*self.ptr()?).current.margin };
+        Ok((margin.top, margin.right, margin.bottom, margin.left))
+    }
+
+    /// Get the size the client wants the layer surface to have.
+    ///
+    /// A size of 0 means it should be as large as the output, between the
+    /// edges it's attached to.
+    pub fn desired_size(&self) -> HandleResult<(u32, u32)> {
+        let current = unsafe { (*self.ptr()?).current };
+        Ok((current.desired_width, current.desired_height))
+    }
+
+    pub fn mapped(&self) -> HandleResult<bool> {
+        unsafe { Ok((*self.ptr()?).mapped) }
+    }
+
+    /// Tell the client the size to draw the layer surface at.
+    pub fn configure(&self, width: u32, height: u32) -> HandleResult<()> {
+        unsafe { wlr_layer_surface_configure(self.ptr()?, width, height) }
+        Ok(())
+    }
+
+    /// Ask the client to destroy the layer surface, e.g. because its output
+    /// went away.
+    pub fn close(&self) -> HandleResult<()> {
+        unsafe { wlr_layer_surface_close(self.ptr()?) }
+        Ok(())
+    }
+}
+
+impl PartialEq for LayerSurface {
+    fn eq(&self, other: &LayerSurface) -> bool {
+        self.layer_surface == other.layer_surface
+    }
+}
+
+impl Eq for LayerSurface {}
+
+/// Determine if a layer surface of another client than the surface's takes
+/// the keyboard exclusively.
+pub(crate) fn blocks_keyboard(surface: *mut wlr_surface) -> bool {
+    unsafe {
+        match exclusive(None) {
+            Some(layer_surface) => {
+                surface::surface_client(surface) !=
+                surface::surface_client((*layer_surface).surface)
+            },
+            None => false
+        }
+    }
+}
+
+/// Remember what had the keyboard focus of the seat before a layer surface
+/// got it, to give it back once the layer surface is unmapped.
+pub(crate) unsafe fn focus_changed(seat: *mut wlr_seat, previous: *mut wlr_surface) {
+    let focused = (*seat).keyboard_state.focused_surface;
+    if focused == previous {
+        return
+    }
+    let to_layer = !focused.is_null() && wlr_surface_is_layer_surface(focused);
+    let from_layer = !previous.is_null() && wlr_surface_is_layer_surface(previous);
+    STATE.with(|state| {
+                   let mut state = match state.try_borrow_mut() {
+                       Ok(state) => state,
+                       Err(_) => return
+                   };
+                   if !to_layer || !from_layer {
+                       state.previous_focus.retain(|&(other, _)| other != seat);
+                   }
+                   let known = !previous.is_null() && !(*previous).data.is_null();
+                   if to_layer && !from_layer && known {
+                       state.previous_focus.push((seat, surface::Handle::from_ptr(previous)));
+                   }
+               })
+}
+
+/// Forget the seat.
+pub(crate) fn seat_destroyed(seat: *mut wlr_seat) {
+    STATE.with(|state| {
+                   if let Ok(mut state) = state.try_borrow_mut() {
+                       state.previous_focus.retain(|&(other, _)| other != seat)
+                   }
+               })
+}
+
+/// Give the keyboards to the layer surface that was mapped.
+unsafe fn take_keyboard(layer_surface: *mut wlr_layer_surface) {
+    let surface = (*layer_surface).surface;
+    if exclusive(None) != Some(layer_surface) || seat::input_blocked(surface) {
+        return
+    }
+    for seat in seat::seat_ptrs() {
+        let previous = (*seat).keyboard_state.focused_surface;
+        if previous == surface {
+            continue
+        }
+        seat::give_keyboard_focus(seat, surface);
+        focus_changed(seat, previous);
+    }
+}
+
+/// Give the keyboards that the unmapped layer surface had to another
+/// exclusive layer surface, or back to what had them before.
+unsafe fn give_keyboard_back(layer_surface: *mut wlr_layer_surface) {
+    let surface = (*layer_surface).surface;
+    let next = exclusive(Some(layer_surface)).map(|next| (*next).surface);
+    for seat in seat::seat_ptrs() {
+        if (*seat).keyboard_state.focused_surface != surface {
+            continue
+        }
+        let previous = STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            let index = state.previous_focus.iter().position(|&(other, _)| other == seat)?;
+            Some(state.previous_focus.remove(index).1)
+        });
+        let previous = previous.and_then(|previous| previous.run(|surface| surface.as_ptr()).ok());
+        let target = next.or(previous).filter(|&target| !seat::input_blocked(target));
+        match target {
+            Some(target) => seat::give_keyboard_focus(seat, target),
+            None => {
+                wlr_seat_keyboard_clear_focus(seat);
+                #[cfg(feature = "shortcuts_inhibit")]
+                shortcuts_inhibit::focus_changed(seat);
+            }
+        }
+    }
+}
+
+/// Get the topmost mapped layer surface that takes the keyboard
+/// exclusively, leaving out the one that's going away.
+///
+/// Of the ones on the same layer the one that was mapped last wins.
+unsafe fn exclusive(leaving: Option<*mut wlr_layer_surface>)
+                    -> Option<*mut wlr_layer_surface> {
+    STATE.with(|state| {
+        let state = state.try_borrow().ok()?;
+        state.surfaces
+             .iter()
+             .map(|entry| entry.layer_surface)
+             .filter(|&layer_surface| Some(layer_surface) != leaving)
+             .filter(|&layer_surface| (*layer_surface).mapped)
+             .filter(|&layer_surface| {
+                         interactivity(layer_surface) == KeyboardInteractivity::Exclusive
+                     })
+             .fold(None, |topmost, layer_surface| match topmost {
+                 Some(topmost) if layer((*topmost).layer) > layer((*layer_surface).layer) => {
+                     Some(topmost)
+                 },
+                 _ => Some(layer_surface)
+             })
+    })
+}
+
+/// Work out the keyboard interactivity of the layer surface.
+///
+/// This version of the protocol only has a flag for it, which makes it
+/// exclusive on the layers above the views and on demand below them.
+unsafe fn interactivity(layer_surface: *mut wlr_layer_surface) -> KeyboardInteractivity {
+    if !(*layer_surface).current.keyboard_interactive {
+        KeyboardInteractivity::None
+    } else if layer((*layer_surface).layer) >= Layer::Top {
+        KeyboardInteractivity::Exclusive
+    } else {
+        KeyboardInteractivity::OnDemand
+    }
+}
+
+fn layer(layer: zwlr_layer_shell_v1_layer) -> Layer {
+    use wlroots_sys::zwlr_layer_shell_v1_layer::*;
+    match layer {
+        ZWLR_LAYER_SHELL_V1_LAYER_BACKGROUND => Layer::Background,
+        ZWLR_LAYER_SHELL_V1_LAYER_BOTTOM => Layer::Bottom,
+        ZWLR_LAYER_SHELL_V1_LAYER_TOP => Layer::Top,
+        ZWLR_LAYER_SHELL_V1_LAYER_OVERLAY => Layer::Overlay
+    }
+}
+
+/// Get a handle to the layer surface, if it's still known.
+fn handle(layer_surface: *mut wlr_layer_surface) -> Option<LayerSurface> {
+    STATE.with(|state| {
+                   state.borrow()
+                        .surfaces
+                        .iter()
+                        .find(|entry| entry.layer_surface == layer_surface)
+                        .map(|entry| LayerSurface { layer_surface,
+                                                    handle: Rc::downgrade(&entry.handle) })
+               })
+}
+
+/// Call the handler, if there is one, without borrowing the state.
+fn notify<F>(f: F)
+    where F: FnOnce(&mut Handler, compositor::Handle)
+{
+    let handler = STATE.with(|state| state.borrow().handler.clone());
+    if let (Some(handler), Some(compositor)) = (handler, compositor::handle()) {
+        f(&mut **handler.borrow_mut(), compositor)
+    }
+}
@@ -1 +1,46 @@
+//! Protocol extensions beyond the core and shell protocols, each behind its
+//! own cargo feature (all of them are in the default `protocols` feature).
+//!
+//! Some of them wrap the implementation of wlroots (`input_inhibit`,
+//! `layer_shell`, `screenshooter`, `server_decoration` and the KDE protocol of
+//! `idle`), and `decoration` decides between the decoration protocols. The
+//! others are protocols this version of wlroots doesn't implement, so this
+//! crate implements them itself on top of `Compositor::create_global`, with
+//! the bindings wlroots-sys generates from wayland-protocols: `content_type`,
+//! `foreign_toplevel_list`, `fullscreen_shell`, `image_capture_source`,
+//! `session_lock`, `shortcuts_inhibit`, `tearing_control`, `xdg_activation`,
+//! `xdg_foreign` and the `ext_idle_notifier_v1` protocol of `idle`.
+//!
+//! Those need the XML of their protocol at build time, which for the staging
+//! protocols means a recent version of wayland-protocols.
+
+#[cfg(feature = "content_type")]
+pub mod content_type;
+#[cfg(feature = "decoration")]
+pub mod decoration;
+#[cfg(feature = "foreign_toplevel_list")]
+pub mod foreign_toplevel_list;
+#[cfg(feature = "fullscreen_shell")]
+pub mod fullscreen_shell;
+#[cfg(feature = "idle")]
+pub mod idle;
+#[cfg(feature = "image_capture_source")]
+pub mod image_capture_source;
+#[cfg(feature = "input_inhibit")]
+pub mod input_inhibit;
+#[cfg(feature = "layer_shell")]
+pub mod layer_shell;
+#[cfg(feature = "screencopy")]
+pub mod screenshooter;
+#[cfg(feature = "server_decoration")]
 pub mod server_decoration;
+#[cfg(feature = "session_lock")]
+pub mod session_lock;
+#[cfg(feature = "shortcuts_inhibit")]
+pub mod shortcuts_inhibit;
+#[cfg(feature = "tearing_control")]
+pub mod tearing_control;
+#[cfg(feature = "xdg_activation")]
+pub mod xdg_activation;
+#[cfg(feature = "xdg_foreign")]
+pub mod xdg_foreign;
@@ -0,0 +1,34 @@
+//! Support for the orbital screenshooter protocol (`orbital_screenshooter`),
+//! with which screenshot tools copy what's shown on an output.
+//!
+//! This version of wlroots doesn't have the newer screencopy protocol
+//! (`zwlr_screencopy_manager_v1`) yet, so this is the only way clients can
+//! take screenshots by themselves. The global is privileged, so it's a good
+//! idea to restrict it with `compositor::Builder::global_filter`.
+
+use wayland_sys::server::wl_display as wl_server_display;
+use wlroots_sys::{wl_display, wlr_screenshooter, wlr_screenshooter_create,
+                  wlr_screenshooter_destroy};
+
+use error::Error;
+
+#[derive(Debug)]
+pub struct Manager {
+    screenshooter: *mut wlr_screenshooter
+}
+
+impl Manager {
+    pub(crate) unsafe fn new(display: *mut wl_server_display) -> Result<Self, Error> {
+        let screenshooter = wlr_screenshooter_create(display as *mut wl_display);
+        if screenshooter.is_null() {
+            return Err(Error::Protocol("orbital_screenshooter"))
+        }
+        Ok(Manager { screenshooter })
+    }
+}
+
+impl Drop for Manager {
+    fn drop(&mut self) {
+        unsafe { wlr_screenshooter_destroy(self.screenshooter) }
+    }
+}
@@ -1,13 +1,79 @@
-use wayland_sys::server::wl_display as wl_server_display;
-use wlroots_sys::{wl_display, wlr_server_decoration_manager, wlr_server_decoration_manager_create,
-                  wlr_server_decoration_manager_destroy,
+//! Support for the KDE server decoration protocol
+//! (`org_kde_kwin_server_decoration_manager`), which some toolkits speak
+//! instead of `xdg-decoration`.
+//!
+//! New decorations start out in the default mode of the manager. Once a
+//! `decoration::Policy` is set, it decides the mode of every new decoration
+//! and of every mode the client asks for.
+
+use std::fmt;
+
+use libc;
+use wayland_sys::server::{signal::wl_signal_add, wl_display as wl_server_display,
+                          WAYLAND_SERVER_HANDLE};
+use wlroots_sys::{wl_display, wlr_server_decoration, wlr_server_decoration_manager,
+                  wlr_server_decoration_manager_create, wlr_server_decoration_manager_destroy,
                   wlr_server_decoration_manager_set_default_mode};
 pub use wlroots_sys::protocols::server_decoration
 ::server::org_kde_kwin_server_decoration_manager::Mode;
 
-#[derive(Debug)]
+use {extensions::decoration, surface};
+
+/// The opcode of the `mode` event of `org_kde_kwin_server_decoration`.
+const MODE_EVENT: u32 = 0;
+
+wayland_listener!(pub(crate) DecorationManager, (), [
+    new_decoration_listener => new_decoration_notify: |_this: &mut DecorationManager,
+                                                        data: *mut libc::c_void,|
+    unsafe {
+        let decoration = data as *mut wlr_server_decoration;
+        let mut listener = ServerDecoration::new(decoration);
+        wl_signal_add(&mut (*decoration).events.mode as *mut _ as _,
+                      listener.mode_listener() as *mut _ as _);
+        wl_signal_add(&mut (*decoration).events.destroy as *mut _ as _,
+                      listener.destroy_listener() as *mut _ as _);
+        Box::into_raw(listener);
+        // NOTE wlroots already sent the default mode, so a different one is
+        // sent on top.
+        if let Some(mode) = decide(decoration, None) {
+            if mode != (*decoration).mode {
+                (*decoration).mode = mode;
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                              wl_resource_post_event,
+                              (*decoration).resource,
+                              MODE_EVENT,
+                              mode);
+            }
+        }
+    };
+]);
+
+wayland_listener!(pub(crate) ServerDecoration, *mut wlr_server_decoration, [
+    mode_listener => mode_notify: |this: &mut ServerDecoration, _data: *mut libc::c_void,|
+    unsafe {
+        // NOTE wlroots sends the client whatever mode is left here once the
+        // signal is done.
+        let decoration = this.data;
+        let requested = (*decoration).mode;
+        if let Some(mode) = decide(decoration, Some(requested)) {
+            (*decoration).mode = mode;
+        }
+    };
+    destroy_listener => destroy_notify: |this: &mut ServerDecoration, _data: *mut libc::c_void,|
+    unsafe {
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_list_remove,
+                      &mut (*this.mode_listener()).link as *mut _ as _);
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_list_remove,
+                      &mut (*this.destroy_listener()).link as *mut _ as _);
+        Box::from_raw(this as *mut ServerDecoration);
+    };
+]);
+
 pub struct Manager {
-    manager: *mut wlr_server_decoration_manager
+    manager: *mut wlr_server_decoration_manager,
+    listener: Box<DecorationManager>
 }
 
 impl Manager {
@@ -15,7 +81,10 @@ impl Manager {
         let manager_raw = wlr_server_decoration_manager_create(display as *mut wl_display);
 
         if !manager_raw.is_null() {
-            Some(Manager { manager: manager_raw })
+            let mut listener = DecorationManager::new(());
+            wl_signal_add(&mut (*manager_raw).events.new_decoration as *mut _ as _,
+                          listener.new_decoration_listener() as *mut _ as _);
+            Some(Manager { manager: manager_raw, listener })
         } else {
             None
         }
@@ -25,10 +94,57 @@ impl Manager {
         wlr_log!(WLR_INFO, "New server decoration mode: {:?}", mode);
         unsafe { wlr_server_decoration_manager_set_default_mode(self.manager, mode.to_raw()) }
     }
+
+    /// Get the mode of the decoration of the surface, if its client uses this
+    /// protocol for it.
+    pub fn mode(&self, surface: &surface::Handle) -> Option<Mode> {
+        unsafe {
+            let surface = surface.as_ptr();
+            let mut mode = None;
+            wl_list_for_each!((*self.manager).decorations, link,
+                              (decoration: wlr_server_decoration) => {
+                                  if (*decoration).surface == surface {
+                                      mode = Mode::from_raw((*decoration).mode);
+                                  }
+                              });
+            mode
+        }
+    }
+}
+
+impl fmt::Debug for Manager {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Manager").field("manager", &self.manager).finish()
+    }
 }
 
 impl Drop for Manager {
     fn drop(&mut self) {
-        unsafe { wlr_server_decoration_manager_destroy(self.manager) }
+        unsafe {
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_list_remove,
+                          &mut (*self.listener.new_decoration_listener()).link as *mut _ as _);
+            wlr_server_decoration_manager_destroy(self.manager)
+        }
+    }
+}
+
+/// Ask the decoration policy for the raw mode of the decoration.
+unsafe fn decide(decoration: *mut wlr_server_decoration, requested: Option<u32>) -> Option<u32> {
+    let surface = (*decoration).surface;
+    if surface.is_null() || (*surface).data.is_null() {
+        return None
     }
+    let requested = requested.and_then(Mode::from_raw);
+    let mode = decoration::decide(surface::Handle::from_ptr(surface),
+                                  requested.map(|requested| match requested {
+                                                    Mode::Server => decoration::Mode::ServerSide,
+                                                    _ => decoration::Mode::ClientSide
+                                                }))?;
+    Some(match (mode, requested) {
+             (decoration::Mode::ServerSide, _) => Mode::Server,
+             // NOTE Clients asking for no decorations at all decorate themselves.
+             (decoration::Mode::ClientSide, Some(Mode::None)) => Mode::None,
+             (decoration::Mode::ClientSide, _) => Mode::Client
+         }.to_raw())
 }
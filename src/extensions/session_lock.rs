@@ -0,0 +1,626 @@
+//! Locking the session, with the `ext-session-lock-v1` protocol, which is
+//! implemented by this crate (see [`extensions`](../index.html)).
+//!
+//! A locker client (e.g. swaylock) asks for the session to be locked and gives
+//! every output a surface showing its prompt. While the session is locked:
+//!
+//! * every output is blanked to a solid color, with only the lock surface of
+//!   the output drawn on top of it,
+//! * the seats refuse to give pointer, keyboard or touch focus to any surface
+//!   but the lock surfaces,
+//! * the locker is only told the session is locked once every output drew a
+//!   locked frame, so nothing is left on screen when it thinks it's safe.
+//!
+//! The session is only unlocked when the locker says so, or when the
+//! compositor calls `Manager::unlock`. If the locker goes away without
+//! unlocking (e.g. it crashed) the session stays locked behind the fallback
+//! color, and another locker can take over.
+//!
+//! The compositor can lock the session itself too with `Manager::lock`, e.g.
+//! before suspending, and then start a locker to unlock it.
+
+use std::{cell::RefCell, mem, rc::Rc};
+
+use libc;
+use wayland_sys::server::{signal::wl_signal_add, WAYLAND_SERVER_HANDLE};
+use wlroots_sys::{wayland_server::{NewResource, Resource,
+                                   protocol::{wl_output::WlOutput, wl_surface::WlSurface}},
+                  wlr_output_effective_resolution, wlr_output_from_resource,
+                  wlr_output_transform_invert, wlr_seat_keyboard_clear_focus,
+                  wlr_seat_pointer_clear_focus, wlr_surface, wlr_surface_from_resource,
+                  wlr_surface_get_root_surface, wlr_surface_set_role};
+use wlroots_sys::protocols::session_lock::server as protocol;
+use self::protocol::{ext_session_lock_manager_v1::{self, ExtSessionLockManagerV1},
+                     ext_session_lock_surface_v1::{self, ExtSessionLockSurfaceV1},
+                     ext_session_lock_v1::{self, ExtSessionLockV1}};
+
+use {area::{Area, Origin, Size},
+     compositor::{self, Compositor, CustomGlobal},
+     output::{self, Output},
+     render::{matrix, RenderPass, Renderer},
+     seat, surface,
+     utils::{current_time, HandleErr, Handleable}};
+#[cfg(feature = "shortcuts_inhibit")]
+use extensions::shortcuts_inhibit;
+
+/// The version of the protocol that is implemented.
+const VERSION: u32 = 1;
+
+/// The role of lock surfaces, as a C string.
+const ROLE: &[u8] = b"ext_session_lock_surface_v1\0";
+
+/// Handles the changes to the state of the lock.
+#[allow(unused_variables)]
+pub trait Handler {
+    /// Called once the session is locked and every output shows it.
+    ///
+    /// This is called while an output is being rendered, so that output
+    /// can't be used in here.
+    fn locked(&mut self, compositor_handle: compositor::Handle) {}
+
+    /// Called when the session was unlocked, by the locker or the compositor.
+    fn unlocked(&mut self, compositor_handle: compositor::Handle) {}
+
+    /// Called when the locker went away without unlocking the session.
+    ///
+    /// The session stays locked behind the fallback color. This is the place
+    /// to start a new locker, so the user can unlock it again.
+    fn locker_lost(&mut self, compositor_handle: compositor::Handle) {}
+}
+
+/// The surface a locker shows on an output.
+struct LockSurface {
+    resource: Resource<ExtSessionLockSurfaceV1>,
+    surface: *mut wlr_surface,
+    output: output::Handle,
+    listener: Box<LockSurfaceListener>
+}
+
+struct State {
+    /// Kept apart so the handler can be called without borrowing the rest.
+    handler: Option<Rc<RefCell<Box<Handler>>>>,
+    /// Whether the outputs were given a `LockPass`, which is once there's a
+    /// `Manager`.
+    blanking: bool,
+    locked: bool,
+    /// The lock of the client that locked the session, if it's still there.
+    locker: Option<Resource<ExtSessionLockV1>>,
+    /// Whether every output drew a locked frame since the session was locked.
+    confirmed: bool,
+    /// The outputs that have yet to draw a locked frame.
+    pending: Vec<output::Handle>,
+    surfaces: Vec<LockSurface>,
+    outputs: Vec<output::Handle>,
+    color: [f32; 4],
+    serial: u32
+}
+
+impl State {
+    fn new() -> Self {
+        State { handler: None,
+                blanking: false,
+                locked: false,
+                locker: None,
+                confirmed: false,
+                pending: Vec::new(),
+                surfaces: Vec::new(),
+                outputs: Vec::new(),
+                color: [0.0, 0.0, 0.0, 1.0],
+                serial: 0 }
+    }
+}
+
+thread_local! {
+    static STATE: RefCell<State> = RefCell::new(State::new());
+}
+
+wayland_listener!(pub(crate) LockSurfaceListener, *mut wlr_surface, [
+    commit_listener => commit_notify: |this: &mut LockSurfaceListener,
+                                       _data: *mut libc::c_void,|
+    unsafe {
+        let surface = this.data;
+        let output = STATE.with(|state| {
+            state.borrow()
+                 .surfaces
+                 .iter()
+                 .find(|lock_surface| lock_surface.surface == surface)
+                 .map(|lock_surface| lock_surface.output.clone())
+        });
+        if let Some(output) = output {
+            redraw(&output);
+        }
+    };
+    destroy_listener => destroy_notify: |this: &mut LockSurfaceListener,
+                                         _data: *mut libc::c_void,|
+    unsafe {
+        let surface = this.data;
+        remove_surface(|lock_surface| lock_surface.surface == surface);
+    };
+]);
+
+/// The `ext_session_lock_manager_v1` global.
+///
+/// The global is removed when this is dropped, but a locked session stays
+/// locked.
+pub struct Manager {
+    global: Option<CustomGlobal<ExtSessionLockManagerV1>>
+}
+
+impl Manager {
+    /// Advertise the global, telling the handler about the changes to the
+    /// lock.
+    pub fn create(compositor: &mut Compositor, handler: Box<Handler>) -> Self {
+        let blanking = STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            state.handler = Some(Rc::new(RefCell::new(handler)));
+            mem::replace(&mut state.blanking, true)
+        });
+        if !blanking {
+            for output in outputs() {
+                output.run(|output| output.add_render_pass(Box::new(LockPass))).ok();
+            }
+        }
+        let global = compositor.create_global(VERSION, |resource, _| bind(resource));
+        Manager { global: Some(global) }
+    }
+
+    /// Advertise the global like `create`, without boxing the handler
+    /// yourself.
+    pub fn create_with<H: Handler + 'static>(compositor: &mut Compositor, handler: H) -> Self {
+        Manager::create(compositor, Box::new(handler))
+    }
+
+    /// Lock the session, without a locker.
+    ///
+    /// Every output shows the fallback color until a locker takes over or
+    /// the session is unlocked. Does nothing if the session is locked
+    /// already.
+    pub fn lock(&mut self) {
+        begin_lock(None);
+    }
+
+    /// Unlock the session.
+    ///
+    /// The locker, if there is one, is told it's done.
+    pub fn unlock(&mut self) {
+        end_lock();
+    }
+
+    /// Determine if the session is locked.
+    ///
+    /// This is true as soon as it's asked for, even when the outputs don't
+    /// show it yet.
+    pub fn locked(&self) -> bool {
+        STATE.with(|state| state.borrow().locked)
+    }
+
+    /// Set the color the outputs are blanked to while the session is locked.
+    ///
+    /// Black by default.
+    pub fn set_fallback_color(&mut self, color: [f32; 4]) {
+        STATE.with(|state| state.borrow_mut().color = color);
+        for output in outputs() {
+            redraw(&output);
+        }
+    }
+}
+
+impl Drop for Manager {
+    fn drop(&mut self) {
+        if let Some(global) = self.global.take() {
+            global.destroy()
+        }
+    }
+}
+
+/// Blanks the output while the session is locked.
+struct LockPass;
+
+impl RenderPass for LockPass {
+    fn post_pass(&mut self, renderer: &mut Renderer) {
+        let output = renderer.output.weak_reference();
+        let (color, surface) = match STATE.with(|state| {
+            let state = state.borrow();
+            if !state.locked {
+                return None
+            }
+            let surface = state.surfaces
+                               .iter()
+                               .find(|lock_surface| lock_surface.output == output)
+                               .map(|lock_surface| lock_surface.surface);
+            Some((state.color, surface))
+        }) {
+            Some(lock) => lock,
+            None => return
+        };
+        renderer.render_scissor(None);
+        renderer.clear(color);
+        if let Some(surface) = surface {
+            unsafe { render_lock_surface(renderer, surface) }
+        }
+        output_locked(&output);
+    }
+}
+
+/// Draw the lock surface and its subsurfaces over the whole output.
+unsafe fn render_lock_surface(renderer: &mut Renderer, surface: *mut wlr_surface) {
+    if (*surface).data.is_null() {
+        return
+    }
+    let mut surfaces = Vec::new();
+    let res = surface::Handle::from_ptr(surface).run(|surface| {
+        surface.for_each_surface(|surface, sx, sy| surfaces.push((surface, sx, sy)))
+    });
+    if res.is_err() {
+        return
+    }
+    let scale = renderer.output.scale();
+    let projection = renderer.output.transform_matrix();
+    let time = current_time();
+    for (surface, sx, sy) in surfaces {
+        surface.run(|surface| {
+                        let (width, height) = surface.current_state().size();
+                        let transform = surface.current_state().transform();
+                        let area = Area::new(Origin::new((sx as f32 * scale) as i32,
+                                                         (sy as f32 * scale) as i32),
+                                             Size::new((width as f32 * scale) as i32,
+                                                       (height as f32 * scale) as i32));
+                        let matrix = matrix::project_box(area,
+                                                         wlr_output_transform_invert(transform),
+                                                         0.0,
+                                                         projection);
+                        renderer.render_surface(surface, matrix);
+                        surface.send_frame_done(time);
+                    })
+               .ok();
+    }
+}
+
+/// Blank a new output while the session is locked, once there's a `Manager`.
+pub(crate) fn output_added(output: &mut Output) {
+    let handle = output.weak_reference();
+    let blanking = STATE.with(|state| {
+                                  let mut state = state.borrow_mut();
+                                  state.outputs.retain(is_alive);
+                                  state.outputs.push(handle);
+                                  state.blanking
+                              });
+    if blanking {
+        output.add_render_pass(Box::new(LockPass));
+    }
+}
+
+/// Forget everything about the lock, once the compositor is shutting down
+/// and its clients are gone.
+pub(crate) fn reset() {
+    // NOTE Dropped outside of the borrow, as it drops the handler.
+    let state = STATE.with(|state| mem::replace(&mut *state.borrow_mut(), State::new()));
+    drop(state)
+}
+
+/// Determine if the session is locked.
+///
+/// When the state can't be checked it's considered locked, as it's better to
+/// fail closed.
+pub(crate) fn is_locked() -> bool {
+    STATE.with(|state| state.try_borrow().map(|state| state.locked).unwrap_or(true))
+}
+
+/// Determine if input must not go to the surface, because the session is
+/// locked and it's not a lock surface.
+pub(crate) fn blocks_input(surface: *mut wlr_surface) -> bool {
+    STATE.with(|state| {
+        let state = match state.try_borrow() {
+            Ok(state) => state,
+            // NOTE Fail closed, like `is_locked`.
+            Err(_) => return true
+        };
+        if !state.locked {
+            return false
+        }
+        let root = unsafe { wlr_surface_get_root_surface(surface) };
+        !state.surfaces.iter().any(|lock_surface| lock_surface.surface == root)
+    })
+}
+
+fn bind(resource: NewResource<ExtSessionLockManagerV1>) {
+    resource.implement(|request, _| match request {
+                           ext_session_lock_manager_v1::Request::Destroy => {},
+                           ext_session_lock_manager_v1::Request::Lock { id } => lock_requested(id)
+                       },
+                       None::<fn(_)>);
+}
+
+fn lock_requested(id: NewResource<ExtSessionLockV1>) {
+    let lock = id.implement(|request, lock: Resource<ExtSessionLockV1>| match request {
+        ext_session_lock_v1::Request::Destroy => {
+            // NOTE Only an error once the locker was told the session is
+            // locked, before that the locker gives up and the session stays
+            // locked.
+            if is_locker(&lock) && STATE.with(|state| state.borrow().confirmed) {
+                lock.post_error(ext_session_lock_v1::Error::InvalidDestroy as u32,
+                                "the session lock was destroyed while locked".into());
+            }
+        },
+        ext_session_lock_v1::Request::GetLockSurface { id, surface, output } => {
+            get_lock_surface(&lock, id, surface, output)
+        },
+        ext_session_lock_v1::Request::UnlockAndDestroy => {
+            if !is_locker(&lock) || !STATE.with(|state| state.borrow().confirmed) {
+                lock.post_error(ext_session_lock_v1::Error::InvalidUnlock as u32,
+                                "the session was unlocked before it was locked".into());
+                return
+            }
+            end_lock();
+        }
+    },
+                            Some(|lock: Resource<ExtSessionLockV1>| locker_destroyed(&lock)));
+    if !begin_lock(Some(lock.clone())) {
+        // NOTE Another locker is still there, so this one is turned down.
+        lock.send(ext_session_lock_v1::Event::Finished);
+    }
+}
+
+/// Lock the session, for the locker if there is one.
+///
+/// Returns false when another locker holds the lock already.
+fn begin_lock(locker: Option<Resource<ExtSessionLockV1>>) -> bool {
+    let (started, confirmed) = STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        if state.locker.as_ref().map_or(false, |locker| locker.is_alive()) {
+            return (false, false)
+        }
+        let started = !state.locked;
+        if started {
+            state.locked = true;
+            state.confirmed = false;
+            state.outputs.retain(is_alive);
+            state.pending = state.outputs
+                                 .iter()
+                                 .filter(|output| unsafe { (*output.as_ptr()).enabled })
+                                 .cloned()
+                                 .collect();
+        }
+        let is_locker = locker.is_some();
+        if is_locker {
+            state.locker = locker;
+        }
+        (is_locker || started, state.confirmed)
+    });
+    if !started {
+        return true
+    }
+    unsafe { clear_focus() }
+    // NOTE When a locker takes over a session that stayed locked, the outputs
+    // show it's locked already. Without outputs there's nothing to wait for.
+    if confirmed || STATE.with(|state| state.borrow().pending.is_empty()) {
+        STATE.with(|state| state.borrow_mut().confirmed = true);
+        confirm();
+    }
+    for output in outputs() {
+        redraw(&output);
+    }
+    true
+}
+
+/// Unlock the session, telling the locker it's done if it didn't ask for it.
+fn end_lock() {
+    let (was_locked, locker) = STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let was_locked = state.locked;
+        state.locked = false;
+        state.confirmed = false;
+        state.pending.clear();
+        (was_locked, state.locker.take())
+    });
+    if let Some(locker) = locker {
+        if locker.is_alive() {
+            locker.send(ext_session_lock_v1::Event::Finished);
+        }
+    }
+    if !was_locked {
+        return
+    }
+    for output in outputs() {
+        redraw(&output);
+    }
+    notify(|handler, compositor| handler.unlocked(compositor));
+}
+
+/// Forget the locker once its lock is gone, keeping the session locked.
+fn locker_destroyed(lock: &Resource<ExtSessionLockV1>) {
+    let lost = STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let is_locker = state.locker
+                             .as_ref()
+                             .map_or(false, |locker| locker.c_ptr() == lock.c_ptr());
+        if is_locker {
+            state.locker = None;
+        }
+        is_locker && state.locked
+    });
+    if lost {
+        wlr_log!(WLR_INFO, "The locker went away, the session stays locked");
+        notify(|handler, compositor| handler.locker_lost(compositor));
+    }
+}
+
+fn is_locker(lock: &Resource<ExtSessionLockV1>) -> bool {
+    STATE.with(|state| {
+        state.borrow()
+             .locker
+             .as_ref()
+             .map_or(false, |locker| locker.c_ptr() == lock.c_ptr())
+    })
+}
+
+fn get_lock_surface(lock: &Resource<ExtSessionLockV1>,
+                    id: NewResource<ExtSessionLockSurfaceV1>,
+                    surface: Resource<WlSurface>,
+                    output: Resource<WlOutput>) {
+    let resource = id.implement(|request, _| match request {
+                                    ext_session_lock_surface_v1::Request::Destroy => {},
+                                    ext_session_lock_surface_v1::Request::AckConfigure { .. } => {}
+                                },
+                                Some(|resource: Resource<ExtSessionLockSurfaceV1>| unsafe {
+                                    remove_surface(|lock_surface| {
+                                        lock_surface.resource.c_ptr() == resource.c_ptr()
+                                    })
+                                }));
+    if !is_locker(lock) {
+        // NOTE The lock is finished, so its surfaces are never shown.
+        return
+    }
+    unsafe {
+        let surface = wlr_surface_from_resource(surface.c_ptr() as _);
+        let output = wlr_output_from_resource(output.c_ptr() as _);
+        if surface.is_null() || output.is_null() {
+            return
+        }
+        let output_handle = output::Handle::from_ptr(output);
+        let duplicate = STATE.with(|state| {
+            state.borrow()
+                 .surfaces
+                 .iter()
+                 .any(|lock_surface| lock_surface.output == output_handle)
+        });
+        if duplicate {
+            lock.post_error(ext_session_lock_v1::Error::DuplicateOutput as u32,
+                            "the output already has a lock surface".into());
+            return
+        }
+        if !wlr_surface_set_role(surface,
+                                 ROLE.as_ptr() as _,
+                                 lock.c_ptr() as _,
+                                 ext_session_lock_v1::Error::Role as u32) {
+            return
+        }
+        let mut listener = LockSurfaceListener::new(surface);
+        wl_signal_add(&mut (*surface).events.commit as *mut _ as _,
+                      listener.commit_listener() as *mut _ as _);
+        wl_signal_add(&mut (*surface).events.destroy as *mut _ as _,
+                      listener.destroy_listener() as *mut _ as _);
+        let serial = STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            state.serial = state.serial.wrapping_add(1);
+            state.surfaces.push(LockSurface { resource: resource.clone(),
+                                              surface,
+                                              output: output_handle,
+                                              listener });
+            state.serial
+        });
+        let (mut width, mut height) = (0, 0);
+        wlr_output_effective_resolution(output, &mut width, &mut height);
+        resource.send(ext_session_lock_surface_v1::Event::Configure { serial,
+                                                                      width: width as u32,
+                                                                      height: height as u32 });
+        focus(surface);
+    }
+}
+
+/// Forget the lock surfaces that match, once they're gone.
+unsafe fn remove_surface<F>(matches: F)
+    where F: Fn(&LockSurface) -> bool
+{
+    let removed: Vec<LockSurface> = STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let (removed, kept) = state.surfaces
+                                   .drain(..)
+                                   .partition(|lock_surface| matches(lock_surface));
+        state.surfaces = kept;
+        removed
+    });
+    for mut lock_surface in removed {
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_list_remove,
+                      &mut (*lock_surface.listener.commit_listener()).link as *mut _ as _);
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_list_remove,
+                      &mut (*lock_surface.listener.destroy_listener()).link as *mut _ as _);
+        redraw(&lock_surface.output);
+    }
+}
+
+/// Called once the output drew a locked frame, telling the locker the
+/// session is locked if it was the last one.
+fn output_locked(output: &output::Handle) {
+    let done = STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        state.pending.retain(|pending| pending != output && is_alive(pending));
+        let done = state.locked && !state.confirmed && state.pending.is_empty();
+        if done {
+            state.confirmed = true;
+        }
+        done
+    });
+    if done {
+        confirm();
+    }
+}
+
+/// Tell the locker and the handler the session is locked.
+fn confirm() {
+    let locker = STATE.with(|state| state.borrow().locker.clone());
+    if let Some(locker) = locker {
+        locker.send(ext_session_lock_v1::Event::Locked);
+    }
+    notify(|handler, compositor| handler.locked(compositor));
+}
+
+/// Take the focus away from everything, so no input goes to the session
+/// behind the lock.
+unsafe fn clear_focus() {
+    for seat in seat::seat_ptrs() {
+        wlr_seat_keyboard_clear_focus(seat);
+        wlr_seat_pointer_clear_focus(seat);
+        #[cfg(feature = "shortcuts_inhibit")]
+        shortcuts_inhibit::focus_changed(seat);
+    }
+}
+
+/// Give the keyboards to the lock surface, unless a lock surface has them
+/// already.
+unsafe fn focus(surface: *mut wlr_surface) {
+    for seat in seat::seat_ptrs() {
+        let focused = (*seat).keyboard_state.focused_surface;
+        if !focused.is_null() && !blocks_input(focused) {
+            continue
+        }
+        seat::give_keyboard_focus(seat, surface);
+    }
+}
+
+/// Get the outputs that are still around.
+fn outputs() -> Vec<output::Handle> {
+    STATE.with(|state| {
+                   let mut state = state.borrow_mut();
+                   state.outputs.retain(is_alive);
+                   state.outputs.clone()
+               })
+}
+
+/// Damage the whole output, so it's drawn again.
+fn redraw(output: &output::Handle) {
+    output.run(|output| {
+                   output.damage().add_whole();
+                   output.schedule_frame();
+               })
+          .ok();
+}
+
+fn is_alive(output: &output::Handle) -> bool {
+    match output.run(|_| ()) {
+        Err(HandleErr::AlreadyDropped) => false,
+        _ => true
+    }
+}
+
+/// Call the handler, if there is one, without borrowing the state.
+fn notify<F>(f: F)
+    where F: FnOnce(&mut Handler, compositor::Handle)
+{
+    let handler = STATE.with(|state| state.borrow().handler.clone());
+    if let (Some(handler), Some(compositor)) = (handler, compositor::handle()) {
+        f(&mut **handler.borrow_mut(), compositor)
+    }
+}
@@ -0,0 +1,286 @@
+//! Support for the keyboard shortcuts inhibit protocol
+//! (`zwp_keyboard_shortcuts_inhibit_manager_v1`), which lets clients like
+//! virtual machines and remote desktops get the key combinations the
+//! compositor would otherwise act on itself.
+//!
+//! Like the other protocols listed in [`extensions`](../index.html), it's
+//! handled by this crate. The `Handler` approves every request to inhibit the
+//! shortcuts, and an approved inhibitor is active whenever its
+//! surface has the keyboard focus of its seat.
+//!
+//! The compositor asks `Seat::shortcuts_inhibited_for` with the focused
+//! surface before running its key bindings, and should keep at least one
+//! binding that isn't inhibited (to get out of the client again), e.g. with
+//! `Manager::revoke`.
+
+use std::{cell::RefCell, ptr, rc::Rc};
+
+use libc;
+use wayland_sys::server::{signal::wl_signal_add, WAYLAND_SERVER_HANDLE};
+use wlroots_sys::{wayland_server::{NewResource, Resource,
+                                   protocol::{wl_seat::WlSeat, wl_surface::WlSurface}},
+                  wlr_seat, wlr_seat_client_from_resource, wlr_surface, wlr_surface_from_resource};
+use wlroots_sys::protocols::keyboard_shortcuts_inhibit::server as protocol;
+use self::protocol::{zwp_keyboard_shortcuts_inhibit_manager_v1 as manager_v1,
+                     zwp_keyboard_shortcuts_inhibitor_v1 as inhibitor_v1};
+use self::{inhibitor_v1::ZwpKeyboardShortcutsInhibitorV1,
+           manager_v1::ZwpKeyboardShortcutsInhibitManagerV1};
+
+use {compositor::{self, Compositor, CustomGlobal}, seat, surface};
+
+/// The version of the protocol that is implemented.
+const VERSION: u32 = 1;
+
+/// Decides which clients may inhibit the shortcuts.
+#[allow(unused_variables)]
+pub trait Handler {
+    /// Called when a client asks to inhibit the shortcuts of the seat while
+    /// its surface has the keyboard focus.
+    ///
+    /// Returns whether it may. By default every client may.
+    fn inhibit_requested(&mut self,
+                         compositor_handle: compositor::Handle,
+                         surface: surface::Handle,
+                         seat: seat::Handle)
+                         -> bool {
+        true
+    }
+}
+
+struct Inhibitor {
+    resource: Resource<ZwpKeyboardShortcutsInhibitorV1>,
+    surface: *mut wlr_surface,
+    seat: *mut wlr_seat,
+    approved: bool,
+    /// Whether the client was told the inhibitor is active.
+    active: bool,
+    /// Listens for the surface to be destroyed, after which the inhibitor
+    /// does nothing.
+    destroy: Option<Box<InhibitedSurface>>
+}
+
+struct State {
+    /// Kept apart so the handler can be called without borrowing the rest.
+    handler: Option<Rc<RefCell<Box<Handler>>>>,
+    inhibitors: Vec<Inhibitor>
+}
+
+thread_local! {
+    static STATE: RefCell<State> = RefCell::new(State { handler: None,
+                                                        inhibitors: Vec::new() });
+}
+
+wayland_listener!(pub(crate) InhibitedSurface, (), [
+    destroy_listener => destroy_notify: |this: &mut InhibitedSurface, _data: *mut libc::c_void,|
+    unsafe {
+        let this_ptr = this as *mut InhibitedSurface as *const InhibitedSurface;
+        let destroy = STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            let inhibitor = state.inhibitors.iter_mut().find(|inhibitor| {
+                inhibitor.destroy
+                         .as_ref()
+                         .map_or(false, |destroy| &**destroy as *const _ == this_ptr)
+            })?;
+            inhibitor.surface = ptr::null_mut();
+            inhibitor.destroy.take()
+        });
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_list_remove,
+                      &mut (*this.destroy_listener()).link as *mut _ as _);
+        // NOTE This frees the listener that's running, so nothing comes after.
+        drop(destroy);
+    };
+]);
+
+/// The `zwp_keyboard_shortcuts_inhibit_manager_v1` global.
+///
+/// The global is removed when this is dropped.
+pub struct Manager {
+    global: Option<CustomGlobal<ZwpKeyboardShortcutsInhibitManagerV1>>
+}
+
+impl Manager {
+    /// Advertise the global, asking the handler whether requests are
+    /// approved.
+    pub fn create(compositor: &mut Compositor, handler: Box<Handler>) -> Self {
+        STATE.with(|state| state.borrow_mut().handler = Some(Rc::new(RefCell::new(handler))));
+        let global = compositor.create_global(VERSION, |resource, _| bind(resource));
+        Manager { global: Some(global) }
+    }
+
+    /// Advertise the global like `create`, without boxing the handler
+    /// yourself.
+    pub fn create_with<H: Handler + 'static>(compositor: &mut Compositor, handler: H) -> Self {
+        Manager::create(compositor, Box::new(handler))
+    }
+
+    /// Give the shortcuts of every seat back to the compositor while the
+    /// surface has the focus, until the client asks again.
+    pub fn revoke(&mut self, surface: &surface::Handle) {
+        let surface = unsafe { surface.as_ptr() };
+        STATE.with(|state| {
+                       for inhibitor in state.borrow_mut()
+                                             .inhibitors
+                                             .iter_mut()
+                                             .filter(|inhibitor| inhibitor.surface == surface) {
+                           inhibitor.approved = false;
+                       }
+                   });
+        update(None);
+    }
+}
+
+impl Drop for Manager {
+    fn drop(&mut self) {
+        if let Some(global) = self.global.take() {
+            global.destroy()
+        }
+    }
+}
+
+/// Determine if the client of the surface gets the shortcuts of the seat.
+pub(crate) fn inhibited(seat: *mut wlr_seat, surface: *mut wlr_surface) -> bool {
+    STATE.with(|state| {
+                   state.borrow().inhibitors.iter().any(|inhibitor| {
+                       inhibitor.seat == seat && inhibitor.surface == surface && inhibitor.approved
+                   })
+               })
+}
+
+/// Forget the seat, its inhibitors do nothing anymore.
+pub(crate) fn seat_destroyed(seat: *mut wlr_seat) {
+    STATE.with(|state| {
+                   for inhibitor in state.borrow_mut()
+                                         .inhibitors
+                                         .iter_mut()
+                                         .filter(|inhibitor| inhibitor.seat == seat) {
+                       inhibitor.seat = ptr::null_mut();
+                       inhibitor.approved = false;
+                   }
+               })
+}
+
+/// Tell the inhibitors of the seat whether they're active, now that its
+/// keyboard focus changed.
+pub(crate) fn focus_changed(seat: *mut wlr_seat) {
+    update(Some(seat))
+}
+
+/// Send `active` or `inactive` to the inhibitors (of the seat, if there is
+/// one) whose state changed.
+fn update(seat: Option<*mut wlr_seat>) {
+    let changed = STATE.with(|state| {
+        let mut state = match state.try_borrow_mut() {
+            Ok(state) => state,
+            Err(_) => return Vec::new()
+        };
+        state.inhibitors
+             .iter_mut()
+             .filter(|inhibitor| !inhibitor.seat.is_null())
+             .filter(|inhibitor| seat.map_or(true, |seat| inhibitor.seat == seat))
+             .filter_map(|inhibitor| unsafe {
+                 let focused = !inhibitor.surface.is_null() &&
+                               (*inhibitor.seat).keyboard_state.focused_surface ==
+                               inhibitor.surface;
+                 let active = inhibitor.approved && focused;
+                 if active == inhibitor.active {
+                     return None
+                 }
+                 inhibitor.active = active;
+                 Some((inhibitor.resource.clone(), active))
+             })
+             .collect::<Vec<_>>()
+    });
+    for (resource, active) in changed {
+        if resource.is_alive() {
+            resource.send(if active {
+                              inhibitor_v1::Event::Active
+                          } else {
+                              inhibitor_v1::Event::Inactive
+                          });
+        }
+    }
+}
+
+fn bind(resource: NewResource<ZwpKeyboardShortcutsInhibitManagerV1>) {
+    resource.implement(|request, manager: Resource<ZwpKeyboardShortcutsInhibitManagerV1>| {
+                           match request {
+                               manager_v1::Request::Destroy => {},
+                               manager_v1::Request::InhibitShortcuts { id, surface, seat } => {
+                                   inhibit(&manager, id, surface, seat)
+                               }
+                           }
+                       },
+                       None::<fn(_)>);
+}
+
+fn inhibit(manager: &Resource<ZwpKeyboardShortcutsInhibitManagerV1>,
+           id: NewResource<ZwpKeyboardShortcutsInhibitorV1>,
+           surface: Resource<WlSurface>,
+           seat: Resource<WlSeat>) {
+    let resource = id.implement(|request, _| match request {
+                                    inhibitor_v1::Request::Destroy => {}
+                                },
+                                Some(|resource: Resource<ZwpKeyboardShortcutsInhibitorV1>| {
+                                    uninhibit(&resource)
+                                }));
+    unsafe {
+        let surface = wlr_surface_from_resource(surface.c_ptr() as _);
+        let seat_client = wlr_seat_client_from_resource(seat.c_ptr() as _);
+        if surface.is_null() || seat_client.is_null() || (*seat_client).seat.is_null() {
+            return
+        }
+        let seat = (*seat_client).seat;
+        let duplicate = STATE.with(|state| {
+            state.borrow()
+                 .inhibitors
+                 .iter()
+                 .any(|inhibitor| inhibitor.surface == surface && inhibitor.seat == seat)
+        });
+        if duplicate {
+            manager.post_error(manager_v1::Error::AlreadyInhibited as u32,
+                               "the shortcuts of the seat are already inhibited for the surface"
+                               .into());
+            return
+        }
+        let handler = STATE.with(|state| state.borrow().handler.clone());
+        let approved = match (handler, compositor::handle()) {
+            (Some(handler), Some(compositor)) if !(*surface).data.is_null() => {
+                handler.borrow_mut().inhibit_requested(compositor,
+                                                       surface::Handle::from_ptr(surface),
+                                                       seat::Handle::from_ptr(seat))
+            },
+            _ => false
+        };
+        let mut destroy = InhibitedSurface::new(());
+        wl_signal_add(&mut (*surface).events.destroy as *mut _ as _,
+                      destroy.destroy_listener() as *mut _ as _);
+        STATE.with(|state| {
+                       state.borrow_mut().inhibitors.push(Inhibitor { resource,
+                                                                       surface,
+                                                                       seat,
+                                                                       approved,
+                                                                       active: false,
+                                                                       destroy: Some(destroy) })
+                   });
+        update(Some(seat));
+    }
+}
+
+/// Forget the inhibitor once its resource is destroyed.
+fn uninhibit(resource: &Resource<ZwpKeyboardShortcutsInhibitorV1>) {
+    let removed = STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let index = state.inhibitors
+                         .iter()
+                         .position(|inhibitor| inhibitor.resource.c_ptr() == resource.c_ptr())?;
+        Some(state.inhibitors.remove(index))
+    });
+    if let Some(mut destroy) = removed.and_then(|mut inhibitor| inhibitor.destroy.take()) {
+        unsafe {
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_list_remove,
+                          &mut (*destroy.destroy_listener()).link as *mut _ as _);
+        }
+    }
+}
@@ -0,0 +1,272 @@
+//! Support for the tearing control protocol (`wp_tearing_control_manager_v1`),
+//! with which clients (e.g. fullscreen games) ask for their frames to be
+//! presented as soon as they're ready, even if that tears, instead of waiting
+//! for the vblank.
+//!
+//! The protocol is implemented by this crate (see
+//! [`extensions`](../index.html)). The hint is double buffered, it's applied
+//! when the surface commits, which is when `Handler::presentation_hint_changed`
+//! is called. The current hint is given by `Surface::presentation_hint`.
+//!
+//! A hint alone doesn't make an output tear: the compositor has to allow it
+//! with `Output::set_allow_tearing` and say which surface the output shows
+//! with `Output::set_tearing_surface`. The backends of this version of wlroots
+//! can't do asynchronous page flips, so a tearing output still flips on the
+//! vblank, but its frames are rendered as soon as it can take them (see
+//! `Output::tearing`).
+
+use std::{cell::RefCell, ptr, rc::Rc};
+
+use libc;
+use wayland_sys::server::{signal::wl_signal_add, WAYLAND_SERVER_HANDLE};
+use wlroots_sys::{wayland_server::{NewResource, Resource, protocol::wl_surface::WlSurface},
+                  wlr_surface, wlr_surface_from_resource};
+use wlroots_sys::protocols::tearing_control::server::{
+    wp_tearing_control_manager_v1::{self as manager_v1, WpTearingControlManagerV1},
+    wp_tearing_control_v1::{self as tearing_control_v1, WpTearingControlV1}};
+pub use wlroots_sys::protocols::tearing_control::server::wp_tearing_control_v1::PresentationHint;
+
+use {compositor::{self, Compositor, CustomGlobal}, surface};
+
+/// The version of the protocol that is implemented.
+const VERSION: u32 = 1;
+
+/// Handles the presentation hints of the surfaces.
+#[allow(unused_variables)]
+pub trait Handler {
+    /// Called when the presentation hint of the surface changed, once the
+    /// surface committed the new hint.
+    fn presentation_hint_changed(&mut self,
+                                 compositor_handle: compositor::Handle,
+                                 surface: surface::Handle,
+                                 hint: PresentationHint) {
+    }
+}
+
+struct Tracked {
+    surface: *mut wlr_surface,
+    /// The object the client sets the hint with, until it's destroyed.
+    resource: Option<Resource<WpTearingControlV1>>,
+    /// The hint that's applied on the next commit, if it was set.
+    pending: Option<PresentationHint>,
+    current: PresentationHint,
+    listener: Option<Box<TearingControlSurface>>
+}
+
+struct State {
+    /// Kept apart so the handler can be called without borrowing the rest.
+    handler: Option<Rc<RefCell<Box<Handler>>>>,
+    surfaces: Vec<Tracked>
+}
+
+thread_local! {
+    static STATE: RefCell<State> = RefCell::new(State { handler: None,
+                                                        surfaces: Vec::new() });
+}
+
+wayland_listener!(pub(crate) TearingControlSurface, *mut wlr_surface, [
+    commit_listener => commit_notify: |this: &mut TearingControlSurface,
+                                       _data: *mut libc::c_void,|
+    unsafe {
+        let surface = this.data;
+        let (changed, done) = STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            let index = match state.surfaces.iter().position(|tracked| tracked.surface == surface) {
+                Some(index) => index,
+                None => return (None, None)
+            };
+            let changed = {
+                let tracked = &mut state.surfaces[index];
+                match tracked.pending.take() {
+                    Some(pending) if pending != tracked.current => {
+                        tracked.current = pending;
+                        Some(pending)
+                    },
+                    _ => None
+                }
+            };
+            // NOTE Once the object is gone and the reset to vsync is applied
+            // there's nothing left to track.
+            let done = if state.surfaces[index].resource.is_none() {
+                Some(state.surfaces.remove(index))
+            } else {
+                None
+            };
+            (changed, done)
+        });
+        if let Some(hint) = changed {
+            presentation_hint_changed(surface, hint);
+        }
+        if let Some(tracked) = done {
+            // NOTE This frees the listener that's running, so nothing comes after.
+            forget(tracked);
+        }
+    };
+    destroy_listener => destroy_notify: |this: &mut TearingControlSurface,
+                                         _data: *mut libc::c_void,|
+    unsafe {
+        let surface = this.data;
+        let tracked = STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            let index = state.surfaces.iter().position(|tracked| tracked.surface == surface)?;
+            Some(state.surfaces.remove(index))
+        });
+        if let Some(tracked) = tracked {
+            // NOTE This frees the listener that's running, so nothing comes after.
+            forget(tracked);
+        }
+    };
+]);
+
+/// The `wp_tearing_control_manager_v1` global.
+///
+/// The global is removed when this is dropped.
+pub struct Manager {
+    global: Option<CustomGlobal<WpTearingControlManagerV1>>
+}
+
+impl Manager {
+    /// Advertise the global, telling the handler about the hints of the
+    /// surfaces.
+    pub fn create(compositor: &mut Compositor, handler: Box<Handler>) -> Self {
+        STATE.with(|state| state.borrow_mut().handler = Some(Rc::new(RefCell::new(handler))));
+        let global = compositor.create_global(VERSION, |resource, _| bind(resource));
+        Manager { global: Some(global) }
+    }
+
+    /// Advertise the global like `create`, without boxing the handler
+    /// yourself.
+    pub fn create_with<H: Handler + 'static>(compositor: &mut Compositor, handler: H) -> Self {
+        Manager::create(compositor, Box::new(handler))
+    }
+}
+
+impl Drop for Manager {
+    fn drop(&mut self) {
+        if let Some(global) = self.global.take() {
+            global.destroy()
+        }
+    }
+}
+
+/// Get the presentation hint the surface last committed.
+///
+/// The surface is only compared against the tracked ones, so it may be
+/// dangling.
+pub(crate) fn current(surface: *mut wlr_surface) -> PresentationHint {
+    STATE.with(|state| {
+                   state.borrow()
+                        .surfaces
+                        .iter()
+                        .find(|tracked| tracked.surface == surface)
+                        .map_or(PresentationHint::Vsync, |tracked| tracked.current)
+               })
+}
+
+fn bind(resource: NewResource<WpTearingControlManagerV1>) {
+    resource.implement(|request, manager: Resource<WpTearingControlManagerV1>| match request {
+                           manager_v1::Request::Destroy => {},
+                           manager_v1::Request::GetTearingControl { id, surface } => {
+                               get_tearing_control(&manager, id, surface)
+                           }
+                       },
+                       None::<fn(_)>);
+}
+
+fn get_tearing_control(manager: &Resource<WpTearingControlManagerV1>,
+                       id: NewResource<WpTearingControlV1>,
+                       surface: Resource<WlSurface>) {
+    let resource = id.implement(|request, resource: Resource<WpTearingControlV1>| match request {
+                                    tearing_control_v1::Request::Destroy => {},
+                                    tearing_control_v1::Request::SetPresentationHint { hint } => {
+                                        set_pending(&resource, Some(hint))
+                                    }
+                                },
+                                Some(|resource: Resource<WpTearingControlV1>| {
+                                    set_pending(&resource, None)
+                                }));
+    unsafe {
+        let surface = wlr_surface_from_resource(surface.c_ptr() as _);
+        if surface.is_null() {
+            return
+        }
+        let exists = STATE.with(|state| {
+            state.borrow()
+                 .surfaces
+                 .iter()
+                 .any(|tracked| tracked.surface == surface && tracked.resource.is_some())
+        });
+        if exists {
+            manager.post_error(manager_v1::Error::TearingControlExists as u32,
+                               "the surface already has a tearing control object".into());
+            return
+        }
+        STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            // NOTE The surface may still be tracked until the reset of its
+            // last object is committed.
+            let tracked = state.surfaces.iter_mut().find(|tracked| tracked.surface == surface);
+            if let Some(tracked) = tracked {
+                tracked.resource = Some(resource);
+                return
+            }
+            let mut listener = TearingControlSurface::new(surface);
+            wl_signal_add(&mut (*surface).events.commit as *mut _ as _,
+                          listener.commit_listener() as *mut _ as _);
+            wl_signal_add(&mut (*surface).events.destroy as *mut _ as _,
+                          listener.destroy_listener() as *mut _ as _);
+            state.surfaces.push(Tracked { surface,
+                                          resource: Some(resource),
+                                          pending: None,
+                                          current: PresentationHint::Vsync,
+                                          listener: Some(listener) });
+        });
+    }
+}
+
+/// Set the hint applied on the next commit, or reset it to vsync once the
+/// object is destroyed (`hint` is `None`).
+fn set_pending(resource: &Resource<WpTearingControlV1>, hint: Option<PresentationHint>) {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let tracked = state.surfaces.iter_mut().find(|tracked| {
+            tracked.resource
+                   .as_ref()
+                   .map_or(false, |other| other.c_ptr() == resource.c_ptr())
+        });
+        if let Some(tracked) = tracked {
+            match hint {
+                Some(hint) => tracked.pending = Some(hint),
+                None => {
+                    tracked.pending = Some(PresentationHint::Vsync);
+                    tracked.resource = None;
+                }
+            }
+        }
+    })
+}
+
+unsafe fn presentation_hint_changed(surface: *mut wlr_surface, hint: PresentationHint) {
+    let handler = STATE.with(|state| state.borrow().handler.clone());
+    match (handler, compositor::handle()) {
+        (Some(handler), Some(compositor)) if !(*surface).data.is_null() => {
+            handler.borrow_mut().presentation_hint_changed(compositor,
+                                                           surface::Handle::from_ptr(surface),
+                                                           hint)
+        },
+        _ => {}
+    }
+}
+
+/// Stop listening to the surface of the entry, which is no longer tracked.
+unsafe fn forget(mut tracked: Tracked) {
+    tracked.surface = ptr::null_mut();
+    if let Some(mut listener) = tracked.listener.take() {
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_list_remove,
+                      &mut (*listener.commit_listener()).link as *mut _ as _);
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_list_remove,
+                      &mut (*listener.destroy_listener()).link as *mut _ as _);
+    }
+}
@@ -0,0 +1,272 @@
+//! Support for the xdg activation protocol (`xdg_activation_v1`), which lets
+//! a client pass on the focus to another window, e.g. a launcher to the
+//! application it started. The protocol is implemented by this crate (see
+//! [`extensions`](../index.html)).
+//!
+//! The client that has the focus asks for a token, hands it to the other
+//! client (e.g. in `XDG_ACTIVATION_TOKEN`), which asks for its window to be
+//! activated with it. The `Handler` decides whether that's honoured, and
+//! focuses the window if it is. Windows that are refused are marked urgent
+//! instead (see `view::urgency`), so the user can still find them.
+
+use std::{cell::RefCell, rc::Rc, time::{Duration, Instant}};
+
+use wlroots_sys::{wayland_server::{NewResource, Resource,
+                                   protocol::{wl_seat::WlSeat, wl_surface::WlSurface}},
+                  wlr_seat, wlr_seat_client_from_resource, wlr_surface,
+                  wlr_surface_from_resource};
+use wlroots_sys::protocols::xdg_activation::server as protocol;
+use self::protocol::{xdg_activation_token_v1::{self, XdgActivationTokenV1},
+                     xdg_activation_v1::{self, XdgActivationV1}};
+
+use {compositor::{self, Compositor, CustomGlobal}, seat, surface, utils, view::View};
+
+/// The version of the protocol that is implemented.
+const VERSION: u32 = 1;
+
+/// How many seconds a token can be used for after it was handed out.
+const TOKEN_TIMEOUT: u64 = 30;
+
+/// Decides which requests to activate a window are honoured.
+#[allow(unused_variables)]
+pub trait Handler {
+    /// Called when a client asks for its window to be activated with a token
+    /// it got from another client.
+    ///
+    /// Returns whether the request is honoured, in which case the window
+    /// should be focused in here. Otherwise the view is marked urgent. By
+    /// default a request is honoured if the token was asked for while its
+    /// surface had the keyboard focus.
+    fn activate(&mut self,
+                compositor_handle: compositor::Handle,
+                view: View,
+                token: &Token)
+                -> bool {
+        token.focused()
+    }
+}
+
+/// What is known about the client that asked for a token.
+#[derive(Debug, Clone)]
+pub struct Token {
+    token: String,
+    app_id: Option<String>,
+    surface: Option<surface::Handle>,
+    seat: Option<seat::Handle>,
+    serial: Option<u32>,
+    focused: bool,
+    created: Instant
+}
+
+impl Token {
+    /// Get the app id of the application that is going to be activated, if
+    /// the client said.
+    pub fn app_id(&self) -> Option<&str> {
+        self.app_id.as_ref().map(|app_id| app_id.as_str())
+    }
+
+    /// Get the surface of the client that asked for the token, if it said.
+    pub fn surface(&self) -> Option<surface::Handle> {
+        self.surface.clone()
+    }
+
+    /// Get the seat the client asked for the token on, if it said.
+    pub fn seat(&self) -> Option<seat::Handle> {
+        self.seat.clone()
+    }
+
+    /// Get the serial of the input event the token was asked for with, if
+    /// the client said.
+    pub fn serial(&self) -> Option<u32> {
+        self.serial
+    }
+
+    /// Determine if the surface that asked for the token had the keyboard
+    /// focus of the seat when it did.
+    pub fn focused(&self) -> bool {
+        self.focused
+    }
+
+    /// Get how long ago the token was handed out.
+    pub fn age(&self) -> Duration {
+        utils::now().duration_since(self.created)
+    }
+}
+
+/// What a client set on a token before committing it.
+#[derive(Default)]
+struct PendingToken {
+    app_id: Option<String>,
+    surface: Option<*mut wlr_surface>,
+    seat: Option<(*mut wlr_seat, u32)>,
+    committed: bool
+}
+
+struct Registry {
+    /// Kept apart so the handler can be called without borrowing the rest.
+    handler: Rc<RefCell<Box<Handler>>>,
+    tokens: Vec<Token>
+}
+
+/// The `xdg_activation_v1` global.
+///
+/// The global is removed when this is dropped.
+pub struct Manager {
+    registry: Rc<RefCell<Registry>>,
+    global: Option<CustomGlobal<XdgActivationV1>>
+}
+
+impl Manager {
+    /// Advertise the global, asking the handler which requests to honour.
+    pub fn create(compositor: &mut Compositor, handler: Box<Handler>) -> Self {
+        let registry = Rc::new(RefCell::new(Registry { handler: Rc::new(RefCell::new(handler)),
+                                                       tokens: Vec::new() }));
+        let global = {
+            let registry = registry.clone();
+            compositor.create_global(VERSION, move |resource, _| bind(resource, registry.clone()))
+        };
+        Manager { registry,
+                  global: Some(global) }
+    }
+
+    /// Advertise the global like `create`, without boxing the handler
+    /// yourself.
+    pub fn create_with<H: Handler + 'static>(compositor: &mut Compositor, handler: H) -> Self {
+        Manager::create(compositor, Box::new(handler))
+    }
+
+    /// Hand out a token of the compositor itself, e.g. for an application it
+    /// launches, to pass on in `XDG_ACTIVATION_TOKEN`.
+    ///
+    /// The token counts as having been asked for while focused.
+    pub fn create_token(&mut self, app_id: Option<String>) -> String {
+        let token = utils::random_token();
+        let mut registry = self.registry.borrow_mut();
+        expire(&mut registry.tokens);
+        registry.tokens.push(Token { token: token.clone(),
+                                     app_id,
+                                     surface: None,
+                                     seat: None,
+                                     serial: None,
+                                     focused: true,
+                                     created: utils::now() });
+        token
+    }
+}
+
+impl Drop for Manager {
+    fn drop(&mut self) {
+        if let Some(global) = self.global.take() {
+            global.destroy()
+        }
+    }
+}
+
+fn bind(resource: NewResource<XdgActivationV1>, registry: Rc<RefCell<Registry>>) {
+    resource.implement(move |request, _| match request {
+                           xdg_activation_v1::Request::Destroy => {},
+                           xdg_activation_v1::Request::GetActivationToken { id } => {
+                               get_token(id, registry.clone())
+                           },
+                           xdg_activation_v1::Request::Activate { token, surface } => {
+                               activate(&registry, &token, surface)
+                           }
+                       },
+                       None::<fn(_)>);
+}
+
+fn get_token(id: NewResource<XdgActivationTokenV1>, registry: Rc<RefCell<Registry>>) {
+    let pending = Rc::new(RefCell::new(PendingToken::default()));
+    id.implement(move |request, resource: Resource<XdgActivationTokenV1>| {
+                     let mut pending = pending.borrow_mut();
+                     if pending.committed {
+                         if let xdg_activation_token_v1::Request::Destroy = request {
+                             return
+                         }
+                         resource.post_error(xdg_activation_token_v1::Error::AlreadyUsed as u32,
+                                             "the token was already committed".into());
+                         return
+                     }
+                     match request {
+                         xdg_activation_token_v1::Request::SetSerial { serial, seat } => unsafe {
+                             let seat = seat_from_resource(&seat);
+                             pending.seat = seat.map(|seat| (seat, serial));
+                         },
+                         xdg_activation_token_v1::Request::SetAppId { app_id } => {
+                             pending.app_id = Some(app_id)
+                         },
+                         xdg_activation_token_v1::Request::SetSurface { surface } => unsafe {
+                             let surface = wlr_surface_from_resource(surface.c_ptr() as _);
+                             pending.surface = Some(surface)
+                         },
+                         xdg_activation_token_v1::Request::Commit => {
+                             pending.committed = true;
+                             let token = unsafe { commit(&pending) };
+                             let done = token.token.clone();
+                             resource.send(xdg_activation_token_v1::Event::Done { token: done });
+                             let mut registry = registry.borrow_mut();
+                             expire(&mut registry.tokens);
+                             registry.tokens.push(token);
+                         },
+                         xdg_activation_token_v1::Request::Destroy => {}
+                     }
+                 },
+                 None::<fn(_)>);
+}
+
+/// Forget the tokens that can't be used anymore.
+fn expire(tokens: &mut Vec<Token>) {
+    tokens.retain(|token| token.age() < Duration::from_secs(TOKEN_TIMEOUT))
+}
+
+/// Make the token out of what the client set.
+unsafe fn commit(pending: &PendingToken) -> Token {
+    let surface = pending.surface
+                         .filter(|surface| !surface.is_null() && !(**surface).data.is_null());
+    let seat = pending.seat.filter(|&(seat, _)| !seat.is_null());
+    let focused = match (surface, seat) {
+        (Some(surface), Some((seat, _))) => (*seat).keyboard_state.focused_surface == surface,
+        _ => false
+    };
+    Token { token: utils::random_token(),
+            app_id: pending.app_id.clone(),
+            surface: surface.map(|surface| surface::Handle::from_ptr(surface)),
+            seat: seat.map(|(seat, _)| seat::Handle::from_ptr(seat)),
+            serial: seat.map(|(_, serial)| serial),
+            focused,
+            created: utils::now() }
+}
+
+unsafe fn seat_from_resource(seat: &Resource<WlSeat>) -> Option<*mut wlr_seat> {
+    let client = wlr_seat_client_from_resource(seat.c_ptr() as _);
+    if client.is_null() || (*client).seat.is_null() {
+        None
+    } else {
+        Some((*client).seat)
+    }
+}
+
+fn activate(registry: &Rc<RefCell<Registry>>, token: &str, surface: Resource<WlSurface>) {
+    let view = unsafe { View::from_surface(wlr_surface_from_resource(surface.c_ptr() as _)) };
+    let view = match view {
+        Some(view) => view,
+        None => return
+    };
+    // NOTE Tokens can only be used once.
+    let (handler, token) = {
+        let mut registry = registry.borrow_mut();
+        expire(&mut registry.tokens);
+        let index = registry.tokens.iter().position(|other| other.token == token);
+        (registry.handler.clone(), index.map(|index| registry.tokens.remove(index)))
+    };
+    let honoured = match (token, compositor::handle()) {
+        (Some(token), Some(compositor)) => {
+            handler.borrow_mut().activate(compositor, view.clone(), &token)
+        },
+        _ => false
+    };
+    if !honoured {
+        wlr_log!(WLR_DEBUG, "Not activating {:?}, marking it urgent", view);
+        view.set_urgent(true);
+    }
+}
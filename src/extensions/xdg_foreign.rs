@@ -0,0 +1,473 @@
+//! Support for the xdg foreign protocols (`zxdg_exporter_v1`/`v2` and
+//! `zxdg_importer_v1`/`v2`), so that a client can parent its toplevels to a
+//! toplevel of another client (e.g. a portal showing a file chooser for the
+//! application that asked for it).
+//!
+//! Both versions are handled by this crate (see
+//! [`extensions`](../index.html)). One client exports its surface and gets a
+//! handle for it, which it passes to the other client; that client imports
+//! the handle and sets the parent of its own toplevels to the exported
+//! surface.
+//!
+//! When both surfaces are xdg shell toplevels, the parent of the child is set
+//! on the toplevel itself, so it shows up in `TopLevel::parent`. Either way
+//! the `Handler` is told about the new parent, and when it's unset again
+//! because the export or import went away.
+
+use std::{cell::RefCell, mem, ptr, rc::{Rc, Weak}};
+
+use libc;
+use wayland_sys::server::{signal::wl_signal_add, wl_resource, WAYLAND_SERVER_HANDLE};
+use wlroots_sys::{wayland_server::{NewResource, Resource, protocol::wl_surface::WlSurface},
+                  wlr_surface, wlr_surface_from_resource, wlr_surface_is_xdg_surface,
+                  wlr_surface_is_xdg_surface_v6, wlr_xdg_surface, wlr_xdg_surface_from_wlr_surface,
+                  wlr_xdg_surface_v6, wlr_xdg_surface_v6_from_wlr_surface,
+                  wlr_xdg_surface_role::WLR_XDG_SURFACE_ROLE_TOPLEVEL,
+                  wlr_xdg_surface_v6_role::WLR_XDG_SURFACE_V6_ROLE_TOPLEVEL};
+use wlroots_sys::protocols::{xdg_foreign_v1::server::{zxdg_exported_v1::{self, ZxdgExportedV1},
+                                                      zxdg_exporter_v1::{self, ZxdgExporterV1},
+                                                      zxdg_imported_v1::{self, ZxdgImportedV1},
+                                                      zxdg_importer_v1::{self, ZxdgImporterV1}},
+                             xdg_foreign_v2::server::{zxdg_exported_v2::{self, ZxdgExportedV2},
+                                                      zxdg_exporter_v2::{self, ZxdgExporterV2},
+                                                      zxdg_imported_v2::{self, ZxdgImportedV2},
+                                                      zxdg_importer_v2::{self, ZxdgImporterV2}}};
+
+use {compositor::{self, Compositor, CustomGlobal}, surface, utils::{self, HandleErr}};
+
+/// The version of the protocols that is implemented.
+const VERSION: u32 = 1;
+
+/// Handles the changes to the parents of toplevels.
+#[allow(unused_variables)]
+pub trait Handler {
+    /// Called when a client exported the surface, with the handle it got.
+    fn surface_exported(&mut self,
+                        compositor_handle: compositor::Handle,
+                        surface: surface::Handle,
+                        handle: &str) {
+    }
+
+    /// Called when a client set the parent of its toplevel to the surface
+    /// of another client, or when that parent was unset (`None`) again.
+    fn set_parent(&mut self,
+                  compositor_handle: compositor::Handle,
+                  child: surface::Handle,
+                  parent: Option<surface::Handle>) {
+    }
+}
+
+/// An exported surface that can be imported by its handle.
+struct Export {
+    handle: String,
+    surface: *mut wlr_surface,
+    /// The `zxdg_exported` resource, which is where the export goes away.
+    resource: *mut wl_resource,
+    destroy: Box<ExportedSurface>
+}
+
+#[derive(Clone)]
+enum ImportedResource {
+    V1(Resource<ZxdgImportedV1>),
+    V2(Resource<ZxdgImportedV2>)
+}
+
+impl ImportedResource {
+    fn c_ptr(&self) -> *mut wl_resource {
+        match *self {
+            ImportedResource::V1(ref resource) => resource.c_ptr(),
+            ImportedResource::V2(ref resource) => resource.c_ptr()
+        }
+    }
+
+    fn send_destroyed(&self) {
+        match *self {
+            ImportedResource::V1(ref resource) => {
+                resource.send(zxdg_imported_v1::Event::Destroyed)
+            },
+            ImportedResource::V2(ref resource) => {
+                resource.send(zxdg_imported_v2::Event::Destroyed)
+            }
+        }
+    }
+}
+
+/// A handle imported by a client, and the toplevels it parented to it.
+struct Import {
+    handle: String,
+    resource: ImportedResource,
+    children: Vec<surface::Handle>
+}
+
+/// A change to the parent of a toplevel, to tell the handler about.
+type ParentChange = (surface::Handle, Option<*mut wlr_surface>);
+
+struct Registry {
+    /// Kept apart so the handler can be called without borrowing the rest.
+    handler: Rc<RefCell<Box<Handler>>>,
+    exports: Vec<Export>,
+    imports: Vec<Import>
+}
+
+wayland_listener!(pub(crate) ExportedSurface, (*mut wl_resource, Weak<RefCell<Registry>>), [
+    destroy_listener => destroy_notify: |this: &mut ExportedSurface, _data: *mut libc::c_void,|
+    unsafe {
+        let resource = this.data.0;
+        if let Some(registry) = this.data.1.upgrade() {
+            unexport(&registry, resource);
+        }
+    };
+]);
+
+/// The xdg foreign globals, both versions of the exporter and the importer.
+///
+/// The globals are removed when this is dropped.
+pub struct Manager {
+    registry: Rc<RefCell<Registry>>,
+    exporter_v1: Option<CustomGlobal<ZxdgExporterV1>>,
+    importer_v1: Option<CustomGlobal<ZxdgImporterV1>>,
+    exporter_v2: Option<CustomGlobal<ZxdgExporterV2>>,
+    importer_v2: Option<CustomGlobal<ZxdgImporterV2>>
+}
+
+impl Manager {
+    /// Advertise the exporters and importers, telling the handler about the
+    /// parents set with them.
+    pub fn create(compositor: &mut Compositor, handler: Box<Handler>) -> Self {
+        let registry = Rc::new(RefCell::new(Registry { handler: Rc::new(RefCell::new(handler)),
+                                                       exports: Vec::new(),
+                                                       imports: Vec::new() }));
+        let exporter_v1 = {
+            let registry = registry.clone();
+            compositor.create_global(VERSION, move |resource, _| {
+                bind_exporter_v1(resource, registry.clone())
+            })
+        };
+        let importer_v1 = {
+            let registry = registry.clone();
+            compositor.create_global(VERSION, move |resource, _| {
+                bind_importer_v1(resource, registry.clone())
+            })
+        };
+        let exporter_v2 = {
+            let registry = registry.clone();
+            compositor.create_global(VERSION, move |resource, _| {
+                bind_exporter_v2(resource, registry.clone())
+            })
+        };
+        let importer_v2 = {
+            let registry = registry.clone();
+            compositor.create_global(VERSION, move |resource, _| {
+                bind_importer_v2(resource, registry.clone())
+            })
+        };
+        Manager { registry,
+                  exporter_v1: Some(exporter_v1),
+                  importer_v1: Some(importer_v1),
+                  exporter_v2: Some(exporter_v2),
+                  importer_v2: Some(importer_v2) }
+    }
+
+    /// Advertise the global like `create`, without boxing the handler
+    /// yourself.
+    pub fn create_with<H: Handler + 'static>(compositor: &mut Compositor, handler: H) -> Self {
+        Manager::create(compositor, Box::new(handler))
+    }
+
+    /// Get the handles of the exported surfaces.
+    pub fn exports(&self) -> Vec<(String, surface::Handle)> {
+        self.registry
+            .borrow()
+            .exports
+            .iter()
+            .filter_map(|export| unsafe {
+                surface_handle(export.surface).map(|surface| (export.handle.clone(), surface))
+            })
+            .collect()
+    }
+
+    /// Get the surface of another client the surface was parented to, if any.
+    pub fn parent_of(&self, surface: &surface::Handle) -> Option<surface::Handle> {
+        let registry = self.registry.borrow();
+        let import = registry.imports
+                             .iter()
+                             .find(|import| import.children.contains(surface))?;
+        let export = registry.exports.iter().find(|export| export.handle == import.handle)?;
+        unsafe { surface_handle(export.surface) }
+    }
+}
+
+impl Drop for Manager {
+    fn drop(&mut self) {
+        if let Some(global) = self.exporter_v1.take() {
+            global.destroy()
+        }
+        if let Some(global) = self.importer_v1.take() {
+            global.destroy()
+        }
+        if let Some(global) = self.exporter_v2.take() {
+            global.destroy()
+        }
+        if let Some(global) = self.importer_v2.take() {
+            global.destroy()
+        }
+        let exports = mem::replace(&mut self.registry.borrow_mut().exports, Vec::new());
+        for export in exports {
+            unsafe { remove_destroy_listener(&export) }
+        }
+    }
+}
+
+fn bind_exporter_v1(resource: NewResource<ZxdgExporterV1>, registry: Rc<RefCell<Registry>>) {
+    resource.implement(move |request, _| match request {
+        zxdg_exporter_v1::Request::Destroy => {},
+        zxdg_exporter_v1::Request::Export { id, surface } => {
+            let registry = registry.clone();
+            let exported = id.implement(|_, _| {},
+                                        Some(move |exported: Resource<ZxdgExportedV1>| unsafe {
+                                            unexport(&registry, exported.c_ptr())
+                                        }));
+            let handle = unsafe { export(&registry, exported.c_ptr(), &surface) };
+            exported.send(zxdg_exported_v1::Event::Handle { handle })
+        }
+    },
+                       None::<fn(_)>);
+}
+
+fn bind_exporter_v2(resource: NewResource<ZxdgExporterV2>, registry: Rc<RefCell<Registry>>) {
+    resource.implement(move |request, exporter: Resource<ZxdgExporterV2>| match request {
+        zxdg_exporter_v2::Request::Destroy => {},
+        zxdg_exporter_v2::Request::ExportToplevel { id, surface } => {
+            if unsafe { !is_toplevel(wlr_surface_from_resource(surface.c_ptr() as *mut _)) } {
+                exporter.post_error(zxdg_exporter_v2::Error::InvalidSurface as u32,
+                                    "surface must be an xdg_toplevel".into());
+                return
+            }
+            let registry = registry.clone();
+            let exported = id.implement(|_, _| {},
+                                        Some(move |exported: Resource<ZxdgExportedV2>| unsafe {
+                                            unexport(&registry, exported.c_ptr())
+                                        }));
+            let handle = unsafe { export(&registry, exported.c_ptr(), &surface) };
+            exported.send(zxdg_exported_v2::Event::Handle { handle })
+        }
+    },
+                       None::<fn(_)>);
+}
+
+fn bind_importer_v1(resource: NewResource<ZxdgImporterV1>, registry: Rc<RefCell<Registry>>) {
+    resource.implement(move |request, _| match request {
+        zxdg_importer_v1::Request::Destroy => {},
+        zxdg_importer_v1::Request::Import { id, handle } => {
+            let (requests, destroyed) = (registry.clone(), registry.clone());
+            let imported = id.implement(move |request, imported: Resource<_>| match request {
+                zxdg_imported_v1::Request::Destroy => {},
+                zxdg_imported_v1::Request::SetParentOf { surface } => unsafe {
+                    set_parent_of(&requests, imported.c_ptr(), &surface)
+                }
+            },
+                                        Some(move |imported: Resource<_>| unsafe {
+                                            unimport(&destroyed, imported.c_ptr())
+                                        }));
+            import(&registry, handle, ImportedResource::V1(imported))
+        }
+    },
+                       None::<fn(_)>);
+}
+
+fn bind_importer_v2(resource: NewResource<ZxdgImporterV2>, registry: Rc<RefCell<Registry>>) {
+    resource.implement(move |request, _| match request {
+        zxdg_importer_v2::Request::Destroy => {},
+        zxdg_importer_v2::Request::ImportToplevel { id, handle } => {
+            let (requests, destroyed) = (registry.clone(), registry.clone());
+            let imported = id.implement(move |request, imported: Resource<ZxdgImportedV2>| {
+                match request {
+                    zxdg_imported_v2::Request::Destroy => {},
+                    zxdg_imported_v2::Request::SetParentOf { surface } => unsafe {
+                        let child = wlr_surface_from_resource(surface.c_ptr() as *mut _);
+                        if !is_toplevel(child) {
+                            imported.post_error(zxdg_imported_v2::Error::InvalidSurface as u32,
+                                                "surface must be an xdg_toplevel".into());
+                            return
+                        }
+                        set_parent_of(&requests, imported.c_ptr(), &surface)
+                    }
+                }
+            },
+                                        Some(move |imported: Resource<_>| unsafe {
+                                            unimport(&destroyed, imported.c_ptr())
+                                        }));
+            import(&registry, handle, ImportedResource::V2(imported))
+        }
+    },
+                       None::<fn(_)>);
+}
+
+/// Export the surface of the `zxdg_exported` resource, returning its handle.
+unsafe fn export(registry: &Rc<RefCell<Registry>>,
+                 resource: *mut wl_resource,
+                 surface: &Resource<WlSurface>)
+                 -> String {
+    // NOTE The handle must not be guessable, so that only the client the
+    // exporter gave it to can import the surface.
+    let handle = utils::random_token();
+    let surface = wlr_surface_from_resource(surface.c_ptr() as *mut _);
+    let mut destroy = ExportedSurface::new((resource, Rc::downgrade(registry)));
+    wl_signal_add(&mut (*surface).events.destroy as *mut _ as _,
+                  destroy.destroy_listener() as *mut _ as _);
+    registry.borrow_mut().exports.push(Export { handle: handle.clone(),
+                                                surface,
+                                                resource,
+                                                destroy });
+    if let (Some(compositor), Some(surface)) = (compositor::handle(), surface_handle(surface)) {
+        let handler = registry.borrow().handler.clone();
+        handler.borrow_mut().surface_exported(compositor, surface, &handle);
+    }
+    handle
+}
+
+/// Remove the export, when its resource or its surface is destroyed.
+///
+/// The imports of its handle are destroyed along with it.
+unsafe fn unexport(registry: &Rc<RefCell<Registry>>, resource: *mut wl_resource) {
+    let (export, imports) = {
+        let mut registry = registry.borrow_mut();
+        let index = match registry.exports.iter().position(|export| export.resource == resource) {
+            Some(index) => index,
+            None => return
+        };
+        let export = registry.exports.remove(index);
+        let (imports, rest) = mem::replace(&mut registry.imports, Vec::new())
+            .into_iter()
+            .partition::<Vec<_>, _>(|import| import.handle == export.handle);
+        registry.imports = rest;
+        (export, imports)
+    };
+    remove_destroy_listener(&export);
+    let mut changes = Vec::new();
+    for import in imports {
+        import.resource.send_destroyed();
+        changes.extend(import.children.into_iter().map(|child| (child, None)));
+    }
+    apply(registry, changes);
+}
+
+unsafe fn remove_destroy_listener(export: &Export) {
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_list_remove,
+                  &mut (*export.destroy.destroy_listener()).link as *mut _ as _);
+}
+
+/// Import the handle, which is destroyed right away if nothing was exported
+/// with it.
+fn import(registry: &Rc<RefCell<Registry>>, handle: String, resource: ImportedResource) {
+    let exported = registry.borrow().exports.iter().any(|export| export.handle == handle);
+    if !exported {
+        resource.send_destroyed();
+        return
+    }
+    registry.borrow_mut().imports.push(Import { handle, resource, children: Vec::new() });
+}
+
+/// Forget about the imported handle, unsetting the parent of its children.
+unsafe fn unimport(registry: &Rc<RefCell<Registry>>, resource: *mut wl_resource) {
+    let import = {
+        let mut registry = registry.borrow_mut();
+        match registry.imports.iter().position(|import| import.resource.c_ptr() == resource) {
+            Some(index) => registry.imports.remove(index),
+            None => return
+        }
+    };
+    apply(registry, import.children.into_iter().map(|child| (child, None)).collect());
+}
+
+/// Parent the surface to the surface exported with the imported handle.
+unsafe fn set_parent_of(registry: &Rc<RefCell<Registry>>,
+                        resource: *mut wl_resource,
+                        child: &Resource<WlSurface>) {
+    let child = match surface_handle(wlr_surface_from_resource(child.c_ptr() as *mut _)) {
+        Some(child) => child,
+        None => return
+    };
+    let parent = {
+        let mut registry = registry.borrow_mut();
+        let Registry { ref exports, ref mut imports, .. } = *registry;
+        let import = match imports.iter_mut().find(|import| import.resource.c_ptr() == resource) {
+            Some(import) => import,
+            None => return
+        };
+        let parent = match exports.iter().find(|export| export.handle == import.handle) {
+            Some(export) => export.surface,
+            None => return
+        };
+        if !import.children.contains(&child) {
+            import.children.push(child.clone());
+        }
+        parent
+    };
+    apply(registry, vec![(child, Some(parent))]);
+}
+
+/// Set the parents of the toplevels, and tell the handler about them.
+unsafe fn apply(registry: &Rc<RefCell<Registry>>, changes: Vec<ParentChange>) {
+    let compositor = match compositor::handle() {
+        Some(compositor) => compositor,
+        None => return
+    };
+    let handler = registry.borrow().handler.clone();
+    for (child, parent) in changes {
+        // NOTE The child could have been destroyed since it was parented.
+        match child.run(|_| ()) {
+            Err(HandleErr::AlreadyDropped) => continue,
+            _ => set_toplevel_parent(child.as_ptr(), parent)
+        }
+        let parent = parent.and_then(|parent| surface_handle(parent));
+        handler.borrow_mut().set_parent(compositor.clone(), child, parent);
+    }
+}
+
+/// Set the parent of the xdg shell toplevel to the other toplevel, if they
+/// both are toplevels of the same version of xdg shell.
+unsafe fn set_toplevel_parent(child: *mut wlr_surface, parent: Option<*mut wlr_surface>) {
+    if wlr_surface_is_xdg_surface(child) {
+        let child = wlr_xdg_surface_from_wlr_surface(child);
+        if (*child).role != WLR_XDG_SURFACE_ROLE_TOPLEVEL {
+            return
+        }
+        let parent = parent.filter(|parent| wlr_surface_is_xdg_surface(*parent))
+                           .map(|parent| wlr_xdg_surface_from_wlr_surface(parent))
+                           .filter(|parent| (**parent).role == WLR_XDG_SURFACE_ROLE_TOPLEVEL)
+                           .unwrap_or(ptr::null_mut::<wlr_xdg_surface>());
+        (*(*child).__bindgen_anon_1.toplevel).parent = parent;
+    } else if wlr_surface_is_xdg_surface_v6(child) {
+        let child = wlr_xdg_surface_v6_from_wlr_surface(child);
+        if (*child).role != WLR_XDG_SURFACE_V6_ROLE_TOPLEVEL {
+            return
+        }
+        let parent = parent.filter(|parent| wlr_surface_is_xdg_surface_v6(*parent))
+                           .map(|parent| wlr_xdg_surface_v6_from_wlr_surface(parent))
+                           .filter(|parent| (**parent).role == WLR_XDG_SURFACE_V6_ROLE_TOPLEVEL)
+                           .unwrap_or(ptr::null_mut::<wlr_xdg_surface_v6>());
+        (*(*child).__bindgen_anon_1.toplevel).parent = parent;
+    }
+}
+
+unsafe fn is_toplevel(surface: *mut wlr_surface) -> bool {
+    if surface.is_null() {
+        return false
+    }
+    if wlr_surface_is_xdg_surface(surface) {
+        (*wlr_xdg_surface_from_wlr_surface(surface)).role == WLR_XDG_SURFACE_ROLE_TOPLEVEL
+    } else if wlr_surface_is_xdg_surface_v6(surface) {
+        (*wlr_xdg_surface_v6_from_wlr_surface(surface)).role == WLR_XDG_SURFACE_V6_ROLE_TOPLEVEL
+    } else {
+        false
+    }
+}
+
+unsafe fn surface_handle(surface: *mut wlr_surface) -> Option<surface::Handle> {
+    if surface.is_null() || (*surface).data.is_null() {
+        return None
+    }
+    Some(surface::Handle::from_ptr(surface))
+}
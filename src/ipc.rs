@@ -0,0 +1,466 @@
+//! A sway/i3-style IPC server, so external tools can query and control the
+//! compositor.
+//!
+//! The server listens on a Unix socket, whose path is given by
+//! `Server::socket_path`. The compositor can hand it to the programs it
+//! spawns by exporting it as `SOCKET_ENV` in their environment. Every request
+//! is a single line of JSON, and gets a single line of JSON back:
+//!
+//! ```text
+//! > {"command": "list_outputs"}
+//! < {"success": true, "result": [{"name": "DP-1"}]}
+//! > {"command": "focus_view", "id": 3}
+//! < {"success": false, "error": "No such view"}
+//! > {"command": "run", "name": "reload", "args": {"path": "config.toml"}}
+//! < {"success": true, "result": null}
+//! ```
+//!
+//! What the commands do is up to the `ipc::Handler` of the compositor.
+//!
+//! After a `subscribe` request the connection also gets every
+//! `accessibility::Event`, as a line of JSON between the responses:
+//!
+//! ```text
+//! > {"command": "subscribe"}
+//! < {"success": true, "result": null}
+//! < {"event": "view_focused", "title": "Terminal", "app_id": "termite"}
+//! ```
+
+use std::{cell::RefCell, collections::HashMap, env, fs, io::{self, Read, Write}, panic, process,
+          os::unix::{io::{AsRawFd, RawFd}, net::{UnixListener, UnixStream}},
+          path::{Path, PathBuf}, ptr, rc::Rc};
+
+use libc::{c_int, c_void};
+use serde_json::{self, Value};
+use wayland_sys::server::{wl_event_loop, wl_event_source, WAYLAND_SERVER_HANDLE};
+
+use {accessibility, compositor,
+     utils::{self, WL_EVENT_ERROR, WL_EVENT_HANGUP, WL_EVENT_READABLE, WL_EVENT_WRITABLE}};
+
+/// The longest request a connection can send, anything longer closes it.
+const MAX_REQUEST: usize = 1 << 16;
+
+/// How many bytes of responses and events can be waiting for a connection
+/// before it's considered stuck and closed, so a subscriber that stops reading
+/// can't make the compositor buffer events forever.
+const MAX_WRITE_BUFFER: usize = 1 << 20;
+
+/// Environment variable clients look for the socket path in.
+///
+/// The server doesn't set it, since changing the environment of the process
+/// isn't safe while other threads may read it. Set it in the environment of
+/// the clients instead, e.g. with `Command::env`.
+pub const SOCKET_ENV: &str = "WLROOTS_IPC_SOCK";
+
+/// Requests that can be sent to the compositor.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    /// List the outputs of the compositor.
+    ListOutputs,
+    /// List the views (i.e. windows) of the compositor.
+    ListViews,
+    /// Focus the view with the id.
+    FocusView { id: u64 },
+    /// List the workspaces of the compositor (see
+    /// `desktop::workspaces::Workspaces::info`).
+    ListWorkspaces,
+    /// Send the accessibility events to this connection from now on.
+    Subscribe,
+    /// Run a compositor specific command.
+    Run {
+        name: String,
+        #[serde(default)]
+        args: Value
+    }
+}
+
+/// The reply to a `Request`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Response {
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>
+}
+
+/// The result of handling a request, either a value to send back or an error
+/// message.
+pub type CommandResult = Result<Value, String>;
+
+/// Handles the requests sent over IPC.
+///
+/// Requests that aren't handled are answered with an error.
+#[allow(unused_variables)]
+pub trait Handler {
+    /// Callback that is triggered when a client asks for the outputs.
+    fn list_outputs(&mut self, compositor_handle: compositor::Handle) -> CommandResult {
+        Err("list_outputs is not supported".into())
+    }
+
+    /// Callback that is triggered when a client asks for the views.
+    fn list_views(&mut self, compositor_handle: compositor::Handle) -> CommandResult {
+        Err("list_views is not supported".into())
+    }
+
+    /// Callback that is triggered when a client wants to focus a view.
+    fn focus_view(&mut self, compositor_handle: compositor::Handle, id: u64) -> CommandResult {
+        Err("focus_view is not supported".into())
+    }
+
+    /// Callback that is triggered when a client asks for the workspaces.
+    fn list_workspaces(&mut self, compositor_handle: compositor::Handle) -> CommandResult {
+        Err("list_workspaces is not supported".into())
+    }
+
+    /// Callback that is triggered when a client runs a compositor specific
+    /// command.
+    fn run(&mut self,
+           compositor_handle: compositor::Handle,
+           name: &str,
+           args: Value)
+           -> CommandResult {
+        Err(format!("Unknown command {}", name))
+    }
+}
+
+impl Response {
+    fn from_result(result: CommandResult) -> Self {
+        match result {
+            Ok(result) => Response { success: true, result: Some(result), error: None },
+            Err(error) => Response { success: false, result: None, error: Some(error) }
+        }
+    }
+}
+
+/// A connected IPC client.
+struct Connection {
+    stream: UnixStream,
+    source: *mut wl_event_source,
+    /// Bytes read that don't make a complete request yet.
+    read_buffer: Vec<u8>,
+    /// Responses that couldn't be written without blocking yet.
+    write_buffer: Vec<u8>,
+    /// Whether the client asked for the accessibility events.
+    subscribed: bool
+}
+
+/// The accessibility events that still have to be sent to the subscribed
+/// connections.
+///
+/// They're sent once the event loop is idle, because events can be published
+/// while the server is handling a request.
+struct EventQueue {
+    events: Vec<accessibility::Event>,
+    /// The idle source that sends them, if one is scheduled.
+    idle: *mut wl_event_source
+}
+
+struct ServerState {
+    listener: UnixListener,
+    event_loop: *mut wl_event_loop,
+    handler: Box<Handler>,
+    connections: HashMap<RawFd, Connection>,
+    events: Rc<RefCell<EventQueue>>
+}
+
+/// The IPC server, stops listening when dropped.
+pub struct Server {
+    path: PathBuf,
+    source: *mut wl_event_source,
+    subscription: Option<accessibility::Subscription>,
+    state: Box<ServerState>
+}
+
+impl Server {
+    /// Start listening for IPC clients on the event loop.
+    ///
+    /// The socket is created in `$XDG_RUNTIME_DIR`, named after the Wayland
+    /// socket of the compositor.
+    pub(crate) unsafe fn new(event_loop: *mut wl_event_loop,
+                             socket_name: &str,
+                             handler: Box<Handler>)
+                             -> io::Result<Self> {
+        let runtime_dir = env::var_os("XDG_RUNTIME_DIR").ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "XDG_RUNTIME_DIR is not set")
+        })?;
+        let path = Path::new(&runtime_dir).join(format!("wlroots-ipc.{}.{}.sock",
+                                                        socket_name,
+                                                        process::id()));
+        // NOTE A stale socket from a crashed compositor with the same pid.
+        let _ = fs::remove_file(&path);
+        let listener = UnixListener::bind(&path)?;
+        listener.set_nonblocking(true)?;
+        let events = Rc::new(RefCell::new(EventQueue { events: Vec::new(),
+                                                       idle: ptr::null_mut() }));
+        let mut state = Box::new(ServerState { listener,
+                                               event_loop,
+                                               handler,
+                                               connections: HashMap::new(),
+                                               events: events.clone() });
+        let source = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                   wl_event_loop_add_fd,
+                                   event_loop,
+                                   state.listener.as_raw_fd(),
+                                   WL_EVENT_READABLE,
+                                   accept_notify,
+                                   &mut *state as *mut ServerState as *mut c_void);
+        if source.is_null() {
+            let _ = fs::remove_file(&path);
+            return Err(io::Error::new(io::ErrorKind::Other, "Could not add IPC socket to event loop"))
+        }
+        let state_ptr = &mut *state as *mut ServerState as *mut c_void;
+        let subscription = accessibility::subscribe(move |event| {
+            let mut queue = events.borrow_mut();
+            queue.events.push(event.clone());
+            if queue.idle.is_null() {
+                queue.idle = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                           wl_event_loop_add_idle,
+                                           event_loop,
+                                           events_idle,
+                                           state_ptr);
+            }
+        });
+        wlr_log!(WLR_DEBUG, "Listening for IPC clients on {}", path.display());
+        Ok(Server { path,
+                    source,
+                    subscription: Some(subscription),
+                    state })
+    }
+
+    /// Get the path of the socket IPC clients connect to.
+    ///
+    /// This is what clients expect to find in `SOCKET_ENV`.
+    pub fn socket_path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.subscription.take();
+        unsafe {
+            let idle = self.state.events.borrow().idle;
+            if !idle.is_null() {
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_event_source_remove, idle);
+            }
+            for (_, connection) in self.state.connections.drain() {
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_event_source_remove, connection.source);
+            }
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_event_source_remove, self.source);
+        }
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+impl ServerState {
+    unsafe fn accept(&mut self) {
+        loop {
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => return,
+                Err(err) => {
+                    wlr_log!(WLR_ERROR, "Could not accept IPC client: {}", err);
+                    return
+                }
+            };
+            if let Err(err) = stream.set_nonblocking(true) {
+                wlr_log!(WLR_ERROR, "Could not set up IPC client: {}", err);
+                continue
+            }
+            let fd = stream.as_raw_fd();
+            let source = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                       wl_event_loop_add_fd,
+                                       self.event_loop,
+                                       fd,
+                                       WL_EVENT_READABLE,
+                                       connection_notify,
+                                       self as *mut ServerState as *mut c_void);
+            if source.is_null() {
+                wlr_log!(WLR_ERROR, "Could not add IPC client to event loop");
+                continue
+            }
+            self.connections.insert(fd, Connection { stream,
+                                                     source,
+                                                     read_buffer: Vec::new(),
+                                                     write_buffer: Vec::new(),
+                                                     subscribed: false });
+        }
+    }
+
+    /// Handle activity on the connection, returning false if it should be
+    /// closed.
+    unsafe fn handle_connection(&mut self, fd: RawFd, mask: u32) -> bool {
+        let ServerState { ref mut connections, ref mut handler, .. } = *self;
+        let connection = match connections.get_mut(&fd) {
+            Some(connection) => connection,
+            None => return false
+        };
+        if mask & WL_EVENT_READABLE != 0 {
+            let mut buffer = [0u8; 4096];
+            let mut closed = false;
+            loop {
+                match connection.stream.read(&mut buffer) {
+                    Ok(0) => {
+                        // NOTE The client may have sent its last requests
+                        // before closing its end, they're still answered.
+                        closed = true;
+                        break
+                    },
+                    Ok(read) => {
+                        connection.read_buffer.extend_from_slice(&buffer[..read]);
+                        // NOTE The rest is read once these requests are handled,
+                        // as the fd is still readable.
+                        if connection.read_buffer.len() > MAX_REQUEST {
+                            break
+                        }
+                    },
+                    Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(_) => return false
+                }
+            }
+            while let Some(end) = connection.read_buffer.iter().position(|&byte| byte == b'\n') {
+                let line: Vec<u8> = connection.read_buffer.drain(..end + 1).collect();
+                let response = dispatch(&mut **handler,
+                                        &line[..end],
+                                        &mut connection.subscribed);
+                let mut response = serde_json::to_vec(&response)
+                    .expect("Could not serialize IPC response");
+                response.push(b'\n');
+                connection.write_buffer.extend_from_slice(&response);
+            }
+            if connection.read_buffer.len() > MAX_REQUEST {
+                wlr_log!(WLR_INFO, "Closing IPC client that sent a request that's too long");
+                return false
+            }
+            if closed {
+                connection.flush();
+                return false
+            }
+        } else if mask & (WL_EVENT_HANGUP | WL_EVENT_ERROR) != 0 {
+            return false
+        }
+        connection.flush()
+    }
+
+    /// Send the queued events to the subscribed connections, closing the
+    /// ones that broke.
+    unsafe fn send_events(&mut self) {
+        let events = {
+            let mut queue = self.events.borrow_mut();
+            // NOTE Idle sources are removed by the event loop once they are
+            // dispatched.
+            queue.idle = ptr::null_mut();
+            queue.events.drain(..).collect::<Vec<_>>()
+        };
+        let mut lines = Vec::new();
+        for event in &events {
+            lines.extend(serde_json::to_vec(event).expect("Could not serialize IPC event"));
+            lines.push(b'\n');
+        }
+        let broken = self.connections
+                         .iter_mut()
+                         .filter(|&(_, ref connection)| connection.subscribed)
+                         .filter_map(|(&fd, connection)| {
+                                         connection.write_buffer.extend_from_slice(&lines);
+                                         if connection.flush() { None } else { Some(fd) }
+                                     })
+                         .collect::<Vec<_>>();
+        for fd in broken {
+            self.close(fd);
+        }
+    }
+
+    unsafe fn close(&mut self, fd: RawFd) {
+        if let Some(connection) = self.connections.remove(&fd) {
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_event_source_remove, connection.source);
+        }
+    }
+}
+
+impl Connection {
+    /// Write as much of the pending responses as possible, only waiting for
+    /// the socket to become writable if there's more left.
+    ///
+    /// Returns false if the connection broke, or has more than
+    /// `MAX_WRITE_BUFFER` bytes left to write.
+    unsafe fn flush(&mut self) -> bool {
+        while !self.write_buffer.is_empty() {
+            match self.stream.write(&self.write_buffer) {
+                Ok(0) => return false,
+                Ok(written) => {
+                    self.write_buffer.drain(..written);
+                },
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => return false
+            }
+        }
+        if self.write_buffer.len() > MAX_WRITE_BUFFER {
+            wlr_log!(WLR_INFO, "Closing IPC client that stopped reading");
+            return false
+        }
+        let mask = if self.write_buffer.is_empty() {
+            WL_EVENT_READABLE
+        } else {
+            WL_EVENT_READABLE | WL_EVENT_WRITABLE
+        };
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_event_source_fd_update, self.source, mask);
+        true
+    }
+}
+
+/// Parse the request and run the matching callback of the handler.
+fn dispatch(handler: &mut Handler, request: &[u8], subscribed: &mut bool) -> Response {
+    let request: Request = match serde_json::from_slice(request) {
+        Ok(request) => request,
+        Err(err) => return Response::from_result(Err(format!("Invalid request: {}", err)))
+    };
+    let compositor = match compositor::handle() {
+        Some(handle) => handle,
+        None => return Response::from_result(Err("The compositor is not running".into()))
+    };
+    let result = match request {
+        Request::ListOutputs => handler.list_outputs(compositor),
+        Request::ListViews => handler.list_views(compositor),
+        Request::FocusView { id } => handler.focus_view(compositor, id),
+        Request::ListWorkspaces => handler.list_workspaces(compositor),
+        Request::Subscribe => {
+            *subscribed = true;
+            Ok(Value::Null)
+        },
+        Request::Run { name, args } => handler.run(compositor, &name, args)
+    };
+    Response::from_result(result)
+}
+
+unsafe extern "C" fn accept_notify(_fd: c_int, _mask: u32, data: *mut c_void) -> c_int {
+    let state = &mut *(data as *mut ServerState);
+    wlr_span!("ipc::accept");
+    state.accept();
+    0
+}
+
+unsafe extern "C" fn events_idle(data: *mut c_void) {
+    let state = &mut *(data as *mut ServerState);
+    wlr_span!("ipc::events");
+    let res = panic::catch_unwind(panic::AssertUnwindSafe(|| state.send_events()));
+    utils::handle_unwind(res);
+}
+
+unsafe extern "C" fn connection_notify(fd: c_int, mask: u32, data: *mut c_void) -> c_int {
+    let state = &mut *(data as *mut ServerState);
+    wlr_span!("ipc::connection");
+    let res = panic::catch_unwind(panic::AssertUnwindSafe(|| state.handle_connection(fd, mask)));
+    match res {
+        Ok(true) => {},
+        Ok(false) => state.close(fd),
+        Err(err) => {
+            state.close(fd);
+            utils::handle_unwind::<()>(Err(err));
+        }
+    }
+    0
+}
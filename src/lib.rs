@@ -6,6 +6,25 @@
 #[macro_use]
 extern crate bitflags;
 extern crate vsprintf;
+#[cfg(feature = "log")]
+extern crate log;
+#[cfg(feature = "tracing")]
+extern crate tracing;
+#[cfg(any(feature = "config", feature = "ipc"))]
+extern crate serde;
+#[cfg(any(feature = "config", feature = "ipc"))]
+#[macro_use]
+extern crate serde_derive;
+#[cfg(feature = "ipc")]
+extern crate serde_json;
+#[cfg(feature = "dbus")]
+extern crate zbus;
+#[cfg(feature = "dbus")]
+extern crate zvariant;
+#[cfg(feature = "testing")]
+extern crate wayland_client;
+#[cfg(feature = "wallpaper")]
+extern crate image;
 #[macro_use]
 pub extern crate wlroots_sys;
 extern crate wlroots_dehandle;
@@ -30,13 +49,37 @@ mod types;
 pub mod extensions;
 #[cfg(feature = "unstable")]
 pub mod render;
-pub mod utils;
 #[cfg(feature = "unstable")]
+pub mod animation;
+pub mod utils;
+mod error;
+mod version;
+#[cfg(all(feature = "unstable", feature = "xwayland"))]
 pub mod xwayland;
 #[cfg(feature = "unstable")]
+pub mod view;
+#[cfg(feature = "unstable")]
+pub mod accessibility;
+#[cfg(feature = "unstable")]
+pub mod debug;
+#[cfg(feature = "desktop")]
+pub mod desktop;
+#[cfg(feature = "unstable")]
 pub mod backend;
+#[cfg(feature = "config")]
+pub mod config;
+#[cfg(feature = "ipc")]
+pub mod ipc;
+#[cfg(feature = "dbus")]
+pub mod dbus;
+#[cfg(feature = "screencast")]
+pub mod screencast;
+#[cfg(feature = "testing")]
+pub mod testing;
 
 pub use types::*;
+pub use error::Error;
+pub use version::{capabilities, version, Capabilities, Version};
 
 #[cfg(feature = "unstable")]
 pub use wlroots_sys::{wlr_keyboard_modifiers as KeyboardModifiers,
@@ -74,6 +74,37 @@ macro_rules! wlr_log {
     }}
 }
 
+/// Enters a `tracing` span for the rest of the enclosing scope.
+///
+/// The span has an empty `id` field that can be filled in with
+/// `wlr_trace_id!` once the handle of the resource is known.
+///
+/// Does nothing if the `tracing` feature is not enabled.
+macro_rules! wlr_span {
+    ($name: expr) => {
+        #[cfg(feature = "tracing")]
+        let _span = ::tracing::trace_span!("wlroots_callback",
+                                           callback = $name,
+                                           id = ::tracing::field::Empty);
+        #[cfg(feature = "tracing")]
+        let _enter = _span.enter();
+    }
+}
+
+/// Records the id of the resource being dispatched to on the current
+/// `tracing` span.
+///
+/// Does nothing (and doesn't evaluate the argument) if the `tracing` feature
+/// is not enabled.
+macro_rules! wlr_trace_id {
+    ($id: expr) => {
+        #[cfg(feature = "tracing")]
+        {
+            ::tracing::Span::current().record("id", &$id);
+        }
+    }
+}
+
 /// Defines a new struct that contains a variable number of listeners that
 /// will trigger unsafe user-defined callbacks.
 ///
@@ -144,7 +175,82 @@ macro_rules! wlr_log {
 ///
 /// Second, this macro doesn't protect against the stored data being unsized.
 /// Passing a pointer of unsized data to C is UB, don't do it.
+///
+/// # Generic listeners
+/// The struct can also take a type parameter (e.g. `Listener<H: Handler>`),
+/// so the data can hold a handler of any type instead of a `Box<Handler>`.
+/// The listeners are then generated for each type the struct is used with,
+/// and call the handler without going through a vtable.
 macro_rules! wayland_listener {
+    ($pub: vis $struct_name: ident<$param: ident: $bound: path>, $data: ty, $([
+        $($listener: ident => $listener_func: ident :
+          |$($func_arg:ident: $func_type:ty,)*| unsafe $body: block;)*])+) => {
+        #[repr(C)]
+        #[doc(hidden)]
+        $pub struct $struct_name<$param: $bound> {
+            data: $data,
+            $($($listener: $crate::wlroots_sys::wl_listener),*)*
+        }
+
+        impl<$param: $bound> $struct_name<$param> {
+            pub(crate) fn new(data: $data) -> Box<Self> {
+                Self::init(Box::new(Self::uninit(data)))
+            }
+
+            /// Like `new`, reusing an allocation from the pool if it has one.
+            #[allow(dead_code)]
+            pub(crate) fn new_in(pool: &$crate::utils::Pool<Self>, data: $data) -> Box<Self> {
+                Self::init(pool.boxed(Self::uninit(data)))
+            }
+
+            fn uninit(data: $data) -> Self {
+                $struct_name {
+                    data,
+                    // NOTE Rationale for zeroed memory:
+                    // * The listeners are only initialized once they're boxed, see `init`
+                    // * The list is initialized by Wayland, which doesn't "drop"
+                    $($($listener: unsafe { ::std::mem::zeroed() }),*)*
+                }
+            }
+
+            /// Initialize the listeners where they'll stay, as an unconnected
+            /// listener's link points to itself.
+            fn init(mut this: Box<Self>) -> Box<Self> {
+                use $crate::wlroots_sys::server::WAYLAND_SERVER_HANDLE;
+                unsafe {
+                    $($(
+                        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                      wl_list_init,
+                                      &mut this.$listener.link as *mut _ as _);
+                        this.$listener.notify = Some($struct_name::<$param>::$listener_func);
+                    )*)*
+                }
+                this
+            }
+
+            $($(pub(crate) unsafe extern "C" fn $listener(&mut self)
+                                                   -> *mut $crate::wlroots_sys::wl_listener {
+                &mut self.$listener as *mut _
+            })*)*
+
+            $($(pub(crate) unsafe extern "C" fn $listener_func(listener:
+                                                        *mut $crate::wlroots_sys::wl_listener,
+                                                        data: *mut $crate::libc::c_void) {
+                let manager: &mut $struct_name<$param> =
+                    &mut (*container_of!(listener, $struct_name<$param>, $listener));
+                wlr_span!(concat!(stringify!($struct_name), "::", stringify!($listener)));
+                let _watchdog = $crate::utils::watchdog::Callback::enter(
+                    concat!(stringify!($struct_name), "::", stringify!($listener)));
+                $crate::utils::handle_listener_unwind(
+                    ::std::panic::catch_unwind(
+                        ::std::panic::AssertUnwindSafe(|| {
+                            (|$($func_arg: $func_type,)*| { $body })(manager, data)
+                        })),
+                    concat!(stringify!($struct_name), "::", stringify!($listener)),
+                    listener);
+            })*)*
+        }
+    };
     ($pub: vis $struct_name: ident, $data: ty, $([
         $($listener: ident => $listener_func: ident :
           |$($func_arg:ident: $func_type:ty,)*| unsafe $body: block;)*])+) => {
@@ -156,23 +262,39 @@ macro_rules! wayland_listener {
         }
 
         impl $struct_name {
-            pub(crate) fn new(data: $data) -> Box<$struct_name> {
-                use $crate::wlroots_sys::server::WAYLAND_SERVER_HANDLE;
-                Box::new($struct_name {
+            pub(crate) fn new(data: $data) -> Box<Self> {
+                Self::init(Box::new(Self::uninit(data)))
+            }
+
+            /// Like `new`, reusing an allocation from the pool if it has one.
+            #[allow(dead_code)]
+            pub(crate) fn new_in(pool: &$crate::utils::Pool<Self>, data: $data) -> Box<Self> {
+                Self::init(pool.boxed(Self::uninit(data)))
+            }
+
+            fn uninit(data: $data) -> Self {
+                $struct_name {
                     data,
-                    $($($listener: unsafe {
-                        // NOTE Rationale for zeroed memory:
-                        // * Need to pass a pointer to wl_list_init
-                        // * The list is initialized by Wayland, which doesn't "drop"
-                        // * The listener is written to without dropping any of the data
-                        let mut listener: $crate::wlroots_sys::wl_listener = ::std::mem::zeroed();
+                    // NOTE Rationale for zeroed memory:
+                    // * The listeners are only initialized once they're boxed, see `init`
+                    // * The list is initialized by Wayland, which doesn't "drop"
+                    $($($listener: unsafe { ::std::mem::zeroed() }),*)*
+                }
+            }
+
+            /// Initialize the listeners where they'll stay, as an unconnected
+            /// listener's link points to itself.
+            fn init(mut this: Box<Self>) -> Box<Self> {
+                use $crate::wlroots_sys::server::WAYLAND_SERVER_HANDLE;
+                unsafe {
+                    $($(
                         ffi_dispatch!(WAYLAND_SERVER_HANDLE,
                                       wl_list_init,
-                                      &mut listener.link as *mut _ as _);
-                        ::std::ptr::write(&mut listener.notify, Some($struct_name::$listener_func));
-                        listener
-                    }),*)*
-                })
+                                      &mut this.$listener.link as *mut _ as _);
+                        this.$listener.notify = Some($struct_name::$listener_func);
+                    )*)*
+                }
+                this
             }
 
             $($(pub(crate) unsafe extern "C" fn $listener(&mut self)
@@ -186,11 +308,16 @@ macro_rules! wayland_listener {
                 let manager: &mut $struct_name = &mut (*container_of!(listener,
                                                                       $struct_name,
                                                                       $listener));
-                $crate::utils::handle_unwind(
+                wlr_span!(concat!(stringify!($struct_name), "::", stringify!($listener)));
+                let _watchdog = $crate::utils::watchdog::Callback::enter(
+                    concat!(stringify!($struct_name), "::", stringify!($listener)));
+                $crate::utils::handle_listener_unwind(
                     ::std::panic::catch_unwind(
                         ::std::panic::AssertUnwindSafe(|| {
                             (|$($func_arg: $func_type,)*| { $body })(manager, data)
-                        })));
+                        })),
+                    concat!(stringify!($struct_name), "::", stringify!($listener)),
+                    listener);
             })*)*
         }
     }
@@ -305,11 +432,16 @@ macro_rules! wayland_listener_static {
                         let manager: &mut $manager = &mut *container_of!(listener,
                                                                          $manager,
                                                                          $listener);
-                        $crate::utils::handle_unwind(
+                        wlr_span!(concat!(stringify!($manager), "::", stringify!($listener)));
+                        let _watchdog = $crate::utils::watchdog::Callback::enter(
+                            concat!(stringify!($manager), "::", stringify!($listener)));
+                        $crate::utils::handle_listener_unwind(
                             ::std::panic::catch_unwind(
                                 ::std::panic::AssertUnwindSafe(|| {
                                     (|$($func_arg: $func_type,)*| { $body })(manager, data)
-                                })))
+                                })),
+                            concat!(stringify!($manager), "::", stringify!($listener)),
+                            listener)
                     }
                 )*
             )*
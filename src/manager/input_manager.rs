@@ -4,7 +4,7 @@
 
 use libc;
 
-use std::{env, panic, process::abort};
+use std::{env, panic, process::abort, ptr};
 
 use wayland_sys::server::signal::wl_signal_add;
 use wlroots_sys::{wlr_input_device, wlr_input_device_type, wlr_keyboard_set_keymap,
@@ -19,6 +19,7 @@ use {compositor,
              tablet_pad::{self, TabletPad, TabletPadWrapper},
              tablet_tool::{self, TabletTool, TabletToolWrapper},
              touch::{self, Touch, TouchWrapper}},
+     seat::{self, devices::SeatForDevice},
      utils::{Handleable, safe_as_cstring}};
 
 /// Callback triggered when an input device is added.
@@ -80,10 +81,13 @@ wayland_listener_static! {
         // (and why we need this hack).
         [
             keyboard_added: KeyboardAdded,
+            // NOTE Not a callback, the lock state new keyboards start with.
+            keyboard_locks: keyboard::Led,
             pointer_added: PointerAdded,
             touch_added: TouchAdded,
             tablet_tool_added: TabletToolAdded,
-            tablet_pad_added: TabletPadAdded
+            tablet_pad_added: TabletPadAdded,
+            seat_for_device: SeatForDevice
         ]
         (InputAdded, add_listener, input_added) => (add_notify, input_added):
         |manager: &mut Manager, data: *mut libc::c_void,| unsafe {
@@ -106,11 +110,18 @@ wayland_listener_static! {
                                 abort()
                             }
                         };
+                        if let Some(locks) = manager.keyboard_locks {
+                            keyboard.set_locks(locks);
+                        }
+                        #[cfg(feature = "config")]
+                        ::config::input_added(&dev);
                         let keyboard_handle = keyboard.weak_reference();
                         let res = manager.keyboard_added.and_then(|f| f(compositor.clone(), keyboard_handle));
                         if let Some(keyboard_handler) = res {
+                            let leds = keyboard.leds();
                             let mut keyboard = KeyboardWrapper::new((keyboard,
-                                                                     keyboard_handler));
+                                                                     keyboard_handler,
+                                                                     leds));
                             wl_signal_add(&mut (*dev.dev_union().keyboard).events.key as *mut _ as _,
                                           keyboard.key_listener() as *mut _ as _);
                             wl_signal_add(&mut (*dev.dev_union().keyboard).events.modifiers
@@ -134,10 +145,12 @@ wayland_listener_static! {
                                 abort()
                             }
                         };
+                        #[cfg(feature = "config")]
+                        ::config::input_added(&dev);
                         let pointer_handle = pointer.weak_reference();
                         let res = manager.pointer_added.and_then(|f| f(compositor.clone(), pointer_handle));
                         if let Some(pointer_handler) = res {
-                            let mut pointer = PointerWrapper::new((pointer, pointer_handler));
+                            let mut pointer = PointerWrapper::new((pointer, pointer_handler, ptr::null_mut()));
                             wl_signal_add(&mut (*dev.dev_union().pointer).events.motion as *mut _ as _,
                                           pointer.motion_listener() as *mut _ as _);
                             wl_signal_add(&mut (*dev.dev_union().pointer)
@@ -160,6 +173,8 @@ wayland_listener_static! {
                                 abort()
                             }
                         };
+                        #[cfg(feature = "config")]
+                        ::config::input_added(&dev);
                         let touch_handle = touch.weak_reference();
                         let res = manager.touch_added.and_then(|f| f(compositor.clone(), touch_handle));
                         if let Some(touch_handler) = res {
@@ -185,6 +200,8 @@ wayland_listener_static! {
                                 abort()
                             }
                         };
+                        #[cfg(feature = "config")]
+                        ::config::input_added(&dev);
                         let tablet_tool_handle = tablet_tool.weak_reference();
                         let res = manager.tablet_tool_added.and_then(|f| f(compositor.clone(), tablet_tool_handle));
                         if let Some(tablet_tool_handler) = res {
@@ -212,6 +229,8 @@ wayland_listener_static! {
                                 abort()
                             }
                         };
+                        #[cfg(feature = "config")]
+                        ::config::input_added(&dev);
                         let tablet_pad_handle = tablet_pad.weak_reference();
                         let res = manager.tablet_pad_added.and_then(|f| f(compositor.clone(), tablet_pad_handle));
                         if let Some(tablet_pad_handler) = res {
@@ -230,6 +249,19 @@ wayland_listener_static! {
                         }
                     }
                 }
+                if let Some(seat_for_device) = manager.seat_for_device {
+                    if let Some(name) = seat_for_device(compositor.clone(), &dev) {
+                        match seat::find(&name) {
+                            Some(seat) => {
+                                seat.run(|seat| seat.attach_device(&dev)).ok();
+                            },
+                            None => wlr_log!(WLR_ERROR,
+                                             "There is no seat {:?} to attach {:?} to",
+                                             name,
+                                             dev.name())
+                        }
+                    }
+                }
                 manager.input_added.map(|f| f(compositor, &mut dev))
             }));
             match res {
@@ -2,11 +2,15 @@
 
 use libc;
 use wayland_sys::server::WAYLAND_SERVER_HANDLE;
-use wlroots_sys::{wlr_input_device, wlr_event_keyboard_key};
+use wlroots_sys::{wlr_input_device, wlr_event_keyboard_key, wlr_key_state::WLR_KEY_PRESSED};
 
 use {compositor,
-     input::keyboard::{self, Keyboard},
+     cursor,
+     debug::input_recording,
+     input::{keyboard::{self, Keyboard}, keyboard_group},
      utils::Handleable};
+#[cfg(feature = "idle")]
+use extensions::idle;
 
 #[allow(unused_variables)]
 pub trait Handler {
@@ -21,6 +25,16 @@ pub trait Handler {
                  compositor_handle: compositor::Handle,
                  keyboard_handle: keyboard::Handle) {}
 
+    /// Callback that is triggered when the LEDs that should be on change,
+    /// e.g. because num lock was pressed.
+    ///
+    /// This can be used to mirror the LED state on the other keyboards of
+    /// the seat.
+    fn leds(&mut self,
+            compositor_handle: compositor::Handle,
+            keyboard_handle: keyboard::Handle,
+            leds: keyboard::Led) {}
+
     /// Callback that is triggered when the keymap is updated.
     fn keymap(&mut self,
               compositor_handle: compositor::Handle,
@@ -37,12 +51,12 @@ pub trait Handler {
                  keyboard_handle: keyboard::Handle) {}
 }
 
-wayland_listener!(pub(crate) KeyboardWrapper, (Keyboard, Box<Handler>), [
+wayland_listener!(pub(crate) KeyboardWrapper, (Keyboard, Box<Handler>, keyboard::Led), [
     on_destroy_listener => on_destroy_notify: |this: &mut KeyboardWrapper, data: *mut libc::c_void,|
     unsafe {
         let input_device_ptr = data as *mut wlr_input_device;
         {
-            let (ref mut keyboard, ref mut keyboard_handler) = this.data;
+            let (ref mut keyboard, ref mut keyboard_handler, _) = this.data;
             let compositor = match compositor::handle() {
                 Some(handle) => handle,
                 None => return
@@ -67,29 +81,46 @@ wayland_listener!(pub(crate) KeyboardWrapper, (Keyboard, Box<Handler>), [
         Box::from_raw((*input_device_ptr).data as *mut KeyboardWrapper);
     };
     key_listener => key_notify: |this: &mut KeyboardWrapper, data: *mut libc::c_void,| unsafe {
-        let (ref mut keyboard, ref mut keyboard_handler) = this.data;
+        let (ref mut keyboard, ref mut keyboard_handler, _) = this.data;
         let compositor = match compositor::handle() {
             Some(handle) => handle,
             None => return
         };
+        // NOTE The group forwards the key, which is handled then.
+        if keyboard_group::grouped(keyboard.input_device().as_ptr()) {
+            return
+        }
+        input_recording::record_key(keyboard.input_device().as_ptr(), data as *mut _);
+        #[cfg(feature = "idle")]
+        idle::notify_activity();
         let xkb_state = (*keyboard.as_ptr()).xkb_state;
         let key = keyboard::event::Key::new(data as *mut wlr_event_keyboard_key, xkb_state);
+        if key.key_state() == WLR_KEY_PRESSED {
+            cursor::key_pressed();
+        }
 
-        keyboard_handler.on_key(compositor, keyboard.weak_reference(), &key);
+        let keyboard_handle = keyboard.weak_reference();
+        wlr_trace_id!(keyboard_handle.id());
+        keyboard_handler.on_key(compositor, keyboard_handle, &key);
     };
     modifiers_listener => modifiers_notify: |this: &mut KeyboardWrapper, _data: *mut libc::c_void,|
     unsafe {
-        let (ref mut keyboard, ref mut keyboard_handler) = this.data;
+        let (ref mut keyboard, ref mut keyboard_handler, ref mut leds) = this.data;
         let compositor = match compositor::handle() {
             Some(handle) => handle,
             None => return
         };
 
-        keyboard_handler.modifiers(compositor, keyboard.weak_reference());
+        keyboard_handler.modifiers(compositor.clone(), keyboard.weak_reference());
+        let new_leds = keyboard.leds();
+        if new_leds != *leds {
+            *leds = new_leds;
+            keyboard_handler.leds(compositor, keyboard.weak_reference(), new_leds);
+        }
     };
     keymap_listener => keymap_notify: |this: &mut KeyboardWrapper, _data: *mut libc::c_void,|
     unsafe {
-        let (ref mut keyboard, ref mut keyboard_handler) = this.data;
+        let (ref mut keyboard, ref mut keyboard_handler, _) = this.data;
         let compositor = match compositor::handle() {
             Some(handle) => handle,
             None => return
@@ -99,7 +130,7 @@ wayland_listener!(pub(crate) KeyboardWrapper, (Keyboard, Box<Handler>), [
     };
    repeat_listener => repeat_notify: |this: &mut KeyboardWrapper, _data: *mut libc::c_void,|
     unsafe {
-        let (ref mut keyboard, ref mut keyboard_handler) = this.data;
+        let (ref mut keyboard, ref mut keyboard_handler, _) = this.data;
         let compositor = match compositor::handle() {
             Some(handle) => handle,
             None => return
@@ -1,12 +1,16 @@
 //! Handler for outputs
 
+use std::{panic, ptr};
+
 use libc;
 use wayland_sys::server::WAYLAND_SERVER_HANDLE;
 use wlroots_sys::wlr_output;
 
 use {compositor,
+     cursor,
+     manager::output_manager,
      output::{self, Output, OutputState},
-     utils::{HandleErr, Handleable}};
+     utils::{self, HandleErr, Handleable, log::LogContext}};
 
 #[allow(unused_variables)]
 pub trait Handler {
@@ -46,6 +50,11 @@ pub trait Handler {
                   output_handle: output::Handle) {}
 
     /// Called when an output is destroyed (e.g. unplugged).
+    ///
+    /// This is the last chance to use the output, once this returns the
+    /// output is removed from its layout and its hardware cursors stop
+    /// working. The `output_removed` callback of the output manager is called
+    /// after this.
     fn destroyed(&mut self,
                  compositor_handle: compositor::Handle,
                  output_handle: output::Handle) {}
@@ -54,6 +63,7 @@ pub trait Handler {
 wayland_listener!(pub(crate) UserOutput, (Output, Box<Handler>), [
     on_destroy_listener => on_destroy_notify: |this: &mut UserOutput, data: *mut libc::c_void,|
     unsafe {
+        let _log = LogContext::output(this.data.0.as_ptr());
         let output_ptr = data as *mut wlr_output;
         {
             let (ref mut output, ref mut manager) = this.data;
@@ -61,14 +71,15 @@ wayland_listener!(pub(crate) UserOutput, (Output, Box<Handler>), [
                 Some(handle) => handle,
                 None => return
             };
-            manager.destroyed(compositor, output.weak_reference());
+            manager.destroyed(compositor.clone(), output.weak_reference());
+            output_manager::output_removed(compositor, output.weak_reference());
             // NOTE Remove the output from the output if there is one.
             if let Some(layout) = output.layout() {
                 match with_handles!([(layout: {layout})] => {
                     layout.remove(output)
                 }) {
                     Ok(_) | Err(HandleErr::AlreadyDropped) => {},
-                    Err(HandleErr::AlreadyBorrowed) => {
+                    Err(HandleErr::AlreadyBorrowed { .. }) => {
                         panic!("Tried to remove layout from output, but the output layout is already borrowed!");
                     }
                 }
@@ -99,20 +110,59 @@ wayland_listener!(pub(crate) UserOutput, (Output, Box<Handler>), [
                       wl_list_remove,
                       &mut (*this.need_swap_listener()).link as *mut _ as _);
         let output_data = (*output_ptr).data as *mut OutputState;
+        if !(*output_data).render_timer.is_null() {
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_event_source_remove,
+                          (*output_data).render_timer);
+            (*output_data).render_timer = ptr::null_mut();
+        }
+        if !(*output_data).color_timer.is_null() {
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_event_source_remove,
+                          (*output_data).color_timer);
+            (*output_data).color_timer = ptr::null_mut();
+        }
+        // NOTE The output might still be borrowed up the stack, in which case
+        // the state is freed once that reference is dropped.
+        this.data.0.release_mirror_texture();
+        this.data.0.mark_destroyed();
+        (*output_ptr).data = ptr::null_mut();
         Box::from_raw((*output_data).output as *mut UserOutput);
     };
-    frame_listener => frame_notify: |this: &mut UserOutput, _output: *mut libc::c_void,| unsafe {
-        let (ref output, ref mut manager) = this.data;
-        let compositor = match compositor::handle() {
-            Some(handle) => handle,
-            None => return
-        };
-
-        manager.on_frame(compositor, output.weak_reference());
+    frame_listener => frame_notify: |this: &mut UserOutput, output_ptr: *mut libc::c_void,|
+    unsafe {
+        let _log = LogContext::output(this.data.0.as_ptr());
+        let output_ptr = output_ptr as *mut wlr_output;
+        let output_data = (*output_ptr).data as *mut OutputState;
+        (*output_data).frame_stats.frame_started(this.data.0.refresh_period());
+        let delay = this.data.0.render_delay();
+        if let Some(delay) = delay {
+            if (*output_data).render_timer.is_null() {
+                if compositor::COMPOSITOR_PTR.is_null() {
+                    return
+                }
+                let event_loop = (*compositor::COMPOSITOR_PTR).event_loop;
+                (*output_data).render_timer =
+                    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                  wl_event_loop_add_timer,
+                                  event_loop,
+                                  render_timer_notify,
+                                  this as *mut UserOutput as *mut _);
+            }
+            let millis = delay.as_secs() * 1000 + (delay.subsec_nanos() / 1_000_000) as u64;
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_event_source_timer_update,
+                          (*output_data).render_timer,
+                          millis as libc::c_int);
+            return
+        }
+        this.frame();
     };
     mode_listener => mode_notify: |this: &mut UserOutput, _output: *mut libc::c_void,|
     unsafe {
+        let _log = LogContext::output(this.data.0.as_ptr());
         let (ref output, ref mut manager) = this.data;
+        cursor::output_changed(output.as_ptr());
         let compositor = match compositor::handle() {
             Some(handle) => handle,
             None => return
@@ -121,6 +171,7 @@ wayland_listener!(pub(crate) UserOutput, (Output, Box<Handler>), [
         manager.on_mode_change(compositor, output.weak_reference());
     };
     enable_listener => enable_notify: |this: &mut UserOutput, _output: *mut libc::c_void,| unsafe {
+        let _log = LogContext::output(this.data.0.as_ptr());
         let (ref output, ref mut manager) = this.data;
         let compositor = match compositor::handle() {
             Some(handle) => handle,
@@ -130,7 +181,9 @@ wayland_listener!(pub(crate) UserOutput, (Output, Box<Handler>), [
         manager.on_enable(compositor, output.weak_reference());
     };
     scale_listener => scale_notify: |this: &mut UserOutput, _output: *mut libc::c_void,| unsafe {
+        let _log = LogContext::output(this.data.0.as_ptr());
         let (ref output, ref mut manager) = this.data;
+        cursor::output_changed(output.as_ptr());
         let compositor = match compositor::handle() {
             Some(handle) => handle,
             None => return
@@ -140,7 +193,9 @@ wayland_listener!(pub(crate) UserOutput, (Output, Box<Handler>), [
     };
     transform_listener => transform_notify: |this: &mut UserOutput, _output: *mut libc::c_void,|
     unsafe {
+        let _log = LogContext::output(this.data.0.as_ptr());
         let (ref output, ref mut manager) = this.data;
+        cursor::output_changed(output.as_ptr());
         let compositor = match compositor::handle() {
             Some(handle) => handle,
             None => return
@@ -151,6 +206,10 @@ wayland_listener!(pub(crate) UserOutput, (Output, Box<Handler>), [
     swap_buffers_listener => swap_buffers_notify: |this: &mut UserOutput,
                                                    _output: *mut libc::c_void,|
     unsafe {
+        let _log = LogContext::output(this.data.0.as_ptr());
+        let output_data = (*this.data.0.as_ptr()).data as *mut OutputState;
+        (*output_data).frame_stats.frame_finished();
+        this.data.0.frame_submitted();
 
         let (ref output, ref mut manager) = this.data;
         let compositor = match compositor::handle() {
@@ -162,6 +221,7 @@ wayland_listener!(pub(crate) UserOutput, (Output, Box<Handler>), [
     };
     need_swap_listener => need_swap_notify: |this: &mut UserOutput, _output: *mut libc::c_void,|
     unsafe {
+        let _log = LogContext::output(this.data.0.as_ptr());
         let (ref output, ref mut manager) = this.data;
         let compositor = match compositor::handle() {
             Some(handle) => handle,
@@ -171,3 +231,36 @@ wayland_listener!(pub(crate) UserOutput, (Output, Box<Handler>), [
         manager.needs_swap(compositor, output.weak_reference());
     };
 ]);
+
+impl UserOutput {
+    /// Tell the user it's time to render a frame.
+    unsafe fn frame(&mut self) {
+        let (ref mut output, ref mut manager) = self.data;
+        let output_data = (*output.as_ptr()).data as *mut OutputState;
+        if !output_data.is_null() {
+            (*output_data).frame_stats.render_started();
+        }
+        if output.render_mirror() {
+            return
+        }
+        let compositor = match compositor::handle() {
+            Some(handle) => handle,
+            None => return
+        };
+
+        let output_handle = output.weak_reference();
+        wlr_trace_id!(output_handle.id());
+        manager.on_frame(compositor, output_handle);
+    }
+}
+
+/// Fired when a frame delayed by the frame pacing policy of the output should be
+/// rendered.
+unsafe extern "C" fn render_timer_notify(data: *mut libc::c_void) -> libc::c_int {
+    let user_output = data as *mut UserOutput;
+    wlr_span!("UserOutput::render_timer");
+    let _log = LogContext::output((*user_output).data.0.as_ptr());
+    let res = panic::catch_unwind(panic::AssertUnwindSafe(|| (*user_output).frame()));
+    utils::handle_unwind(res);
+    0
+}
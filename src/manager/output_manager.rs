@@ -55,9 +55,25 @@ pub type OutputAdded = fn(compositor_handle: compositor::Handle,
                           output_builder: OutputBuilder)
                           -> Option<BuilderResult>;
 
+/// Callback that is triggered when an output that was set up by `OutputAdded`
+/// is removed (e.g. unplugged), after its handler's `destroyed` callback.
+///
+/// By the time this is called the output can't be rendered to anymore.
+pub type OutputRemoved = fn(compositor_handle: compositor::Handle,
+                            output_handle: output::Handle);
+
+/// Tell the output manager that an output is being removed.
+pub(crate) unsafe fn output_removed(compositor_handle: compositor::Handle,
+                                    output_handle: output::Handle) {
+    if let Some(output_removed) = MANAGER.output_removed {
+        output_removed(compositor_handle, output_handle)
+    }
+}
+
 wayland_listener_static! {
     static mut MANAGER;
     (Manager, Builder): [
+        [output_removed: OutputRemoved]
         (OutputAdded, add_listener, output_added) => (add_notify, add_callback):
         |manager: &mut Manager, data: *mut libc::c_void,| unsafe {
             let data = data as *mut wlr_output;
@@ -110,6 +126,10 @@ wayland_listener_static! {
                               output.need_swap_listener() as _);
                 wl_signal_add(&mut (*data).events.destroy as *mut _ as _,
                               output.on_destroy_listener() as _);
+                #[cfg(feature = "config")]
+                ::config::output_added(&mut output.data.0);
+                #[cfg(feature = "session_lock")]
+                ::extensions::session_lock::output_added(&mut output.data.0);
                 let output_data = (*data).data as *mut OutputState;
                 (*output_data).output = Box::into_raw(output);
             }
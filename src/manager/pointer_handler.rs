@@ -1,13 +1,18 @@
 //! Handler for pointers
 
+use std::{panic, ptr};
+
 use libc;
-use wayland_sys::server::WAYLAND_SERVER_HANDLE;
+use wayland_sys::server::{wl_event_source, WAYLAND_SERVER_HANDLE};
 use wlroots_sys::{wlr_input_device, wlr_event_pointer_axis, wlr_event_pointer_button,
                   wlr_event_pointer_motion};
 
 use {compositor,
+     debug::input_recording,
      input::pointer::{self, Pointer},
-     utils::Handleable};
+     utils::{self, Handleable}};
+#[cfg(feature = "idle")]
+use extensions::idle;
 
 #[allow(unused_variables)]
 pub trait Handler {
@@ -34,13 +39,23 @@ pub trait Handler {
                pointer_handle: pointer::Handle,
                event: &pointer::event::Axis) {}
 
+    /// Callback that is triggered after the events that the pointer reported
+    /// at the same time (e.g. motion and a button press) have been handled.
+    ///
+    /// This version of wlroots doesn't report frames itself, so a frame ends
+    /// once the event loop is done dispatching the events of the device. It's
+    /// the place to call `Seat::pointer_notify_frame`.
+    fn on_frame(&mut self,
+                compositor_handle: compositor::Handle,
+                pointer_handle: pointer::Handle) {}
+
     /// Callback that is triggered when the pointer is destroyed.
     fn destroyed(&mut self,
                  compositor_handle: compositor::Handle,
                  pointer_handle: pointer::Handle) {}
 }
 
-wayland_listener!(pub(crate) PointerWrapper, (Pointer, Box<Handler>), [
+wayland_listener!(pub(crate) PointerWrapper, (Pointer, Box<Handler>, *mut wl_event_source), [
     on_destroy_listener => on_destroy_notify: |this: &mut PointerWrapper, data: *mut libc::c_void,|
     unsafe {
         let input_device_ptr = data as *mut wlr_input_device;
@@ -52,6 +67,9 @@ wayland_listener!(pub(crate) PointerWrapper, (Pointer, Box<Handler>), [
             };
             pointer_handler.destroyed(compositor, pointer.weak_reference());
         }
+        if !this.data.2.is_null() {
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_event_source_remove, this.data.2);
+        }
         ffi_dispatch!(WAYLAND_SERVER_HANDLE,
                       wl_list_remove,
                       &mut (*this.on_destroy_listener()).link as *mut _ as _);
@@ -76,8 +94,14 @@ wayland_listener!(pub(crate) PointerWrapper, (Pointer, Box<Handler>), [
             Some(handle) => handle,
             None => return
         };
+        input_recording::record_pointer_button(data as *mut _);
+        #[cfg(feature = "idle")]
+        idle::notify_activity();
 
-        this.data.1.on_button(compositor, pointer.weak_reference(), &event);
+        let pointer_handle = pointer.weak_reference();
+        wlr_trace_id!(pointer_handle.id());
+        this.data.1.on_button(compositor, pointer_handle, &event);
+        this.schedule_frame();
     };
     motion_listener => motion_notify:  |this: &mut PointerWrapper, data: *mut libc::c_void,|
     unsafe {
@@ -87,8 +111,14 @@ wayland_listener!(pub(crate) PointerWrapper, (Pointer, Box<Handler>), [
             Some(handle) => handle,
             None => return
         };
+        input_recording::record_pointer_motion(data as *mut _);
+        #[cfg(feature = "idle")]
+        idle::notify_activity();
 
-        this.data.1.on_motion(compositor, pointer.weak_reference(), &event);
+        let pointer_handle = pointer.weak_reference();
+        wlr_trace_id!(pointer_handle.id());
+        this.data.1.on_motion(compositor, pointer_handle, &event);
+        this.schedule_frame();
     };
     motion_absolute_listener => motion_absolute_notify:
     |this: &mut PointerWrapper, data: *mut libc::c_void,| unsafe {
@@ -98,8 +128,14 @@ wayland_listener!(pub(crate) PointerWrapper, (Pointer, Box<Handler>), [
             Some(handle) => handle,
             None => return
         };
+        input_recording::record_pointer_motion_absolute(data as *mut _);
+        #[cfg(feature = "idle")]
+        idle::notify_activity();
 
-        this.data.1.on_motion_absolute(compositor, pointer.weak_reference(), &event);
+        let pointer_handle = pointer.weak_reference();
+        wlr_trace_id!(pointer_handle.id());
+        this.data.1.on_motion_absolute(compositor, pointer_handle, &event);
+        this.schedule_frame();
     };
     axis_listener => axis_notify:  |this: &mut PointerWrapper, data: *mut libc::c_void,| unsafe {
         let pointer = &mut this.data.0;
@@ -108,7 +144,43 @@ wayland_listener!(pub(crate) PointerWrapper, (Pointer, Box<Handler>), [
             Some(handle) => handle,
             None => return
         };
+        input_recording::record_pointer_axis(data as *mut _);
+        #[cfg(feature = "idle")]
+        idle::notify_activity();
 
-        this.data.1.on_axis(compositor, pointer.weak_reference(), &event);
+        let pointer_handle = pointer.weak_reference();
+        wlr_trace_id!(pointer_handle.id());
+        this.data.1.on_axis(compositor, pointer_handle, &event);
+        this.schedule_frame();
     };
 ]);
+
+impl PointerWrapper {
+    /// End the frame of the current events once the event loop is idle.
+    unsafe fn schedule_frame(&mut self) {
+        if !self.data.2.is_null() || compositor::COMPOSITOR_PTR.is_null() {
+            return
+        }
+        let event_loop = (*compositor::COMPOSITOR_PTR).event_loop;
+        self.data.2 = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                    wl_event_loop_add_idle,
+                                    event_loop,
+                                    frame_idle,
+                                    self as *mut PointerWrapper as *mut libc::c_void);
+    }
+}
+
+unsafe extern "C" fn frame_idle(data: *mut libc::c_void) {
+    let wrapper = &mut *(data as *mut PointerWrapper);
+    // NOTE Idle sources are removed by the event loop once they are dispatched.
+    wrapper.data.2 = ptr::null_mut();
+    let compositor = match compositor::handle() {
+        Some(handle) => handle,
+        None => return
+    };
+    let pointer_handle = wrapper.data.0.weak_reference();
+    let res = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+                                      wrapper.data.1.on_frame(compositor, pointer_handle)
+                                  }));
+    utils::handle_unwind(res);
+}
@@ -5,8 +5,11 @@ use wlroots_sys::wlr_input_device;
 use wayland_sys::server::WAYLAND_SERVER_HANDLE;
 
 use {compositor,
+     debug::input_recording,
      input::tablet_pad::{self, TabletPad},
      utils::Handleable};
+#[cfg(feature = "idle")]
+use extensions::idle;
 
 #[allow(unused_variables)]
 pub trait Handler {
@@ -68,6 +71,9 @@ wayland_listener!(pub(crate) TabletPadWrapper, (TabletPad, Box<Handler>), [
             Some(handle) => handle,
             None => return
         };
+        input_recording::record_tablet_pad_button(pad.input_device().as_ptr(), data as *mut _);
+        #[cfg(feature = "idle")]
+        idle::notify_activity();
 
         handler.on_button(compositor,
                           pad.weak_reference(),
@@ -81,6 +87,9 @@ wayland_listener!(pub(crate) TabletPadWrapper, (TabletPad, Box<Handler>), [
             Some(handle) => handle,
             None => return
         };
+        input_recording::record_tablet_pad_strip(pad.input_device().as_ptr(), data as *mut _);
+        #[cfg(feature = "idle")]
+        idle::notify_activity();
 
         handler.on_strip(compositor,
                          pad.weak_reference(),
@@ -94,6 +103,9 @@ wayland_listener!(pub(crate) TabletPadWrapper, (TabletPad, Box<Handler>), [
             Some(handle) => handle,
             None => return
         };
+        input_recording::record_tablet_pad_ring(pad.input_device().as_ptr(), data as *mut _);
+        #[cfg(feature = "idle")]
+        idle::notify_activity();
 
         handler.on_ring(compositor,
                         pad.weak_reference(),
@@ -5,8 +5,11 @@ use wlroots_sys::wlr_input_device;
 use wayland_sys::server::WAYLAND_SERVER_HANDLE;
 
 use {compositor,
+     debug::input_recording,
      input::tablet_tool::{self, TabletTool},
      utils::Handleable};
+#[cfg(feature = "idle")]
+use extensions::idle;
 
 #[allow(unused_variables)]
 pub trait Handler {
@@ -78,6 +81,9 @@ wayland_listener!(pub(crate) TabletToolWrapper, (TabletTool, Box<Handler>), [
             Some(handle) => handle,
             None => return
         };
+        input_recording::record_tablet_tool_axis(data as *mut _);
+        #[cfg(feature = "idle")]
+        idle::notify_activity();
 
         handler.on_axis(compositor,
                         tool.weak_reference(),
@@ -92,6 +98,9 @@ wayland_listener!(pub(crate) TabletToolWrapper, (TabletTool, Box<Handler>), [
             Some(handle) => handle,
             None => return
         };
+        input_recording::record_tablet_tool_proximity(data as *mut _);
+        #[cfg(feature = "idle")]
+        idle::notify_activity();
 
         handler.on_proximity(compositor,
                              tool.weak_reference(),
@@ -104,6 +113,9 @@ wayland_listener!(pub(crate) TabletToolWrapper, (TabletTool, Box<Handler>), [
             Some(handle) => handle,
             None => return
         };
+        input_recording::record_tablet_tool_tip(data as *mut _);
+        #[cfg(feature = "idle")]
+        idle::notify_activity();
 
         handler.on_tip(compositor,
                        tool.weak_reference(),
@@ -117,6 +129,9 @@ wayland_listener!(pub(crate) TabletToolWrapper, (TabletTool, Box<Handler>), [
             Some(handle) => handle,
             None => return
         };
+        input_recording::record_tablet_tool_button(data as *mut _);
+        #[cfg(feature = "idle")]
+        idle::notify_activity();
 
         handler.on_button(compositor,
                           tool.weak_reference(),
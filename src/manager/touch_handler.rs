@@ -5,8 +5,11 @@ use wlroots_sys::wlr_input_device;
 use wayland_sys::server::WAYLAND_SERVER_HANDLE;
 
 use {compositor,
+     debug::input_recording,
      input::touch::{self, Touch},
      utils::Handleable};
+#[cfg(feature = "idle")]
+use extensions::idle;
 
 #[allow(unused_variables)]
 pub trait Handler {
@@ -79,6 +82,9 @@ wayland_listener!(pub(crate) TouchWrapper, (Touch, Box<Handler>), [
             Some(handle) => handle,
             None => return
         };
+        input_recording::record_touch_down(data as *mut _);
+        #[cfg(feature = "idle")]
+        idle::notify_activity();
 
         handler.on_down(compositor,
                         touch.weak_reference(),
@@ -91,6 +97,9 @@ wayland_listener!(pub(crate) TouchWrapper, (Touch, Box<Handler>), [
             Some(handle) => handle,
             None => return
         };
+        input_recording::record_touch_up(data as *mut _);
+        #[cfg(feature = "idle")]
+        idle::notify_activity();
 
         handler.on_up(compositor,
                       touch.weak_reference(),
@@ -103,6 +112,9 @@ wayland_listener!(pub(crate) TouchWrapper, (Touch, Box<Handler>), [
             Some(handle) => handle,
             None => return
         };
+        input_recording::record_touch_motion(data as *mut _);
+        #[cfg(feature = "idle")]
+        idle::notify_activity();
 
         handler.on_motion(compositor,
                           touch.weak_reference(),
@@ -115,6 +127,8 @@ wayland_listener!(pub(crate) TouchWrapper, (Touch, Box<Handler>), [
             Some(handle) => handle,
             None => return
         };
+        #[cfg(feature = "idle")]
+        idle::notify_activity();
 
         handler.on_cancel(compositor,
                           touch.weak_reference(),
@@ -2,12 +2,16 @@
 
 use libc;
 use wayland_sys::server::WAYLAND_SERVER_HANDLE;
-use wlroots_sys::wlr_xdg_surface;
+use wlroots_sys::{wlr_xdg_surface,
+                  wlr_xdg_surface_role::{WLR_XDG_SURFACE_ROLE_POPUP,
+                                         WLR_XDG_SURFACE_ROLE_TOPLEVEL}};
 
 use {compositor,
      surface,
-     shell::xdg_shell::{self, SurfaceState},
-     utils::Handleable};
+     shell::{popup_grab::{self, PopupSurface}, xdg_shell::{self, SurfaceState}, ShellEvents},
+     utils::{Handleable, Pool}};
+#[cfg(feature = "foreign_toplevel_list")]
+use {extensions::foreign_toplevel_list, view::View};
 
 /// Handles events from the client stable XDG shells.
 #[allow(unused_variables)]
@@ -94,6 +98,26 @@ pub trait Handler {
                      surface_handle: surface::Handle,
                      xdg_shell_handle: xdg_shell::Handle) {
     }
+    /// The events this handler is called for.
+    ///
+    /// Only these events are listened to on the surfaces the handler is
+    /// given to, so a compositor with many windows doesn't pay for events it
+    /// does nothing with.
+    ///
+    /// Called once, when the surface appears. By default every event is
+    /// listened to.
+    fn events(&self) -> ShellEvents {
+        ShellEvents::all()
+    }
+}
+
+/// How many of the listeners of destroyed surfaces are kept for new ones.
+const POOL_LIMIT: usize = 256;
+
+thread_local! {
+    /// Where the listeners of the surfaces are allocated, as clients can make
+    /// lots of surfaces in a short time (e.g. tooltips).
+    pub(crate) static POOL: Pool<XdgShell> = Pool::new(POOL_LIMIT);
 }
 
 wayland_listener!(pub(crate) XdgShell, (xdg_shell::Surface, Option<Box<Handler>>), [
@@ -108,9 +132,20 @@ wayland_listener!(pub(crate) XdgShell, (xdg_shell::Surface, Option<Box<Handler>>
         }
         let surface_ptr = data as *mut wlr_xdg_surface;
         let shell_state_ptr = (*surface_ptr).data as *mut SurfaceState;
-        Box::from_raw((*shell_state_ptr).shell);
+        POOL.with(|pool| pool.release(Box::from_raw((*shell_state_ptr).shell)));
     };
     commit_listener => commit_notify: |this: &mut XdgShell, _data: *mut libc::c_void,| unsafe {
+        // NOTE There's no signal for when the title or app id change.
+        #[cfg(feature = "foreign_toplevel_list")]
+        {
+            let view = View::XdgShell(this.data.0.weak_reference());
+            if let Some(&mut xdg_shell::ShellState::TopLevel(ref toplevel)) = this.data.0.state() {
+                let toplevel = toplevel.as_ptr();
+                foreign_toplevel_list::xdg_toplevel_committed(&view,
+                                                              (*toplevel).title,
+                                                              (*toplevel).app_id);
+            }
+        }
         let (ref mut shell_surface, ref mut manager) = match &mut this.data {
             (_, None) => return,
             (ss, Some(manager)) => (ss, manager)
@@ -262,7 +297,15 @@ wayland_listener!(pub(crate) XdgShell, (xdg_shell::Surface, Option<Box<Handler>>
                                          &event);
     };
 
-    map_listener => map_notify: |this: &mut XdgShell, _event: *mut libc::c_void,| unsafe {
+    map_listener => map_notify: |this: &mut XdgShell, event: *mut libc::c_void,| unsafe {
+        let xdg_surface = event as *mut wlr_xdg_surface;
+        if (*xdg_surface).role == WLR_XDG_SURFACE_ROLE_POPUP {
+            popup_grab::popup_mapped(PopupSurface::Stable(xdg_surface));
+        }
+        if (*xdg_surface).role == WLR_XDG_SURFACE_ROLE_TOPLEVEL {
+            #[cfg(feature = "foreign_toplevel_list")]
+            foreign_toplevel_list::view_mapped(View::XdgShell(this.data.0.weak_reference()));
+        }
         let (ref mut shell_surface, ref mut manager) = match &mut this.data {
             (_, None) => return,
             (ss, Some(manager)) => (ss, manager)
@@ -278,7 +321,15 @@ wayland_listener!(pub(crate) XdgShell, (xdg_shell::Surface, Option<Box<Handler>>
                             shell_surface.weak_reference());
     };
 
-    unmap_listener => unmap_notify: |this: &mut XdgShell, _event: *mut libc::c_void,| unsafe {
+    unmap_listener => unmap_notify: |this: &mut XdgShell, event: *mut libc::c_void,| unsafe {
+        let xdg_surface = event as *mut wlr_xdg_surface;
+        if (*xdg_surface).role == WLR_XDG_SURFACE_ROLE_POPUP {
+            popup_grab::popup_unmapped(PopupSurface::Stable(xdg_surface));
+        }
+        if (*xdg_surface).role == WLR_XDG_SURFACE_ROLE_TOPLEVEL {
+            #[cfg(feature = "foreign_toplevel_list")]
+            foreign_toplevel_list::view_unmapped(&View::XdgShell(this.data.0.weak_reference()));
+        }
         let (ref mut shell_surface, ref mut manager) = match &mut this.data {
             (_, None) => return,
             (ss, Some(manager)) => (ss, manager)
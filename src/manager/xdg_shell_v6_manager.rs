@@ -2,13 +2,13 @@
 
 use libc;
 use wayland_sys::server::signal::wl_signal_add;
-use wlroots_sys::{wlr_xdg_surface_v6, wlr_xdg_surface_v6_role::*};
+use wlroots_sys::{wl_listener, wl_signal, wlr_xdg_surface_v6, wlr_xdg_surface_v6_role::*};
 
 use {compositor,
-     shell::xdg_shell_v6::{self, ShellState},
+     shell::{xdg_shell_v6::{self, ShellState}, ShellEvents},
      surface,
      utils::Handleable};
-use super::xdg_shell_v6_handler::XdgShellV6;
+use super::xdg_shell_v6_handler::{XdgShellV6, POOL};
 
 /// Callback that is triggered when a new XDG shell v6 surface appears.
 pub type NewSurface = fn(compositor_handle: compositor::Handle,
@@ -47,7 +47,11 @@ wayland_listener_static! {
                     Some(f) => f(compositor, shell_surface.weak_reference())
                 };
 
-            let mut shell_surface = XdgShellV6::new((shell_surface, shell_surface_handler));
+            let listened = shell_surface_handler.as_ref()
+                             .map_or(ShellEvents::empty(), |handler| handler.events());
+            let mut shell_surface = POOL.with(|pool| {
+                XdgShellV6::new_in(pool, (shell_surface, shell_surface_handler))
+            });
             let surface_state = (*(*data).surface).data as *mut surface::InternalState;
             if let Some(surface_handler) = surface_handler {
                 (*(*surface_state).surface).data().1 = surface_handler;
@@ -55,12 +59,22 @@ wayland_listener_static! {
 
             wl_signal_add(&mut (*data).events.destroy as *mut _ as _,
                           shell_surface.destroy_listener() as _);
-            wl_signal_add(&mut (*(*data).surface).events.commit as *mut _ as _,
-                          shell_surface.commit_listener() as _);
-            wl_signal_add(&mut (*data).events.ping_timeout as *mut _ as _,
-                          shell_surface.ping_timeout_listener() as _);
-            wl_signal_add(&mut (*data).events.new_popup as *mut _ as _,
-                          shell_surface.new_popup_listener() as _);
+            // NOTE The commits of toplevels are needed for the foreign
+            // toplevel list, whether the handler wants them or not.
+            let toplevel = cfg!(feature = "foreign_toplevel_list")
+                           && (*data).role == WLR_XDG_SURFACE_V6_ROLE_TOPLEVEL;
+            if listened.contains(ShellEvents::COMMIT) || toplevel {
+                wl_signal_add(&mut (*(*data).surface).events.commit as *mut _ as _,
+                              shell_surface.commit_listener() as _);
+            }
+            if listened.contains(ShellEvents::PING_TIMEOUT) {
+                wl_signal_add(&mut (*data).events.ping_timeout as *mut _ as _,
+                              shell_surface.ping_timeout_listener() as _);
+            }
+            if listened.contains(ShellEvents::NEW_POPUP) {
+                wl_signal_add(&mut (*data).events.new_popup as *mut _ as _,
+                              shell_surface.new_popup_listener() as _);
+            }
             wl_signal_add(&mut (*data).events.map as *mut _ as _,
                           shell_surface.map_listener() as _);
             wl_signal_add(&mut (*data).events.unmap as *mut _ as _,
@@ -72,18 +86,30 @@ wayland_listener_static! {
                 }
             }).expect("Cannot borrow xdg shell surface");
             if let Some(mut events) = events {
-                wl_signal_add(&mut events.request_maximize as *mut _ as _,
-                              shell_surface.maximize_listener() as _);
-                wl_signal_add(&mut events.request_fullscreen as *mut _ as _,
-                              shell_surface.fullscreen_listener() as _);
-                wl_signal_add(&mut events.request_minimize as *mut _ as _,
-                              shell_surface.minimize_listener() as _);
-                wl_signal_add(&mut events.request_move as *mut _ as _,
-                              shell_surface.move_listener() as _);
-                wl_signal_add(&mut events.request_resize as *mut _ as _,
-                              shell_surface.resize_listener() as _);
-                wl_signal_add(&mut events.request_show_window_menu as *mut _ as _,
-                              shell_surface.show_window_menu_listener() as _);
+                let requests: [(ShellEvents, *mut wl_signal, *mut wl_listener); 6] =
+                    [(ShellEvents::MAXIMIZE,
+                      &mut events.request_maximize as *mut _,
+                      shell_surface.maximize_listener()),
+                     (ShellEvents::FULLSCREEN,
+                      &mut events.request_fullscreen as *mut _,
+                      shell_surface.fullscreen_listener()),
+                     (ShellEvents::MINIMIZE,
+                      &mut events.request_minimize as *mut _,
+                      shell_surface.minimize_listener()),
+                     (ShellEvents::MOVE,
+                      &mut events.request_move as *mut _,
+                      shell_surface.move_listener()),
+                     (ShellEvents::RESIZE,
+                      &mut events.request_resize as *mut _,
+                      shell_surface.resize_listener()),
+                     (ShellEvents::SHOW_WINDOW_MENU,
+                      &mut events.request_show_window_menu as *mut _,
+                      shell_surface.show_window_menu_listener())];
+                for &(event, signal, listener) in &requests {
+                    if listened.contains(event) {
+                        wl_signal_add(signal as _, listener as _);
+                    }
+                }
             }
 
             let shell_data = (*data).data as *mut xdg_shell_v6::SurfaceState;
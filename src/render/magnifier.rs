@@ -0,0 +1,313 @@
+//! Zooming into the outputs around a point, usually the cursor, for users who
+//! need to see what's on screen larger.
+//!
+//! A `Magnifier` is a render pass that runs after everything else, so it
+//! works whatever the compositor draws. It copies the finished frame into a
+//! texture and draws part of it over the whole output again:
+//!
+//! ```rust,no_run,ignore
+//! let magnifier = Magnifier::new(2.0);
+//! output.add_render_pass(Box::new(magnifier.clone()));
+//!
+//! // When the cursor moves:
+//! magnifier.set_focus(cursor.coords());
+//!
+//! // On the key binding:
+//! magnifier.toggle();
+//! ```
+//!
+//! While it's magnifying, what was drawn before is zoomed into, so the whole
+//! output has to be drawn again every frame (see `Magnifier::magnifying`).
+//! Drawing only the damaged parts leaves what the last frame zoomed into in
+//! the rest of the buffer.
+
+use std::{cell::{Cell, RefCell}, rc::Rc, time::{Duration, Instant}};
+
+use wlroots_sys::{wl_shm_format, wlr_output_transform_invert, wlr_texture, wlr_texture_destroy,
+                  wl_output_transform::{WL_OUTPUT_TRANSFORM_FLIPPED_180,
+                                        WL_OUTPUT_TRANSFORM_NORMAL}};
+
+use {area::{Area, Origin, Size},
+     output,
+     render::{matrix, RenderPass, Renderer, Texture},
+     utils::{self, HandleErr, Handleable}};
+
+/// How close the view has to be to the point it pans to, in pixels, for it to
+/// stop panning.
+const PAN_THRESHOLD: f64 = 0.5;
+
+#[derive(Debug)]
+struct Settings {
+    enabled: bool,
+    factor: f64,
+    /// The point to zoom into, in layout coordinates.
+    focus: (f64, f64),
+    smoothing: Duration,
+    /// The outputs the magnifier was drawn on, to redraw them on changes.
+    outputs: Vec<output::Handle>
+}
+
+/// The texture a magnifier copies the frames of its output into.
+#[derive(Debug)]
+struct FrameTexture {
+    texture: *mut wlr_texture,
+    /// Whether the renderer the texture belongs to is still around.
+    renderer: Rc<Cell<bool>>
+}
+
+impl Drop for FrameTexture {
+    fn drop(&mut self) {
+        if self.renderer.get() {
+            unsafe { wlr_texture_destroy(self.texture) }
+        }
+    }
+}
+
+/// Zooms into the outputs it's added to as a render pass.
+///
+/// Clones share their settings, so one can be added to every output and
+/// another one kept to control all of them. Each output pans on its own
+/// towards the focus.
+#[derive(Debug)]
+pub struct Magnifier {
+    settings: Rc<RefCell<Settings>>,
+    /// The center of the part of the output that is shown, in buffer
+    /// coordinates.
+    center: Option<(f64, f64)>,
+    last_frame: Option<Instant>,
+    /// Kept while magnifying, and made again when the size of the output
+    /// changes.
+    texture: Option<FrameTexture>
+}
+
+impl Clone for Magnifier {
+    fn clone(&self) -> Self {
+        // NOTE The clone is for another output, which needs its own texture.
+        Magnifier { settings: self.settings.clone(),
+                    center: self.center,
+                    last_frame: self.last_frame,
+                    texture: None }
+    }
+}
+
+impl Magnifier {
+    /// Make a magnifier that zooms in by the factor once it's enabled.
+    ///
+    /// It starts out disabled.
+    pub fn new(factor: f64) -> Self {
+        let settings = Settings { enabled: false,
+                                  factor: factor.max(1.0),
+                                  focus: (0.0, 0.0),
+                                  smoothing: Duration::from_millis(100),
+                                  outputs: Vec::new() };
+        Magnifier { settings: Rc::new(RefCell::new(settings)),
+                    center: None,
+                    last_frame: None,
+                    texture: None }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.settings.borrow().enabled
+    }
+
+    /// Start or stop magnifying.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.settings.borrow_mut().enabled = enabled;
+        self.redraw();
+    }
+
+    /// Stop magnifying if it's magnifying, otherwise start.
+    pub fn toggle(&self) {
+        let enabled = self.enabled();
+        self.set_enabled(!enabled)
+    }
+
+    /// Determine if the outputs are zoomed into, i.e. if it's enabled with a
+    /// factor greater than 1.
+    ///
+    /// The compositor must draw all of the outputs every frame while this is
+    /// true.
+    pub fn magnifying(&self) -> bool {
+        let settings = self.settings.borrow();
+        settings.enabled && settings.factor > 1.0
+    }
+
+    pub fn factor(&self) -> f64 {
+        self.settings.borrow().factor
+    }
+
+    /// Set how much the outputs are zoomed into.
+    ///
+    /// Factors smaller than 1 are treated as 1, which doesn't zoom at all.
+    pub fn set_factor(&self, factor: f64) {
+        self.settings.borrow_mut().factor = factor.max(1.0);
+        self.redraw();
+    }
+
+    /// Set the point to zoom into, in layout coordinates.
+    ///
+    /// The outputs pan to it smoothly. Near the edges of an output the part
+    /// that's shown stops at the edge, so the point isn't in the center then.
+    pub fn set_focus(&self, (x, y): (f64, f64)) {
+        self.settings.borrow_mut().focus = (x, y);
+        if self.magnifying() {
+            self.redraw();
+        }
+    }
+
+    /// Set how long the outputs take to pan to a new focus.
+    ///
+    /// This is roughly the time it takes to get two thirds of the way there.
+    /// With no time at all they follow the focus right away. It's 100ms by
+    /// default.
+    pub fn set_smoothing(&self, smoothing: Duration) {
+        self.settings.borrow_mut().smoothing = smoothing
+    }
+
+    /// Damage the outputs the magnifier is drawn on, so they're drawn again.
+    fn redraw(&self) {
+        let outputs = {
+            let mut settings = self.settings.borrow_mut();
+            settings.outputs.retain(|output| match output.run(|_| ()) {
+                                        Err(HandleErr::AlreadyDropped) => false,
+                                        _ => true
+                                    });
+            settings.outputs.clone()
+        };
+        for output in outputs {
+            output.run(|output| output.damage().add_whole()).ok();
+        }
+    }
+
+    /// Move the center towards the target, returning whether it's still
+    /// moving.
+    fn pan(&mut self, target: (f64, f64), smoothing: Duration) -> bool {
+        let now = utils::now();
+        let center = match (self.center, self.last_frame) {
+            (Some(center), Some(last_frame)) if smoothing > Duration::from_secs(0) => {
+                let elapsed = duration_secs(now.duration_since(last_frame));
+                let progress = 1.0 - (-elapsed / duration_secs(smoothing)).exp();
+                (center.0 + (target.0 - center.0) * progress,
+                 center.1 + (target.1 - center.1) * progress)
+            },
+            _ => target
+        };
+        self.last_frame = Some(now);
+        let moving = (target.0 - center.0).abs() > PAN_THRESHOLD ||
+                     (target.1 - center.1).abs() > PAN_THRESHOLD;
+        self.center = Some(if moving { center } else { target });
+        moving
+    }
+
+    /// Get the texture to copy the frame into, making it if the size of the
+    /// output changed.
+    fn frame_texture(&mut self,
+                     renderer: &mut Renderer,
+                     width: i32,
+                     height: i32)
+                     -> Option<*mut wlr_texture> {
+        let reuse = match self.texture {
+            Some(ref texture) => unsafe {
+                texture.renderer.get()
+                && Texture::from_ptr(texture.texture).size() == (width, height)
+            },
+            None => false
+        };
+        if !reuse {
+            self.texture = None;
+            // NOTE The contents don't matter, the frames are copied into it.
+            let stride = width as u32 * 4;
+            let pixels = vec![0; stride as usize * height as usize];
+            let format = wl_shm_format::WL_SHM_FORMAT_ABGR8888;
+            let texture = renderer.create_texture_from_pixels(format,
+                                                              stride,
+                                                              width as u32,
+                                                              height as u32,
+                                                              &pixels)?;
+            self.texture = Some(FrameTexture { texture: unsafe { texture.as_ptr() },
+                                               renderer: renderer.liveliness() });
+        }
+        self.texture.as_ref().map(|texture| texture.texture)
+    }
+}
+
+impl RenderPass for Magnifier {
+    fn post_pass(&mut self, renderer: &mut Renderer) {
+        let handle = renderer.output.weak_reference();
+        let (enabled, factor, focus, smoothing) = {
+            let mut settings = self.settings.borrow_mut();
+            if !settings.outputs.contains(&handle) {
+                settings.outputs.push(handle);
+            }
+            (settings.enabled, settings.factor, settings.focus, settings.smoothing)
+        };
+        if !enabled || factor <= 1.0 {
+            self.center = None;
+            self.last_frame = None;
+            self.texture = None;
+            return
+        }
+        let (width, height) = renderer.output.size();
+        if width <= 0 || height <= 0 {
+            return
+        }
+        // NOTE The frame is read and drawn again in buffer coordinates, so the
+        // focus is moved there from the layout.
+        let (transformed_width, transformed_height) = renderer.output.transformed_resolution();
+        let (output_x, output_y) = renderer.output.layout_space_pos();
+        let scale = renderer.output.scale() as f64;
+        let (local_x, local_y) = ((focus.0 - output_x as f64) * scale,
+                                  (focus.1 - output_y as f64) * scale);
+        let focused = local_x >= 0.0 &&
+                      local_y >= 0.0 &&
+                      local_x < transformed_width as f64 &&
+                      local_y < transformed_height as f64;
+        let target = if focused {
+            let transform = unsafe {
+                wlr_output_transform_invert(renderer.output.get_transform())
+            };
+            let point = Area::new(Origin::new(local_x as i32, local_y as i32), Size::default())
+                .transform(transform, transformed_width, transformed_height);
+            (point.origin.x as f64, point.origin.y as f64)
+        } else {
+            // NOTE Outputs the focus isn't on stay where they are.
+            self.center.unwrap_or((width as f64 / 2.0, height as f64 / 2.0))
+        };
+        let (half_width, half_height) = (width as f64 / factor / 2.0,
+                                         height as f64 / factor / 2.0);
+        let target = (target.0.max(half_width).min(width as f64 - half_width),
+                      target.1.max(half_height).min(height as f64 - half_height));
+        if self.pan(target, smoothing) {
+            renderer.output.damage().add_whole();
+        }
+        let center = self.center.unwrap_or(target);
+
+        // NOTE The frame is copied on the GPU into a texture that's kept from
+        // frame to frame.
+        let texture = match self.frame_texture(renderer, width, height) {
+            Some(texture) => unsafe { Texture::from_ptr(texture) },
+            None => {
+                wlr_log!(WLR_ERROR, "Could not make the texture to magnify into");
+                return
+            }
+        };
+        if !unsafe { texture.copy_framebuffer(width, height) } {
+            wlr_log!(WLR_ERROR, "Could not copy the frame to magnify");
+            self.texture = None;
+            return
+        }
+        let area = Area::new(Origin::new((-(center.0 - half_width) * factor).round() as i32,
+                                         (-(center.1 - half_height) * factor).round() as i32),
+                             Size::new((width as f64 * factor).round() as i32,
+                                       (height as f64 * factor).round() as i32));
+        let projection = matrix::projection([0.0; 9], width, height, WL_OUTPUT_TRANSFORM_NORMAL);
+        // NOTE The rows were copied bottom up, so it's drawn upside down.
+        let matrix = matrix::project_box(area, WL_OUTPUT_TRANSFORM_FLIPPED_180, 0.0, projection);
+        renderer.render_scissor(None);
+        renderer.render_texture_with_matrix(&texture, matrix);
+    }
+}
+
+fn duration_secs(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1_000_000_000.0
+}
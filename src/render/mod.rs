@@ -8,6 +8,18 @@ pub mod matrix;
 mod image;
 #[cfg(feature = "unstable")]
 mod pixman_region;
+#[cfg(feature = "unstable")]
+pub(crate) mod pixman;
+#[cfg(feature = "unstable")]
+mod pass;
+#[cfg(feature = "unstable")]
+mod occlusion;
+#[cfg(feature = "unstable")]
+pub(crate) mod snapshot;
+#[cfg(feature = "wallpaper")]
+mod wallpaper;
+#[cfg(feature = "unstable")]
+mod magnifier;
 
 #[cfg(feature = "unstable")]
 pub use self::renderer::*;
@@ -17,3 +29,13 @@ pub use self::pixman_region::*;
 pub use self::image::*;
 #[cfg(feature = "unstable")]
 pub use self::texture::*;
+#[cfg(feature = "unstable")]
+pub use self::pass::*;
+#[cfg(feature = "unstable")]
+pub use self::occlusion::*;
+#[cfg(feature = "unstable")]
+pub use self::snapshot::{Pixels, Snapshot};
+#[cfg(feature = "wallpaper")]
+pub use self::wallpaper::*;
+#[cfg(feature = "unstable")]
+pub use self::magnifier::*;
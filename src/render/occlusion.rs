@@ -0,0 +1,75 @@
+//! Skipping the surfaces that are hidden behind opaque surfaces.
+//!
+//! Go through the surfaces from front to back, checking each one with
+//! `Occlusion::is_occluded` before adding it, then draw the ones that aren't
+//! occluded from back to front as usual:
+//!
+//! ```rust,no_run,ignore
+//! let mut occlusion = Occlusion::new();
+//! let visible: Vec<_> = views.iter().rev().filter(|view| {
+//!     let occluded = occlusion.is_occluded(view.area());
+//!     occlusion.add_surface(&view.surface, view.x, view.y);
+//!     !occluded
+//! }).collect();
+//! occlusion.clip_damage(&mut damage);
+//! ```
+//!
+//! All positions are in the same coordinate space (e.g. output-local), with
+//! surfaces placed at their top-left corner and not scaled or transformed.
+
+use libc::c_int;
+
+use {area::Area, render::PixmanRegion, surface::Surface};
+
+/// The part of the output covered by the opaque surfaces seen so far.
+#[derive(Debug)]
+pub struct Occlusion {
+    covered: PixmanRegion
+}
+
+impl Occlusion {
+    /// Start with nothing covered.
+    pub fn new() -> Self {
+        Occlusion { covered: PixmanRegion::new() }
+    }
+
+    /// Cover what's behind the opaque region of the surface at the position.
+    pub fn add_surface(&mut self, surface: &Surface, x: c_int, y: c_int) {
+        let mut opaque = surface.opaque_region();
+        opaque.translate(x, y);
+        self.covered.union(&opaque);
+    }
+
+    /// Cover what's behind the area, e.g. of a solid background.
+    pub fn add_area(&mut self, area: Area) {
+        if area.size.width > 0 && area.size.height > 0 {
+            self.covered.rectangle(area.origin.x,
+                                   area.origin.y,
+                                   area.size.width as _,
+                                   area.size.height as _);
+        }
+    }
+
+    /// Determines if the area is entirely covered, so whatever is there
+    /// doesn't need to be drawn.
+    pub fn is_occluded(&self, area: Area) -> bool {
+        self.covered.contains_area(area)
+    }
+
+    /// Remove the covered part from the damage, leaving the part that the
+    /// surfaces behind the covering ones need to repaint.
+    pub fn clip_damage(&self, damage: &mut PixmanRegion) {
+        damage.subtract(&self.covered)
+    }
+
+    /// Get the region that is covered.
+    pub fn covered(&self) -> &PixmanRegion {
+        &self.covered
+    }
+}
+
+impl Default for Occlusion {
+    fn default() -> Self {
+        Occlusion::new()
+    }
+}
@@ -0,0 +1,39 @@
+//! Hooks into rendering an output, for effects layered on top of the
+//! compositor's own drawing.
+//!
+//! Render passes are added to an output with `Output::add_render_pass`, and
+//! are run by the `Renderer` in the order they were added:
+//!
+//! * `pre_pass` once `GenericRenderer::render` has started the frame,
+//! * `surface` for every surface drawn with `Renderer::render_surface`,
+//! * `post_pass` right before the buffers are swapped.
+
+use render::Renderer;
+use surface::Surface;
+
+/// How a surface is about to be drawn, which a render pass can change.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SurfaceDraw {
+    /// The matrix the surface texture is drawn with.
+    pub matrix: [f32; 9],
+    /// The opacity of the surface, from 0.0 to 1.0.
+    pub alpha: f32,
+    /// Whether the surface is drawn at all.
+    ///
+    /// A pass that hides a surface is free to draw something in its place
+    /// (e.g. a solid rectangle when redacting it).
+    pub visible: bool
+}
+
+/// An effect that runs as part of rendering an output.
+#[allow(unused_variables)]
+pub trait RenderPass {
+    /// Called at the start of the frame, before the compositor draws anything.
+    fn pre_pass(&mut self, renderer: &mut Renderer) {}
+
+    /// Called before a surface is drawn with `Renderer::render_surface`.
+    fn surface(&mut self, renderer: &mut Renderer, surface: &mut Surface, draw: &mut SurfaceDraw) {}
+
+    /// Called at the end of the frame, to draw on top of everything else.
+    fn post_pass(&mut self, renderer: &mut Renderer) {}
+}
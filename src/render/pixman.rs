@@ -0,0 +1,772 @@
+//! A renderer that draws on the CPU with pixman, chosen with
+//! `compositor::Builder::renderer(RendererKind::Pixman)`.
+//!
+//! wlroots only got a pixman renderer in 0.14, so this is a `wlr_renderer` of
+//! this crate, which wlroots uses like any other (e.g. to upload the buffers
+//! of the clients and to draw the software cursors). pixman is linked by
+//! wlroots anyway, and draws the same on every machine, so the frames can be
+//! compared pixel for pixel in tests.
+//!
+//! The outputs of this version of wlroots can only be presented with EGL, so
+//! the frames are kept in memory, a buffer for each EGL surface, and are only
+//! seen by reading them back. EGL is still set up for the surfaces of the
+//! outputs, but nothing is drawn with GL.
+
+use std::{cell::Cell, mem, ptr, slice};
+
+use libc::{c_int, c_void};
+use wlroots_sys::{pixman_color, pixman_f_transform, pixman_f_transform_invert,
+                  pixman_filter_t, pixman_format_code_t, pixman_image_composite32,
+                  pixman_image_create_bits, pixman_image_create_solid_fill,
+                  pixman_image_set_clip_region32, pixman_image_set_filter,
+                  pixman_image_set_transform, pixman_image_t, pixman_image_unref, pixman_op_t,
+                  pixman_region32_fini, pixman_region32_init_rect, pixman_region32_t,
+                  pixman_transform, pixman_transform_from_pixman_f_transform, wl_shm_format,
+                  wlr_box, wlr_egl, wlr_egl_init, wlr_renderer, wlr_renderer_impl,
+                  wlr_renderer_init, wlr_texture, wlr_texture_impl, wlr_texture_init};
+
+/// Taken from EGL/egl.h.
+const EGL_DRAW: i32 = 0x3059;
+/// How many buffers are kept, the one drawn to the longest ago is dropped
+/// first.
+const MAX_BUFFERS: usize = 16;
+/// The largest mask an ellipse is drawn with.
+const MAX_ELLIPSE_MASK: c_int = 1024;
+
+/// The formats textures can be made from.
+static FORMATS: [wl_shm_format; 4] = [wl_shm_format::WL_SHM_FORMAT_ARGB8888,
+                                      wl_shm_format::WL_SHM_FORMAT_XRGB8888,
+                                      wl_shm_format::WL_SHM_FORMAT_ABGR8888,
+                                      wl_shm_format::WL_SHM_FORMAT_XBGR8888];
+
+extern "C" {
+    fn eglGetCurrentSurface(readdraw: i32) -> *mut c_void;
+}
+
+thread_local! {
+    /// The renderer that last began drawing, for copying from its buffer.
+    static DRAWING: Cell<*mut PixmanRenderer> = Cell::new(ptr::null_mut());
+}
+
+/// A 3x3 matrix, row by row.
+type Matrix = [f64; 9];
+
+/// The `wlr_renderer` comes first, so the pointers wlroots hands back can be
+/// cast to this.
+#[repr(C)]
+struct PixmanRenderer {
+    renderer: wlr_renderer,
+    renderer_impl: wlr_renderer_impl,
+    /// The buffers drawn to, the most recently used last.
+    buffers: Vec<Buffer>,
+    /// The image of the buffer being drawn to, which is the last one.
+    current: *mut pixman_image_t,
+    width: c_int,
+    height: c_int
+}
+
+/// What was drawn to an EGL surface (i.e. an output), or offscreen.
+struct Buffer {
+    /// The EGL surface that was current, null when drawing offscreen.
+    surface: usize,
+    image: *mut pixman_image_t,
+    /// The pixels of `image`.
+    _data: Vec<u32>,
+    width: c_int,
+    height: c_int
+}
+
+#[repr(C)]
+struct PixmanTexture {
+    texture: wlr_texture,
+    texture_impl: wlr_texture_impl,
+    image: *mut pixman_image_t,
+    /// The pixels of `image`.
+    _data: Vec<u32>,
+    width: c_int,
+    height: c_int
+}
+
+impl Buffer {
+    unsafe fn new(surface: usize, width: c_int, height: c_int) -> Option<Buffer> {
+        let mut data = vec![0; width as usize * height as usize];
+        let image = pixman_image_create_bits(pixman_format_code_t::PIXMAN_a8r8g8b8,
+                                             width,
+                                             height,
+                                             data.as_mut_ptr(),
+                                             width * 4);
+        if image.is_null() {
+            return None
+        }
+        Some(Buffer { surface,
+                      image,
+                      _data: data,
+                      width,
+                      height })
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        unsafe { pixman_image_unref(self.image); }
+    }
+}
+
+impl Drop for PixmanTexture {
+    fn drop(&mut self) {
+        unsafe { pixman_image_unref(self.image); }
+    }
+}
+
+/// Set up EGL for the outputs of the backend, and make the pixman renderer.
+///
+/// This is what the backends are given as their render setup function.
+pub(crate) unsafe extern "C" fn setup(egl: *mut wlr_egl,
+                                      platform: u32,
+                                      remote_display: *mut c_void,
+                                      config_attribs: *mut i32,
+                                      visual_id: i32)
+                                      -> *mut wlr_renderer {
+    if !wlr_egl_init(egl, platform, remote_display, config_attribs, visual_id) {
+        wlr_log!(WLR_ERROR, "Could not set up EGL for the outputs of the pixman renderer");
+        return ptr::null_mut()
+    }
+    let mut renderer = Box::new(PixmanRenderer { renderer: mem::zeroed(),
+                                                 renderer_impl: mem::zeroed(),
+                                                 buffers: Vec::new(),
+                                                 current: ptr::null_mut(),
+                                                 width: 0,
+                                                 height: 0 });
+    renderer.renderer_impl.begin = Some(begin);
+    renderer.renderer_impl.end = Some(end);
+    renderer.renderer_impl.clear = Some(clear);
+    renderer.renderer_impl.scissor = Some(scissor);
+    renderer.renderer_impl.render_texture_with_matrix = Some(render_texture_with_matrix);
+    renderer.renderer_impl.render_quad_with_matrix = Some(render_quad_with_matrix);
+    renderer.renderer_impl.render_ellipse_with_matrix = Some(render_ellipse_with_matrix);
+    renderer.renderer_impl.formats = Some(formats);
+    renderer.renderer_impl.format_supported = Some(format_supported);
+    renderer.renderer_impl.texture_from_pixels = Some(texture_from_pixels);
+    renderer.renderer_impl.read_pixels = Some(read_pixels);
+    renderer.renderer_impl.destroy = Some(destroy);
+    let renderer = Box::into_raw(renderer);
+    wlr_renderer_init(&mut (*renderer).renderer, &(*renderer).renderer_impl);
+    renderer as *mut wlr_renderer
+}
+
+/// Determine if the renderer is a pixman renderer.
+pub(crate) unsafe fn is_pixman(renderer: *mut wlr_renderer) -> bool {
+    !renderer.is_null() && !(*renderer).impl_.is_null()
+    && (*(*renderer).impl_).begin == Some(begin as _)
+}
+
+/// Determine if the texture belongs to a pixman renderer.
+pub(crate) unsafe fn is_pixman_texture(texture: *mut wlr_texture) -> bool {
+    !texture.is_null() && !(*texture).impl_.is_null()
+    && (*(*texture).impl_).get_size == Some(texture_get_size as _)
+}
+
+/// Copy the bottom left corner of the buffer being drawn to into the
+/// texture, bottom up like `Texture::copy_framebuffer` does on the GPU.
+pub(crate) unsafe fn copy_framebuffer(texture: *mut wlr_texture,
+                                      width: c_int,
+                                      height: c_int)
+                                      -> bool {
+    let renderer = DRAWING.with(|drawing| drawing.get());
+    if renderer.is_null() || (*renderer).current.is_null() || !is_pixman_texture(texture) {
+        return false
+    }
+    let renderer = &*renderer;
+    let texture = &*(texture as *mut PixmanTexture);
+    let (width, height) = (width.min(texture.width), height.min(texture.height));
+    if width <= 0 || height <= 0 || width > renderer.width || height > renderer.height {
+        return false
+    }
+    // NOTE The row of the texture at y is the row of the buffer at the
+    // height of the buffer minus one minus y.
+    let flip = [1.0, 0.0, 0.0,
+                0.0, -1.0, renderer.height as f64,
+                0.0, 0.0, 1.0];
+    let transform = match inverse_transform(&flip) {
+        Some(transform) => transform,
+        None => return false
+    };
+    pixman_image_set_transform(renderer.current, &transform);
+    pixman_image_composite32(pixman_op_t::PIXMAN_OP_SRC,
+                             renderer.current,
+                             ptr::null_mut(),
+                             texture.image,
+                             0,
+                             0,
+                             0,
+                             0,
+                             0,
+                             0,
+                             width,
+                             height);
+    pixman_image_set_transform(renderer.current, ptr::null());
+    true
+}
+
+/// Read the whole texture into `data`, as `WL_SHM_FORMAT_ABGR8888` without
+/// padding between the rows.
+pub(crate) unsafe fn read_texture(texture: *mut wlr_texture, data: &mut [u8]) -> bool {
+    if !is_pixman_texture(texture) {
+        return false
+    }
+    let texture = &*(texture as *mut PixmanTexture);
+    if data.len() < texture.width as usize * texture.height as usize * 4 {
+        return false
+    }
+    let target = pixman_image_create_bits(pixman_format_code_t::PIXMAN_a8b8g8r8,
+                                          texture.width,
+                                          texture.height,
+                                          data.as_mut_ptr() as *mut u32,
+                                          texture.width * 4);
+    if target.is_null() {
+        return false
+    }
+    pixman_image_composite32(pixman_op_t::PIXMAN_OP_SRC,
+                             texture.image,
+                             ptr::null_mut(),
+                             target,
+                             0,
+                             0,
+                             0,
+                             0,
+                             0,
+                             0,
+                             texture.width,
+                             texture.height);
+    pixman_image_unref(target);
+    true
+}
+
+unsafe extern "C" fn begin(renderer: *mut wlr_renderer, width: u32, height: u32) {
+    let this = renderer as *mut PixmanRenderer;
+    DRAWING.with(|drawing| drawing.set(this));
+    let renderer = &mut *this;
+    let surface = eglGetCurrentSurface(EGL_DRAW) as usize;
+    let (width, height) = (width as c_int, height as c_int);
+    let index = renderer.buffers.iter().position(|buffer| buffer.surface == surface);
+    let buffer = match index.map(|index| renderer.buffers.remove(index)) {
+        Some(ref buffer) if buffer.width != width || buffer.height != height => None,
+        buffer => buffer
+    };
+    renderer.current = ptr::null_mut();
+    renderer.width = width;
+    renderer.height = height;
+    let buffer = match buffer {
+        Some(buffer) => buffer,
+        None if width > 0 && height > 0 => match Buffer::new(surface, width, height) {
+            Some(buffer) => buffer,
+            None => {
+                wlr_log!(WLR_ERROR, "Could not allocate a {}x{} buffer", width, height);
+                return
+            }
+        },
+        None => return
+    };
+    renderer.current = buffer.image;
+    renderer.buffers.push(buffer);
+    if renderer.buffers.len() > MAX_BUFFERS {
+        renderer.buffers.remove(0);
+    }
+}
+
+unsafe extern "C" fn end(renderer: *mut wlr_renderer) {
+    // NOTE The buffer stays current, so the frame can still be read back
+    // (e.g. by the screenshooter) until the next one begins.
+    let renderer = &mut *(renderer as *mut PixmanRenderer);
+    if !renderer.current.is_null() {
+        pixman_image_set_clip_region32(renderer.current, ptr::null_mut());
+    }
+}
+
+unsafe extern "C" fn clear(renderer: *mut wlr_renderer, color: *const f32) {
+    let renderer = &mut *(renderer as *mut PixmanRenderer);
+    if renderer.current.is_null() {
+        return
+    }
+    let fill = solid_fill(color);
+    pixman_image_composite32(pixman_op_t::PIXMAN_OP_SRC,
+                             fill,
+                             ptr::null_mut(),
+                             renderer.current,
+                             0,
+                             0,
+                             0,
+                             0,
+                             0,
+                             0,
+                             renderer.width,
+                             renderer.height);
+    pixman_image_unref(fill);
+}
+
+unsafe extern "C" fn scissor(renderer: *mut wlr_renderer, area: *mut wlr_box) {
+    let renderer = &mut *(renderer as *mut PixmanRenderer);
+    if renderer.current.is_null() {
+        return
+    }
+    if area.is_null() {
+        pixman_image_set_clip_region32(renderer.current, ptr::null_mut());
+        return
+    }
+    let area = &*area;
+    // NOTE The box is in GL coordinates like with the GLES2 renderer, which
+    // start at the bottom.
+    let mut region: pixman_region32_t = mem::zeroed();
+    pixman_region32_init_rect(&mut region,
+                              area.x,
+                              renderer.height - area.y - area.height,
+                              area.width.max(0) as u32,
+                              area.height.max(0) as u32);
+    pixman_image_set_clip_region32(renderer.current, &mut region);
+    pixman_region32_fini(&mut region);
+}
+
+unsafe extern "C" fn render_texture_with_matrix(renderer: *mut wlr_renderer,
+                                                texture: *mut wlr_texture,
+                                                matrix: *const f32,
+                                                alpha: f32)
+                                                -> bool {
+    let renderer = &mut *(renderer as *mut PixmanRenderer);
+    if renderer.current.is_null() || !is_pixman_texture(texture) {
+        return false
+    }
+    let texture = &*(texture as *mut PixmanTexture);
+    let to_texture = scale(1.0 / texture.width as f64, 1.0 / texture.height as f64);
+    let forward = multiply(&multiply(&to_buffer(renderer), &read_matrix(matrix)), &to_texture);
+    let transform = match inverse_transform(&forward) {
+        Some(transform) => transform,
+        None => return false
+    };
+    let (x, y, width, height) = match bounds(renderer, &forward, texture.width, texture.height) {
+        Some(bounds) => bounds,
+        None => return true
+    };
+    // NOTE Textures drawn at their size can be copied as they are, which
+    // keeps their pixels exact.
+    let filter = if is_translation(&forward) {
+        pixman_filter_t::PIXMAN_FILTER_NEAREST
+    } else {
+        pixman_filter_t::PIXMAN_FILTER_BILINEAR
+    };
+    pixman_image_set_filter(texture.image, filter, ptr::null(), 0);
+    pixman_image_set_transform(texture.image, &transform);
+    let mask = if alpha < 1.0 {
+        solid_fill([0.0, 0.0, 0.0, alpha].as_ptr())
+    } else {
+        ptr::null_mut()
+    };
+    pixman_image_composite32(pixman_op_t::PIXMAN_OP_OVER,
+                             texture.image,
+                             mask,
+                             renderer.current,
+                             x,
+                             y,
+                             0,
+                             0,
+                             x,
+                             y,
+                             width,
+                             height);
+    if !mask.is_null() {
+        pixman_image_unref(mask);
+    }
+    pixman_image_set_transform(texture.image, ptr::null());
+    true
+}
+
+unsafe extern "C" fn render_quad_with_matrix(renderer: *mut wlr_renderer,
+                                             color: *const f32,
+                                             matrix: *const f32) {
+    let renderer = &mut *(renderer as *mut PixmanRenderer);
+    // NOTE The quad is the pixel of a 1x1 mask, stretched over it.
+    let mut data = [0xffff_ffff_u32];
+    render_masked(renderer, color, matrix, 1, &mut data, pixman_filter_t::PIXMAN_FILTER_NEAREST);
+}
+
+unsafe extern "C" fn render_ellipse_with_matrix(renderer: *mut wlr_renderer,
+                                                color: *const f32,
+                                                matrix: *const f32) {
+    let renderer = &mut *(renderer as *mut PixmanRenderer);
+    if renderer.current.is_null() {
+        return
+    }
+    let forward = multiply(&to_buffer(renderer), &read_matrix(matrix));
+    let size = match bounds(renderer, &forward, 1, 1) {
+        Some((_, _, width, height)) => width.max(height).max(1).min(MAX_ELLIPSE_MASK),
+        None => return
+    };
+    let stride = (size as usize + 3) / 4 * 4;
+    let mut data = vec![0_u32; stride / 4 * size as usize];
+    {
+        let mask = slice::from_raw_parts_mut(data.as_mut_ptr() as *mut u8, stride * size as usize);
+        for y in 0..size as usize {
+            for x in 0..size as usize {
+                let dx = (x as f64 + 0.5) / size as f64 - 0.5;
+                let dy = (y as f64 + 0.5) / size as f64 - 0.5;
+                if dx * dx + dy * dy <= 0.25 {
+                    mask[y * stride + x] = 0xff;
+                }
+            }
+        }
+    }
+    render_masked(renderer,
+                  color,
+                  matrix,
+                  size,
+                  &mut data,
+                  pixman_filter_t::PIXMAN_FILTER_BILINEAR);
+}
+
+/// Fill the unit square the matrix projects with the color, through the
+/// `size` by `size` alpha mask in `data`.
+unsafe fn render_masked(renderer: &mut PixmanRenderer,
+                        color: *const f32,
+                        matrix: *const f32,
+                        size: c_int,
+                        data: &mut [u32],
+                        filter: pixman_filter_t) {
+    if renderer.current.is_null() {
+        return
+    }
+    let to_mask = scale(1.0 / size as f64, 1.0 / size as f64);
+    let forward = multiply(&multiply(&to_buffer(renderer), &read_matrix(matrix)), &to_mask);
+    let transform = match inverse_transform(&forward) {
+        Some(transform) => transform,
+        None => return
+    };
+    let (x, y, width, height) = match bounds(renderer, &forward, size, size) {
+        Some(bounds) => bounds,
+        None => return
+    };
+    let stride = (size + 3) / 4 * 4;
+    let mask = pixman_image_create_bits(pixman_format_code_t::PIXMAN_a8,
+                                        size,
+                                        size,
+                                        data.as_mut_ptr(),
+                                        stride);
+    if mask.is_null() {
+        return
+    }
+    pixman_image_set_filter(mask, filter, ptr::null(), 0);
+    pixman_image_set_transform(mask, &transform);
+    let fill = solid_fill(color);
+    pixman_image_composite32(pixman_op_t::PIXMAN_OP_OVER,
+                             fill,
+                             mask,
+                             renderer.current,
+                             0,
+                             0,
+                             x,
+                             y,
+                             x,
+                             y,
+                             width,
+                             height);
+    pixman_image_unref(fill);
+    pixman_image_unref(mask);
+}
+
+unsafe extern "C" fn formats(_renderer: *mut wlr_renderer,
+                             len: *mut usize)
+                             -> *const wl_shm_format {
+    *len = FORMATS.len();
+    FORMATS.as_ptr()
+}
+
+unsafe extern "C" fn format_supported(_renderer: *mut wlr_renderer,
+                                      format: wl_shm_format)
+                                      -> bool {
+    pixman_format(format).is_some()
+}
+
+unsafe extern "C" fn texture_from_pixels(_renderer: *mut wlr_renderer,
+                                         format: wl_shm_format,
+                                         stride: u32,
+                                         width: u32,
+                                         height: u32,
+                                         data: *const c_void)
+                                         -> *mut wlr_texture {
+    let pixman_format = match pixman_format(format) {
+        Some(pixman_format) => pixman_format,
+        None => {
+            wlr_log!(WLR_ERROR, "Unsupported texture format {:?}", format);
+            return ptr::null_mut()
+        }
+    };
+    if width == 0 || height == 0 || (stride as u64) < width as u64 * 4 {
+        return ptr::null_mut()
+    }
+    let (width, height, stride) = (width as usize, height as usize, stride as usize);
+    let mut pixels = vec![0_u32; width * height];
+    let source = slice::from_raw_parts(data as *const u8, stride * height);
+    for row in 0..height {
+        ptr::copy_nonoverlapping(source[row * stride..].as_ptr(),
+                                 pixels[row * width..].as_mut_ptr() as *mut u8,
+                                 width * 4);
+    }
+    let image = pixman_image_create_bits(pixman_format,
+                                         width as c_int,
+                                         height as c_int,
+                                         pixels.as_mut_ptr(),
+                                         width as c_int * 4);
+    if image.is_null() {
+        return ptr::null_mut()
+    }
+    let mut texture = Box::new(PixmanTexture { texture: mem::zeroed(),
+                                               texture_impl: mem::zeroed(),
+                                               image,
+                                               _data: pixels,
+                                               width: width as c_int,
+                                               height: height as c_int });
+    texture.texture_impl.get_size = Some(texture_get_size);
+    texture.texture_impl.write_pixels = Some(texture_write_pixels);
+    texture.texture_impl.destroy = Some(texture_destroy);
+    let texture = Box::into_raw(texture);
+    wlr_texture_init(&mut (*texture).texture, &(*texture).texture_impl);
+    texture as *mut wlr_texture
+}
+
+unsafe extern "C" fn read_pixels(renderer: *mut wlr_renderer,
+                                 format: wl_shm_format,
+                                 flags: *mut u32,
+                                 stride: u32,
+                                 width: u32,
+                                 height: u32,
+                                 src_x: u32,
+                                 src_y: u32,
+                                 dst_x: u32,
+                                 dst_y: u32,
+                                 data: *mut c_void)
+                                 -> bool {
+    let renderer = &mut *(renderer as *mut PixmanRenderer);
+    let pixman_format = match pixman_format(format) {
+        Some(pixman_format) => pixman_format,
+        None => return false
+    };
+    if renderer.current.is_null() || stride % 4 != 0 {
+        return false
+    }
+    let fits = |offset: u32, length: u32, size: c_int| {
+        offset.checked_add(length).map_or(false, |end| end as i64 <= size as i64)
+    };
+    if !fits(src_x, width, renderer.width) || !fits(src_y, height, renderer.height) {
+        return false
+    }
+    let target = pixman_image_create_bits(pixman_format,
+                                          (dst_x + width) as c_int,
+                                          (dst_y + height) as c_int,
+                                          data as *mut u32,
+                                          stride as c_int);
+    if target.is_null() {
+        return false
+    }
+    pixman_image_composite32(pixman_op_t::PIXMAN_OP_SRC,
+                             renderer.current,
+                             ptr::null_mut(),
+                             target,
+                             src_x as i32,
+                             src_y as i32,
+                             0,
+                             0,
+                             dst_x as i32,
+                             dst_y as i32,
+                             width as i32,
+                             height as i32);
+    pixman_image_unref(target);
+    // NOTE Unlike with GL the rows are stored top down.
+    if !flags.is_null() {
+        *flags = 0;
+    }
+    true
+}
+
+unsafe extern "C" fn destroy(renderer: *mut wlr_renderer) {
+    let renderer = renderer as *mut PixmanRenderer;
+    DRAWING.with(|drawing| {
+                     if drawing.get() == renderer {
+                         drawing.set(ptr::null_mut())
+                     }
+                 });
+    drop(Box::from_raw(renderer));
+}
+
+unsafe extern "C" fn texture_get_size(texture: *mut wlr_texture,
+                                      width: *mut c_int,
+                                      height: *mut c_int) {
+    let texture = &*(texture as *mut PixmanTexture);
+    *width = texture.width;
+    *height = texture.height;
+}
+
+unsafe extern "C" fn texture_write_pixels(texture: *mut wlr_texture,
+                                          format: wl_shm_format,
+                                          stride: u32,
+                                          width: u32,
+                                          height: u32,
+                                          src_x: u32,
+                                          src_y: u32,
+                                          dst_x: u32,
+                                          dst_y: u32,
+                                          data: *const c_void)
+                                          -> bool {
+    let texture = &mut *(texture as *mut PixmanTexture);
+    let pixman_format = match pixman_format(format) {
+        Some(pixman_format) => pixman_format,
+        None => return false
+    };
+    if stride % 4 != 0 {
+        return false
+    }
+    // NOTE The pixels are only read from.
+    let source = pixman_image_create_bits(pixman_format,
+                                          (src_x + width) as c_int,
+                                          (src_y + height) as c_int,
+                                          data as *mut u32,
+                                          stride as c_int);
+    if source.is_null() {
+        return false
+    }
+    pixman_image_composite32(pixman_op_t::PIXMAN_OP_SRC,
+                             source,
+                             ptr::null_mut(),
+                             texture.image,
+                             src_x as i32,
+                             src_y as i32,
+                             0,
+                             0,
+                             dst_x as i32,
+                             dst_y as i32,
+                             width as i32,
+                             height as i32);
+    pixman_image_unref(source);
+    true
+}
+
+unsafe extern "C" fn texture_destroy(texture: *mut wlr_texture) {
+    drop(Box::from_raw(texture as *mut PixmanTexture));
+}
+
+fn pixman_format(format: wl_shm_format) -> Option<pixman_format_code_t> {
+    // NOTE The wl_shm formats are little endian, pixman's are in the byte
+    // order of the machine.
+    match format {
+        wl_shm_format::WL_SHM_FORMAT_ARGB8888 => Some(pixman_format_code_t::PIXMAN_a8r8g8b8),
+        wl_shm_format::WL_SHM_FORMAT_XRGB8888 => Some(pixman_format_code_t::PIXMAN_x8r8g8b8),
+        wl_shm_format::WL_SHM_FORMAT_ABGR8888 => Some(pixman_format_code_t::PIXMAN_a8b8g8r8),
+        wl_shm_format::WL_SHM_FORMAT_XBGR8888 => Some(pixman_format_code_t::PIXMAN_x8b8g8r8),
+        _ => None
+    }
+}
+
+/// Make a solid image of the premultiplied color.
+unsafe fn solid_fill(color: *const f32) -> *mut pixman_image_t {
+    let color = slice::from_raw_parts(color, 4);
+    let channel = |value: f32| (value.max(0.0).min(1.0) * 65535.0).round() as u16;
+    let color = pixman_color { red: channel(color[0]),
+                               green: channel(color[1]),
+                               blue: channel(color[2]),
+                               alpha: channel(color[3]) };
+    pixman_image_create_solid_fill(&color)
+}
+
+unsafe fn read_matrix(matrix: *const f32) -> Matrix {
+    let matrix = slice::from_raw_parts(matrix, 9);
+    let mut read = [0.0; 9];
+    for (read, value) in read.iter_mut().zip(matrix) {
+        *read = *value as f64;
+    }
+    read
+}
+
+/// The matrix from the GL coordinates the matrices of wlroots project to
+/// (from -1 to 1, bottom up) to the pixels of the buffer (top down).
+fn to_buffer(renderer: &PixmanRenderer) -> Matrix {
+    let (width, height) = (renderer.width as f64, renderer.height as f64);
+    [width / 2.0, 0.0, width / 2.0,
+     0.0, -height / 2.0, height / 2.0,
+     0.0, 0.0, 1.0]
+}
+
+fn scale(x: f64, y: f64) -> Matrix {
+    [x, 0.0, 0.0,
+     0.0, y, 0.0,
+     0.0, 0.0, 1.0]
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut product = [0.0; 9];
+    for row in 0..3 {
+        for column in 0..3 {
+            product[row * 3 + column] = (0..3).map(|i| a[row * 3 + i] * b[i * 3 + column]).sum();
+        }
+    }
+    product
+}
+
+/// Determine if the matrix only moves by whole pixels.
+fn is_translation(matrix: &Matrix) -> bool {
+    let whole = |value: f64| (value - value.round()).abs() < 1e-6;
+    let close = |value: f64, expected: f64| (value - expected).abs() < 1e-6;
+    close(matrix[0], 1.0) && close(matrix[1], 0.0) && close(matrix[3], 0.0)
+    && close(matrix[4], 1.0) && whole(matrix[2]) && whole(matrix[5])
+}
+
+/// Get the transform from the pixels of the buffer to the pixels of the
+/// image the matrix places on the buffer, which is what pixman samples with.
+unsafe fn inverse_transform(forward: &Matrix) -> Option<pixman_transform> {
+    let mut matrix: pixman_f_transform = mem::zeroed();
+    for row in 0..3 {
+        for column in 0..3 {
+            matrix.m[row][column] = forward[row * 3 + column];
+        }
+    }
+    let mut inverse: pixman_f_transform = mem::zeroed();
+    if pixman_f_transform_invert(&mut inverse, &matrix) == 0 {
+        return None
+    }
+    let mut transform: pixman_transform = mem::zeroed();
+    if pixman_transform_from_pixman_f_transform(&mut transform, &inverse) == 0 {
+        return None
+    }
+    Some(transform)
+}
+
+/// Get the area of the buffer covered by an image of the size placed with
+/// the matrix, as x, y, width and height.
+///
+/// Returns `None` if none of it is on the buffer.
+fn bounds(renderer: &PixmanRenderer,
+          forward: &Matrix,
+          width: c_int,
+          height: c_int)
+          -> Option<(c_int, c_int, c_int, c_int)> {
+    let corners = [(0.0, 0.0), (width as f64, 0.0), (0.0, height as f64),
+                   (width as f64, height as f64)];
+    let (mut x1, mut y1, mut x2, mut y2) = (::std::f64::MAX, ::std::f64::MAX,
+                                            ::std::f64::MIN, ::std::f64::MIN);
+    for &(x, y) in &corners {
+        let w = forward[6] * x + forward[7] * y + forward[8];
+        if w.abs() < ::std::f64::EPSILON {
+            return None
+        }
+        let (x, y) = ((forward[0] * x + forward[1] * y + forward[2]) / w,
+                      (forward[3] * x + forward[4] * y + forward[5]) / w);
+        x1 = x1.min(x);
+        y1 = y1.min(y);
+        x2 = x2.max(x);
+        y2 = y2.max(y);
+    }
+    let x1 = (x1.floor().max(0.0) as c_int).min(renderer.width);
+    let y1 = (y1.floor().max(0.0) as c_int).min(renderer.height);
+    let x2 = (x2.ceil().max(0.0) as c_int).min(renderer.width);
+    let y2 = (y2.ceil().max(0.0) as c_int).min(renderer.height);
+    if x2 <= x1 || y2 <= y1 {
+        return None
+    }
+    Some((x1, y1, x2 - x1, y2 - y1))
+}
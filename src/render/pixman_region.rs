@@ -1,8 +1,14 @@
-use std::mem;
+use std::{mem, slice};
 
 use libc::{c_int, c_uint};
-use wlroots_sys::{pixman_region32_fini, pixman_region32_init,
-                  pixman_region32_t, pixman_region32_union_rect};
+use wlroots_sys::{pixman_region32_contains_rectangle, pixman_region32_copy,
+                  pixman_region32_fini, pixman_region32_init, pixman_region32_intersect,
+                  pixman_region32_not_empty, pixman_region32_rectangles,
+                  pixman_region32_subtract, pixman_region32_t, pixman_region32_translate,
+                  pixman_region32_union, pixman_region32_union_rect, pixman_box32_t,
+                  pixman_region_overlap_t::PIXMAN_REGION_IN};
+
+use area::{Area, Origin, Size};
 
 /// A pixman region, used for damage tracking.
 #[derive(Debug)]
@@ -28,6 +34,78 @@ impl PixmanRegion {
             pixman_region32_union_rect(region_ptr, region_ptr, x, y, width, height);
         }
     }
+
+    /// Make a copy of the raw region.
+    pub(crate) unsafe fn from_raw(region: *const pixman_region32_t) -> Self {
+        let mut copy = PixmanRegion::new();
+        pixman_region32_copy(&mut copy.region, region as *mut _);
+        copy
+    }
+
+    /// Add the other region to this one.
+    pub fn union(&mut self, other: &PixmanRegion) {
+        unsafe {
+            let region_ptr = &mut self.region as *mut _;
+            pixman_region32_union(region_ptr, region_ptr, &other.region as *const _ as *mut _);
+        }
+    }
+
+    /// Only keep the part of this region that is in the other one too.
+    pub fn intersect(&mut self, other: &PixmanRegion) {
+        unsafe {
+            let region_ptr = &mut self.region as *mut _;
+            pixman_region32_intersect(region_ptr, region_ptr, &other.region as *const _ as *mut _);
+        }
+    }
+
+    /// Remove the other region from this one.
+    pub fn subtract(&mut self, other: &PixmanRegion) {
+        unsafe {
+            let region_ptr = &mut self.region as *mut _;
+            pixman_region32_subtract(region_ptr, region_ptr, &other.region as *const _ as *mut _);
+        }
+    }
+
+    /// Move the region by the offset.
+    pub fn translate(&mut self, dx: c_int, dy: c_int) {
+        unsafe { pixman_region32_translate(&mut self.region, dx, dy) }
+    }
+
+    /// Determines if the region covers nothing.
+    pub fn is_empty(&self) -> bool {
+        unsafe { !pixman_region32_not_empty(&self.region as *const _ as *mut _) }
+    }
+
+    /// Determines if the area is entirely inside of the region.
+    pub fn contains_area(&self, area: Area) -> bool {
+        if area.size.width <= 0 || area.size.height <= 0 {
+            return true
+        }
+        unsafe {
+            let mut rect = pixman_box32_t { x1: area.origin.x,
+                                            y1: area.origin.y,
+                                            x2: area.origin.x + area.size.width,
+                                            y2: area.origin.y + area.size.height };
+            pixman_region32_contains_rectangle(&self.region as *const _ as *mut _, &mut rect) ==
+                PIXMAN_REGION_IN
+        }
+    }
+
+    /// Get the rectangles that make up the region.
+    pub fn rectangles(&self) -> Vec<Area> {
+        unsafe {
+            let mut count = 0;
+            let region_ptr = &self.region as *const _ as *mut _;
+            let boxes = pixman_region32_rectangles(region_ptr, &mut count);
+            if boxes.is_null() || count <= 0 {
+                return Vec::new()
+            }
+            slice::from_raw_parts(boxes, count as usize).iter()
+                .map(|rect| Area::new(Origin::new(rect.x1, rect.y1),
+                                      Size::new(rect.x2 - rect.x1, rect.y2 - rect.y1)))
+                .collect()
+        }
+    }
 }
 
 impl Drop for PixmanRegion {
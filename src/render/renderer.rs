@@ -1,16 +1,71 @@
 //! TODO Documentation
 
-use std::{ptr, time::Duration};
+use std::{cell::Cell, error::Error, fmt, marker::PhantomData, ptr, rc::Rc, time::Duration};
 
 
 use libc::{c_float, c_int, c_void};
-use wlroots_sys::{wl_shm_format, wlr_backend, wlr_backend_get_renderer,
-                  wlr_render_ellipse_with_matrix, wlr_render_quad_with_matrix, wlr_render_rect,
+use wlroots_sys::{wl_shm_format, wlr_backend, wlr_backend_get_egl, wlr_backend_get_renderer,
+                  wlr_egl, wlr_render_ellipse_with_matrix, wlr_render_quad_with_matrix, wlr_render_rect,
                   wlr_render_texture, wlr_render_texture_with_matrix, wlr_renderer,
                   wlr_renderer_begin, wlr_renderer_clear, wlr_renderer_destroy, wlr_renderer_end,
+                  wlr_renderer_read_pixels,
                   wlr_texture_from_pixels, wlr_texture_destroy, wlr_renderer_scissor};
 
-use {area::Area, output::Output, render::{PixmanRegion, texture::Texture}};
+use {area::{Area, Origin, Size}, output::{self, Output}, surface::Surface,
+     render::{pixman, PixmanRegion, RenderPass, SurfaceDraw, texture::Texture}};
+
+/// The flag `wlr_renderer_read_pixels` sets when the rows are upside down.
+pub(crate) const READ_PIXELS_Y_INVERT: u32 = 1;
+/// Taken from GLES2/gl2.h.
+const GL_BLEND: u32 = 0x0BE2;
+const GL_ONE: u32 = 1;
+const GL_ONE_MINUS_SRC_ALPHA: u32 = 0x0303;
+
+extern "C" {
+    // NOTE The GLES2 renderer of wlroots leaves blending on for everything.
+    fn glEnable(cap: u32);
+    fn glDisable(cap: u32);
+    fn glBlendFunc(sfactor: u32, dfactor: u32);
+    fn glViewport(x: i32, y: i32, width: i32, height: i32);
+}
+
+/// The ways setting up rendering can fail.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum RenderError {
+    /// There's no EGL (or no renderer at all) available on the backend.
+    NoEGL,
+    /// The output's rendering context could not be made current, e.g.
+    /// because the GPU was reset.
+    ContextLost,
+    /// The output was destroyed (e.g. unplugged).
+    OutputGone,
+    /// The renderer can't do it, e.g. running GL code with the pixman
+    /// renderer, or using the pixman renderer on a backend other than the
+    /// headless one.
+    Unsupported
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RenderError::NoEGL => write!(f, "no EGL renderer available"),
+            RenderError::ContextLost => write!(f, "could not make the output context current"),
+            RenderError::OutputGone => write!(f, "the output was destroyed"),
+            RenderError::Unsupported => write!(f, "not supported by the renderer")
+        }
+    }
+}
+
+impl Error for RenderError {
+    fn description(&self) -> &str {
+        match *self {
+            RenderError::NoEGL => "No EGL renderer available",
+            RenderError::ContextLost => "Could not make the output context current",
+            RenderError::OutputGone => "The output was destroyed",
+            RenderError::Unsupported => "Not supported by the renderer"
+        }
+    }
+}
 
 /// A generic interface for rendering to the screen.
 ///
@@ -18,7 +73,21 @@ use {area::Area, output::Output, render::{PixmanRegion, texture::Texture}};
 /// at the same time.
 #[derive(Debug)]
 pub struct GenericRenderer {
-    renderer: *mut wlr_renderer
+    renderer: *mut wlr_renderer,
+    egl: *mut wlr_egl,
+    /// Cleared once the renderer is destroyed, for what outlives it.
+    liveliness: Rc<Cell<bool>>
+}
+
+/// The raw EGL handles the renderer draws with, for running custom GL code.
+///
+/// Load the GL functions with `eglGetProcAddress` from the EGL library of
+/// your choice. It borrows the renderer, as the handles are only valid for
+/// as long as it is.
+#[derive(Debug, Eq, PartialEq)]
+pub struct GlContext<'renderer> {
+    egl: *mut wlr_egl,
+    phantom: PhantomData<&'renderer GenericRenderer>
 }
 
 /// The state machine type that allows you to manipulate a screen and
@@ -29,18 +98,46 @@ pub struct GenericRenderer {
 #[derive(Debug)]
 pub struct Renderer<'output> {
     renderer: *mut wlr_renderer,
+    egl: *mut wlr_egl,
+    liveliness: Rc<Cell<bool>>,
     pub damage: Option<(PixmanRegion, Duration)>,
     pub output: &'output mut Output
 }
 
 impl GenericRenderer {
     /// Make a gles2 renderer.
-    pub(crate) unsafe fn gles2_renderer(backend: *mut wlr_backend) -> Self {
+    pub(crate) unsafe fn gles2_renderer(backend: *mut wlr_backend)
+                                        -> Result<Self, RenderError> {
         let renderer = wlr_backend_get_renderer(backend);
-        if renderer.is_null() {
-            panic!("Could not construct GLES2 renderer");
+        let egl = wlr_backend_get_egl(backend);
+        if renderer.is_null() || egl.is_null() {
+            return Err(RenderError::NoEGL)
         }
-        GenericRenderer { renderer }
+        Ok(GenericRenderer { renderer,
+                             egl,
+                             liveliness: Rc::new(Cell::new(true)) })
+    }
+
+    /// Get the pixman renderer the backend was set up with (see
+    /// `render::pixman::setup`).
+    pub(crate) unsafe fn pixman_renderer(backend: *mut wlr_backend)
+                                         -> Result<Self, RenderError> {
+        let renderer = wlr_backend_get_renderer(backend);
+        if !pixman::is_pixman(renderer) {
+            return Err(RenderError::Unsupported)
+        }
+        Ok(GenericRenderer { renderer,
+                             egl: wlr_backend_get_egl(backend),
+                             liveliness: Rc::new(Cell::new(true)) })
+    }
+
+    /// Get the EGL handles of the renderer.
+    ///
+    /// Nothing guarantees the context is current outside of
+    /// `Renderer::with_gl`.
+    pub fn gl_context(&self) -> GlContext {
+        GlContext { egl: self.egl,
+                    phantom: PhantomData }
     }
 
     /// Drops a texture that was created explicitly through the renderer.
@@ -54,20 +151,35 @@ impl GenericRenderer {
 
     /// Make the `Renderer` state machine type.
     ///
-    /// This automatically makes the given output the current output.
+    /// This automatically makes the given output the current output, which
+    /// fails if the output is gone or its context was lost. Nothing is drawn
+    /// in that case.
+    ///
+    /// The `pre_pass` of the render passes of the output run before this
+    /// returns.
     pub fn render<'output, T>(&mut self,
                               output: &'output mut Output,
                               damage: T)
-                              -> Renderer<'output>
+                              -> Result<Renderer<'output>, RenderError>
         where T: Into<Option<(PixmanRegion, Duration)>>
     {
+        if output.is_destroyed() {
+            return Err(RenderError::OutputGone)
+        }
         unsafe {
-            output.make_current();
+            let (current, _) = output.make_current();
+            if !current {
+                return Err(RenderError::ContextLost)
+            }
             let (width, height) = output.size();
             wlr_renderer_begin(self.renderer, width, height);
-            Renderer { renderer: self.renderer,
-                       damage: damage.into(),
-                       output }
+            let mut renderer = Renderer { renderer: self.renderer,
+                                          egl: self.egl,
+                                          liveliness: self.liveliness.clone(),
+                                          damage: damage.into(),
+                                          output };
+            renderer.run_passes(|pass, renderer| pass.pre_pass(renderer));
+            Ok(renderer)
         }
     }
 
@@ -92,15 +204,113 @@ impl GenericRenderer {
     pub(crate) unsafe fn as_ptr(&self) -> *mut wlr_renderer {
         self.renderer
     }
+
+    pub(crate) unsafe fn egl(&self) -> *mut wlr_egl {
+        self.egl
+    }
+
+    /// Get a flag that's cleared once the renderer is destroyed.
+    pub(crate) fn liveliness(&self) -> Rc<Cell<bool>> {
+        self.liveliness.clone()
+    }
 }
 
 impl Drop for GenericRenderer {
     fn drop(&mut self) {
+        self.liveliness.set(false);
         unsafe { wlr_renderer_destroy(self.renderer) }
     }
 }
 
+impl<'renderer> GlContext<'renderer> {
+    /// Get the `EGLDisplay`.
+    pub fn display(&self) -> *mut c_void {
+        unsafe { (*self.egl).display as *mut c_void }
+    }
+
+    /// Get the `EGLContext`.
+    pub fn context(&self) -> *mut c_void {
+        unsafe { (*self.egl).context as *mut c_void }
+    }
+
+    /// Get the `EGLConfig` the context was created with.
+    pub fn config(&self) -> *mut c_void {
+        unsafe { (*self.egl).config as *mut c_void }
+    }
+}
+
 impl<'output> Renderer<'output> {
+    /// Run custom GL code (e.g. a blur shader) in the middle of rendering.
+    ///
+    /// The context of the output is made current for the closure, which
+    /// draws into the same buffer as the rest of the frame. Afterwards the
+    /// viewport and blending are set up again; the renderer binds its own
+    /// programs and textures for every draw anyway. Anything else the closure
+    /// changes (e.g. the bound framebuffer) it has to restore itself.
+    ///
+    /// Fails without running the closure if the context could not be made
+    /// current, or with `RenderError::Unsupported` if the compositor draws
+    /// with the pixman renderer.
+    pub fn with_gl<F, R>(&mut self, f: F) -> Result<R, RenderError>
+        where F: FnOnce(&GlContext) -> R
+    {
+        unsafe {
+            if pixman::is_pixman(self.renderer) {
+                return Err(RenderError::Unsupported)
+            }
+            let (current, _) = self.output.make_current();
+            if !current {
+                return Err(RenderError::ContextLost)
+            }
+        }
+        let gl = GlContext { egl: self.egl,
+                             phantom: PhantomData };
+        let res = f(&gl);
+        unsafe {
+            // NOTE What `wlr_renderer_begin` sets up, which can't be called
+            // again in the middle of the frame.
+            let (width, height) = self.output.size();
+            glViewport(0, 0, width, height);
+            glEnable(GL_BLEND);
+            glBlendFunc(GL_ONE, GL_ONE_MINUS_SRC_ALPHA);
+        }
+        Ok(res)
+    }
+
+    /// Get a flag that's cleared once the renderer is destroyed, for the
+    /// textures that are kept from frame to frame.
+    pub(crate) fn liveliness(&self) -> Rc<Cell<bool>> {
+        self.liveliness.clone()
+    }
+
+    /// Get the age of the buffer being rendered to, see `Output::buffer_age`.
+    pub fn buffer_age(&self) -> Option<c_int> {
+        self.output.buffer_age()
+    }
+
+    /// Get the region of the buffer that has to be repainted, which is the
+    /// damage given to `GenericRenderer::render` plus the damage of the
+    /// frames the buffer missed because of its age.
+    ///
+    /// Returns `None` when the whole output has to be repainted, e.g. because
+    /// no damage was given or the age of the buffer isn't known.
+    pub fn repaint_region(&self) -> Option<PixmanRegion> {
+        let damage = self.damage.as_ref()?;
+        self.output.accumulated_damage(&damage.0)
+    }
+
+    /// Run the render passes of the output. Passes added while they run only
+    /// take part from the next call on.
+    fn run_passes<F>(&mut self, mut f: F)
+        where F: FnMut(&mut RenderPass, &mut Renderer)
+    {
+        let mut passes = self.output.take_render_passes();
+        for pass in &mut passes {
+            f(&mut **pass, self)
+        }
+        self.output.restore_render_passes(passes);
+    }
+
     /// Create a texture using this renderer.
     pub fn create_texture_from_pixels(&mut self,
                                       format: wl_shm_format,
@@ -163,6 +373,88 @@ impl<'output> Renderer<'output> {
         }
     }
 
+    /// Render the texture of the surface with the matrix, running the
+    /// `surface` hook of the render passes of the output first.
+    ///
+    /// Surfaces that are entirely opaque are drawn without blending, unless
+    /// a render pass made them translucent.
+    ///
+    /// Returns false if the surface has no texture or wasn't drawn.
+    pub fn render_surface(&mut self, surface: &mut Surface, matrix: [f32; 9]) -> bool {
+        let mut draw = SurfaceDraw { matrix, alpha: 1.0, visible: true };
+        self.run_passes(|pass, renderer| pass.surface(renderer, surface, &mut draw));
+        if !draw.visible {
+            return false
+        }
+        let opaque = draw.alpha >= 1.0 && surface.is_opaque();
+        match surface.texture() {
+            Some(texture) => unsafe {
+                if opaque {
+                    glDisable(GL_BLEND);
+                }
+                let res = wlr_render_texture_with_matrix(self.renderer,
+                                                         texture.as_ptr(),
+                                                         draw.matrix.as_ptr(),
+                                                         draw.alpha);
+                if opaque {
+                    glEnable(GL_BLEND);
+                }
+                res
+            },
+            None => false
+        }
+    }
+
+    /// Read the pixels in the area of the buffer being rendered to into
+    /// `data`, which is laid out with `stride` bytes per row.
+    ///
+    /// Only the 32 bit RGB formats (e.g. `WL_SHM_FORMAT_ARGB8888`) can be
+    /// read.
+    ///
+    /// Returns `None` if the pixels could not be read, e.g. because the area
+    /// isn't within the buffer or `data` is too small for it, or whether the
+    /// rows were read bottom to top (i.e. the image is upside down).
+    pub fn read_pixels(&mut self,
+                       format: wl_shm_format,
+                       stride: u32,
+                       area: Area,
+                       data: &mut [u8])
+                       -> Option<bool> {
+        let Area { origin: Origin { x, y }, size: Size { width, height } } = area;
+        if x < 0 || y < 0 || width <= 0 || height <= 0 {
+            return None
+        }
+        let (buffer_width, buffer_height) = self.output.size();
+        if x.checked_add(width)? > buffer_width || y.checked_add(height)? > buffer_height {
+            return None
+        }
+        if stride < (width as u32).checked_mul(bytes_per_pixel(format)?)? {
+            return None
+        }
+        if data.len() < (stride as usize).checked_mul(height as usize)? {
+            return None
+        }
+        let mut flags = 0;
+        let read = unsafe {
+            wlr_renderer_read_pixels(self.renderer,
+                                     format,
+                                     &mut flags,
+                                     stride,
+                                     width as u32,
+                                     height as u32,
+                                     x as u32,
+                                     y as u32,
+                                     0,
+                                     0,
+                                     data.as_mut_ptr() as *mut c_void)
+        };
+        if read {
+            Some(flags & READ_PIXELS_Y_INVERT != 0)
+        } else {
+            None
+        }
+    }
+
     /// Defines a scissor box. Only pixels that lie within the scissor box can be
     /// modified by drawing functions.
     ///
@@ -189,14 +481,88 @@ impl<'output> Renderer<'output> {
     pub fn render_colored_rect(&mut self, area: Area, color: [f32; 4], matrix: [f32; 9]) {
         unsafe { wlr_render_rect(self.renderer, &area.into(), color.as_ptr(), matrix.as_ptr()) }
     }
+
+    /// Renders a bar graph of the recent render times of the output in its
+    /// bottom left corner.
+    ///
+    /// Each bar is one frame, and its height is relative to the refresh
+    /// period of the output, which is marked with a white line. Frames that
+    /// missed the refresh period are drawn in red.
+    ///
+    /// This is done automatically when the buffers are swapped if
+    /// `Output::set_frame_stats_overlay` is enabled.
+    pub fn render_frame_stats(&mut self) {
+        const BAR_WIDTH: c_int = 3;
+        const GRAPH_HEIGHT: c_int = 60;
+        let stats = self.output.frame_stats();
+        let period = stats.refresh_period()
+                          .or_else(|| stats.average_vblank_interval())
+                          .unwrap_or_else(|| Duration::from_millis(16));
+        let period_nanos = duration_nanos(period).max(1);
+        let (_, height) = self.output.effective_resolution();
+        let matrix = self.output.transform_matrix();
+        // NOTE The scissor box is in output coordinates, so make sure it's not in
+        // effect when drawing the overlay.
+        self.render_scissor(None);
+        let bottom = height;
+        let period_line = bottom - GRAPH_HEIGHT / 2;
+        for (index, render_time) in stats.render_times().enumerate() {
+            let bar_height = (duration_nanos(*render_time) * (GRAPH_HEIGHT as u64 / 2) /
+                              period_nanos).min(GRAPH_HEIGHT as u64) as c_int;
+            let color = if *render_time > period {
+                [1.0, 0.0, 0.0, 0.8]
+            } else {
+                [0.0, 1.0, 0.0, 0.8]
+            };
+            let area = Area::new(Origin::new(index as c_int * BAR_WIDTH, bottom - bar_height),
+                                 Size::new(BAR_WIDTH - 1, bar_height.max(1)));
+            self.render_colored_rect(area, color, matrix);
+        }
+        let width = stats.render_times().count() as c_int * BAR_WIDTH;
+        if let Some((ref mut damage, _)) = self.damage {
+            damage.rectangle(0, bottom - GRAPH_HEIGHT, width as _, GRAPH_HEIGHT as _);
+        }
+        self.render_colored_rect(Area::new(Origin::new(0, period_line), Size::new(width, 1)),
+                                 [1.0, 1.0, 1.0, 0.8],
+                                 matrix);
+    }
+}
+
+fn duration_nanos(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000_000 + duration.subsec_nanos() as u64
+}
+
+/// Get the size of a pixel in the format, if `wlr_renderer_read_pixels` can
+/// read it.
+fn bytes_per_pixel(format: wl_shm_format) -> Option<u32> {
+    match format {
+        wl_shm_format::WL_SHM_FORMAT_ARGB8888 |
+        wl_shm_format::WL_SHM_FORMAT_XRGB8888 |
+        wl_shm_format::WL_SHM_FORMAT_ABGR8888 |
+        wl_shm_format::WL_SHM_FORMAT_XBGR8888 => Some(4),
+        _ => None
+    }
 }
 
 impl<'output> Drop for Renderer<'output> {
     fn drop(&mut self) {
+        if self.output.is_destroyed() {
+            // NOTE The output went away while rendering, so there's nothing
+            // to swap the buffers of.
+            unsafe { wlr_renderer_end(self.renderer) };
+            return
+        }
+        self.run_passes(|pass, renderer| pass.post_pass(renderer));
+        unsafe { output::capture_for_mirrors(self) };
+        if self.output.frame_stats_overlay() {
+            self.render_frame_stats();
+        }
         unsafe {
             if let Some((mut damage, when)) = self.damage.take() {
+                self.output.push_damage(Some(&damage));
                 self.output.swap_buffers(Some(when), Some(&mut damage));
             } else {
+                self.output.push_damage(None);
                 self.output.swap_buffers(None, None);
             }
             wlr_renderer_end(self.renderer);
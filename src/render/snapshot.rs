@@ -0,0 +1,286 @@
+//! Copying what a surface and its subsurfaces show into a texture, so it can
+//! be drawn after the client is gone (e.g. for a close animation).
+//!
+//! The surfaces are drawn into a framebuffer of their own, outside of the
+//! rendering of any output, and read back into a texture of the renderer, or
+//! into memory (e.g. to capture a single window).
+
+use std::{cell::Cell, fmt, ptr, rc::Rc};
+
+use libc::{c_int, c_void};
+use wlroots_sys::{wl_output_transform, wl_shm_format, wlr_egl_make_current,
+                  wlr_output_transform_invert, wlr_render_texture_with_matrix, wlr_renderer_begin,
+                  wlr_renderer_clear, wlr_renderer_end, wlr_renderer_read_pixels, wlr_surface,
+                  wlr_surface_for_each_surface, wlr_surface_get_texture, wlr_texture,
+                  wlr_texture_destroy, wl_output_transform::WL_OUTPUT_TRANSFORM_NORMAL};
+
+use {area::{Area, Origin, Size},
+     compositor::Retained,
+     render::{matrix, pixman, renderer::READ_PIXELS_Y_INVERT, GenericRenderer, Texture}};
+
+// NOTE Taken from GLES2/gl2.h.
+const GL_TEXTURE_2D: u32 = 0x0DE1;
+const GL_RGBA: u32 = 0x1908;
+const GL_UNSIGNED_BYTE: u32 = 0x1401;
+const GL_FRAMEBUFFER: u32 = 0x8D40;
+const GL_FRAMEBUFFER_BINDING: u32 = 0x8CA6;
+const GL_FRAMEBUFFER_COMPLETE: u32 = 0x8CD5;
+const GL_COLOR_ATTACHMENT0: u32 = 0x8CE0;
+
+extern "C" {
+    fn glGetIntegerv(pname: u32, data: *mut c_int);
+    fn glGenTextures(n: c_int, textures: *mut u32);
+    fn glBindTexture(target: u32, texture: u32);
+    fn glTexImage2D(target: u32,
+                    level: c_int,
+                    internal_format: c_int,
+                    width: c_int,
+                    height: c_int,
+                    border: c_int,
+                    format: u32,
+                    type_: u32,
+                    pixels: *const c_void);
+    fn glDeleteTextures(n: c_int, textures: *const u32);
+    fn glGenFramebuffers(n: c_int, framebuffers: *mut u32);
+    fn glBindFramebuffer(target: u32, framebuffer: u32);
+    fn glFramebufferTexture2D(target: u32,
+                              attachment: u32,
+                              textarget: u32,
+                              texture: u32,
+                              level: c_int);
+    fn glCheckFramebufferStatus(target: u32) -> u32;
+    fn glDeleteFramebuffers(n: c_int, framebuffers: *const u32);
+}
+
+/// What a surface and its subsurfaces showed, read back into memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pixels {
+    pub width: u32,
+    pub height: u32,
+    /// Bytes per row of `data`.
+    pub stride: u32,
+    /// The rows of pixels from top to bottom, in `WL_SHM_FORMAT_ARGB8888`.
+    pub data: Vec<u8>
+}
+
+/// What a surface and its subsurfaces showed, copied into a texture of the
+/// renderer with `Surface::capture_snapshot`.
+///
+/// The texture is destroyed when this is dropped. It belongs to the renderer
+/// it was created with, so once that's destroyed (i.e. the compositor shut
+/// down) there's no texture anymore.
+pub struct Snapshot {
+    texture: *mut wlr_texture,
+    /// Whether the renderer the texture belongs to is still around.
+    renderer: Rc<Cell<bool>>,
+    /// Counts the texture for the client of the surface.
+    _retained: Option<Rc<Retained>>,
+    /// Run when the texture is destroyed, in the order they were registered.
+    on_destroy: Vec<Box<FnOnce()>>
+}
+
+impl Snapshot {
+    /// Get the texture the surfaces were copied into.
+    ///
+    /// Returns `None` if the renderer is gone.
+    pub fn texture<'snapshot>(&'snapshot self) -> Option<Texture<'snapshot>> {
+        if !self.renderer.get() {
+            return None
+        }
+        unsafe { Some(Texture::from_ptr(self.texture)) }
+    }
+
+    /// Call the function when the texture is destroyed, i.e. when this is
+    /// dropped.
+    ///
+    /// The callbacks run in the order they were registered, even if the
+    /// renderer was destroyed first.
+    pub fn on_destroy<F>(&mut self, callback: F)
+        where F: FnOnce() + 'static
+    {
+        self.on_destroy.push(Box::new(callback))
+    }
+}
+
+impl fmt::Debug for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Snapshot").field("texture", &self.texture).finish()
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        if self.renderer.get() {
+            unsafe { wlr_texture_destroy(self.texture) }
+        }
+        for callback in self.on_destroy.drain(..) {
+            callback()
+        }
+    }
+}
+
+/// A surface of the tree to draw, at its position relative to the root.
+struct Layer {
+    texture: *mut wlr_texture,
+    area: Area,
+    transform: wl_output_transform
+}
+
+/// Draw the surface and its subsurfaces into a new texture.
+///
+/// Returns `None` if nothing of the surface is shown, or if drawing it
+/// failed.
+pub(crate) unsafe fn capture(renderer: &mut GenericRenderer,
+                             surface: *mut wlr_surface)
+                             -> Option<Snapshot> {
+    let pixels = read_back(renderer, surface)?;
+    let texture = renderer.create_texture_from_pixels(wl_shm_format::WL_SHM_FORMAT_ARGB8888,
+                                                      pixels.stride,
+                                                      pixels.width,
+                                                      pixels.height,
+                                                      &pixels.data)?;
+    let texture = texture.as_ptr();
+    let retained = Retained::track(surface, ptr::null_mut(), texture, renderer.liveliness());
+    Some(Snapshot { texture,
+                    renderer: renderer.liveliness(),
+                    _retained: retained,
+                    on_destroy: Vec::new() })
+}
+
+/// Draw the surface and its subsurfaces, and read them back into memory.
+///
+/// Returns `None` if nothing of the surface is shown, or if drawing it
+/// failed.
+pub(crate) unsafe fn read_back(renderer: &mut GenericRenderer,
+                               surface: *mut wlr_surface)
+                               -> Option<Pixels> {
+    let mut layers: Vec<Layer> = Vec::new();
+    wlr_surface_for_each_surface(surface, Some(collect), &mut layers as *mut _ as *mut c_void);
+    let bounds = layers.iter().fold(None, |bounds: Option<(c_int, c_int, c_int, c_int)>, layer| {
+        let Area { origin: Origin { x, y }, size: Size { width, height } } = layer.area;
+        Some(match bounds {
+                 None => (x, y, x + width, y + height),
+                 Some((x1, y1, x2, y2)) => {
+                     (x1.min(x), y1.min(y), x2.max(x + width), y2.max(y + height))
+                 }
+             })
+    });
+    let (x1, y1, x2, y2) = bounds?;
+    // NOTE The snapshot has the resolution of the root surface, so that it
+    // doesn't look blurry on the output the window was on.
+    let scale = (*surface).current.scale.max(1);
+    let (width, height) = ((x2 - x1) * scale, (y2 - y1) * scale);
+    if width <= 0 || height <= 0 {
+        return None
+    }
+    if !wlr_egl_make_current(renderer.egl(), ptr::null_mut(), ptr::null_mut()) {
+        return None
+    }
+    if pixman::is_pixman(renderer.as_ptr()) {
+        // NOTE Without a surface current the pixman renderer draws into a
+        // buffer of its own, so there's no framebuffer to set up.
+        let (stride, data) = draw(renderer, &layers, (x1, y1), scale, width, height)?;
+        return Some(Pixels { width: width as u32,
+                             height: height as u32,
+                             stride,
+                             data })
+    }
+    let mut previous = 0;
+    glGetIntegerv(GL_FRAMEBUFFER_BINDING, &mut previous);
+    let (mut texture, mut framebuffer) = (0, 0);
+    glGenTextures(1, &mut texture);
+    glBindTexture(GL_TEXTURE_2D, texture);
+    glTexImage2D(GL_TEXTURE_2D,
+                 0,
+                 GL_RGBA as c_int,
+                 width,
+                 height,
+                 0,
+                 GL_RGBA,
+                 GL_UNSIGNED_BYTE,
+                 ptr::null());
+    glBindTexture(GL_TEXTURE_2D, 0);
+    glGenFramebuffers(1, &mut framebuffer);
+    glBindFramebuffer(GL_FRAMEBUFFER, framebuffer);
+    glFramebufferTexture2D(GL_FRAMEBUFFER, GL_COLOR_ATTACHMENT0, GL_TEXTURE_2D, texture, 0);
+    let pixels = if glCheckFramebufferStatus(GL_FRAMEBUFFER) == GL_FRAMEBUFFER_COMPLETE {
+        draw(renderer, &layers, (x1, y1), scale, width, height)
+    } else {
+        None
+    };
+    glBindFramebuffer(GL_FRAMEBUFFER, previous as u32);
+    glDeleteFramebuffers(1, &framebuffer);
+    glDeleteTextures(1, &texture);
+    let (stride, data) = pixels?;
+    Some(Pixels { width: width as u32,
+                  height: height as u32,
+                  stride,
+                  data })
+}
+
+/// Add a surface of the tree.
+///
+/// NOTE wlroots walks the tree in the order it's stacked in, from bottom to
+/// top, so subsurfaces placed below their parent are drawn before it.
+unsafe extern "C" fn collect(surface: *mut wlr_surface,
+                             x: c_int,
+                             y: c_int,
+                             data: *mut c_void) {
+    let layers = &mut *(data as *mut Vec<Layer>);
+    let texture = wlr_surface_get_texture(surface);
+    let (width, height) = ((*surface).current.width, (*surface).current.height);
+    if !texture.is_null() && width > 0 && height > 0 {
+        layers.push(Layer { texture,
+                            area: Area::new(Origin::new(x, y), Size::new(width, height)),
+                            transform: (*surface).current.transform });
+    }
+}
+
+/// Draw the layers into the bound framebuffer and read it back.
+///
+/// Returns the stride and the rows of pixels, top to bottom.
+unsafe fn draw(renderer: &mut GenericRenderer,
+               layers: &[Layer],
+               (x, y): (c_int, c_int),
+               scale: c_int,
+               width: c_int,
+               height: c_int)
+               -> Option<(u32, Vec<u8>)> {
+    let wlr_renderer = renderer.as_ptr();
+    wlr_renderer_begin(wlr_renderer, width, height);
+    wlr_renderer_clear(wlr_renderer, [0.0, 0.0, 0.0, 0.0].as_ptr());
+    let projection = matrix::projection([0.0; 9], width, height, WL_OUTPUT_TRANSFORM_NORMAL);
+    for layer in layers {
+        let Area { origin, size } = layer.area;
+        let area = Area::new(Origin::new((origin.x - x) * scale, (origin.y - y) * scale),
+                             Size::new(size.width * scale, size.height * scale));
+        let transform = wlr_output_transform_invert(layer.transform);
+        let matrix = matrix::project_box(area, transform, 0.0, projection);
+        wlr_render_texture_with_matrix(wlr_renderer, layer.texture, matrix.as_ptr(), 1.0);
+    }
+    let stride = width as u32 * 4;
+    let mut pixels = vec![0; stride as usize * height as usize];
+    let mut flags = 0;
+    let read = wlr_renderer_read_pixels(wlr_renderer,
+                                        wl_shm_format::WL_SHM_FORMAT_ARGB8888,
+                                        &mut flags,
+                                        stride,
+                                        width as u32,
+                                        height as u32,
+                                        0,
+                                        0,
+                                        0,
+                                        0,
+                                        pixels.as_mut_ptr() as *mut c_void);
+    wlr_renderer_end(wlr_renderer);
+    if !read {
+        return None
+    }
+    if flags & READ_PIXELS_Y_INVERT != 0 {
+        pixels = pixels.chunks(stride as usize)
+                       .rev()
+                       .flat_map(|row| row.iter().cloned())
+                       .collect();
+    }
+    Some((stride, pixels))
+}
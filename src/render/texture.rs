@@ -1,7 +1,49 @@
-use std::marker::PhantomData;
+use std::{marker::PhantomData, mem};
 
-use libc::c_int;
-use wlroots_sys::{wl_shm_format, wlr_texture, wlr_texture_get_size};
+use libc::{c_int, c_void};
+use wlroots_sys::{wl_shm_format, wlr_gles2_texture_attribs, wlr_gles2_texture_get_attribs,
+                  wlr_texture, wlr_texture_get_size, wlr_texture_is_gles2};
+
+use render::pixman;
+
+// NOTE Taken from GLES2/gl2.h.
+const GL_NO_ERROR: u32 = 0;
+const GL_RGBA: u32 = 0x1908;
+const GL_UNSIGNED_BYTE: u32 = 0x1401;
+const GL_FRAMEBUFFER: u32 = 0x8D40;
+const GL_FRAMEBUFFER_BINDING: u32 = 0x8CA6;
+const GL_FRAMEBUFFER_COMPLETE: u32 = 0x8CD5;
+const GL_COLOR_ATTACHMENT0: u32 = 0x8CE0;
+
+extern "C" {
+    fn glGetError() -> u32;
+    fn glGetIntegerv(pname: u32, data: *mut c_int);
+    fn glBindTexture(target: u32, texture: u32);
+    fn glCopyTexSubImage2D(target: u32,
+                           level: c_int,
+                           x_offset: c_int,
+                           y_offset: c_int,
+                           x: c_int,
+                           y: c_int,
+                           width: c_int,
+                           height: c_int);
+    fn glGenFramebuffers(n: c_int, framebuffers: *mut u32);
+    fn glBindFramebuffer(target: u32, framebuffer: u32);
+    fn glFramebufferTexture2D(target: u32,
+                              attachment: u32,
+                              textarget: u32,
+                              texture: u32,
+                              level: c_int);
+    fn glCheckFramebufferStatus(target: u32) -> u32;
+    fn glDeleteFramebuffers(n: c_int, framebuffers: *const u32);
+    fn glReadPixels(x: c_int,
+                    y: c_int,
+                    width: c_int,
+                    height: c_int,
+                    format: u32,
+                    type_: u32,
+                    pixels: *mut c_void);
+}
 
 /// Wrapper around wl_shm_format, to make it easier and nicer to type.
 #[repr(u32)]
@@ -104,4 +146,88 @@ impl <'surface> Texture<'surface> {
             (width, height)
         }
     }
+
+    /// Estimate how much memory the texture takes on the GPU, in bytes.
+    ///
+    /// This assumes 4 bytes a pixel, which is what the GLES2 renderer
+    /// uploads every format as. The driver may use more (e.g. for padding).
+    pub fn memory_size(&self) -> u64 {
+        let (width, height) = self.size();
+        width.max(0) as u64 * height.max(0) as u64 * 4
+    }
+
+    /// Copy the bottom left corner of the framebuffer that's being rendered
+    /// to into the texture, on the GPU.
+    ///
+    /// The rows are copied bottom up, like GL stores the framebuffer, which
+    /// is emulated for pixman textures. Returns false if the texture isn't a
+    /// GLES2 or pixman texture or the copy failed.
+    pub(crate) unsafe fn copy_framebuffer(&self, width: c_int, height: c_int) -> bool {
+        if pixman::is_pixman_texture(self.texture) {
+            return pixman::copy_framebuffer(self.texture, width, height)
+        }
+        let attribs = match self.gles2_attribs() {
+            Some(attribs) => attribs,
+            None => return false
+        };
+        while glGetError() != GL_NO_ERROR {}
+        glBindTexture(attribs.target, attribs.tex);
+        glCopyTexSubImage2D(attribs.target, 0, 0, 0, 0, 0, width, height);
+        glBindTexture(attribs.target, 0);
+        glGetError() == GL_NO_ERROR
+    }
+
+    /// Read the whole texture back into memory, through a framebuffer of its
+    /// own.
+    ///
+    /// The pixels are in `WL_SHM_FORMAT_ABGR8888`, with 4 bytes per pixel and
+    /// no padding between the rows. Returns false if `data` is too small or
+    /// the texture couldn't be read.
+    pub(crate) unsafe fn read_pixels(&self, data: &mut [u8]) -> bool {
+        let (width, height) = self.size();
+        if width <= 0 || height <= 0 || data.len() < width as usize * height as usize * 4 {
+            return false
+        }
+        if pixman::is_pixman_texture(self.texture) {
+            return pixman::read_texture(self.texture, data)
+        }
+        let attribs = match self.gles2_attribs() {
+            Some(attribs) => attribs,
+            None => return false
+        };
+        let mut previous = 0;
+        glGetIntegerv(GL_FRAMEBUFFER_BINDING, &mut previous);
+        let mut framebuffer = 0;
+        glGenFramebuffers(1, &mut framebuffer);
+        glBindFramebuffer(GL_FRAMEBUFFER, framebuffer);
+        glFramebufferTexture2D(GL_FRAMEBUFFER,
+                               GL_COLOR_ATTACHMENT0,
+                               attribs.target,
+                               attribs.tex,
+                               0);
+        let read = glCheckFramebufferStatus(GL_FRAMEBUFFER) == GL_FRAMEBUFFER_COMPLETE;
+        if read {
+            while glGetError() != GL_NO_ERROR {}
+            glReadPixels(0,
+                         0,
+                         width,
+                         height,
+                         GL_RGBA,
+                         GL_UNSIGNED_BYTE,
+                         data.as_mut_ptr() as *mut c_void);
+        }
+        let read = read && glGetError() == GL_NO_ERROR;
+        glBindFramebuffer(GL_FRAMEBUFFER, previous as u32);
+        glDeleteFramebuffers(1, &framebuffer);
+        read
+    }
+
+    unsafe fn gles2_attribs(&self) -> Option<wlr_gles2_texture_attribs> {
+        if !wlr_texture_is_gles2(self.texture) {
+            return None
+        }
+        let mut attribs: wlr_gles2_texture_attribs = mem::zeroed();
+        wlr_gles2_texture_get_attribs(self.texture, &mut attribs);
+        Some(attribs)
+    }
 }
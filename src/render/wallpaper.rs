@@ -0,0 +1,270 @@
+//! Drawing an image behind everything on an output.
+//!
+//! A `Wallpaper` is a render pass, so it's drawn at the start of every frame
+//! of the outputs it's added to:
+//!
+//! ```rust,no_run,ignore
+//! let wallpaper = Wallpaper::open("/usr/share/backgrounds/default.png", Fill::Cover)?;
+//! output.add_render_pass(Box::new(wallpaper.clone()));
+//! ```
+//!
+//! It covers the whole output (the parts the image doesn't cover are cleared
+//! to its background color), so the compositor shouldn't clear the frame
+//! itself afterwards.
+
+use std::{cell::Cell, path::Path, rc::Rc};
+
+use image::{self, imageops, DynamicImage, FilterType, ImageResult, RgbaImage};
+use wlroots_sys::{wl_shm_format, wlr_texture_destroy,
+                  wl_output_transform::WL_OUTPUT_TRANSFORM_NORMAL};
+
+use {area::{Area, Origin, Size},
+     render::{matrix, RenderPass, Renderer, Texture}};
+
+/// How many textures are kept, the one drawn the longest ago is destroyed
+/// first.
+///
+/// The output only needs one at a time, the others are for switching back
+/// and forth (e.g. between modes or scales).
+const MAX_TEXTURES: usize = 3;
+
+/// How the image is fitted to the output.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Fill {
+    /// Stretched to the size of the output.
+    Stretch,
+    /// As large as it fits on the output without being cropped, keeping its
+    /// aspect ratio.
+    Fit,
+    /// As small as it covers the output, keeping its aspect ratio. What
+    /// doesn't fit is cropped.
+    Cover,
+    /// Repeated at its own size from the top left of the output.
+    Tile,
+    /// At its own size in the center of the output.
+    Center
+}
+
+/// An image drawn behind everything on an output.
+///
+/// The image is uploaded at the resolution it's shown at, once for every
+/// size and scale of the output (up to a few of them), so it stays sharp on
+/// outputs with a scale. Clones share the decoded image, so one can be made
+/// for every output.
+#[derive(Debug)]
+pub struct Wallpaper {
+    image: Rc<RgbaImage>,
+    fill: Fill,
+    background: [f32; 4],
+    /// The textures by the size of the output in pixels and its scale, the
+    /// most recently drawn last.
+    textures: Vec<(Size, u32, Texture<'static>)>,
+    /// Whether the renderer the textures belong to is still around.
+    renderer: Option<Rc<Cell<bool>>>
+}
+
+impl Wallpaper {
+    /// Load the image at the path.
+    pub fn open<P: AsRef<Path>>(path: P, fill: Fill) -> ImageResult<Self> {
+        Ok(Wallpaper::from_image(image::open(path)?, fill))
+    }
+
+    /// Use an image that's already loaded.
+    pub fn from_image(image: DynamicImage, fill: Fill) -> Self {
+        Wallpaper { image: Rc::new(image.to_rgba()),
+                    fill,
+                    background: [0.0, 0.0, 0.0, 1.0],
+                    textures: Vec::new(),
+                    renderer: None }
+    }
+
+    /// Set the color of the parts of the output the image doesn't cover.
+    ///
+    /// Black by default.
+    pub fn set_background(&mut self, color: [f32; 4]) {
+        self.background = color
+    }
+
+    pub fn fill(&self) -> Fill {
+        self.fill
+    }
+
+    /// Change how the image is fitted to the output.
+    pub fn set_fill(&mut self, fill: Fill) {
+        if fill != self.fill {
+            self.fill = fill;
+            self.forget_textures();
+        }
+    }
+
+    /// Get the size of the image in pixels.
+    pub fn image_size(&self) -> Size {
+        Size::new(self.image.width() as i32, self.image.height() as i32)
+    }
+
+    /// Get the areas of the output (in pixels) the texture is drawn at, and
+    /// the size of the texture.
+    fn layout(&self, output: Size, scale: f32) -> (Size, Vec<Area>) {
+        let image = self.image_size();
+        let scaled = |width: f32, height: f32| {
+            Size::new((width.round() as i32).max(1), (height.round() as i32).max(1))
+        };
+        let centered = |size: Size| {
+            Area::new(Origin::new((output.width - size.width) / 2,
+                                  (output.height - size.height) / 2),
+                      size)
+        };
+        let (width_ratio, height_ratio) = (output.width as f32 / image.width as f32,
+                                           output.height as f32 / image.height as f32);
+        match self.fill {
+            Fill::Stretch | Fill::Cover => (output, vec![Area::new(Origin::default(), output)]),
+            Fill::Fit => {
+                let ratio = width_ratio.min(height_ratio);
+                let size = scaled(image.width as f32 * ratio, image.height as f32 * ratio);
+                (size, vec![centered(size)])
+            },
+            Fill::Center => {
+                let size = scaled(image.width as f32 * scale, image.height as f32 * scale);
+                (size, vec![centered(size)])
+            },
+            Fill::Tile => {
+                let size = scaled(image.width as f32 * scale, image.height as f32 * scale);
+                let mut areas = Vec::new();
+                let mut y = 0;
+                while y < output.height {
+                    let mut x = 0;
+                    while x < output.width {
+                        areas.push(Area::new(Origin::new(x, y), size));
+                        x += size.width;
+                    }
+                    y += size.height;
+                }
+                (size, areas)
+            }
+        }
+    }
+
+    /// Get the image resized to the size of the texture.
+    fn resized(&self, size: Size) -> RgbaImage {
+        let (width, height) = (size.width as u32, size.height as u32);
+        if self.fill != Fill::Cover {
+            return imageops::resize(&*self.image, width, height, FilterType::Triangle)
+        }
+        // NOTE The part of the image with the aspect ratio of the output is
+        // cut out of its center.
+        let (image_width, image_height) = self.image.dimensions();
+        let ratio = (width as f32 / image_width as f32).max(height as f32 / image_height as f32);
+        let crop_width = ((width as f32 / ratio).round() as u32).max(1).min(image_width);
+        let crop_height = ((height as f32 / ratio).round() as u32).max(1).min(image_height);
+        let mut image = (*self.image).clone();
+        let cropped = imageops::crop(&mut image,
+                                     (image_width - crop_width) / 2,
+                                     (image_height - crop_height) / 2,
+                                     crop_width,
+                                     crop_height).to_image();
+        imageops::resize(&cropped, width, height, FilterType::Triangle)
+    }
+
+    /// Get the texture for the output size and scale, uploading it if it's
+    /// not there yet.
+    fn texture(&mut self,
+               renderer: &mut Renderer,
+               output: Size,
+               scale: f32,
+               size: Size)
+               -> Option<&Texture<'static>> {
+        let alive = self.renderer.as_ref().map_or(false, |renderer| renderer.get());
+        if !alive {
+            self.forget_textures();
+            self.renderer = Some(renderer.liveliness());
+        }
+        let scale = scale.to_bits();
+        let index = self.textures
+                        .iter()
+                        .position(|&(other, other_scale, _)| {
+                            other == output && other_scale == scale
+                        });
+        let entry = match index {
+            Some(index) => self.textures.remove(index),
+            None => {
+                let pixels = self.resized(size);
+                // NOTE The bytes of the pixels are in RGBA order, which is
+                // ABGR8888 for wl_shm.
+                let texture = renderer.create_texture_from_pixels(
+                    wl_shm_format::WL_SHM_FORMAT_ABGR8888,
+                    pixels.width() * 4,
+                    pixels.width(),
+                    pixels.height(),
+                    &pixels.into_raw())?;
+                (output, scale, texture)
+            }
+        };
+        self.textures.push(entry);
+        if self.textures.len() > MAX_TEXTURES {
+            // NOTE It isn't drawn this frame, so it can go while rendering.
+            let (_, _, texture) = self.textures.remove(0);
+            unsafe { wlr_texture_destroy(texture.as_ptr()) }
+        }
+        self.textures.last().map(|&(_, _, ref texture)| texture)
+    }
+
+    /// Destroy the uploaded textures, unless the renderer they belong to is
+    /// already gone (e.g. the compositor shut down before the wallpaper was
+    /// dropped).
+    fn forget_textures(&mut self) {
+        let alive = self.renderer.as_ref().map_or(false, |renderer| renderer.get());
+        for (_, _, texture) in self.textures.drain(..) {
+            if alive {
+                unsafe { wlr_texture_destroy(texture.as_ptr()) }
+            }
+        }
+    }
+}
+
+impl Clone for Wallpaper {
+    fn clone(&self) -> Self {
+        Wallpaper { image: self.image.clone(),
+                    fill: self.fill,
+                    background: self.background,
+                    textures: Vec::new(),
+                    renderer: None }
+    }
+}
+
+impl Drop for Wallpaper {
+    fn drop(&mut self) {
+        self.forget_textures()
+    }
+}
+
+impl RenderPass for Wallpaper {
+    fn pre_pass(&mut self, renderer: &mut Renderer) {
+        let (width, height) = renderer.output.transformed_resolution();
+        let scale = renderer.output.scale();
+        let projection = renderer.output.transform_matrix();
+        let output = Size::new(width, height);
+        renderer.clear(self.background);
+        if width <= 0 || height <= 0 {
+            return
+        }
+        let (size, areas) = self.layout(output, scale);
+        let matrices = areas.into_iter()
+                            .map(|area| {
+                                matrix::project_box(area,
+                                                    WL_OUTPUT_TRANSFORM_NORMAL,
+                                                    0.0,
+                                                    projection)
+                            })
+                            .collect::<Vec<_>>();
+        let texture = match self.texture(renderer, output, scale, size) {
+            Some(texture) => texture,
+            None => {
+                wlr_log!(WLR_ERROR, "Could not upload the wallpaper");
+                return
+            }
+        };
+        for matrix in matrices {
+            renderer.render_texture_with_matrix(texture, matrix);
+        }
+    }
+}
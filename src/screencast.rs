@@ -0,0 +1,224 @@
+//! Streaming the frames of an output, e.g. into a PipeWire stream for
+//! screen sharing.
+//!
+//! A `screencast::Stream` is a render pass that copies every frame of the
+//! output it's added to into a texture on the GPU, and hands it to a
+//! `screencast::Sink`:
+//!
+//! ```rust,no_run,ignore
+//! output.add_render_pass(Box::new(screencast::Stream::new(Box::new(sink))));
+//! ```
+//!
+//! Add the stream after any other render passes so their drawing ends up in
+//! the stream. The frame stats overlay is never part of it.
+//!
+//! Exporting the buffers as dmabufs isn't possible with this version of
+//! wlroots. A sink that needs the frames in memory (e.g. to copy them into
+//! shared memory) reads them back with `Frame::read_pixels`, which is the only
+//! time they leave the GPU. Setting up the PipeWire stream and the
+//! xdg-desktop-portal handshake is left to the sink.
+//!
+//! Frames used to be read back for every sink, and handed over as
+//! `Frame::data` with its `stride` and `format`. Those fields are gone: read
+//! the pixels with `Frame::read_pixels` (still in `screencast::FORMAT`) and
+//! get their stride from `Frame::stride`.
+
+use std::{cell::Cell, rc::Rc, time::Duration};
+
+use wlroots_sys::{wl_shm_format, wlr_output_cursor, wlr_texture_destroy};
+
+use {area::{Area, Origin, Size}, render::{RenderPass, Renderer, Texture}, utils::Handleable};
+
+/// The format frames are read back in.
+pub const FORMAT: wl_shm_format = wl_shm_format::WL_SHM_FORMAT_ARGB8888;
+
+/// Bytes per pixel of `FORMAT`.
+const BYTES_PER_PIXEL: u32 = 4;
+
+/// A hardware cursor of the output, which isn't part of the frame's pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CursorInfo {
+    /// The position of the cursor in output buffer coordinates.
+    pub position: (f64, f64),
+    /// The hotspot of the cursor image.
+    pub hotspot: (i32, i32),
+    /// The size of the cursor image.
+    pub size: (u32, u32),
+    pub visible: bool
+}
+
+/// A frame rendered to the output.
+#[derive(Debug)]
+pub struct Frame<'stream> {
+    pub width: u32,
+    pub height: u32,
+    /// The frame, copied on the GPU. It's only valid during `Sink::frame`.
+    pub texture: &'stream Texture<'static>,
+    /// Whether the rows of the texture, and of the pixels read back from it,
+    /// are stored bottom to top.
+    pub y_invert: bool,
+    /// The parts of the frame that changed since the previous one, in output
+    /// buffer coordinates. Covers the whole frame when the damage isn't known.
+    pub damage: Vec<Area>,
+    /// The hardware cursors of the output.
+    pub cursors: Vec<CursorInfo>,
+    /// When the frame was rendered, if known.
+    pub when: Option<Duration>
+}
+
+/// Receives the frames of a `screencast::Stream`.
+#[allow(unused_variables)]
+pub trait Sink {
+    /// Determines if frames should be captured at all right now (e.g. the
+    /// consumer of the stream is connected).
+    ///
+    /// Frames aren't copied while this returns false.
+    fn active(&self) -> bool {
+        true
+    }
+
+    /// Callback that is triggered with every frame rendered to the output.
+    fn frame(&mut self, frame: &Frame) {}
+
+    /// Callback that is triggered when a frame could not be copied.
+    fn failed(&mut self) {}
+}
+
+impl<'stream> Frame<'stream> {
+    /// Bytes per row of the pixels read back with `read_pixels`.
+    pub fn stride(&self) -> u32 {
+        self.width * BYTES_PER_PIXEL
+    }
+
+    /// Read the frame back from the GPU into `data`, in `screencast::FORMAT`
+    /// with `stride` bytes per row.
+    ///
+    /// This stalls until the frame is rendered, so only do it when the
+    /// consumer can't use the texture. Returns false if `data` is smaller
+    /// than `stride * height` bytes or the frame couldn't be read.
+    pub fn read_pixels(&self, data: &mut [u8]) -> bool {
+        if !unsafe { self.texture.read_pixels(data) } {
+            return false
+        }
+        // NOTE Textures are read as ABGR8888, which only differs from
+        // `FORMAT` in the order of red and blue.
+        let len = (self.stride() * self.height) as usize;
+        for pixel in data[..len].chunks_mut(BYTES_PER_PIXEL as usize) {
+            pixel.swap(0, 2);
+        }
+        true
+    }
+}
+
+/// A render pass streaming the frames of the output to a sink.
+pub struct Stream {
+    sink: Box<Sink>,
+    /// The copy of the last frame, recreated when the size of the output
+    /// changes.
+    texture: Option<Texture<'static>>,
+    /// Whether the renderer the texture belongs to is still around.
+    renderer: Option<Rc<Cell<bool>>>,
+    /// Set until a whole frame was sent, as the sink has nothing to apply
+    /// damage on yet.
+    needs_full_frame: bool
+}
+
+impl Stream {
+    pub fn new(sink: Box<Sink>) -> Self {
+        Stream { sink,
+                 texture: None,
+                 renderer: None,
+                 needs_full_frame: true }
+    }
+
+    /// Destroy the copy of the last frame, unless the renderer already took
+    /// it with it (e.g. the stream outlived the compositor).
+    fn release_texture(&mut self) {
+        let alive = self.renderer.take().map_or(false, |renderer| renderer.get());
+        if let Some(texture) = self.texture.take() {
+            if alive {
+                unsafe { wlr_texture_destroy(texture.as_ptr()) }
+            }
+        }
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        self.release_texture()
+    }
+}
+
+impl RenderPass for Stream {
+    fn post_pass(&mut self, renderer: &mut Renderer) {
+        if !self.sink.active() {
+            // NOTE The sink missed frames, so it can't rely on damage either.
+            self.needs_full_frame = true;
+            return
+        }
+        let (width, height) = renderer.output.size();
+        if width <= 0 || height <= 0 {
+            return
+        }
+        if self.texture.as_ref().map(|texture| texture.size()) != Some((width, height)) {
+            self.release_texture();
+            let stride = width as u32 * BYTES_PER_PIXEL;
+            let pixels = vec![0; stride as usize * height as usize];
+            // NOTE The frames are copied into it on the GPU, which GL does in
+            // RGBA.
+            let format = wl_shm_format::WL_SHM_FORMAT_ABGR8888;
+            self.texture = renderer.create_texture_from_pixels(format,
+                                                               stride,
+                                                               width as u32,
+                                                               height as u32,
+                                                               &pixels);
+            self.renderer = Some(renderer.liveliness());
+            // NOTE The sink has to start over with the new size.
+            self.needs_full_frame = true;
+        }
+        let copied = match self.texture {
+            Some(ref texture) => unsafe { texture.copy_framebuffer(width, height) },
+            None => false
+        };
+        if !copied {
+            self.release_texture();
+            self.needs_full_frame = true;
+            self.sink.failed();
+            return
+        }
+        let full = Area::new(Origin::new(0, 0), Size::new(width, height));
+        let (damage, when) = match renderer.damage {
+            Some((ref region, when)) if !self.needs_full_frame => (region.rectangles(), Some(when)),
+            Some((_, when)) => (vec![full], Some(when)),
+            None => (vec![full], None)
+        };
+        let cursors = unsafe { cursors(renderer) };
+        self.needs_full_frame = false;
+        let texture = match self.texture {
+            Some(ref texture) => texture,
+            None => return
+        };
+        self.sink.frame(&Frame { width: width as u32,
+                                 height: height as u32,
+                                 texture,
+                                 // NOTE The rows are copied bottom up.
+                                 y_invert: true,
+                                 damage,
+                                 cursors,
+                                 when });
+    }
+}
+
+unsafe fn cursors(renderer: &mut Renderer) -> Vec<CursorInfo> {
+    let mut cursors = Vec::new();
+    let output = renderer.output.as_ptr();
+    wl_list_for_each!((*output).cursors, link, (cursor: wlr_output_cursor) => {
+        if (*cursor).enabled {
+            cursors.push(CursorInfo { position: ((*cursor).x, (*cursor).y),
+                                      hotspot: ((*cursor).hotspot_x, (*cursor).hotspot_y),
+                                      size: ((*cursor).width, (*cursor).height),
+                                      visible: (*cursor).visible });
+        }
+    });
+    cursors
+}
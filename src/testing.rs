@@ -0,0 +1,337 @@
+//! A harness for integration testing compositors built on this crate.
+//!
+//! `testing::run` runs a compositor on the headless backend and hands a
+//! `testing::Harness` to the test, which steps the event loop itself. Clients
+//! connect with `Harness::connect`, and are driven in lockstep with the
+//! compositor, so a test never races the compositor:
+//!
+//! ```rust,no_run,ignore
+//! testing::run(compositor::Builder::new().gles2(true), (), |harness| {
+//!     harness.add_output(800, 600).unwrap();
+//!     let mut client = harness.connect();
+//!     let mut surface = client.create_surface(harness, 100, 100, 0xff_ff_00_00);
+//!     let frame = surface.frame();
+//!     surface.commit();
+//!     harness.assert_frame_done(&mut client, &frame, Duration::from_secs(1));
+//! });
+//! ```
+//!
+//! Only one compositor can run per process at a time, so run the tests with
+//! `--test-threads=1`.
+//!
+//! For frames that come out the same on every machine, build the compositor
+//! with `renderer(RendererKind::Pixman)`, which draws on the CPU, and read
+//! them back with `Renderer::read_pixels`. The outputs still need EGL
+//! pbuffers (a software driver is enough); without EGL the compositor runs
+//! without a renderer.
+//!
+//! Time is controlled in two ways. `Harness::skip` moves the clock of the
+//! crate ahead without waiting, which is what animations, frame callbacks,
+//! idle times and the like are timed with. The timers of the event loop use
+//! the real monotonic clock though, so those only fire by dispatching the
+//! event loop for a while with `Harness::advance`.
+
+use std::{any::Any, env, fs::{self, OpenOptions}, io::Write, os::unix::io::AsRawFd,
+          path::PathBuf, process, sync::{Arc, atomic::{AtomicBool, AtomicUsize, Ordering}},
+          time::{Duration, Instant}};
+
+use wayland_client::{Display, EventQueue, GlobalManager,
+                     protocol::{wl_buffer, wl_callback, wl_compositor, wl_shm, wl_shm_pool,
+                                wl_surface}};
+use wayland_sys::server::{wl_display, wl_event_loop, WAYLAND_SERVER_HANDLE};
+
+use {backend::Backend, compositor, output, utils};
+
+/// Used to name the shared memory files of the clients.
+static SHM_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Run the test against a compositor built by the builder on the headless
+/// backend.
+///
+/// Panics in the test are re-thrown once the compositor is torn down.
+pub fn run<D, F>(builder: compositor::Builder, data: D, test: F)
+    where D: Any + 'static,
+          F: FnOnce(&mut Harness)
+{
+    let compositor = builder.build_headless(data);
+    // NOTE The harness doesn't keep a reference to the compositor, so the
+    // test can upgrade its handle.
+    compositor.run_unborrowed(|| {
+        let mut harness = unsafe {
+            let compositor = compositor::COMPOSITOR_PTR;
+            Harness { display: (*compositor).display,
+                      event_loop: (*compositor).event_loop,
+                      socket_name: (*compositor).socket_name().into() }
+        };
+        test(&mut harness);
+    });
+}
+
+/// Steps the event loop of the compositor under test.
+#[derive(Debug)]
+pub struct Harness {
+    display: *mut wl_display,
+    event_loop: *mut wl_event_loop,
+    socket_name: String
+}
+
+impl Harness {
+    /// Dispatch the events of the compositor, waiting up to `timeout` for
+    /// one to arrive.
+    pub fn dispatch(&mut self, timeout: Duration) {
+        let millis = timeout.as_secs() * 1000 + (timeout.subsec_nanos() / 1_000_000) as u64;
+        unsafe {
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_display_flush_clients, self.display);
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_event_loop_dispatch,
+                          self.event_loop,
+                          millis as i32);
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_display_flush_clients, self.display);
+        }
+    }
+
+    /// Keep the event loop running for the duration, e.g. so timers fire.
+    pub fn advance(&mut self, duration: Duration) {
+        let start = Instant::now();
+        while start.elapsed() < duration {
+            self.dispatch(duration - start.elapsed());
+        }
+    }
+
+    /// Move the clock of the crate ahead by the duration without waiting,
+    /// then dispatch the events that are ready.
+    ///
+    /// The timers of the event loop aren't affected, see `advance` for those.
+    pub fn skip(&mut self, duration: Duration) {
+        utils::skip(duration);
+        self.dispatch(Duration::from_millis(0));
+    }
+
+    /// Dispatch events of the compositor and the client until `condition`
+    /// holds, for at most `timeout`.
+    ///
+    /// Returns whether the condition held in time.
+    pub fn dispatch_until<F>(&mut self,
+                             client: &mut TestClient,
+                             timeout: Duration,
+                             mut condition: F)
+                             -> bool
+        where F: FnMut() -> bool
+    {
+        let start = Instant::now();
+        loop {
+            client.dispatch_pending();
+            if condition() {
+                return true
+            }
+            if start.elapsed() >= timeout {
+                return false
+            }
+            self.dispatch(Duration::from_millis(1));
+        }
+    }
+
+    /// Panic unless the compositor signals the frame callback in time.
+    pub fn assert_frame_done(&mut self,
+                             client: &mut TestClient,
+                             frame: &FrameCallback,
+                             timeout: Duration) {
+        if !self.dispatch_until(client, timeout, || frame.done()) {
+            panic!("The frame callback was not done within {:?}", timeout)
+        }
+    }
+
+    /// Add a headless output, which is announced through the output manager
+    /// like any other.
+    pub fn add_output(&mut self, width: u32, height: u32) -> Option<output::Handle> {
+        let handle = compositor::handle()?;
+        let output = handle.run(|compositor| match *compositor.backend() {
+                                   Backend::Headless(ref headless) => {
+                                       headless.add_output(width, height)
+                                   },
+                                   _ => None
+                               })
+                           .ok()??;
+        self.dispatch(Duration::from_millis(0));
+        Some(output)
+    }
+
+    /// Connect a new client to the compositor.
+    pub fn connect(&mut self) -> TestClient {
+        let (display, event_queue) = Display::connect_to_name(self.socket_name.clone())
+            .expect("Could not connect to the compositor");
+        let globals = GlobalManager::new(&display);
+        let mut client = TestClient { display,
+                                      event_queue,
+                                      globals,
+                                      compositor: None,
+                                      shm: None };
+        client.roundtrip(self);
+        // NOTE The globals are bound once, the surfaces are all created
+        // through the same objects.
+        client.compositor = client.globals
+            .instantiate_auto::<wl_compositor::WlCompositor, _>(|compositor| {
+                compositor.implement_dummy()
+            })
+            .ok();
+        client.shm = client.globals
+            .instantiate_auto::<wl_shm::WlShm, _>(|shm| shm.implement_dummy())
+            .ok();
+        client
+    }
+}
+
+/// A Wayland client connected to the compositor under test.
+pub struct TestClient {
+    display: Display,
+    event_queue: EventQueue,
+    globals: GlobalManager,
+    /// The `wl_compositor` global, if the compositor has one.
+    compositor: Option<wl_compositor::WlCompositor>,
+    /// The `wl_shm` global, if the compositor has one.
+    shm: Option<wl_shm::WlShm>
+}
+
+impl TestClient {
+    /// Send the requests and wait for the compositor to have handled all of
+    /// them.
+    pub fn roundtrip(&mut self, harness: &mut Harness) {
+        let done = Arc::new(AtomicBool::new(false));
+        {
+            let done = done.clone();
+            self.display.sync(move |callback| {
+                                  callback.implement(move |_, _| done.store(true, Ordering::SeqCst),
+                                                     ())
+                              })
+                        .expect("The client display is gone");
+        }
+        if !harness.dispatch_until(self, Duration::from_secs(5), || done.load(Ordering::SeqCst)) {
+            panic!("The compositor did not answer the roundtrip")
+        }
+    }
+
+    /// Send buffered requests and handle the events that arrived, without
+    /// blocking.
+    pub fn dispatch_pending(&mut self) {
+        self.display.flush().ok();
+        if let Some(guard) = self.event_queue.prepare_read() {
+            // NOTE There being nothing to read yet is not an error here.
+            guard.read_events().ok();
+        }
+        self.event_queue.dispatch_pending().expect("The client lost its connection");
+    }
+
+    /// Create a surface with a buffer of a single color (in ARGB8888) attached
+    /// but not committed yet.
+    pub fn create_surface(&mut self,
+                          harness: &mut Harness,
+                          width: i32,
+                          height: i32,
+                          color: u32)
+                          -> TestSurface {
+        let buffer = {
+            let shm = self.shm.as_ref().expect("The compositor has no wl_shm global");
+            create_buffer(shm, width, height, color)
+        };
+        let surface = self.compositor
+                          .as_ref()
+                          .expect("The compositor has no wl_compositor global")
+                          .create_surface(|surface| surface.implement_dummy())
+                                .expect("Could not create surface");
+        surface.attach(Some(&buffer), 0, 0);
+        surface.damage(0, 0, width, height);
+        self.roundtrip(harness);
+        TestSurface { surface, buffer, width, height }
+    }
+}
+
+/// A surface of a `TestClient`.
+pub struct TestSurface {
+    pub surface: wl_surface::WlSurface,
+    pub buffer: wl_buffer::WlBuffer,
+    width: i32,
+    height: i32
+}
+
+impl TestSurface {
+    /// Ask to be told when the compositor draws the next commit.
+    pub fn frame(&mut self) -> FrameCallback {
+        let done = Arc::new(AtomicBool::new(false));
+        {
+            let done = done.clone();
+            self.surface
+                .frame(move |callback| {
+                           callback.implement(move |event, _| match event {
+                                                  wl_callback::Event::Done { .. } => {
+                                                      done.store(true, Ordering::SeqCst)
+                                                  }
+                                              },
+                                              ())
+                       })
+                .expect("The surface is gone");
+        }
+        FrameCallback { done }
+    }
+
+    /// Attach the buffer again, damaging the whole surface.
+    pub fn reattach(&mut self) {
+        self.surface.attach(Some(&self.buffer), 0, 0);
+        self.surface.damage(0, 0, self.width, self.height);
+    }
+
+    pub fn commit(&mut self) {
+        self.surface.commit();
+    }
+}
+
+/// A frame callback requested with `TestSurface::frame`.
+#[derive(Debug, Clone)]
+pub struct FrameCallback {
+    done: Arc<AtomicBool>
+}
+
+impl FrameCallback {
+    /// Determines if the compositor signaled the callback.
+    pub fn done(&self) -> bool {
+        self.done.load(Ordering::SeqCst)
+    }
+}
+
+/// Create a buffer of a single color in a shared memory file.
+fn create_buffer(shm: &wl_shm::WlShm, width: i32, height: i32, color: u32) -> wl_buffer::WlBuffer {
+    let stride = width * 4;
+    let size = stride * height;
+    let dir = env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from).unwrap_or_else(env::temp_dir);
+    let path = dir.join(format!("wlroots-test-{}-{}",
+                                process::id(),
+                                SHM_COUNTER.fetch_add(1, Ordering::SeqCst)));
+    let mut file = OpenOptions::new().read(true)
+                                     .write(true)
+                                     .create_new(true)
+                                     .open(&path)
+                                     .expect("Could not create shared memory file");
+    // NOTE The file stays around for as long as the fd is open.
+    fs::remove_file(&path).ok();
+    let pixels: Vec<u8> = (0..width * height).flat_map(|_| {
+                                                 let bytes = [color as u8,
+                                                              (color >> 8) as u8,
+                                                              (color >> 16) as u8,
+                                                              (color >> 24) as u8];
+                                                 bytes.to_vec()
+                                             })
+                                             .collect();
+    file.write_all(&pixels).expect("Could not write shared memory file");
+    let pool = shm.create_pool(file.as_raw_fd(), size, |pool: wl_shm_pool::NewProxy<_>| {
+                      pool.implement_dummy()
+                  })
+                  .expect("Could not create shm pool");
+    let buffer = pool.create_buffer(0,
+                                    width,
+                                    height,
+                                    stride,
+                                    wl_shm::Format::Argb8888,
+                                    |buffer| buffer.implement_dummy())
+                     .expect("Could not create buffer");
+    pool.destroy();
+    buffer
+}
@@ -3,7 +3,7 @@
 
 use libc::{c_double, c_float, c_int};
 
-use wlroots_sys::{wl_output_transform, wlr_box, wlr_box_closest_point, wlr_box_contains_point,
+use wlroots_sys::{wl_output_transform, wlr_box, wlr_box_contains_point,
                   wlr_box_empty, wlr_box_intersection, wlr_box_rotated_bounds, wlr_box_transform};
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
@@ -94,15 +94,18 @@ impl Area {
 
     /// Finds the closest point within the box to the given point.
     /// If the (x, y) point lies outside of the box, then it finds the closest
-    /// corner and returns that.
+    /// point on its edge and returns that.
+    ///
+    /// The far edges are at `x + width - 1` and `y + height - 1`, so the
+    /// point is always one `contains_point` accepts (unless the box is
+    /// empty, in which case its origin is returned).
     ///
     /// Returned value is in form of (x, y).
     pub fn closest_point(self, x: c_double, y: c_double) -> (c_double, c_double) {
-        unsafe {
-            let (mut dest_x, mut dest_y) = (0.0, 0.0);
-            wlr_box_closest_point(&mut self.into(), x, y, &mut dest_x, &mut dest_y);
-            (dest_x, dest_y)
-        }
+        let Area { origin: Origin { x: left, y: top }, size: Size { width, height } } = self;
+        let right = (left + width - 1).max(left) as c_double;
+        let bottom = (top + height - 1).max(top) as c_double;
+        (x.max(left as c_double).min(right), y.max(top as c_double).min(bottom))
     }
 
     /// Gets the intersection of the two areas.
@@ -134,9 +137,9 @@ impl Area {
     /// e.g: If it's `WL_OUTPUT_TRANSFORM_90` then it will flip the Area 90° clockwise.
     pub fn transform(self, transform: wl_output_transform, width: c_int, height: c_int) -> Area {
         unsafe {
-            let res = Area::default();
-            wlr_box_transform(&mut self.into(), transform, width, height, &mut res.into());
-            res
+            let mut res: wlr_box = Area::default().into();
+            wlr_box_transform(&mut self.into(), transform, width, height, &mut res);
+            Area::from_box(res)
         }
     }
 
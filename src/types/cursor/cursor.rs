@@ -1,9 +1,10 @@
 //! Wrapper for wlr_cursor
 
-use std::{fmt, panic, ptr, cell::Cell, rc::{Rc, Weak}};
+use std::{fmt, ffi::CString, panic::{self, Location}, ptr, cell::{Cell, RefCell},
+          rc::{Rc, Weak}, time::Duration};
 
 use libc;
-use wayland_sys::server::WAYLAND_SERVER_HANDLE;
+use wayland_sys::server::{wl_event_source, WAYLAND_SERVER_HANDLE};
 use wayland_sys::server::signal::wl_signal_add;
 use wlroots_sys::{wlr_cursor, wlr_cursor_absolute_to_layout_coords,
                   wlr_cursor_attach_input_device, wlr_cursor_attach_output_layout,
@@ -11,15 +12,17 @@ use wlroots_sys::{wlr_cursor, wlr_cursor_absolute_to_layout_coords,
                   wlr_cursor_map_input_to_output, wlr_cursor_map_input_to_region,
                   wlr_cursor_map_to_output, wlr_cursor_map_to_region, wlr_cursor_move,
                   wlr_cursor_set_image, wlr_cursor_set_surface, wlr_cursor_warp,
-                  wlr_cursor_warp_absolute};
+                  wlr_cursor_warp_absolute, wlr_output, wlr_xcursor_manager,
+                  wlr_xcursor_manager_load, wlr_xcursor_manager_set_cursor_image};
 
 use {area::Area,
      compositor,
+     error::Error,
      input::{self, pointer, tablet_tool, touch},
      output::{self, Output, layout::Layout},
-     surface::Surface,
-     cursor::xcursor,
-     utils::{HandleErr, HandleResult, Handleable}};
+     surface::{self, Surface},
+     cursor::{xcursor, xcursor_manager},
+     utils::{self, HandleErr, HandleResult, Handleable}};
 
 #[derive(Debug)]
 pub(crate) struct CursorState {
@@ -30,7 +33,41 @@ pub(crate) struct CursorState {
     /// they cannot be upgraded.
     counter: Rc<Cell<bool>>,
     /// A raw pointer to the Cursor on the heap
-    cursor: *mut Cursor
+    cursor: *mut Cursor,
+    /// The `Listener` of the cursor, with the handler.
+    listener: *mut libc::c_void,
+    /// Destroys the `Listener`, whichever type of handler it has.
+    destroy_listener: unsafe fn(*mut libc::c_void),
+    /// The area, in layout coordinates, the cursor is not allowed to leave.
+    bounds: Option<Area>,
+    /// The image to show when the cursor isn't hidden.
+    image: Option<CursorImage>,
+    /// Whether the cursor is hidden, see `Cursor::hide`.
+    hidden: bool,
+    /// How long the pointer can be left alone before the cursor is hidden.
+    hide_timeout: Option<Duration>,
+    /// Fires once the cursor has been left alone for `hide_timeout`.
+    hide_timer: *mut wl_event_source,
+    /// Whether the cursor is hidden when a key is pressed.
+    hide_when_typing: bool
+}
+
+/// What the cursor last showed, so it can be shown again after it's hidden
+/// or once an output changed.
+#[derive(Debug)]
+enum CursorImage {
+    Pixels { buffer: Vec<u8>, width: u32, height: u32, hotspot_x: u32, hotspot_y: u32 },
+    Surface { surface: Option<surface::Handle>, hotspot_x: i32, hotspot_y: i32 },
+    /// A cursor of the theme of an `xcursor::Manager`, at every scale it
+    /// loaded.
+    Themed { manager: *mut wlr_xcursor_manager, name: CString }
+}
+
+thread_local! {
+    /// All of the cursors that have been created.
+    static CURSORS: RefCell<Vec<*mut wlr_cursor>> = RefCell::new(Vec::new());
+    /// The cursors that are hidden when a key is pressed.
+    static HIDE_WHEN_TYPING: RefCell<Vec<*mut wlr_cursor>> = RefCell::new(Vec::new());
 }
 
 // NOTE We can't use `utils::Handle` because we own the cursor.
@@ -111,10 +148,107 @@ pub trait Handler {
     }
 }
 
-wayland_listener!(pub Cursor, (*mut wlr_cursor, Box<Handler>, Option<output::layout::Handle>), [
-    pointer_motion_listener => pointer_motion_notify: |this: &mut Cursor, event: *mut libc::c_void,|
+impl<H: Handler + ?Sized> Handler for Box<H> {
+    fn on_pointer_motion(&mut self,
+                         compositor_handle: compositor::Handle,
+                         cursor_handle: Handle,
+                         event: &pointer::event::Motion) {
+        (**self).on_pointer_motion(compositor_handle, cursor_handle, event)
+    }
+
+    fn on_pointer_motion_absolute(&mut self,
+                                  compositor_handle: compositor::Handle,
+                                  cursor_handle: Handle,
+                                  event: &pointer::event::AbsoluteMotion) {
+        (**self).on_pointer_motion_absolute(compositor_handle, cursor_handle, event)
+    }
+
+    fn on_pointer_button(&mut self,
+                         compositor_handle: compositor::Handle,
+                         cursor_handle: Handle,
+                         event: &pointer::event::Button) {
+        (**self).on_pointer_button(compositor_handle, cursor_handle, event)
+    }
+
+    fn on_pointer_axis(&mut self,
+                       compositor_handle: compositor::Handle,
+                       cursor_handle: Handle,
+                       event: &pointer::event::Axis) {
+        (**self).on_pointer_axis(compositor_handle, cursor_handle, event)
+    }
+
+    fn on_touch_up(&mut self,
+                   compositor_handle: compositor::Handle,
+                   cursor_handle: Handle,
+                   event: &touch::event::Up) {
+        (**self).on_touch_up(compositor_handle, cursor_handle, event)
+    }
+
+    fn on_touch_down(&mut self,
+                     compositor_handle: compositor::Handle,
+                     cursor_handle: Handle,
+                     event: &touch::event::Down) {
+        (**self).on_touch_down(compositor_handle, cursor_handle, event)
+    }
+
+    fn on_touch_motion(&mut self,
+                       compositor_handle: compositor::Handle,
+                       cursor_handle: Handle,
+                       event: &touch::event::Motion) {
+        (**self).on_touch_motion(compositor_handle, cursor_handle, event)
+    }
+
+    fn on_touch_cancel(&mut self,
+                       compositor_handle: compositor::Handle,
+                       cursor_handle: Handle,
+                       event: &touch::event::Cancel) {
+        (**self).on_touch_cancel(compositor_handle, cursor_handle, event)
+    }
+
+    fn on_tablet_tool_axis(&mut self,
+                           compositor_handle: compositor::Handle,
+                           cursor_handle: Handle,
+                           event: &tablet_tool::event::Axis) {
+        (**self).on_tablet_tool_axis(compositor_handle, cursor_handle, event)
+    }
+
+    fn on_tablet_tool_proximity(&mut self,
+                                compositor_handle: compositor::Handle,
+                                cursor_handle: Handle,
+                                event: &tablet_tool::event::Proximity) {
+        (**self).on_tablet_tool_proximity(compositor_handle, cursor_handle, event)
+    }
+
+    fn on_tablet_tool_tip(&mut self,
+                          compositor_handle: compositor::Handle,
+                          cursor_handle: Handle,
+                          event: &tablet_tool::event::Tip) {
+        (**self).on_tablet_tool_tip(compositor_handle, cursor_handle, event)
+    }
+
+    fn on_tablet_tool_button(&mut self,
+                             compositor_handle: compositor::Handle,
+                             cursor_handle: Handle,
+                             event: &tablet_tool::event::Button) {
+        (**self).on_tablet_tool_button(compositor_handle, cursor_handle, event)
+    }
+}
+
+/// A cursor, which moves around the output layout with the input devices
+/// attached to it.
+pub struct Cursor {
+    cursor: *mut wlr_cursor,
+    output_layout: Option<output::layout::Handle>
+}
+
+// NOTE The handler is stored as is rather than boxed, so the events of the
+// cursor (pointer motion in particular, which comes in at the rate of the
+// mouse) are dispatched to it statically.
+wayland_listener!(pub(crate) Listener<H: Handler>, (*mut wlr_cursor, H), [
+    pointer_motion_listener => pointer_motion_notify:
+    |this: &mut Listener<H>, event: *mut libc::c_void,|
     unsafe {
-        let (cursor_ptr, ref mut cursor_handler, _) = this.data;
+        let (cursor_ptr, ref mut cursor_handler) = this.data;
         let cursor = Cursor::from_ptr(cursor_ptr);
         let event = pointer::event::Motion::from_ptr(event as _);
         let compositor = match compositor::handle() {
@@ -122,6 +256,7 @@ wayland_listener!(pub Cursor, (*mut wlr_cursor, Box<Handler>, Option<output::lay
             None => return
         };
 
+        pointer_activity(cursor_ptr);
         cursor_handler.on_pointer_motion(compositor,
                                          cursor.weak_reference(),
                                          &event);
@@ -129,9 +264,9 @@ wayland_listener!(pub Cursor, (*mut wlr_cursor, Box<Handler>, Option<output::lay
         Box::into_raw(cursor);
     };
     pointer_motion_absolute_listener => pointer_motion_absolute_notify:
-    |this: &mut Cursor, event: *mut libc::c_void,|
+    |this: &mut Listener<H>, event: *mut libc::c_void,|
     unsafe {
-        let (cursor_ptr, ref mut cursor_handler, _) = this.data;
+        let (cursor_ptr, ref mut cursor_handler) = this.data;
         let event = pointer::event::AbsoluteMotion::from_ptr(event as _);
         let cursor = Cursor::from_ptr(cursor_ptr);
         let compositor = match compositor::handle() {
@@ -139,15 +274,17 @@ wayland_listener!(pub Cursor, (*mut wlr_cursor, Box<Handler>, Option<output::lay
             None => return
         };
 
+        pointer_activity(cursor_ptr);
         cursor_handler.on_pointer_motion_absolute(compositor,
                                                   cursor.weak_reference(),
                                                   &event);
 
         Box::into_raw(cursor);
     };
-    pointer_button_listener => pointer_button_notify: |this: &mut Cursor, event: *mut libc::c_void,|
+    pointer_button_listener => pointer_button_notify:
+    |this: &mut Listener<H>, event: *mut libc::c_void,|
     unsafe {
-        let (cursor_ptr, ref mut cursor_handler, _) = this.data;
+        let (cursor_ptr, ref mut cursor_handler) = this.data;
         let cursor = Cursor::from_ptr(cursor_ptr);
         let event = pointer::event::Button::from_ptr(event as _);
         let compositor = match compositor::handle() {
@@ -155,15 +292,17 @@ wayland_listener!(pub Cursor, (*mut wlr_cursor, Box<Handler>, Option<output::lay
             None => return
         };
 
+        pointer_activity(cursor_ptr);
         cursor_handler.on_pointer_button(compositor,
                                          cursor.weak_reference(),
                                          &event);
 
         Box::into_raw(cursor);
     };
-    pointer_axis_listener => pointer_axis_notify: |this: &mut Cursor, event: *mut libc::c_void,|
+    pointer_axis_listener => pointer_axis_notify:
+    |this: &mut Listener<H>, event: *mut libc::c_void,|
     unsafe {
-        let (cursor_ptr, ref mut cursor_handler, _) = this.data;
+        let (cursor_ptr, ref mut cursor_handler) = this.data;
         let cursor = Cursor::from_ptr(cursor_ptr);
         let event = pointer::event::Axis::from_ptr(event as _);
         let compositor = match compositor::handle() {
@@ -171,15 +310,17 @@ wayland_listener!(pub Cursor, (*mut wlr_cursor, Box<Handler>, Option<output::lay
             None => return
         };
 
+        pointer_activity(cursor_ptr);
         cursor_handler.on_pointer_axis(compositor,
                                        cursor.weak_reference(),
                                        &event);
 
         Box::into_raw(cursor);
     };
-    touch_up_listener => touch_up_notify: |this: &mut Cursor, event: *mut libc::c_void,|
+    touch_up_listener => touch_up_notify:
+    |this: &mut Listener<H>, event: *mut libc::c_void,|
     unsafe {
-        let (cursor_ptr, ref mut cursor_handler, _) = this.data;
+        let (cursor_ptr, ref mut cursor_handler) = this.data;
         let cursor = Cursor::from_ptr(cursor_ptr);
         let event = touch::event::Up::from_ptr(event as _);
         let compositor = match compositor::handle() {
@@ -193,9 +334,10 @@ wayland_listener!(pub Cursor, (*mut wlr_cursor, Box<Handler>, Option<output::lay
 
         Box::into_raw(cursor);
     };
-    touch_down_listener => touch_down_notify: |this: &mut Cursor, event: *mut libc::c_void,|
+    touch_down_listener => touch_down_notify:
+    |this: &mut Listener<H>, event: *mut libc::c_void,|
     unsafe {
-        let (cursor_ptr, ref mut cursor_handler, _) = this.data;
+        let (cursor_ptr, ref mut cursor_handler) = this.data;
         let cursor = Cursor::from_ptr(cursor_ptr);
         let event = touch::event::Down::from_ptr(event as _);
         let compositor = match compositor::handle() {
@@ -209,9 +351,10 @@ wayland_listener!(pub Cursor, (*mut wlr_cursor, Box<Handler>, Option<output::lay
 
         Box::into_raw(cursor);
     };
-    touch_motion_listener => touch_motion_notify: |this: &mut Cursor, event: *mut libc::c_void,|
+    touch_motion_listener => touch_motion_notify:
+    |this: &mut Listener<H>, event: *mut libc::c_void,|
     unsafe {
-        let (cursor_ptr, ref mut cursor_handler, _) = this.data;
+        let (cursor_ptr, ref mut cursor_handler) = this.data;
         let cursor = Cursor::from_ptr(cursor_ptr);
         let event = touch::event::Motion::from_ptr(event as _);
         let compositor = match compositor::handle() {
@@ -225,9 +368,10 @@ wayland_listener!(pub Cursor, (*mut wlr_cursor, Box<Handler>, Option<output::lay
 
         Box::into_raw(cursor);
     };
-    touch_cancel_listener => touch_cancel_notify: |this: &mut Cursor, event: *mut libc::c_void,|
+    touch_cancel_listener => touch_cancel_notify:
+    |this: &mut Listener<H>, event: *mut libc::c_void,|
     unsafe {
-        let (cursor_ptr, ref mut cursor_handler, _) = this.data;
+        let (cursor_ptr, ref mut cursor_handler) = this.data;
         let cursor = Cursor::from_ptr(cursor_ptr);
         let event = touch::event::Cancel::from_ptr(event as _);
         let compositor = match compositor::handle() {
@@ -241,10 +385,10 @@ wayland_listener!(pub Cursor, (*mut wlr_cursor, Box<Handler>, Option<output::lay
 
         Box::into_raw(cursor);
     };
-    tablet_tool_axis_listener => tablet_tool_axis_notify: |this: &mut Cursor,
-                                                           event: *mut libc::c_void,|
+    tablet_tool_axis_listener => tablet_tool_axis_notify:
+    |this: &mut Listener<H>, event: *mut libc::c_void,|
     unsafe {
-        let (cursor_ptr, ref mut cursor_handler, _) = this.data;
+        let (cursor_ptr, ref mut cursor_handler) = this.data;
         let cursor = Cursor::from_ptr(cursor_ptr);
         let event = tablet_tool::event::Axis::from_ptr(event as _);
         let compositor = match compositor::handle() {
@@ -252,16 +396,17 @@ wayland_listener!(pub Cursor, (*mut wlr_cursor, Box<Handler>, Option<output::lay
             None => return
         };
 
+        pointer_activity(cursor_ptr);
         cursor_handler.on_tablet_tool_axis(compositor,
                                            cursor.weak_reference(),
                                            &event);
 
         Box::into_raw(cursor);
     };
-    tablet_tool_proximity_listener => tablet_tool_proximity_notify: |this: &mut Cursor,
-                                                                     event: *mut libc::c_void,|
+    tablet_tool_proximity_listener => tablet_tool_proximity_notify:
+    |this: &mut Listener<H>, event: *mut libc::c_void,|
     unsafe {
-        let (cursor_ptr, ref mut cursor_handler, _) = this.data;
+        let (cursor_ptr, ref mut cursor_handler) = this.data;
         let cursor = Cursor::from_ptr(cursor_ptr);
         let event = tablet_tool::event::Proximity::from_ptr(event as _);
         let compositor = match compositor::handle() {
@@ -275,10 +420,10 @@ wayland_listener!(pub Cursor, (*mut wlr_cursor, Box<Handler>, Option<output::lay
 
         Box::into_raw(cursor);
     };
-    tablet_tool_tip_listener => tablet_tool_tip_notify: |this: &mut Cursor,
-                                                         event: *mut libc::c_void,|
+    tablet_tool_tip_listener => tablet_tool_tip_notify:
+    |this: &mut Listener<H>, event: *mut libc::c_void,|
     unsafe {
-        let (cursor_ptr, ref mut cursor_handler, _) = this.data;
+        let (cursor_ptr, ref mut cursor_handler) = this.data;
         let cursor = Cursor::from_ptr(cursor_ptr);
         let event = tablet_tool::event::Tip::from_ptr(event as _);
         let compositor = match compositor::handle() {
@@ -286,16 +431,17 @@ wayland_listener!(pub Cursor, (*mut wlr_cursor, Box<Handler>, Option<output::lay
             None => return
         };
 
+        pointer_activity(cursor_ptr);
         cursor_handler.on_tablet_tool_tip(compositor,
                                           cursor.weak_reference(),
                                           &event);
 
         Box::into_raw(cursor);
     };
-    tablet_tool_button_listener => tablet_tool_button_notify: |this: &mut Cursor,
-                                                               event: *mut libc::c_void,|
+    tablet_tool_button_listener => tablet_tool_button_notify:
+    |this: &mut Listener<H>, event: *mut libc::c_void,|
     unsafe {
-        let (cursor_ptr, ref mut cursor_handler, _) = this.data;
+        let (cursor_ptr, ref mut cursor_handler) = this.data;
         let cursor = Cursor::from_ptr(cursor_ptr);
         let event = tablet_tool::event::Button::from_ptr(event as _);
         let compositor = match compositor::handle() {
@@ -313,50 +459,70 @@ wayland_listener!(pub Cursor, (*mut wlr_cursor, Box<Handler>, Option<output::lay
 
 impl fmt::Debug for Cursor {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        write!(f, "{:?}", self.data.0)
+        write!(f, "{:?}", self.cursor)
     }
 }
 
 impl Cursor {
-    pub fn create(cursor_handler: Box<Handler>) -> Handle {
+    /// Create a cursor, which isn't attached to an output layout or any
+    /// input devices yet.
+    pub fn create(cursor_handler: Box<Handler>) -> Result<Handle, Error> {
+        Cursor::create_with(cursor_handler)
+    }
+
+    /// Create a cursor like `create`, without boxing the handler.
+    ///
+    /// The events of the cursor then call the methods of the handler
+    /// directly, rather than through a trait object.
+    pub fn create_with<H: Handler + 'static>(cursor_handler: H) -> Result<Handle, Error> {
         unsafe {
             let cursor_ptr = wlr_cursor_create();
             if cursor_ptr.is_null() {
-                panic!("Could not create wlr_cursor")
+                return Err(Error::Create("wlr_cursor"))
             }
-            let mut cursor = Cursor::new((cursor_ptr, cursor_handler, None));
+            let mut listener = Listener::new((cursor_ptr, cursor_handler));
             wl_signal_add(&mut (*cursor_ptr).events.motion as *mut _ as _,
-                          cursor.pointer_motion_listener() as *mut _ as _);
+                          listener.pointer_motion_listener() as *mut _ as _);
             wl_signal_add(&mut (*cursor_ptr).events.motion_absolute as *mut _ as _,
-                          cursor.pointer_motion_absolute_listener() as *mut _ as _);
+                          listener.pointer_motion_absolute_listener() as *mut _ as _);
             wl_signal_add(&mut (*cursor_ptr).events.button as *mut _ as _,
-                          cursor.pointer_button_listener() as *mut _ as _);
+                          listener.pointer_button_listener() as *mut _ as _);
             wl_signal_add(&mut (*cursor_ptr).events.axis as *mut _ as _,
-                          cursor.pointer_axis_listener() as *mut _ as _);
+                          listener.pointer_axis_listener() as *mut _ as _);
             wl_signal_add(&mut (*cursor_ptr).events.touch_up as *mut _ as _,
-                          cursor.touch_up_listener() as *mut _ as _);
+                          listener.touch_up_listener() as *mut _ as _);
             wl_signal_add(&mut (*cursor_ptr).events.touch_down as *mut _ as _,
-                          cursor.touch_down_listener() as *mut _ as _);
+                          listener.touch_down_listener() as *mut _ as _);
             wl_signal_add(&mut (*cursor_ptr).events.touch_motion as *mut _ as _,
-                          cursor.touch_motion_listener() as *mut _ as _);
+                          listener.touch_motion_listener() as *mut _ as _);
             wl_signal_add(&mut (*cursor_ptr).events.touch_cancel as *mut _ as _,
-                          cursor.touch_cancel_listener() as *mut _ as _);
+                          listener.touch_cancel_listener() as *mut _ as _);
             wl_signal_add(&mut (*cursor_ptr).events.tablet_tool_axis as *mut _ as _,
-                          cursor.tablet_tool_axis_listener() as *mut _ as _);
+                          listener.tablet_tool_axis_listener() as *mut _ as _);
             wl_signal_add(&mut (*cursor_ptr).events.tablet_tool_proximity as *mut _ as _,
-                          cursor.tablet_tool_proximity_listener() as *mut _ as _);
+                          listener.tablet_tool_proximity_listener() as *mut _ as _);
             wl_signal_add(&mut (*cursor_ptr).events.tablet_tool_tip as *mut _ as _,
-                          cursor.tablet_tool_tip_listener() as *mut _ as _);
+                          listener.tablet_tool_tip_listener() as *mut _ as _);
             wl_signal_add(&mut (*cursor_ptr).events.tablet_tool_button as *mut _ as _,
-                          cursor.tablet_tool_button_listener() as *mut _ as _);
+                          listener.tablet_tool_button_listener() as *mut _ as _);
             let counter = Rc::new(Cell::new(false));
             let handle = Rc::downgrade(&counter);
+            let cursor = Box::new(Cursor { cursor: cursor_ptr, output_layout: None });
             let state = Box::new(CursorState { counter,
                                                cursor: Box::into_raw(cursor),
-                                               output_layout: None });
+                                               listener: Box::into_raw(listener) as *mut _,
+                                               destroy_listener: destroy_listener::<H>,
+                                               output_layout: None,
+                                               bounds: None,
+                                               image: None,
+                                               hidden: false,
+                                               hide_timeout: None,
+                                               hide_timer: ptr::null_mut(),
+                                               hide_when_typing: false });
             (*cursor_ptr).data = Box::into_raw(state) as *mut libc::c_void;
-            Handle { cursor: cursor_ptr,
-                           handle }
+            CURSORS.with(|cursors| cursors.borrow_mut().push(cursor_ptr));
+            Ok(Handle { cursor: cursor_ptr,
+                        handle })
         }
     }
 
@@ -369,14 +535,14 @@ impl Cursor {
     }
 
     pub(crate) fn as_ptr(&self) -> *mut wlr_cursor {
-        self.data.0
+        self.cursor
     }
 
     /// Get a weak reference to this `Cursor`.
     pub fn weak_reference(&self) -> Handle {
         unsafe {
-            let handle = Rc::downgrade(&(*((*self.data.0).data as *mut CursorState)).counter);
-            Handle { cursor: self.data.0,
+            let handle = Rc::downgrade(&(*((*self.cursor).data as *mut CursorState)).counter);
+            Handle { cursor: self.cursor,
                            handle }
         }
     }
@@ -385,28 +551,84 @@ impl Cursor {
     pub fn attach_output_layout(&mut self, output_layout: &mut Layout) {
         unsafe {
             let weak_reference = Some(output_layout.weak_reference().clone());
-            self.data.2 = weak_reference.clone();
-            let mut data = Box::from_raw((*self.data.0).data as *mut CursorState);
+            self.output_layout = weak_reference.clone();
+            let mut data = Box::from_raw((*self.cursor).data as *mut CursorState);
             data.output_layout = weak_reference;
-            (*self.data.0).data = Box::into_raw(data) as *mut libc::c_void;
-            wlr_cursor_attach_output_layout(self.data.0, output_layout.as_ptr());
+            (*self.cursor).data = Box::into_raw(data) as *mut libc::c_void;
+            wlr_cursor_attach_output_layout(self.cursor, output_layout.as_ptr());
         }
     }
 
     pub fn deattach_output_layout(&mut self) {
         unsafe {
             let weak_reference = None;
-            self.data.2 = weak_reference.clone();
-            let mut data = Box::from_raw((*self.data.0).data as *mut CursorState);
+            self.output_layout = weak_reference.clone();
+            let mut data = Box::from_raw((*self.cursor).data as *mut CursorState);
             data.output_layout = weak_reference;
-            (*self.data.0).data = Box::into_raw(data) as *mut libc::c_void;
-            wlr_cursor_attach_output_layout(self.data.0, ptr::null_mut());
+            (*self.cursor).data = Box::into_raw(data) as *mut libc::c_void;
+            wlr_cursor_attach_output_layout(self.cursor, ptr::null_mut());
         }
     }
 
     /// Get the coordinates the cursor is located at.
     pub fn coords(&self) -> (f64, f64) {
-        unsafe { ((*self.data.0).x, (*self.data.0).y) }
+        unsafe { ((*self.cursor).x, (*self.cursor).y) }
+    }
+
+    /// Restrict the cursor to the given area, in layout coordinates.
+    ///
+    /// While bounds are set warps outside of the area are rejected and
+    /// relative motion is clamped to the edge of the area. This is useful to
+    /// stop the cursor from wandering into gaps between the outputs of a
+    /// layout, where it can't be seen.
+    ///
+    /// If the cursor is currently outside of the bounds it is moved to the
+    /// closest point within them.
+    ///
+    /// Pass `None` to remove the bounds (the default).
+    pub fn set_bounds<T: Into<Option<Area>>>(&mut self, bounds: T) {
+        let bounds = bounds.into();
+        unsafe {
+            (*((*self.cursor).data as *mut CursorState)).bounds = bounds;
+        }
+        if let Some(bounds) = bounds {
+            let (x, y) = self.coords();
+            if !bounds.contains_point(x, y) {
+                let (x, y) = bounds.closest_point(x, y);
+                self.warp(None, x, y);
+            }
+        }
+    }
+
+    /// Get the bounds set by `set_bounds`, if there are any.
+    pub fn bounds(&self) -> Option<Area> {
+        unsafe { (*((*self.cursor).data as *mut CursorState)).bounds }
+    }
+
+    /// Restrict the cursor to the area of the attached output layout.
+    ///
+    /// This is a convenience wrapper around `set_bounds` using the bounding box
+    /// of the attached `Layout`. Note the bounds are not updated when the layout
+    /// changes, so call this again when an output is added, removed or moved.
+    ///
+    /// Returns an error if the layout can't be used right now, in which case
+    /// the bounds are left alone, or if it was dropped, in which case they're
+    /// removed.
+    pub fn clamp_to_layout(&mut self) -> HandleResult<()> {
+        self.assert_layout();
+        let layout_box = self.output_layout.clone().unwrap()
+            .run(|layout| layout.get_box(None));
+        match layout_box {
+            Ok(area) => {
+                self.set_bounds(area);
+                Ok(())
+            },
+            Err(HandleErr::AlreadyDropped) => {
+                self.set_bounds(None);
+                Err(HandleErr::AlreadyDropped)
+            },
+            Err(err) => Err(err)
+        }
     }
 
     /// Warp the cursor to the given x and y in layout coordinates. If x and y are
@@ -420,10 +642,15 @@ impl Cursor {
         where O: Into<Option<&'this input::Device>>
     {
         self.assert_layout();
+        if let Some(bounds) = self.bounds() {
+            if !bounds.contains_point(x, y) {
+                return false
+            }
+        }
         unsafe {
             let dev_ptr = dev.into().map(|input_device| input_device.as_ptr())
                              .unwrap_or(ptr::null_mut());
-            wlr_cursor_warp(self.data.0, dev_ptr, x, y)
+            wlr_cursor_warp(self.cursor, dev_ptr, x, y)
         }
     }
 
@@ -434,7 +661,20 @@ impl Cursor {
         unsafe {
             let dev_ptr = dev.into().map(|input_device| input_device.as_ptr())
                              .unwrap_or(ptr::null_mut());
-            wlr_cursor_warp_absolute(self.data.0, dev_ptr, x_mm, y_mm)
+            match self.bounds() {
+                None => wlr_cursor_warp_absolute(self.cursor, dev_ptr, x_mm, y_mm),
+                Some(bounds) => {
+                    let (mut lx, mut ly) = (0.0, 0.0);
+                    wlr_cursor_absolute_to_layout_coords(self.cursor,
+                                                         dev_ptr,
+                                                         x_mm,
+                                                         y_mm,
+                                                         &mut lx,
+                                                         &mut ly);
+                    let (lx, ly) = bounds.closest_point(lx, ly);
+                    wlr_cursor_warp(self.cursor, dev_ptr, lx, ly);
+                }
+            }
         }
     }
 
@@ -442,14 +682,24 @@ impl Cursor {
     ///
     /// `dev` may be passed to respect device mapping constraints. If `dev` is None,
     /// device mapping constraints will be ignored.
+    ///
+    /// If bounds are set with `set_bounds` the motion is clamped to them.
     pub fn move_to<'this, O>(&'this mut self, dev: O, delta_x: f64, delta_y: f64)
         where O: Into<Option<&'this input::Device>>
     {
         self.assert_layout();
+        let (delta_x, delta_y) = match self.bounds() {
+            None => (delta_x, delta_y),
+            Some(bounds) => {
+                let (x, y) = self.coords();
+                let (dest_x, dest_y) = bounds.closest_point(x + delta_x, y + delta_y);
+                (dest_x - x, dest_y - y)
+            }
+        };
         unsafe {
             let dev_ptr = dev.into().map(|dev| dev.as_ptr())
                              .unwrap_or(ptr::null_mut());
-            wlr_cursor_move(self.data.0, dev_ptr, delta_x, delta_y)
+            wlr_cursor_move(self.cursor, dev_ptr, delta_x, delta_y)
         }
     }
 
@@ -457,12 +707,21 @@ impl Cursor {
     /// Sets the image of the cursor to the image.
     pub fn set_cursor_image(&mut self, image: &xcursor::Image) {
         unsafe {
+            let state = (*self.cursor).data as *mut CursorState;
+            (*state).image = Some(CursorImage::Pixels { buffer: image.buffer.to_vec(),
+                                                        width: image.width,
+                                                        height: image.height,
+                                                        hotspot_x: image.hotspot_x,
+                                                        hotspot_y: image.hotspot_y });
+            if (*state).hidden {
+                return
+            }
             // NOTE Rationale for why lifetime isn't attached:
             //
             // wlr_cursor_set_image uses gl calls internally, which copies
             // the buffer and so it doesn't matter what happens to the
             // xcursor image after this call.
-            wlr_cursor_set_image(self.data.0,
+            wlr_cursor_set_image(self.cursor,
                                  image.buffer.as_ptr(),
                                  (image.width * 4) as i32,
                                  image.width,
@@ -481,10 +740,77 @@ impl Cursor {
         where O: Into<Option<&'this Surface>>
     {
         unsafe {
-            let surface_ptr = surface.into()
-                                     .map(|surface| surface.as_ptr())
+            let surface = surface.into();
+            let state = (*self.cursor).data as *mut CursorState;
+            (*state).image = Some(CursorImage::Surface { surface: surface.map(|surface| {
+                                                             surface.weak_reference()
+                                                         }),
+                                                         hotspot_x,
+                                                         hotspot_y });
+            if (*state).hidden {
+                return
+            }
+            let surface_ptr = surface.map(|surface| surface.as_ptr())
                                      .unwrap_or(ptr::null_mut());
-            wlr_cursor_set_surface(self.data.0, surface_ptr, hotspot_x, hotspot_y)
+            wlr_cursor_set_surface(self.cursor, surface_ptr, hotspot_x, hotspot_y)
+        }
+    }
+
+    /// Show the cursor of the theme of the manager, at every scale it loaded.
+    pub(crate) unsafe fn set_themed_image(&self,
+                                          manager: *mut wlr_xcursor_manager,
+                                          name: CString) {
+        let state = (*self.cursor).data as *mut CursorState;
+        (*state).image = Some(CursorImage::Themed { manager, name });
+        if !(*state).hidden {
+            apply_image(self.cursor)
+        }
+    }
+
+    /// Hide the cursor, until it's shown again with `show` or the pointer
+    /// moves.
+    ///
+    /// The image or surface set while it's hidden is shown once it's shown
+    /// again. This hides both hardware and software cursors.
+    pub fn hide(&mut self) {
+        unsafe { hide(self.cursor) }
+    }
+
+    /// Show the cursor again after it was hidden.
+    pub fn show(&mut self) {
+        unsafe { show(self.cursor) }
+    }
+
+    /// Determines if the cursor is hidden.
+    pub fn is_hidden(&self) -> bool {
+        unsafe { (*((*self.cursor).data as *mut CursorState)).hidden }
+    }
+
+    /// Hide the cursor once the pointer has been left alone for the duration,
+    /// until it moves again.
+    ///
+    /// Pass `None` to never hide it because of inactivity (the default).
+    pub fn set_hide_timeout<T: Into<Option<Duration>>>(&mut self, timeout: T) {
+        unsafe {
+            let state = (*self.cursor).data as *mut CursorState;
+            (*state).hide_timeout = timeout.into();
+            restart_hide_timer(self.cursor);
+        }
+    }
+
+    /// Hide the cursor whenever a key is pressed, until the pointer moves
+    /// again.
+    pub fn set_hide_when_typing(&mut self, hide_when_typing: bool) {
+        unsafe {
+            let cursor_ptr = self.cursor;
+            (*((*cursor_ptr).data as *mut CursorState)).hide_when_typing = hide_when_typing;
+            HIDE_WHEN_TYPING.with(|cursors| {
+                                      let mut cursors = cursors.borrow_mut();
+                                      cursors.retain(|&cursor| cursor != cursor_ptr);
+                                      if hide_when_typing {
+                                          cursors.push(cursor_ptr);
+                                      }
+                                  });
         }
     }
 
@@ -502,12 +828,12 @@ impl Cursor {
         // Internally, on the destroy event this will automatically
         // destroy the internal wlr_cursor_device used to refer to
         // this input::Device.
-        unsafe { wlr_cursor_attach_input_device(self.data.0, dev.as_ptr()) }
+        unsafe { wlr_cursor_attach_input_device(self.cursor, dev.as_ptr()) }
     }
 
     /// Deattaches the input device from this cursor.
     pub fn deattach_input_device(&mut self, dev: &input::Device) {
-        unsafe { wlr_cursor_detach_input_device(self.data.0, dev.as_ptr()) }
+        unsafe { wlr_cursor_detach_input_device(self.cursor, dev.as_ptr()) }
     }
 
     /// Attaches this cursor to the given output, which must be among the outputs in
@@ -515,13 +841,13 @@ impl Cursor {
     pub fn map_to_output<'a, T: Into<Option<&'a mut Output>>>(&mut self, output: T) {
         self.assert_layout();
         match output.into() {
-            None => unsafe { wlr_cursor_map_to_output(self.data.0, ptr::null_mut()) },
+            None => unsafe { wlr_cursor_map_to_output(self.cursor, ptr::null_mut()) },
             Some(output) => {
                 if !self.output_in_output_layout(output.weak_reference()) {
                     wlr_log!(WLR_ERROR, "Tried to map output not in the Layout");
                     return
                 }
-                unsafe { wlr_cursor_map_to_output(self.data.0, output.as_ptr()) }
+                unsafe { wlr_cursor_map_to_output(self.cursor, output.as_ptr()) }
             }
         }
     }
@@ -541,7 +867,7 @@ impl Cursor {
 
         match output.into() {
             None => unsafe {
-                wlr_cursor_map_input_to_output(self.data.0, dev.as_ptr(), ptr::null_mut())
+                wlr_cursor_map_input_to_output(self.cursor, dev.as_ptr(), ptr::null_mut())
             },
             Some(output) => {
                 if !self.output_in_output_layout(output.weak_reference()) {
@@ -550,7 +876,7 @@ impl Cursor {
                     return
                 }
                 unsafe {
-                    wlr_cursor_map_input_to_output(self.data.0, dev.as_ptr(), output.as_ptr())
+                    wlr_cursor_map_input_to_output(self.cursor, dev.as_ptr(), output.as_ptr())
                 }
             }
         }
@@ -560,7 +886,7 @@ impl Cursor {
     /// wlr_output_layout.
     pub fn map_to_region(&mut self, area: Area) {
         self.assert_layout();
-        unsafe { wlr_cursor_map_to_region(self.data.0, &mut area.into()) }
+        unsafe { wlr_cursor_map_to_region(self.cursor, &mut area.into()) }
     }
 
     /// Maps inputs from this input device to an arbitrary region on the associated
@@ -573,7 +899,7 @@ impl Cursor {
         //
         // If the input isn't found, then wlroots prints a diagnostic and
         // returns early (and thus does nothing unsafe).
-        unsafe { wlr_cursor_map_input_to_region(self.data.0, dev.as_ptr(), &mut area.into()) }
+        unsafe { wlr_cursor_map_input_to_region(self.cursor, dev.as_ptr(), &mut area.into()) }
     }
 
     /// Convert absolute coordinates to layout coordinates for the device.
@@ -587,7 +913,7 @@ impl Cursor {
         self.assert_layout();
         unsafe {
             let (mut lx, mut ly) = (0.0, 0.0);
-            wlr_cursor_absolute_to_layout_coords(self.data.0,
+            wlr_cursor_absolute_to_layout_coords(self.cursor,
                                                  dev.as_ptr(),
                                                  x_mm,
                                                  y_mm,
@@ -599,8 +925,8 @@ impl Cursor {
 
     /// Determines if we are within a valid layout.
     fn assert_layout(&self) {
-        match self.data.2.clone().map(|layout| layout.run(|_| ())) {
-            Some(Ok(())) | Some(Err(HandleErr::AlreadyBorrowed)) => {}
+        match self.output_layout.clone().map(|layout| layout.run(|_| ())) {
+            Some(Ok(())) | Some(Err(HandleErr::AlreadyBorrowed { .. })) => {}
             None | Some(Err(_)) => panic!("Cursor was not attached to an output layout!")
         }
     }
@@ -612,7 +938,7 @@ impl Cursor {
     /// Otherwise it returns `true`.
     fn output_in_output_layout(&mut self, output: output::Handle) -> bool {
         self.assert_layout();
-        match self.data.2.clone().unwrap().run(|output_layout| {
+        match self.output_layout.clone().unwrap().run(|output_layout| {
                                                    for (cur_output, _) in output_layout.outputs() {
                                                        if cur_output == output {
                                                            return true
@@ -630,52 +956,175 @@ impl Cursor {
 impl Drop for Cursor {
     fn drop(&mut self) {
         wlr_log!(WLR_DEBUG, "Dropped {:?}", self);
-        let cursor_ptr = self.data.0;
+        let cursor_ptr = self.cursor;
         unsafe {
-            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
-                          wl_list_remove,
-                          &mut (*self.pointer_motion_listener()).link as *mut _ as _);
-            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
-                          wl_list_remove,
-                          &mut (*self.pointer_motion_absolute_listener()).link as *mut _ as _);
-            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
-                          wl_list_remove,
-                          &mut (*self.pointer_button_listener()).link as *mut _ as _);
-            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
-                          wl_list_remove,
-                          &mut (*self.pointer_axis_listener()).link as *mut _ as _);
-            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
-                          wl_list_remove,
-                          &mut (*self.touch_up_listener()).link as *mut _ as _);
-            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
-                          wl_list_remove,
-                          &mut (*self.touch_down_listener()).link as *mut _ as _);
-            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
-                          wl_list_remove,
-                          &mut (*self.touch_motion_listener()).link as *mut _ as _);
-            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
-                          wl_list_remove,
-                          &mut (*self.touch_cancel_listener()).link as *mut _ as _);
-            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
-                          wl_list_remove,
-                          &mut (*self.tablet_tool_axis_listener()).link as *mut _ as _);
-            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
-                          wl_list_remove,
-                          &mut (*self.tablet_tool_proximity_listener()).link as *mut _ as _);
-            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
-                          wl_list_remove,
-                          &mut (*self.tablet_tool_tip_listener()).link as *mut _ as _);
-            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
-                          wl_list_remove,
-                          &mut (*self.tablet_tool_button_listener()).link as *mut _ as _);
+            let state = (*cursor_ptr).data as *mut CursorState;
+            ((*state).destroy_listener)((*state).listener);
+            utils::user_data::clear(cursor_ptr);
+            HIDE_WHEN_TYPING.with(|cursors| {
+                                      cursors.borrow_mut().retain(|&cursor| cursor != cursor_ptr)
+                                  });
+            CURSORS.with(|cursors| cursors.borrow_mut().retain(|&cursor| cursor != cursor_ptr));
             let data = Box::from_raw((*cursor_ptr).data as *mut CursorState);
-            let _ = Box::from_raw(data.cursor);
+            if !data.hide_timer.is_null() {
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_event_source_remove, data.hide_timer);
+            }
+            // NOTE `data.cursor` is what's being dropped, so it isn't freed here.
             assert_eq!(Rc::strong_count(&data.counter),
                        1,
                        "Cursor had more than 1 reference count");
             (*cursor_ptr).data = ptr::null_mut();
-            wlr_cursor_destroy(self.data.0)
+            wlr_cursor_destroy(self.cursor)
+        }
+    }
+}
+
+/// Stop listening to the events of the cursor, and drop the handler.
+unsafe fn destroy_listener<H: Handler>(listener: *mut libc::c_void) {
+    let mut listener = Box::from_raw(listener as *mut Listener<H>);
+    let listeners = [listener.pointer_motion_listener(),
+                     listener.pointer_motion_absolute_listener(),
+                     listener.pointer_button_listener(),
+                     listener.pointer_axis_listener(),
+                     listener.touch_up_listener(),
+                     listener.touch_down_listener(),
+                     listener.touch_motion_listener(),
+                     listener.touch_cancel_listener(),
+                     listener.tablet_tool_axis_listener(),
+                     listener.tablet_tool_proximity_listener(),
+                     listener.tablet_tool_tip_listener(),
+                     listener.tablet_tool_button_listener()];
+    for &listener in &listeners {
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_list_remove,
+                      &mut (*listener).link as *mut _ as _);
+    }
+}
+
+unsafe fn hide(cursor: *mut wlr_cursor) {
+    let state = (*cursor).data as *mut CursorState;
+    if (*state).hidden {
+        return
+    }
+    (*state).hidden = true;
+    wlr_cursor_set_image(cursor, ptr::null(), 0, 0, 0, 0, 0, 1.0)
+}
+
+unsafe fn show(cursor: *mut wlr_cursor) {
+    let state = (*cursor).data as *mut CursorState;
+    if !(*state).hidden {
+        return
+    }
+    (*state).hidden = false;
+    apply_image(cursor)
+}
+
+/// Set the image the cursor last showed again, e.g. after it was hidden.
+///
+/// wlroots uploads the image for every output as it is then, so this has to
+/// be done again whenever the scale or transform of an output changes.
+unsafe fn apply_image(cursor: *mut wlr_cursor) {
+    let state = (*cursor).data as *mut CursorState;
+    match (*state).image {
+        Some(CursorImage::Pixels { ref buffer, width, height, hotspot_x, hotspot_y }) => {
+            wlr_cursor_set_image(cursor,
+                                 buffer.as_ptr(),
+                                 (width * 4) as i32,
+                                 width,
+                                 height,
+                                 hotspot_x as _,
+                                 hotspot_y as _,
+                                 1.0)
+        },
+        Some(CursorImage::Surface { ref surface, hotspot_x, hotspot_y }) => {
+            // NOTE The surface could have been destroyed while hidden.
+            let surface_ptr = surface.as_ref()
+                                     .and_then(|surface| {
+                                         surface.run(|surface| surface.as_ptr()).ok()
+                                     })
+                                     .unwrap_or(ptr::null_mut());
+            wlr_cursor_set_surface(cursor, surface_ptr, hotspot_x, hotspot_y)
+        },
+        Some(CursorImage::Themed { manager, ref name }) => {
+            if xcursor_manager::is_alive(manager) {
+                wlr_xcursor_manager_set_cursor_image(manager, name.as_ptr(), cursor)
+            }
+        },
+        None => {}
+    }
+}
+
+/// Upload the images of the cursors again for the output, whose scale,
+/// transform or mode changed, or that was just added to a layout.
+///
+/// Themed cursors load their theme at the scale of the output first.
+pub(crate) unsafe fn output_changed(output: *mut wlr_output) {
+    let cursors = CURSORS.with(|cursors| cursors.borrow().clone());
+    for cursor in cursors {
+        let state = (*cursor).data as *mut CursorState;
+        if state.is_null() || (*state).hidden {
+            continue
+        }
+        if let Some(CursorImage::Themed { manager, .. }) = (*state).image {
+            if xcursor_manager::is_alive(manager) {
+                wlr_xcursor_manager_load(manager, (*output).scale);
+            }
         }
+        apply_image(cursor)
+    }
+}
+
+/// Schedule hiding the cursor after its timeout, if it has one.
+unsafe fn restart_hide_timer(cursor: *mut wlr_cursor) {
+    let state = (*cursor).data as *mut CursorState;
+    let timeout = match (*state).hide_timeout {
+        Some(timeout) => timeout,
+        None => {
+            if !(*state).hide_timer.is_null() {
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                              wl_event_source_timer_update,
+                              (*state).hide_timer,
+                              0);
+            }
+            return
+        }
+    };
+    if (*state).hide_timer.is_null() {
+        if compositor::COMPOSITOR_PTR.is_null() {
+            return
+        }
+        let event_loop = (*compositor::COMPOSITOR_PTR).event_loop;
+        (*state).hide_timer = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                            wl_event_loop_add_timer,
+                                            event_loop,
+                                            hide_timer_notify,
+                                            cursor as *mut libc::c_void);
+    }
+    // NOTE A timeout of 0 would disarm the timer instead.
+    let millis = (timeout.as_secs() * 1000 + (timeout.subsec_nanos() / 1_000_000) as u64).max(1);
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_event_source_timer_update,
+                  (*state).hide_timer,
+                  millis as libc::c_int);
+}
+
+unsafe extern "C" fn hide_timer_notify(data: *mut libc::c_void) -> libc::c_int {
+    hide(data as *mut wlr_cursor);
+    0
+}
+
+/// Show the cursor again if the pointer is used, and restart its hide
+/// timer.
+unsafe fn pointer_activity(cursor: *mut wlr_cursor) {
+    show(cursor);
+    restart_hide_timer(cursor);
+}
+
+/// Hide the cursors that are hidden while typing, as a key was pressed.
+pub(crate) unsafe fn key_pressed() {
+    let cursors = HIDE_WHEN_TYPING.with(|cursors| cursors.borrow().clone());
+    for cursor in cursors {
+        hide(cursor)
     }
 }
 
@@ -689,13 +1138,36 @@ impl Handle {
         Handle { handle: Weak::new(),
                        cursor: ptr::null_mut() }
     }
+
+    /// Get the unique id of the cursor this handle refers to.
+    ///
+    /// See `utils::Handle::id` for details.
+    pub fn id(&self) -> u64 {
+        utils::user_data::id(self.cursor as usize, &self.handle)
+    }
+
+    /// Call the function when the cursor is destroyed.
+    ///
+    /// See `utils::Handle::on_destroy` for details.
+    pub fn on_destroy<F>(&self, callback: F) -> HandleResult<()>
+        where F: FnOnce() + 'static
+    {
+        if utils::user_data::on_destroy(self.cursor as usize, &self.handle, Box::new(callback)) {
+            Ok(())
+        } else {
+            Err(HandleErr::AlreadyDropped)
+        }
+    }
+
     /// Upgrades the cursor handle to a reference to the backing `Cursor`.
     ///
     /// # Unsafety
     /// This function is unsafe, because it creates an unbound `Cursor`
     /// which may live forever..
     /// But a cursor could be destoryed else where.
+    #[track_caller]
     pub(crate) unsafe fn upgrade(&self) -> HandleResult<Box<Cursor>> {
+        let location = Location::caller();
         self.handle.upgrade()
             .ok_or(HandleErr::AlreadyDropped)
         // NOTE
@@ -703,9 +1175,9 @@ impl Handle {
         // pointer to exist!
             .and_then(|check| {
                 if check.get() {
-                    return Err(HandleErr::AlreadyBorrowed)
+                    return Err(utils::already_borrowed::<Cursor>(&check))
                 }
-                check.set(true);
+                utils::borrow_lock(&check, location);
                 Ok(Cursor::from_ptr(self.cursor))
             })
     }
@@ -727,11 +1199,12 @@ impl Handle {
     ///
     /// So don't nest `run` calls or call this in a Cursor callback
     /// and everything will be ok :).
+    #[track_caller]
     pub fn run<F, R>(&self, runner: F) -> HandleResult<R>
         where F: FnOnce(&mut Cursor) -> R
     {
         let mut cursor = unsafe { self.upgrade()? };
-        let cursor_ptr = cursor.data.0;
+        let cursor_ptr = cursor.cursor;
         let res = panic::catch_unwind(panic::AssertUnwindSafe(|| runner(&mut cursor)));
         Box::into_raw(cursor);
         self.handle.upgrade().map(|check| {
@@ -743,7 +1216,7 @@ impl Handle {
                                                    cursor_ptr);
                                           panic!("Lock in incorrect state!");
                                       }
-                                      check.set(false);
+                                      utils::release_lock(&check);
                                   });
         match res {
             Ok(res) => Ok(res),
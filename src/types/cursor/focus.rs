@@ -0,0 +1,180 @@
+//! Focus policies driven by the cursor, for compositors that focus windows
+//! by pointing at them.
+//!
+//! A `FocusFollowsMouse` is told whenever the cursor moves and focuses the
+//! surface under it, optionally only once the cursor rested on it for a
+//! while so that crossing a window on the way to another doesn't focus it:
+//!
+//! ```rust,no_run,ignore
+//! fn on_pointer_motion(&mut self, compositor: compositor::Handle, cursor: cursor::Handle, ..) {
+//!     with_handles!([(cursor: {cursor})] => {
+//!         self.focus_follows_mouse.pointer_moved(compositor, cursor);
+//!     }).unwrap();
+//! }
+//! ```
+//!
+//! When the focus is moved some other way (e.g. with a key binding)
+//! `warp_to_center` moves the cursor along with it.
+
+use std::{panic, ptr, time::Duration};
+
+use libc;
+use wayland_sys::server::{wl_event_source, WAYLAND_SERVER_HANDLE};
+
+use {area::Area, compositor, cursor::Cursor, surface, utils};
+
+/// Callback that finds the surface at the position in the output layout,
+/// e.g. the topmost view there.
+pub type SurfaceAt = fn(compositor_handle: compositor::Handle, lx: f64, ly: f64)
+                        -> Option<surface::Handle>;
+
+/// Callback that gives the surface focus, e.g. keyboard focus of the seat.
+pub type Focus = fn(compositor_handle: compositor::Handle, surface: surface::Handle);
+
+struct FocusState {
+    surface_at: SurfaceAt,
+    focus: Focus,
+    delay: Duration,
+    /// The surface that was given focus last.
+    focused: Option<surface::Handle>,
+    /// The surface under the cursor that gets focus once the delay is over.
+    pending: Option<surface::Handle>,
+    timer: *mut wl_event_source
+}
+
+/// Gives focus to the surface under the cursor.
+pub struct FocusFollowsMouse {
+    state: Box<FocusState>
+}
+
+impl FocusFollowsMouse {
+    /// Focus the surfaces found by `surface_at` with `focus`, as soon as the
+    /// cursor moves onto them.
+    pub fn new(surface_at: SurfaceAt, focus: Focus) -> Self {
+        FocusFollowsMouse { state: Box::new(FocusState { surface_at,
+                                                         focus,
+                                                         delay: Duration::from_secs(0),
+                                                         focused: None,
+                                                         pending: None,
+                                                         timer: ptr::null_mut() }) }
+    }
+
+    /// Only focus a surface once the cursor stayed on it for the duration.
+    pub fn set_delay(&mut self, delay: Duration) {
+        self.state.delay = delay;
+    }
+
+    /// Get how long the cursor has to stay on a surface for it to be focused.
+    pub fn delay(&self) -> Duration {
+        self.state.delay
+    }
+
+    /// Tell the policy which surface has focus, when the focus was moved
+    /// some other way.
+    ///
+    /// This stops the surface from being focused again when the cursor moves
+    /// on it.
+    pub fn set_focused(&mut self, surface: Option<surface::Handle>) {
+        self.state.focused = surface;
+        self.cancel_pending();
+    }
+
+    /// Focus the surface under the cursor if it changed.
+    ///
+    /// Call this whenever the cursor moves.
+    pub fn pointer_moved(&mut self, compositor_handle: compositor::Handle, cursor: &Cursor) {
+        let (lx, ly) = cursor.coords();
+        let surface = match (self.state.surface_at)(compositor_handle.clone(), lx, ly) {
+            Some(surface) => surface,
+            None => {
+                self.cancel_pending();
+                return
+            }
+        };
+        if self.state.focused.as_ref() == Some(&surface) {
+            self.cancel_pending();
+            return
+        }
+        if self.state.delay == Duration::from_secs(0) {
+            self.state.focused = Some(surface.clone());
+            (self.state.focus)(compositor_handle, surface);
+            return
+        }
+        if self.state.pending.as_ref() == Some(&surface) {
+            return
+        }
+        self.state.pending = Some(surface);
+        unsafe { self.arm_timer() }
+    }
+
+    unsafe fn arm_timer(&mut self) {
+        if self.state.timer.is_null() {
+            if compositor::COMPOSITOR_PTR.is_null() {
+                return
+            }
+            let event_loop = (*compositor::COMPOSITOR_PTR).event_loop;
+            let state = &mut *self.state as *mut FocusState;
+            self.state.timer = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                             wl_event_loop_add_timer,
+                                             event_loop,
+                                             focus_timer_notify,
+                                             state as *mut libc::c_void);
+        }
+        let delay = self.state.delay;
+        let millis = (delay.as_secs() * 1000 + (delay.subsec_nanos() / 1_000_000) as u64).max(1);
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_event_source_timer_update,
+                      self.state.timer,
+                      millis as libc::c_int);
+    }
+
+    fn cancel_pending(&mut self) {
+        self.state.pending = None;
+        if !self.state.timer.is_null() {
+            unsafe {
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                              wl_event_source_timer_update,
+                              self.state.timer,
+                              0);
+            }
+        }
+    }
+}
+
+impl Drop for FocusFollowsMouse {
+    fn drop(&mut self) {
+        if !self.state.timer.is_null() {
+            unsafe {
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_event_source_remove, self.state.timer);
+            }
+        }
+    }
+}
+
+unsafe extern "C" fn focus_timer_notify(data: *mut libc::c_void) -> libc::c_int {
+    let state = &mut *(data as *mut FocusState);
+    let (surface, compositor) = match (state.pending.take(), compositor::handle()) {
+        (Some(surface), Some(compositor)) => (surface, compositor),
+        _ => return 0
+    };
+    // NOTE The surface could have been destroyed while waiting.
+    if surface.run(|_| ()).is_err() {
+        return 0
+    }
+    state.focused = Some(surface.clone());
+    let focus = state.focus;
+    let res = panic::catch_unwind(panic::AssertUnwindSafe(|| focus(compositor, surface)));
+    utils::handle_unwind(res);
+    0
+}
+
+/// Move the cursor to the center of the area (e.g. of a newly focused view),
+/// in output layout coordinates.
+///
+/// Returns false if the center is outside of the layout, and the cursor
+/// didn't move.
+pub fn warp_to_center(cursor: &mut Cursor, area: Area) -> bool {
+    let x = area.origin.x as f64 + area.size.width as f64 / 2.0;
+    let y = area.origin.y as f64 + area.size.height as f64 / 2.0;
+    cursor.warp(None, x, y)
+}
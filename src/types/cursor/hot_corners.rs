@@ -0,0 +1,429 @@
+//! Actions for when the cursor is pushed into a corner or against an edge of
+//! the screen, e.g. to show an overview or to slide in a panel.
+//!
+//! Only the outer borders of the output layout count: the edge between two
+//! outputs next to each other is just where the cursor moves over. Every
+//! edge and corner can have an action of its own, which is run once the
+//! cursor stayed there for its delay and pushed past it for its pressure:
+//!
+//! ```rust,no_run,ignore
+//! let mut hot_corners = HotCorners::new();
+//! hot_corners.set_action(ScreenEdge::TopLeft, Some(show_overview));
+//! hot_corners.set_pressure(ScreenEdge::TopLeft, 50.0);
+//!
+//! fn on_pointer_motion(&mut self, compositor: compositor::Handle, cursor: cursor::Handle,
+//!                      event: &pointer::event::Motion) {
+//!     with_handles!([(cursor: {cursor}), (layout: {&self.layout})] => {
+//!         self.hot_corners.pointer_moved(compositor, cursor, layout, event.delta());
+//!     }).unwrap();
+//! }
+//! ```
+//!
+//! An action is run once each time the cursor gets there, it has to leave
+//! the edge before it can run again.
+
+use std::{panic, ptr, time::{Duration, Instant}};
+
+use libc;
+use wayland_sys::server::{wl_event_source, WAYLAND_SERVER_HANDLE};
+
+use {area::Area, compositor, cursor::Cursor, output::{self, layout::Layout}, utils};
+
+/// The edges and corners of the screen.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ScreenEdge {
+    TopLeft,
+    Top,
+    TopRight,
+    Right,
+    BottomRight,
+    Bottom,
+    BottomLeft,
+    Left
+}
+
+/// Callback that is run when the cursor was pushed into the edge of the
+/// output.
+pub type EdgeAction = fn(compositor_handle: compositor::Handle,
+                         output_handle: output::Handle,
+                         edge: ScreenEdge);
+
+#[derive(Clone, Copy)]
+struct Trigger {
+    edge: ScreenEdge,
+    action: EdgeAction,
+    delay: Duration,
+    pressure: f64
+}
+
+/// The edge the cursor is at.
+struct Current {
+    edge: ScreenEdge,
+    output: output::Handle,
+    entered: Instant,
+    /// How far the cursor was pushed past the edge since it got there.
+    pressure: f64,
+    done: bool
+}
+
+struct HotCornersState {
+    triggers: Vec<Trigger>,
+    corner_size: f64,
+    current: Option<Current>,
+    timer: *mut wl_event_source
+}
+
+/// Runs the actions of the edges and corners of the screen the cursor is
+/// pushed into.
+pub struct HotCorners {
+    state: Box<HotCornersState>
+}
+
+impl HotCorners {
+    /// Watch the edges and corners, with no actions yet.
+    pub fn new() -> Self {
+        HotCorners { state: Box::new(HotCornersState { triggers: Vec::new(),
+                                                       corner_size: 8.0,
+                                                       current: None,
+                                                       timer: ptr::null_mut() }) }
+    }
+
+    /// Set the action of the edge, or remove it.
+    ///
+    /// A new action is run as soon as the cursor gets there, until a delay or
+    /// pressure is set for it.
+    pub fn set_action(&mut self, edge: ScreenEdge, action: Option<EdgeAction>) {
+        let index = self.state.triggers.iter().position(|trigger| trigger.edge == edge);
+        match (index, action) {
+            (Some(index), Some(action)) => self.state.triggers[index].action = action,
+            (Some(index), None) => {
+                self.state.triggers.remove(index);
+            },
+            (None, Some(action)) => {
+                self.state.triggers.push(Trigger { edge,
+                                                   action,
+                                                   delay: Duration::from_secs(0),
+                                                   pressure: 0.0 })
+            },
+            (None, None) => {}
+        }
+        self.leave()
+    }
+
+    /// Only run the action of the edge once the cursor stayed there for the
+    /// duration.
+    pub fn set_delay(&mut self, edge: ScreenEdge, delay: Duration) {
+        if let Some(trigger) = self.trigger_mut(edge) {
+            trigger.delay = delay
+        }
+    }
+
+    /// Get how long the cursor has to stay at the edge for its action to run.
+    pub fn delay(&self, edge: ScreenEdge) -> Option<Duration> {
+        self.trigger(edge).map(|trigger| trigger.delay)
+    }
+
+    /// Only run the action of the edge once the cursor was pushed past it by
+    /// the distance, in the units of the pointer motion.
+    ///
+    /// This keeps the action from running when the cursor only gets to the
+    /// edge on its way to e.g. a button there.
+    pub fn set_pressure(&mut self, edge: ScreenEdge, pressure: f64) {
+        if let Some(trigger) = self.trigger_mut(edge) {
+            trigger.pressure = pressure
+        }
+    }
+
+    /// Get how far past the edge the cursor has to be pushed for its action
+    /// to run.
+    pub fn pressure(&self, edge: ScreenEdge) -> Option<f64> {
+        self.trigger(edge).map(|trigger| trigger.pressure)
+    }
+
+    /// Set how far from a corner the cursor can be, along the edges, to be in
+    /// the corner. Defaults to 8.
+    pub fn set_corner_size(&mut self, corner_size: f64) {
+        self.state.corner_size = corner_size
+    }
+
+    /// Run the action of the edge the cursor is at, if it's time.
+    ///
+    /// Call this whenever the cursor moves, with the delta of the motion
+    /// before the cursor was kept in the layout (e.g. `event.delta()`), or
+    /// no delta after absolute motion.
+    pub fn pointer_moved(&mut self,
+                         compositor_handle: compositor::Handle,
+                         cursor: &Cursor,
+                         layout: &mut Layout,
+                         delta: (f64, f64)) {
+        let (lx, ly) = cursor.coords();
+        let (edge, output) = match self.edge_at(layout, lx, ly) {
+            Some(found) => found,
+            None => return self.leave()
+        };
+        let trigger = match self.trigger(edge) {
+            Some(trigger) => *trigger,
+            None => return self.leave()
+        };
+        let entered = match self.state.current {
+            Some(ref current) => current.edge != edge || current.output != output,
+            None => true
+        };
+        if entered {
+            self.leave();
+            self.state.current = Some(Current { edge,
+                                                output,
+                                                entered: utils::now(),
+                                                pressure: 0.0,
+                                                done: false });
+        }
+        let elapsed = {
+            let current = self.state.current.as_mut().unwrap();
+            if current.done {
+                return
+            }
+            current.pressure += outwards(edge, delta);
+            if current.pressure < trigger.pressure {
+                return
+            }
+            utils::now().duration_since(current.entered)
+        };
+        if elapsed >= trigger.delay {
+            run(&mut self.state, compositor_handle, trigger.action)
+        } else {
+            unsafe { self.arm_timer(trigger.delay - elapsed) }
+        }
+    }
+
+    fn trigger(&self, edge: ScreenEdge) -> Option<&Trigger> {
+        self.state.triggers.iter().find(|trigger| trigger.edge == edge)
+    }
+
+    fn trigger_mut(&mut self, edge: ScreenEdge) -> Option<&mut Trigger> {
+        self.state.triggers.iter_mut().find(|trigger| trigger.edge == edge)
+    }
+
+    /// Find the edge of the screen the position is at, and the output it's
+    /// the edge of.
+    fn edge_at(&self,
+               layout: &mut Layout,
+               lx: f64,
+               ly: f64)
+               -> Option<(ScreenEdge, output::Handle)> {
+        let output = layout.output_at(lx, ly)?;
+        let area = output.run(|output| layout.get_box(output)).ok()?;
+        let corner = self.state.corner_size;
+        // NOTE Where another output is on the other side, it's not an edge.
+        let edge = screen_edge(area, lx, ly, corner, |x, y| layout.output_at(x, y).is_none())?;
+        Some((edge, output))
+    }
+
+    unsafe fn arm_timer(&mut self, delay: Duration) {
+        if self.state.timer.is_null() {
+            if compositor::COMPOSITOR_PTR.is_null() {
+                return
+            }
+            let event_loop = (*compositor::COMPOSITOR_PTR).event_loop;
+            let state = &mut *self.state as *mut HotCornersState;
+            self.state.timer = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                             wl_event_loop_add_timer,
+                                             event_loop,
+                                             hot_corner_timer_notify,
+                                             state as *mut libc::c_void);
+        }
+        let millis = (delay.as_secs() * 1000 + (delay.subsec_nanos() / 1_000_000) as u64).max(1);
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_event_source_timer_update,
+                      self.state.timer,
+                      millis as libc::c_int);
+    }
+
+    /// Forget the edge the cursor was at.
+    fn leave(&mut self) {
+        self.state.current = None;
+        if !self.state.timer.is_null() {
+            unsafe {
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                              wl_event_source_timer_update,
+                              self.state.timer,
+                              0);
+            }
+        }
+    }
+}
+
+impl Default for HotCorners {
+    fn default() -> Self {
+        HotCorners::new()
+    }
+}
+
+impl Drop for HotCorners {
+    fn drop(&mut self) {
+        if !self.state.timer.is_null() {
+            unsafe {
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_event_source_remove, self.state.timer);
+            }
+        }
+    }
+}
+
+/// Find the edge of the area the position is at, if it's at one.
+///
+/// `is_border` tells if a position just outside of the area is outside of
+/// the screen too.
+fn screen_edge<F>(area: Area, lx: f64, ly: f64, corner: f64, mut is_border: F) -> Option<ScreenEdge>
+    where F: FnMut(f64, f64) -> bool
+{
+    let (left, top) = (area.origin.x as f64, area.origin.y as f64);
+    let right = left + area.size.width as f64;
+    let bottom = top + area.size.height as f64;
+    let at_left = lx < left + 1.0 && is_border(left - 1.0, ly);
+    let at_right = lx >= right - 1.0 && is_border(right + 1.0, ly);
+    let at_top = ly < top + 1.0 && is_border(lx, top - 1.0);
+    let at_bottom = ly >= bottom - 1.0 && is_border(lx, bottom + 1.0);
+    let (near_left, near_right) = (lx < left + corner, lx >= right - corner);
+    let (near_top, near_bottom) = (ly < top + corner, ly >= bottom - corner);
+    let edge = if (at_top && near_left) || (at_left && near_top) {
+        ScreenEdge::TopLeft
+    } else if (at_top && near_right) || (at_right && near_top) {
+        ScreenEdge::TopRight
+    } else if (at_bottom && near_left) || (at_left && near_bottom) {
+        ScreenEdge::BottomLeft
+    } else if (at_bottom && near_right) || (at_right && near_bottom) {
+        ScreenEdge::BottomRight
+    } else if at_top {
+        ScreenEdge::Top
+    } else if at_bottom {
+        ScreenEdge::Bottom
+    } else if at_left {
+        ScreenEdge::Left
+    } else if at_right {
+        ScreenEdge::Right
+    } else {
+        return None
+    };
+    Some(edge)
+}
+
+/// Get how far the motion pushed past the edge.
+fn outwards(edge: ScreenEdge, (dx, dy): (f64, f64)) -> f64 {
+    let (left, right, up, down) = (-dx.min(0.0), dx.max(0.0), -dy.min(0.0), dy.max(0.0));
+    match edge {
+        ScreenEdge::TopLeft => up + left,
+        ScreenEdge::Top => up,
+        ScreenEdge::TopRight => up + right,
+        ScreenEdge::Right => right,
+        ScreenEdge::BottomRight => down + right,
+        ScreenEdge::Bottom => down,
+        ScreenEdge::BottomLeft => down + left,
+        ScreenEdge::Left => left
+    }
+}
+
+/// Run the action of the edge the cursor is at.
+fn run(state: &mut HotCornersState, compositor_handle: compositor::Handle, action: EdgeAction) {
+    let (edge, output) = match state.current {
+        Some(ref mut current) if !current.done => {
+            current.done = true;
+            (current.edge, current.output.clone())
+        },
+        _ => return
+    };
+    // NOTE The output could have been destroyed while waiting.
+    if output.run(|_| ()).is_err() {
+        return
+    }
+    let run_action = || action(compositor_handle, output, edge);
+    let res = panic::catch_unwind(panic::AssertUnwindSafe(run_action));
+    utils::handle_unwind(res);
+}
+
+unsafe extern "C" fn hot_corner_timer_notify(data: *mut libc::c_void) -> libc::c_int {
+    let state = &mut *(data as *mut HotCornersState);
+    let compositor = match compositor::handle() {
+        Some(compositor) => compositor,
+        None => return 0
+    };
+    let action = match state.current {
+        Some(ref current) => {
+            state.triggers
+                 .iter()
+                 .find(|trigger| trigger.edge == current.edge)
+                 .map(|trigger| trigger.action)
+        },
+        None => None
+    };
+    if let Some(action) = action {
+        run(state, compositor, action)
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use area::{Origin, Size};
+
+    fn area() -> Area {
+        Area::new(Origin::new(0, 0), Size::new(100, 50))
+    }
+
+    fn action(_: compositor::Handle, _: output::Handle, _: ScreenEdge) {}
+
+    #[test]
+    fn finds_edges_and_corners() {
+        let edge = |lx, ly| screen_edge(area(), lx, ly, 8.0, |_, _| true);
+        assert_eq!(edge(0.0, 0.0), Some(ScreenEdge::TopLeft));
+        assert_eq!(edge(7.0, 0.0), Some(ScreenEdge::TopLeft));
+        assert_eq!(edge(0.0, 7.0), Some(ScreenEdge::TopLeft));
+        assert_eq!(edge(50.0, 0.0), Some(ScreenEdge::Top));
+        assert_eq!(edge(99.5, 0.0), Some(ScreenEdge::TopRight));
+        assert_eq!(edge(99.5, 25.0), Some(ScreenEdge::Right));
+        assert_eq!(edge(99.5, 49.5), Some(ScreenEdge::BottomRight));
+        assert_eq!(edge(50.0, 49.5), Some(ScreenEdge::Bottom));
+        assert_eq!(edge(0.0, 49.5), Some(ScreenEdge::BottomLeft));
+        assert_eq!(edge(0.0, 25.0), Some(ScreenEdge::Left));
+        assert_eq!(edge(50.0, 25.0), None);
+        assert_eq!(edge(1.0, 25.0), None);
+    }
+
+    #[test]
+    fn edges_next_to_other_outputs_are_not_edges() {
+        // NOTE Another output to the right.
+        let edge = |lx, ly| screen_edge(area(), lx, ly, 8.0, |x, _| x < 100.0);
+        assert_eq!(edge(99.5, 25.0), None);
+        assert_eq!(edge(99.5, 0.0), Some(ScreenEdge::TopRight));
+        assert_eq!(edge(0.0, 25.0), Some(ScreenEdge::Left));
+    }
+
+    #[test]
+    fn pressure_is_the_motion_outwards() {
+        assert_eq!(outwards(ScreenEdge::Top, (5.0, -3.0)), 3.0);
+        assert_eq!(outwards(ScreenEdge::Top, (5.0, 3.0)), 0.0);
+        assert_eq!(outwards(ScreenEdge::Left, (-2.0, 0.0)), 2.0);
+        assert_eq!(outwards(ScreenEdge::Right, (-2.0, 0.0)), 0.0);
+        assert_eq!(outwards(ScreenEdge::TopLeft, (-2.0, -3.0)), 5.0);
+        assert_eq!(outwards(ScreenEdge::BottomRight, (2.0, 3.0)), 5.0);
+        assert_eq!(outwards(ScreenEdge::BottomLeft, (2.0, 3.0)), 3.0);
+    }
+
+    #[test]
+    fn settings_need_an_action() {
+        let mut hot_corners = HotCorners::new();
+        hot_corners.set_delay(ScreenEdge::Top, Duration::from_secs(1));
+        assert_eq!(hot_corners.delay(ScreenEdge::Top), None);
+        hot_corners.set_action(ScreenEdge::Top, Some(action));
+        assert_eq!(hot_corners.delay(ScreenEdge::Top), Some(Duration::from_secs(0)));
+        assert_eq!(hot_corners.pressure(ScreenEdge::Top), Some(0.0));
+        hot_corners.set_delay(ScreenEdge::Top, Duration::from_secs(1));
+        hot_corners.set_pressure(ScreenEdge::Top, 50.0);
+        // NOTE Replacing the action keeps the settings.
+        hot_corners.set_action(ScreenEdge::Top, Some(action));
+        assert_eq!(hot_corners.delay(ScreenEdge::Top), Some(Duration::from_secs(1)));
+        assert_eq!(hot_corners.pressure(ScreenEdge::Top), Some(50.0));
+        assert_eq!(hot_corners.pressure(ScreenEdge::Left), None);
+        hot_corners.set_action(ScreenEdge::Top, None);
+        assert_eq!(hot_corners.delay(ScreenEdge::Top), None);
+    }
+}
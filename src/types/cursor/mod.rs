@@ -1,5 +1,9 @@
 #[cfg(feature = "unstable")]
 mod cursor;
+#[cfg(feature = "unstable")]
+pub mod focus;
+#[cfg(feature = "unstable")]
+pub mod hot_corners;
 pub mod xcursor;
 #[cfg(feature = "unstable")]
 pub(crate) mod xcursor_manager;
@@ -14,7 +14,7 @@ use libc::c_int;
 use wlroots_sys::{wlr_xcursor, wlr_xcursor_frame, wlr_xcursor_image, wlr_xcursor_theme,
                   wlr_xcursor_theme_destroy, wlr_xcursor_theme_get_cursor, wlr_xcursor_theme_load};
 
-use utils::{c_to_rust_string, safe_as_cstring};
+use {error::Error, utils::{c_to_rust_string, safe_as_cstring}};
 #[cfg(feature = "unstable")]
 pub use super::xcursor_manager::*;
 
@@ -78,16 +78,17 @@ impl Theme {
     /// to find inherited themes and searches along the path for those themes as well.
     ///
     /// If no name is given, defaults to "default".
-    /// If no theme can be found `None` is returned.
-    pub fn load_theme<T: Into<Option<String>>>(name: T, size: c_int) -> Option<Self> {
+    /// If no theme can be found `Error::CursorTheme` is returned.
+    pub fn load_theme<T: Into<Option<String>>>(name: T, size: c_int) -> Result<Self, Error> {
         unsafe {
-            let name_str = name.into().map(safe_as_cstring);
-            let name_ptr = name_str.map(|s| s.as_ptr()).unwrap_or(ptr::null_mut());
+            let name = name.into();
+            let name_str = name.clone().map(safe_as_cstring);
+            let name_ptr = name_str.as_ref().map(|s| s.as_ptr()).unwrap_or(ptr::null_mut());
             let theme = wlr_xcursor_theme_load(name_ptr, size);
             if theme.is_null() {
-                None
+                Err(Error::CursorTheme(name))
             } else {
-                Some(Theme { theme })
+                Ok(Theme { theme })
             }
         }
     }
@@ -1,12 +1,18 @@
-use std::{marker::PhantomData, ptr};
+use std::{cell::RefCell, marker::PhantomData, ptr};
 
 use wlroots_sys::{wlr_xcursor_manager, wlr_xcursor_manager_create, wlr_xcursor_manager_destroy,
                   wlr_xcursor_manager_get_xcursor, wlr_xcursor_manager_load,
-                  wlr_xcursor_manager_set_cursor_image, wlr_xcursor_manager_theme};
+                  wlr_xcursor_manager_theme};
 
 use {cursor::{Cursor, xcursor::{self, XCursor}},
+     error::Error,
      utils::{c_to_rust_string, safe_as_cstring}};
 
+thread_local! {
+    /// The managers that haven't been destroyed yet.
+    static MANAGERS: RefCell<Vec<*mut wlr_xcursor_manager>> = RefCell::new(Vec::new());
+}
+
 /// An `xcursor::Theme` at a particular scale factor of the base size.
 #[derive(Debug)]
 pub struct ManagerTheme<'manager> {
@@ -41,15 +47,18 @@ impl<'manager> ManagerTheme<'manager> {
 
 impl Manager {
     /// Create a new `xcursor::Manager`.
-    pub fn create<T: Into<Option<String>>>(name: T, size: u32) -> Option<Self> {
+    ///
+    /// The theme itself is only loaded at a scale with `load`.
+    pub fn create<T: Into<Option<String>>>(name: T, size: u32) -> Result<Self, Error> {
         unsafe {
             let name_str = name.into().map(safe_as_cstring);
-            let name_ptr = name_str.map(|s| s.as_ptr()).unwrap_or(ptr::null_mut());
+            let name_ptr = name_str.as_ref().map(|s| s.as_ptr()).unwrap_or(ptr::null_mut());
             let manager = wlr_xcursor_manager_create(name_ptr, size);
             if manager.is_null() {
-                None
+                Err(Error::Create("wlr_xcursor_manager"))
             } else {
-                Some(Manager { manager: manager })
+                MANAGERS.with(|managers| managers.borrow_mut().push(manager));
+                Ok(Manager { manager: manager })
             }
         }
     }
@@ -100,12 +109,12 @@ impl Manager {
 
     /// Ensures an xcursor theme at the given scale factor is loaded in the manager.
     ///
-    /// Returns false if the scaled theme was successfully loaded and true otherwise
-    pub fn load(&self, scale: f32) -> bool {
+    /// Returns `Error::CursorTheme` if the theme couldn't be loaded at that scale.
+    pub fn load(&self, scale: f32) -> Result<(), Error> {
         unsafe {
             match wlr_xcursor_manager_load(self.manager, scale) {
-                0 => false,
-                _ => true
+                0 => Ok(()),
+                _ => Err(Error::CursorTheme(c_to_rust_string((*self.manager).name)))
             }
         }
     }
@@ -114,16 +123,24 @@ impl Manager {
     /// factors. The `Cursor` will take over from this point and ensure the correct
     /// cursor is used on each output, assuming an `OutputLayout` is attached to
     /// it.
+    ///
+    /// When the scale of an output changes later on, the theme is loaded at
+    /// the new scale and the image is uploaded again on its own.
     pub fn set_cursor_image(&mut self, name: String, cursor: &Cursor) {
         let name_str = safe_as_cstring(name);
-        unsafe {
-            wlr_xcursor_manager_set_cursor_image(self.manager, name_str.as_ptr(), cursor.as_ptr());
-        }
+        unsafe { cursor.set_themed_image(self.manager, name_str) }
     }
 }
 
 impl Drop for Manager {
     fn drop(&mut self) {
+        let manager = self.manager;
+        MANAGERS.with(|managers| managers.borrow_mut().retain(|&other| other != manager));
         unsafe { wlr_xcursor_manager_destroy(self.manager) }
     }
 }
+
+/// Determine if the manager wasn't destroyed yet.
+pub(crate) fn is_alive(manager: *mut wlr_xcursor_manager) -> bool {
+    MANAGERS.with(|managers| managers.borrow().contains(&manager))
+}
@@ -0,0 +1,443 @@
+//! Turning pointer and touch input into the gestures compositors usually act
+//! on: double and triple clicks, drags once the pointer moved far enough, and
+//! swipes in from the border of an output.
+//!
+//! A `GestureDetector` is told about the input as it comes in, together with
+//! where it happened in the output layout (the position of the cursor, or of
+//! the touch point), and says when a gesture was made:
+//!
+//! ```rust,no_run,ignore
+//! fn on_button(&mut self, compositor: compositor::Handle, pointer: pointer::Handle,
+//!              event: &pointer::event::Button) {
+//!     match self.gestures.pointer_button(event, cursor.coords()) {
+//!         Some(Gesture::DoubleClick { .. }) => self.maximize_view_under_cursor(),
+//!         Some(Gesture::DragStart { .. }) => self.begin_move(),
+//!         _ => {}
+//!     }
+//! }
+//! ```
+//!
+//! Nothing is sent to clients, the input still has to be passed on to the
+//! seat as usual.
+
+use std::time::Duration;
+
+use wlroots_sys::wlr_button_state;
+
+use {area::Area,
+     input::{pointer, touch},
+     utils::edges::Edges};
+
+/// The timings and distances the gestures are detected with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timings {
+    /// How long after a click another one still counts as the next click of
+    /// a double or triple click.
+    pub multi_click: Duration,
+    /// How far apart, in layout coordinates, the clicks of a double or triple
+    /// click may be.
+    pub multi_click_distance: f64,
+    /// How far the pointer or a touch point has to move while pressed before
+    /// it's a drag.
+    pub drag_threshold: f64,
+    /// How close to the border of an output a touch point has to go down to
+    /// start an edge swipe.
+    pub edge_width: f64,
+    /// How far a touch point has to move away from the border for an edge
+    /// swipe.
+    pub edge_swipe_distance: f64
+}
+
+impl Default for Timings {
+    fn default() -> Self {
+        Timings { multi_click: Duration::from_millis(400),
+                  multi_click_distance: 4.0,
+                  drag_threshold: 8.0,
+                  edge_width: 16.0,
+                  edge_swipe_distance: 64.0 }
+    }
+}
+
+/// A gesture made with the pointer or a touch point.
+///
+/// Positions are in layout coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Gesture {
+    /// A button was pressed, and it wasn't the second or third click of a
+    /// double or triple click.
+    Click { button: u32, x: f64, y: f64 },
+    /// A button was pressed the second time in a row.
+    DoubleClick { button: u32, x: f64, y: f64 },
+    /// A button was pressed the third time in a row. Clicking again counts
+    /// as another single click.
+    TripleClick { button: u32, x: f64, y: f64 },
+    /// The pointer moved far enough while the button was held down. The
+    /// position is where the button was pressed.
+    DragStart { button: u32, x: f64, y: f64 },
+    /// The button of a drag was released.
+    DragEnd { button: u32, x: f64, y: f64 },
+    /// A touch point moved far enough. The position is where it went down.
+    TouchDragStart { touch_id: i32, x: f64, y: f64 },
+    /// A touch point that was dragging went up.
+    TouchDragEnd { touch_id: i32, x: f64, y: f64 },
+    /// A touch point went down at an edge of an output and moved away from
+    /// it, e.g. to bring up a panel.
+    EdgeSwipe { touch_id: i32, edge: Edges }
+}
+
+/// The button that is held down.
+#[derive(Debug, Clone, Copy)]
+struct Press {
+    button: u32,
+    start: (f64, f64),
+    dragging: bool
+}
+
+/// The last click, to count the clicks of double and triple clicks.
+#[derive(Debug, Clone, Copy)]
+struct LastClick {
+    button: u32,
+    time: Duration,
+    position: (f64, f64),
+    count: u32
+}
+
+/// A touch point that is down.
+#[derive(Debug, Clone, Copy)]
+struct TouchPoint {
+    touch_id: i32,
+    start: (f64, f64),
+    position: (f64, f64),
+    /// The edge of the output the touch point went down at, if it did.
+    edge: Edges,
+    swiped: bool,
+    dragging: bool
+}
+
+/// Detects gestures in the pointer and touch input it's given.
+#[derive(Debug, Clone)]
+pub struct GestureDetector {
+    timings: Timings,
+    press: Option<Press>,
+    last_click: Option<LastClick>,
+    touch_points: Vec<TouchPoint>
+}
+
+impl Default for GestureDetector {
+    fn default() -> Self {
+        GestureDetector::new(Timings::default())
+    }
+}
+
+impl GestureDetector {
+    /// Make a detector that uses the timings.
+    pub fn new(timings: Timings) -> Self {
+        GestureDetector { timings,
+                          press: None,
+                          last_click: None,
+                          touch_points: Vec::new() }
+    }
+
+    /// Get the timings gestures are detected with.
+    pub fn timings(&self) -> Timings {
+        self.timings
+    }
+
+    /// Change the timings gestures are detected with.
+    pub fn set_timings(&mut self, timings: Timings) {
+        self.timings = timings
+    }
+
+    /// Tell the detector a button was pressed or released at the position of
+    /// the cursor.
+    ///
+    /// Clicks are reported as the button is pressed, drags end as it's
+    /// released.
+    pub fn pointer_button(&mut self,
+                          event: &pointer::event::Button,
+                          position: (f64, f64))
+                          -> Option<Gesture> {
+        let pressed = event.state() == wlr_button_state::WLR_BUTTON_PRESSED;
+        self.button(event.button(), pressed, event.time(), position)
+    }
+
+    fn button(&mut self,
+              button: u32,
+              pressed: bool,
+              time: Duration,
+              position: (f64, f64))
+              -> Option<Gesture> {
+        let (x, y) = position;
+        if !pressed {
+            return match self.press.take() {
+                       Some(press) if press.button == button && press.dragging => {
+                           Some(Gesture::DragEnd { button, x, y })
+                       },
+                       Some(press) if press.button != button => {
+                           self.press = Some(press);
+                           None
+                       },
+                       _ => None
+                   }
+        }
+        if self.press.is_some() {
+            // NOTE Only the first button pressed is tracked.
+            return None
+        }
+        self.press = Some(Press { button,
+                                  start: position,
+                                  dragging: false });
+        let timings = self.timings;
+        let count = match self.last_click {
+            Some(last) if last.button == button
+                          && last.count < 3
+                          && time.checked_sub(last.time)
+                                 .map_or(true, |elapsed| elapsed <= timings.multi_click)
+                          && distance(last.position, position) <= timings.multi_click_distance => {
+                last.count + 1
+            },
+            _ => 1
+        };
+        self.last_click = Some(LastClick { button, time, position, count });
+        Some(match count {
+                 1 => Gesture::Click { button, x, y },
+                 2 => Gesture::DoubleClick { button, x, y },
+                 _ => Gesture::TripleClick { button, x, y }
+             })
+    }
+
+    /// Tell the detector the cursor moved to the position.
+    pub fn pointer_motion(&mut self, position: (f64, f64)) -> Option<Gesture> {
+        let threshold = self.timings.drag_threshold;
+        let press = self.press.as_mut()?;
+        if press.dragging || distance(press.start, position) < threshold {
+            return None
+        }
+        press.dragging = true;
+        let (button, (x, y)) = (press.button, press.start);
+        // NOTE A drag doesn't count towards a double click.
+        self.last_click = None;
+        Some(Gesture::DragStart { button, x, y })
+    }
+
+    /// Tell the detector a touch point went down at the position, on the
+    /// output that takes up the area of the layout.
+    pub fn touch_down(&mut self, event: &touch::event::Down, position: (f64, f64), output: Area) {
+        self.touch_down_at(event.touch_id(), position, output)
+    }
+
+    fn touch_down_at(&mut self, touch_id: i32, position: (f64, f64), output: Area) {
+        let edge = self.edge_at(position, output);
+        self.touch_points.retain(|point| point.touch_id != touch_id);
+        self.touch_points.push(TouchPoint { touch_id,
+                                            start: position,
+                                            position,
+                                            edge,
+                                            swiped: false,
+                                            dragging: false })
+    }
+
+    /// Tell the detector a touch point moved to the position.
+    pub fn touch_motion(&mut self,
+                        event: &touch::event::Motion,
+                        position: (f64, f64))
+                        -> Option<Gesture> {
+        self.touch_moved(event.touch_id(), position)
+    }
+
+    fn touch_moved(&mut self, touch_id: i32, position: (f64, f64)) -> Option<Gesture> {
+        let timings = self.timings;
+        let point = self.touch_points.iter_mut().find(|point| point.touch_id == touch_id)?;
+        point.position = position;
+        if point.dragging || point.swiped {
+            return None
+        }
+        // NOTE Touch points that go down at an edge can only swipe, not drag.
+        if !point.edge.is_empty() {
+            if swiped(point, timings.edge_swipe_distance) {
+                point.swiped = true;
+                return Some(Gesture::EdgeSwipe { touch_id, edge: point.edge })
+            }
+            return None
+        }
+        if distance(point.start, position) < timings.drag_threshold {
+            return None
+        }
+        point.dragging = true;
+        let (x, y) = point.start;
+        Some(Gesture::TouchDragStart { touch_id, x, y })
+    }
+
+    /// Tell the detector a touch point went up.
+    pub fn touch_up(&mut self, event: &touch::event::Up) -> Option<Gesture> {
+        self.touch_lifted(event.touch_id())
+    }
+
+    fn touch_lifted(&mut self, touch_id: i32) -> Option<Gesture> {
+        let index = self.touch_points.iter().position(|point| point.touch_id == touch_id)?;
+        let point = self.touch_points.remove(index);
+        if !point.dragging {
+            return None
+        }
+        let (x, y) = point.position;
+        Some(Gesture::TouchDragEnd { touch_id, x, y })
+    }
+
+    /// Forget the touch points, because the touch sequence was cancelled.
+    pub fn touch_cancel(&mut self) {
+        self.touch_points.clear()
+    }
+
+    /// Get the edge of the output the position is at, if it is.
+    fn edge_at(&self, (x, y): (f64, f64), output: Area) -> Edges {
+        let width = self.timings.edge_width;
+        let left = output.origin.x as f64;
+        let top = output.origin.y as f64;
+        let right = left + output.size.width as f64;
+        let bottom = top + output.size.height as f64;
+        if x < left + width {
+            Edges::WLR_EDGE_LEFT
+        } else if x >= right - width {
+            Edges::WLR_EDGE_RIGHT
+        } else if y < top + width {
+            Edges::WLR_EDGE_TOP
+        } else if y >= bottom - width {
+            Edges::WLR_EDGE_BOTTOM
+        } else {
+            Edges::empty()
+        }
+    }
+}
+
+fn distance((x1, y1): (f64, f64), (x2, y2): (f64, f64)) -> f64 {
+    ((x2 - x1).powi(2) + (y2 - y1).powi(2)).sqrt()
+}
+
+/// Determine if the touch point moved far enough away from the edge it went
+/// down at.
+fn swiped(point: &TouchPoint, swipe_distance: f64) -> bool {
+    let (dx, dy) = (point.position.0 - point.start.0, point.position.1 - point.start.1);
+    let inwards = if point.edge == Edges::WLR_EDGE_LEFT {
+        dx
+    } else if point.edge == Edges::WLR_EDGE_RIGHT {
+        -dx
+    } else if point.edge == Edges::WLR_EDGE_TOP {
+        dy
+    } else {
+        -dy
+    };
+    inwards >= swipe_distance
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use area::{Origin, Size};
+
+    const LEFT: u32 = 0x110;
+    const RIGHT: u32 = 0x111;
+
+    fn millis(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn click(detector: &mut GestureDetector,
+             button: u32,
+             time: u64,
+             position: (f64, f64))
+             -> Option<Gesture> {
+        let gesture = detector.button(button, true, millis(time), position);
+        detector.button(button, false, millis(time + 10), position);
+        gesture
+    }
+
+    fn output() -> Area {
+        Area::new(Origin::new(0, 0), Size::new(1000, 500))
+    }
+
+    #[test]
+    fn counts_clicks() {
+        let mut detector = GestureDetector::default();
+        let at = (10.0, 10.0);
+        assert_eq!(click(&mut detector, LEFT, 0, at),
+                   Some(Gesture::Click { button: LEFT, x: 10.0, y: 10.0 }));
+        assert_eq!(click(&mut detector, LEFT, 100, (12.0, 10.0)),
+                   Some(Gesture::DoubleClick { button: LEFT, x: 12.0, y: 10.0 }));
+        assert_eq!(click(&mut detector, LEFT, 200, at),
+                   Some(Gesture::TripleClick { button: LEFT, x: 10.0, y: 10.0 }));
+        // NOTE A fourth click starts over.
+        assert_eq!(click(&mut detector, LEFT, 300, at),
+                   Some(Gesture::Click { button: LEFT, x: 10.0, y: 10.0 }));
+    }
+
+    #[test]
+    fn clicks_too_late_far_or_of_another_button_are_single() {
+        let mut detector = GestureDetector::default();
+        let at = (10.0, 10.0);
+        click(&mut detector, LEFT, 0, at);
+        assert_eq!(click(&mut detector, LEFT, 1000, at),
+                   Some(Gesture::Click { button: LEFT, x: 10.0, y: 10.0 }));
+        assert_eq!(click(&mut detector, LEFT, 1100, (20.0, 10.0)),
+                   Some(Gesture::Click { button: LEFT, x: 20.0, y: 10.0 }));
+        assert_eq!(click(&mut detector, RIGHT, 1200, (20.0, 10.0)),
+                   Some(Gesture::Click { button: RIGHT, x: 20.0, y: 10.0 }));
+    }
+
+    #[test]
+    fn drags_once_the_pointer_moved_far_enough() {
+        let mut detector = GestureDetector::default();
+        detector.button(LEFT, true, millis(0), (10.0, 10.0));
+        assert_eq!(detector.pointer_motion((14.0, 10.0)), None);
+        assert_eq!(detector.pointer_motion((20.0, 10.0)),
+                   Some(Gesture::DragStart { button: LEFT, x: 10.0, y: 10.0 }));
+        assert_eq!(detector.pointer_motion((30.0, 10.0)), None);
+        // NOTE Other buttons are ignored while one is held.
+        assert_eq!(detector.button(RIGHT, true, millis(10), (30.0, 10.0)), None);
+        assert_eq!(detector.button(RIGHT, false, millis(20), (30.0, 10.0)), None);
+        assert_eq!(detector.button(LEFT, false, millis(30), (30.0, 10.0)),
+                   Some(Gesture::DragEnd { button: LEFT, x: 30.0, y: 10.0 }));
+        // NOTE A drag doesn't count towards a double click.
+        assert_eq!(click(&mut detector, LEFT, 40, (10.0, 10.0)),
+                   Some(Gesture::Click { button: LEFT, x: 10.0, y: 10.0 }));
+        assert_eq!(detector.pointer_motion((50.0, 10.0)), None);
+    }
+
+    #[test]
+    fn touch_drags() {
+        let mut detector = GestureDetector::default();
+        detector.touch_down_at(1, (500.0, 250.0), output());
+        assert_eq!(detector.touch_moved(1, (504.0, 250.0)), None);
+        assert_eq!(detector.touch_moved(2, (600.0, 250.0)), None);
+        assert_eq!(detector.touch_moved(1, (520.0, 250.0)),
+                   Some(Gesture::TouchDragStart { touch_id: 1, x: 500.0, y: 250.0 }));
+        assert_eq!(detector.touch_moved(1, (540.0, 250.0)), None);
+        assert_eq!(detector.touch_lifted(1),
+                   Some(Gesture::TouchDragEnd { touch_id: 1, x: 540.0, y: 250.0 }));
+        assert_eq!(detector.touch_lifted(1), None);
+        detector.touch_down_at(1, (500.0, 250.0), output());
+        assert_eq!(detector.touch_lifted(1), None);
+    }
+
+    #[test]
+    fn edge_swipes() {
+        let mut detector = GestureDetector::default();
+        detector.touch_down_at(1, (995.0, 250.0), output());
+        // NOTE Moving along the edge is neither a swipe nor a drag.
+        assert_eq!(detector.touch_moved(1, (995.0, 350.0)), None);
+        assert_eq!(detector.touch_moved(1, (900.0, 350.0)),
+                   Some(Gesture::EdgeSwipe { touch_id: 1, edge: Edges::WLR_EDGE_RIGHT }));
+        assert_eq!(detector.touch_moved(1, (800.0, 350.0)), None);
+        assert_eq!(detector.touch_lifted(1), None);
+        detector.touch_down_at(2, (500.0, 5.0), output());
+        assert_eq!(detector.touch_moved(2, (500.0, 100.0)),
+                   Some(Gesture::EdgeSwipe { touch_id: 2, edge: Edges::WLR_EDGE_TOP }));
+    }
+
+    #[test]
+    fn cancelling_forgets_the_touch_points() {
+        let mut detector = GestureDetector::default();
+        detector.touch_down_at(1, (500.0, 250.0), output());
+        detector.touch_cancel();
+        assert_eq!(detector.touch_moved(1, (600.0, 250.0)), None);
+    }
+}
@@ -0,0 +1,425 @@
+//! Injecting input events as if they came from real devices, e.g. for remote
+//! desktops (like wayvnc) or automated UI testing.
+//!
+//! An `inject::Injector` adds a keyboard and a pointer to the headless
+//! backend, which are announced through the input manager like any other
+//! device and whose events go through the same handlers. Keep that in mind
+//! when setting them up, e.g. the keyboard needs a keymap before it can send
+//! keys.
+//!
+//! When the compositor doesn't run on the headless backend, a headless
+//! backend is added to the multi backend just for these devices.
+//!
+//! A touch device can be added too with `Injector::add_touch`, for injecting
+//! touch points.
+
+use std::{cell::RefCell, fmt, ptr};
+
+use libc::{c_double, c_void};
+use wayland_sys::server::{signal::{wl_signal_add, wl_signal_emit}, WAYLAND_SERVER_HANDLE};
+use wlroots_sys::{wlr_axis_orientation, wlr_axis_source, wlr_backend, wlr_backend_destroy,
+                  wlr_backend_start, wlr_button_state, wlr_event_keyboard_key,
+                  wlr_event_pointer_axis, wlr_event_pointer_button, wlr_event_pointer_motion,
+                  wlr_event_pointer_motion_absolute, wlr_event_touch_down,
+                  wlr_event_touch_motion, wlr_event_touch_up, wlr_headless_add_input_device,
+                  wlr_headless_backend_create, wlr_input_device, wlr_input_device_type,
+                  wlr_input_device_type::{WLR_INPUT_DEVICE_KEYBOARD, WLR_INPUT_DEVICE_POINTER,
+                                          WLR_INPUT_DEVICE_TOUCH},
+                  wlr_key_state, wlr_keyboard_notify_key, wlr_multi_backend_remove};
+
+use {backend::Backend,
+     compositor,
+     cursor::Cursor,
+     input,
+     output::{layout::Layout, Output},
+     utils::{current_time, ToMs}};
+
+/// A headless backend to create virtual input devices on.
+#[derive(Debug)]
+pub(crate) struct VirtualBackend {
+    /// The multi backend the headless backend was added to, if it had to be
+    /// created.
+    multi: *mut wlr_backend,
+    headless: *mut wlr_backend
+}
+
+impl VirtualBackend {
+    /// Use the headless backend of the compositor, or add one to its multi
+    /// backend.
+    ///
+    /// Returns `None` for any other backend.
+    pub(crate) fn new(backend: &Backend) -> Option<Self> {
+        unsafe {
+            match *backend {
+                Backend::Headless(ref headless) => {
+                    Some(VirtualBackend { multi: ptr::null_mut(), headless: headless.as_ptr() })
+                },
+                Backend::Multi(ref multi) => {
+                    if compositor::COMPOSITOR_PTR.is_null() {
+                        return None
+                    }
+                    let display = (*compositor::COMPOSITOR_PTR).display;
+                    let headless = wlr_headless_backend_create(display as *mut _, None);
+                    if headless.is_null() {
+                        return None
+                    }
+                    if !multi.add_backend(headless) || !wlr_backend_start(headless) {
+                        wlr_backend_destroy(headless);
+                        return None
+                    }
+                    Some(VirtualBackend { multi: multi.backend, headless })
+                },
+                _ => None
+            }
+        }
+    }
+
+    /// Add a device, which is announced through the input manager right away.
+    pub(crate) unsafe fn add_device(&mut self, kind: wlr_input_device_type)
+                                    -> *mut wlr_input_device {
+        wlr_headless_add_input_device(self.headless, kind)
+    }
+}
+
+impl Drop for VirtualBackend {
+    fn drop(&mut self) {
+        // NOTE The devices are destroyed with the backend they belong to, so
+        // only the backend created for them is torn down here. If the
+        // compositor is gone the multi backend already destroyed it.
+        if self.multi.is_null() || unsafe { compositor::COMPOSITOR_PTR.is_null() } {
+            return
+        }
+        unsafe {
+            wlr_multi_backend_remove(self.multi, self.headless);
+            wlr_backend_destroy(self.headless);
+        }
+    }
+}
+
+thread_local! {
+    /// The devices of every injector that are still around.
+    static INJECTED: RefCell<Vec<*mut wlr_input_device>> = RefCell::new(Vec::new());
+}
+
+/// Determine if the device belongs to an `Injector`.
+pub(crate) fn is_injected(device: *mut wlr_input_device) -> bool {
+    INJECTED.with(|injected| injected.borrow().contains(&device))
+}
+
+/// Add the device created for an injector to the injected ones.
+unsafe fn inject(device: *mut wlr_input_device) -> Option<Box<InjectedDevice>> {
+    let tracked = InjectedDevice::track(device)?;
+    INJECTED.with(|injected| injected.borrow_mut().push(device));
+    Some(tracked)
+}
+
+/// Forget the device of an injector once it's gone.
+fn uninject(device: *mut wlr_input_device) {
+    INJECTED.with(|injected| injected.borrow_mut().retain(|other| *other != device));
+}
+
+wayland_listener!(pub(crate) InjectedDevice, *mut wlr_input_device, [
+    destroy_listener => destroy_notify: |this: &mut InjectedDevice, _data: *mut c_void,|
+    unsafe {
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_list_remove,
+                      &mut (*this.destroy_listener()).link as *mut _ as _);
+        uninject(this.data);
+        this.data = ptr::null_mut();
+    };
+]);
+
+impl InjectedDevice {
+    /// Keep track of the device, which is forgotten once it's destroyed
+    /// (e.g. along with the headless backend of the compositor).
+    pub(crate) unsafe fn track(device: *mut wlr_input_device) -> Option<Box<InjectedDevice>> {
+        if device.is_null() {
+            return None
+        }
+        let mut tracked = InjectedDevice::new(device);
+        wl_signal_add(&mut (*device).events.destroy as *mut _ as _,
+                      tracked.destroy_listener() as _);
+        Some(tracked)
+    }
+
+    /// Get the device, unless it was destroyed.
+    pub(crate) fn device(&self) -> Option<*mut wlr_input_device> {
+        if self.data.is_null() { None } else { Some(self.data) }
+    }
+}
+
+impl fmt::Debug for InjectedDevice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("InjectedDevice").field("device", &self.data).finish()
+    }
+}
+
+impl Drop for InjectedDevice {
+    fn drop(&mut self) {
+        if self.data.is_null() {
+            return
+        }
+        uninject(self.data);
+        unsafe {
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_list_remove,
+                          &mut (*self.destroy_listener()).link as *mut _ as _);
+        }
+    }
+}
+
+/// A keyboard and a pointer that events can be injected into.
+///
+/// Events for devices that were destroyed in the meantime (e.g. when the
+/// compositor is shutting down) are dropped.
+#[derive(Debug)]
+pub struct Injector {
+    /// The devices are destroyed along with it, unless it's the headless
+    /// backend of the compositor.
+    backend: VirtualBackend,
+    keyboard: Box<InjectedDevice>,
+    pointer: Box<InjectedDevice>,
+    /// The touch device, once it's added.
+    touch: Option<Box<InjectedDevice>>
+}
+
+impl Injector {
+    /// Create the devices on the backend of the compositor.
+    ///
+    /// Returns `None` if the backend is neither the headless nor the multi
+    /// backend, or the devices could not be created.
+    pub fn new(backend: &Backend) -> Option<Self> {
+        let mut backend = VirtualBackend::new(backend)?;
+        unsafe {
+            let keyboard = inject(backend.add_device(WLR_INPUT_DEVICE_KEYBOARD))?;
+            let pointer = inject(backend.add_device(WLR_INPUT_DEVICE_POINTER))?;
+            Some(Injector { backend,
+                            keyboard,
+                            pointer,
+                            touch: None })
+        }
+    }
+
+    /// Get the devices events are injected into that are still around.
+    pub(crate) fn devices(&self) -> Vec<*mut wlr_input_device> {
+        let touch = self.touch.as_ref().and_then(|touch| touch.device());
+        vec![self.keyboard.device(), self.pointer.device(), touch].into_iter()
+                                                                    .filter_map(|device| device)
+                                                                    .collect()
+    }
+
+    /// Press or release the key, going through the keyboard's XKB state.
+    ///
+    /// The keycode is a Linux input event code (e.g. `KEY_A` is 30), like
+    /// the ones reported by `keyboard::event::Key::keycode`.
+    pub fn inject_key(&mut self, keycode: u32, state: wlr_key_state) {
+        let device = match self.keyboard.device() {
+            Some(device) => device,
+            None => return
+        };
+        unsafe {
+            let keyboard = (*device).__bindgen_anon_1.keyboard;
+            let mut event = wlr_event_keyboard_key { time_msec: current_time().to_ms(),
+                                                     keycode,
+                                                     update_state: true,
+                                                     state };
+            wlr_keyboard_notify_key(keyboard, &mut event);
+        }
+    }
+
+    /// Move the pointer to the position, in output local coordinates, of the
+    /// output in the output layout.
+    ///
+    /// The absolute motion event is normalized to what the pointer is mapped
+    /// to by the cursor (e.g. with `Cursor::map_to_output`), which is the
+    /// whole layout by default. Nothing is sent if the position is outside of
+    /// it.
+    pub fn inject_pointer_motion_absolute(&mut self,
+                                          cursor: &mut Cursor,
+                                          layout: &mut Layout,
+                                          output: &mut Output,
+                                          x: c_double,
+                                          y: c_double) {
+        let device = match self.pointer.device() {
+            Some(device) => device,
+            None => return
+        };
+        // NOTE wlroots maps absolute coordinates linearly onto the mapping of
+        // the device, so its corners give the box it's mapped to.
+        let input = unsafe { input::Device::from_ptr(device) };
+        let (left, top) = cursor.absolute_to_layout_coords(&input, 0.0, 0.0);
+        let (right, bottom) = cursor.absolute_to_layout_coords(&input, 1.0, 1.0);
+        let (width, height) = (right - left, bottom - top);
+        if width <= 0.0 || height <= 0.0 {
+            return
+        }
+        let output_box = layout.get_box(output);
+        let x = (output_box.origin.x as c_double + x - left) / width;
+        let y = (output_box.origin.y as c_double + y - top) / height;
+        if x < 0.0 || x > 1.0 || y < 0.0 || y > 1.0 {
+            return
+        }
+        unsafe {
+            let pointer = (*device).__bindgen_anon_1.pointer;
+            let mut event = wlr_event_pointer_motion_absolute {
+                device,
+                time_msec: current_time().to_ms(),
+                x,
+                y };
+            wl_signal_emit(&mut (*pointer).events.motion_absolute as *mut _ as _,
+                           &mut event as *mut _ as *mut c_void);
+        }
+    }
+
+    /// Move the pointer to the position, normalized to the whole layout
+    /// (i.e. between 0 and 1), like absolute motion events report it.
+    pub fn inject_pointer_motion_normalized(&mut self, x: c_double, y: c_double) {
+        let device = match self.pointer.device() {
+            Some(device) => device,
+            None => return
+        };
+        unsafe {
+            let pointer = (*device).__bindgen_anon_1.pointer;
+            let mut event = wlr_event_pointer_motion_absolute { device,
+                                                                time_msec:
+                                                                    current_time().to_ms(),
+                                                                x,
+                                                                y };
+            wl_signal_emit(&mut (*pointer).events.motion_absolute as *mut _ as _,
+                           &mut event as *mut _ as *mut c_void);
+        }
+    }
+
+    /// Move the pointer by the delta, like a mouse does.
+    pub fn inject_pointer_motion(&mut self, dx: c_double, dy: c_double) {
+        let device = match self.pointer.device() {
+            Some(device) => device,
+            None => return
+        };
+        unsafe {
+            let pointer = (*device).__bindgen_anon_1.pointer;
+            let mut event = wlr_event_pointer_motion { device,
+                                                       time_msec: current_time().to_ms(),
+                                                       delta_x: dx,
+                                                       delta_y: dy };
+            wl_signal_emit(&mut (*pointer).events.motion as *mut _ as _,
+                           &mut event as *mut _ as *mut c_void);
+        }
+    }
+
+    /// Scroll by the delta, in discrete steps for sources that have them.
+    pub fn inject_axis(&mut self,
+                       orientation: wlr_axis_orientation,
+                       delta: c_double,
+                       delta_discrete: i32,
+                       source: wlr_axis_source) {
+        let device = match self.pointer.device() {
+            Some(device) => device,
+            None => return
+        };
+        unsafe {
+            let pointer = (*device).__bindgen_anon_1.pointer;
+            let mut event = wlr_event_pointer_axis { device,
+                                                     time_msec: current_time().to_ms(),
+                                                     source,
+                                                     orientation,
+                                                     delta,
+                                                     delta_discrete };
+            wl_signal_emit(&mut (*pointer).events.axis as *mut _ as _,
+                           &mut event as *mut _ as *mut c_void);
+        }
+    }
+
+    /// Press or release the pointer button, e.g. `BTN_LEFT` (272).
+    pub fn inject_button(&mut self, button: u32, state: wlr_button_state) {
+        let device = match self.pointer.device() {
+            Some(device) => device,
+            None => return
+        };
+        unsafe {
+            let pointer = (*device).__bindgen_anon_1.pointer;
+            let mut event = wlr_event_pointer_button { device,
+                                                       time_msec: current_time().to_ms(),
+                                                       button,
+                                                       state };
+            wl_signal_emit(&mut (*pointer).events.button as *mut _ as _,
+                           &mut event as *mut _ as *mut c_void);
+        }
+    }
+
+    /// Add a touch device, which is announced through the input manager
+    /// right away.
+    ///
+    /// Returns false if the device could not be created. Does nothing if the
+    /// injector already has one.
+    pub fn add_touch(&mut self) -> bool {
+        if self.touch.is_none() {
+            self.touch = unsafe { inject(self.backend.add_device(WLR_INPUT_DEVICE_TOUCH)) };
+        }
+        self.touch.is_some()
+    }
+
+    /// Get the touch device, if it was added and is still around.
+    fn touch(&self) -> Option<*mut wlr_input_device> {
+        self.touch.as_ref().and_then(|touch| touch.device())
+    }
+
+    /// Put a touch point down at the position, normalized to the layout
+    /// (i.e. between 0 and 1).
+    ///
+    /// Does nothing without a touch device, see `add_touch`.
+    pub fn inject_touch_down(&mut self, touch_id: i32, x: c_double, y: c_double) {
+        let device = match self.touch() {
+            Some(device) => device,
+            None => return
+        };
+        unsafe {
+            let touch = (*device).__bindgen_anon_1.touch;
+            let mut event = wlr_event_touch_down { device,
+                                                   time_msec: current_time().to_ms(),
+                                                   touch_id,
+                                                   x,
+                                                   y };
+            wl_signal_emit(&mut (*touch).events.down as *mut _ as _,
+                           &mut event as *mut _ as *mut c_void);
+        }
+    }
+
+    /// Lift the touch point.
+    ///
+    /// Does nothing without a touch device, see `add_touch`.
+    pub fn inject_touch_up(&mut self, touch_id: i32) {
+        let device = match self.touch() {
+            Some(device) => device,
+            None => return
+        };
+        unsafe {
+            let touch = (*device).__bindgen_anon_1.touch;
+            let mut event = wlr_event_touch_up { device,
+                                                 time_msec: current_time().to_ms(),
+                                                 touch_id };
+            wl_signal_emit(&mut (*touch).events.up as *mut _ as _,
+                           &mut event as *mut _ as *mut c_void);
+        }
+    }
+
+    /// Move the touch point to the position, normalized to the layout.
+    ///
+    /// Does nothing without a touch device, see `add_touch`.
+    pub fn inject_touch_motion(&mut self, touch_id: i32, x: c_double, y: c_double) {
+        let device = match self.touch() {
+            Some(device) => device,
+            None => return
+        };
+        unsafe {
+            let touch = (*device).__bindgen_anon_1.touch;
+            let mut event = wlr_event_touch_motion { device,
+                                                     time_msec: current_time().to_ms(),
+                                                     touch_id,
+                                                     x,
+                                                     y };
+            wl_signal_emit(&mut (*touch).events.motion as *mut _ as _,
+                           &mut event as *mut _ as *mut c_void);
+        }
+    }
+}
@@ -1,11 +1,16 @@
-use std::{cell::Cell, rc::Weak};
+use std::{cell::{Cell, RefCell}, collections::HashMap, rc::Weak};
 
-use libc::{c_double, c_uint};
-use wlroots_sys::{wlr_input_device, wlr_input_device_pointer, wlr_input_device_type,
-                  wlr_input_device_type::*};
+use libc::{self, c_double, c_uint};
+use wayland_sys::server::{signal::wl_signal_add, WAYLAND_SERVER_HANDLE};
+use wlroots_sys::{wlr_axis_orientation, wlr_event_pointer_axis, wlr_input_device,
+                  wlr_input_device_pointer, wlr_input_device_type, wlr_input_device_type::*};
 
-use {input::{keyboard, pointer, touch, tablet_pad, tablet_tool},
-     utils::c_to_rust_string};
+use {area::Area,
+     cursor::Cursor,
+     input::{keyboard, pointer, touch, tablet_pad, tablet_tool},
+     output::Output,
+     seat::{self, devices},
+     utils::{c_to_rust_string, HandleResult}};
 pub(crate) use manager::input_manager::Manager;
 
 /// A handle to an input device.
@@ -17,11 +22,82 @@ pub enum Handle {
     TabletTool(tablet_tool::Handle)
 }
 
+impl Handle {
+    /// Call the function when the device is destroyed (e.g. unplugged).
+    ///
+    /// See `utils::Handle::on_destroy` for details.
+    pub fn on_destroy<F>(&self, callback: F) -> HandleResult<()>
+        where F: FnOnce() + 'static
+    {
+        match *self {
+            Handle::Keyboard(ref handle) => handle.on_destroy(callback),
+            Handle::Pointer(ref handle) => handle.on_destroy(callback),
+            Handle::Touch(ref handle) => handle.on_destroy(callback),
+            Handle::TabletPad(ref handle) => handle.on_destroy(callback),
+            Handle::TabletTool(ref handle) => handle.on_destroy(callback)
+        }
+    }
+}
+
 pub(crate) struct InputState {
     pub(crate) handle: Weak<Cell<bool>>,
     pub(crate) device: Device
 }
 
+thread_local! {
+    /// The scroll factors of the devices that don't scroll at normal speed,
+    /// by device pointer.
+    static SCROLL_FACTORS: RefCell<HashMap<usize, Box<ScrollFactor>>> =
+        RefCell::new(HashMap::new());
+}
+
+/// What's left over from scaling the discrete steps of a device.
+#[derive(Debug, Default)]
+pub(crate) struct Remainder {
+    /// The fractions of a step that weren't sent yet, vertical first.
+    steps: [f64; 2],
+    /// The event that's being dispatched and the steps it was scaled to.
+    last: Option<(*mut wlr_event_pointer_axis, i32)>
+}
+
+wayland_listener!(pub(crate) ScrollFactor, (*mut wlr_input_device, f64, Remainder), [
+    destroy_listener => destroy_notify: |this: &mut ScrollFactor, _data: *mut libc::c_void,|
+    unsafe {
+        let device = this.data.0;
+        remove_scroll_factor(device);
+    };
+    axis_listener => axis_notify: |this: &mut ScrollFactor, data: *mut libc::c_void,|
+    unsafe {
+        let event = data as *mut wlr_event_pointer_axis;
+        let (_, factor, ref mut remainder) = this.data;
+        let index = match (*event).orientation {
+            wlr_axis_orientation::WLR_AXIS_ORIENTATION_VERTICAL => 0,
+            wlr_axis_orientation::WLR_AXIS_ORIENTATION_HORIZONTAL => 1
+        };
+        let scaled = (*event).delta_discrete as f64 * factor;
+        // NOTE What's left over from scrolling the other way is dropped.
+        if scaled * remainder.steps[index] < 0.0 {
+            remainder.steps[index] = 0.0;
+        }
+        let total = scaled + remainder.steps[index];
+        let steps = total.trunc();
+        remainder.steps[index] = total - steps;
+        remainder.last = Some((event, steps as i32));
+    };
+]);
+
+unsafe fn remove_scroll_factor(device: *mut wlr_input_device) {
+    let factor = SCROLL_FACTORS.with(|factors| factors.borrow_mut().remove(&(device as usize)));
+    if let Some(mut factor) = factor {
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_list_remove,
+                      &mut (*factor.destroy_listener()).link as *mut _ as _);
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_list_remove,
+                      &mut (*factor.axis_listener()).link as *mut _ as _);
+    }
+}
+
 /// Wrapper for wlr_input_device
 #[derive(Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
 pub struct Device {
@@ -42,22 +118,48 @@ impl Device {
         Device { device: self.device }
     }
 
+    /// Get the USB vendor id of the device.
+    ///
+    /// This will be 0 if the backend doesn't know it.
     pub fn vendor(&self) -> c_uint {
         unsafe { (*self.device).vendor }
     }
 
+    /// Get the USB product id of the device.
+    ///
+    /// This will be 0 if the backend doesn't know it.
     pub fn product(&self) -> c_uint {
         unsafe { (*self.device).product }
     }
 
+    /// Get the name of the device, as reported by the kernel.
     pub fn name(&self) -> Option<String> {
         unsafe { c_to_rust_string((*self.device).name) }
     }
 
+    /// Get the name of the output the device is built into (e.g. the
+    /// touchscreen of a laptop), if any.
     pub fn output_name(&self) -> Option<String> {
         unsafe { c_to_rust_string((*self.device).output_name) }
     }
 
+    /// Get an identifier for the device that stays the same across reboots,
+    /// in the `vendor:product:name` format also used by sway
+    /// (e.g. `1133:49970:Logitech_Gaming_Keyboard_G110`).
+    ///
+    /// Whitespace in the name is replaced with underscores, so the identifier
+    /// can be used as is in configuration files.
+    ///
+    /// Note that identical devices will have the same identifier.
+    pub fn identifier(&self) -> String {
+        let name = self.name().unwrap_or_default();
+        let name = name.trim()
+                       .split_whitespace()
+                       .collect::<Vec<_>>()
+                       .join("_");
+        format!("{}:{}:{}", self.vendor(), self.product(), name)
+    }
+
     /// Get the size in (width_mm, height_mm) format.
     ///
     /// These values will be 0 if it's not supported.
@@ -98,6 +200,98 @@ impl Device {
         }
     }
 
+    /// Maps all input from this device to the given output, using the
+    /// cursor it is attached to.
+    ///
+    /// This is mostly useful for absolute devices (e.g. tablets and
+    /// touchscreens), which would otherwise span the entire output layout.
+    ///
+    /// Pass `None` to remove the mapping.
+    ///
+    /// See `Cursor::map_input_to_output` for more details.
+    pub fn map_to_output<'output, O>(&self, cursor: &mut Cursor, output: O)
+        where O: Into<Option<&'output Output>>
+    {
+        cursor.map_input_to_output(self, output)
+    }
+
+    /// Maps all input from this device to an arbitrary region of the output
+    /// layout, using the cursor it is attached to.
+    ///
+    /// See `Cursor::map_input_to_region` for more details.
+    pub fn map_to_region(&self, cursor: &mut Cursor, area: Area) {
+        cursor.map_input_to_region(self, area)
+    }
+
+    /// Get the seat the device is attached to, if any.
+    ///
+    /// See `seat::devices` for how devices are assigned to seats.
+    pub fn seat(&self) -> Option<seat::Handle> {
+        unsafe { devices::seat_of(self.device).map(|seat| seat::Handle::from_ptr(seat)) }
+    }
+
+    /// Scale how far the device scrolls, e.g. to slow down touchpad scrolling.
+    ///
+    /// libinput has no setting for this, so the axis events of the device
+    /// are scaled before they reach the handlers instead. The discrete steps
+    /// of wheels are scaled too, in whole steps: the fractions that are left
+    /// over are added to the next event in the same direction, so e.g. with a
+    /// factor of 0.5 every other detent scrolls one step.
+    pub fn set_scroll_factor(&self, factor: f64) {
+        unsafe {
+            remove_scroll_factor(self.device);
+            if factor == 1.0 {
+                return
+            }
+            let mut scroll_factor = ScrollFactor::new((self.device, factor, Remainder::default()));
+            wl_signal_add(&mut (*self.device).events.destroy as *mut _ as _,
+                          scroll_factor.destroy_listener() as *mut _ as _);
+            if (*self.device).type_ == WLR_INPUT_DEVICE_POINTER {
+                // NOTE The steps are scaled before any other listener (e.g. a
+                // cursor the device is attached to) sees the event, so each
+                // event is only carried over once.
+                let pointer = self.dev_union().pointer;
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                              wl_list_insert,
+                              &mut (*pointer).events.axis.listener_list as *mut _ as _,
+                              &mut (*scroll_factor.axis_listener()).link as *mut _ as _);
+            }
+            SCROLL_FACTORS.with(|factors| {
+                                    factors.borrow_mut()
+                                           .insert(self.device as usize, scroll_factor)
+                                });
+        }
+    }
+
+    /// Get how much the scrolling of the device is scaled.
+    ///
+    /// This is 1.0 unless it's changed with `set_scroll_factor`.
+    pub fn scroll_factor(&self) -> f64 {
+        SCROLL_FACTORS.with(|factors| {
+                                factors.borrow()
+                                       .get(&(self.device as usize))
+                                       .map(|factor| factor.data.1)
+                                       .unwrap_or(1.0)
+                            })
+    }
+
+    /// Get the discrete steps of the axis event of the device that's being
+    /// dispatched, scaled by its scroll factor.
+    pub(crate) unsafe fn scaled_discrete(&self, event: *mut wlr_event_pointer_axis) -> i32 {
+        SCROLL_FACTORS.with(|factors| {
+                                match factors.borrow().get(&(self.device as usize)) {
+                                    Some(factor) => match factor.data.2.last {
+                                        Some((last, steps)) if last == event => steps,
+                                        _ => {
+                                            ((*event).delta_discrete as f64 * factor.data.1)
+                                                .round() as i32
+                                        }
+                                    },
+                                    None => (*event).delta_discrete
+                                }
+                            })
+    }
+
     pub(crate) unsafe fn dev_union(&self) -> wlr_input_device_pointer {
         (*self.device).__bindgen_anon_1
     }
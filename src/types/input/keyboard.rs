@@ -2,11 +2,11 @@
 use std::{fmt, cell::Cell, rc::Rc};
 
 use wlroots_sys::{wlr_input_device, wlr_keyboard, wlr_keyboard_get_modifiers, wlr_keyboard_led,
-                  wlr_keyboard_led_update, wlr_keyboard_modifier, wlr_keyboard_set_keymap,
-                  xkb_keysym_t};
+                  wlr_keyboard_led_update, wlr_keyboard_modifier, wlr_keyboard_notify_modifiers,
+                  wlr_keyboard_set_keymap, xkb_keysym_t};
 pub use wlroots_sys::wlr_key_state;
 use xkbcommon::xkb::{self, Keycode, Keymap, LedIndex, ModIndex};
-use xkbcommon::xkb::ffi::{xkb_keymap, xkb_state};
+use xkbcommon::xkb::ffi::{xkb_keymap, xkb_state, xkb_state_led_index_is_active};
 
 use {KeyboardModifiers,
      input::{self, InputState},
@@ -153,12 +153,29 @@ impl Keyboard {
     /// Update the LED lights using the provided bitmap.
     ///
     /// 1 means one, 0 means off.
+    ///
+    /// This doesn't change the lock state of the keyboard (see `set_locks`),
+    /// the LEDs are updated from the XKB state again the next time the
+    /// modifiers change.
     pub fn update_led(&mut self, leds: Led) {
         unsafe {
             wlr_keyboard_led_update(self.keyboard, leds.bits() as u32);
         }
     }
 
+    /// Get the LED lights that should be on according to the XKB state.
+    pub fn leds(&self) -> Led {
+        unsafe { leds(self.keyboard) }
+    }
+
+    /// Lock or unlock num lock and caps lock, e.g. to enable num lock on start.
+    ///
+    /// The LEDs follow the new lock state and the `modifiers` callback is
+    /// triggered. Scroll lock has no modifier, so it is ignored.
+    pub fn set_locks(&mut self, locks: Led) {
+        unsafe { set_locks(self.keyboard, locks) }
+    }
+
     /// Get the modifiers that are currently pressed on the keyboard.
     pub fn get_modifiers(&self) -> Modifier {
         unsafe { Modifier::from_bits_truncate(wlr_keyboard_get_modifiers(self.keyboard)) }
@@ -170,10 +187,50 @@ impl Keyboard {
     }
 }
 
+/// Get the LED lights that should be on for the keyboard.
+pub(crate) unsafe fn leds(keyboard: *mut wlr_keyboard) -> Led {
+    let mut leds = Led::empty();
+    let xkb_state = (*keyboard).xkb_state as *mut xkb_state;
+    if xkb_state.is_null() {
+        return leds
+    }
+    let all_leds = [Led::WLR_LED_NUM_LOCK, Led::WLR_LED_CAPS_LOCK, Led::WLR_LED_SCROLL_LOCK];
+    for (led, index) in all_leds.iter().zip((*keyboard).led_indexes.iter()) {
+        if xkb_state_led_index_is_active(xkb_state, *index) > 0 {
+            leds.insert(*led);
+        }
+    }
+    leds
+}
+
+/// Lock or unlock num lock and caps lock on the keyboard.
+pub(crate) unsafe fn set_locks(keyboard: *mut wlr_keyboard, locks: Led) {
+    let lock_modifiers = [(Led::WLR_LED_NUM_LOCK, Modifier::WLR_MODIFIER_MOD2),
+                          (Led::WLR_LED_CAPS_LOCK, Modifier::WLR_MODIFIER_CAPS)];
+    let mut modifiers = (*keyboard).modifiers;
+    for &(led, modifier) in lock_modifiers.iter() {
+        let mod_index = (*keyboard).mod_indexes[modifier.bits().trailing_zeros() as usize];
+        if mod_index == xkb::MOD_INVALID {
+            continue
+        }
+        if locks.contains(led) {
+            modifiers.locked |= 1 << mod_index;
+        } else {
+            modifiers.locked &= !(1 << mod_index);
+        }
+    }
+    wlr_keyboard_notify_modifiers(keyboard,
+                                  modifiers.depressed,
+                                  modifiers.latched,
+                                  modifiers.locked,
+                                  modifiers.group);
+}
+
 impl Drop for Keyboard {
     fn drop(&mut self) {
         if Rc::strong_count(&self.liveliness) == 1 {
             wlr_log!(WLR_DEBUG, "Dropped Keyboard {:p}", self.keyboard);
+            utils::user_data::clear(self.keyboard);
             unsafe {
                 let _ = Box::from_raw((*self.keyboard).data as *mut InputState);
             }
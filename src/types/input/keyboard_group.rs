@@ -0,0 +1,219 @@
+//! Merging keyboards into one logical keyboard, so that modifiers held on one
+//! keyboard apply to keys pressed on another (e.g. a foot pedal for Shift).
+//!
+//! This version of wlroots has no `wlr_keyboard_group`, so a group is a
+//! virtual keyboard on the headless backend (see `inject`) that the keys of
+//! its members are forwarded to. Like any other keyboard it's announced
+//! through the input manager, where it gets the default keymap.
+//!
+//! The keymap of the group is used for the keys of all of its members, so
+//! changing it with `Keyboard::set_keymap` changes the layout of all of them.
+//! The keys of the members only reach the keyboard handler through the group,
+//! so bindings don't see them twice. Give the group to the seat instead of its
+//! members:
+//!
+//! ```rust,no_run,ignore
+//! let mut group = KeyboardGroup::new(compositor.backend()).unwrap();
+//! group.add_keyboard(keyboard);
+//! seat.set_keyboard(group.input_device());
+//! ```
+
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use libc;
+use wayland_sys::server::{signal::wl_signal_add, WAYLAND_SERVER_HANDLE};
+use wlroots_sys::{wlr_event_keyboard_key, wlr_input_device, wlr_input_device_destroy,
+                  wlr_keyboard, wlr_keyboard_notify_key,
+                  wlr_input_device_type::WLR_INPUT_DEVICE_KEYBOARD,
+                  wlr_key_state::{self, WLR_KEY_PRESSED, WLR_KEY_RELEASED}};
+
+use {backend::Backend,
+     input::{self, inject::{InjectedDevice, VirtualBackend}, keyboard::Keyboard},
+     utils::{current_time, ToMs}};
+
+/// How many members hold down each key.
+type PressedKeys = Rc<RefCell<HashMap<u32, usize>>>;
+
+thread_local! {
+    /// The keyboards that are members of a group.
+    static MEMBERS: RefCell<Vec<*mut wlr_input_device>> = RefCell::new(Vec::new());
+}
+
+/// Determine if the keyboard is a member of a group, so its keys are only
+/// handled through the group.
+pub(crate) fn grouped(device: *mut wlr_input_device) -> bool {
+    MEMBERS.with(|members| members.borrow().contains(&device))
+}
+
+wayland_listener!(pub(crate) GroupMember, (*mut wlr_input_device,
+                                           *mut wlr_keyboard,
+                                           PressedKeys,
+                                           Vec<u32>), [
+    key_listener => key_notify: |this: &mut GroupMember, data: *mut libc::c_void,|
+    unsafe {
+        let event = &*(data as *mut wlr_event_keyboard_key);
+        let group = this.data.1;
+        let forward = {
+            let mut pressed = this.data.2.borrow_mut();
+            let count = pressed.entry(event.keycode).or_insert(0);
+            match event.state {
+                WLR_KEY_PRESSED if !this.data.3.contains(&event.keycode) => {
+                    this.data.3.push(event.keycode);
+                    *count += 1;
+                    *count == 1
+                },
+                WLR_KEY_RELEASED if this.data.3.contains(&event.keycode) => {
+                    this.data.3.retain(|&key| key != event.keycode);
+                    *count -= 1;
+                    *count == 0
+                },
+                _ => false
+            }
+        };
+        if forward {
+            notify_key(group, event.time_msec, event.keycode, event.state);
+        }
+    };
+    destroy_listener => destroy_notify: |this: &mut GroupMember, _data: *mut libc::c_void,|
+    unsafe {
+        this.release_keys();
+        this.remove_listeners();
+        this.data.0 = ::std::ptr::null_mut();
+    };
+]);
+
+impl GroupMember {
+    /// Release the keys this member still holds down on the group.
+    unsafe fn release_keys(&mut self) {
+        let time = current_time().to_ms();
+        for key in ::std::mem::replace(&mut self.data.3, Vec::new()) {
+            let released = {
+                let mut pressed = self.data.2.borrow_mut();
+                let count = pressed.entry(key).or_insert(1);
+                *count -= 1;
+                *count == 0
+            };
+            if released {
+                notify_key(self.data.1, time, key, WLR_KEY_RELEASED);
+            }
+        }
+    }
+
+    unsafe fn remove_listeners(&mut self) {
+        let device = self.data.0;
+        MEMBERS.with(|members| members.borrow_mut().retain(|member| *member != device));
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_list_remove,
+                      &mut (*self.key_listener()).link as *mut _ as _);
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_list_remove,
+                      &mut (*self.destroy_listener()).link as *mut _ as _);
+    }
+}
+
+unsafe fn notify_key(group: *mut wlr_keyboard, time_msec: u32, keycode: u32, state: wlr_key_state) {
+    let mut event = wlr_event_keyboard_key { time_msec, keycode, update_state: true, state };
+    wlr_keyboard_notify_key(group, &mut event);
+}
+
+/// A virtual keyboard that combines the keys of its member keyboards.
+pub struct KeyboardGroup {
+    _backend: VirtualBackend,
+    device: input::Device,
+    /// Tells whether the group keyboard is still around, so it's only
+    /// destroyed once.
+    tracked: Box<InjectedDevice>,
+    members: Vec<Box<GroupMember>>,
+    pressed: PressedKeys
+}
+
+impl KeyboardGroup {
+    /// Create the group keyboard on the backend of the compositor.
+    ///
+    /// Returns `None` if the backend is neither the headless nor the multi
+    /// backend, or the keyboard could not be created.
+    pub fn new(backend: &Backend) -> Option<Self> {
+        let mut backend = VirtualBackend::new(backend)?;
+        let device = unsafe { backend.add_device(WLR_INPUT_DEVICE_KEYBOARD) };
+        let tracked = unsafe { InjectedDevice::track(device)? };
+        Some(KeyboardGroup { _backend: backend,
+                             device: input::Device { device },
+                             tracked,
+                             members: Vec::new(),
+                             pressed: Rc::new(RefCell::new(HashMap::new())) })
+    }
+
+    /// Get the device of the group keyboard, to give to the seat.
+    pub fn input_device(&self) -> &input::Device {
+        &self.device
+    }
+
+    /// Add the keyboard to the group.
+    ///
+    /// Returns `false` if it's already a member.
+    pub fn add_keyboard(&mut self, keyboard: &Keyboard) -> bool {
+        unsafe {
+            let device = keyboard.input_device().as_ptr();
+            if device == self.device.as_ptr() || self.position(device).is_some() {
+                return false
+            }
+            let mut member = GroupMember::new((device,
+                                               (*self.device.as_ptr()).__bindgen_anon_1.keyboard,
+                                               self.pressed.clone(),
+                                               Vec::new()));
+            wl_signal_add(&mut (*keyboard.as_ptr()).events.key as *mut _ as _,
+                          member.key_listener() as *mut _ as _);
+            wl_signal_add(&mut (*device).events.destroy as *mut _ as _,
+                          member.destroy_listener() as *mut _ as _);
+            MEMBERS.with(|members| members.borrow_mut().push(device));
+            self.members.push(member);
+            true
+        }
+    }
+
+    /// Remove the keyboard from the group, releasing the keys it holds down.
+    pub fn remove_keyboard(&mut self, keyboard: &Keyboard) {
+        unsafe {
+            if let Some(index) = self.position(keyboard.input_device().as_ptr()) {
+                let mut member = self.members.remove(index);
+                member.release_keys();
+                member.remove_listeners();
+            }
+        }
+    }
+
+    /// Get the number of keyboards in the group.
+    pub fn len(&self) -> usize {
+        self.members.iter().filter(|member| !member.data.0.is_null()).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn position(&mut self, device: *mut wlr_input_device) -> Option<usize> {
+        // NOTE Unplugged members are only cleaned up here, as they can't
+        // remove themselves from the group.
+        self.members.retain(|member| !member.data.0.is_null());
+        self.members.iter().position(|member| member.data.0 == device)
+    }
+}
+
+impl Drop for KeyboardGroup {
+    fn drop(&mut self) {
+        unsafe {
+            for member in &mut self.members {
+                if !member.data.0.is_null() {
+                    member.release_keys();
+                    member.remove_listeners();
+                }
+            }
+            // NOTE The group keyboard belongs to the headless backend, which
+            // may outlive the group. Destroying it announces it's gone like
+            // an unplugged keyboard.
+            if let Some(device) = self.tracked.device() {
+                wlr_input_device_destroy(device);
+            }
+        }
+    }
+}
@@ -4,6 +4,9 @@ pub mod pointer;
 pub mod touch;
 pub mod tablet_tool;
 pub mod tablet_pad;
+pub mod inject;
+pub mod keyboard_group;
+pub mod gestures;
 
 pub use self::input_device::*;
 
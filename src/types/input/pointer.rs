@@ -71,6 +71,7 @@ impl Drop for Pointer {
     fn drop(&mut self) {
         if Rc::strong_count(&self.liveliness) == 1 {
             wlr_log!(WLR_DEBUG, "Dropped Pointer {:p}", self.pointer);
+            utils::user_data::clear(self.pointer);
             unsafe {
                 let _ = Box::from_raw((*self.pointer).data as *mut InputState);
             }
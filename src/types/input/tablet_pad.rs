@@ -66,6 +66,7 @@ impl Drop for TabletPad {
             return
         }
         wlr_log!(WLR_DEBUG, "Dropped TabletPad {:p}", self.pad);
+        utils::user_data::clear(self.pad);
         unsafe {
             let _ = Box::from_raw((*self.pad).data as *mut InputState);
         }
@@ -83,6 +83,7 @@ impl Drop for TabletTool {
             return
         }
         wlr_log!(WLR_DEBUG, "Dropped TabletTool {:p}", self.tool);
+        utils::user_data::clear(self.tool);
         unsafe {
             let _ = Box::from_raw((*self.tool).data as *mut InputState);
         }
@@ -64,6 +64,7 @@ impl Drop for Touch {
     fn drop(&mut self) {
         if Rc::strong_count(&self.liveliness) == 1 {
             wlr_log!(WLR_DEBUG, "Dropped Touch {:p}", self.touch);
+            utils::user_data::clear(self.touch);
             unsafe {
                 let _ = Box::from_raw((*self.touch).data as *mut input::Device);
             }
@@ -0,0 +1,78 @@
+//! Policies for where `output::layout::Layout::add_auto` and
+//! `output::layout::Layout::rearrange` put outputs.
+
+use std::collections::HashMap;
+
+use area::{Origin, Size};
+
+/// How outputs are arranged in an output layout.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Arrangement {
+    /// Side by side from left to right, in the order they were added, with
+    /// their top edges lined up.
+    Horizontal,
+    /// Stacked from top to bottom, in the order they were added, with their
+    /// left edges lined up.
+    Vertical,
+    /// All at the origin, so they show the same part of the layout.
+    Mirror,
+    /// Outputs are put where they were remembered to be, by name (e.g. the
+    /// positions in a `config::Config`). The others are placed from left to
+    /// right after them.
+    Remembered(HashMap<String, Origin>)
+}
+
+impl Default for Arrangement {
+    fn default() -> Self {
+        Arrangement::Horizontal
+    }
+}
+
+impl Arrangement {
+    /// Get where the outputs go, given their names and sizes in layout
+    /// coordinates in the order they were added.
+    pub(crate) fn positions(&self, outputs: &[(String, Size)]) -> Vec<Origin> {
+        match *self {
+            Arrangement::Horizontal => pack(outputs.iter().map(|&(_, size)| size), 0, 0, true),
+            Arrangement::Vertical => pack(outputs.iter().map(|&(_, size)| size), 0, 0, false),
+            Arrangement::Mirror => vec![Origin::default(); outputs.len()],
+            Arrangement::Remembered(ref positions) => {
+                let (mut right, mut top) = (None, None);
+                for &(ref name, size) in outputs {
+                    if let Some(origin) = positions.get(name) {
+                        let edge = origin.x + size.width;
+                        right = Some(right.map_or(edge, |right: i32| right.max(edge)));
+                        top = Some(top.map_or(origin.y, |top: i32| top.min(origin.y)));
+                    }
+                }
+                let rest = outputs.iter()
+                                  .filter(|&&(ref name, _)| !positions.contains_key(name))
+                                  .map(|&(_, size)| size);
+                let mut rest = pack(rest, right.unwrap_or(0), top.unwrap_or(0), true)
+                    .into_iter();
+                outputs.iter()
+                       .map(|&(ref name, _)| match positions.get(name) {
+                                Some(origin) => *origin,
+                                None => rest.next().unwrap_or_default()
+                            })
+                       .collect()
+            }
+        }
+    }
+}
+
+/// Place the outputs one after the other, without gaps, starting at (x, y).
+fn pack<I>(sizes: I, mut x: i32, mut y: i32, horizontal: bool) -> Vec<Origin>
+    where I: Iterator<Item = Size>
+{
+    sizes.map(|size| {
+             let origin = Origin::new(x, y);
+             if horizontal {
+                 x += size.width;
+             } else {
+                 y += size.height;
+             }
+             origin
+         })
+         .collect()
+}
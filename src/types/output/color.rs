@@ -0,0 +1,399 @@
+//! Color correction of outputs.
+//!
+//! This version of wlroots has no color transform stage in the renderer, so
+//! the only way to correct colors is the gamma ramps of the output (i.e. the
+//! CRTC lookup tables on DRM). From an ICC profile that means the calibration
+//! curves in its `vcgt` tag are applied, while the rest of the profile (e.g.
+//! the primaries of a wide-gamut monitor) can't be taken into account.
+//!
+//! The color temperature of an output (e.g. for blue light reduction at
+//! night) is applied on top of its color transform through the same ramps.
+
+use std::{fs, io, path::Path, time::{Duration, Instant}};
+
+use utils;
+
+/// The size of the tables generated from the formula of a `vcgt` tag.
+const FORMULA_LUT_SIZE: usize = 256;
+
+/// The color temperature of daylight, which leaves the colors as they are.
+pub const NEUTRAL_TEMPERATURE: u32 = 6500;
+
+/// How the colors of an output are transformed before being scanned out.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColorTransform {
+    /// Leave the colors as they are.
+    Identity,
+    /// A lookup table for each channel, mapping evenly spaced input values
+    /// to output values.
+    ///
+    /// The tables can be of any length, they are resampled to the gamma size
+    /// of the output.
+    Lut {
+        red: Vec<u16>,
+        green: Vec<u16>,
+        blue: Vec<u16>
+    }
+}
+
+impl Default for ColorTransform {
+    fn default() -> Self {
+        ColorTransform::Identity
+    }
+}
+
+impl ColorTransform {
+    /// Load the calibration curves of the ICC profile at the path.
+    pub fn from_icc_profile<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        ColorTransform::from_icc_data(&fs::read(path)?)
+    }
+
+    /// Load the calibration curves of the ICC profile in `data`.
+    ///
+    /// Fails if the profile is malformed or has no `vcgt` tag.
+    pub fn from_icc_data(data: &[u8]) -> io::Result<Self> {
+        let tag = find_tag(data, b"vcgt").ok_or_else(|| invalid("The profile has no vcgt tag"))?;
+        if tag.len() < 12 || &tag[..4] != b"vcgt" {
+            return Err(invalid("The vcgt tag is malformed"))
+        }
+        match read_u32(tag, 8) {
+            Some(0) => parse_vcgt_table(tag),
+            Some(1) => parse_vcgt_formula(tag),
+            _ => Err(invalid("Unknown vcgt tag type"))
+        }
+    }
+
+    /// Get the gamma ramps of the transform, resampled to `size` entries and
+    /// tinted to the color temperature.
+    pub(crate) fn ramps(&self, size: usize, temperature: f64) -> (Vec<u16>, Vec<u16>, Vec<u16>) {
+        let identity = [0, u16::max_value()];
+        let (red, green, blue) = match *self {
+            ColorTransform::Identity => (&identity[..], &identity[..], &identity[..]),
+            ColorTransform::Lut { ref red, ref green, ref blue } => {
+                (&red[..], &green[..], &blue[..])
+            }
+        };
+        let [red_factor, green_factor, blue_factor] = whitepoint(temperature);
+        (scale(resample(red, size), red_factor),
+         scale(resample(green, size), green_factor),
+         scale(resample(blue, size), blue_factor))
+    }
+}
+
+/// A color temperature change that's in progress.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct TemperatureFade {
+    from: f64,
+    to: f64,
+    start: Instant,
+    duration: Duration
+}
+
+impl TemperatureFade {
+    pub(crate) fn new(from: f64, to: f64, duration: Duration) -> Self {
+        TemperatureFade { from, to, start: utils::now(), duration }
+    }
+
+    /// Get the temperature at this point of the fade.
+    pub(crate) fn current(&self) -> f64 {
+        let elapsed = utils::now().duration_since(self.start);
+        if elapsed >= self.duration {
+            return self.to
+        }
+        let progress = duration_secs(elapsed) / duration_secs(self.duration);
+        self.from + (self.to - self.from) * progress
+    }
+
+    pub(crate) fn target(&self) -> f64 {
+        self.to
+    }
+
+    pub(crate) fn finished(&self) -> bool {
+        utils::now().duration_since(self.start) >= self.duration
+    }
+}
+
+fn duration_secs(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1e9
+}
+
+/// Get how much each channel is scaled by to tint white to the temperature,
+/// relative to `NEUTRAL_TEMPERATURE`.
+fn whitepoint(temperature: f64) -> [f64; 3] {
+    let neutral = blackbody(NEUTRAL_TEMPERATURE as f64);
+    let tint = blackbody(temperature);
+    [(tint[0] / neutral[0]).min(1.0),
+     (tint[1] / neutral[1]).min(1.0),
+     (tint[2] / neutral[2]).min(1.0)]
+}
+
+/// Approximate the color of a black body at the temperature in Kelvin.
+///
+/// This is Tanner Helland's fit of the CIE color matching data, which is
+/// accurate enough from 1000K to 40000K.
+fn blackbody(temperature: f64) -> [f64; 3] {
+    let temperature = temperature.max(1000.0).min(40000.0) / 100.0;
+    let red = if temperature <= 66.0 {
+        255.0
+    } else {
+        329.698727446 * (temperature - 60.0).powf(-0.1332047592)
+    };
+    let green = if temperature <= 66.0 {
+        99.4708025861 * temperature.ln() - 161.1195681661
+    } else {
+        288.1221695283 * (temperature - 60.0).powf(-0.0755148492)
+    };
+    let blue = if temperature >= 66.0 {
+        255.0
+    } else if temperature <= 19.0 {
+        0.0
+    } else {
+        138.5177312231 * (temperature - 10.0).ln() - 305.0447927307
+    };
+    let clamp = |value: f64| value.max(0.0).min(255.0) / 255.0;
+    [clamp(red), clamp(green), clamp(blue)]
+}
+
+fn scale(ramp: Vec<u16>, factor: f64) -> Vec<u16> {
+    if factor >= 1.0 {
+        return ramp
+    }
+    ramp.into_iter().map(|value| (value as f64 * factor).round() as u16).collect()
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset + 2)
+        .map(|bytes| (bytes[0] as u16) << 8 | bytes[1] as u16)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some((read_u16(data, offset)? as u32) << 16 | read_u16(data, offset + 2)? as u32)
+}
+
+/// Read an ICC `s15Fixed16Number`.
+fn read_fixed(data: &[u8], offset: usize) -> Option<f64> {
+    read_u32(data, offset).map(|value| value as i32 as f64 / 65536.0)
+}
+
+/// Find the tag with the signature in the tag table following the header.
+fn find_tag<'data>(data: &'data [u8], signature: &[u8; 4]) -> Option<&'data [u8]> {
+    // NOTE The count comes from the file, don't loop past the table.
+    let count = (read_u32(data, 128)? as usize).min(data.len().saturating_sub(132) / 12);
+    (0..count).filter_map(|i| {
+                  let entry = 132 + i * 12;
+                  if data.get(entry..entry + 4)? != signature {
+                      return None
+                  }
+                  let offset = read_u32(data, entry + 4)? as usize;
+                  let size = read_u32(data, entry + 8)? as usize;
+                  data.get(offset..offset.checked_add(size)?)
+              })
+              .next()
+}
+
+fn parse_vcgt_table(tag: &[u8]) -> io::Result<ColorTransform> {
+    let malformed = || invalid("The vcgt table is malformed");
+    let channels = read_u16(tag, 12).ok_or_else(malformed)? as usize;
+    let count = read_u16(tag, 14).ok_or_else(malformed)? as usize;
+    let entry_size = read_u16(tag, 16).ok_or_else(malformed)? as usize;
+    if (channels != 1 && channels != 3) || count == 0 {
+        return Err(malformed())
+    }
+    let mut tables = Vec::with_capacity(channels);
+    for channel in 0..channels {
+        let table = (0..count).map(|i| {
+                                   let offset = 18 + (channel * count + i) * entry_size;
+                                   match entry_size {
+                                       1 => tag.get(offset).map(|&value| value as u16 * 257),
+                                       2 => read_u16(tag, offset),
+                                       _ => None
+                                   }
+                               })
+                              .collect::<Option<Vec<_>>>()
+                              .ok_or_else(malformed)?;
+        tables.push(table);
+    }
+    let red = tables.remove(0);
+    let (green, blue) = if tables.is_empty() {
+        (red.clone(), red.clone())
+    } else {
+        (tables.remove(0), tables.remove(0))
+    };
+    Ok(ColorTransform::Lut { red, green, blue })
+}
+
+fn parse_vcgt_formula(tag: &[u8]) -> io::Result<ColorTransform> {
+    let mut tables = Vec::with_capacity(3);
+    for channel in 0..3 {
+        let offset = 12 + channel * 12;
+        let (gamma, min, max) = match (read_fixed(tag, offset),
+                                       read_fixed(tag, offset + 4),
+                                       read_fixed(tag, offset + 8)) {
+            (Some(gamma), Some(min), Some(max)) => (gamma, min, max),
+            _ => return Err(invalid("The vcgt formula is malformed"))
+        };
+        let table = (0..FORMULA_LUT_SIZE).map(|i| {
+                                             let x = i as f64 / (FORMULA_LUT_SIZE - 1) as f64;
+                                             let y = min + (max - min) * x.powf(gamma);
+                                             (y.max(0.0).min(1.0) * 65535.0).round() as u16
+                                         })
+                                         .collect();
+        tables.push(table);
+    }
+    let blue = tables.pop().unwrap();
+    let green = tables.pop().unwrap();
+    let red = tables.pop().unwrap();
+    Ok(ColorTransform::Lut { red, green, blue })
+}
+
+/// Linearly interpolate the table to `size` entries.
+fn resample(table: &[u16], size: usize) -> Vec<u16> {
+    if table.len() == size || table.len() < 2 {
+        return table.iter().cloned().cycle().take(size).collect()
+    }
+    if size < 2 {
+        return table[..size].to_vec()
+    }
+    (0..size).map(|i| {
+                 let position = i as f64 * (table.len() - 1) as f64 / (size - 1) as f64;
+                 let index = position.floor() as usize;
+                 let next = (index + 1).min(table.len() - 1);
+                 let fraction = position - index as f64;
+                 let value = table[index] as f64 * (1.0 - fraction) + table[next] as f64 * fraction;
+                 value.round() as u16
+             })
+             .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A profile with a header, a tag table with only the tag, and the tag.
+    fn profile(signature: &[u8; 4], tag: &[u8]) -> Vec<u8> {
+        let mut data = vec![0; 128];
+        data.extend_from_slice(&[0, 0, 0, 1]);
+        data.extend_from_slice(signature);
+        data.extend_from_slice(&[0, 0, 0, 144]);
+        data.extend_from_slice(&[0, 0, 0, tag.len() as u8]);
+        data.extend_from_slice(tag);
+        data
+    }
+
+    fn vcgt(kind: u8, body: &[u8]) -> Vec<u8> {
+        let mut tag = b"vcgt\0\0\0\0\0\0\0".to_vec();
+        tag.push(kind);
+        tag.extend_from_slice(body);
+        tag
+    }
+
+    #[test]
+    fn reads_big_endian() {
+        let data = [0x12, 0x34, 0x56, 0x78, 0xff, 0xfe, 0x80, 0x00];
+        assert_eq!(read_u16(&data, 0), Some(0x1234));
+        assert_eq!(read_u16(&data, 7), None);
+        assert_eq!(read_u32(&data, 0), Some(0x1234_5678));
+        assert_eq!(read_u32(&data, 5), None);
+        assert_eq!(read_fixed(&data, 4), Some(-1.5));
+    }
+
+    #[test]
+    fn finds_tags() {
+        let data = profile(b"vcgt", b"vcgt tag");
+        assert_eq!(find_tag(&data, b"vcgt"), Some(&b"vcgt tag"[..]));
+        assert_eq!(find_tag(&data, b"desc"), None);
+        assert_eq!(find_tag(&data[..100], b"vcgt"), None);
+    }
+
+    #[test]
+    fn tags_out_of_the_file_are_not_found() {
+        let mut data = profile(b"vcgt", b"vcgt tag");
+        // NOTE A count much larger than the table.
+        data[128..132].copy_from_slice(&[0xff, 0xff, 0xff, 0xff]);
+        assert_eq!(find_tag(&data, b"vcgt"), Some(&b"vcgt tag"[..]));
+        // NOTE A size that goes past the end.
+        data[143] = 100;
+        assert_eq!(find_tag(&data, b"vcgt"), None);
+        // NOTE An offset past the end.
+        data[136..140].copy_from_slice(&[0xff, 0xff, 0xff, 0xff]);
+        assert_eq!(find_tag(&data, b"vcgt"), None);
+    }
+
+    #[test]
+    fn parses_a_table() {
+        let tag = vcgt(0, &[0, 1, 0, 3, 0, 2, 0x00, 0x00, 0x80, 0x00, 0xff, 0xff]);
+        let transform = ColorTransform::from_icc_data(&profile(b"vcgt", &tag)).unwrap();
+        let table = vec![0, 0x8000, 0xffff];
+        assert_eq!(transform,
+                   ColorTransform::Lut { red: table.clone(), green: table.clone(), blue: table });
+    }
+
+    #[test]
+    fn parses_a_table_of_bytes_per_channel() {
+        let tag = vcgt(0, &[0, 3, 0, 2, 0, 1, 0x00, 0xff, 0x00, 0x80, 0xff, 0x00]);
+        assert_eq!(parse_vcgt_table(&tag).unwrap(),
+                   ColorTransform::Lut { red: vec![0, 0xffff],
+                                         green: vec![0, 0x8080],
+                                         blue: vec![0xffff, 0] });
+    }
+
+    #[test]
+    fn rejects_malformed_tables() {
+        // NOTE Two channels.
+        assert!(parse_vcgt_table(&vcgt(0, &[0, 2, 0, 1, 0, 1, 0, 0])).is_err());
+        // NOTE No entries.
+        assert!(parse_vcgt_table(&vcgt(0, &[0, 1, 0, 0, 0, 1])).is_err());
+        // NOTE Entries of 4 bytes.
+        assert!(parse_vcgt_table(&vcgt(0, &[0, 1, 0, 1, 0, 4, 0, 0, 0, 0])).is_err());
+        // NOTE Fewer entries than the count.
+        assert!(parse_vcgt_table(&vcgt(0, &[0, 1, 0, 3, 0, 2, 0, 0])).is_err());
+        assert!(parse_vcgt_table(&vcgt(0, &[0, 1])).is_err());
+        assert!(ColorTransform::from_icc_data(&profile(b"vcgt", &vcgt(2, &[]))).is_err());
+        assert!(ColorTransform::from_icc_data(&profile(b"desc", &vcgt(0, &[]))).is_err());
+    }
+
+    #[test]
+    fn parses_a_formula() {
+        // NOTE A gamma of 1 from 0 to 1, a gamma of 2 from 0 to 1/2, and a
+        // constant 1.
+        let tag = vcgt(1,
+                       &[0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0,
+                         0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0x80, 0,
+                         0, 1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0]);
+        match parse_vcgt_formula(&tag).unwrap() {
+            ColorTransform::Lut { red, green, blue } => {
+                assert_eq!(red.len(), FORMULA_LUT_SIZE);
+                assert_eq!((red[0], red[51], red[255]), (0, 13107, 65535));
+                assert_eq!((green[0], green[255]), (0, 32768));
+                assert_eq!(green[128], 8256);
+                assert!(blue.iter().all(|&value| value == 65535));
+            },
+            other => panic!("Parsed {:?}", other)
+        }
+        assert!(parse_vcgt_formula(&vcgt(1, &[0, 1, 0, 0])).is_err());
+    }
+
+    #[test]
+    fn resamples() {
+        assert_eq!(resample(&[0, 65535], 3), vec![0, 32768, 65535]);
+        assert_eq!(resample(&[0, 100, 200, 300, 400], 3), vec![0, 200, 400]);
+        assert_eq!(resample(&[1, 2, 3], 3), vec![1, 2, 3]);
+        assert_eq!(resample(&[7], 3), vec![7, 7, 7]);
+        assert_eq!(resample(&[1, 2, 3], 1), vec![1]);
+        assert_eq!(resample(&[1, 2, 3], 0), Vec::<u16>::new());
+    }
+
+    #[test]
+    fn neutral_temperature_keeps_the_ramps() {
+        let (red, green, blue) = ColorTransform::Identity.ramps(3, NEUTRAL_TEMPERATURE as f64);
+        assert_eq!((red, green, blue),
+                   (vec![0, 32768, 65535], vec![0, 32768, 65535], vec![0, 32768, 65535]));
+        let (red, _, blue) = ColorTransform::Identity.ramps(2, 3000.0);
+        assert_eq!(red[1], 65535);
+        assert!(blue[1] < red[1]);
+    }
+}
@@ -20,7 +20,8 @@ pub struct Cursor {
 impl Cursor {
     /// Creates a new `output::Cursor` that's bound to the given `Output`.
     ///
-    /// When the `Output` is destroyed, this can no longer be used.
+    /// When the `Output` is destroyed, this can no longer be used. The getters
+    /// return default values from then on.
     ///
     /// # Ergonomics
     ///
@@ -40,6 +41,11 @@ impl Cursor {
         }
     }
 
+    /// Determines if the output of the cursor still exists.
+    fn is_alive(&self) -> bool {
+        self.output_handle.handle.upgrade().is_some()
+    }
+
     /// Sets the hardware cursor's image.
     pub fn set_image(&mut self, image: &render::Image) -> bool {
         unsafe {
@@ -56,7 +62,7 @@ impl Cursor {
             match res {
                 Ok(res) => res,
                 Err(HandleErr::AlreadyDropped) => false,
-                err @ Err(HandleErr::AlreadyBorrowed) => panic!(err)
+                err @ Err(HandleErr::AlreadyBorrowed { .. }) => panic!(err)
             }
         }
     }
@@ -78,7 +84,7 @@ impl Cursor {
                                              });
             match res {
                 Ok(_) | Err(HandleErr::AlreadyDropped) => {}
-                err @ Err(HandleErr::AlreadyBorrowed) => panic!(err)
+                err @ Err(HandleErr::AlreadyBorrowed { .. }) => panic!(err)
             }
         }
     }
@@ -91,7 +97,7 @@ impl Cursor {
             match res {
                 Ok(res) => res,
                 Err(HandleErr::AlreadyDropped) => false,
-                err @ Err(HandleErr::AlreadyBorrowed) => panic!(err)
+                err @ Err(HandleErr::AlreadyBorrowed { .. }) => panic!(err)
             }
         }
     }
@@ -100,23 +106,29 @@ impl Cursor {
     ///
     /// Returned value is in (x, y) format.
     pub fn coords(&self) -> (f64, f64) {
+        if !self.is_alive() {
+            return (0.0, 0.0)
+        }
         unsafe { ((*self.cursor).x, (*self.cursor).y) }
     }
 
     /// Determines if the hardware cursor is enabled or not.
     pub fn enabled(&self) -> bool {
-        unsafe { (*self.cursor).enabled }
+        self.is_alive() && unsafe { (*self.cursor).enabled }
     }
 
     /// Determines if the hardware cursor is visible or not.
     pub fn visible(&self) -> bool {
-        unsafe { (*self.cursor).visible }
+        self.is_alive() && unsafe { (*self.cursor).visible }
     }
 
     /// Gets the width and height of the hardware cursor.
     ///
     /// Returned value is in (width, height) format.
     pub fn size(&self) -> (u32, u32) {
+        if !self.is_alive() {
+            return (0, 0)
+        }
         unsafe { ((*self.cursor).width, (*self.cursor).height) }
     }
 
@@ -124,12 +136,18 @@ impl Cursor {
     ///
     /// Returned value is in (x, y) coordinates.
     pub fn hotspots(&self) -> (i32, i32) {
+        if !self.is_alive() {
+            return (0, 0)
+        }
         unsafe { ((*self.cursor).hotspot_x, (*self.cursor).hotspot_y) }
     }
 
     /// Gets the texture for the cursor, if a software cursor is used without a
     /// surface.
     pub fn texture<'surface>(&'surface self) -> Option<render::Texture<'surface>> {
+        if !self.is_alive() {
+            return None
+        }
         unsafe {
             let texture = (*self.cursor).texture;
             if texture.is_null() {
@@ -142,6 +160,9 @@ impl Cursor {
 
     /// Gets the surface for the cursor, if using a cursor surface.
     pub fn surface(&self) -> Option<surface::Handle> {
+        if !self.is_alive() {
+            return None
+        }
         unsafe {
             let surface = (*self.cursor).surface;
             if surface.is_null() {
@@ -155,6 +176,10 @@ impl Cursor {
 
 impl Drop for Cursor {
     fn drop(&mut self) {
-        unsafe { wlr_output_cursor_destroy(self.cursor) }
+        // NOTE wlroots destroys the hardware cursors of an output along with
+        // it, so only destroy it ourselves if the output is still around.
+        if self.is_alive() {
+            unsafe { wlr_output_cursor_destroy(self.cursor) }
+        }
     }
 }
@@ -0,0 +1,244 @@
+//! When the frames of an output are rendered, trading latency for throughput.
+//!
+//! This version of wlroots queues at most one frame on an output and emits
+//! the frame event once it's shown. Everything beyond that (rendering as late
+//! as possible, or keeping the output busy) is done by delaying or scheduling
+//! frame events here.
+//!
+//! Clients draw a new frame whenever they get a frame callback, so windows
+//! that can't be seen can be sent fewer of them, see
+//! `Output::set_hidden_frame_interval`. Windows that aren't on any output are
+//! sent theirs by a timer, as no output renders them.
+
+use std::{cell::RefCell, collections::HashMap, mem, ptr, time::{Duration, Instant}};
+
+use libc;
+use wayland_sys::server::{wl_event_source, WAYLAND_SERVER_HANDLE};
+
+use {compositor, output::FrameStats, utils::current_time, view::View};
+
+/// Added to the estimated render time of an output, so that an unusually
+/// slow frame still makes it for the vblank.
+const RENDER_TIME_MARGIN_MS: u64 = 1;
+/// How much the render delay can grow from one frame to the next.
+const DELAY_STEP_MS: u64 = 1;
+/// How often views on no output get frame callbacks, unless the output that
+/// last had them says otherwise with `Output::set_hidden_frame_interval`.
+const OFFSCREEN_FRAME_INTERVAL: Duration = Duration::from_secs(1);
+
+/// When new frames are scheduled on the output.
+///
+/// This version of wlroots only ever queues one frame, so this doesn't
+/// change how many buffers the output uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Buffering {
+    /// A frame is only rendered when the compositor asks for one (e.g. when
+    /// the output is damaged).
+    OnDemand,
+    /// A new frame is scheduled as soon as the last one was submitted, so
+    /// the compositor renders again as soon as the output can take a frame.
+    ///
+    /// This keeps the output busy (and the frame rate up) at the cost of
+    /// power, even when nothing changed.
+    Continuous
+}
+
+/// At what point of the refresh cycle the frame is rendered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timing {
+    /// Render as soon as the output is ready for a new frame, which leaves
+    /// the most time to render it.
+    Immediate,
+    /// Render as late as possible before the next vblank, so that the frame
+    /// shows the latest state (e.g. of the cursor) when it's scanned out.
+    ///
+    /// The time left to render is the max render time of the output if it's
+    /// set, otherwise it's estimated from how long the recent frames took.
+    Vblank
+}
+
+/// The frame pacing policy of an output, set with `Output::set_frame_pacing`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Policy {
+    pub buffering: Buffering,
+    pub timing: Timing
+}
+
+impl Policy {
+    /// Render frames when asked, right after the previous one was shown.
+    pub const DEFAULT: Policy = Policy { buffering: Buffering::OnDemand,
+                                         timing: Timing::Immediate };
+    /// Render frames when asked, as close to the vblank as possible.
+    pub const LOW_LATENCY: Policy = Policy { buffering: Buffering::OnDemand,
+                                             timing: Timing::Vblank };
+    /// Render frames continuously, as soon as the output can take them.
+    pub const THROUGHPUT: Policy = Policy { buffering: Buffering::Continuous,
+                                            timing: Timing::Immediate };
+
+    /// How long to wait after the frame event before rendering, given the
+    /// delay of the previous frame.
+    ///
+    /// The delay shrinks right away when frames get slower, but only grows a
+    /// little every frame when they get faster, so that it settles instead of
+    /// going back and forth with the render times.
+    ///
+    /// Returns `None` if the frame should be rendered right away.
+    pub(crate) fn render_delay(&self,
+                               refresh_period: Option<Duration>,
+                               max_render_time: Option<Duration>,
+                               stats: &FrameStats,
+                               previous: Option<Duration>)
+                               -> Option<Duration> {
+        if self.timing == Timing::Immediate {
+            return None
+        }
+        let period = refresh_period?;
+        let render_time = match max_render_time {
+            Some(max_render_time) => max_render_time,
+            None => *stats.render_times().max()? + Duration::from_millis(RENDER_TIME_MARGIN_MS)
+        };
+        let mut delay = period.checked_sub(render_time)?;
+        if max_render_time.is_none() {
+            let limit = previous.unwrap_or_default() + Duration::from_millis(DELAY_STEP_MS);
+            delay = delay.min(limit);
+        }
+        if delay >= Duration::from_millis(1) {
+            Some(delay)
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy::DEFAULT
+    }
+}
+
+/// Keeps track of when the views that can't be seen on an output last got
+/// frame callbacks.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Throttle {
+    /// How often hidden views get frame callbacks, or `None` to send them
+    /// every frame.
+    pub(crate) interval: Option<Duration>,
+    last_sent: HashMap<View, Instant>
+}
+
+impl Throttle {
+    /// Determine if the view gets its frame callbacks now, and remember it
+    /// if it does.
+    ///
+    /// A view that was just hidden gets them once more right away.
+    pub(crate) fn should_send(&mut self, view: &View, visible: bool, now: Instant) -> bool {
+        let interval = match self.interval {
+            Some(interval) if !visible => interval,
+            _ => {
+                self.last_sent.remove(view);
+                return true
+            }
+        };
+        if let Some(last_sent) = self.last_sent.get(view) {
+            if now.duration_since(*last_sent) < interval {
+                return false
+            }
+        }
+        self.last_sent.insert(view.clone(), now);
+        true
+    }
+
+    /// Forget the views that were destroyed.
+    pub(crate) fn prune(&mut self) {
+        self.last_sent.retain(|view, _| view.is_alive())
+    }
+}
+
+/// The views that aren't on any output, which a timer sends frame callbacks
+/// to.
+struct Offscreen {
+    views: Vec<View>,
+    interval: Duration,
+    timer: *mut wl_event_source
+}
+
+thread_local! {
+    static OFFSCREEN: RefCell<Offscreen> =
+        RefCell::new(Offscreen { views: Vec::new(),
+                                 interval: OFFSCREEN_FRAME_INTERVAL,
+                                 timer: ptr::null_mut() });
+}
+
+/// Send the view its frame callbacks with the timer, until it's back on an
+/// output.
+///
+/// `interval` is the hidden frame interval of the output it was left by.
+pub(crate) fn park(view: &View, interval: Option<Duration>) {
+    let start = OFFSCREEN.with(|offscreen| {
+        let mut offscreen = offscreen.borrow_mut();
+        offscreen.interval = interval.unwrap_or(OFFSCREEN_FRAME_INTERVAL);
+        if offscreen.views.contains(view) {
+            return false
+        }
+        offscreen.views.push(view.clone());
+        offscreen.timer.is_null()
+    });
+    if start {
+        unsafe { arm_offscreen_timer() }
+    }
+}
+
+/// Stop sending the view frame callbacks with the timer, now that it's on an
+/// output.
+pub(crate) fn unpark(view: &View) {
+    OFFSCREEN.with(|offscreen| offscreen.borrow_mut().views.retain(|other| other != view))
+}
+
+unsafe fn arm_offscreen_timer() {
+    let (timer, interval) = OFFSCREEN.with(|offscreen| {
+        let mut offscreen = offscreen.borrow_mut();
+        if offscreen.timer.is_null() && !compositor::COMPOSITOR_PTR.is_null() {
+            let event_loop = (*compositor::COMPOSITOR_PTR).event_loop;
+            offscreen.timer = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                            wl_event_loop_add_timer,
+                                            event_loop,
+                                            offscreen_timer_notify,
+                                            ptr::null_mut());
+        }
+        (offscreen.timer, offscreen.interval)
+    });
+    if timer.is_null() {
+        return
+    }
+    let interval = interval.as_secs() as u64 * 1000 + interval.subsec_millis() as u64;
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_event_source_timer_update,
+                  timer,
+                  interval.max(1).min(libc::c_int::max_value() as u64) as libc::c_int);
+}
+
+unsafe extern "C" fn offscreen_timer_notify(_data: *mut libc::c_void) -> libc::c_int {
+    let views = OFFSCREEN.with(|offscreen| {
+        let mut offscreen = offscreen.borrow_mut();
+        offscreen.views.retain(|view| view.is_alive());
+        offscreen.views.clone()
+    });
+    let time = current_time();
+    for view in &views {
+        let _ = view.for_each_surface(|surface, _, _| {
+                                          surface.run(|surface| surface.send_frame_done(time))
+                                                 .ok();
+                                      });
+    }
+    if views.is_empty() {
+        // NOTE The timer is added again once a view is parked.
+        let timer = OFFSCREEN.with(|offscreen| {
+                                       let mut offscreen = offscreen.borrow_mut();
+                                       mem::replace(&mut offscreen.timer, ptr::null_mut())
+                                   });
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_event_source_remove, timer);
+    } else {
+        arm_offscreen_timer();
+    }
+    0
+}
@@ -0,0 +1,142 @@
+//! Statistics about the frames rendered to an output, for performance
+//! debugging.
+
+use std::{collections::VecDeque, time::{Duration, Instant}};
+
+use utils;
+
+/// How many frames of history are kept.
+const HISTORY_LEN: usize = 120;
+
+/// Timing information about the frames recently rendered to an output.
+///
+/// Obtained with `Output::frame_stats`.
+#[derive(Debug, Clone, Default)]
+pub struct FrameStats {
+    /// Time between the compositor starting to render and the buffers being
+    /// swapped.
+    render_times: VecDeque<Duration>,
+    /// Time between consecutive frame events.
+    vblank_intervals: VecDeque<Duration>,
+    /// The refresh period of the output when the last frame was started.
+    refresh_period: Option<Duration>,
+    missed_frames: u64,
+    frames: u64,
+    last_frame: Option<Instant>,
+    render_start: Option<Instant>
+}
+
+impl FrameStats {
+    /// The time it took to render each of the recent frames, oldest first.
+    ///
+    /// This is the CPU time between the compositor being asked to render the
+    /// frame and the buffers being swapped. It doesn't include the time the
+    /// frame was delayed by the frame pacing of the output.
+    pub fn render_times(&self) -> impl Iterator<Item = &Duration> {
+        self.render_times.iter()
+    }
+
+    /// The time between each of the recent frame events, oldest first.
+    pub fn vblank_intervals(&self) -> impl Iterator<Item = &Duration> {
+        self.vblank_intervals.iter()
+    }
+
+    /// The time it took to render the last frame, if one has been rendered.
+    pub fn last_render_time(&self) -> Option<Duration> {
+        self.render_times.back().cloned()
+    }
+
+    /// The average time it took to render the recent frames.
+    pub fn average_render_time(&self) -> Option<Duration> {
+        average(&self.render_times)
+    }
+
+    /// The average time between the recent frame events.
+    pub fn average_vblank_interval(&self) -> Option<Duration> {
+        average(&self.vblank_intervals)
+    }
+
+    /// The amount of frames per second, based on the recent frame events.
+    pub fn fps(&self) -> Option<f64> {
+        self.average_vblank_interval().map(|interval| 1.0 / as_secs_f64(interval))
+    }
+
+    /// The refresh period of the output, if it reports a refresh rate.
+    pub fn refresh_period(&self) -> Option<Duration> {
+        self.refresh_period
+    }
+
+    /// The total number of frames whose buffers were swapped more than a
+    /// refresh period after their frame event, i.e. that missed the next
+    /// vblank.
+    pub fn missed_frames(&self) -> u64 {
+        self.missed_frames
+    }
+
+    /// The total number of frame events for the output.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Clear all the collected statistics.
+    pub fn reset(&mut self) {
+        *self = FrameStats::default()
+    }
+
+    /// Called when the frame event for the output fires.
+    pub(crate) fn frame_started(&mut self, refresh_period: Option<Duration>) {
+        let now = utils::now();
+        if let Some(last_frame) = self.last_frame {
+            push(&mut self.vblank_intervals, now - last_frame);
+        }
+        self.frames += 1;
+        self.last_frame = Some(now);
+        self.render_start = None;
+        self.refresh_period = refresh_period;
+    }
+
+    /// Get when the frame event of the last frame fired.
+    pub(crate) fn last_frame(&self) -> Option<Instant> {
+        self.last_frame
+    }
+
+    /// Called right before the compositor renders the frame, once it's no
+    /// longer delayed.
+    pub(crate) fn render_started(&mut self) {
+        self.render_start = Some(Instant::now());
+    }
+
+    /// Called when the buffers of the output are swapped.
+    pub(crate) fn frame_finished(&mut self) {
+        let render_start = match self.render_start.take() {
+            Some(render_start) => render_start,
+            None => return
+        };
+        let render_time = render_start.elapsed();
+        if let (Some(period), Some(last_frame)) = (self.refresh_period, self.last_frame) {
+            if utils::now().duration_since(last_frame) > period {
+                self.missed_frames += 1;
+            }
+        }
+        push(&mut self.render_times, render_time);
+    }
+}
+
+fn push(history: &mut VecDeque<Duration>, value: Duration) {
+    if history.len() == HISTORY_LEN {
+        history.pop_front();
+    }
+    history.push_back(value);
+}
+
+fn average(history: &VecDeque<Duration>) -> Option<Duration> {
+    if history.is_empty() {
+        return None
+    }
+    let total = history.iter().fold(Duration::from_secs(0), |total, value| total + *value);
+    Some(total / history.len() as u32)
+}
+
+fn as_secs_f64(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1_000_000_000.0
+}
@@ -1,6 +1,7 @@
 //! TODO Documentation
 
-use std::{fmt, panic, ptr, cell::Cell, marker::PhantomData, rc::{Rc, Weak}};
+use std::{fmt, ffi::CStr, panic::{self, Location}, ptr, cell::Cell, marker::PhantomData,
+          rc::{Rc, Weak}};
 
 use libc::{self, c_double, c_int};
 use wayland_sys::server::{signal::wl_signal_add, WAYLAND_SERVER_HANDLE};
@@ -12,10 +13,11 @@ use wlroots_sys::{wlr_output_effective_resolution, wlr_output_layout, wlr_output
                   wlr_output_layout_move, wlr_output_layout_output, wlr_output_layout_output_at,
                   wlr_output_layout_output_coords, wlr_output_layout_remove};
 
-use {area::{Area, Origin},
+use {area::{Area, Origin, Size},
      compositor,
-     output,
-     utils::{HandleErr, HandleResult, Handleable}};
+     cursor,
+     output::{self, arrangement::Arrangement},
+     utils::{self, HandleErr, HandleResult, Handleable}};
 
 struct OutputLayoutState {
     /// A counter that will always have a strong count of 1.
@@ -24,7 +26,9 @@ struct OutputLayoutState {
     /// they cannot be upgraded.
     counter: Rc<Cell<bool>>,
     /// A raw pointer to the `output::layout::Layout` on the heap.
-    layout: *mut Layout
+    layout: *mut Layout,
+    /// Where outputs added with `add_auto` go.
+    arrangement: Arrangement
 }
 
 #[allow(unused_variables)]
@@ -152,12 +156,19 @@ impl Layout {
             let counter = Rc::new(Cell::new(false));
             let handle = Rc::downgrade(&counter);
             let state = Box::new(OutputLayoutState { counter,
-                                                     layout: Box::into_raw(output_layout) });
+                                                     layout: Box::into_raw(output_layout),
+                                                     arrangement: Arrangement::default() });
             (*layout).data = Box::into_raw(state) as *mut libc::c_void;
             Handle { layout, handle }
         }
     }
 
+    /// Construct an output layout like `create`, without boxing the handler
+    /// yourself.
+    pub fn create_with<H: Handler + 'static>(handler: H) -> Handle {
+        Layout::create(Box::new(handler))
+    }
+
     pub(crate) unsafe fn as_ptr(&self) -> *mut wlr_output_layout {
         self.data.0
     }
@@ -204,20 +215,78 @@ impl Layout {
     /// Adds an output to the layout at the given coordinates.
     pub fn add(&mut self, output: &mut output::Output, origin: Origin) {
         let (x, y) = (origin.x, origin.y);
-        unsafe { wlr_output_layout_add(self.data.0, output.as_ptr(), x, y) }
+        unsafe {
+            wlr_output_layout_add(self.data.0, output.as_ptr(), x, y);
+            // NOTE The cursors only get an image for the output once it's set
+            // again after they started showing on it.
+            cursor::output_changed(output.as_ptr())
+        }
     }
 
     /// Adds an output to the layout, automatically positioning it with
-    /// the others that are already there.
+    /// the others that are already there according to the arrangement.
+    ///
+    /// With the default (`Arrangement::Horizontal`) wlroots keeps the
+    /// outputs added this way side by side on its own, even as they change
+    /// size or others are removed.
     pub fn add_auto(&mut self, output: &mut output::Output) {
         unsafe {
             let layout_handle = self.weak_reference();
             output.set_output_layout(Some(layout_handle));
-            wlr_output_layout_add_auto(self.data.0, output.as_ptr());
+            if self.arrangement() == &Arrangement::Horizontal {
+                wlr_output_layout_add_auto(self.data.0, output.as_ptr());
+            } else {
+                wlr_output_layout_add(self.data.0, output.as_ptr(), 0, 0);
+                self.rearrange();
+            }
+            cursor::output_changed(output.as_ptr());
             wlr_log!(WLR_DEBUG, "Added {:?} to {:?}", output, self);
         }
     }
 
+    /// Get how `add_auto` and `rearrange` arrange outputs.
+    pub fn arrangement(&self) -> &Arrangement {
+        unsafe { &(*self.state()).arrangement }
+    }
+
+    /// Change how `add_auto` and `rearrange` arrange outputs.
+    ///
+    /// The outputs that are already in the layout aren't moved until
+    /// `rearrange` is called.
+    pub fn set_arrangement(&mut self, arrangement: Arrangement) {
+        unsafe { (*self.state()).arrangement = arrangement }
+    }
+
+    /// Move every output of the layout to where the arrangement puts it,
+    /// e.g. to close the gap an unplugged output left.
+    ///
+    /// Outputs are arranged in the order they were added. Afterwards none of
+    /// them are positioned by wlroots anymore, so call this again when
+    /// outputs are added, removed or change size.
+    pub fn rearrange(&mut self) {
+        unsafe {
+            let mut outputs = vec![];
+            wl_list_for_each!((*self.data.0).outputs, link, (pos: wlr_output_layout_output) => {
+                let output = (*pos).output;
+                let (mut width, mut height) = (0, 0);
+                wlr_output_effective_resolution(output, &mut width, &mut height);
+                let name = CStr::from_ptr((*output).name.as_ptr()).to_string_lossy().into_owned();
+                outputs.push((output, name, Size::new(width, height)));
+            });
+            let sizes = outputs.iter()
+                               .map(|&(_, ref name, size)| (name.clone(), size))
+                               .collect::<Vec<_>>();
+            let positions = self.arrangement().positions(&sizes);
+            for (&(output, _, _), origin) in outputs.iter().zip(positions) {
+                wlr_output_layout_move(self.data.0, output, origin.x, origin.y);
+            }
+        }
+    }
+
+    unsafe fn state(&self) -> *mut OutputLayoutState {
+        (*self.data.0).data as *mut OutputLayoutState
+    }
+
     /// Moves the output to the given coordinates.
     ///
     /// If the output is not part of this layout this does nothing.
@@ -350,6 +419,7 @@ impl Drop for Layout {
     fn drop(&mut self) {
         let layout_ptr = self.data.0;
         unsafe {
+            utils::user_data::clear(layout_ptr);
             let data = Box::from_raw((*layout_ptr).data as *mut OutputLayoutState);
             let mut manager = Box::from_raw(data.layout);
             assert_eq!(Rc::strong_count(&data.counter),
@@ -383,6 +453,26 @@ impl Handle {
         }
     }
 
+    /// Get the unique id of the output layout this handle refers to.
+    ///
+    /// See `utils::Handle::id` for details.
+    pub fn id(&self) -> u64 {
+        utils::user_data::id(self.layout as usize, &self.handle)
+    }
+
+    /// Call the function when the output layout is destroyed.
+    ///
+    /// See `utils::Handle::on_destroy` for details.
+    pub fn on_destroy<F>(&self, callback: F) -> HandleResult<()>
+        where F: FnOnce() + 'static
+    {
+        if utils::user_data::on_destroy(self.layout as usize, &self.handle, Box::new(callback)) {
+            Ok(())
+        } else {
+            Err(HandleErr::AlreadyDropped)
+        }
+    }
+
     /// Upgrades the `Handle` to a reference
     /// to the backing `output::layout::Layout`.
     ///
@@ -390,7 +480,9 @@ impl Handle {
     /// This function is unsafe, because it creates an unbound `output::layout::Layout`
     /// which may live forever..
     /// But the actual lifetime of `output::layout::Layout` is determined by the user.
+    #[track_caller]
     pub(crate) unsafe fn upgrade(&self) -> HandleResult<Box<Layout>> {
+        let location = Location::caller();
         self.handle.upgrade()
             .ok_or(HandleErr::AlreadyDropped)
             // NOTE
@@ -398,9 +490,9 @@ impl Handle {
             // pointer to exist!
             .and_then(|check| {
                 if check.get() {
-                    return Err(HandleErr::AlreadyBorrowed)
+                    return Err(utils::already_borrowed::<Layout>(&check))
                 }
-                check.set(true);
+                utils::borrow_lock(&check, location);
                 Ok(Layout::from_ptr(self.layout))
             })
     }
@@ -414,6 +506,7 @@ impl Handle {
     /// to a short lived scope of an anonymous function,
     /// this function ensures the output::layout::Layout does not live longer
     /// than it exists (because the lifetime is controlled by the user).
+    #[track_caller]
     pub fn run<F, R>(&self, runner: F) -> HandleResult<R>
         where F: FnOnce(&mut Layout) -> R
     {
@@ -428,7 +521,7 @@ impl Handle {
                                                    output_layout);
                                           panic!("Lock in incorrect state!");
                                       }
-                                      check.set(false);
+                                      utils::release_lock(&check);
                                   });
         Box::into_raw(output_layout);
         match res {
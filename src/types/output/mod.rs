@@ -1,13 +1,21 @@
 mod output;
 pub mod layout;
+pub mod arrangement;
 mod mode;
 mod cursor;
 mod damage;
+mod frame_stats;
+pub mod frame_pacing;
+mod color;
+mod transaction;
 
 pub use self::cursor::*;
 pub use self::damage::*;
+pub use self::frame_stats::*;
+pub use self::color::*;
 pub use self::output::*;
 pub use self::mode::*;
+pub use self::transaction::*;
 
 pub mod manager {
     //! Output resources are managed by the output resource manager.
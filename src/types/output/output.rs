@@ -1,26 +1,34 @@
 //! TODO Documentation
 
-use std::{cell::Cell, ffi::CStr, mem::ManuallyDrop, rc::{Rc, Weak},
-          time::Duration, panic, ptr};
+use std::{cell::Cell, collections::VecDeque, ffi::CStr, io, mem::ManuallyDrop, path::Path, rc::{Rc, Weak},
+          time::{Duration, Instant}, mem, panic, ptr};
 
-use libc::{c_float, c_int, clock_t};
-use wayland_sys::server::WAYLAND_SERVER_HANDLE;
-use wlroots_sys::{timespec, wl_list, wl_output_subpixel, wl_output_transform, wlr_output,
+use libc::{c_float, c_int, c_void, clock_t};
+use wayland_sys::server::{wl_event_source, WAYLAND_SERVER_HANDLE};
+use wlroots_sys::{timespec, wl_list, wl_shm_format, wl_output_subpixel, wl_output_transform, wlr_output,
                   wlr_output_damage, wlr_output_effective_resolution, wlr_output_enable,
                   wlr_output_get_gamma_size, wlr_output_make_current, wlr_output_mode,
                   wlr_output_schedule_frame, wlr_output_set_custom_mode,
                   wlr_output_set_gamma, wlr_output_set_mode,
                   wlr_output_set_position, wlr_output_set_scale, wlr_output_set_transform,
-                  wlr_output_swap_buffers, wlr_output_transformed_resolution};
+                  wlr_output_swap_buffers, wlr_output_transformed_resolution, wlr_texture,
+                  wlr_texture_destroy};
 
-use {area::{Origin, Size},
+use {compositor, area::{Area, Origin, Size},
      utils::{self, HandleErr, HandleResult, Handleable, c_to_rust_string},
-     output::{self, layout},
-     render::PixmanRegion};
+     output::{self, frame_pacing, layout, ColorTransform, TemperatureFade, NEUTRAL_TEMPERATURE},
+     render::{matrix, PixmanRegion, RenderPass, Renderer, Texture},
+     surface::{self, Surface},
+     view::{self, View}};
+#[cfg(feature = "tearing_control")]
+use extensions::tearing_control::{self, PresentationHint};
 pub use manager::output_handler::*;
 pub use manager::output_manager::{OutputBuilder as Builder, BuilderResult};
 pub(crate) use manager::output_manager::Manager;
 
+/// How often the gamma ramps are updated while fading the color temperature.
+const COLOR_FADE_STEP_MS: c_int = 50;
+
 pub type Subpixel = wl_output_subpixel;
 pub type Transform = wl_output_transform;
 
@@ -28,9 +36,58 @@ pub(crate) struct OutputState {
     pub(crate) output: *mut UserOutput,
     handle: Weak<Cell<bool>>,
     damage: *mut wlr_output_damage,
-    layout_handle: Option<layout::Handle>
+    layout_handle: Option<layout::Handle>,
+    /// How long before the next vblank the frame event should be emitted.
+    pub(crate) max_render_time: Option<Duration>,
+    /// How long the last frame event was delayed, if it was.
+    render_delay: Option<Duration>,
+    /// Timer used to delay the frame event, lazily created.
+    pub(crate) render_timer: *mut wl_event_source,
+    /// When frames are rendered, see `output::frame_pacing`.
+    frame_pacing: frame_pacing::Policy,
+    pub(crate) frame_stats: output::FrameStats,
+    /// When the views that can't be seen get frame callbacks.
+    frame_throttle: frame_pacing::Throttle,
+    /// Whether to draw the frame stats on top of the output when rendering.
+    frame_stats_overlay: bool,
+    /// Whether frames can be presented immediately for `tearing_surface`.
+    allow_tearing: bool,
+    /// The surface whose presentation hint decides if the output tears.
+    tearing_surface: Option<surface::Handle>,
+    /// The render passes run by the `render::Renderer`, in order.
+    render_passes: Vec<Box<RenderPass>>,
+    /// The color transform applied to the gamma ramps.
+    color_transform: ColorTransform,
+    /// The color temperature the ramps are tinted to, in Kelvin.
+    color_temperature: f64,
+    /// The change of color temperature in progress, if any.
+    temperature_fade: Option<TemperatureFade>,
+    /// Timer used to step through `temperature_fade`, lazily created.
+    pub(crate) color_timer: *mut wl_event_source,
+    /// The output whose contents are shown on this one instead.
+    mirror_of: Option<Handle>,
+    /// The outputs mirroring this one.
+    mirrors: Vec<Handle>,
+    /// The last frame rendered to this output, for its mirrors.
+    mirror_texture: *mut wlr_texture,
+    /// Whether the renderer `mirror_texture` belongs to is still around.
+    mirror_renderer: Option<Rc<Cell<bool>>>,
+    /// Whether the rows of `mirror_texture` are upside down.
+    mirror_y_invert: bool,
+    /// The age of the buffer that was last made current, if it's known.
+    buffer_age: Option<c_int>,
+    /// The damage of the last frames, newest first, to repaint buffers that
+    /// are more than a frame old.
+    damage_history: VecDeque<PixmanRegion>,
+    /// Set once the `wlr_output` is destroyed, while this state may still be
+    /// kept alive by an upgraded handle up the stack.
+    destroyed: bool
 }
 
+/// How many frames of damage are remembered, which is enough for triple
+/// buffering with some room to spare.
+const DAMAGE_HISTORY_LEN: usize = 4;
+
 #[derive(Debug)]
 pub struct Output {
     /// The structure that ensures weak handles to this structure are still alive.
@@ -46,7 +103,10 @@ pub struct Output {
     /// The tracker for damage on the output.
     damage: ManuallyDrop<output::Damage>,
     /// The output ptr that refers to this `Output`
-    output: *mut wlr_output
+    output: *mut wlr_output,
+    /// The state stored in the output's user data, which can outlive the
+    /// `wlr_output`.
+    state: *mut OutputState
 }
 
 pub type Handle = utils::Handle<*mut wlr_output_damage, wlr_output, Output>;
@@ -63,7 +123,8 @@ impl Output {
     pub(crate) unsafe fn clone(&self) -> Output {
         Output { liveliness: self.liveliness.clone(),
                  damage: ManuallyDrop::new(self.damage.clone()),
-                 output: self.output }
+                 output: self.output,
+                 state: self.state }
     }
 
     /// Makes a new `Output` from a `wlr_output`.
@@ -79,11 +140,35 @@ impl Output {
         let state = Box::new(OutputState { output: ptr::null_mut(),
                                            handle,
                                            damage: damage.as_ptr(),
-                                           layout_handle: None });
-        (*output).data = Box::into_raw(state) as *mut _;
+                                           layout_handle: None,
+                                           max_render_time: None,
+                                           render_delay: None,
+                                           render_timer: ptr::null_mut(),
+                                           frame_pacing: frame_pacing::Policy::default(),
+                                           frame_stats: output::FrameStats::default(),
+                                           frame_throttle: frame_pacing::Throttle::default(),
+                                           frame_stats_overlay: false,
+                                           allow_tearing: false,
+                                           tearing_surface: None,
+                                           render_passes: Vec::new(),
+                                           color_transform: ColorTransform::Identity,
+                                           color_temperature: NEUTRAL_TEMPERATURE as f64,
+                                           temperature_fade: None,
+                                           color_timer: ptr::null_mut(),
+                                           mirror_of: None,
+                                           mirrors: Vec::new(),
+                                           mirror_texture: ptr::null_mut(),
+                                           mirror_renderer: None,
+                                           mirror_y_invert: false,
+                                           buffer_age: None,
+                                           damage_history: VecDeque::new(),
+                                           destroyed: false });
+        let state = Box::into_raw(state);
+        (*output).data = state as *mut _;
         Output { liveliness,
                  damage,
-                 output }
+                 output,
+                 state }
     }
 
     pub(crate) unsafe fn set_output_layout<T>(&mut self, layout_handle: T)
@@ -94,13 +179,11 @@ impl Output {
         if user_data.is_null() {
             return
         }
-        let mut data = Box::from_raw(user_data);
-        data.layout_handle = layout_handle.into();
-        (*self.output).data = Box::into_raw(data) as *mut _;
+        (*user_data).layout_handle = layout_handle.into();
     }
 
     unsafe fn user_data(&mut self) -> *mut OutputState {
-        (*self.output).data as *mut _
+        self.state
     }
 
     /// Used to clear the pointer to an OutputLayout when the OutputLayout
@@ -110,9 +193,7 @@ impl Output {
         if user_data.is_null() {
             return
         }
-        let mut data = Box::from_raw(user_data);
-        data.layout_handle = None;
-        (*self.output).data = Box::into_raw(data) as *mut _;
+        (*user_data).layout_handle = None;
     }
 
     /// Remove this Output from an OutputLayout, if it is part of an
@@ -126,7 +207,7 @@ impl Output {
         if let Some(layout_handle) = (*output_data).layout_handle.take() {
             match layout_handle.run(|layout| layout.remove(self)) {
                 Ok(_) | Err(HandleErr::AlreadyDropped) => self.clear_output_layout_data(),
-                Err(HandleErr::AlreadyBorrowed) => {
+                Err(HandleErr::AlreadyBorrowed { .. }) => {
                     panic!("Could not add OutputLayout to Output user data!")
                 }
             }
@@ -284,9 +365,75 @@ impl Output {
         } else {
             Some(buffer_age)
         };
+        if !self.state.is_null() {
+            (*self.state).buffer_age = buffer_age;
+        }
         (res, buffer_age)
     }
 
+    /// Get the age of the buffer that is being rendered to (or was last), in
+    /// frames.
+    ///
+    /// A buffer that's 1 frame old holds the previous frame, so only the
+    /// damage of this frame needs to be repainted. A buffer that's N frames
+    /// old needs the damage of the last N - 1 frames repainted too. The age is
+    /// 0 when the buffer holds nothing useful, and `None` when it's unknown;
+    /// repaint everything in both cases. `Renderer::repaint_region` does this
+    /// accumulation.
+    pub fn buffer_age(&self) -> Option<c_int> {
+        unsafe {
+            if self.state.is_null() {
+                None
+            } else {
+                (*self.state).buffer_age
+            }
+        }
+    }
+
+    /// Get the region that has to be repainted in the current buffer: the
+    /// damage of this frame and of the frames since the buffer was drawn.
+    ///
+    /// Returns `None` if the whole output has to be repainted.
+    pub(crate) fn accumulated_damage(&self, damage: &PixmanRegion) -> Option<PixmanRegion> {
+        unsafe {
+            if self.state.is_null() {
+                return None
+            }
+            let age = (*self.state).buffer_age? as usize;
+            let history = &(*self.state).damage_history;
+            if age == 0 || age - 1 > history.len() {
+                return None
+            }
+            let mut region = PixmanRegion::new();
+            region.union(damage);
+            for previous in history.iter().take(age - 1) {
+                region.union(previous);
+            }
+            Some(region)
+        }
+    }
+
+    /// Remember the damage of the frame that was just rendered, or that the
+    /// whole output was repainted if it's `None`.
+    pub(crate) fn push_damage(&mut self, damage: Option<&PixmanRegion>) {
+        unsafe {
+            if self.state.is_null() {
+                return
+            }
+            let mut region = PixmanRegion::new();
+            match damage {
+                Some(damage) => region.union(damage),
+                None => {
+                    let (width, height) = self.size();
+                    region.rectangle(0, 0, width as _, height as _);
+                }
+            }
+            let history = &mut (*self.state).damage_history;
+            history.push_front(region);
+            history.truncate(DAMAGE_HISTORY_LEN);
+        }
+    }
+
     /// Swaps the buffers and draws whatever is in the back buffer on the screen.
     ///
     /// If the time of the frame is not known, set `when` to None.
@@ -388,6 +535,84 @@ impl Output {
         unsafe { wlr_output_get_gamma_size(self.output) }
     }
 
+    /// Apply the color transform to the gamma ramps of this output.
+    ///
+    /// The color temperature of the output is applied on top of it.
+    ///
+    /// Returns false if the output doesn't support setting the gamma.
+    pub fn set_color_transform(&mut self, transform: &ColorTransform) -> bool {
+        unsafe {
+            let data = self.user_data();
+            if data.is_null() {
+                return false
+            }
+            (*data).color_transform = transform.clone();
+            apply_color(self.output, data)
+        }
+    }
+
+    /// Change the color temperature of this output (e.g. to 3500 Kelvin to
+    /// reduce blue light at night), fading to it over `duration`.
+    ///
+    /// Setting it to `output::NEUTRAL_TEMPERATURE` turns this off. This uses
+    /// the gamma ramps, so a client using the gamma control protocol
+    /// overrides it until it's changed again.
+    pub fn set_color_temperature(&mut self, kelvin: u32, duration: Duration) {
+        unsafe {
+            let data = self.user_data();
+            if data.is_null() {
+                return
+            }
+            let target = kelvin as f64;
+            if duration == Duration::from_secs(0) || compositor::COMPOSITOR_PTR.is_null() {
+                (*data).temperature_fade = None;
+                (*data).color_temperature = target;
+                apply_color(self.output, data);
+                return
+            }
+            let from = (*data).color_temperature;
+            (*data).temperature_fade = Some(TemperatureFade::new(from, target, duration));
+            if (*data).color_timer.is_null() {
+                let event_loop = (*compositor::COMPOSITOR_PTR).event_loop;
+                (*data).color_timer = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                                    wl_event_loop_add_timer,
+                                                    event_loop,
+                                                    color_timer_notify,
+                                                    self.output as *mut c_void);
+            }
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_event_source_timer_update,
+                          (*data).color_timer,
+                          1);
+        }
+    }
+
+    /// Get the color temperature of this output in Kelvin, which is in
+    /// between values while fading.
+    pub fn color_temperature(&self) -> u32 {
+        unsafe {
+            let data = self.state;
+            if data.is_null() {
+                NEUTRAL_TEMPERATURE
+            } else {
+                (*data).color_temperature.round() as u32
+            }
+        }
+    }
+
+    /// Apply the calibration curves of the ICC profile at the path to this
+    /// output.
+    ///
+    /// See the `output::ColorTransform` docs for what of the profile is used.
+    pub fn set_icc_profile<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let transform = ColorTransform::from_icc_profile(path)?;
+        if self.set_color_transform(&transform) {
+            Ok(())
+        } else {
+            Err(io::Error::new(io::ErrorKind::Other, "The output does not support gamma ramps"))
+        }
+    }
+
     /// Sets the position of this output.
     pub fn set_position(&mut self, origin: Origin) {
         unsafe { wlr_output_set_position(self.output, origin.x, origin.y) }
@@ -398,11 +623,654 @@ impl Output {
         unsafe { wlr_output_set_scale(self.output, scale) }
     }
 
+    /// Set the maximum amount of time the compositor needs to render a frame
+    /// for this output.
+    ///
+    /// When set, the frame event is delayed until `max_render_time` before
+    /// the next expected vblank instead of being emitted right after the
+    /// previous one. This trades render headroom for lower latency.
+    ///
+    /// The next vblank is estimated from the refresh rate of the current mode,
+    /// so this has no effect on outputs that don't report one.
+    ///
+    /// Pass `None` to disable the delay (the default).
+    ///
+    /// This sets the timing of the frame pacing policy of the output to
+    /// `Timing::Vblank`, or back to `Timing::Immediate` for `None`.
+    pub fn set_max_render_time<T>(&mut self, max_render_time: T)
+        where T: Into<Option<Duration>>
+    {
+        unsafe {
+            let data = self.user_data();
+            if data.is_null() {
+                return
+            }
+            let max_render_time = max_render_time.into();
+            (*data).frame_pacing.timing = match max_render_time {
+                Some(_) => frame_pacing::Timing::Vblank,
+                None => frame_pacing::Timing::Immediate
+            };
+            (*data).max_render_time = max_render_time;
+        }
+    }
+
+    /// Get the maximum render time set with `set_max_render_time`, if any.
+    pub fn max_render_time(&self) -> Option<Duration> {
+        unsafe {
+            let data = self.state;
+            if data.is_null() {
+                None
+            } else {
+                (*data).max_render_time
+            }
+        }
+    }
+
+    /// Set when the frames of this output are rendered, trading latency for
+    /// throughput.
+    ///
+    /// With `Timing::Vblank` the max render time set with
+    /// `set_max_render_time` is kept, and used instead of the estimate.
+    pub fn set_frame_pacing(&mut self, policy: frame_pacing::Policy) {
+        unsafe {
+            let data = self.user_data();
+            if data.is_null() {
+                return
+            }
+            (*data).frame_pacing = policy;
+        }
+        if policy.buffering == frame_pacing::Buffering::Continuous {
+            self.schedule_frame();
+        }
+    }
+
+    /// Get the frame pacing policy of this output.
+    pub fn frame_pacing(&self) -> frame_pacing::Policy {
+        unsafe {
+            let data = self.state;
+            if data.is_null() {
+                frame_pacing::Policy::default()
+            } else {
+                (*data).frame_pacing
+            }
+        }
+    }
+
+    /// Allow the frames of this output to be presented as soon as they're
+    /// ready, when the surface set with `set_tearing_surface` asks for it with
+    /// the tearing control protocol (see `extensions::tearing_control`).
+    ///
+    /// It's off by default.
+    pub fn set_allow_tearing(&mut self, allow: bool) {
+        unsafe {
+            let data = self.user_data();
+            if !data.is_null() {
+                (*data).allow_tearing = allow
+            }
+        }
+    }
+
+    /// Determine if tearing was allowed with `set_allow_tearing`.
+    pub fn allow_tearing(&self) -> bool {
+        unsafe {
+            let data = self.state;
+            !data.is_null() && (*data).allow_tearing
+        }
+    }
+
+    /// Set the surface this output shows on its own (e.g. a fullscreen
+    /// game), whose presentation hint decides if the output tears.
+    ///
+    /// Pass `None` when the output shows more than a single surface.
+    pub fn set_tearing_surface(&mut self, surface: Option<&Surface>) {
+        unsafe {
+            let data = self.user_data();
+            if !data.is_null() {
+                (*data).tearing_surface = surface.map(|surface| surface.weak_reference())
+            }
+        }
+    }
+
+    /// Determine if the frames of this output are presented immediately:
+    /// tearing is allowed, and the tearing surface asks for it.
+    ///
+    /// The backends of this version of wlroots can't do asynchronous page
+    /// flips, so the output still flips on the vblank. What changes is the
+    /// frame pacing: the frame event isn't delayed, and the next frame is
+    /// scheduled as soon as the last one was submitted, so the latest frame
+    /// of the surface is always the one that's flipped to.
+    pub fn tearing(&self) -> bool {
+        unsafe {
+            let data = self.state;
+            if data.is_null() || !(*data).allow_tearing {
+                return false
+            }
+            match (*data).tearing_surface {
+                Some(ref surface) => wants_tearing(surface),
+                None => false
+            }
+        }
+    }
+
+    /// Only send frame callbacks to the views that can't be seen at the
+    /// interval, e.g. once a second.
+    ///
+    /// Clients draw a frame for every frame callback, so this keeps windows
+    /// hidden behind others, off screen or minimized from busying the CPU.
+    /// Pass `None` to send them every frame like visible views (the default).
+    ///
+    /// Only affects the frame callbacks sent with `send_frame_done` and
+    /// `send_frame_callbacks`. Views on no output at all are sent theirs at
+    /// this interval too, or once a second if it's `None`.
+    pub fn set_hidden_frame_interval<T>(&mut self, interval: T)
+        where T: Into<Option<Duration>>
+    {
+        unsafe {
+            let data = self.user_data();
+            if !data.is_null() {
+                (*data).frame_throttle.interval = interval.into()
+            }
+        }
+    }
+
+    /// Get the interval set with `set_hidden_frame_interval`, if any.
+    pub fn hidden_frame_interval(&self) -> Option<Duration> {
+        unsafe {
+            let data = self.state;
+            if data.is_null() {
+                None
+            } else {
+                (*data).frame_throttle.interval
+            }
+        }
+    }
+
+    /// Send the frame callbacks of the surfaces of the view, once the frame
+    /// was rendered.
+    ///
+    /// Views that aren't visible (e.g. minimized ones) are throttled to the
+    /// interval set with `set_hidden_frame_interval`.
+    pub fn send_frame_done(&mut self,
+                           view: &View,
+                           visible: bool,
+                           time: Duration)
+                           -> HandleResult<()> {
+        let send = unsafe {
+            let data = self.user_data();
+            data.is_null() || (*data).frame_throttle.should_send(view, visible, utils::now())
+        };
+        if !send {
+            return Ok(())
+        }
+        view.for_each_surface(|surface, _, _| {
+                                  surface.run(|surface| surface.send_frame_done(time)).ok();
+                              })
+    }
+
+    /// Send the frame callbacks of the views shown on this output, once the
+    /// frame was rendered.
+    ///
+    /// `stack` are the views with where they are in the layout, from bottom
+    /// to top. The views that are covered by the ones above them on this
+    /// output are throttled (see `View::is_visible`), and the views that
+    /// aren't on this output at all are left to the outputs they're on. Views
+    /// that aren't on any output are sent theirs by a timer, at the hidden
+    /// frame interval, until they're on an output again.
+    ///
+    /// Minimized views should be left out, and be sent theirs with
+    /// `send_frame_done`.
+    pub fn send_frame_callbacks(&mut self,
+                                layout: &mut layout::Layout,
+                                stack: &[(View, Origin)],
+                                time: Duration) {
+        let this = self.weak_reference();
+        if !layout.outputs().into_iter().any(|(output, _)| output == this) {
+            return
+        }
+        let area = layout.get_box(&mut *self);
+        let mut on_output = PixmanRegion::new();
+        on_output.rectangle(area.origin.x,
+                            area.origin.y,
+                            area.size.width as _,
+                            area.size.height as _);
+        let outputs = view::outputs_region(layout);
+        let interval = self.hidden_frame_interval();
+        for (index, &(ref view, origin)) in stack.iter().enumerate() {
+            let mut region = match view.surfaces_region(origin) {
+                Ok(region) => region,
+                Err(_) => continue
+            };
+            region.intersect(&outputs);
+            if region.is_empty() {
+                frame_pacing::park(view, interval);
+                continue
+            }
+            frame_pacing::unpark(view);
+            region.intersect(&on_output);
+            if region.is_empty() {
+                continue
+            }
+            view::occlude(&mut region, &stack[index + 1..]);
+            let visible = !region.is_empty();
+            self.send_frame_done(view, visible, time).ok();
+        }
+        unsafe {
+            let data = self.user_data();
+            if !data.is_null() {
+                (*data).frame_throttle.prune()
+            }
+        }
+    }
+
+    /// Get timing statistics about the frames recently rendered on this output.
+    pub fn frame_stats(&self) -> output::FrameStats {
+        unsafe {
+            let data = self.state;
+            if data.is_null() {
+                output::FrameStats::default()
+            } else {
+                (*data).frame_stats.clone()
+            }
+        }
+    }
+
+    /// Get when the frame event of the frame being rendered fired.
+    pub(crate) fn frame_time(&self) -> Option<Instant> {
+        unsafe {
+            let data = self.state;
+            if data.is_null() {
+                None
+            } else {
+                (*data).frame_stats.last_frame()
+            }
+        }
+    }
+
+    /// Clear the statistics returned by `frame_stats`.
+    pub fn reset_frame_stats(&mut self) {
+        unsafe {
+            let data = self.user_data();
+            if !data.is_null() {
+                (*data).frame_stats.reset()
+            }
+        }
+    }
+
+    /// Draw a bar graph of the recent render times on top of this output.
+    ///
+    /// The overlay is drawn by the `render::Renderer` right before the buffers
+    /// are swapped. Frames that took longer than the refresh period are
+    /// drawn in red.
+    pub fn set_frame_stats_overlay(&mut self, enabled: bool) {
+        unsafe {
+            let data = self.user_data();
+            if !data.is_null() {
+                (*data).frame_stats_overlay = enabled
+            }
+        }
+    }
+
+    /// Determines if the frame stats overlay is drawn on this output.
+    pub fn frame_stats_overlay(&self) -> bool {
+        unsafe {
+            let data = self.state;
+            !data.is_null() && (*data).frame_stats_overlay
+        }
+    }
+
+    /// Show the contents of `source` on this output, scaled to fit and
+    /// centered if the resolutions don't match.
+    ///
+    /// Every frame rendered to `source` is copied on the GPU and drawn on this output,
+    /// and `on_frame` isn't called for this output while it's a mirror. The
+    /// buffer of `source` is shown as is, it is not transformed again.
+    ///
+    /// The output should usually be removed from the output layout, so clients
+    /// and the cursor don't end up on it.
+    ///
+    /// Returns false if `source` is this output or a mirror itself.
+    pub fn set_mirror_of(&mut self, source: &mut Output) -> bool {
+        unsafe {
+            let (data, source_data) = (self.user_data(), source.user_data());
+            if data.is_null() || source_data.is_null() || data == source_data
+                || (*source_data).mirror_of.is_some() {
+                return false
+            }
+            (*data).mirror_of = Some(source.weak_reference());
+            let handle = self.weak_reference();
+            if !(*source_data).mirrors.contains(&handle) {
+                (*source_data).mirrors.push(handle);
+            }
+        }
+        source.schedule_frame();
+        true
+    }
+
+    /// Stop mirroring another output.
+    pub fn clear_mirror(&mut self) {
+        unsafe {
+            let data = self.user_data();
+            if !data.is_null() && (*data).mirror_of.take().is_some() {
+                self.schedule_frame();
+            }
+        }
+    }
+
+    /// Get the output this output is mirroring, if any.
+    pub fn mirror_of(&self) -> Option<Handle> {
+        unsafe {
+            let data = self.state;
+            if data.is_null() {
+                None
+            } else {
+                (*data).mirror_of.clone()
+            }
+        }
+    }
+
+    /// Draw the last frame of the output this one mirrors.
+    ///
+    /// Returns false if this output isn't a mirror, in which case the
+    /// frame should be rendered as usual.
+    pub(crate) unsafe fn render_mirror(&mut self) -> bool {
+        let data = self.user_data();
+        if data.is_null() {
+            return false
+        }
+        let source = match (*data).mirror_of.clone() {
+            Some(source) => source,
+            None => return false
+        };
+        let frame = source.run(|source| {
+            let source_data = source.user_data();
+            if source_data.is_null() {
+                (ptr::null_mut(), false)
+            } else {
+                ((*source_data).mirror_texture, (*source_data).mirror_y_invert)
+            }
+        });
+        let (texture, y_invert) = match frame {
+            Ok(frame) => frame,
+            Err(HandleErr::AlreadyDropped) => {
+                (*data).mirror_of = None;
+                return false
+            },
+            // NOTE The source is being rendered right now, it will schedule
+            // another frame once it's done.
+            Err(HandleErr::AlreadyBorrowed { .. }) => return true
+        };
+        if compositor::COMPOSITOR_PTR.is_null() {
+            return true
+        }
+        let generic_renderer = match (*compositor::COMPOSITOR_PTR).renderer.as_mut() {
+            Some(renderer) => renderer,
+            None => return true
+        };
+        let (width, height) = self.transformed_resolution();
+        let mut renderer = match generic_renderer.render(self, None) {
+            Ok(renderer) => renderer,
+            Err(err) => {
+                wlr_log!(WLR_ERROR, "Could not render mirror: {}", err);
+                return true
+            }
+        };
+        renderer.clear([0.0, 0.0, 0.0, 1.0]);
+        if texture.is_null() {
+            return true
+        }
+        let texture = Texture::from_ptr(texture);
+        let (source_width, source_height) = texture.size();
+        if source_width <= 0 || source_height <= 0 {
+            return true
+        }
+        let scale = (width as f64 / source_width as f64).min(height as f64 / source_height as f64);
+        let size = Size::new((source_width as f64 * scale).round() as c_int,
+                             (source_height as f64 * scale).round() as c_int);
+        let origin = Origin::new((width - size.width) / 2, (height - size.height) / 2);
+        let transform = if y_invert {
+            wl_output_transform::WL_OUTPUT_TRANSFORM_FLIPPED_180
+        } else {
+            wl_output_transform::WL_OUTPUT_TRANSFORM_NORMAL
+        };
+        let matrix = matrix::project_box(Area::new(origin, size),
+                                         transform,
+                                         0.0,
+                                         renderer.output.transform_matrix());
+        renderer.render_texture_with_matrix(&texture, matrix);
+        true
+    }
+
+    /// Destroy the copy of the last frame kept for the mirrors.
+    ///
+    /// It's only forgotten if the renderer is already gone (e.g. the output
+    /// is destroyed while the compositor shuts down), as that took the
+    /// texture with it.
+    pub(crate) unsafe fn release_mirror_texture(&mut self) {
+        let data = self.user_data();
+        if !data.is_null() && !(*data).mirror_texture.is_null() {
+            let alive = (*data).mirror_renderer.take().map_or(false, |renderer| renderer.get());
+            if alive {
+                wlr_texture_destroy((*data).mirror_texture);
+            }
+            (*data).mirror_texture = ptr::null_mut();
+        }
+    }
+
+    /// Add a render pass that is run every time this output is rendered to,
+    /// after the passes that were added before it.
+    pub fn add_render_pass(&mut self, pass: Box<RenderPass>) {
+        unsafe {
+            let data = self.user_data();
+            if !data.is_null() {
+                (*data).render_passes.push(pass)
+            }
+        }
+    }
+
+    /// Remove all of the render passes of this output.
+    pub fn clear_render_passes(&mut self) {
+        unsafe {
+            let data = self.user_data();
+            if !data.is_null() {
+                (*data).render_passes.clear()
+            }
+        }
+    }
+
+    /// Take the render passes out of the output while they run, as they get
+    /// the output through the renderer.
+    pub(crate) fn take_render_passes(&mut self) -> Vec<Box<RenderPass>> {
+        unsafe {
+            let data = self.user_data();
+            if data.is_null() {
+                return Vec::new()
+            }
+            mem::replace(&mut (*data).render_passes, Vec::new())
+        }
+    }
+
+    /// Put the render passes back, keeping any that were added while they
+    /// ran after them.
+    pub(crate) fn restore_render_passes(&mut self, mut passes: Vec<Box<RenderPass>>) {
+        unsafe {
+            let data = self.user_data();
+            if !data.is_null() {
+                passes.extend((*data).render_passes.drain(..));
+                (*data).render_passes = passes
+            }
+        }
+    }
+
+    /// The refresh period of the current mode, if the output reports one.
+    pub(crate) fn refresh_period(&self) -> Option<Duration> {
+        let refresh = self.refresh_rate();
+        if refresh <= 0 {
+            return None
+        }
+        // Refresh rate is in mHz.
+        Some(Duration::from_nanos(1_000_000_000_000 / refresh as u64))
+    }
+
+    /// How long to wait after a frame event before telling the user about it,
+    /// based on the frame pacing policy and refresh rate.
+    ///
+    /// Returns `None` if the frame should be rendered immediately.
+    pub(crate) fn render_delay(&self) -> Option<Duration> {
+        unsafe {
+            let data = self.state;
+            if data.is_null() {
+                return None
+            }
+            if self.tearing() {
+                (*data).render_delay = None;
+                return None
+            }
+            let delay = (*data).frame_pacing.render_delay(self.refresh_period(),
+                                                          (*data).max_render_time,
+                                                          &(*data).frame_stats,
+                                                          (*data).render_delay);
+            (*data).render_delay = delay;
+            delay
+        }
+    }
+
+    /// Called once a frame was submitted, to keep the output busy with
+    /// continuous buffering or while it tears.
+    pub(crate) fn frame_submitted(&mut self) {
+        let continuous = self.frame_pacing().buffering == frame_pacing::Buffering::Continuous;
+        if continuous || self.tearing() {
+            self.schedule_frame();
+        }
+    }
+
+    /// Determines if the output has been destroyed (e.g. unplugged) while
+    /// this reference to it was held.
+    ///
+    /// Nothing but dropping the `Output` is safe once this is true. This can
+    /// only happen if the output is destroyed by something done while the
+    /// output is borrowed, e.g. destroying a headless output.
+    pub fn is_destroyed(&self) -> bool {
+        unsafe { !self.state.is_null() && (*self.state).destroyed }
+    }
+
+    /// Record that the `wlr_output` is gone, so dropping this doesn't touch it.
+    pub(crate) unsafe fn mark_destroyed(&mut self) {
+        if !self.state.is_null() {
+            (*self.state).destroyed = true;
+        }
+    }
+
     pub fn damage(&mut self) -> &mut output::Damage {
         &mut *self.damage
     }
 }
 
+/// Copy the frame being rendered for the mirrors of the output, and schedule
+/// a frame on them.
+pub(crate) unsafe fn capture_for_mirrors(renderer: &mut Renderer) {
+    let handle = renderer.output.weak_reference();
+    let data = renderer.output.user_data();
+    if data.is_null() || (*data).mirrors.is_empty() {
+        return
+    }
+    (*data).mirrors.retain(|mirror| {
+        match mirror.run(|mirror| mirror.mirror_of().as_ref() == Some(&handle)) {
+            Ok(mirroring) => mirroring,
+            Err(HandleErr::AlreadyDropped) => false,
+            Err(HandleErr::AlreadyBorrowed { .. }) => true
+        }
+    });
+    if (*data).mirrors.is_empty() {
+        renderer.output.release_mirror_texture();
+        return
+    }
+    let (width, height) = renderer.output.size();
+    if width <= 0 || height <= 0 {
+        return
+    }
+    // NOTE The texture is only uploaded when the size of the output changes,
+    // the frames are copied into it on the GPU.
+    let current = (*data).mirror_texture;
+    if current.is_null() || Texture::from_ptr(current).size() != (width, height) {
+        renderer.output.release_mirror_texture();
+        let stride = width as u32 * 4;
+        let pixels = vec![0; stride as usize * height as usize];
+        let texture = renderer.create_texture_from_pixels(wl_shm_format::WL_SHM_FORMAT_ABGR8888,
+                                                          stride,
+                                                          width as u32,
+                                                          height as u32,
+                                                          &pixels);
+        match texture {
+            Some(texture) => {
+                (*data).mirror_texture = texture.as_ptr();
+                (*data).mirror_renderer = Some(renderer.liveliness());
+            },
+            None => return
+        }
+    }
+    if !Texture::from_ptr((*data).mirror_texture).copy_framebuffer(width, height) {
+        wlr_log!(WLR_ERROR, "Could not copy the frame for the mirrors");
+        renderer.output.release_mirror_texture();
+        return
+    }
+    (*data).mirror_y_invert = true;
+    for mirror in &(*data).mirrors {
+        mirror.run(|mirror| mirror.schedule_frame()).ok();
+    }
+}
+
+/// Determine if the surface is alive and asks for its frames to tear.
+#[cfg(feature = "tearing_control")]
+fn wants_tearing(surface: &surface::Handle) -> bool {
+    // NOTE The id is 0 once the surface is gone, the hint is only looked up
+    // by the address of the surface.
+    surface.id() != 0
+    && tearing_control::current(unsafe { surface.as_ptr() }) == PresentationHint::Async
+}
+
+#[cfg(not(feature = "tearing_control"))]
+fn wants_tearing(_: &surface::Handle) -> bool {
+    false
+}
+
+/// Set the gamma ramps of the output from its color transform and temperature.
+unsafe fn apply_color(output: *mut wlr_output, data: *mut OutputState) -> bool {
+    let size = wlr_output_get_gamma_size(output);
+    if size == 0 {
+        return false
+    }
+    let (red, green, blue) = (*data).color_transform.ramps(size, (*data).color_temperature);
+    if red.len() != size || green.len() != size || blue.len() != size {
+        return false
+    }
+    wlr_output_set_gamma(output, size, red.as_ptr(), green.as_ptr(), blue.as_ptr())
+}
+
+/// Step through the color temperature fade of the output.
+unsafe extern "C" fn color_timer_notify(data: *mut c_void) -> c_int {
+    let output = data as *mut wlr_output;
+    let data = (*output).data as *mut OutputState;
+    if data.is_null() {
+        return 0
+    }
+    let fade = match (*data).temperature_fade {
+        Some(fade) => fade,
+        None => return 0
+    };
+    (*data).color_temperature = fade.current();
+    apply_color(output, data);
+    if fade.finished() {
+        (*data).color_temperature = fade.target();
+        (*data).temperature_fade = None;
+    } else {
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_event_source_timer_update,
+                      (*data).color_timer,
+                      COLOR_FADE_STEP_MS);
+    }
+    0
+}
+
 impl Drop for Output {
     fn drop(&mut self) {
         // NOTE
@@ -414,6 +1282,7 @@ impl Drop for Output {
         // that is handled automatically by the listeners in wlroots.
         if Rc::strong_count(&self.liveliness) == 1 {
             wlr_log!(WLR_DEBUG, "Dropped output {:p}", self.output);
+            utils::user_data::clear(self.output);
             let weak_count = Rc::weak_count(&self.liveliness);
             if weak_count > 0 {
                 wlr_log!(WLR_DEBUG,
@@ -427,7 +1296,11 @@ impl Drop for Output {
         // TODO Move back up in the some after NLL is a thing.
         unsafe {
             self.remove_from_output_layout();
-            let _ = Box::from_raw((*self.output).data as *mut OutputState);
+            self.release_mirror_texture();
+            let state = Box::from_raw(self.state);
+            if !state.destroyed {
+                (*self.output).data = ptr::null_mut();
+            }
         }
     }
 }
@@ -435,13 +1308,13 @@ impl Drop for Output {
 impl Handleable<*mut wlr_output_damage, wlr_output> for Output {
     #[doc(hidden)]
     unsafe fn from_ptr(ptr: *mut wlr_output) -> Self where Self: Sized {
-        let data = Box::from_raw((*ptr).data as *mut OutputState);
-        let handle = data.handle.clone();
-        let damage = data.damage;
-        (*ptr).data = Box::into_raw(data) as *mut _;
+        let state = (*ptr).data as *mut OutputState;
+        let handle = (*state).handle.clone();
+        let damage = (*state).damage;
         Output { liveliness: handle.upgrade().unwrap(),
                  damage: ManuallyDrop::new(output::Damage::from_ptr(damage)),
-                 output: ptr}
+                 output: ptr,
+                 state }
 
     }
 
@@ -457,7 +1330,8 @@ impl Handleable<*mut wlr_output_damage, wlr_output> for Output {
             .ok_or_else(|| HandleErr::AlreadyDropped)?;
         Ok(Output { liveliness,
                     damage: ManuallyDrop::new(output::Damage::from_ptr(handle.data)),
-                    output: handle.as_ptr() })
+                    output: handle.as_ptr(),
+                    state: (*handle.as_ptr()).data as *mut OutputState })
     }
 
     fn weak_reference(&self) -> Handle {
@@ -0,0 +1,348 @@
+//! Changing the configuration of several outputs at once, all or nothing.
+//!
+//! The changes are staged on an `OutputConfigurationTransaction` and checked
+//! before any of them is applied. If the backend then refuses one of them
+//! (e.g. a mode the monitor doesn't take), the outputs that were already
+//! changed are put back the way they were:
+//!
+//! ```rust,no_run,ignore
+//! let mut transaction = OutputConfigurationTransaction::new();
+//! transaction.set_mode(&laptop, Size::new(1920, 1080), None)
+//!            .set_scale(&laptop, 1.5)
+//!            .set_position(&monitor, Origin::new(1280, 0))
+//!            .enable(&projector, false);
+//! if let Err(err) = transaction.commit(layout) {
+//!     wlr_log!(WLR_ERROR, "Could not configure the outputs: {}", err);
+//! }
+//! ```
+//!
+//! This is what the apply requests of output management clients should go
+//! through, so they can't leave the outputs half configured.
+//!
+//! This version of wlroots can't test a configuration on the hardware
+//! without applying it, so only what can be known up front (the outputs are
+//! still there, the modes are ones they have, the scales are valid) is
+//! checked before committing, and the rest is undone if it fails.
+
+use std::{error::Error, fmt};
+
+use libc::c_float;
+use wlroots_sys::wlr_output_mode;
+
+use {area::{Origin, Size},
+     output::{self, layout::Layout, Output, Transform},
+     utils::Handleable};
+
+/// A mode to set on an output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModeChoice {
+    pub size: Size,
+    /// The refresh rate in mHz, or `None` for the highest one the output has
+    /// at the size.
+    pub refresh: Option<i32>
+}
+
+/// The changes staged for an output. Whatever is `None` is left as it is.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OutputChanges {
+    pub enabled: Option<bool>,
+    pub mode: Option<ModeChoice>,
+    /// Where the output goes in the output layout, adding it if it's not in
+    /// it yet.
+    pub position: Option<Origin>,
+    pub scale: Option<c_float>,
+    pub transform: Option<Transform>
+}
+
+/// The ways committing an `OutputConfigurationTransaction` can fail.
+///
+/// The outputs are named by their name (e.g. `DP-1`), if it's known. None of
+/// the changes are applied when committing fails.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum ConfigurationError {
+    /// One of the outputs was destroyed (e.g. unplugged).
+    OutputGone,
+    /// The mode isn't one the output has, and it doesn't take custom modes.
+    InvalidMode(String),
+    /// The scale isn't a positive number.
+    InvalidScale(String),
+    /// The backend refused a change to the output.
+    Rejected(String)
+}
+
+impl fmt::Display for ConfigurationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigurationError::OutputGone => write!(f, "an output was destroyed"),
+            ConfigurationError::InvalidMode(ref name) => {
+                write!(f, "output {} has no such mode", name)
+            },
+            ConfigurationError::InvalidScale(ref name) => {
+                write!(f, "invalid scale for output {}", name)
+            },
+            ConfigurationError::Rejected(ref name) => {
+                write!(f, "output {} rejected the configuration", name)
+            }
+        }
+    }
+}
+
+impl Error for ConfigurationError {
+    fn description(&self) -> &str {
+        match *self {
+            ConfigurationError::OutputGone => "An output was destroyed",
+            ConfigurationError::InvalidMode(_) => "The output has no such mode",
+            ConfigurationError::InvalidScale(_) => "Invalid output scale",
+            ConfigurationError::Rejected(_) => "The output rejected the configuration"
+        }
+    }
+}
+
+/// Changes to the configuration of several outputs, applied all at once with
+/// `commit`.
+#[derive(Debug, Clone, Default)]
+pub struct OutputConfigurationTransaction {
+    changes: Vec<(output::Handle, OutputChanges)>
+}
+
+/// How an output was before the transaction changed it.
+struct Saved {
+    output: output::Handle,
+    enabled: bool,
+    mode: SavedMode,
+    scale: c_float,
+    transform: Transform,
+    position: Option<Origin>
+}
+
+enum SavedMode {
+    None,
+    Listed(*mut wlr_output_mode),
+    Custom(Size, i32)
+}
+
+impl OutputConfigurationTransaction {
+    /// Start with no changes.
+    pub fn new() -> Self {
+        OutputConfigurationTransaction { changes: Vec::new() }
+    }
+
+    /// Get the changes staged for the output, if there are any.
+    pub fn changes(&self, output: &output::Handle) -> Option<&OutputChanges> {
+        self.changes
+            .iter()
+            .find(|&&(ref other, _)| other == output)
+            .map(|&(_, ref changes)| changes)
+    }
+
+    /// Stage all the changes for the output at once.
+    pub fn set_changes(&mut self, output: &output::Handle, changes: OutputChanges) -> &mut Self {
+        *self.changes_mut(output) = changes;
+        self
+    }
+
+    /// Stage enabling or disabling the output.
+    pub fn enable(&mut self, output: &output::Handle, enabled: bool) -> &mut Self {
+        self.changes_mut(output).enabled = Some(enabled);
+        self
+    }
+
+    /// Stage a mode for the output.
+    ///
+    /// If the output has no such mode, it's set as a custom mode if the
+    /// output takes those.
+    pub fn set_mode(&mut self,
+                    output: &output::Handle,
+                    size: Size,
+                    refresh: Option<i32>)
+                    -> &mut Self {
+        self.changes_mut(output).mode = Some(ModeChoice { size, refresh });
+        self
+    }
+
+    /// Stage moving the output in the output layout.
+    pub fn set_position(&mut self, output: &output::Handle, origin: Origin) -> &mut Self {
+        self.changes_mut(output).position = Some(origin);
+        self
+    }
+
+    /// Stage a scale for the output.
+    pub fn set_scale(&mut self, output: &output::Handle, scale: c_float) -> &mut Self {
+        self.changes_mut(output).scale = Some(scale);
+        self
+    }
+
+    /// Stage a transform for the output.
+    pub fn set_transform(&mut self, output: &output::Handle, transform: Transform) -> &mut Self {
+        self.changes_mut(output).transform = Some(transform);
+        self
+    }
+
+    /// Check what can be checked about the changes without applying them.
+    pub fn test(&self) -> Result<(), ConfigurationError> {
+        for &(ref output, ref changes) in &self.changes {
+            output.run(|output| check(output, changes))
+                  .map_err(|_| ConfigurationError::OutputGone)??;
+        }
+        Ok(())
+    }
+
+    /// Apply the changes, or none of them.
+    ///
+    /// The outputs are changed in the order they were first staged in. If
+    /// one of them can't be, the ones changed before it are put back the
+    /// way they were.
+    pub fn commit(self, layout: &mut Layout) -> Result<(), ConfigurationError> {
+        self.test()?;
+        let mut applied: Vec<Saved> = Vec::with_capacity(self.changes.len());
+        for &(ref output, ref changes) in &self.changes {
+            let res = output.run(|output| {
+                                     applied.push(save(output, layout));
+                                     apply(output, changes, layout)
+                                 })
+                            .unwrap_or(Err(ConfigurationError::OutputGone));
+            if let Err(err) = res {
+                for saved in applied.into_iter().rev() {
+                    restore(saved, layout)
+                }
+                return Err(err)
+            }
+        }
+        Ok(())
+    }
+
+    fn changes_mut(&mut self, output: &output::Handle) -> &mut OutputChanges {
+        let index = match self.changes.iter().position(|&(ref other, _)| other == output) {
+            Some(index) => index,
+            None => {
+                self.changes.push((output.clone(), OutputChanges::default()));
+                self.changes.len() - 1
+            }
+        };
+        &mut self.changes[index].1
+    }
+}
+
+fn check(output: &Output, changes: &OutputChanges) -> Result<(), ConfigurationError> {
+    if let Some(scale) = changes.scale {
+        if !(scale.is_finite() && scale > 0.0) {
+            return Err(ConfigurationError::InvalidScale(output.name()))
+        }
+    }
+    if let Some(mode) = changes.mode {
+        let invalid = mode.size.width <= 0
+                      || mode.size.height <= 0
+                      || (!takes_custom_modes(output) && find_mode(output, mode).is_none());
+        if invalid {
+            return Err(ConfigurationError::InvalidMode(output.name()))
+        }
+    }
+    Ok(())
+}
+
+/// Determine if modes other than the ones the output lists can be set.
+///
+/// Monitors with a mode list only take the modes from it, while the outputs
+/// of the nested and headless backends have none and take any size.
+fn takes_custom_modes(output: &Output) -> bool {
+    output.modes().is_empty()
+}
+
+/// Find the mode of the output that fits the choice best.
+fn find_mode(output: &Output, choice: ModeChoice) -> Option<*mut wlr_output_mode> {
+    output.modes()
+          .into_iter()
+          .filter(|mode| mode.dimensions() == (choice.size.width, choice.size.height))
+          .filter(|mode| choice.refresh.map_or(true, |refresh| mode.refresh() == refresh))
+          .max_by_key(|mode| mode.refresh())
+          .map(|mode| unsafe { mode.as_ptr() })
+}
+
+fn apply(output: &mut Output,
+         changes: &OutputChanges,
+         layout: &mut Layout)
+         -> Result<(), ConfigurationError> {
+    if let Some(enabled) = changes.enabled {
+        if !output.enable(enabled) {
+            return Err(ConfigurationError::Rejected(output.name()))
+        }
+    }
+    if let Some(choice) = changes.mode {
+        let set = match find_mode(output, choice) {
+            Some(mode) => output.set_mode(unsafe { output::Mode::new(mode) }),
+            None => output.set_custom_mode(choice.size, choice.refresh.unwrap_or(0))
+        };
+        if !set {
+            return Err(ConfigurationError::Rejected(output.name()))
+        }
+    }
+    if let Some(scale) = changes.scale {
+        output.set_scale(scale)
+    }
+    if let Some(transform) = changes.transform {
+        output.transform(transform)
+    }
+    if let Some(origin) = changes.position {
+        if position_in(output, layout).is_some() {
+            layout.move_output(output, origin)
+        } else {
+            layout.add(output, origin)
+        }
+    }
+    Ok(())
+}
+
+fn save(output: &mut Output, layout: &mut Layout) -> Saved {
+    let mode = match output.current_mode() {
+        Some(mode) => SavedMode::Listed(unsafe { mode.as_ptr() }),
+        None => {
+            let (width, height) = output.size();
+            if width > 0 && height > 0 {
+                SavedMode::Custom(Size::new(width, height), output.refresh_rate())
+            } else {
+                SavedMode::None
+            }
+        }
+    };
+    Saved { output: output.weak_reference(),
+            enabled: output.enabled(),
+            mode,
+            scale: output.scale(),
+            transform: output.get_transform(),
+            position: position_in(output, layout) }
+}
+
+/// Put the output back the way it was, as far as the backend lets it.
+fn restore(saved: Saved, layout: &mut Layout) {
+    let Saved { output, enabled, mode, scale, transform, position } = saved;
+    output.run(|output| {
+                   output.enable(enabled);
+                   match mode {
+                       SavedMode::Listed(mode) => {
+                           output.set_mode(unsafe { output::Mode::new(mode) });
+                       },
+                       SavedMode::Custom(size, refresh) => {
+                           output.set_custom_mode(size, refresh);
+                       },
+                       SavedMode::None => {}
+                   }
+                   output.set_scale(scale);
+                   output.transform(transform);
+                   match (position, position_in(output, layout)) {
+                       (Some(origin), Some(_)) => layout.move_output(output, origin),
+                       (Some(origin), None) => layout.add(output, origin),
+                       (None, Some(_)) => layout.remove(output),
+                       (None, None) => {}
+                   }
+               })
+          .ok();
+}
+
+/// Get where the output is in the layout, if it's in it.
+fn position_in(output: &Output, layout: &mut Layout) -> Option<Origin> {
+    let handle = output.weak_reference();
+    layout.outputs()
+          .into_iter()
+          .find(|&(ref other, _)| *other == handle)
+          .map(|(_, origin)| origin)
+}
@@ -0,0 +1,143 @@
+//! Assigning input devices to seats, for multi-seat setups (e.g. a keyboard
+//! and mouse for each user of the same machine).
+//!
+//! Give the input manager a `SeatForDevice` callback with
+//! `input::manager::Builder::seat_for_device`, and every new device is
+//! attached to the seat with the name it returns. Devices can also be moved
+//! between seats by hand with `Seat::attach_device` and `Seat::detach_device`.
+//!
+//! The capabilities of a seat follow the devices attached to it, and the
+//! keyboard attached last is the active keyboard of the seat. Unplugged
+//! devices are detached automatically.
+
+use std::{cell::RefCell, collections::HashMap};
+
+use libc;
+use wayland_sys::server::{signal::wl_signal_add, WAYLAND_SERVER_HANDLE};
+use wlroots_sys::{wlr_input_device, wlr_seat, wlr_seat_set_capabilities,
+                  wlr_input_device_type::*};
+
+use {compositor, input, seat::{self, Capability}};
+
+/// Callback that decides which seat a new input device is attached to.
+///
+/// Return the name of the seat (as given to `Seat::create`), or `None` to not
+/// attach the device to any seat.
+pub type SeatForDevice = fn(compositor_handle: compositor::Handle,
+                            device: &input::Device)
+                            -> Option<String>;
+
+thread_local! {
+    /// The devices attached to each seat, by seat pointer.
+    static ATTACHED: RefCell<HashMap<usize, Vec<*mut DeviceAssignment>>> =
+        RefCell::new(HashMap::new());
+}
+
+wayland_listener!(pub(crate) DeviceAssignment, (*mut wlr_input_device, *mut wlr_seat), [
+    destroy_listener => destroy_notify: |this: &mut DeviceAssignment, _data: *mut libc::c_void,|
+    unsafe {
+        let (device, seat) = this.data;
+        detach(seat, device);
+    };
+]);
+
+/// Attach the device to the seat, detaching it from any other seat first.
+pub(crate) unsafe fn attach(seat: *mut wlr_seat, device: *mut wlr_input_device) {
+    if let Some(current) = seat_of(device) {
+        if current == seat {
+            return
+        }
+        detach(current, device);
+    }
+    let mut assignment = DeviceAssignment::new((device, seat));
+    wl_signal_add(&mut (*device).events.destroy as *mut _ as _,
+                  assignment.destroy_listener() as *mut _ as _);
+    let assignment = Box::into_raw(assignment);
+    ATTACHED.with(|attached| {
+                      attached.borrow_mut()
+                              .entry(seat as usize)
+                              .or_insert_with(Vec::new)
+                              .push(assignment)
+                  });
+    if (*device).type_ == WLR_INPUT_DEVICE_KEYBOARD {
+        seat::set_keyboard(seat, device);
+    }
+    update_capabilities(seat);
+}
+
+/// Detach the device from the seat, if it's attached to it.
+pub(crate) unsafe fn detach(seat: *mut wlr_seat, device: *mut wlr_input_device) {
+    let assignment = ATTACHED.with(|attached| {
+        let mut attached = attached.borrow_mut();
+        let assignments = attached.get_mut(&(seat as usize))?;
+        let index = assignments.iter().position(|assignment| (**assignment).data.0 == device)?;
+        Some(assignments.remove(index))
+    });
+    let assignment = match assignment {
+        Some(assignment) => Box::from_raw(assignment),
+        None => return
+    };
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_list_remove,
+                  &mut (*assignment.destroy_listener()).link as *mut _ as _);
+    if (*device).type_ == WLR_INPUT_DEVICE_KEYBOARD {
+        seat::keyboard_detached(seat, device);
+    }
+    if (*device).type_ == WLR_INPUT_DEVICE_KEYBOARD &&
+       (*seat).keyboard_state.keyboard == (*device).__bindgen_anon_1.keyboard {
+        // NOTE Fall back to the keyboard that was attached before it.
+        let fallback = devices(seat).into_iter()
+                                    .rev()
+                                    .find(|device| (**device).type_ == WLR_INPUT_DEVICE_KEYBOARD)
+                                    .unwrap_or(::std::ptr::null_mut());
+        seat::set_keyboard(seat, fallback);
+    }
+    update_capabilities(seat);
+}
+
+/// Detach all of the devices from the seat, which is being destroyed.
+pub(crate) unsafe fn detach_all(seat: *mut wlr_seat) {
+    let assignments = ATTACHED.with(|attached| attached.borrow_mut().remove(&(seat as usize)));
+    for assignment in assignments.unwrap_or_default() {
+        let assignment = Box::from_raw(assignment);
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_list_remove,
+                      &mut (*assignment.destroy_listener()).link as *mut _ as _);
+    }
+}
+
+/// Get the devices attached to the seat, in the order they were attached.
+pub(crate) unsafe fn devices(seat: *mut wlr_seat) -> Vec<*mut wlr_input_device> {
+    ATTACHED.with(|attached| {
+        attached.borrow()
+                .get(&(seat as usize))
+                .map(|assignments| {
+                         assignments.iter().map(|assignment| (**assignment).data.0).collect()
+                     })
+                .unwrap_or_default()
+    })
+}
+
+/// Get the seat the device is attached to, if any.
+pub(crate) unsafe fn seat_of(device: *mut wlr_input_device) -> Option<*mut wlr_seat> {
+    ATTACHED.with(|attached| {
+        attached.borrow()
+                .values()
+                .flat_map(|assignments| assignments.iter())
+                .find(|assignment| (***assignment).data.0 == device)
+                .map(|assignment| (**assignment).data.1)
+    })
+}
+
+unsafe fn update_capabilities(seat: *mut wlr_seat) {
+    let mut capabilities = Capability::empty();
+    for device in devices(seat) {
+        capabilities |= match (*device).type_ {
+            WLR_INPUT_DEVICE_KEYBOARD => Capability::Keyboard,
+            WLR_INPUT_DEVICE_POINTER | WLR_INPUT_DEVICE_TABLET_TOOL => Capability::Pointer,
+            WLR_INPUT_DEVICE_TOUCH => Capability::Touch,
+            WLR_INPUT_DEVICE_TABLET_PAD => Capability::empty()
+        };
+    }
+    wlr_seat_set_capabilities(seat, capabilities.bits());
+}
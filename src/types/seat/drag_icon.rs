@@ -1,8 +1,8 @@
-use std::{cell::Cell, rc::{Rc, Weak}, hash::{Hash, Hasher}, panic};
+use std::{cell::Cell, rc::{Rc, Weak}, hash::{Hash, Hasher}, panic::{self, Location}};
 
 use wlroots_sys::wlr_drag_icon;
 
-use {surface, utils::{HandleErr, HandleResult}};
+use {surface, utils::{self, HandleErr, HandleResult}};
 pub use manager::drag_icon_handler::*;
 
 #[derive(Debug)]
@@ -108,7 +108,9 @@ impl Handle {
         }
     }
 
+    #[track_caller]
     pub(crate) unsafe fn upgrade(&self) -> HandleResult<DragIcon> {
+        let location = Location::caller();
         self.handle.upgrade()
             .ok_or(HandleErr::AlreadyDropped)
             // NOTE
@@ -117,13 +119,14 @@ impl Handle {
             .and_then(|check| {
                 let drag_icon = DragIcon::from_handle(self)?;
                 if check.get() {
-                    return Err(HandleErr::AlreadyBorrowed)
+                    return Err(utils::already_borrowed::<DragIcon>(&check))
                 }
-                check.set(true);
+                utils::borrow_lock(&check, location);
                 Ok(drag_icon)
             })
     }
 
+    #[track_caller]
     pub fn run<F, R>(&self, runner: F) -> HandleResult<R>
         where F: FnOnce(&mut DragIcon) -> R
     {
@@ -138,7 +141,7 @@ impl Handle {
                                                    drag_icon);
                                           panic!("Lock in incorrect state!");
                                       }
-                                      check.set(false);
+                                      utils::release_lock(&check);
                                   });
         match res {
             Ok(res) => Ok(res),
@@ -1,4 +1,32 @@
-use wlroots_sys::{wlr_seat_keyboard_grab, wlr_seat_pointer_grab, wlr_seat_touch_grab};
+//! Grabs of the input of a seat.
+//!
+//! The `Pointer`, `Keyboard` and `Touch` types are the grabs wlroots reports
+//! through `seat::Handler`, e.g. the ones of drags.
+//!
+//! Compositors can take a grab of their own by implementing `PointerGrab`,
+//! `KeyboardGrab` or `TouchGrab` and passing it to `Seat::start_pointer_grab`,
+//! `Seat::start_keyboard_grab` or `Seat::start_touch_grab`. While the grab is
+//! active, the events the seat is notified of (e.g. with
+//! `Seat::pointer_notify_motion`) go to it instead of the focused client,
+//! which is how interactive moves and resizes, drag and drop and popup menus
+//! keep the input to themselves. The methods send the events on to the
+//! focused client by default, so a grab only overrides the events it's
+//! interested in.
+
+use std::{any::Any, cell::{Cell, RefCell}, mem, slice, time::Duration};
+
+use libc::{c_void, size_t};
+use xkbcommon::xkb::Keycode;
+use wlroots_sys::{wlr_axis_orientation, wlr_axis_source, wlr_keyboard_grab_interface,
+                  wlr_keyboard_modifiers, wlr_pointer_grab_interface, wlr_seat,
+                  wlr_seat_keyboard_enter, wlr_seat_keyboard_grab, wlr_seat_pointer_enter,
+                  wlr_seat_pointer_grab, wlr_seat_touch_grab, wlr_seat_touch_send_down,
+                  wlr_surface, wlr_touch_grab_interface, wlr_touch_point};
+
+use {KeyboardModifiers,
+     seat::{self, Seat, TouchPoint},
+     surface::Surface,
+     utils::Handleable};
 
 pub struct Pointer {
     grab: *mut wlr_seat_pointer_grab
@@ -44,3 +72,459 @@ impl Touch {
         Touch { grab }
     }
 }
+
+/// A grab of the pointer of a seat, started with `Seat::start_pointer_grab`.
+///
+/// Coordinates are surface-local, like the ones the seat was notified of.
+#[allow(unused_variables)]
+pub trait PointerGrab {
+    /// The seat was notified that the pointer entered the surface.
+    fn enter(&mut self, seat: &Seat, surface: &mut Surface, sx: f64, sy: f64) {
+        seat.pointer_enter(surface, sx, sy)
+    }
+
+    /// The seat was notified of pointer motion.
+    fn motion(&mut self, seat: &Seat, time: Duration, sx: f64, sy: f64) {
+        seat.send_motion(time, sx, sy)
+    }
+
+    /// The seat was notified of a button press or release.
+    ///
+    /// Returns the serial of the button event that was sent, or zero if none
+    /// was sent.
+    fn button(&mut self, seat: &Seat, time: Duration, button: u32, state: u32) -> u32 {
+        seat.send_button(time, button, state)
+    }
+
+    /// The seat was notified of an axis event.
+    fn axis(&mut self,
+            seat: &Seat,
+            time: Duration,
+            orientation: wlr_axis_orientation,
+            value: f64,
+            value_discrete: i32,
+            source: wlr_axis_source) {
+        seat.send_axis(time, orientation, value, value_discrete, source)
+    }
+
+    /// The grab was ended with `Seat::end_grab`, or by the seat itself.
+    fn cancel(&mut self, seat: &Seat) {}
+}
+
+/// A grab of the keyboard of a seat, started with `Seat::start_keyboard_grab`.
+#[allow(unused_variables)]
+pub trait KeyboardGrab {
+    /// The seat was notified that the keyboard focus moved to the surface.
+    fn enter(&mut self,
+             seat: &Seat,
+             surface: &mut Surface,
+             keycodes: &mut [Keycode],
+             modifiers: &mut KeyboardModifiers) {
+        seat.keyboard_enter(surface, keycodes, modifiers)
+    }
+
+    /// The seat was notified of a key press or release.
+    fn key(&mut self, seat: &Seat, time: Duration, key: u32, state: u32) {
+        seat.keyboard_send_key(time, key, state)
+    }
+
+    /// The seat was notified that the modifiers changed.
+    fn modifiers(&mut self, seat: &Seat, modifiers: &mut KeyboardModifiers) {
+        seat.keyboard_send_modifiers(modifiers)
+    }
+
+    /// The grab was ended with `Seat::end_grab`, or by the seat itself.
+    fn cancel(&mut self, seat: &Seat) {}
+}
+
+/// A grab of the touch points of a seat, started with `Seat::start_touch_grab`.
+#[allow(unused_variables)]
+pub trait TouchGrab {
+    /// The seat was notified of a touch down on the surface of the point.
+    ///
+    /// Returns the serial of the touch down that was sent, or zero if none
+    /// was sent.
+    fn down(&mut self, seat: &Seat, surface: &mut Surface, time: Duration, point: &TouchPoint)
+            -> u32 {
+        let (sx, sy) = point.coords();
+        seat.touch_send_down(surface, time, point.touch_id(), sx, sy)
+    }
+
+    /// The seat was notified that the point is up.
+    fn up(&mut self, seat: &Seat, time: Duration, point: &TouchPoint) {
+        seat.touch_send_up(time, point.touch_id())
+    }
+
+    /// The seat was notified that the point moved.
+    fn motion(&mut self, seat: &Seat, time: Duration, point: &TouchPoint) {
+        let (sx, sy) = point.coords();
+        seat.touch_send_motion(time, point.touch_id(), sx, sy)
+    }
+
+    /// The point entered another surface, see `Seat::touch_point_focus`.
+    fn enter(&mut self, seat: &Seat, time: Duration, point: &TouchPoint) {}
+
+    /// The grab was ended with `Seat::end_grab`, or by the seat itself.
+    fn cancel(&mut self, seat: &Seat) {}
+}
+
+/// A grab that sends every event on to the focused client, like the default
+/// grab of the seat does.
+///
+/// Useful to keep the seat from acting on client grabs (e.g. drags) for a
+/// while, or as a starting point to wrap.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Passthrough;
+
+impl PointerGrab for Passthrough {}
+
+impl KeyboardGrab for Passthrough {}
+
+impl TouchGrab for Passthrough {}
+
+/// A pointer grab of the compositor, along with the wlroots grab it's
+/// started with.
+pub(crate) struct CustomPointer {
+    grab: wlr_seat_pointer_grab,
+    handler: Box<PointerGrab>
+}
+
+/// A keyboard grab of the compositor, along with the wlroots grab it's
+/// started with.
+pub(crate) struct CustomKeyboard {
+    grab: wlr_seat_keyboard_grab,
+    handler: Box<KeyboardGrab>
+}
+
+/// A touch grab of the compositor, along with the wlroots grab it's started
+/// with.
+pub(crate) struct CustomTouch {
+    grab: wlr_seat_touch_grab,
+    handler: Box<TouchGrab>
+}
+
+thread_local! {
+    /// How many grab callbacks are running.
+    static DISPATCHING: Cell<u32> = Cell::new(0);
+    /// The grabs that were ended while a grab callback was running, which are
+    /// freed once it returns.
+    static ENDED: RefCell<Vec<Box<Any>>> = RefCell::new(Vec::new());
+}
+
+/// Marks a grab callback as running while it's alive.
+struct Dispatch;
+
+impl Dispatch {
+    fn enter() -> Dispatch {
+        DISPATCHING.with(|dispatching| dispatching.set(dispatching.get() + 1));
+        Dispatch
+    }
+}
+
+impl Drop for Dispatch {
+    fn drop(&mut self) {
+        let depth = DISPATCHING.with(|dispatching| {
+                                         let depth = dispatching.get() - 1;
+                                         dispatching.set(depth);
+                                         depth
+                                     });
+        if depth == 0 {
+            // NOTE Dropped outside of the borrow, as the grabs can end others.
+            let ended = ENDED.with(|ended| mem::replace(&mut *ended.borrow_mut(), Vec::new()));
+            drop(ended)
+        }
+    }
+}
+
+/// Free a grab of the compositor that was ended.
+///
+/// The grab might be the one whose callback is running (e.g. when it ends
+/// itself), so it's only freed once no grab callback is running anymore.
+pub(crate) fn release<T: 'static>(grab: Box<T>) {
+    if DISPATCHING.with(|dispatching| dispatching.get()) == 0 {
+        return
+    }
+    ENDED.with(|ended| ended.borrow_mut().push(grab as Box<Any>))
+}
+
+static POINTER_INTERFACE: wlr_pointer_grab_interface =
+    wlr_pointer_grab_interface { enter: Some(pointer_enter),
+                                 motion: Some(pointer_motion),
+                                 button: Some(pointer_button),
+                                 axis: Some(pointer_axis),
+                                 cancel: Some(pointer_cancel) };
+
+static KEYBOARD_INTERFACE: wlr_keyboard_grab_interface =
+    wlr_keyboard_grab_interface { enter: Some(keyboard_enter),
+                                  key: Some(keyboard_key),
+                                  modifiers: Some(keyboard_modifiers),
+                                  cancel: Some(keyboard_cancel) };
+
+static TOUCH_INTERFACE: wlr_touch_grab_interface =
+    wlr_touch_grab_interface { down: Some(touch_down),
+                               up: Some(touch_up),
+                               motion: Some(touch_motion),
+                               enter: Some(touch_enter),
+                               cancel: Some(touch_cancel) };
+
+impl CustomPointer {
+    /// Box the grab, so the wlroots grab can point back at it.
+    pub(crate) fn new(seat: *mut wlr_seat, handler: Box<PointerGrab>) -> Box<Self> {
+        let mut custom = Box::new(CustomPointer { grab: wlr_seat_pointer_grab {
+                                                      interface: &POINTER_INTERFACE,
+                                                      seat,
+                                                      data: ::std::ptr::null_mut()
+                                                  },
+                                                  handler });
+        custom.grab.data = &mut *custom as *mut CustomPointer as *mut c_void;
+        custom
+    }
+
+    pub(crate) fn as_ptr(&mut self) -> *mut wlr_seat_pointer_grab {
+        &mut self.grab
+    }
+}
+
+impl CustomKeyboard {
+    /// Box the grab, so the wlroots grab can point back at it.
+    pub(crate) fn new(seat: *mut wlr_seat, handler: Box<KeyboardGrab>) -> Box<Self> {
+        let mut custom = Box::new(CustomKeyboard { grab: wlr_seat_keyboard_grab {
+                                                       interface: &KEYBOARD_INTERFACE,
+                                                       seat,
+                                                       data: ::std::ptr::null_mut()
+                                                   },
+                                                   handler });
+        custom.grab.data = &mut *custom as *mut CustomKeyboard as *mut c_void;
+        custom
+    }
+
+    pub(crate) fn as_ptr(&mut self) -> *mut wlr_seat_keyboard_grab {
+        &mut self.grab
+    }
+}
+
+impl CustomTouch {
+    /// Box the grab, so the wlroots grab can point back at it.
+    pub(crate) fn new(seat: *mut wlr_seat, handler: Box<TouchGrab>) -> Box<Self> {
+        let mut custom = Box::new(CustomTouch { grab: wlr_seat_touch_grab {
+                                                    interface: &TOUCH_INTERFACE,
+                                                    seat,
+                                                    data: ::std::ptr::null_mut()
+                                                },
+                                                handler });
+        custom.grab.data = &mut *custom as *mut CustomTouch as *mut c_void;
+        custom
+    }
+
+    pub(crate) fn as_ptr(&mut self) -> *mut wlr_seat_touch_grab {
+        &mut self.grab
+    }
+}
+
+/// Get the compositor grab and the seat it's on, unless the seat is already
+/// being destroyed.
+unsafe fn pointer_grab<'grab>(grab: *mut wlr_seat_pointer_grab)
+                              -> Option<(&'grab mut CustomPointer, &'grab Seat)> {
+    let seat = seat::seat_ref((*grab).seat)?;
+    Some((&mut *((*grab).data as *mut CustomPointer), seat))
+}
+
+unsafe fn keyboard_grab<'grab>(grab: *mut wlr_seat_keyboard_grab)
+                               -> Option<(&'grab mut CustomKeyboard, &'grab Seat)> {
+    let seat = seat::seat_ref((*grab).seat)?;
+    Some((&mut *((*grab).data as *mut CustomKeyboard), seat))
+}
+
+unsafe fn touch_grab<'grab>(grab: *mut wlr_seat_touch_grab)
+                            -> Option<(&'grab mut CustomTouch, &'grab Seat)> {
+    let seat = seat::seat_ref((*grab).seat)?;
+    Some((&mut *((*grab).data as *mut CustomTouch), seat))
+}
+
+/// Get the wrapper of the surface, if it has one.
+unsafe fn surface(surface: *mut wlr_surface) -> Option<Surface> {
+    if surface.is_null() || (*surface).data.is_null() {
+        return None
+    }
+    Some(Surface::from_ptr(surface))
+}
+
+unsafe extern "C" fn pointer_enter(grab: *mut wlr_seat_pointer_grab,
+                                   surface_ptr: *mut wlr_surface,
+                                   sx: f64,
+                                   sy: f64) {
+    let _dispatch = Dispatch::enter();
+    let (custom, seat) = match pointer_grab(grab) {
+        Some(grab) => grab,
+        None => return
+    };
+    match surface(surface_ptr) {
+        Some(mut surface) => custom.handler.enter(seat, &mut surface, sx, sy),
+        None => wlr_seat_pointer_enter((*grab).seat, surface_ptr, sx, sy)
+    }
+}
+
+unsafe extern "C" fn pointer_motion(grab: *mut wlr_seat_pointer_grab,
+                                    time: u32,
+                                    sx: f64,
+                                    sy: f64) {
+    let _dispatch = Dispatch::enter();
+    if let Some((custom, seat)) = pointer_grab(grab) {
+        custom.handler.motion(seat, Duration::from_millis(time as u64), sx, sy)
+    }
+}
+
+unsafe extern "C" fn pointer_button(grab: *mut wlr_seat_pointer_grab,
+                                    time: u32,
+                                    button: u32,
+                                    state: u32)
+                                    -> u32 {
+    let _dispatch = Dispatch::enter();
+    match pointer_grab(grab) {
+        Some((custom, seat)) => {
+            custom.handler.button(seat, Duration::from_millis(time as u64), button, state)
+        },
+        None => 0
+    }
+}
+
+unsafe extern "C" fn pointer_axis(grab: *mut wlr_seat_pointer_grab,
+                                  time: u32,
+                                  orientation: wlr_axis_orientation,
+                                  value: f64,
+                                  value_discrete: i32,
+                                  source: wlr_axis_source) {
+    let _dispatch = Dispatch::enter();
+    if let Some((custom, seat)) = pointer_grab(grab) {
+        custom.handler.axis(seat,
+                            Duration::from_millis(time as u64),
+                            orientation,
+                            value,
+                            value_discrete,
+                            source)
+    }
+}
+
+unsafe extern "C" fn pointer_cancel(grab: *mut wlr_seat_pointer_grab) {
+    let _dispatch = Dispatch::enter();
+    if let Some((custom, seat)) = pointer_grab(grab) {
+        custom.handler.cancel(seat)
+    }
+}
+
+unsafe extern "C" fn keyboard_enter(grab: *mut wlr_seat_keyboard_grab,
+                                    surface_ptr: *mut wlr_surface,
+                                    keycodes: *mut u32,
+                                    num_keycodes: size_t,
+                                    modifiers: *mut wlr_keyboard_modifiers) {
+    let _dispatch = Dispatch::enter();
+    let (custom, seat) = match keyboard_grab(grab) {
+        Some(grab) => grab,
+        None => return
+    };
+    match (surface(surface_ptr), modifiers.is_null()) {
+        (Some(mut surface), false) => {
+            let keycodes = if keycodes.is_null() {
+                &mut []
+            } else {
+                slice::from_raw_parts_mut(keycodes, num_keycodes)
+            };
+            custom.handler.enter(seat, &mut surface, keycodes, &mut *modifiers)
+        },
+        _ => wlr_seat_keyboard_enter((*grab).seat, surface_ptr, keycodes, num_keycodes, modifiers)
+    }
+}
+
+unsafe extern "C" fn keyboard_key(grab: *mut wlr_seat_keyboard_grab,
+                                  time: u32,
+                                  key: u32,
+                                  state: u32) {
+    let _dispatch = Dispatch::enter();
+    if let Some((custom, seat)) = keyboard_grab(grab) {
+        custom.handler.key(seat, Duration::from_millis(time as u64), key, state)
+    }
+}
+
+unsafe extern "C" fn keyboard_modifiers(grab: *mut wlr_seat_keyboard_grab,
+                                        modifiers: *mut wlr_keyboard_modifiers) {
+    let _dispatch = Dispatch::enter();
+    if modifiers.is_null() {
+        return
+    }
+    if let Some((custom, seat)) = keyboard_grab(grab) {
+        custom.handler.modifiers(seat, &mut *modifiers)
+    }
+}
+
+unsafe extern "C" fn keyboard_cancel(grab: *mut wlr_seat_keyboard_grab) {
+    let _dispatch = Dispatch::enter();
+    if let Some((custom, seat)) = keyboard_grab(grab) {
+        custom.handler.cancel(seat)
+    }
+}
+
+unsafe extern "C" fn touch_down(grab: *mut wlr_seat_touch_grab,
+                                time: u32,
+                                point: *mut wlr_touch_point)
+                                -> u32 {
+    let _dispatch = Dispatch::enter();
+    let (custom, seat) = match touch_grab(grab) {
+        Some(grab) => grab,
+        None => return 0
+    };
+    let surface_ptr = (*point).surface;
+    match surface(surface_ptr) {
+        Some(mut surface) => {
+            custom.handler.down(seat,
+                                &mut surface,
+                                Duration::from_millis(time as u64),
+                                &TouchPoint::from_ptr(point))
+        },
+        None => {
+            wlr_seat_touch_send_down((*grab).seat,
+                                     surface_ptr,
+                                     time,
+                                     (*point).touch_id,
+                                     (*point).sx,
+                                     (*point).sy)
+        }
+    }
+}
+
+unsafe extern "C" fn touch_up(grab: *mut wlr_seat_touch_grab,
+                              time: u32,
+                              point: *mut wlr_touch_point) {
+    let _dispatch = Dispatch::enter();
+    if let Some((custom, seat)) = touch_grab(grab) {
+        custom.handler.up(seat, Duration::from_millis(time as u64), &TouchPoint::from_ptr(point))
+    }
+}
+
+unsafe extern "C" fn touch_motion(grab: *mut wlr_seat_touch_grab,
+                                  time: u32,
+                                  point: *mut wlr_touch_point) {
+    let _dispatch = Dispatch::enter();
+    if let Some((custom, seat)) = touch_grab(grab) {
+        custom.handler.motion(seat,
+                              Duration::from_millis(time as u64),
+                              &TouchPoint::from_ptr(point))
+    }
+}
+
+unsafe extern "C" fn touch_enter(grab: *mut wlr_seat_touch_grab,
+                                 time: u32,
+                                 point: *mut wlr_touch_point) {
+    let _dispatch = Dispatch::enter();
+    if let Some((custom, seat)) = touch_grab(grab) {
+        custom.handler.enter(seat,
+                             Duration::from_millis(time as u64),
+                             &TouchPoint::from_ptr(point))
+    }
+}
+
+unsafe extern "C" fn touch_cancel(grab: *mut wlr_seat_touch_grab) {
+    let _dispatch = Dispatch::enter();
+    if let Some((custom, seat)) = touch_grab(grab) {
+        custom.handler.cancel(seat)
+    }
+}
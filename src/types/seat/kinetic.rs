@@ -0,0 +1,205 @@
+//! Kinetic scrolling, for users used to scrolling that keeps going once the
+//! fingers leave the touchpad.
+//!
+//! Once enabled with `Seat::set_kinetic_scrolling`, the seat watches the axis
+//! events of finger scrolls it's notified of. When the fingers are lifted
+//! (i.e. the axis stops) while they still moved fast enough, the stop is held
+//! back and the scroll carries on with the speed it had, slowing down with
+//! the friction until it's too slow to notice, at which point the axis stops.
+//!
+//! Clients doing kinetic scrolling of their own would do it twice, so the
+//! synthesized events have a continuous source. Scrolling again, pressing a
+//! button or the pointer entering another surface stops the scroll right
+//! away.
+
+use std::{ptr, time::Duration};
+
+use libc;
+use wayland_sys::server::{wl_event_source, WAYLAND_SERVER_HANDLE};
+use wlroots_sys::{wlr_axis_orientation::{self, *}, wlr_seat};
+
+use {compositor, seat, utils::current_time};
+
+/// How often the events of the kinetic scroll are sent.
+const TICK: Duration = Duration::from_millis(16);
+/// How far back the axis events of a finger scroll count towards its speed.
+const SAMPLE_WINDOW: Duration = Duration::from_millis(100);
+
+/// How kinetic scrolls slow down.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KineticScrolling {
+    /// How quickly the scroll slows down. The speed decays exponentially at
+    /// this rate per second, so higher is shorter.
+    pub friction: f64,
+    /// The speed, in axis units per second, below which the scroll ends.
+    ///
+    /// Finger scrolls that are slower than this when they end don't carry on.
+    pub min_velocity: f64
+}
+
+impl Default for KineticScrolling {
+    fn default() -> Self {
+        KineticScrolling { friction: 4.0,
+                           min_velocity: 30.0 }
+    }
+}
+
+/// The kinetic scrolling state of an axis.
+#[derive(Debug, Default)]
+struct Axis {
+    /// The finger scroll events of the last `SAMPLE_WINDOW`, with their time.
+    samples: Vec<(Duration, f64)>,
+    /// The speed of the kinetic scroll, if it's going.
+    velocity: Option<f64>
+}
+
+pub(crate) struct Kinetic {
+    seat: *mut wlr_seat,
+    config: KineticScrolling,
+    vertical: Axis,
+    horizontal: Axis,
+    /// When the last events of the kinetic scroll were sent.
+    last_tick: Duration,
+    timer: *mut wl_event_source
+}
+
+impl Kinetic {
+    pub fn new(seat: *mut wlr_seat, config: KineticScrolling) -> Box<Self> {
+        Box::new(Kinetic { seat,
+                           config,
+                           vertical: Axis::default(),
+                           horizontal: Axis::default(),
+                           last_tick: Duration::from_secs(0),
+                           timer: ptr::null_mut() })
+    }
+
+    pub fn config(&self) -> KineticScrolling {
+        self.config
+    }
+
+    pub fn set_config(&mut self, config: KineticScrolling) {
+        self.config = config;
+    }
+
+    fn axis(&mut self, orientation: wlr_axis_orientation) -> &mut Axis {
+        match orientation {
+            WLR_AXIS_ORIENTATION_VERTICAL => &mut self.vertical,
+            WLR_AXIS_ORIENTATION_HORIZONTAL => &mut self.horizontal
+        }
+    }
+
+    /// Take note of an axis event of a finger scroll.
+    ///
+    /// Returns true if the event is the end of the scroll and a kinetic
+    /// scroll takes over, so it's not to be sent.
+    pub unsafe fn finger_axis(&mut self,
+                              time: Duration,
+                              orientation: wlr_axis_orientation,
+                              value: f64)
+                              -> bool {
+        let min_velocity = self.config.min_velocity;
+        let started = {
+            let axis = self.axis(orientation);
+            axis.samples.retain(|&(sample, _)| time < sample + SAMPLE_WINDOW);
+            if value != 0.0 {
+                axis.samples.push((time, value));
+                return false
+            }
+            let samples = ::std::mem::replace(&mut axis.samples, Vec::new());
+            let first = match samples.first() {
+                Some(&(first, _)) => first,
+                None => return false
+            };
+            // NOTE Every event stands for a frame's worth of motion.
+            let span = (time.max(first) - first + TICK).as_secs_f64();
+            let velocity = samples.iter().map(|&(_, value)| value).sum::<f64>() / span;
+            if velocity.abs() < min_velocity {
+                return false
+            }
+            axis.velocity = Some(velocity);
+            true
+        };
+        if started {
+            self.last_tick = current_time();
+            self.arm_timer();
+        }
+        started
+    }
+
+    /// Stop the kinetic scroll, if it's going.
+    pub unsafe fn stop(&mut self) {
+        let time = current_time();
+        for &orientation in &[WLR_AXIS_ORIENTATION_VERTICAL, WLR_AXIS_ORIENTATION_HORIZONTAL] {
+            if self.axis(orientation).velocity.take().is_some() {
+                seat::notify_kinetic_axis(self.seat, time, orientation, 0.0);
+            }
+        }
+        self.disarm_timer();
+    }
+
+    /// Send the events of the kinetic scroll since the last tick.
+    unsafe fn tick(&mut self) {
+        let now = current_time();
+        let elapsed = (now.max(self.last_tick) - self.last_tick).as_secs_f64();
+        self.last_tick = now;
+        let decay = (-self.config.friction * elapsed).exp();
+        let min_velocity = self.config.min_velocity;
+        let mut going = false;
+        for &orientation in &[WLR_AXIS_ORIENTATION_VERTICAL, WLR_AXIS_ORIENTATION_HORIZONTAL] {
+            let velocity = match self.axis(orientation).velocity {
+                Some(velocity) => velocity * decay,
+                None => continue
+            };
+            if velocity.abs() < min_velocity {
+                self.axis(orientation).velocity = None;
+                seat::notify_kinetic_axis(self.seat, now, orientation, 0.0);
+            } else {
+                self.axis(orientation).velocity = Some(velocity);
+                seat::notify_kinetic_axis(self.seat, now, orientation, velocity * elapsed);
+                going = true;
+            }
+        }
+        if going {
+            self.arm_timer();
+        }
+    }
+
+    unsafe fn arm_timer(&mut self) {
+        if self.timer.is_null() {
+            if compositor::COMPOSITOR_PTR.is_null() {
+                return
+            }
+            let event_loop = (*compositor::COMPOSITOR_PTR).event_loop;
+            self.timer = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                       wl_event_loop_add_timer,
+                                       event_loop,
+                                       kinetic_timer_notify,
+                                       self as *mut Kinetic as *mut libc::c_void);
+        }
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_event_source_timer_update,
+                      self.timer,
+                      TICK.subsec_millis() as libc::c_int);
+    }
+
+    unsafe fn disarm_timer(&mut self) {
+        if !self.timer.is_null() {
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_event_source_timer_update, self.timer, 0);
+        }
+    }
+}
+
+impl Drop for Kinetic {
+    fn drop(&mut self) {
+        if !self.timer.is_null() {
+            unsafe {
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_event_source_remove, self.timer);
+            }
+        }
+    }
+}
+
+unsafe extern "C" fn kinetic_timer_notify(data: *mut libc::c_void) -> libc::c_int {
+    (*(data as *mut Kinetic)).tick();
+    0
+}
@@ -1,9 +1,16 @@
+pub mod devices;
 pub mod drag_icon;
 mod seat_client;
 mod seat;
 pub mod grab;
+mod pointer_frame;
 mod touch_point;
+pub mod touch_tracker;
+pub mod tablet_emulation;
+pub mod kinetic;
 
 pub use self::seat::*;
 pub use self::seat_client::*;
 pub use self::touch_point::*;
+pub use self::touch_tracker::TrackedTouch;
+pub use self::kinetic::KineticScrolling;
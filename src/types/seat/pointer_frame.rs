@@ -0,0 +1,120 @@
+//! Grouping the pointer events sent to clients into `wl_pointer.frame`s.
+//!
+//! This version of wlroots ends every pointer event it sends with a frame, so
+//! the events of a frame are queued here instead and sent to the pointers of
+//! the focused client directly.
+
+use wayland_sys::server::{wl_resource, WAYLAND_SERVER_HANDLE};
+use wlroots_sys::{wl_list, wlr_axis_orientation, wlr_axis_source, wlr_seat};
+
+// NOTE Taken from the wl_pointer interface in wayland.xml.
+const MOTION: u32 = 2;
+const BUTTON: u32 = 3;
+const AXIS: u32 = 4;
+const FRAME: u32 = 5;
+const AXIS_SOURCE: u32 = 6;
+const AXIS_STOP: u32 = 7;
+const AXIS_DISCRETE: u32 = 8;
+/// The version of wl_pointer that added frames, along with the axis details.
+const FRAME_SINCE_VERSION: i32 = 5;
+
+/// A pointer event waiting for the end of its frame.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Event {
+    Motion { time: u32, sx: f64, sy: f64 },
+    Button { time: u32, serial: u32, button: u32, state: u32 },
+    Axis {
+        time: u32,
+        orientation: wlr_axis_orientation,
+        value: f64,
+        value_discrete: i32,
+        source: wlr_axis_source
+    }
+}
+
+/// Send the events to the pointers of the focused client of the seat as one
+/// frame.
+pub(crate) unsafe fn send(seat: *mut wlr_seat, events: &[Event]) {
+    let client = (*seat).pointer_state.focused_client;
+    if client.is_null() || events.is_empty() {
+        return
+    }
+    let head: *mut wl_list = &mut (*client).pointers;
+    let mut link = (*head).next;
+    while link != head {
+        let next = (*link).next;
+        let resource = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_from_link, link as *mut _);
+        send_to(resource, events);
+        link = next;
+    }
+}
+
+unsafe fn send_to(resource: *mut wl_resource, events: &[Event]) {
+    let version = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_version, resource);
+    let has_frames = version >= FRAME_SINCE_VERSION;
+    // NOTE Only one axis source is allowed in a frame.
+    let mut sent_source = false;
+    for event in events {
+        match *event {
+            Event::Motion { time, sx, sy } => {
+                post(resource, MOTION, &[time as i32, to_fixed(sx), to_fixed(sy)])
+            },
+            Event::Button { time, serial, button, state } => {
+                post(resource, BUTTON, &[serial as i32, time as i32, button as i32, state as i32])
+            },
+            Event::Axis { time, orientation, value, value_discrete, source } => {
+                let orientation = orientation as i32;
+                if has_frames && !sent_source {
+                    post(resource, AXIS_SOURCE, &[source as i32]);
+                    sent_source = true;
+                }
+                if value != 0.0 {
+                    if has_frames && value_discrete != 0 {
+                        post(resource, AXIS_DISCRETE, &[orientation, value_discrete]);
+                    }
+                    post(resource, AXIS, &[time as i32, orientation, to_fixed(value)]);
+                } else if has_frames {
+                    post(resource, AXIS_STOP, &[time as i32, orientation]);
+                }
+            }
+        }
+    }
+    if has_frames {
+        post(resource, FRAME, &[]);
+    }
+}
+
+/// Post the event, whose arguments are all 32 bit integers.
+unsafe fn post(resource: *mut wl_resource, opcode: u32, args: &[i32]) {
+    match *args {
+        [] => ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_post_event, resource, opcode),
+        [a] => ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_post_event, resource, opcode, a),
+        [a, b] => {
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_post_event, resource, opcode, a, b)
+        },
+        [a, b, c] => {
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_resource_post_event,
+                          resource,
+                          opcode,
+                          a,
+                          b,
+                          c)
+        },
+        [a, b, c, d] => {
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_resource_post_event,
+                          resource,
+                          opcode,
+                          a,
+                          b,
+                          c,
+                          d)
+        },
+        _ => unreachable!("wl_pointer has no events with that many arguments")
+    }
+}
+
+fn to_fixed(value: f64) -> i32 {
+    (value * 256.0) as i32
+}
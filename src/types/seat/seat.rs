@@ -3,12 +3,12 @@
 //!
 //! TODO This module could really use some examples, as the API surface is huge.
 
-use std::{fmt, panic, ptr, cell::Cell, rc::{Rc, Weak}, time::Duration};
+use std::{fmt, panic::{self, Location}, ptr, cell::{Cell, RefCell}, rc::{Rc, Weak}, time::Duration};
 
 use libc;
 use wayland_sys::server::{signal::wl_signal_add, WAYLAND_SERVER_HANDLE};
-use wlroots_sys::{wlr_axis_orientation, wlr_seat, wlr_seat_create, wlr_seat_destroy,
-                  wlr_seat_get_keyboard, wlr_seat_keyboard_clear_focus,
+use wlroots_sys::{wlr_axis_orientation, wlr_input_device, wlr_seat, wlr_seat_create,
+                  wlr_seat_destroy, wlr_seat_get_keyboard, wlr_seat_keyboard_clear_focus,
                   wlr_seat_keyboard_end_grab, wlr_seat_keyboard_enter, wlr_seat_keyboard_has_grab,
                   wlr_seat_keyboard_notify_enter, wlr_seat_keyboard_notify_key,
                   wlr_seat_keyboard_notify_modifiers, wlr_seat_keyboard_send_key,
@@ -26,17 +26,134 @@ use wlroots_sys::{wlr_axis_orientation, wlr_seat, wlr_seat_create, wlr_seat_dest
                   wlr_seat_touch_num_points, wlr_seat_touch_point_clear_focus,
                   wlr_seat_touch_point_focus, wlr_seat_touch_send_down,
                   wlr_seat_touch_send_motion, wlr_seat_touch_send_up, wlr_seat_touch_start_grab,
-                  wlr_axis_source, wlr_drag_icon};
+                  wlr_axis_source::{self, WLR_AXIS_SOURCE_CONTINUOUS, WLR_AXIS_SOURCE_FINGER},
+                  wlr_drag_icon, wlr_surface,
+                  wlr_button_state::{self, WLR_BUTTON_PRESSED, WLR_BUTTON_RELEASED},
+                  wlr_tablet_tool_proximity_state::WLR_TABLET_TOOL_PROXIMITY_OUT,
+                  wlr_tablet_tool_tip_state::WLR_TABLET_TOOL_TIP_DOWN};
 pub use wlroots_sys::wayland_server::protocol::wl_seat::Capability;
 use xkbcommon::xkb::Keycode;
 
 use {KeyboardModifiers,
      compositor::{self, Compositor},
-     input::{self, keyboard},
+     input::{self, keyboard, pointer, tablet_tool},
      surface::{self, Surface},
-     seat::{self, grab, touch_point::{TouchId, TouchPoint}, drag_icon::{self, DragIcon}},
-     utils::{ToMs, HandleErr, HandleResult, c_to_rust_string, safe_as_cstring}};
+     shell::popup_grab,
+     seat::{self, devices, grab, pointer_frame, touch_point::{TouchId, TouchPoint},
+            touch_tracker::{self, TrackedTouch}, tablet_emulation,
+            kinetic::{self, KineticScrolling},
+            drag_icon::{self, DragIcon}},
+     area::Origin,
+     view::View,
+     utils::{self, ToMs, HandleErr, HandleResult, c_to_rust_string, safe_as_cstring}};
 pub use events::seat_events as event;
+#[cfg(feature = "idle")]
+use extensions::idle;
+#[cfg(feature = "input_inhibit")]
+use extensions::input_inhibit;
+#[cfg(feature = "layer_shell")]
+use extensions::layer_shell;
+#[cfg(feature = "session_lock")]
+use extensions::session_lock;
+#[cfg(feature = "shortcuts_inhibit")]
+use extensions::shortcuts_inhibit;
+
+thread_local! {
+    /// All of the seats that have been created.
+    static SEATS: RefCell<Vec<*mut wlr_seat>> = RefCell::new(Vec::new());
+}
+
+/// Get the pointers to all of the seats.
+pub(crate) fn seat_ptrs() -> Vec<*mut wlr_seat> {
+    SEATS.with(|seats| seats.borrow().clone())
+}
+
+/// Determine if the surface may not get the pointer or touch focus, because
+/// the session is locked or another client inhibits the input.
+///
+/// While the session is locked only the lock surfaces get input, whatever
+/// else asked for it.
+#[allow(unused_variables)]
+pub(crate) fn input_blocked(surface: *mut wlr_surface) -> bool {
+    #[cfg(feature = "session_lock")]
+    {
+        if session_lock::is_locked() {
+            return session_lock::blocks_input(surface)
+        }
+    }
+    #[cfg(feature = "input_inhibit")]
+    {
+        if input_inhibit::blocks_input(surface) {
+            return true
+        }
+    }
+    false
+}
+
+/// Determine if the surface may not get the keyboard focus, which a layer
+/// surface can take for itself too on top of `input_blocked`.
+#[allow(unused_variables)]
+pub(crate) fn keyboard_blocked(surface: *mut wlr_surface) -> bool {
+    if input_blocked(surface) {
+        return true
+    }
+    #[cfg(feature = "layer_shell")]
+    {
+        #[cfg(feature = "session_lock")]
+        {
+            if session_lock::is_locked() {
+                return false
+            }
+        }
+        if layer_shell::blocks_keyboard(surface) {
+            return true
+        }
+    }
+    false
+}
+
+/// Tell the protocols that follow the keyboard focus that it moved from the
+/// `previous` surface.
+#[allow(unused_variables)]
+unsafe fn keyboard_focus_changed(seat: *mut wlr_seat, previous: *mut wlr_surface) {
+    #[cfg(feature = "layer_shell")]
+    layer_shell::focus_changed(seat, previous);
+    #[cfg(feature = "shortcuts_inhibit")]
+    shortcuts_inhibit::focus_changed(seat);
+}
+
+/// Give the keyboard focus of the seat to the surface as is, with the keys
+/// that are pressed on the keyboard of the seat.
+///
+/// This goes around any keyboard grab, e.g. the one of a popup that keeps
+/// the focus where it is.
+pub(crate) unsafe fn give_keyboard_focus(seat: *mut wlr_seat, surface: *mut wlr_surface) {
+    let keyboard = wlr_seat_get_keyboard(seat);
+    if keyboard.is_null() {
+        wlr_seat_keyboard_enter(seat, surface, ptr::null_mut(), 0, ptr::null_mut());
+    } else {
+        wlr_seat_keyboard_enter(seat,
+                                surface,
+                                (*keyboard).keycodes.as_mut_ptr(),
+                                (*keyboard).num_keycodes,
+                                &mut (*keyboard).modifiers);
+    }
+    #[cfg(feature = "shortcuts_inhibit")]
+    shortcuts_inhibit::focus_changed(seat);
+}
+
+/// Get the seat with the name, if there is one.
+pub fn find(name: &str) -> Option<Handle> {
+    SEATS.with(|seats| {
+        seats.borrow()
+             .iter()
+             .find(|&&seat| unsafe {
+                       !(*seat).name.is_null() &&
+                       c_to_rust_string((*seat).name).map_or(false, |seat_name| seat_name == name)
+                   })
+             .map(|&seat| unsafe { Handle::from_ptr(seat) })
+    })
+}
 
 struct SeatState {
     /// A counter that will always have a strong count of 1.
@@ -45,7 +162,28 @@ struct SeatState {
     /// they cannot be upgraded.
     counter: Rc<Cell<bool>>,
     /// A raw pointer to the Seat on the heap.
-    seat: *mut Seat
+    seat: *mut Seat,
+    /// Whether keyboards take over the lock state of the keyboard before them.
+    sync_locks: bool,
+    /// The lock state of the last keyboard that was detached.
+    last_locks: Option<keyboard::Led>,
+    /// The pointer events of the current frame, if pointer events are grouped
+    /// into frames.
+    pointer_frame: Option<Vec<pointer_frame::Event>>,
+    /// The pointer grab started with `Seat::start_pointer_grab`, if there is
+    /// one.
+    pointer_grab: Option<Box<grab::CustomPointer>>,
+    /// The keyboard grab started with `Seat::start_keyboard_grab`, if there
+    /// is one.
+    keyboard_grab: Option<Box<grab::CustomKeyboard>>,
+    /// The touch grab started with `Seat::start_touch_grab`, if there is one.
+    touch_grab: Option<Box<grab::CustomTouch>>,
+    /// The touch points that went down with `Seat::touch_down_at`.
+    touch_points: touch_tracker::Tracker,
+    /// The tablet tools driving the pointer.
+    tablet_emulation: tablet_emulation::Emulation,
+    /// The kinetic scrolling of finger scrolls, if it's enabled.
+    kinetic: Option<Box<kinetic::Kinetic>>
 }
 
 #[derive(Debug, Clone)]
@@ -112,10 +250,19 @@ pub trait Handler {
                          seat_handle: Handle) {}
 
     /// The seat is being destroyed.
+    #[deprecated(note = "Use `destroyed` instead")]
     fn destroy(&mut self,
                compositor_handle: compositor::Handle,
                seat_handle: Handle) {}
 
+    /// The seat is being destroyed.
+    #[allow(deprecated)]
+    fn destroyed(&mut self,
+                 compositor_handle: compositor::Handle,
+                 seat_handle: Handle) {
+        self.destroy(compositor_handle, seat_handle)
+    }
+
     /// A new drag icon has been created.
     fn new_drag_icon(&mut self,
                      compositor_handle: compositor::Handle,
@@ -315,7 +462,7 @@ wayland_listener!(pub Seat, (*mut wlr_seat, Box<Handler>), [
         };
         let seat = Seat::from_ptr(seat_ptr);
 
-        handler.destroy(compositor, seat.weak_reference());
+        handler.destroyed(compositor, seat.weak_reference());
 
         // NOTE Destructor is already being run,
         // otherwise this would be a double free.
@@ -361,12 +508,38 @@ impl Seat {
             let counter = Rc::new(Cell::new(false));
             let handle = Rc::downgrade(&counter);
             let state = Box::new(SeatState { counter,
-                                             seat: Box::into_raw(res) });
+                                             seat: Box::into_raw(res),
+                                             sync_locks: false,
+                                             last_locks: None,
+                                             pointer_frame: None,
+                                             pointer_grab: None,
+                                             keyboard_grab: None,
+                                             touch_grab: None,
+                                             touch_points: Default::default(),
+                                             tablet_emulation: Default::default(),
+                                             kinetic: None });
             (*seat).data = Box::into_raw(state) as *mut libc::c_void;
+            #[cfg(feature = "idle")]
+            idle::add_seat(seat);
+            #[cfg(feature = "xwayland")]
+            {
+                if let Some(xwayland) = compositor.xwayland.as_mut() {
+                    xwayland.seat_created(seat);
+                }
+            }
+            SEATS.with(|seats| seats.borrow_mut().push(seat));
             Handle { seat: seat, handle }
         }
     }
 
+    /// Create a seat like `create`, without boxing the handler yourself.
+    pub fn create_with<H: Handler + 'static>(compositor: &mut Compositor,
+                                             name: String,
+                                             handler: H)
+                                             -> Handle {
+        Seat::create(compositor, name, Box::new(handler))
+    }
+
     /// Reconstruct the box from the wlr_seat.
     unsafe fn from_ptr(seat: *mut wlr_seat) -> Box<Seat> {
         let data = (*seat).data as *mut SeatState;
@@ -385,6 +558,14 @@ impl Seat {
         }
     }
 
+    /// Get the raw pointer to the seat.
+    ///
+    /// # Safety
+    /// There's no guarantees that this pointer is not dangling.
+    pub unsafe fn as_ptr(&self) -> *mut wlr_seat {
+        self.data.0
+    }
+
     /// Get the name of the seat.
     pub fn name(&self) -> Option<String> {
         unsafe {
@@ -431,7 +612,11 @@ impl Seat {
     // Compositor should use `Seat::pointer_notify_enter` to
     // change pointer focus to respect pointer grabs.
     pub fn pointer_enter(&self, surface: &mut Surface, sx: f64, sy: f64) {
+        if input_blocked(unsafe { surface.as_ptr() }) {
+            return
+        }
         unsafe {
+            flush_pointer_frame(self.data.0);
             wlr_seat_pointer_enter(self.data.0, surface.as_ptr(), sx, sy);
         }
     }
@@ -439,7 +624,10 @@ impl Seat {
     /// Clears the focused surface for the pointer and leaves all entered
     /// surfaces.
     pub fn pointer_clear_focus(&self) {
-        unsafe { wlr_seat_pointer_clear_focus(self.data.0) }
+        unsafe {
+            flush_pointer_frame(self.data.0);
+            wlr_seat_pointer_clear_focus(self.data.0)
+        }
     }
 
     /// Sends a motion event to the surface with pointer focus.
@@ -481,6 +669,19 @@ impl Seat {
         }
     }
 
+    /// Send the axis event to the surface with pointer focus as is, e.g. to
+    /// keep its discrete steps and source.
+    ///
+    /// Compositors should use `Seat::pointer_notify_axis_event` to
+    /// send axis events to respect pointer grabs.
+    pub fn send_axis_event(&self, event: &pointer::event::Axis) {
+        self.send_axis(event.time(),
+                       event.orientation(),
+                       event.delta(),
+                       event.delta_discrete(),
+                       event.source())
+    }
+
     /// Start a grab of the pointer of this seat. The grabber is responsible for
     /// handling all pointer events until the grab ends.
     pub fn pointer_start_grab(&self, grab: grab::Pointer) {
@@ -493,6 +694,103 @@ impl Seat {
         unsafe { wlr_seat_pointer_end_grab(self.data.0) }
     }
 
+    /// Start a grab of the pointer of this seat, which gets the pointer events
+    /// the seat is notified of until it's ended with `end_grab`.
+    ///
+    /// This replaces the current pointer grab, including a grab started with
+    /// this before, which is cancelled.
+    pub fn start_pointer_grab(&self, grab: Box<grab::PointerGrab>) {
+        unsafe {
+            let seat = self.data.0;
+            let state = (*seat).data as *mut SeatState;
+            flush_pointer_frame(seat);
+            // NOTE Ended first, so the grab that's replaced is cancelled.
+            if let Some(mut replaced) = (*state).pointer_grab.take() {
+                if (*seat).pointer_state.grab == replaced.as_ptr() {
+                    wlr_seat_pointer_end_grab(seat);
+                }
+                grab::release(replaced);
+            }
+            let mut custom = grab::CustomPointer::new(seat, grab);
+            wlr_seat_pointer_start_grab(seat, custom.as_ptr());
+            (*state).pointer_grab = Some(custom);
+        }
+    }
+
+    /// Start a grab of the keyboard of this seat, which gets the keyboard
+    /// events the seat is notified of until it's ended with `end_grab`.
+    ///
+    /// This replaces the current keyboard grab, including a grab started with
+    /// this before, which is cancelled.
+    pub fn start_keyboard_grab(&self, grab: Box<grab::KeyboardGrab>) {
+        unsafe {
+            let seat = self.data.0;
+            let state = (*seat).data as *mut SeatState;
+            // NOTE Ended first, so the grab that's replaced is cancelled.
+            if let Some(mut replaced) = (*state).keyboard_grab.take() {
+                if (*seat).keyboard_state.grab == replaced.as_ptr() {
+                    wlr_seat_keyboard_end_grab(seat);
+                }
+                grab::release(replaced);
+            }
+            let mut custom = grab::CustomKeyboard::new(seat, grab);
+            wlr_seat_keyboard_start_grab(seat, custom.as_ptr());
+            (*state).keyboard_grab = Some(custom);
+        }
+    }
+
+    /// Start a grab of the touch points of this seat, which gets the touch
+    /// events the seat is notified of until it's ended with `end_grab`.
+    ///
+    /// This replaces the current touch grab, including a grab started with
+    /// this before, which is cancelled.
+    pub fn start_touch_grab(&self, grab: Box<grab::TouchGrab>) {
+        unsafe {
+            let seat = self.data.0;
+            let state = (*seat).data as *mut SeatState;
+            // NOTE Ended first, so the grab that's replaced is cancelled.
+            if let Some(mut replaced) = (*state).touch_grab.take() {
+                if (*seat).touch_state.grab == replaced.as_ptr() {
+                    wlr_seat_touch_end_grab(seat);
+                }
+                grab::release(replaced);
+            }
+            let mut custom = grab::CustomTouch::new(seat, grab);
+            wlr_seat_touch_start_grab(seat, custom.as_ptr());
+            (*state).touch_grab = Some(custom);
+        }
+    }
+
+    /// End the grabs started with `start_pointer_grab`, `start_keyboard_grab`
+    /// and `start_touch_grab` that are still active, cancelling them.
+    ///
+    /// Grabs that were replaced in the meantime, e.g. by a drag, are left
+    /// alone.
+    pub fn end_grab(&self) {
+        unsafe {
+            let seat = self.data.0;
+            let state = (*seat).data as *mut SeatState;
+            if let Some(mut custom) = (*state).pointer_grab.take() {
+                if (*seat).pointer_state.grab == custom.as_ptr() {
+                    wlr_seat_pointer_end_grab(seat);
+                }
+                grab::release(custom);
+            }
+            if let Some(mut custom) = (*state).keyboard_grab.take() {
+                if (*seat).keyboard_state.grab == custom.as_ptr() {
+                    wlr_seat_keyboard_end_grab(seat);
+                }
+                grab::release(custom);
+            }
+            if let Some(mut custom) = (*state).touch_grab.take() {
+                if (*seat).touch_state.grab == custom.as_ptr() {
+                    wlr_seat_touch_end_grab(seat);
+                }
+                grab::release(custom);
+            }
+        }
+    }
+
     /// Whether or not the pointer has a grab other than the default grab.
     pub fn pointer_has_grab(&self) -> bool {
         unsafe { wlr_seat_pointer_has_grab(self.data.0) }
@@ -503,14 +801,29 @@ impl Seat {
     ///
     /// Pass surface-local coordinates where the enter occurred.
     pub fn pointer_notify_enter(&self, surface: &mut Surface, sx: f64, sy: f64) {
-        unsafe { wlr_seat_pointer_notify_enter(self.data.0, surface.as_ptr(), sx, sy) }
+        if input_blocked(unsafe { surface.as_ptr() }) {
+            return
+        }
+        unsafe {
+            if (*self.data.0).pointer_state.focused_surface != surface.as_ptr() {
+                stop_kinetic_scroll(self.data.0);
+            }
+            flush_pointer_frame(self.data.0);
+            wlr_seat_pointer_notify_enter(self.data.0, surface.as_ptr(), sx, sy)
+        }
     }
 
     /// Notify the seat of motion over the given surface.
     ///
     /// Pass surface-local coordinates where the pointer motion occurred.
     pub fn pointer_notify_motion(&self, time: Duration, sx: f64, sy: f64) {
-        unsafe { wlr_seat_pointer_notify_motion(self.data.0, time.to_ms(), sx, sy) }
+        unsafe {
+            let time = time.to_ms();
+            match pointer_frame_events(self.data.0) {
+                Some(events) => events.push(pointer_frame::Event::Motion { time, sx, sy }),
+                None => wlr_seat_pointer_notify_motion(self.data.0, time, sx, sy)
+            }
+        }
     }
 
     // TODO Wrapper type around Button and State
@@ -519,7 +832,35 @@ impl Seat {
     ///
     /// Returns the serial of the button press or zero if no button press was sent.
     pub fn pointer_notify_button(&self, time: Duration, button: u32, state: u32) -> u32 {
-        unsafe { wlr_seat_pointer_notify_button(self.data.0, time.to_ms(), button, state) }
+        unsafe {
+            let time = time.to_ms();
+            let seat = self.data.0;
+            stop_kinetic_scroll(seat);
+            let events = match pointer_frame_events(seat) {
+                Some(events) => events,
+                None => return wlr_seat_pointer_notify_button(seat, time, button, state)
+            };
+            // NOTE This keeps track of the buttons like wlroots does, as grabs
+            // started from a button press check against it.
+            let pointer_state = &mut (*seat).pointer_state;
+            if state == WLR_BUTTON_PRESSED as u32 {
+                if pointer_state.button_count == 0 {
+                    pointer_state.grab_button = button;
+                    pointer_state.grab_time = time;
+                }
+                pointer_state.button_count += 1;
+            } else if pointer_state.button_count > 0 {
+                pointer_state.button_count -= 1;
+            }
+            let serial = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                       wl_display_next_serial,
+                                       (*seat).display as *mut _);
+            if pointer_state.button_count == 1 {
+                pointer_state.grab_serial = serial;
+            }
+            events.push(pointer_frame::Event::Button { time, serial, button, state });
+            serial
+        }
     }
 
     /// Notify the seat of an axis event.
@@ -529,12 +870,140 @@ impl Seat {
                                value: f64,
                                value_discrete: i32,
                                source: wlr_axis_source) {
-        unsafe { wlr_seat_pointer_notify_axis(self.data.0, time.to_ms(), orientation, value, value_discrete, source) }
+        unsafe {
+            let state = (*self.data.0).data as *mut SeatState;
+            if let Some(kinetic) = (*state).kinetic.as_mut() {
+                kinetic.stop();
+                if source == WLR_AXIS_SOURCE_FINGER &&
+                   kinetic.finger_axis(time, orientation, value) {
+                    return
+                }
+            }
+            let time = time.to_ms();
+            match pointer_frame_events(self.data.0) {
+                Some(events) => events.push(pointer_frame::Event::Axis { time,
+                                                                         orientation,
+                                                                         value,
+                                                                         value_discrete,
+                                                                         source }),
+                None => wlr_seat_pointer_notify_axis(self.data.0,
+                                                     time,
+                                                     orientation,
+                                                     value,
+                                                     value_discrete,
+                                                     source)
+            }
+        }
+    }
+
+    /// Group the pointer events of the seat into frames, like devices report
+    /// them (e.g. scrolling diagonally is one frame of two axis events).
+    ///
+    /// While enabled, `pointer_notify_motion`, `pointer_notify_button` and
+    /// `pointer_notify_axis` hold on to the events until the frame is ended
+    /// with `pointer_notify_frame`, which is best called from
+    /// `pointer::Handler::on_frame`. Events that go to a pointer grab are
+    /// passed on to it right away.
+    ///
+    /// When disabled every event is a frame of its own. Disabling this sends
+    /// the events of the current frame.
+    pub fn set_pointer_frames(&mut self, enabled: bool) {
+        unsafe {
+            let state = (*self.data.0).data as *mut SeatState;
+            if enabled && (*state).pointer_frame.is_none() {
+                (*state).pointer_frame = Some(Vec::new());
+            } else if !enabled {
+                flush_pointer_frame(self.data.0);
+                (*state).pointer_frame = None;
+            }
+        }
+    }
+
+    /// Keep finger scrolls going once the fingers are lifted, slowing down
+    /// as configured, or stop doing so with `None`.
+    ///
+    /// See the `seat::kinetic` module for how this works.
+    pub fn set_kinetic_scrolling(&mut self, config: Option<KineticScrolling>) {
+        unsafe {
+            let state = (*self.data.0).data as *mut SeatState;
+            match config {
+                Some(config) => {
+                    match (*state).kinetic.as_mut() {
+                        Some(kinetic) => kinetic.set_config(config),
+                        None => (*state).kinetic = Some(kinetic::Kinetic::new(self.data.0, config))
+                    }
+                },
+                None => {
+                    if let Some(mut kinetic) = (*state).kinetic.take() {
+                        kinetic.stop();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Get how finger scrolls are kept going, if they are.
+    pub fn kinetic_scrolling(&self) -> Option<KineticScrolling> {
+        unsafe {
+            let state = (*self.data.0).data as *mut SeatState;
+            (*state).kinetic.as_ref().map(|kinetic| kinetic.config())
+        }
+    }
+
+    /// Send the pointer events of the current frame to the focused client.
+    ///
+    /// This does nothing unless frames are enabled with `set_pointer_frames`.
+    pub fn pointer_notify_frame(&self) {
+        unsafe { flush_pointer_frame(self.data.0) }
+    }
+
+    /// Notify the seat of the axis event of a pointer, passing on its
+    /// discrete steps and source so clients can scroll by wheel detents and
+    /// do kinetic scrolling of finger scrolls.
+    pub fn pointer_notify_axis_event(&self, event: &pointer::event::Axis) {
+        self.pointer_notify_axis(event.time(),
+                                 event.orientation(),
+                                 event.delta(),
+                                 event.delta_discrete(),
+                                 event.source())
+    }
+
+    /// Attach the input device to this seat, detaching it from the seat it
+    /// was attached to.
+    ///
+    /// See the `seat::devices` module for what this does.
+    pub fn attach_device(&mut self, dev: &input::Device) {
+        unsafe { devices::attach(self.data.0, dev.as_ptr()) }
+    }
+
+    /// Detach the input device from this seat, if it's attached to it.
+    pub fn detach_device(&mut self, dev: &input::Device) {
+        unsafe { devices::detach(self.data.0, dev.as_ptr()) }
+    }
+
+    /// Get the input devices attached to this seat, in the order they were
+    /// attached.
+    pub fn devices(&self) -> Vec<input::Device> {
+        unsafe {
+            devices::devices(self.data.0).into_iter()
+                                         .map(|device| input::Device { device })
+                                         .collect()
+        }
     }
 
     /// Set this keyboard as the active keyboard for the seat.
     pub fn set_keyboard(&mut self, dev: &input::Device) {
-        unsafe { wlr_seat_set_keyboard(self.data.0, dev.as_ptr()) }
+        unsafe { set_keyboard(self.data.0, dev.as_ptr()) }
+    }
+
+    /// Make keyboards take over the num lock and caps lock state of the
+    /// previous keyboard of the seat when they become its keyboard.
+    ///
+    /// This keeps e.g. caps lock on when switching keyboards. For devices
+    /// attached with `attach_device` the lock state also survives unplugging
+    /// the only keyboard and plugging in another one.
+    pub fn set_sync_locks(&mut self, sync: bool) {
+        unsafe { (*((*self.data.0).data as *mut SeatState)).sync_locks = sync }
     }
 
     // TODO Point to the correct function name in this documentation.
@@ -573,13 +1042,18 @@ impl Seat {
                                  surface: &mut Surface,
                                  keycodes: &mut [Keycode],
                                  modifiers: &mut KeyboardModifiers) {
+        if keyboard_blocked(unsafe { surface.as_ptr() }) {
+            return
+        }
         let keycodes_length = keycodes.len();
         unsafe {
+            let previous = (*self.data.0).keyboard_state.focused_surface;
             wlr_seat_keyboard_notify_enter(self.data.0,
                                            surface.as_ptr(),
                                            keycodes.as_mut_ptr(),
                                            keycodes_length,
-                                           modifiers)
+                                           modifiers);
+            keyboard_focus_changed(self.data.0, previous);
         }
     }
 
@@ -594,13 +1068,18 @@ impl Seat {
                           surface: &mut Surface,
                           keycodes: &mut [Keycode],
                           modifiers: &mut KeyboardModifiers) {
+        if keyboard_blocked(unsafe { surface.as_ptr() }) {
+            return
+        }
         let keycodes_length = keycodes.len();
         unsafe {
+            let previous = (*self.data.0).keyboard_state.focused_surface;
             wlr_seat_keyboard_enter(self.data.0,
                                     surface.as_ptr(),
                                     keycodes.as_mut_ptr(),
                                     keycodes_length,
-                                    modifiers)
+                                    modifiers);
+            keyboard_focus_changed(self.data.0, previous);
         }
     }
 
@@ -624,7 +1103,60 @@ impl Seat {
     /// Clear the focused surface for the keyboard and leave all entered
     /// surfaces.
     pub fn keyboard_clear_focus(&self) {
-        unsafe { wlr_seat_keyboard_clear_focus(self.data.0) }
+        unsafe {
+            let previous = (*self.data.0).keyboard_state.focused_surface;
+            wlr_seat_keyboard_clear_focus(self.data.0);
+            keyboard_focus_changed(self.data.0, previous);
+        }
+    }
+
+    /// Give the keyboard focus to the surface, with the keys that are pressed
+    /// on the keyboard of the seat.
+    ///
+    /// Returns whether the surface has the focus now. It's refused while the
+    /// session is locked, while another client inhibits the input, or while a
+    /// layer surface of another client takes the keyboard exclusively (see
+    /// `extensions::layer_shell`).
+    ///
+    /// Popups of other clients that grabbed the seat are dismissed.
+    pub fn set_keyboard_focus(&self, surface: &mut Surface) -> bool {
+        unsafe {
+            let surface = surface.as_ptr();
+            if keyboard_blocked(surface) {
+                return false
+            }
+            popup_grab::input_outside(self.data.0, surface);
+            let previous = (*self.data.0).keyboard_state.focused_surface;
+            give_keyboard_focus(self.data.0, surface);
+            #[cfg(feature = "layer_shell")]
+            layer_shell::focus_changed(self.data.0, previous);
+            (*self.data.0).keyboard_state.focused_surface == surface
+        }
+    }
+
+    /// Determine if xdg popups grabbed the seat.
+    ///
+    /// While they do the pointer and keyboard only go to their client, and the
+    /// popups are dismissed when the user clicks or touches another client.
+    pub fn popups_grabbed(&self) -> bool {
+        popup_grab::grabbed(self.data.0)
+    }
+
+    /// Dismiss the xdg popups that grabbed the seat, e.g. when switching to
+    /// another workspace.
+    pub fn dismiss_popups(&self) {
+        unsafe { popup_grab::dismiss(self.data.0) }
+    }
+
+    /// Determine if the client of the surface asked for the key combinations
+    /// the compositor would act on, and was allowed to (see
+    /// `extensions::shortcuts_inhibit`).
+    ///
+    /// Key bindings should be skipped while this is true for the focused
+    /// surface.
+    #[cfg(feature = "shortcuts_inhibit")]
+    pub fn shortcuts_inhibited_for(&self, surface: &Surface) -> bool {
+        shortcuts_inhibit::inhibited(self.data.0, unsafe { surface.as_ptr() })
     }
 
     /// Notify the seat that the modifiers for the keyboard have changed.
@@ -767,7 +1299,11 @@ impl Seat {
                              sx: f64,
                              sy: f64)
                              -> u32 {
+        if input_blocked(unsafe { surface.as_ptr() }) {
+            return 0
+        }
         unsafe {
+            popup_grab::input_outside(self.data.0, surface.as_ptr());
             wlr_seat_touch_notify_down(self.data.0,
                                        surface.as_ptr(),
                                        time.to_ms(),
@@ -793,11 +1329,388 @@ impl Seat {
         unsafe { wlr_seat_touch_notify_motion(self.data.0, time.to_ms(), touch_id.into(), sx, sy) }
     }
 
+    /// Notify the seat of a touch down at the layout coordinates, on the
+    /// topmost of the views that takes input there.
+    ///
+    /// The views are stacked from the bottom to the top, with where their
+    /// main surfaces are in the layout. The point keeps the surface it landed
+    /// on until it's up, see the `seat::touch_tracker` module.
+    ///
+    /// Returns the surface that got the touch down, if any did.
+    pub fn touch_down_at(&self,
+                         views: &[(View, Origin)],
+                         time: Duration,
+                         touch_id: TouchId,
+                         lx: f64,
+                         ly: f64)
+                         -> Option<surface::Handle> {
+        unsafe {
+            let state = (*self.data.0).data as *mut SeatState;
+            let (surface, surface_x, surface_y) = touch_tracker::surface_at(views, lx, ly)?;
+            let surface_ptr = surface.run(|surface| surface.as_ptr()).ok()?;
+            if input_blocked(surface_ptr) {
+                return None
+            }
+            let tracker = &mut (*state).touch_points;
+            let emulated = tracker.emulate_pointer && !tracker.emulating() &&
+                           !touch_tracker::takes_touch(self.data.0, surface_ptr);
+            let (sx, sy) = (lx - surface_x, ly - surface_y);
+            surface.run(|surface| if emulated {
+                                      self.pointer_notify_enter(surface, sx, sy);
+                                      self.pointer_notify_motion(time, sx, sy);
+                                      self.pointer_notify_button(time,
+                                                                 pointer::event::BTN_LEFT,
+                                                                 WLR_BUTTON_PRESSED as u32);
+                                      self.pointer_notify_frame();
+                                  } else {
+                                      self.touch_notify_down(surface, time, touch_id, sx, sy);
+                                  })
+                   .ok()?;
+            tracker.remove(touch_id);
+            tracker.points.push(TrackedTouch { touch_id,
+                                               surface: surface.clone(),
+                                               lx,
+                                               ly,
+                                               surface_x,
+                                               surface_y,
+                                               emulated });
+            Some(surface)
+        }
+    }
+
+    /// Notify the seat that the touch point that went down with
+    /// `touch_down_at` moved to the layout coordinates.
+    ///
+    /// Returns false if the point isn't tracked.
+    pub fn touch_motion_at(&self, time: Duration, touch_id: TouchId, lx: f64, ly: f64) -> bool {
+        unsafe {
+            let state = (*self.data.0).data as *mut SeatState;
+            let point = match (*state).touch_points.get_mut(touch_id) {
+                Some(point) => point,
+                None => return false
+            };
+            point.lx = lx;
+            point.ly = ly;
+            let (sx, sy) = point.surface_coords();
+            if point.emulated {
+                self.pointer_notify_motion(time, sx, sy);
+                self.pointer_notify_frame();
+            } else {
+                self.touch_notify_motion(time, touch_id, sx, sy);
+            }
+            true
+        }
+    }
+
+    /// Notify the seat that the touch point that went down with
+    /// `touch_down_at` is up, and stop tracking it.
+    ///
+    /// Returns false if the point isn't tracked.
+    pub fn touch_up_at(&self, time: Duration, touch_id: TouchId) -> bool {
+        unsafe {
+            let state = (*self.data.0).data as *mut SeatState;
+            let point = match (*state).touch_points.remove(touch_id) {
+                Some(point) => point,
+                None => return false
+            };
+            if point.emulated {
+                self.pointer_notify_button(time,
+                                           pointer::event::BTN_LEFT,
+                                           WLR_BUTTON_RELEASED as u32);
+                self.pointer_notify_frame();
+            } else {
+                self.touch_notify_up(time, touch_id);
+            }
+            true
+        }
+    }
+
+    /// Get the touch point that went down with `touch_down_at`, if it's still
+    /// down.
+    pub fn tracked_touch(&self, touch_id: TouchId) -> Option<TrackedTouch> {
+        unsafe {
+            let state = (*self.data.0).data as *mut SeatState;
+            (*state).touch_points.get(touch_id).cloned()
+        }
+    }
+
+    /// Get the touch points that went down with `touch_down_at` and are still
+    /// down, the oldest first.
+    pub fn tracked_touches(&self) -> Vec<TrackedTouch> {
+        unsafe { (*((*self.data.0).data as *mut SeatState)).touch_points.points.clone() }
+    }
+
+    /// Turn touch points on surfaces of clients that don't take touch input
+    /// into pointer input, see the `seat::touch_tracker` module.
+    ///
+    /// Off by default. Only points that go down afterwards are affected.
+    pub fn set_touch_pointer_emulation(&mut self, enabled: bool) {
+        unsafe {
+            (*((*self.data.0).data as *mut SeatState)).touch_points.emulate_pointer = enabled
+        }
+    }
+
+    /// Turn the pointer emulation of the tablet tool on or off, see the
+    /// `seat::tablet_emulation` module.
+    ///
+    /// It's on for every tool by default. Turning it off releases the
+    /// buttons the tool holds down.
+    pub fn set_tablet_pointer_emulation(&mut self, tool: &tablet_tool::Handle, enabled: bool) {
+        let id = tool.id();
+        unsafe {
+            let state = (*self.data.0).data as *mut SeatState;
+            if enabled {
+                (*state).tablet_emulation.disabled.remove(&id);
+            } else {
+                (*state).tablet_emulation.disabled.insert(id);
+                if let Some(tool) = (*state).tablet_emulation.remove(id) {
+                    self.release_tablet_tool(tool, Duration::from_millis(0));
+                }
+            }
+        }
+    }
+
+    /// Determine if the tablet tool drives the pointer of the seat.
+    pub fn tablet_pointer_emulation(&self, tool: &tablet_tool::Handle) -> bool {
+        unsafe {
+            (*((*self.data.0).data as *mut SeatState)).tablet_emulation.enabled(tool.id())
+        }
+    }
+
+    /// Notify the seat that the tablet tool moved to the layout coordinates,
+    /// over the views stacked from the bottom to the top.
+    ///
+    /// Returns whether the pointer was moved along with the tool.
+    pub fn tablet_tool_notify_motion_at(&self,
+                                        views: &[(View, Origin)],
+                                        tool: &tablet_tool::Handle,
+                                        time: Duration,
+                                        lx: f64,
+                                        ly: f64)
+                                        -> bool {
+        let id = tool.id();
+        unsafe {
+            let state = (*self.data.0).data as *mut SeatState;
+            if !(*state).tablet_emulation.enabled(id) {
+                return false
+            }
+            let tool = (*state).tablet_emulation.tool(id);
+            let focus = tablet_emulation::focus_at(tool, views, lx, ly);
+            let (surface, surface_x, surface_y) = match focus {
+                Some(focus) => focus,
+                None => {
+                    if tool.focus.take().is_some() {
+                        self.pointer_clear_focus();
+                    }
+                    return true
+                }
+            };
+            let (sx, sy) = (lx - surface_x, ly - surface_y);
+            let entered = tool.focus
+                              .as_ref()
+                              .map_or(false, |&(ref focused, _, _)| *focused == surface);
+            let moved = surface.run(|surface| {
+                                        if !entered {
+                                            self.pointer_notify_enter(surface, sx, sy);
+                                        }
+                                        self.pointer_notify_motion(time, sx, sy);
+                                        self.pointer_notify_frame();
+                                    });
+            tool.focus = moved.ok().map(|_| (surface, surface_x, surface_y));
+            true
+        }
+    }
+
+    /// Notify the seat that the tip of the tablet tool touched the tablet or
+    /// left it, which presses or releases the left pointer button.
+    ///
+    /// Returns whether the pointer button was emulated.
+    pub fn tablet_tool_notify_tip(&self,
+                                  tool: &tablet_tool::Handle,
+                                  event: &tablet_tool::event::Tip)
+                                  -> bool {
+        let state = if event.state() == WLR_TABLET_TOOL_TIP_DOWN {
+            WLR_BUTTON_PRESSED
+        } else {
+            WLR_BUTTON_RELEASED
+        };
+        self.tablet_tool_button(tool, event.time(), tablet_emulation::TIP_BUTTON, state)
+    }
+
+    /// Notify the seat that a button of the tablet tool was pressed or
+    /// released, which presses or releases the pointer button it stands for.
+    ///
+    /// Returns whether the pointer button was emulated.
+    pub fn tablet_tool_notify_button(&self,
+                                     tool: &tablet_tool::Handle,
+                                     event: &tablet_tool::event::Button)
+                                     -> bool {
+        let button = tablet_emulation::pointer_button(event.button());
+        self.tablet_tool_button(tool, event.time(), button, event.state())
+    }
+
+    /// Notify the seat that the tablet tool came into or left proximity.
+    ///
+    /// Once it left, the buttons it holds down are released and the pointer
+    /// leaves the surface it was over.
+    pub fn tablet_tool_notify_proximity(&self,
+                                        tool: &tablet_tool::Handle,
+                                        event: &tablet_tool::event::Proximity) {
+        if event.state() != WLR_TABLET_TOOL_PROXIMITY_OUT {
+            return
+        }
+        unsafe {
+            let state = (*self.data.0).data as *mut SeatState;
+            if let Some(tool) = (*state).tablet_emulation.remove(tool.id()) {
+                self.release_tablet_tool(tool, event.time());
+            }
+        }
+    }
+
+    fn tablet_tool_button(&self,
+                          tool: &tablet_tool::Handle,
+                          time: Duration,
+                          button: u32,
+                          state: wlr_button_state)
+                          -> bool {
+        let id = tool.id();
+        unsafe {
+            let seat_state = (*self.data.0).data as *mut SeatState;
+            if !(*seat_state).tablet_emulation.enabled(id) {
+                return false
+            }
+            let tool = (*seat_state).tablet_emulation.tool(id);
+            if state == WLR_BUTTON_PRESSED {
+                if tool.buttons.contains(&button) {
+                    return true
+                }
+                tool.buttons.push(button);
+            } else if tool.buttons.iter().any(|&held| held == button) {
+                tool.buttons.retain(|&held| held != button);
+            } else {
+                return true
+            }
+            self.pointer_notify_button(time, button, state as u32);
+            self.pointer_notify_frame();
+            true
+        }
+    }
+
+    /// Release the buttons the tablet tool holds down, and take the pointer
+    /// off its surface.
+    fn release_tablet_tool(&self, tool: tablet_emulation::Tool, time: Duration) {
+        for button in tool.buttons {
+            self.pointer_notify_button(time, button, WLR_BUTTON_RELEASED as u32);
+        }
+        self.pointer_notify_frame();
+        if tool.focus.is_some() {
+            self.pointer_clear_focus();
+        }
+    }
+
     pub(crate) unsafe fn as_ptr(&self) -> *mut wlr_seat {
         self.data.0
     }
 }
 
+/// Get the seat of the wlr_seat, unless it's already being destroyed.
+pub(crate) unsafe fn seat_ref<'seat>(seat: *mut wlr_seat) -> Option<&'seat Seat> {
+    let state = (*seat).data as *mut SeatState;
+    if state.is_null() {
+        return None
+    }
+    Some(&*(*state).seat)
+}
+
+/// Get the pointer events of the current frame, if the seat groups them into
+/// frames and they go to the focused client as is.
+unsafe fn pointer_frame_events<'seat>(seat: *mut wlr_seat)
+                                      -> Option<&'seat mut Vec<pointer_frame::Event>> {
+    let state = (*seat).data as *mut SeatState;
+    if state.is_null() || (*seat).pointer_state.focused_client.is_null() ||
+       wlr_seat_pointer_has_grab(seat) {
+        return None
+    }
+    (*state).pointer_frame.as_mut()
+}
+
+/// Stop the kinetic scroll of the seat, if one is going.
+unsafe fn stop_kinetic_scroll(seat: *mut wlr_seat) {
+    let state = (*seat).data as *mut SeatState;
+    if state.is_null() {
+        return
+    }
+    if let Some(kinetic) = (*state).kinetic.as_mut() {
+        kinetic.stop();
+    }
+}
+
+/// Notify the seat of an axis event of a kinetic scroll, as a frame of its
+/// own.
+pub(crate) unsafe fn notify_kinetic_axis(seat: *mut wlr_seat,
+                                         time: Duration,
+                                         orientation: wlr_axis_orientation,
+                                         value: f64) {
+    let time = time.to_ms();
+    let source = WLR_AXIS_SOURCE_CONTINUOUS;
+    match pointer_frame_events(seat) {
+        Some(events) => {
+            events.push(pointer_frame::Event::Axis { time,
+                                                     orientation,
+                                                     value,
+                                                     value_discrete: 0,
+                                                     source });
+            flush_pointer_frame(seat);
+        },
+        None => wlr_seat_pointer_notify_axis(seat, time, orientation, value, 0, source)
+    }
+}
+
+/// Send the pointer events of the current frame, e.g. before they could end
+/// up at another surface.
+unsafe fn flush_pointer_frame(seat: *mut wlr_seat) {
+    let state = (*seat).data as *mut SeatState;
+    if state.is_null() {
+        return
+    }
+    if let Some(events) = (*state).pointer_frame.as_mut() {
+        pointer_frame::send(seat, events);
+        events.clear();
+    }
+}
+
+/// Set the active keyboard of the seat, carrying over the lock state if the
+/// seat syncs it.
+pub(crate) unsafe fn set_keyboard(seat: *mut wlr_seat, device: *mut wlr_input_device) {
+    let state = (*seat).data as *mut SeatState;
+    let previous = (*seat).keyboard_state.keyboard;
+    wlr_seat_set_keyboard(seat, device);
+    if state.is_null() || !(*state).sync_locks || device.is_null() {
+        return
+    }
+    let keyboard = (*device).__bindgen_anon_1.keyboard;
+    let locks = if !previous.is_null() && previous != keyboard {
+        Some(keyboard::leds(previous))
+    } else if previous.is_null() {
+        (*state).last_locks
+    } else {
+        None
+    };
+    if let Some(locks) = locks {
+        keyboard::set_locks(keyboard, locks);
+    }
+}
+
+/// Remember the lock state of the keyboard that's being detached from the
+/// seat, for the next keyboard.
+pub(crate) unsafe fn keyboard_detached(seat: *mut wlr_seat, device: *mut wlr_input_device) {
+    let state = (*seat).data as *mut SeatState;
+    if state.is_null() || (*seat).keyboard_state.keyboard != (*device).__bindgen_anon_1.keyboard {
+        return
+    }
+    (*state).last_locks = Some(keyboard::leds((*seat).keyboard_state.keyboard));
+}
+
 impl fmt::Debug for Seat {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "Seat {:p}", self.data.0)
@@ -808,6 +1721,7 @@ impl Drop for Seat {
     fn drop(&mut self) {
         let seat_ptr = self.data.0;
         unsafe {
+            utils::user_data::clear(seat_ptr);
             let data = Box::from_raw((*seat_ptr).data as *mut SeatState);
             let mut manager = Box::from_raw(data.seat);
             assert_eq!(Rc::strong_count(&data.counter),
@@ -844,6 +1758,15 @@ impl Drop for Seat {
             ffi_dispatch!(WAYLAND_SERVER_HANDLE,
                           wl_list_remove,
                           &mut (*manager.new_drag_icon_listener()).link as *mut _ as _);
+            #[cfg(feature = "idle")]
+            idle::remove_seat(seat_ptr);
+            #[cfg(feature = "shortcuts_inhibit")]
+            shortcuts_inhibit::seat_destroyed(seat_ptr);
+            #[cfg(feature = "layer_shell")]
+            layer_shell::seat_destroyed(seat_ptr);
+            popup_grab::seat_destroyed(seat_ptr);
+            devices::detach_all(seat_ptr);
+            SEATS.with(|seats| seats.borrow_mut().retain(|&seat| seat != seat_ptr));
             wlr_seat_destroy(seat_ptr);
             ffi_dispatch!(WAYLAND_SERVER_HANDLE,
                           wl_list_remove,
@@ -865,6 +1788,26 @@ impl Handle {
         }
     }
 
+    /// Get the unique id of the seat this handle refers to.
+    ///
+    /// See `utils::Handle::id` for details.
+    pub fn id(&self) -> u64 {
+        utils::user_data::id(self.seat as usize, &self.handle)
+    }
+
+    /// Call the function when the seat is destroyed.
+    ///
+    /// See `utils::Handle::on_destroy` for details.
+    pub fn on_destroy<F>(&self, callback: F) -> HandleResult<()>
+        where F: FnOnce() + 'static
+    {
+        if utils::user_data::on_destroy(self.seat as usize, &self.handle, Box::new(callback)) {
+            Ok(())
+        } else {
+            Err(HandleErr::AlreadyDropped)
+        }
+    }
+
     /// Creates an seat::Handle from the raw pointer, using the saved
     /// user data to recreate the memory model.
     pub(crate) unsafe fn from_ptr(seat: *mut wlr_seat) -> Self {
@@ -883,7 +1826,9 @@ impl Handle {
     /// This function is unsafe, because it creates an unbound `Seat`
     /// which may live forever..
     /// But a seat could be destroyed else where.
+    #[track_caller]
     pub(crate) unsafe fn upgrade(&self) -> HandleResult<Box<Seat>> {
+        let location = Location::caller();
         self.handle.upgrade()
             .ok_or(HandleErr::AlreadyDropped)
             // NOTE
@@ -891,9 +1836,9 @@ impl Handle {
             // pointer to exist!
             .and_then(|check| {
                 if check.get() {
-                    return Err(HandleErr::AlreadyBorrowed)
+                    return Err(utils::already_borrowed::<Seat>(&check))
                 }
-                check.set(true);
+                utils::borrow_lock(&check, location);
                 Ok(Seat::from_ptr(self.seat))
             })
     }
@@ -915,6 +1860,7 @@ impl Handle {
     ///
     /// So don't nest `run` calls or call this in a Seat callback
     /// and everything will be ok :).
+    #[track_caller]
     pub fn run<F, R>(&self, runner: F) -> HandleResult<R>
         where F: FnOnce(&mut Seat) -> R
     {
@@ -931,7 +1877,7 @@ impl Handle {
                                                    seat_ptr);
                                           panic!("Lock in incorrect state!");
                                       }
-                                      check.set(false);
+                                      utils::release_lock(&check);
                                   });
         match res {
             Ok(res) => Ok(res),
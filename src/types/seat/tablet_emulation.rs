@@ -0,0 +1,89 @@
+//! Driving the pointer of a seat with tablet tools.
+//!
+//! This version of wlroots doesn't speak the tablet protocol, so no client
+//! gets tablet input of its own. To keep drawing tablets usable, the tablet
+//! tool events the seat is notified of with `Seat::tablet_tool_notify_*` are
+//! turned into pointer input instead: moving the tool in proximity moves the
+//! pointer over the surface under it, the tip is the left button and the two
+//! stylus buttons are the right and middle buttons. While the tip or a
+//! button is down the pointer stays on the surface it was pressed on, like
+//! with a mouse.
+//!
+//! The emulation is on for every tool unless it's turned off for the tool
+//! with `Seat::set_tablet_pointer_emulation`, e.g. for a tool the compositor
+//! only uses for its own gestures.
+
+use std::collections::HashSet;
+
+use {area::Origin, surface, view::View,
+     input::{pointer::event::{BTN_LEFT, BTN_MIDDLE, BTN_RIGHT},
+             tablet_tool::event::{BTN_STYLUS, BTN_STYLUS2}},
+     seat::touch_tracker};
+
+/// What a tool in proximity is doing with the pointer.
+#[derive(Debug, Clone)]
+pub(crate) struct Tool {
+    pub id: u64,
+    /// The surface the tool has the pointer on, with where it was in layout
+    /// coordinates when the pointer entered it.
+    pub focus: Option<(surface::Handle, f64, f64)>,
+    /// The pointer buttons held down by the tool.
+    pub buttons: Vec<u32>
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Emulation {
+    /// The ids of the tools the emulation was turned off for.
+    pub disabled: HashSet<u64>,
+    /// The tools in proximity.
+    pub tools: Vec<Tool>
+}
+
+impl Emulation {
+    pub fn enabled(&self, id: u64) -> bool {
+        !self.disabled.contains(&id)
+    }
+
+    /// Get the state of the tool, starting to track it if it's new.
+    pub fn tool(&mut self, id: u64) -> &mut Tool {
+        match self.tools.iter().position(|tool| tool.id == id) {
+            Some(index) => &mut self.tools[index],
+            None => {
+                self.tools.push(Tool { id, focus: None, buttons: Vec::new() });
+                self.tools.last_mut().unwrap()
+            }
+        }
+    }
+
+    pub fn remove(&mut self, id: u64) -> Option<Tool> {
+        let index = self.tools.iter().position(|tool| tool.id == id)?;
+        Some(self.tools.remove(index))
+    }
+}
+
+/// Get the surface the tool at the layout coordinates has the pointer on,
+/// with where it is in layout coordinates.
+///
+/// Returns `None` for the surface if the tool isn't over any.
+pub(crate) fn focus_at(tool: &Tool,
+                       views: &[(View, Origin)],
+                       lx: f64,
+                       ly: f64)
+                       -> Option<(surface::Handle, f64, f64)> {
+    if !tool.buttons.is_empty() {
+        return tool.focus.clone()
+    }
+    touch_tracker::surface_at(views, lx, ly)
+}
+
+/// Get the pointer button a button of a tablet tool stands for.
+pub(crate) fn pointer_button(button: u32) -> u32 {
+    match button {
+        BTN_STYLUS => BTN_RIGHT,
+        BTN_STYLUS2 => BTN_MIDDLE,
+        button => button
+    }
+}
+
+/// The pointer button of the tip of a tool.
+pub(crate) const TIP_BUTTON: u32 = BTN_LEFT;
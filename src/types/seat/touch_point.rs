@@ -1,5 +1,7 @@
 use wlroots_sys::wlr_touch_point;
 
+use surface;
+
 #[derive(Clone)]
 pub struct TouchPoint {
     touch_point: *mut wlr_touch_point
@@ -23,6 +25,24 @@ impl TouchPoint {
         unsafe { TouchId((*self.touch_point).touch_id) }
     }
 
+    /// Get the surface that got the touch down of this point, if it's still
+    /// alive.
+    pub fn surface(&self) -> Option<surface::Handle> {
+        unsafe {
+            let surface = (*self.touch_point).surface;
+            if surface.is_null() || (*surface).data.is_null() {
+                return None
+            }
+            Some(surface::Handle::from_ptr(surface))
+        }
+    }
+
+    /// Get where the point is, relative to the surface that got its touch
+    /// down.
+    pub fn coords(&self) -> (f64, f64) {
+        unsafe { ((*self.touch_point).sx, (*self.touch_point).sy) }
+    }
+
     #[allow(dead_code)]
     pub(crate) unsafe fn as_ptr(&self) -> *mut wlr_touch_point {
         self.touch_point
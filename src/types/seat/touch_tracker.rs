@@ -0,0 +1,101 @@
+//! Keeping track of the touch points of a seat in layout coordinates.
+//!
+//! `Seat::touch_down_at` finds the surface under a new touch point in a stack
+//! of views and remembers where it is, so `Seat::touch_motion_at` can turn
+//! the layout coordinates of later motion into coordinates relative to that
+//! surface. Each point keeps the focus of its touch down until it's up, like
+//! wl_touch expects.
+//!
+//! Clients that didn't bind wl_touch (e.g. most XWayland clients) would
+//! never see the touch input, so with pointer emulation on the first touch
+//! point that lands on one of them is turned into the pointer entering the
+//! surface, moving with the point and holding the left button while it's
+//! down.
+
+use wlroots_sys::{wl_list, wlr_seat, wlr_seat_client_for_wl_client, wlr_surface};
+
+use {area::Origin, surface, view::View, seat::TouchId};
+
+/// A touch point that's down, as tracked by the seat.
+#[derive(Debug, Clone)]
+pub struct TrackedTouch {
+    /// The id the touch device gave the point.
+    pub touch_id: TouchId,
+    /// The surface that got the touch down, and keeps the focus of the point.
+    pub surface: surface::Handle,
+    /// Where the point is in layout coordinates.
+    pub lx: f64,
+    pub ly: f64,
+    /// Where the surface was in layout coordinates when the point went down.
+    pub(crate) surface_x: f64,
+    pub(crate) surface_y: f64,
+    /// Whether the point drives the pointer, because the client of the
+    /// surface doesn't take touch input.
+    pub emulated: bool
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Tracker {
+    pub points: Vec<TrackedTouch>,
+    pub emulate_pointer: bool
+}
+
+impl TrackedTouch {
+    /// Get where the point is relative to its surface.
+    pub fn surface_coords(&self) -> (f64, f64) {
+        (self.lx - self.surface_x, self.ly - self.surface_y)
+    }
+}
+
+impl Tracker {
+    pub fn get(&self, touch_id: TouchId) -> Option<&TrackedTouch> {
+        self.points.iter().find(|point| point.touch_id == touch_id)
+    }
+
+    pub fn get_mut(&mut self, touch_id: TouchId) -> Option<&mut TrackedTouch> {
+        self.points.iter_mut().find(|point| point.touch_id == touch_id)
+    }
+
+    pub fn remove(&mut self, touch_id: TouchId) -> Option<TrackedTouch> {
+        let index = self.points.iter().position(|point| point.touch_id == touch_id)?;
+        Some(self.points.remove(index))
+    }
+
+    /// Determine if a point already drives the pointer.
+    pub fn emulating(&self) -> bool {
+        self.points.iter().any(|point| point.emulated)
+    }
+}
+
+/// Find the surface under the layout coordinates, along with where it is in
+/// the layout.
+///
+/// The views are stacked from the bottom to the top, like for
+/// `View::is_visible`.
+pub(crate) fn surface_at(views: &[(View, Origin)],
+                         lx: f64,
+                         ly: f64)
+                         -> Option<(surface::Handle, f64, f64)> {
+    views.iter()
+         .rev()
+         .filter_map(|&(ref view, origin)| {
+                         let (x, y) = (lx - origin.x as f64, ly - origin.y as f64);
+                         view.surface_at(x, y).ok().and_then(|found| found)
+                     })
+         .next()
+         .map(|(surface, sx, sy)| (surface, lx - sx, ly - sy))
+}
+
+/// Determine if the client of the surface bound wl_touch on the seat.
+pub(crate) unsafe fn takes_touch(seat: *mut wlr_seat, surface: *mut wlr_surface) -> bool {
+    let client = surface::surface_client(surface);
+    if client.is_null() {
+        return false
+    }
+    let seat_client = wlr_seat_client_for_wl_client(seat, client);
+    if seat_client.is_null() {
+        return false
+    }
+    let touches: *mut wl_list = &mut (*seat_client).touches;
+    (*touches).next != touches
+}
@@ -1,2 +1,30 @@
+pub(crate) mod popup_grab;
 pub mod xdg_shell_v6;
 pub mod xdg_shell;
+
+bitflags! {
+    /// The events of a shell surface its handler is called for, see
+    /// `xdg_shell::Handler::events` and `xwayland::surface::Handler::events`.
+    ///
+    /// Destroying, mapping and unmapping the surface aren't in here, as
+    /// they're always listened to. The events a shell doesn't have are
+    /// ignored (e.g. `TITLE` for the XDG shells, `NEW_POPUP` for XWayland).
+    pub struct ShellEvents: u32 {
+        const COMMIT = 1 << 0;
+        const PING_TIMEOUT = 1 << 1;
+        const NEW_POPUP = 1 << 2;
+        const MAXIMIZE = 1 << 3;
+        const MINIMIZE = 1 << 4;
+        const MOVE = 1 << 5;
+        const RESIZE = 1 << 6;
+        const FULLSCREEN = 1 << 7;
+        const SHOW_WINDOW_MENU = 1 << 8;
+        const CONFIGURE = 1 << 9;
+        const TITLE = 1 << 10;
+        const CLASS = 1 << 11;
+        const PARENT = 1 << 12;
+        const PID = 1 << 13;
+        const WINDOW_TYPE = 1 << 14;
+        const HINTS = 1 << 15;
+    }
+}
@@ -0,0 +1,185 @@
+//! The parts of popup grabs that wlroots leaves to the compositor.
+//!
+//! When an xdg popup takes an explicit grab, wlroots keeps the pointer on the
+//! client of the popup and dismisses the popups once the user clicks on
+//! another client, but it doesn't move the keyboard focus. This gives the
+//! keyboard of the seat to the grabbing popup once it's mapped, hands it
+//! down the chain of popups as they're closed, and back to what had it before
+//! once the last one is. Touching another client dismisses the popups too.
+//!
+//! Both the stable and the v6 xdg shell are handled the same way.
+
+use std::cell::RefCell;
+
+use wlroots_sys::{wlr_seat, wlr_surface, wlr_xdg_surface, wlr_xdg_surface_send_close,
+                  wlr_xdg_surface_v6, wlr_xdg_surface_v6_send_close,
+                  wlr_seat_keyboard_clear_focus};
+
+use {seat, surface};
+#[cfg(feature = "shortcuts_inhibit")]
+use extensions::shortcuts_inhibit;
+
+/// The xdg surface of a popup, of either version of the shell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PopupSurface {
+    Stable(*mut wlr_xdg_surface),
+    V6(*mut wlr_xdg_surface_v6)
+}
+
+/// A mapped popup that grabbed the seat.
+struct Grab {
+    seat: *mut wlr_seat,
+    surface: *mut wlr_surface,
+    popup: PopupSurface
+}
+
+struct State {
+    /// The grabbing popups, from the parents to the children.
+    grabs: Vec<Grab>,
+    /// The surface each grabbed seat had the keyboard focus on before the
+    /// first popup took it.
+    previous_focus: Vec<(*mut wlr_seat, surface::Handle)>
+}
+
+thread_local! {
+    static STATE: RefCell<State> = RefCell::new(State { grabs: Vec::new(),
+                                                        previous_focus: Vec::new() });
+}
+
+impl PopupSurface {
+    unsafe fn surface(&self) -> *mut wlr_surface {
+        match *self {
+            PopupSurface::Stable(popup) => (*popup).surface,
+            PopupSurface::V6(popup) => (*popup).surface
+        }
+    }
+
+    /// Get the seat the popup grabbed, if it did.
+    unsafe fn grabbed_seat(&self) -> *mut wlr_seat {
+        match *self {
+            PopupSurface::Stable(popup) => (*(*popup).__bindgen_anon_1.popup).seat,
+            PopupSurface::V6(popup) => (*(*popup).__bindgen_anon_1.popup).seat
+        }
+    }
+
+    /// Send `popup_done`.
+    unsafe fn dismiss(&self) {
+        match *self {
+            PopupSurface::Stable(popup) => wlr_xdg_surface_send_close(popup),
+            PopupSurface::V6(popup) => wlr_xdg_surface_v6_send_close(popup)
+        }
+    }
+}
+
+/// Give the keyboard to the popup that was mapped, if it grabbed a seat.
+pub(crate) unsafe fn popup_mapped(popup: PopupSurface) {
+    let seat = popup.grabbed_seat();
+    let surface = popup.surface();
+    if seat.is_null() || surface.is_null() {
+        return
+    }
+    STATE.with(|state| {
+                   let mut state = state.borrow_mut();
+                   if !state.grabs.iter().any(|grab| grab.seat == seat) {
+                       state.previous_focus.retain(|&(other, _)| other != seat);
+                       let focused = (*seat).keyboard_state.focused_surface;
+                       if !focused.is_null() && !(*focused).data.is_null() {
+                           state.previous_focus
+                                .push((seat, surface::Handle::from_ptr(focused)));
+                       }
+                   }
+                   state.grabs.push(Grab { seat, surface, popup });
+               });
+    if !seat::keyboard_blocked(surface) {
+        seat::give_keyboard_focus(seat, surface);
+    }
+}
+
+/// Give the keyboard the unmapped popup had to its parent popup, or back to
+/// what had it before the popups grabbed the seat.
+pub(crate) unsafe fn popup_unmapped(popup: PopupSurface) {
+    let ungrabbed = STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let index = state.grabs.iter().position(|grab| grab.popup == popup)?;
+        let grab = state.grabs.remove(index);
+        let next = state.grabs
+                        .iter()
+                        .rev()
+                        .find(|other| other.seat == grab.seat)
+                        .map(|other| surface::Handle::from_ptr(other.surface));
+        let next = match next {
+            Some(next) => Some(next),
+            None => {
+                let index = state.previous_focus
+                                 .iter()
+                                 .position(|&(other, _)| other == grab.seat);
+                index.map(|index| state.previous_focus.remove(index).1)
+            }
+        };
+        Some((grab, next))
+    });
+    let (grab, next) = match ungrabbed {
+        Some(ungrabbed) => ungrabbed,
+        None => return
+    };
+    if (*grab.seat).keyboard_state.focused_surface != grab.surface {
+        return
+    }
+    let next = next.and_then(|next| next.run(|surface| surface.as_ptr()).ok())
+                   .filter(|&next| !seat::keyboard_blocked(next));
+    match next {
+        Some(next) => seat::give_keyboard_focus(grab.seat, next),
+        None => {
+            wlr_seat_keyboard_clear_focus(grab.seat);
+            #[cfg(feature = "shortcuts_inhibit")]
+            shortcuts_inhibit::focus_changed(grab.seat);
+        }
+    }
+}
+
+/// Determine if popups grabbed the seat.
+pub(crate) fn grabbed(seat: *mut wlr_seat) -> bool {
+    STATE.with(|state| state.borrow().grabs.iter().any(|grab| grab.seat == seat))
+}
+
+/// Dismiss the popups that grabbed the seat if the surface is of another
+/// client, e.g. because the user touched it.
+pub(crate) unsafe fn input_outside(seat: *mut wlr_seat, surface: *mut wlr_surface) {
+    let client = surface::surface_client(surface);
+    let outside = STATE.with(|state| {
+        state.borrow()
+             .grabs
+             .iter()
+             .any(|grab| {
+                      grab.seat == seat && surface::surface_client(grab.surface) != client
+                  })
+    });
+    if outside {
+        dismiss(seat)
+    }
+}
+
+/// Send `popup_done` to the popups that grabbed the seat, the topmost first.
+pub(crate) unsafe fn dismiss(seat: *mut wlr_seat) {
+    let popups = STATE.with(|state| {
+        state.borrow()
+             .grabs
+             .iter()
+             .rev()
+             .filter(|grab| grab.seat == seat)
+             .map(|grab| grab.popup)
+             .collect::<Vec<_>>()
+    });
+    for popup in popups {
+        popup.dismiss()
+    }
+}
+
+/// Forget the seat.
+pub(crate) fn seat_destroyed(seat: *mut wlr_seat) {
+    STATE.with(|state| {
+                   let mut state = state.borrow_mut();
+                   state.grabs.retain(|grab| grab.seat != seat);
+                   state.previous_focus.retain(|&(other, _)| other != seat);
+               })
+}
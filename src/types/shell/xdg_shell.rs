@@ -1,6 +1,6 @@
 //! TODO Documentation
 
-use std::{cell::Cell, rc::{Rc, Weak}, panic, ptr};
+use std::{any::Any, cell::Cell, rc::{Rc, Weak}, panic, ptr};
 
 use libc::c_void;
 use wlroots_sys::{wlr_xdg_popup, wlr_xdg_surface, wlr_xdg_surface_ping,
@@ -165,39 +165,48 @@ impl Surface {
         }
     }
 
-    /// Find a surface within this surface at the surface-local coordinates.
+    /// Find the surface of the popups and subsurfaces of this surface that
+    /// takes input at the surface-local coordinates, the topmost first.
     ///
-    /// Returns the popup and coordinates in the topmost surface coordinate system
-    /// or None if no popup is found at that location.
-    pub fn surface_at(&mut self,
-                      sx: f64,
-                      sy: f64,
-                      sub_sx: &mut f64,
-                      sub_sy: &mut f64)
-                      -> Option<surface::Handle> {
+    /// Returns the surface with the coordinates relative to it, or `None` if
+    /// none accepts input there, e.g. because it's outside the input region
+    /// the client set.
+    pub fn surface_at(&self, sx: f64, sy: f64) -> Option<(surface::Handle, f64, f64)> {
+        let (mut sub_x, mut sub_y) = (0.0, 0.0);
         unsafe {
             let sub_surface =
-                wlr_xdg_surface_surface_at(self.shell_surface, sx, sy, sub_sx, sub_sy);
+                wlr_xdg_surface_surface_at(self.shell_surface, sx, sy, &mut sub_x, &mut sub_y);
             if sub_surface.is_null() {
                 None
             } else {
-                Some(surface::Handle::from_ptr(sub_surface))
+                Some((surface::Handle::from_ptr(sub_surface), sub_x, sub_y))
             }
         }
     }
 
     pub fn for_each_surface<F>(&self, mut iterator: F)
             where F: FnMut(surface::Handle, i32, i32) {
-        let mut iterator_ref: &mut FnMut(surface::Handle, i32, i32) = &mut iterator;
+        // NOTE The panic is re-thrown once we're out of C.
+        let mut iteration: (&mut FnMut(surface::Handle, i32, i32), Option<Box<Any + Send>>) =
+            (&mut iterator, None);
         unsafe {
             unsafe extern "C" fn c_iterator(wlr_surface: *mut wlr_surface, sx: i32, sy: i32, data: *mut c_void) {
-                let iterator_fn = &mut *(data as *mut &mut FnMut(surface::Handle, i32, i32));
+                let &mut (ref mut iterator_fn, ref mut panic_error) =
+                    &mut *(data as *mut (&mut FnMut(surface::Handle, i32, i32),
+                                         Option<Box<Any + Send>>));
+                if panic_error.is_some() {
+                    return
+                }
                 let surface = surface::Handle::from_ptr(wlr_surface);
-                iterator_fn(surface, sx, sy);
+                let res = panic::catch_unwind(panic::AssertUnwindSafe(|| iterator_fn(surface, sx, sy)));
+                *panic_error = res.err();
             }
-            let iterator_ptr: *mut c_void = &mut iterator_ref as *mut _ as *mut c_void;
+            let iterator_ptr: *mut c_void = &mut iteration as *mut _ as *mut c_void;
             wlr_xdg_surface_for_each_surface(self.shell_surface, Some(c_iterator), iterator_ptr);
         }
+        if let Some(err) = iteration.1 {
+            panic::resume_unwind(err)
+        }
     }
 }
 
@@ -205,6 +214,7 @@ impl Drop for Surface {
     fn drop(&mut self) {
         if Rc::strong_count(&self.liveliness) == 1 {
             wlr_log!(WLR_DEBUG, "Dropped xdg shell {:p}", self.shell_surface);
+            utils::user_data::clear(self.shell_surface);
             let weak_count = Rc::weak_count(&self.liveliness);
             if weak_count > 0 {
                 wlr_log!(WLR_DEBUG,
@@ -269,16 +279,16 @@ impl TopLevel {
                         toplevel }
     }
 
-    /// Get the title associated with this XDG shell toplevel.
-    pub fn title(&self) -> String {
-        unsafe { c_to_rust_string((*self.toplevel).title).expect("Could not parse class as UTF-8") }
+    /// Get the title associated with this XDG shell toplevel, if the client
+    /// set one.
+    pub fn title(&self) -> Option<String> {
+        unsafe { c_to_rust_string((*self.toplevel).title) }
     }
 
-    /// Get the app id associated with this XDG shell toplevel.
-    pub fn app_id(&self) -> String {
-        unsafe {
-            c_to_rust_string((*self.toplevel).app_id).expect("Could not parse class as UTF-8")
-        }
+    /// Get the app id associated with this XDG shell toplevel, if the client
+    /// set one.
+    pub fn app_id(&self) -> Option<String> {
+        unsafe { c_to_rust_string((*self.toplevel).app_id) }
     }
 
     /// Get a handle to the base surface of the xdg tree.
@@ -0,0 +1,123 @@
+//! Keeping the buffer of a surface around after the client replaced or
+//! destroyed it, without copying its contents.
+
+use std::{cell::Cell, ptr, rc::Rc};
+
+use libc::c_int;
+use wlroots_sys::{wl_output_transform, wlr_buffer, wlr_buffer_ref, wlr_buffer_unref, wlr_surface};
+
+use {compositor::{self, Retained}, render::Texture};
+
+/// A reference to the buffer that was committed to a surface, obtained with
+/// `Surface::lock_current_buffer`.
+///
+/// The buffer and its texture stay alive until this is dropped, even if
+/// the surface is destroyed or the client goes away in the meantime (e.g.
+/// to fade out a closed window).
+///
+/// The texture belongs to the renderer of the compositor. Once that's
+/// destroyed (i.e. the compositor shut down) there's no texture anymore, and
+/// the buffer is leaked when this is dropped instead of being released.
+#[derive(Debug)]
+pub struct LockedBuffer {
+    buffer: *mut wlr_buffer,
+    size: (c_int, c_int),
+    scale: i32,
+    transform: wl_output_transform,
+    /// Whether the renderer the texture belongs to is still around.
+    renderer: Rc<Cell<bool>>,
+    /// Counts the texture for the client of the surface, shared by the
+    /// clones.
+    retained: Option<Rc<Retained>>
+}
+
+impl LockedBuffer {
+    /// Lock the buffer, along with the state of the surface it was
+    /// committed with.
+    pub(crate) unsafe fn new(surface: *mut wlr_surface,
+                             buffer: *mut wlr_buffer,
+                             size: (c_int, c_int),
+                             scale: i32,
+                             transform: wl_output_transform)
+                             -> Self {
+        let renderer = if compositor::COMPOSITOR_PTR.is_null() {
+            Rc::new(Cell::new(false))
+        } else {
+            match (*compositor::COMPOSITOR_PTR).renderer {
+                Some(ref renderer) => renderer.liveliness(),
+                // NOTE Without a renderer nothing is uploaded.
+                None => Rc::new(Cell::new(true))
+            }
+        };
+        let retained = Retained::track(surface, buffer, ptr::null_mut(), renderer.clone());
+        LockedBuffer { buffer: wlr_buffer_ref(buffer),
+                       size,
+                       scale,
+                       transform,
+                       renderer,
+                       retained }
+    }
+
+    /// Get the texture the buffer was uploaded to.
+    ///
+    /// Returns `None` if the upload failed, or the renderer is gone.
+    pub fn texture<'buffer>(&'buffer self) -> Option<Texture<'buffer>> {
+        if !self.renderer.get() {
+            return None
+        }
+        unsafe {
+            let texture = (*self.buffer).texture;
+            if texture.is_null() {
+                None
+            } else {
+                Some(Texture::from_ptr(texture))
+            }
+        }
+    }
+
+    /// Get the size of the surface when the buffer was committed, in surface
+    /// coordinates.
+    ///
+    /// Return value is in (width, height) format.
+    pub fn size(&self) -> (c_int, c_int) {
+        self.size
+    }
+
+    /// Get the scale of the buffer.
+    pub fn scale(&self) -> i32 {
+        self.scale
+    }
+
+    /// Get the transform of the buffer.
+    pub fn transform(&self) -> wl_output_transform {
+        self.transform
+    }
+
+    pub unsafe fn as_ptr(&self) -> *mut wlr_buffer {
+        self.buffer
+    }
+}
+
+impl Clone for LockedBuffer {
+    fn clone(&self) -> Self {
+        unsafe {
+            LockedBuffer { buffer: wlr_buffer_ref(self.buffer),
+                           size: self.size,
+                           scale: self.scale,
+                           transform: self.transform,
+                           renderer: self.renderer.clone(),
+                           retained: self.retained.clone() }
+        }
+    }
+}
+
+impl Drop for LockedBuffer {
+    fn drop(&mut self) {
+        // NOTE Releasing the last reference destroys the texture, which
+        // needs the renderer.
+        if !self.renderer.get() {
+            return
+        }
+        unsafe { wlr_buffer_unref(self.buffer) }
+    }
+}
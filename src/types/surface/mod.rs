@@ -1,7 +1,9 @@
 mod surface;
 mod surface_state;
+mod locked_buffer;
 pub mod subsurface;
 pub(crate) mod subsurface_manager;
 
 pub use self::surface::*;
 pub use self::surface_state::*;
+pub use self::locked_buffer::*;
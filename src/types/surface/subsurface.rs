@@ -14,10 +14,20 @@ pub type Handle = utils::Handle<(), wlr_subsurface, Subsurface>;
 
 #[allow(unused_variables)]
 pub trait Handler {
+    #[deprecated(note = "Use `destroyed` instead")]
     fn on_destroy(&mut self,
                   compositor_handle: compositor::Handle,
                   subsurface_handle: Handle,
                   surface_handle: surface::Handle) {}
+
+    /// Callback that is triggered when the subsurface is destroyed.
+    #[allow(deprecated)]
+    fn destroyed(&mut self,
+                 compositor_handle: compositor::Handle,
+                 subsurface_handle: Handle,
+                 surface_handle: surface::Handle) {
+        self.on_destroy(compositor_handle, subsurface_handle, surface_handle)
+    }
 }
 
 wayland_listener!(pub(crate) InternalSubsurface, (Subsurface, Box<Handler>), [
@@ -31,7 +41,7 @@ wayland_listener!(pub(crate) InternalSubsurface, (Subsurface, Box<Handler>), [
             Some(handle) => handle,
             None => return
         };
-        manager.on_destroy(compositor, subsurface.weak_reference(), surface);
+        manager.destroyed(compositor, subsurface.weak_reference(), surface);
         Box::from_raw((*subsurface_ptr).data as *mut InternalSubsurface);
     };
 ]);
@@ -133,6 +143,7 @@ impl Handleable<(), wlr_subsurface> for Subsurface {
 
 impl Drop for InternalSubsurface {
     fn drop(&mut self) {
+        utils::user_data::clear(self.data.0.subsurface);
         unsafe {
             ffi_dispatch!(WAYLAND_SERVER_HANDLE,
                           wl_list_remove,
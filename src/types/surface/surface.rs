@@ -1,22 +1,28 @@
 //! TODO Documentation
 
-use libc::{self, c_double};
-use std::{panic, ptr, cell::Cell, rc::{Rc, Weak}, time::Duration};
+use libc::{self, c_double, c_void};
+use std::{any::Any, panic, ptr, cell::Cell, rc::{Rc, Weak}, time::Duration};
 
-use wayland_sys::server::WAYLAND_SERVER_HANDLE;
+use wayland_sys::server::{wl_client, WAYLAND_SERVER_HANDLE};
 use wayland_sys::server::signal::wl_signal_add;
 use wlroots_sys::{timespec, wlr_subsurface, wlr_surface, wlr_surface_get_root_surface,
                   wlr_surface_has_buffer, wlr_surface_point_accepts_input, wlr_surface_send_enter,
                   wlr_surface_send_frame_done, wlr_surface_send_leave, wlr_surface_surface_at,
-                  wlr_surface_is_xdg_surface, wlr_surface_get_texture};
+                  wlr_surface_is_xdg_surface, wlr_surface_get_texture,
+                  wlr_surface_for_each_surface};
 
-use {compositor,
-     surface::{self,
+use {area::{Area, Origin, Size},
+     compositor,
+     surface::{self, LockedBuffer,
                subsurface::{self, Subsurface, InternalSubsurface},
                subsurface_manager::SubsurfaceManager},
      output::Output,
-     render::Texture,
-     utils::{self, Handleable, HandleErr, HandleResult, c_to_rust_string}};
+     render::{snapshot, GenericRenderer, Pixels, PixmanRegion, Snapshot, Texture},
+     utils::{self, Handleable, HandleErr, HandleResult, c_to_rust_string, log::LogContext}};
+#[cfg(feature = "content_type")]
+use extensions::content_type::{self, ContentType};
+#[cfg(feature = "tearing_control")]
+use extensions::tearing_control::{self, PresentationHint};
 
 pub type Handle = utils::Handle<Weak<Box<SubsurfaceManager>>,
                                 wlr_surface,
@@ -36,7 +42,16 @@ pub trait Handler {
         None
     }
 
+    #[deprecated(note = "Use `destroyed` instead")]
     fn on_destroy(&mut self, compositor::Handle, Handle) {}
+
+    /// Callback that is triggered when the surface is destroyed.
+    ///
+    /// Its subsurfaces aren't destroyed with it, they're only detached.
+    #[allow(deprecated)]
+    fn destroyed(&mut self, compositor_handle: compositor::Handle, surface_handle: Handle) {
+        self.on_destroy(compositor_handle, surface_handle)
+    }
 }
 
 impl Handler for () {}
@@ -44,16 +59,20 @@ impl Handler for () {}
 wayland_listener!(pub(crate) InternalSurface, (Surface, Box<Handler>), [
     on_commit_listener => on_commit_notify: |this: &mut InternalSurface, _data: *mut libc::c_void,|
     unsafe {
+        let _log = LogContext::client(surface_client(this.data.0.as_ptr()));
         let (ref mut surface, ref mut manager) = this.data;
         let compositor = match compositor::handle() {
             Some(handle) => handle,
             None => return
         };
-        manager.on_commit(compositor, surface.weak_reference());
+        let surface_handle = surface.weak_reference();
+        wlr_trace_id!(surface_handle.id());
+        manager.on_commit(compositor, surface_handle);
     };
     new_subsurface_listener => new_listener_notify: |this: &mut InternalSurface,
                                                      data: *mut libc::c_void,|
     unsafe {
+        let _log = LogContext::client(surface_client(this.data.0.as_ptr()));
         let (ref mut surface, ref mut manager) = this.data;
         let compositor = match compositor::handle() {
             Some(handle) => handle,
@@ -72,12 +91,13 @@ wayland_listener!(pub(crate) InternalSurface, (Surface, Box<Handler>), [
     };
     on_destroy_listener => on_destroy_notify: |this: &mut InternalSurface, data: *mut libc::c_void,|
     unsafe {
+        let _log = LogContext::client(surface_client(this.data.0.as_ptr()));
         let (ref mut surface, ref mut manager) = this.data;
         let compositor = match compositor::handle() {
             Some(handle) => handle,
             None => return
         };
-        manager.on_destroy(compositor, surface.weak_reference());
+        manager.destroyed(compositor, surface.weak_reference());
         let surface_ptr = data as *mut wlr_surface;
         let surface_state_ptr = (*surface_ptr).data as *mut InternalState;
         // NOTE that wlroots cleans up the wlr_surface properly (so the Surface drop is called).
@@ -103,6 +123,16 @@ pub(crate) struct InternalState {
     subsurfaces_manager: Weak<Box<SubsurfaceManager>>
 }
 
+/// Get the client of the surface.
+pub(crate) unsafe fn surface_client(surface: *mut wlr_surface) -> *mut wl_client {
+    if surface.is_null() || (*surface).resource.is_null() {
+        return ptr::null_mut()
+    }
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_get_client,
+                  (*surface).resource as *mut _)
+}
+
 /// A Wayland object that represents the data that we display on the screen.
 ///
 /// Most surfaces come from Wayland clients, though they can also be created
@@ -174,6 +204,24 @@ impl Surface {
         self.surface
     }
 
+    /// Get the part of the surface the client promised is fully opaque, in
+    /// surface-local coordinates.
+    ///
+    /// Whatever is behind this part doesn't need to be drawn, and it can be
+    /// drawn without blending.
+    pub fn opaque_region(&self) -> PixmanRegion {
+        unsafe { PixmanRegion::from_raw(&(*self.surface).current.opaque) }
+    }
+
+    /// Determines if the whole surface is opaque.
+    pub fn is_opaque(&self) -> bool {
+        unsafe {
+            let (width, height) = ((*self.surface).current.width, (*self.surface).current.height);
+            self.opaque_region()
+                .contains_area(Area::new(Origin::new(0, 0), Size::new(width, height)))
+        }
+    }
+
     /// Get the surface state.
     pub fn current_state<'surface>(&'surface mut self) -> surface::State<'surface> {
         unsafe {
@@ -195,6 +243,37 @@ impl Surface {
         self.subsurfaces_manager.subsurfaces()
     }
 
+    /// Call the iterator on this surface and its subsurfaces, with their
+    /// position relative to this surface.
+    pub fn for_each_surface<F>(&self, mut iterator: F)
+            where F: FnMut(Handle, i32, i32) {
+        // NOTE The panic is re-thrown once we're out of C.
+        let mut iteration: (&mut FnMut(Handle, i32, i32), Option<Box<Any + Send>>) =
+            (&mut iterator, None);
+        unsafe {
+            unsafe extern "C" fn c_iterator(wlr_surface: *mut wlr_surface,
+                                            sx: i32,
+                                            sy: i32,
+                                            data: *mut c_void) {
+                let &mut (ref mut iterator_fn, ref mut panic_error) =
+                    &mut *(data as *mut (&mut FnMut(Handle, i32, i32), Option<Box<Any + Send>>));
+                if panic_error.is_some() {
+                    return
+                }
+                let surface = Handle::from_ptr(wlr_surface);
+                let res = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+                                                  iterator_fn(surface, sx, sy)
+                                              }));
+                *panic_error = res.err();
+            }
+            let iterator_ptr: *mut c_void = &mut iteration as *mut _ as *mut c_void;
+            wlr_surface_for_each_surface(self.surface, Some(c_iterator), iterator_ptr);
+        }
+        if let Some(err) = iteration.1 {
+            panic::resume_unwind(err)
+        }
+    }
+
     /// Get the texture of this surface.
     ///
     /// Returns None if no buffer is currently attached or if something went
@@ -210,6 +289,66 @@ impl Surface {
         }
     }
 
+    /// Keep the buffer currently committed to this surface alive, e.g. to
+    /// keep drawing it once the surface is gone.
+    ///
+    /// Returns `None` if no buffer is currently attached.
+    pub fn lock_current_buffer(&self) -> Option<LockedBuffer> {
+        unsafe {
+            let buffer = (*self.surface).buffer;
+            if buffer.is_null() {
+                return None
+            }
+            let current = &(*self.surface).current;
+            Some(LockedBuffer::new(self.surface,
+                                   buffer,
+                                   (current.width, current.height),
+                                   current.scale,
+                                   current.transform))
+        }
+    }
+
+    /// Copy what this surface and its subsurfaces currently show into a new
+    /// texture, which stays valid once the surface is gone.
+    ///
+    /// Unlike `lock_current_buffer` this includes the subsurfaces, but it
+    /// costs a copy on the GPU and a read back. The texture has the size of
+    /// the whole tree at the scale of this surface, and it's destroyed when
+    /// the snapshot is dropped.
+    ///
+    /// This must not be called while rendering to an output. Returns `None`
+    /// if nothing is shown or the copy failed.
+    pub fn capture_snapshot(&self, renderer: &mut GenericRenderer) -> Option<Snapshot> {
+        unsafe { snapshot::capture(renderer, self.surface) }
+    }
+
+    /// Copy what this surface and its subsurfaces currently show into
+    /// memory, e.g. to screenshot a single window.
+    ///
+    /// The same as `capture_snapshot` otherwise, and it must not be called
+    /// while rendering to an output either.
+    pub fn capture_pixels(&self, renderer: &mut GenericRenderer) -> Option<Pixels> {
+        unsafe { snapshot::read_back(renderer, self.surface) }
+    }
+
+    /// Get the content type the client last committed for this surface
+    /// (see `extensions::content_type`).
+    ///
+    /// It's `ContentType::None` unless the client gave a hint.
+    #[cfg(feature = "content_type")]
+    pub fn content_type(&self) -> ContentType {
+        content_type::current(self.surface)
+    }
+
+    /// Get the presentation hint the client last committed for this surface
+    /// (see `extensions::tearing_control`).
+    ///
+    /// It's `PresentationHint::Vsync` unless the client asked for tearing.
+    #[cfg(feature = "tearing_control")]
+    pub fn presentation_hint(&self) -> PresentationHint {
+        tearing_control::current(self.surface)
+    }
+
     /// Get the lifetime bound role (if one exists) for this surface.
     pub fn role(&self) -> Option<String> {
         unsafe { c_to_rust_string((*(*self.surface).role).name) }
@@ -228,7 +367,16 @@ impl Surface {
 
     /// Determines if this surface accepts input or not at the provided surface
     /// local coordinates.
+    #[deprecated(note = "Use `point_accepts_input` instead")]
     pub fn accepts_input(&self, sx: c_double, sy: c_double) -> bool {
+        self.point_accepts_input(sx, sy)
+    }
+
+    /// Determines if the point, in surface-local coordinates, is on the
+    /// surface and inside the input region the client set.
+    ///
+    /// This doesn't look at subsurfaces, see `surface_at` for that.
+    pub fn point_accepts_input(&self, sx: c_double, sy: c_double) -> bool {
         unsafe { wlr_surface_point_accepts_input(self.surface, sx, sy) }
     }
 
@@ -260,6 +408,23 @@ impl Surface {
         }
     }
 
+    /// Find the surface of the subsurface tree of this surface that takes
+    /// input at the surface-local coordinates, the topmost first.
+    ///
+    /// Returns the surface with the coordinates relative to it, or `None` if
+    /// no surface of the tree accepts input there.
+    pub fn surface_at(&self, sx: f64, sy: f64) -> Option<(Handle, f64, f64)> {
+        let (mut sub_x, mut sub_y) = (0.0, 0.0);
+        unsafe {
+            let surface = wlr_surface_surface_at(self.surface, sx, sy, &mut sub_x, &mut sub_y);
+            if surface.is_null() {
+                None
+            } else {
+                Some((Handle::from_ptr(surface), sub_x, sub_y))
+            }
+        }
+    }
+
     /// Get the top of the subsurface tree for this surface.
     pub fn get_root_surface(&self) -> Option<Handle> {
         unsafe {
@@ -339,6 +504,7 @@ impl Drop for Surface {
             return
         }
         wlr_log!(WLR_DEBUG, "Dropped surface {:p}", self.surface);
+        utils::user_data::clear(self.surface);
         let weak_count = Rc::weak_count(&self.liveliness);
         if weak_count > 0 {
             wlr_log!(WLR_DEBUG,
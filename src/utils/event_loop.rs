@@ -0,0 +1,12 @@
+//! The masks of `wl_event_loop_add_fd`, which wayland-sys doesn't expose.
+
+#![allow(dead_code)]
+
+/// The fd can be read from without blocking.
+pub(crate) const WL_EVENT_READABLE: u32 = 0x01;
+/// The fd can be written to without blocking.
+pub(crate) const WL_EVENT_WRITABLE: u32 = 0x02;
+/// The other end hung up.
+pub(crate) const WL_EVENT_HANGUP: u32 = 0x04;
+/// There was an error on the fd.
+pub(crate) const WL_EVENT_ERROR: u32 = 0x08;
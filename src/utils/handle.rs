@@ -1,7 +1,9 @@
 //! The generic implementation of a "handle" proxy object used throughout wlroots-rs.
 
-use std::{clone::Clone, cell::Cell, error::Error, fmt, rc::Weak,
-          hash::{Hash, Hasher}, ptr, panic, marker::PhantomData};
+use std::{any::{self, Any}, clone::Clone, cell::{Cell, RefCell}, error::Error, fmt, rc::Weak,
+          hash::{Hash, Hasher}, ptr, panic::{self, Location}, marker::PhantomData};
+#[cfg(debug_assertions)]
+use std::collections::HashMap;
 
 /// The result of trying to upgrade a handle, either using `run` or
 /// `with_handles!`.
@@ -12,11 +14,77 @@ pub type HandleResult<T> = Result<T, HandleErr>;
 pub enum HandleErr {
     /// Attempting a handle that already has a mutable borrow to its
     /// backing structure.
-    AlreadyBorrowed,
+    AlreadyBorrowed {
+        /// The type of the structure.
+        type_name: &'static str,
+        /// Where the structure was borrowed, which is only tracked in debug
+        /// builds.
+        location: Option<&'static Location<'static>>
+    },
     /// Tried to upgrade a handle for a structure that has already been dropped.
     AlreadyDropped
 }
 
+#[cfg(debug_assertions)]
+thread_local! {
+    /// Where each of the currently borrowed resources was borrowed, by lock.
+    static BORROWS: RefCell<HashMap<usize, &'static Location<'static>>> =
+        RefCell::new(HashMap::new());
+}
+
+thread_local! {
+    /// The locks of the resources whose destroy callbacks are running.
+    static TEARING_DOWN: RefCell<Vec<usize>> = RefCell::new(Vec::new());
+}
+
+/// Run the function while the resource is torn down, so its handles can't be
+/// upgraded and report it as dropped.
+#[cfg(feature = "unstable")]
+pub(crate) fn tear_down<F: FnOnce()>(lock: &Cell<bool>, f: F) {
+    let key = lock as *const _ as usize;
+    let was_locked = lock.replace(true);
+    TEARING_DOWN.with(|tearing_down| tearing_down.borrow_mut().push(key));
+    let res = panic::catch_unwind(panic::AssertUnwindSafe(f));
+    TEARING_DOWN.with(|tearing_down| tearing_down.borrow_mut().retain(|other| *other != key));
+    lock.set(was_locked);
+    if let Err(err) = res {
+        panic::resume_unwind(err)
+    }
+}
+
+/// Take the lock of a resource that is being upgraded from a handle.
+pub(crate) fn borrow_lock(lock: &Cell<bool>, location: &'static Location<'static>) {
+    lock.set(true);
+    #[cfg(debug_assertions)]
+    BORROWS.with(|borrows| borrows.borrow_mut().insert(lock as *const _ as usize, location));
+    #[cfg(not(debug_assertions))]
+    let _ = location;
+}
+
+/// Release the lock of a resource once it's done being used.
+pub(crate) fn release_lock(lock: &Cell<bool>) {
+    lock.set(false);
+    #[cfg(debug_assertions)]
+    BORROWS.with(|borrows| borrows.borrow_mut().remove(&(lock as *const _ as usize)));
+}
+
+/// The error for upgrading a handle whose lock is already taken.
+///
+/// That's `AlreadyDropped` while the resource is torn down.
+pub(crate) fn already_borrowed<W>(lock: &Cell<bool>) -> HandleErr {
+    let key = lock as *const _ as usize;
+    if TEARING_DOWN.with(|tearing_down| tearing_down.borrow().contains(&key)) {
+        return HandleErr::AlreadyDropped
+    }
+    #[cfg(debug_assertions)]
+    let location = BORROWS.with(|borrows| {
+        borrows.borrow().get(&(lock as *const _ as usize)).cloned()
+    });
+    #[cfg(not(debug_assertions))]
+    let location = { let _ = lock; None };
+    HandleErr::AlreadyBorrowed { type_name: any::type_name::<W>(), location }
+}
+
 /// A non-owned reference counted handle to a resource.
 ///
 /// The resource could be destroyed at any time, it depends on the resource.
@@ -65,6 +133,42 @@ pub trait Handleable<D: Clone, T> {
 
     /// Creates a weak reference to the resource.
     fn weak_reference(&self) -> Handle<D, T, Self> where Self: Sized;
+
+    /// Attaches a value to the resource, replacing (and returning) any value
+    /// of the same type that was previously attached.
+    ///
+    /// Only one value per type can be attached, so use a newtype if you need
+    /// to store multiple values of the same type.
+    ///
+    /// The value is dropped when the resource is destroyed.
+    fn insert_data<U: Any>(&mut self, value: U) -> Option<U> where Self: Sized {
+        let handle = self.weak_reference();
+        ::utils::user_data::insert(handle.ptr as usize, handle.handle, value)
+    }
+
+    /// Gets a reference to the value of this type attached to the resource,
+    /// if there is one.
+    fn data<U: Any>(&self) -> Option<&U> where Self: Sized {
+        // NOTE Rationale for safety:
+        //
+        // The value is boxed so it won't move, and it can only be removed
+        // or replaced through a mutable reference to the resource.
+        unsafe { ::utils::user_data::get::<U>(self.as_ptr() as usize).map(|value| &*value) }
+    }
+
+    /// Gets a mutable reference to the value of this type attached to the
+    /// resource, if there is one.
+    fn data_mut<U: Any>(&mut self) -> Option<&mut U> where Self: Sized {
+        unsafe {
+            ::utils::user_data::get::<U>(self.as_ptr() as usize).map(|value| &mut *value)
+        }
+    }
+
+    /// Removes the value of this type attached to the resource, returning it
+    /// if there was one.
+    fn remove_data<U: Any>(&mut self) -> Option<U> where Self: Sized {
+        unsafe { ::utils::user_data::remove::<U>(self.as_ptr() as usize) }
+    }
 }
 
 impl <D: Clone, T, W: Handleable<D, T>> Clone for Handle<D, T, W> {
@@ -132,6 +236,41 @@ impl <D: Clone, T, W: Handleable<D, T>> Handle<D, T, W> {
         self.ptr
     }
 
+    /// Get the unique id of the resource this handle refers to.
+    ///
+    /// Ids are assigned the first time they are requested and are never reused,
+    /// even after the resource is destroyed, which makes them suitable as keys
+    /// or in log messages and IPC responses in place of the raw pointer.
+    ///
+    /// Once the resource has been destroyed this returns 0, which is never
+    /// the id of a live resource.
+    pub fn id(&self) -> u64 {
+        ::utils::user_data::id(self.ptr as usize, &self.handle)
+    }
+
+    /// Call the function when the resource is destroyed, so state kept about
+    /// it can be cleaned up without polling the handle.
+    ///
+    /// Destroy callbacks run after the `destroyed` callback of the resource's
+    /// handler, in the order they were registered. Resources that have a
+    /// mapped state (e.g. shell surfaces) are always unmapped before they are
+    /// destroyed. Children aren't necessarily destroyed before their parents:
+    /// a subsurface outlives its parent surface, it's only detached from it.
+    ///
+    /// The resource is being torn down when the callback runs, so upgrading
+    /// its handles from the callback returns `AlreadyDropped`.
+    ///
+    /// Returns `AlreadyDropped` if the resource was already destroyed.
+    pub fn on_destroy<F>(&self, callback: F) -> HandleResult<()>
+        where F: FnOnce() + 'static
+    {
+        if ::utils::user_data::on_destroy(self.ptr as usize, &self.handle, Box::new(callback)) {
+            Ok(())
+        } else {
+            Err(HandleErr::AlreadyDropped)
+        }
+    }
+
     /// Run a function with a reference to the resource if it's still alive.
     ///
     /// Returns the result of the function, if successful.
@@ -146,6 +285,7 @@ impl <D: Clone, T, W: Handleable<D, T>> Handle<D, T, W> {
     /// This function will panic if multiple mutable borrows are detected.
     /// This will happen if you call `upgrade` directly within this callback,
     /// or if a handle to the same resource was upgraded some where else up the stack.
+    #[track_caller]
     pub fn run<F, R>(&self, runner: F) -> HandleResult<R>
         where F: FnOnce(&mut W) -> R
     {
@@ -163,7 +303,7 @@ impl <D: Clone, T, W: Handleable<D, T>> Handle<D, T, W> {
                 wlr_log!(WLR_ERROR, "After running callback, mutable lock was false");
                 panic!("Lock in incorrect state!");
             }
-            check.set(false);
+            release_lock(&check);
         });
         match res {
             Ok(res) => Ok(res),
@@ -181,7 +321,9 @@ impl <D: Clone, T, W: Handleable<D, T>> Handle<D, T, W> {
     ///
     /// Regardless, you should not use this interface. Use the `run` method.
     #[doc(hidden)]
+    #[track_caller]
     pub unsafe fn upgrade(&self) -> HandleResult<W> {
+        let location = Location::caller();
         self.handle.upgrade()
             .ok_or(HandleErr::AlreadyDropped)
             // NOTE
@@ -189,10 +331,10 @@ impl <D: Clone, T, W: Handleable<D, T>> Handle<D, T, W> {
             // pointer to exist!
             .and_then(|check| {
                 if check.get() {
-                    return Err(HandleErr::AlreadyDropped)
+                    return Err(already_borrowed::<W>(&check))
                 }
                 let wrapper_obj = W::from_handle(self)?;
-                check.set(true);
+                borrow_lock(&check, location);
                 Ok(wrapper_obj)
             })
 
@@ -203,7 +345,12 @@ impl fmt::Display for HandleErr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use self::HandleErr::*;
         match *self {
-            AlreadyBorrowed => write!(f, "already borrowed"),
+            AlreadyBorrowed { type_name, location: Some(location) } => {
+                write!(f, "{} already borrowed at {}", type_name, location)
+            },
+            AlreadyBorrowed { type_name, location: None } => {
+                write!(f, "{} already borrowed", type_name)
+            },
             AlreadyDropped => write!(f, "already dropped")
         }
     }
@@ -213,7 +360,7 @@ impl Error for HandleErr {
     fn description(&self) -> &str {
         use self::HandleErr::*;
         match *self {
-            AlreadyBorrowed => "Structure is already mutably borrowed",
+            AlreadyBorrowed { .. } => "Structure is already mutably borrowed",
             AlreadyDropped => "Structure has already been dropped"
         }
     }
@@ -4,9 +4,21 @@
 //! [initializing the logs](fn.init_logging.html).
 //!
 //! To log using this system please utilize the [`wlr_log!`](../../macro.wlr_log.html) macro.
+//!
+//! With the `log` feature enabled the logs can instead be sent to the
+//! [`log`](https://docs.rs/log) crate using
+//! [`init_log_crate`](fn.init_log_crate.html), or messages logged with the `log`
+//! crate can be sent to wlroots using [`WlrLogger`](struct.WlrLogger.html).
+//!
+//! Messages logged while the compositor handles an output or a client carry
+//! the name of the output (`output`) and the pid of the client (`client_pid`)
+//! as structured fields of the `log` record.
 
-use libc::c_char;
-use wlroots_sys::{wlr_log_importance, __va_list_tag, wlr_log_init};
+use std::{cell::RefCell, panic};
+
+use libc::{c_char, pid_t};
+use wayland_sys::server::{wl_client, WAYLAND_SERVER_HANDLE};
+use wlroots_sys::{wlr_log_importance, __va_list_tag, wlr_log_init, wlr_output};
 use vsprintf::vsprintf;
 
 use utils::c_to_rust_string;
@@ -26,6 +38,70 @@ pub type LogCallback = fn(verbosity: LogVerbosity, message: String);
 
 static mut RUST_LOGGING_FN: LogCallback = dummy_callback;
 
+/// The verbosity given to wlroots, which is `WLR_ERROR` until it's set.
+static mut VERBOSITY: LogVerbosity = WLR_ERROR;
+
+/// What the messages logged right now are about.
+#[derive(Debug, Clone, Default)]
+struct Fields {
+    output: Option<String>,
+    client_pid: Option<pid_t>
+}
+
+thread_local! {
+    static FIELDS: RefCell<Fields> = RefCell::new(Fields::default());
+}
+
+/// Attaches the output or client being handled to the messages logged while
+/// it's alive, restoring the previous fields when dropped.
+pub(crate) struct LogContext {
+    previous: Option<Fields>
+}
+
+impl LogContext {
+    /// Attach the name of the output.
+    pub(crate) unsafe fn output(output: *mut wlr_output) -> Self {
+        if !cfg!(feature = "log") || output.is_null() {
+            return LogContext { previous: None }
+        }
+        let name = c_to_rust_string((*output).name.as_ptr());
+        LogContext::enter(|fields| fields.output = name)
+    }
+
+    /// Attach the pid of the client.
+    pub(crate) unsafe fn client(client: *mut wl_client) -> Self {
+        if !cfg!(feature = "log") || client.is_null() {
+            return LogContext { previous: None }
+        }
+        let (mut pid, mut uid, mut gid) = (0, 0, 0);
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_client_get_credentials,
+                      client,
+                      &mut pid,
+                      &mut uid,
+                      &mut gid);
+        LogContext::enter(|fields| fields.client_pid = Some(pid))
+    }
+
+    fn enter<F: FnOnce(&mut Fields)>(set: F) -> Self {
+        let previous = FIELDS.with(|fields| {
+                                       let mut fields = fields.borrow_mut();
+                                       let previous = fields.clone();
+                                       set(&mut fields);
+                                       previous
+                                   });
+        LogContext { previous: Some(previous) }
+    }
+}
+
+impl Drop for LogContext {
+    fn drop(&mut self) {
+        if let Some(previous) = self.previous.take() {
+            FIELDS.with(|fields| *fields.borrow_mut() = previous);
+        }
+    }
+}
+
 /// Initialize wlroots logging at a certain level of verbosity with
 /// an optional callback that will be called for every log.
 ///
@@ -35,6 +111,7 @@ pub fn init_logging<F>(verbosity: LogVerbosity, callback: F)
 where F: Into<Option<LogCallback>>
 {
     unsafe {
+        VERBOSITY = verbosity;
         match callback.into() {
             None => wlr_log_init(verbosity, None),
             Some(callback) => {
@@ -56,5 +133,135 @@ unsafe extern "C" fn log_callback(importance: wlr_log_importance,
     let message = vsprintf(fmt, va_list).unwrap_or_else(|_| {
         c_to_rust_string(fmt).unwrap_or_else(|| "".into())
     });
-    RUST_LOGGING_FN(importance, message);
+    let res = panic::catch_unwind(|| RUST_LOGGING_FN(importance, message));
+    if res.is_err() {
+        // NOTE Logging the panic through wlroots would call back in here.
+        eprintln!("The log callback panicked, logging to stderr instead");
+        RUST_LOGGING_FN = stderr_callback;
+    }
+}
+
+/// Fallback for when the user-supplied callback panicked.
+fn stderr_callback(_: LogVerbosity, message: String) {
+    eprintln!("{}", message);
+}
+
+/// Initialize wlroots logging so that every message is forwarded to the
+/// [`log`](https://docs.rs/log) crate.
+///
+/// The verbosity passed to wlroots is derived from `log::max_level()`, so
+/// install and configure your logger (e.g. `env_logger`) first.
+///
+/// The target of each record is derived from the wlroots source file that
+/// logged it, e.g. a message from `backend/drm/drm.c` has the target
+/// `wlroots::backend::drm::drm`. Messages logged using `wlr_log!` get the
+/// target `wlroots::<file>` in the same way. This allows filtering per module,
+/// e.g. `RUST_LOG=wlroots=info,wlroots::backend=debug`.
+///
+/// Do not use this together with [`WlrLogger`](struct.WlrLogger.html), as
+/// messages would be sent back and forth forever.
+#[cfg(feature = "log")]
+pub fn init_log_crate() {
+    use log::LevelFilter;
+    let verbosity = match ::log::max_level() {
+        LevelFilter::Off => WLR_SILENT,
+        LevelFilter::Error | LevelFilter::Warn => WLR_ERROR,
+        LevelFilter::Info => WLR_INFO,
+        LevelFilter::Debug | LevelFilter::Trace => WLR_DEBUG
+    };
+    init_logging(verbosity, Some(log_crate_callback as LogCallback));
+}
+
+/// Forwards a wlroots log message to the `log` crate.
+#[cfg(feature = "log")]
+fn log_crate_callback(verbosity: LogVerbosity, message: String) {
+    use log::Level;
+    let level = match verbosity {
+        WLR_SILENT => return,
+        WLR_ERROR => Level::Error,
+        WLR_INFO => Level::Info,
+        _ => Level::Debug
+    };
+    use log::kv::Value;
+    let (target, message) = split_log_prefix(&message);
+    let fields = FIELDS.with(|fields| fields.borrow().clone());
+    let key_values = [fields.output.as_ref().map(|name| ("output", Value::from(name.as_str()))),
+                      fields.client_pid.map(|pid| ("client_pid", Value::from(pid)))];
+    ::log::logger().log(&::log::Record::builder().args(format_args!("{}", message))
+                                                 .level(level)
+                                                 .target(&target)
+                                                 .key_values(&key_values)
+                                                 .build());
+}
+
+/// The wlroots verbosity a `log` level is logged at.
+#[cfg(feature = "log")]
+fn wlr_verbosity(level: ::log::Level) -> LogVerbosity {
+    use log::Level;
+    match level {
+        Level::Error | Level::Warn => WLR_ERROR,
+        Level::Info => WLR_INFO,
+        Level::Debug | Level::Trace => WLR_DEBUG
+    }
+}
+
+/// Splits the `[file:line] ` prefix wlroots puts in front of every message,
+/// turning the file into a `log` target.
+#[cfg(feature = "log")]
+fn split_log_prefix(message: &str) -> (String, &str) {
+    let prefix_end = match message.find("] ") {
+        Some(index) if message.starts_with('[') => index,
+        _ => return ("wlroots".into(), message)
+    };
+    let location = &message[1..prefix_end];
+    let file = location.rsplitn(2, ':').last().unwrap_or(location);
+    let module = file.trim_end_matches(".c")
+                     .trim_end_matches(".rs")
+                     .trim_start_matches("src/")
+                     .replace('/', "::");
+    (format!("wlroots::{}", module), &message[prefix_end + 2..])
+}
+
+/// A [`log`](https://docs.rs/log) logger that forwards every record to
+/// the wlroots logger, so it shows up alongside the wlroots messages.
+///
+/// Install it with `log::set_logger(&WlrLogger)` after calling
+/// [`init_logging`](fn.init_logging.html). Records more verbose than the
+/// verbosity given to `init_logging` are dropped.
+///
+/// Warnings are logged as errors, and trace records as debug messages,
+/// as wlroots does not have those levels.
+#[cfg(feature = "log")]
+pub struct WlrLogger;
+
+#[cfg(feature = "log")]
+impl ::log::Log for WlrLogger {
+    fn enabled(&self, metadata: &::log::Metadata) -> bool {
+        let verbosity = unsafe { VERBOSITY };
+        verbosity != WLR_SILENT && wlr_verbosity(metadata.level()) as u32 <= verbosity as u32
+    }
+
+    fn log(&self, record: &::log::Record) {
+        use std::ffi::CString;
+        use wlroots_sys::_wlr_log;
+        if !self.enabled(record.metadata()) {
+            return
+        }
+        let verbosity = wlr_verbosity(record.level());
+        let message = format!("[{}:{}] {}",
+                              record.file().unwrap_or_else(|| record.target()),
+                              record.line().unwrap_or(0),
+                              record.args());
+        let message = match CString::new(message) {
+            Ok(message) => message,
+            Err(_) => return
+        };
+        unsafe {
+            _wlr_log(verbosity,
+                     b"%s\0".as_ptr() as *const c_char,
+                     message.as_ptr());
+        }
+    }
+
+    fn flush(&self) {}
 }
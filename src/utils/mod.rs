@@ -6,29 +6,32 @@ pub mod region;
 
 // Rust specific utilities that don't wrap a wlroots utility.
 mod time;
+mod event_loop;
 mod string;
 mod handle;
+mod pool;
+#[cfg(feature = "unstable")]
+mod token;
+pub(crate) mod user_data;
+#[cfg(feature = "unstable")]
+mod unwind;
+#[cfg(feature = "unstable")]
+pub(crate) mod watchdog;
 
-pub use self::time::{current_time, ToMs};
+pub use self::time::{current_time, event_instant, event_time, ToMs};
+pub(crate) use self::time::now;
+#[cfg(feature = "testing")]
+pub(crate) use self::time::skip;
+pub(crate) use self::event_loop::*;
 pub use self::handle::*;
+pub use self::pool::Pool;
 pub(crate) use self::string::{c_to_rust_string, safe_as_cstring};
-
-
-/// Handle unwinding from a panic, used in conjunction with
-/// `::std::panic::catch_unwind`.
-///
-/// When a panic occurs, we terminate the compositor and let the rest
-/// of the code run.
 #[cfg(feature = "unstable")]
-pub(crate) unsafe fn handle_unwind<T>(res: ::std::thread::Result<T>) {
-    match res {
-        Ok(_) => {}
-        Err(err) => {
-            if ::compositor::COMPOSITOR_PTR == 0 as *mut _ {
-                ::std::process::abort();
-            }
-            (&mut *::compositor::COMPOSITOR_PTR).save_panic_error(err);
-            ::compositor::terminate()
-        }
-    }
-}
+pub use self::unwind::PanicPolicy;
+#[cfg(feature = "unstable")]
+pub use self::watchdog::{Watchdog, WatchdogAction};
+#[cfg(feature = "unstable")]
+pub(crate) use self::unwind::{begin_shutdown, end_shutdown, handle_listener_unwind,
+                              handle_unwind};
+#[cfg(feature = "unstable")]
+pub(crate) use self::token::random_token;
@@ -0,0 +1,70 @@
+//! A pool of heap allocations that are reused instead of freed.
+//!
+//! The structs that hold the listeners of a wlroots object are handed to it
+//! by address, so they're boxed and can't move. When lots of them come and go
+//! at once (e.g. a client opening hundreds of tooltips), putting new ones in
+//! the boxes of the ones that were destroyed keeps the allocator out of it.
+
+use std::{cell::RefCell, mem::ManuallyDrop, ptr};
+
+/// Allocations for values of type `T`, kept for reuse once the values in them
+/// are dropped.
+///
+/// The pool can be used from within the destructor of the values in it, so
+/// it can live in a `thread_local!` without a `RefCell` around it.
+#[derive(Debug)]
+pub struct Pool<T> {
+    free: RefCell<Vec<*mut T>>,
+    limit: usize
+}
+
+impl<T> Pool<T> {
+    /// Make a pool that keeps at most `limit` allocations around.
+    pub fn new(limit: usize) -> Self {
+        Pool { free: RefCell::new(Vec::new()), limit }
+    }
+
+    /// Box the value, reusing an allocation from the pool if there is one.
+    pub fn boxed(&self, value: T) -> Box<T> {
+        let free = self.free.borrow_mut().pop();
+        match free {
+            Some(allocation) => unsafe {
+                ptr::write(allocation, value);
+                Box::from_raw(allocation)
+            },
+            None => Box::new(value)
+        }
+    }
+
+    /// Drop the boxed value, keeping its allocation in the pool unless it's
+    /// full.
+    pub fn release(&self, value: Box<T>) {
+        if self.len() >= self.limit {
+            return
+        }
+        let allocation = Box::into_raw(value);
+        // NOTE The pool isn't borrowed while the value is dropped, in case
+        // dropping it releases another value into the pool.
+        unsafe { ptr::drop_in_place(allocation) }
+        self.free.borrow_mut().push(allocation)
+    }
+
+    /// Get how many allocations are kept for reuse.
+    pub fn len(&self) -> usize {
+        self.free.borrow().len()
+    }
+
+    /// Determine if there are no allocations to reuse.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Drop for Pool<T> {
+    fn drop(&mut self) {
+        for allocation in self.free.borrow_mut().drain(..) {
+            // NOTE The values were already dropped, only the memory is freed.
+            drop(unsafe { Box::from_raw(allocation as *mut ManuallyDrop<T>) })
+        }
+    }
+}
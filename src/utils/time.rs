@@ -1,10 +1,18 @@
 //! Timing is important for compositors and clients to know when to render frames.
 //! Most of these functions will be used for that purpose.
 
-use std::time::Duration;
+#[cfg(feature = "testing")]
+use std::cell::Cell;
+use std::time::{Duration, Instant};
 
 use libc::{clock_gettime, timespec, CLOCK_MONOTONIC};
 
+#[cfg(feature = "testing")]
+thread_local! {
+    /// How far the clock was moved ahead with `testing::Harness::skip`.
+    static SKIPPED: Cell<Duration> = Cell::new(Duration::from_secs(0));
+}
+
 /// Trait to convert something to milliseconds.
 ///
 /// Used primarily to convert a `std::time::Duration` into
@@ -18,9 +26,10 @@ pub trait ToMs {
 
 impl ToMs for Duration {
     fn to_ms(self) -> u32 {
-        let seconds_delta = self.as_secs() as u32;
-        let mili_delta = self.subsec_millis();
-        (seconds_delta * 1000) + mili_delta
+        // NOTE The timestamps of wlroots wrap around, so this does too.
+        let seconds_delta = self.as_secs().wrapping_mul(1000);
+        let mili_delta = self.subsec_millis() as u64;
+        seconds_delta.wrapping_add(mili_delta) as u32
     }
 }
 
@@ -30,6 +39,62 @@ pub fn current_time() -> Duration {
     unsafe {
         let mut ts = timespec{tv_sec: 0, tv_nsec: 0};
         clock_gettime(CLOCK_MONOTONIC, &mut ts);
-        Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+        Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32) + skipped()
+    }
+}
+
+/// Get the current time as an `Instant`, which the timing of the crate (e.g.
+/// of animations and frame callbacks) is based on.
+///
+/// Like `current_time` it includes the time skipped by the testing harness.
+pub(crate) fn now() -> Instant {
+    Instant::now() + skipped()
+}
+
+/// Move the clock of the crate ahead, without waiting.
+#[cfg(feature = "testing")]
+pub(crate) fn skip(duration: Duration) {
+    SKIPPED.with(|skipped| skipped.set(skipped.get() + duration))
+}
+
+#[cfg(feature = "testing")]
+fn skipped() -> Duration {
+    SKIPPED.with(|skipped| skipped.get())
+}
+
+#[cfg(not(feature = "testing"))]
+fn skipped() -> Duration {
+    Duration::from_secs(0)
+}
+
+/// Get the time on the monotonic clock an input event happened at, from its
+/// timestamp in milliseconds.
+///
+/// The timestamps are cut down to 32 bits, so they wrap around after 49.7
+/// days. They're put back together using the current time, so this works as
+/// long as the event happened less than 24 days ago. Unlike the timestamps,
+/// the times can be compared and subtracted, e.g. to detect double clicks.
+///
+/// The time converts back to the timestamp with `ToMs` when it's passed on to
+/// a seat.
+pub fn event_time(time_msec: u32) -> Duration {
+    let now = current_time();
+    let now_msec = now.as_secs() * 1000 + now.subsec_millis() as u64;
+    let delta = time_msec.wrapping_sub(now_msec as u32) as i32 as i64;
+    let time_msec = (now_msec as i64 + delta).max(0) as u64;
+    Duration::from_millis(time_msec)
+}
+
+/// Get the `Instant` an input event happened at, from its timestamp in
+/// milliseconds.
+///
+/// See `event_time`.
+pub fn event_instant(time_msec: u32) -> Instant {
+    let (now, instant) = (current_time(), self::now());
+    let time = event_time(time_msec);
+    if time <= now {
+        instant.checked_sub(now - time).unwrap_or(instant)
+    } else {
+        instant + (time - now)
     }
 }
@@ -0,0 +1,21 @@
+//! Tokens that are handed to clients, and must not be guessable by others.
+//!
+//! Protocols such as xdg-foreign and xdg-activation identify what they share
+//! by a string the compositor makes up, so anything derived from addresses or
+//! counters would let a client import or activate what it wasn't given.
+
+use std::{fs::File, io::Read};
+
+/// How many random bytes are in a token.
+const TOKEN_BYTES: usize = 16;
+
+/// Make up a token from the random numbers of the kernel, as hex.
+///
+/// # Panicking
+/// Panics if `/dev/urandom` can't be read, as there's no safe fallback.
+pub(crate) fn random_token() -> String {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    File::open("/dev/urandom").and_then(|mut urandom| urandom.read_exact(&mut bytes))
+                              .expect("Could not read random bytes for a token");
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
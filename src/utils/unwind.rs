@@ -0,0 +1,131 @@
+//! Keeping panics in user callbacks from unwinding into C.
+//!
+//! Every callback into user code is wrapped in `catch_unwind`, and what
+//! happens once a panic is caught is decided by the `PanicPolicy` of the
+//! compositor.
+
+use std::{any::Any, cell::{Cell, RefCell}, process, thread};
+
+use wlroots_sys::{wl_listener, server::WAYLAND_SERVER_HANDLE};
+
+use compositor;
+
+/// What to do when a callback panics.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum PanicPolicy {
+    /// Abort the process right away.
+    Abort,
+    /// Terminate the compositor, running the usual clean up code, and re-throw
+    /// the panic from `Compositor::run` once it's safe to do so.
+    ///
+    /// This is the default.
+    Terminate,
+    /// Log the panic and stop calling the callback that panicked, while the
+    /// compositor keeps running.
+    ///
+    /// A panic while a resource is being destroyed still terminates the
+    /// compositor, as the resource can't be safely cleaned up anymore.
+    Disable
+}
+
+impl Default for PanicPolicy {
+    fn default() -> Self {
+        PanicPolicy::Terminate
+    }
+}
+
+thread_local! {
+    /// The panic policy of the compositor that's shutting down, if one is.
+    static SHUTTING_DOWN: Cell<Option<PanicPolicy>> = Cell::new(None);
+    /// The first panic caught while the compositor was shutting down.
+    static SHUTDOWN_PANIC: RefCell<Option<Box<Any + Send>>> = RefCell::new(None);
+}
+
+/// Catch the panics of the callbacks that run while the compositor shuts
+/// down, when it can't be reached anymore.
+pub(crate) fn begin_shutdown(policy: PanicPolicy) {
+    SHUTTING_DOWN.with(|shutting_down| shutting_down.set(Some(policy)))
+}
+
+/// Get the panic that was caught while the compositor shut down, if there
+/// was one, to be re-thrown.
+pub(crate) fn end_shutdown() -> Option<Box<Any + Send>> {
+    SHUTTING_DOWN.with(|shutting_down| shutting_down.set(None));
+    SHUTDOWN_PANIC.with(|panic| panic.borrow_mut().take())
+}
+
+/// Get the message of the panic, if it has one.
+fn panic_message(err: &(Any + Send)) -> &str {
+    if let Some(message) = err.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = err.downcast_ref::<String>() {
+        message
+    } else {
+        "Box<Any>"
+    }
+}
+
+/// Handle unwinding from a panic, used in conjunction with
+/// `::std::panic::catch_unwind`.
+///
+/// There's no callback that can be disabled, so `PanicPolicy::Disable`
+/// terminates the compositor.
+pub(crate) unsafe fn handle_unwind<T>(res: thread::Result<T>) {
+    if let Err(err) = res {
+        unwind(err, "callback", None)
+    }
+}
+
+/// Handle unwinding from a panic in the listener, used by the listener macros.
+///
+/// `context` is the name of the listener, which is logged with the panic.
+pub(crate) unsafe fn handle_listener_unwind<T>(res: thread::Result<T>,
+                                               context: &str,
+                                               listener: *mut wl_listener) {
+    if let Err(err) = res {
+        // NOTE Destroy listeners fire only once, and by the time the panic
+        // is caught the resource is half torn down.
+        let listener = if context.contains("destroy") {
+            None
+        } else {
+            Some(listener)
+        };
+        unwind(err, context, listener)
+    }
+}
+
+unsafe fn unwind(err: Box<Any + Send>, context: &str, listener: Option<*mut wl_listener>) {
+    if compositor::COMPOSITOR_PTR.is_null() {
+        match SHUTTING_DOWN.with(|shutting_down| shutting_down.get()) {
+            Some(PanicPolicy::Abort) | None => process::abort(),
+            Some(_) => {
+                wlr_log!(WLR_ERROR,
+                         "Panic in {} during shutdown: {}",
+                         context,
+                         panic_message(&*err));
+                SHUTDOWN_PANIC.with(|panic| {
+                                        panic.borrow_mut().get_or_insert(err);
+                                    });
+                return
+            }
+        }
+    }
+    let compositor = &mut *compositor::COMPOSITOR_PTR;
+    wlr_log!(WLR_ERROR, "Panic in {}: {}", context, panic_message(&*err));
+    match (compositor.panic_policy, listener) {
+        (PanicPolicy::Abort, _) => process::abort(),
+        (PanicPolicy::Disable, Some(listener)) => {
+            wlr_log!(WLR_ERROR, "Disabling {}", context);
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_list_remove,
+                          &mut (*listener).link as *mut _ as _);
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_list_init,
+                          &mut (*listener).link as *mut _ as _);
+        },
+        (PanicPolicy::Terminate, _) | (PanicPolicy::Disable, None) => {
+            compositor.save_panic_error(err);
+            compositor::terminate()
+        }
+    }
+}
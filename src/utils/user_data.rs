@@ -0,0 +1,137 @@
+//! Storage for arbitrary data attached to wlroots resources.
+//!
+//! The data is keyed by the pointer to the resource, and is dropped when the
+//! resource is destroyed. See `Handleable::insert_data` for the public
+//! interface.
+
+use std::{any::{Any, TypeId}, cell::{Cell, RefCell}, collections::HashMap, rc::Weak};
+
+struct Entry {
+    /// Used to detect when the resource has been destroyed, so that a
+    /// reused pointer does not see the data of a dead resource.
+    liveliness: Weak<Cell<bool>>,
+    values: HashMap<TypeId, Box<Any>>
+}
+
+thread_local! {
+    static USER_DATA: RefCell<HashMap<usize, Entry>> = RefCell::new(HashMap::new());
+    /// The last id handed out by `id`. Ids start at 1.
+    static LAST_ID: Cell<u64> = Cell::new(0);
+}
+
+/// The id of a resource, stored alongside the rest of its user data.
+struct ResourceId(u64);
+
+/// The callbacks to run when the resource is destroyed, in the order they
+/// were registered.
+struct DestroyCallbacks(Vec<Box<FnOnce()>>);
+
+/// Get the id of the resource, assigning it a new one if it doesn't have one
+/// yet.
+///
+/// Returns 0 if the resource has been destroyed.
+pub(crate) fn id(ptr: usize, liveliness: &Weak<Cell<bool>>) -> u64 {
+    if liveliness.upgrade().is_none() {
+        return 0
+    }
+    if let Some(id) = get::<ResourceId>(ptr) {
+        return unsafe { (*id).0 }
+    }
+    let id = LAST_ID.with(|last_id| {
+        let id = last_id.get() + 1;
+        last_id.set(id);
+        id
+    });
+    insert(ptr, liveliness.clone(), ResourceId(id));
+    id
+}
+
+/// Attach the value to the resource, returning the previous value of the
+/// same type if there was one.
+pub(crate) fn insert<T: Any>(ptr: usize, liveliness: Weak<Cell<bool>>, value: T) -> Option<T> {
+    USER_DATA.with(|user_data| {
+        let mut user_data = user_data.borrow_mut();
+        // Drop anything left behind by resources that were not cleared.
+        user_data.retain(|_, entry| entry.liveliness.upgrade().is_some());
+        let entry = user_data.entry(ptr).or_insert_with(|| {
+            Entry { liveliness,
+                    values: HashMap::new() }
+        });
+        entry.values.insert(TypeId::of::<T>(), Box::new(value))
+             .map(|old| *old.downcast::<T>().expect("User data stored under wrong type"))
+    })
+}
+
+/// Get a pointer to the value of the given type attached to the resource.
+///
+/// The pointer is valid until the value is removed or replaced.
+pub(crate) fn get<T: Any>(ptr: usize) -> Option<*mut T> {
+    USER_DATA.with(|user_data| {
+        let mut user_data = user_data.borrow_mut();
+        let entry = user_data.get_mut(&ptr)?;
+        if entry.liveliness.upgrade().is_none() {
+            return None
+        }
+        entry.values.get_mut(&TypeId::of::<T>())
+             .and_then(|value| value.downcast_mut::<T>())
+             .map(|value| value as *mut T)
+    })
+}
+
+/// Remove the value of the given type attached to the resource.
+pub(crate) fn remove<T: Any>(ptr: usize) -> Option<T> {
+    USER_DATA.with(|user_data| {
+        let mut user_data = user_data.borrow_mut();
+        let value = user_data.get_mut(&ptr)?
+                             .values
+                             .remove(&TypeId::of::<T>())?;
+        Some(*value.downcast::<T>().expect("User data stored under wrong type"))
+    })
+}
+
+/// Call the function when the resource is destroyed.
+///
+/// Returns false if the resource has already been destroyed.
+pub(crate) fn on_destroy(ptr: usize,
+                         liveliness: &Weak<Cell<bool>>,
+                         callback: Box<FnOnce()>)
+                         -> bool {
+    if liveliness.upgrade().is_none() {
+        return false
+    }
+    match get::<DestroyCallbacks>(ptr) {
+        Some(callbacks) => unsafe { (*callbacks).0.push(callback) },
+        None => {
+            insert(ptr, liveliness.clone(), DestroyCallbacks(vec![callback]));
+        }
+    }
+    true
+}
+
+/// Run the destroy callbacks of the resource and drop all the data attached
+/// to it.
+///
+/// Called when the resource is destroyed.
+#[cfg(feature = "unstable")]
+pub(crate) fn clear<T>(ptr: *mut T) {
+    // NOTE The values are dropped outside of the borrow, in case their
+    // destructor touches user data as well.
+    let entry = USER_DATA.with(|user_data| user_data.borrow_mut().remove(&(ptr as usize)));
+    let mut entry = match entry {
+        Some(entry) => entry,
+        None => return
+    };
+    let callbacks = entry.values
+                         .remove(&TypeId::of::<DestroyCallbacks>())
+                         .and_then(|callbacks| callbacks.downcast::<DestroyCallbacks>().ok());
+    if let (Some(callbacks), Some(lock)) = (callbacks, entry.liveliness.upgrade()) {
+        // NOTE The resource is half torn down at this point, so it must not
+        // be upgraded from the callbacks.
+        super::tear_down(&lock, || {
+            for callback in callbacks.0 {
+                callback()
+            }
+        });
+    }
+    drop(entry)
+}
@@ -0,0 +1,208 @@
+//! Noticing callbacks that block the compositor.
+//!
+//! Everything the compositor does happens on the thread of its event loop, so
+//! a callback that blocks (e.g. on a slow file system, or a dead lock) freezes
+//! the whole session, without anything showing which callback it was.
+//!
+//! When a `Watchdog` is set with `compositor::Builder::watchdog`, a thread is
+//! started that checks how long the listener callback that's running has
+//! been going. Once it's over the threshold the callback is logged by name,
+//! or the process is aborted from the blocked thread so the core dump shows
+//! where it was stuck. Callbacks that finish late are also logged, with how
+//! long they took.
+//!
+//! Only the outermost callback is timed, the listeners triggered from within
+//! it count towards its time.
+
+use std::{cell::{Cell, RefCell}, sync::{mpsc, Arc, Mutex}, thread,
+          time::{Duration, Instant}};
+
+use libc;
+
+/// What the watchdog does when a callback takes too long.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum WatchdogAction {
+    /// Log the callback, and keep going.
+    ///
+    /// This is the default.
+    Log,
+    /// Log the callback, then abort from the blocked thread, so a debugger
+    /// or the core dump has the backtrace of the callback.
+    Abort
+}
+
+impl Default for WatchdogAction {
+    fn default() -> Self {
+        WatchdogAction::Log
+    }
+}
+
+/// When callbacks are considered blocked, and what to do about it.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct Watchdog {
+    /// How long a callback can run before it's considered blocked.
+    pub threshold: Duration,
+    pub action: WatchdogAction
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Watchdog { threshold: Duration::from_millis(500),
+                   action: WatchdogAction::default() }
+    }
+}
+
+/// The callback that's running, shared with the watchdog thread.
+#[derive(Debug, Default)]
+struct Running {
+    callback: Option<(&'static str, Instant)>,
+    /// Counts the callbacks, so each blocked one is only reported once.
+    generation: u64
+}
+
+#[derive(Debug)]
+struct Shared {
+    config: Watchdog,
+    running: Mutex<Running>
+}
+
+/// The watchdog thread, which is stopped when this is dropped.
+#[derive(Debug)]
+pub(crate) struct Thread {
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<thread::JoinHandle<()>>
+}
+
+thread_local! {
+    static WATCHED: RefCell<Option<Arc<Shared>>> = RefCell::new(None);
+    /// How many callbacks are nested on this thread.
+    static DEPTH: Cell<u32> = Cell::new(0);
+}
+
+impl Thread {
+    /// Start watching the callbacks that run on this thread.
+    pub fn start(config: Watchdog) -> Thread {
+        let shared = Arc::new(Shared { config,
+                                       running: Mutex::new(Running::default()) });
+        let (stop, stopped) = mpsc::channel();
+        let blocked = unsafe { libc::pthread_self() };
+        let watched = shared.clone();
+        let thread = thread::Builder::new().name("wlroots-watchdog".into())
+                                           .spawn(move || watch(watched, stopped, blocked));
+        let thread = match thread {
+            Ok(thread) => Some(thread),
+            Err(err) => {
+                wlr_log!(WLR_ERROR, "Could not start the watchdog thread: {}", err);
+                None
+            }
+        };
+        if thread.is_some() {
+            WATCHED.with(|watched| *watched.borrow_mut() = Some(shared));
+        }
+        Thread { stop: Some(stop), thread }
+    }
+}
+
+impl Drop for Thread {
+    fn drop(&mut self) {
+        WATCHED.with(|watched| watched.borrow_mut().take());
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn watch(shared: Arc<Shared>, stopped: mpsc::Receiver<()>, blocked: libc::pthread_t) {
+    let config = shared.config;
+    let interval = (config.threshold / 4).max(Duration::from_millis(10));
+    let mut reported = None;
+    while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+        let (name, elapsed, generation) = {
+            let running = match shared.running.lock() {
+                Ok(running) => running,
+                Err(_) => return
+            };
+            match running.callback {
+                Some((name, start)) => (name, start.elapsed(), running.generation),
+                None => continue
+            }
+        };
+        if elapsed < config.threshold || reported == Some(generation) {
+            continue
+        }
+        reported = Some(generation);
+        wlr_log!(WLR_ERROR,
+                 "{} has been blocking the compositor for {}ms",
+                 name,
+                 elapsed.as_millis());
+        if config.action == WatchdogAction::Abort {
+            wlr_log!(WLR_ERROR, "Aborting in {}", name);
+            unsafe {
+                libc::pthread_kill(blocked, libc::SIGABRT);
+            }
+            return
+        }
+    }
+}
+
+/// Times a callback while it's alive, used by the listener macros.
+pub(crate) struct Callback {
+    name: &'static str
+}
+
+impl Callback {
+    pub fn enter(name: &'static str) -> Callback {
+        let depth = DEPTH.with(|depth| {
+                                   let outer = depth.get();
+                                   depth.set(outer + 1);
+                                   outer
+                               });
+        if depth == 0 {
+            with_shared(|shared| {
+                            if let Ok(mut running) = shared.running.lock() {
+                                running.callback = Some((name, Instant::now()));
+                                running.generation += 1;
+                            }
+                        });
+        }
+        Callback { name }
+    }
+}
+
+impl Drop for Callback {
+    fn drop(&mut self) {
+        let depth = DEPTH.with(|depth| {
+                                   let remaining = depth.get().saturating_sub(1);
+                                   depth.set(remaining);
+                                   remaining
+                               });
+        if depth != 0 {
+            return
+        }
+        let name = self.name;
+        with_shared(|shared| {
+            let start = match shared.running.lock() {
+                Ok(mut running) => running.callback.take().map(|(_, start)| start),
+                Err(_) => None
+            };
+            if let Some(start) = start {
+                let elapsed = start.elapsed();
+                if elapsed >= shared.config.threshold {
+                    wlr_log!(WLR_ERROR,
+                             "{} blocked the compositor for {}ms",
+                             name,
+                             elapsed.as_millis());
+                }
+            }
+        });
+    }
+}
+
+fn with_shared<F: FnOnce(&Shared)>(f: F) {
+    WATCHED.with(|watched| {
+                     if let Some(ref shared) = *watched.borrow() {
+                         f(shared)
+                     }
+                 })
+}
@@ -0,0 +1,98 @@
+//! What the wlroots the compositor runs on is and can do, so optional
+//! features can be turned off instead of failing once they're used:
+//!
+//! ```rust,no_run,ignore
+//! if wlroots::version() < Version::new(0, 2, 0) {
+//!     wlr_log!(WLR_INFO, "wlroots {} is too old for tablets", wlroots::version());
+//! }
+//! if wlroots::capabilities().contains(Capabilities::XWAYLAND) {
+//!     builder = builder.xwayland(xwayland_builder);
+//! }
+//! ```
+//!
+//! wlroots has no way to ask the library for its version, so the version is
+//! the one it was built against.
+
+use std::{env, fmt, path::Path};
+
+use wlroots_sys::WLROOTS_VERSION;
+
+/// A wlroots release.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub micro: u32
+}
+
+impl Version {
+    pub fn new(major: u32, minor: u32, micro: u32) -> Self {
+        Version { major, minor, micro }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.micro)
+    }
+}
+
+bitflags! {
+    /// Optional parts of wlroots, which depend on how it was built and on
+    /// the system it runs on.
+    pub struct Capabilities: u32 {
+        /// Sessions can be taken through logind, so the compositor can run
+        /// on the DRM backend without being root.
+        const SESSION = 1 << 0;
+        /// X11 clients can be run, i.e. the `Xwayland` server is installed.
+        const XWAYLAND = 1 << 1;
+        /// The compositor can run in a window on an X11 server.
+        const X11_BACKEND = 1 << 2;
+        /// The Vulkan renderer, added in wlroots 0.16.
+        const VULKAN = 1 << 3;
+        /// Explicit synchronization with DRM timelines, added in wlroots
+        /// 0.18.
+        const TIMELINE = 1 << 4;
+    }
+}
+
+/// Get the version of wlroots.
+///
+/// It's 0.0.0 if it couldn't be found out when building.
+pub fn version() -> Version {
+    let mut parts = WLROOTS_VERSION.split('.')
+                                   .map(|part| part.trim_end_matches(|c: char| !c.is_digit(10)))
+                                   .map(|part| part.parse().unwrap_or(0));
+    Version { major: parts.next().unwrap_or(0),
+              minor: parts.next().unwrap_or(0),
+              micro: parts.next().unwrap_or(0) }
+}
+
+/// Find out which of the optional parts of wlroots can be used.
+///
+/// `VULKAN` and `TIMELINE` are never set with this version of wlroots.
+pub fn capabilities() -> Capabilities {
+    let mut capabilities = Capabilities::empty();
+    // NOTE logind keeps the state of the seats there while it runs.
+    let logind = Path::new("/run/systemd/seats").is_dir();
+    if cfg!(any(feature = "systemd", feature = "elogind")) && logind {
+        capabilities |= Capabilities::SESSION
+    }
+    if cfg!(feature = "xwayland") && in_path("Xwayland") {
+        capabilities |= Capabilities::XWAYLAND
+    }
+    if cfg!(feature = "x11_backend") {
+        capabilities |= Capabilities::X11_BACKEND
+    }
+    capabilities
+}
+
+/// Determine if the program can be run without giving its path, like wlroots
+/// runs `Xwayland`.
+fn in_path(program: &str) -> bool {
+    env::var_os("PATH").map_or(false, |paths| {
+                                   env::split_paths(&paths).any(|path| {
+                                                                   path.join(program).is_file()
+                                                               })
+                               })
+}
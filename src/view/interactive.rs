@@ -0,0 +1,177 @@
+//! Moving and resizing views by dragging them with the pointer.
+//!
+//! Both keep where the view and the cursor were when the drag began, and
+//! work out from the cursor where the view goes. The compositor keeps the
+//! position of views itself, so it's given back to be stored:
+//!
+//! ```rust,no_run,ignore
+//! // In the request_resize callback of the shell:
+//! self.resize = Some(view.begin_interactive_resize(event.edges(), position, cursor)?);
+//!
+//! // When the pointer moves:
+//! resize.motion(cursor.coords())?;
+//!
+//! // When the surface of the view commits:
+//! self.position = resize.committed()?;
+//!
+//! // When the button is released:
+//! resize.end()?;
+//! ```
+
+use {area::{Area, Origin, Size},
+     utils::{edges::Edges, HandleResult}};
+
+use super::View;
+
+/// Moving a view with the pointer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InteractiveMove {
+    view: View,
+    /// The position of the view when the move began.
+    position: Origin,
+    /// The position of the cursor when the move began.
+    cursor: (f64, f64)
+}
+
+impl InteractiveMove {
+    /// Get the view being moved.
+    pub fn view(&self) -> &View {
+        &self.view
+    }
+
+    /// Get where the view goes now that the cursor moved there.
+    pub fn motion(&self, (x, y): (f64, f64)) -> Origin {
+        Origin::new(self.position.x + (x - self.cursor.0) as i32,
+                    self.position.y + (y - self.cursor.1) as i32)
+    }
+}
+
+/// Resizing a view with the pointer, from some of its edges.
+///
+/// The edges opposite to the ones that are dragged stay where they are.
+/// Clients take their time to draw themselves at the new size, so the
+/// position of a view resized from its left or top edge is only changed
+/// once it committed the new size (see `committed`). Otherwise the window
+/// would jump back and forth while it's resized.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InteractiveResize {
+    view: View,
+    edges: Edges,
+    /// The window (without its shadows) in layout coordinates, when the
+    /// resize began.
+    area: Area,
+    /// The position of the cursor when the resize began.
+    cursor: (f64, f64),
+    /// The last size the window was asked to take.
+    requested: Size
+}
+
+impl InteractiveResize {
+    /// Get the view being resized.
+    pub fn view(&self) -> &View {
+        &self.view
+    }
+
+    /// Get the edges being dragged.
+    pub fn edges(&self) -> Edges {
+        self.edges
+    }
+
+    /// Get the last size the window was asked to take.
+    pub fn requested_size(&self) -> Size {
+        self.requested
+    }
+
+    /// Ask the window to take the size the cursor dragged it to.
+    ///
+    /// Returns the size that was asked for. The window is never made smaller
+    /// than 1x1; clients can limit their size further.
+    pub fn motion(&mut self, (x, y): (f64, f64)) -> HandleResult<Size> {
+        let (dx, dy) = ((x - self.cursor.0) as i32, (y - self.cursor.1) as i32);
+        let Size { mut width, mut height } = self.area.size;
+        if self.edges.contains(Edges::WLR_EDGE_LEFT) {
+            width -= dx;
+        } else if self.edges.contains(Edges::WLR_EDGE_RIGHT) {
+            width += dx;
+        }
+        if self.edges.contains(Edges::WLR_EDGE_TOP) {
+            height -= dy;
+        } else if self.edges.contains(Edges::WLR_EDGE_BOTTOM) {
+            height += dy;
+        }
+        let size = Size::new(width.max(1), height.max(1));
+        if size != self.requested {
+            let origin = self.anchored_origin(size);
+            self.view.configure(Area::new(origin, size))?;
+            self.requested = size;
+        }
+        Ok(size)
+    }
+
+    /// Get the position of the view for the size it committed.
+    ///
+    /// Call this whenever the surface of the view commits while it's being
+    /// resized. The returned position is where the surface goes (not the
+    /// window inside of it, see `View::geometry`) so that the edges that
+    /// aren't dragged stay put, whatever size the client actually chose.
+    pub fn committed(&self) -> HandleResult<Origin> {
+        let geometry = self.view.geometry()?;
+        let window = self.anchored_origin(geometry.size);
+        Ok(Origin::new(window.x - geometry.origin.x, window.y - geometry.origin.y))
+    }
+
+    /// Tell the window it's not being resized anymore.
+    pub fn end(self) -> HandleResult<()> {
+        self.view.set_resizing(false)
+    }
+
+    /// Get where the window goes in the layout, if it has the size.
+    fn anchored_origin(&self, size: Size) -> Origin {
+        let Area { origin, size: start } = self.area;
+        let x = if self.edges.contains(Edges::WLR_EDGE_LEFT) {
+            origin.x + start.width - size.width
+        } else {
+            origin.x
+        };
+        let y = if self.edges.contains(Edges::WLR_EDGE_TOP) {
+            origin.y + start.height - size.height
+        } else {
+            origin.y
+        };
+        Origin::new(x, y)
+    }
+}
+
+impl View {
+    /// Start moving the view with the pointer.
+    ///
+    /// `position` is where the view is in the layout, and `cursor` is where
+    /// the cursor is.
+    pub fn begin_interactive_move(&self, position: Origin, cursor: (f64, f64)) -> InteractiveMove {
+        InteractiveMove { view: self.clone(),
+                          position,
+                          cursor }
+    }
+
+    /// Start resizing the view from the edges with the pointer.
+    ///
+    /// `position` is where the surface of the view is in the layout, and
+    /// `cursor` is where the cursor is. Resizing from no edge at all does
+    /// nothing.
+    pub fn begin_interactive_resize(&self,
+                                    edges: Edges,
+                                    position: Origin,
+                                    cursor: (f64, f64))
+                                    -> HandleResult<InteractiveResize> {
+        let geometry = self.geometry()?;
+        let area = Area::new(Origin::new(position.x + geometry.origin.x,
+                                         position.y + geometry.origin.y),
+                             geometry.size);
+        self.set_resizing(true)?;
+        Ok(InteractiveResize { view: self.clone(),
+                               edges,
+                               area,
+                               cursor,
+                               requested: geometry.size })
+    }
+}
@@ -0,0 +1,437 @@
+//! Views are the windows of the compositor, whichever shell their client
+//! speaks.
+//!
+//! A `View` wraps the handle of an XDG shell toplevel (stable or v6) or of an
+//! XWayland surface, so the window management of the compositor can treat
+//! them the same way:
+//!
+//! ```rust,no_run,ignore
+//! let view = View::from(xdg_shell_handle);
+//! view.configure(Area::new(Origin::new(0, 0), Size::new(800, 600)))?;
+//! view.activate(true)?;
+//! view.for_each_surface(|surface, sx, sy| {
+//!     // Render the surface at (sx, sy) relative to the view...
+//! })?;
+//! ```
+//!
+//! The operations that only toplevels have do nothing on XDG popups.
+//!
+//! Whether a view can be seen behind the ones above it is found with
+//! `View::is_visible`, e.g. to only send frame callbacks to views that are:
+//!
+//! ```rust,no_run,ignore
+//! for (index, &(ref view, origin)) in stack.iter().enumerate() {
+//!     if view.is_visible(layout, origin, &stack[index + 1..])? {
+//!         view.for_each_surface(|surface, _, _| {
+//!             surface.run(|surface| surface.send_frame_done(now)).ok();
+//!         })?;
+//!     }
+//! }
+//! ```
+//!
+//! Moving and resizing views with the pointer is done with
+//! `InteractiveMove` and `InteractiveResize`, and views that want attention
+//! are tracked in `urgency`.
+
+mod interactive;
+pub mod urgency;
+
+pub use self::interactive::*;
+
+use wlroots_sys::{wlr_surface, wlr_surface_is_xdg_surface, wlr_surface_is_xdg_surface_v6,
+                  wlr_xdg_surface_from_wlr_surface, wlr_xdg_surface_v6_from_wlr_surface};
+#[cfg(feature = "xwayland")]
+use wlroots_sys::{wlr_surface_is_xwayland_surface, wlr_xwayland_surface_from_wlr_surface};
+
+use {accessibility,
+     area::{Area, Origin, Size},
+     output::layout::Layout,
+     render::{Occlusion, PixmanRegion},
+     shell::{xdg_shell, xdg_shell_v6},
+     surface,
+     utils::{HandleErr, HandleResult}};
+#[cfg(feature = "xwayland")]
+use xwayland;
+
+/// A window of a client, from any of the shells.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum View {
+    XdgShell(xdg_shell::Handle),
+    XdgShellV6(xdg_shell_v6::Handle),
+    #[cfg(feature = "xwayland")]
+    XWayland(xwayland::surface::Handle)
+}
+
+/// Run the body with the toplevel of the XDG shell surface, if it is one.
+macro_rules! with_toplevel {
+    ($handle: expr, $shell: ident, |$toplevel: ident| $body: expr) => {
+        $handle.run(|shell_surface| match shell_surface.state() {
+                        Some(&mut $shell::ShellState::TopLevel(ref mut $toplevel)) => Some($body),
+                        _ => None
+                    })
+    };
+}
+
+impl View {
+    /// Get the title of the window, if it has one.
+    pub fn title(&self) -> HandleResult<Option<String>> {
+        match *self {
+            View::XdgShell(ref handle) => {
+                with_toplevel!(handle, xdg_shell, |toplevel| toplevel.title())
+                    .map(|title| title.and_then(|title| title))
+            },
+            View::XdgShellV6(ref handle) => {
+                with_toplevel!(handle, xdg_shell_v6, |toplevel| toplevel.title())
+                    .map(|title| title.and_then(|title| title))
+            },
+            #[cfg(feature = "xwayland")]
+            View::XWayland(ref handle) => handle.run(|surface| surface.title())
+        }
+    }
+
+    /// Get what application the window belongs to: the app id of XDG
+    /// toplevels, or the class of X11 windows.
+    pub fn app_id(&self) -> HandleResult<Option<String>> {
+        match *self {
+            View::XdgShell(ref handle) => {
+                with_toplevel!(handle, xdg_shell, |toplevel| toplevel.app_id())
+                    .map(|app_id| app_id.and_then(|app_id| app_id))
+            },
+            View::XdgShellV6(ref handle) => {
+                with_toplevel!(handle, xdg_shell_v6, |toplevel| toplevel.app_id())
+                    .map(|app_id| app_id.and_then(|app_id| app_id))
+            },
+            #[cfg(feature = "xwayland")]
+            View::XWayland(ref handle) => handle.run(|surface| surface.class())
+        }
+    }
+
+    /// Get the surface the window is drawn on.
+    ///
+    /// X11 windows don't have one until they're mapped.
+    pub fn surface(&self) -> HandleResult<Option<surface::Handle>> {
+        match *self {
+            View::XdgShell(ref handle) => handle.run(|shell_surface| Some(shell_surface.surface())),
+            View::XdgShellV6(ref handle) => {
+                handle.run(|shell_surface| Some(shell_surface.surface()))
+            },
+            #[cfg(feature = "xwayland")]
+            View::XWayland(ref handle) => handle.run(|surface| surface.surface())
+        }
+    }
+
+    /// Get the size of the window, without the shadows XDG clients draw
+    /// around it.
+    pub fn size(&self) -> HandleResult<Size> {
+        self.geometry().map(|geometry| geometry.size)
+    }
+
+    /// Get the part of the surface that is the window, relative to the
+    /// surface.
+    ///
+    /// XDG clients can draw shadows around their window, which aren't part
+    /// of it. Clients that don't say where their window is, and X11 windows,
+    /// take up their whole surface.
+    pub fn geometry(&self) -> HandleResult<Area> {
+        let geometry = match *self {
+            View::XdgShell(ref handle) => handle.run(|shell_surface| shell_surface.geometry())?,
+            View::XdgShellV6(ref handle) => {
+                handle.run(|shell_surface| shell_surface.geometry())?
+            },
+            #[cfg(feature = "xwayland")]
+            View::XWayland(ref handle) => {
+                let size = handle.run(|surface| surface.geometry().size)?;
+                return Ok(Area::new(Origin::new(0, 0), size))
+            }
+        };
+        if geometry.size.width > 0 && geometry.size.height > 0 {
+            return Ok(geometry)
+        }
+        let size = match self.surface()? {
+            Some(surface) => {
+                let (width, height) = surface.run(|surface| surface.current_state().size())?;
+                Size::new(width, height)
+            },
+            None => Size::default()
+        };
+        Ok(Area::new(Origin::new(0, 0), size))
+    }
+
+    /// Ask the window to take up the area of the layout.
+    ///
+    /// Wayland clients don't know where they are, so only the size is sent
+    /// to XDG toplevels and the compositor keeps their position itself. X11
+    /// windows are told both.
+    pub fn configure(&self, area: Area) -> HandleResult<()> {
+        let Size { width, height } = area.size;
+        let (width, height) = (width.max(0), height.max(0));
+        match *self {
+            View::XdgShell(ref handle) => {
+                with_toplevel!(handle, xdg_shell, |toplevel| {
+                    toplevel.set_size(width as u32, height as u32)
+                }).map(|_| ())
+            },
+            View::XdgShellV6(ref handle) => {
+                with_toplevel!(handle, xdg_shell_v6, |toplevel| {
+                    toplevel.set_size(width as u32, height as u32)
+                }).map(|_| ())
+            },
+            #[cfg(feature = "xwayland")]
+            View::XWayland(ref handle) => {
+                handle.run(|surface| {
+                              surface.configure(area.origin.x as i16,
+                                                area.origin.y as i16,
+                                                width as u16,
+                                                height as u16)
+                          })
+            }
+        }
+    }
+
+    /// Tell the window whether it's the focused one, so it can draw itself
+    /// that way.
+    ///
+    /// Activating it publishes an `accessibility::Event::ViewFocused`, and
+    /// clears its urgency.
+    pub fn activate(&self, activated: bool) -> HandleResult<()> {
+        let res = match *self {
+            View::XdgShell(ref handle) => {
+                with_toplevel!(handle, xdg_shell, |toplevel| toplevel.set_activated(activated))
+                    .map(|_| ())
+            },
+            View::XdgShellV6(ref handle) => {
+                with_toplevel!(handle, xdg_shell_v6, |toplevel| toplevel.set_activated(activated))
+                    .map(|_| ())
+            },
+            #[cfg(feature = "xwayland")]
+            View::XWayland(ref handle) => handle.run(|surface| surface.set_activated(activated))
+        };
+        if res.is_ok() && activated {
+            self.set_urgent(false);
+            if let Ok(event) = accessibility::Event::view_focused(self) {
+                accessibility::publish(event);
+            }
+        }
+        res
+    }
+
+    /// Tell the window whether it's being resized interactively.
+    ///
+    /// X11 windows aren't told.
+    pub fn set_resizing(&self, resizing: bool) -> HandleResult<()> {
+        match *self {
+            View::XdgShell(ref handle) => {
+                with_toplevel!(handle, xdg_shell, |toplevel| toplevel.set_resizing(resizing))
+                    .map(|_| ())
+            },
+            View::XdgShellV6(ref handle) => {
+                with_toplevel!(handle, xdg_shell_v6, |toplevel| toplevel.set_resizing(resizing))
+                    .map(|_| ())
+            },
+            #[cfg(feature = "xwayland")]
+            View::XWayland(_) => Ok(())
+        }
+    }
+
+    /// Ask the window to close.
+    ///
+    /// Clients can ignore this, e.g. to ask whether to save first.
+    pub fn close(&self) -> HandleResult<()> {
+        match *self {
+            View::XdgShell(ref handle) => {
+                with_toplevel!(handle, xdg_shell, |toplevel| toplevel.close()).map(|_| ())
+            },
+            View::XdgShellV6(ref handle) => {
+                with_toplevel!(handle, xdg_shell_v6, |toplevel| toplevel.close()).map(|_| ())
+            },
+            #[cfg(feature = "xwayland")]
+            View::XWayland(ref handle) => handle.run(|surface| surface.close())
+        }
+    }
+
+    /// Call the iterator on every surface of the window (its subsurfaces and,
+    /// for XDG surfaces, its popups), with their position relative to the
+    /// window.
+    pub fn for_each_surface<F>(&self, iterator: F) -> HandleResult<()>
+        where F: FnMut(surface::Handle, i32, i32)
+    {
+        match *self {
+            View::XdgShell(ref handle) => {
+                handle.run(|shell_surface| shell_surface.for_each_surface(iterator))
+            },
+            View::XdgShellV6(ref handle) => {
+                handle.run(|shell_surface| shell_surface.for_each_surface(iterator))
+            },
+            #[cfg(feature = "xwayland")]
+            View::XWayland(ref handle) => {
+                match handle.run(|surface| surface.surface())? {
+                    Some(surface) => surface.run(|surface| surface.for_each_surface(iterator)),
+                    None => Ok(())
+                }
+            }
+        }
+    }
+
+    /// Find the surface of the window that takes input at the coordinates,
+    /// relative to its main surface, the topmost first.
+    ///
+    /// Popups, subsurfaces and the input regions their clients set are taken
+    /// into account, so this is what pointer input should be routed to.
+    /// Returns the surface with the coordinates relative to it.
+    pub fn surface_at(&self,
+                      sx: f64,
+                      sy: f64)
+                      -> HandleResult<Option<(surface::Handle, f64, f64)>> {
+        match *self {
+            View::XdgShell(ref handle) => {
+                handle.run(|shell_surface| shell_surface.surface_at(sx, sy))
+            },
+            View::XdgShellV6(ref handle) => {
+                handle.run(|shell_surface| shell_surface.surface_at(sx, sy))
+            },
+            #[cfg(feature = "xwayland")]
+            View::XWayland(ref handle) => handle.run(|surface| surface.surface_at(sx, sy))
+        }
+    }
+
+    /// Get the part of the window that can be seen on the outputs of the
+    /// layout, in layout coordinates.
+    ///
+    /// `origin` is where the main surface of the window is in the layout, and
+    /// `above` are the views stacked on top of it with where they are. Only
+    /// the opaque regions of the views above cover the window, and views
+    /// that were destroyed are skipped.
+    pub fn visible_region(&self,
+                          layout: &mut Layout,
+                          origin: Origin,
+                          above: &[(View, Origin)])
+                          -> HandleResult<PixmanRegion> {
+        let mut region = self.surfaces_region(origin)?;
+        region.intersect(&outputs_region(layout));
+        occlude(&mut region, above);
+        Ok(region)
+    }
+
+    /// Get the area the surfaces of the window cover in the layout, whether
+    /// it can be seen or not.
+    pub(crate) fn surfaces_region(&self, origin: Origin) -> HandleResult<PixmanRegion> {
+        let mut region = PixmanRegion::new();
+        self.for_each_surface(|surface, sx, sy| {
+                                  let size = surface.run(|surface| surface.current_state().size());
+                                  let (width, height) = size.unwrap_or((0, 0));
+                                  if width > 0 && height > 0 {
+                                      region.rectangle(origin.x + sx,
+                                                       origin.y + sy,
+                                                       width as _,
+                                                       height as _)
+                                  }
+                              })?;
+        Ok(region)
+    }
+
+    /// Determines if any of the window can be seen on the outputs of the
+    /// layout, see `visible_region`.
+    ///
+    /// Fully covered windows don't need frame callbacks (so their clients
+    /// stop drawing frames no one sees) or to be drawn.
+    pub fn is_visible(&self,
+                      layout: &mut Layout,
+                      origin: Origin,
+                      above: &[(View, Origin)])
+                      -> HandleResult<bool> {
+        self.visible_region(layout, origin, above).map(|region| !region.is_empty())
+    }
+
+    /// Get the view drawn on the surface, if the surface is the main surface
+    /// of a window.
+    pub(crate) unsafe fn from_surface(surface: *mut wlr_surface) -> Option<View> {
+        if surface.is_null() {
+            return None
+        }
+        if wlr_surface_is_xdg_surface(surface) {
+            let shell_surface = wlr_xdg_surface_from_wlr_surface(surface);
+            if shell_surface.is_null() || (*shell_surface).data.is_null() {
+                return None
+            }
+            return Some(View::XdgShell(xdg_shell::Handle::from_ptr(shell_surface)))
+        }
+        if wlr_surface_is_xdg_surface_v6(surface) {
+            let shell_surface = wlr_xdg_surface_v6_from_wlr_surface(surface);
+            if shell_surface.is_null() || (*shell_surface).data.is_null() {
+                return None
+            }
+            return Some(View::XdgShellV6(xdg_shell_v6::Handle::from_ptr(shell_surface)))
+        }
+        #[cfg(feature = "xwayland")]
+        {
+            if wlr_surface_is_xwayland_surface(surface) {
+                let xwayland_surface = wlr_xwayland_surface_from_wlr_surface(surface);
+                if xwayland_surface.is_null() || (*xwayland_surface).data.is_null() {
+                    return None
+                }
+                return Some(View::XWayland(xwayland::surface::Handle::from_ptr(xwayland_surface)))
+            }
+        }
+        None
+    }
+
+    /// Determines if the view is still alive, i.e. the window wasn't
+    /// destroyed.
+    pub fn is_alive(&self) -> bool {
+        let res = match *self {
+            View::XdgShell(ref handle) => handle.run(|_| ()),
+            View::XdgShellV6(ref handle) => handle.run(|_| ()),
+            #[cfg(feature = "xwayland")]
+            View::XWayland(ref handle) => handle.run(|_| ())
+        };
+        match res {
+            Err(HandleErr::AlreadyDropped) => false,
+            _ => true
+        }
+    }
+}
+
+impl From<xdg_shell::Handle> for View {
+    fn from(handle: xdg_shell::Handle) -> Self {
+        View::XdgShell(handle)
+    }
+}
+
+impl From<xdg_shell_v6::Handle> for View {
+    fn from(handle: xdg_shell_v6::Handle) -> Self {
+        View::XdgShellV6(handle)
+    }
+}
+
+#[cfg(feature = "xwayland")]
+impl From<xwayland::surface::Handle> for View {
+    fn from(handle: xwayland::surface::Handle) -> Self {
+        View::XWayland(handle)
+    }
+}
+
+/// Get the area the outputs of the layout cover.
+pub(crate) fn outputs_region(layout: &mut Layout) -> PixmanRegion {
+    let mut outputs = PixmanRegion::new();
+    for (output, _) in layout.outputs() {
+        if let Ok(area) = output.run(|output| layout.get_box(output)) {
+            outputs.rectangle(area.origin.x,
+                              area.origin.y,
+                              area.size.width as _,
+                              area.size.height as _)
+        }
+    }
+    outputs
+}
+
+/// Remove what the opaque regions of the views above cover from the region.
+pub(crate) fn occlude(region: &mut PixmanRegion, above: &[(View, Origin)]) {
+    let mut occlusion = Occlusion::new();
+    for &(ref view, origin) in above {
+        let _ = view.for_each_surface(|surface, sx, sy| {
+            surface.run(|surface| occlusion.add_surface(surface, origin.x + sx, origin.y + sy))
+                   .ok();
+        });
+    }
+    occlusion.clip_damage(region);
+}
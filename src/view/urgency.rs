@@ -0,0 +1,131 @@
+//! Views that want the attention of the user, e.g. for a bar to mark them.
+//!
+//! The compositor can mark views urgent itself with `View::set_urgent`, and
+//! they're marked on their own:
+//!
+//! * when they ask to be activated with `xdg-activation` and the compositor
+//!   doesn't honour it (see `extensions::xdg_activation`),
+//! * when an X11 window sets the urgency hint in its `WM_HINTS`.
+//!
+//! Activating a view with `View::activate` clears it.
+
+use std::{cell::RefCell, rc::Rc};
+
+#[cfg(feature = "xwayland")]
+use wlroots_sys::wlr_xwayland_surface;
+
+#[cfg(feature = "xwayland")]
+use xwayland;
+
+use super::View;
+
+/// The urgency flag of `WM_HINTS`.
+#[cfg(feature = "xwayland")]
+const X_URGENCY_HINT: u32 = 1 << 8;
+
+/// Called with the view and whether it's urgent now, whenever that changes.
+pub type UrgencyCallback = Box<FnMut(View, bool)>;
+
+/// A view that is urgent.
+struct Urgent {
+    view: View,
+    /// Whether it's only urgent because of its X11 urgency hint, which
+    /// clears it again once the hint is unset.
+    from_hint: bool
+}
+
+thread_local! {
+    static URGENT: RefCell<Vec<Urgent>> = RefCell::new(Vec::new());
+    static CALLBACK: RefCell<Option<Rc<RefCell<UrgencyCallback>>>> = RefCell::new(None);
+}
+
+/// Set the function that's called whenever a view becomes urgent, or stops
+/// being urgent.
+pub fn set_urgency_callback<F>(callback: F)
+    where F: FnMut(View, bool) + 'static
+{
+    let callback: UrgencyCallback = Box::new(callback);
+    CALLBACK.with(|current| *current.borrow_mut() = Some(Rc::new(RefCell::new(callback))))
+}
+
+/// Get the views that are urgent, in the order they became urgent.
+pub fn urgent_views() -> Vec<View> {
+    URGENT.with(|urgent| {
+                    let mut urgent = urgent.borrow_mut();
+                    urgent.retain(|entry| entry.view.is_alive());
+                    urgent.iter().map(|entry| entry.view.clone()).collect()
+                })
+}
+
+impl View {
+    /// Determine if the view wants the attention of the user.
+    pub fn urgent(&self) -> bool {
+        URGENT.with(|urgent| urgent.borrow().iter().any(|entry| entry.view == *self))
+    }
+
+    /// Mark the view as wanting the attention of the user, or not.
+    ///
+    /// The urgency callback is called if this changes it.
+    pub fn set_urgent(&self, urgent: bool) {
+        set(self, urgent, false)
+    }
+}
+
+fn set(view: &View, urgent: bool, from_hint: bool) {
+    let changed = URGENT.with(|entries| {
+        let mut entries = entries.borrow_mut();
+        entries.retain(|entry| entry.view.is_alive());
+        match entries.iter().position(|entry| entry.view == *view) {
+            Some(index) if !urgent => {
+                entries.remove(index);
+                true
+            },
+            Some(index) => {
+                // NOTE It stays urgent once the compositor marked it, even if
+                // the hint is unset again.
+                entries[index].from_hint &= from_hint;
+                false
+            },
+            None if urgent => {
+                entries.push(Urgent { view: view.clone(), from_hint });
+                true
+            },
+            None => false
+        }
+    });
+    if !changed {
+        return
+    }
+    let callback = CALLBACK.with(|callback| callback.borrow().clone());
+    if let Some(callback) = callback {
+        if let Ok(mut callback) = callback.try_borrow_mut() {
+            (&mut **callback)(view.clone(), urgent)
+        }
+    }
+}
+
+/// Mark the X11 window urgent if its urgency hint is set, or not urgent
+/// anymore if that was why it was urgent.
+///
+/// It's checked when the window is mapped and whenever its `WM_HINTS` are
+/// set.
+#[cfg(feature = "xwayland")]
+pub(crate) unsafe fn check_hints(surface: *mut wlr_xwayland_surface) {
+    if surface.is_null() || (*surface).data.is_null() {
+        return
+    }
+    // NOTE The hints are gone once the property is deleted.
+    let hinted = !(*surface).hints.is_null() && (*(*surface).hints).flags & X_URGENCY_HINT != 0;
+    let view = View::XWayland(xwayland::surface::Handle::from_ptr(surface));
+    let from_hint = URGENT.with(|urgent| {
+        urgent.borrow()
+              .iter()
+              .find(|entry| entry.view == view)
+              .map(|entry| entry.from_hint)
+    });
+    match from_hint {
+        None if hinted => set(&view, true, true),
+        Some(true) if !hinted => set(&view, false, true),
+        _ => {}
+    }
+}
@@ -9,13 +9,21 @@
 
 use libc;
 use wayland_sys::server::signal::wl_signal_add;
-use wlroots_sys::wlr_xwayland_surface;
+use wlroots_sys::{wl_listener, wl_signal, wlr_xwayland, wlr_xwayland_surface};
 
-use {compositor, xwayland, utils::Handleable};
+use {compositor, shell::ShellEvents, xwayland, utils::Handleable};
 
 /// Callback that's triggered when the XWayland library is ready.
 pub type OnReady = fn(compositor::Handle);
 
+/// Callback that's triggered when the X server is ready, with the name of its
+/// display (e.g. `":1"`).
+///
+/// No X11 client is connected yet, so this is the time to export `DISPLAY`
+/// (wlroots doesn't set it), start an xsettings daemon or load the X
+/// resources with `xrdb`.
+pub type ServerReady = fn(compositor_handle: compositor::Handle, display_name: &str);
+
 /// Callback that's triggered when a new surface is presented to the X
 /// server.
 pub type NewSurface = fn(compositor_handle: compositor::Handle,
@@ -25,14 +33,19 @@ pub type NewSurface = fn(compositor_handle: compositor::Handle,
 wayland_listener_static! {
     static mut MANAGER;
     (Manager, Builder): [
+        [server_ready: ServerReady]
         (OnReady, on_ready_listener, xwayland_ready) => (ready_notify, xwayland_ready):
-        |manager: &mut Manager, _data: *mut libc::c_void,|
+        |manager: &mut Manager, data: *mut libc::c_void,|
         unsafe {
             let compositor = match compositor::handle() {
                 Some(handle) => handle,
                 None => return
             };
 
+            let xwayland = data as *mut wlr_xwayland;
+            if let (Some(server_ready), false) = (manager.server_ready, xwayland.is_null()) {
+                server_ready(compositor.clone(), &format!(":{}", (*xwayland).display));
+            }
             manager.xwayland_ready.map(|f| f(compositor));
         };
 
@@ -47,36 +60,66 @@ wayland_listener_static! {
             let shell_surface = xwayland::surface::Surface::new(surface_ptr);
             let xwayland_handler = manager.surface_added
                 .and_then(|f| f(compositor, shell_surface.weak_reference()));
+            let listened = xwayland_handler.as_ref()
+                                           .map_or(ShellEvents::empty(), |handler| handler.events());
             let mut shell = xwayland::surface::Shell::new((shell_surface, xwayland_handler));
 
             wl_signal_add(&mut (*surface_ptr).events.destroy as *mut _ as _,
                           shell.destroy_listener() as *mut _ as _);
-            wl_signal_add(&mut (*surface_ptr).events.request_configure as *mut _ as _,
-                          shell.request_configure_listener() as *mut _ as _);
-            wl_signal_add(&mut (*surface_ptr).events.request_move as *mut _ as _,
-                          shell.request_move_listener() as *mut _ as _);
-            wl_signal_add(&mut (*surface_ptr).events.request_resize as *mut _ as _,
-                          shell.request_resize_listener() as *mut _ as _);
-            wl_signal_add(&mut (*surface_ptr).events.request_maximize as *mut _ as _,
-                          shell.request_maximize_listener() as *mut _ as _);
-            wl_signal_add(&mut (*surface_ptr).events.request_fullscreen as *mut _ as _,
-                          shell.request_fullscreen_listener() as *mut _ as _);
             wl_signal_add(&mut (*surface_ptr).events.map as *mut _ as _,
                           shell.map_listener() as *mut _ as _);
             wl_signal_add(&mut (*surface_ptr).events.unmap as *mut _ as _,
                           shell.unmap_listener() as *mut _ as _);
-            wl_signal_add(&mut (*surface_ptr).events.set_title as *mut _ as _,
-                          shell.set_title_listener() as *mut _ as _);
-            wl_signal_add(&mut (*surface_ptr).events.set_class as *mut _ as _,
-                          shell.set_class_listener() as *mut _ as _);
-            wl_signal_add(&mut (*surface_ptr).events.set_parent as *mut _ as _,
-                          shell.set_parent_listener() as *mut _ as _);
-            wl_signal_add(&mut (*surface_ptr).events.set_pid as *mut _ as _,
-                          shell.set_pid_listener() as *mut _ as _);
-            wl_signal_add(&mut (*surface_ptr).events.set_window_type as *mut _ as _,
-                          shell.set_window_type_listener() as *mut _ as _);
-            wl_signal_add(&mut (*surface_ptr).events.ping_timeout as *mut _ as _,
-                          shell.ping_timeout_listener() as *mut _ as _);
+            // NOTE The hints are needed for the urgency of the view, and the
+            // title and class for the foreign toplevel list, whether the
+            // handler wants them or not.
+            let mut listened = listened | ShellEvents::HINTS;
+            if cfg!(feature = "foreign_toplevel_list") {
+                listened |= ShellEvents::TITLE | ShellEvents::CLASS;
+            }
+            let events = &mut (*surface_ptr).events;
+            let signals: [(ShellEvents, *mut wl_signal, *mut wl_listener); 12] =
+                [(ShellEvents::CONFIGURE,
+                  &mut events.request_configure as *mut _,
+                  shell.request_configure_listener()),
+                 (ShellEvents::MOVE,
+                  &mut events.request_move as *mut _,
+                  shell.request_move_listener()),
+                 (ShellEvents::RESIZE,
+                  &mut events.request_resize as *mut _,
+                  shell.request_resize_listener()),
+                 (ShellEvents::MAXIMIZE,
+                  &mut events.request_maximize as *mut _,
+                  shell.request_maximize_listener()),
+                 (ShellEvents::FULLSCREEN,
+                  &mut events.request_fullscreen as *mut _,
+                  shell.request_fullscreen_listener()),
+                 (ShellEvents::TITLE,
+                  &mut events.set_title as *mut _,
+                  shell.set_title_listener()),
+                 (ShellEvents::CLASS,
+                  &mut events.set_class as *mut _,
+                  shell.set_class_listener()),
+                 (ShellEvents::PARENT,
+                  &mut events.set_parent as *mut _,
+                  shell.set_parent_listener()),
+                 (ShellEvents::PID,
+                  &mut events.set_pid as *mut _,
+                  shell.set_pid_listener()),
+                 (ShellEvents::WINDOW_TYPE,
+                  &mut events.set_window_type as *mut _,
+                  shell.set_window_type_listener()),
+                 (ShellEvents::HINTS,
+                  &mut events.set_hints as *mut _,
+                  shell.set_hints_listener()),
+                 (ShellEvents::PING_TIMEOUT,
+                  &mut events.ping_timeout as *mut _,
+                  shell.ping_timeout_listener())];
+            for &(event, signal, listener) in &signals {
+                if listened.contains(event) {
+                    wl_signal_add(signal as _, listener as _);
+                }
+            }
             let shell_data = (*surface_ptr).data as *mut xwayland::surface::State;
             (*shell_data).shell = Box::into_raw(shell);
             // TODO Pass in the new surface from the data
@@ -2,6 +2,8 @@ pub(crate) mod hints;
 pub mod manager;
 mod server;
 pub mod surface;
+#[cfg(feature = "xwayland_xcb")]
+pub mod xcb;
 
 pub use events::xwayland_events as event;
 pub use self::server::*;
@@ -1,32 +1,36 @@
 use libc::c_int;
 use wayland_sys::server::signal::wl_signal_add;
-use wlroots_sys::{pid_t, wl_client, wl_display, wlr_compositor, wlr_xwayland, wlr_xwayland_create,
-                  wlr_xwayland_destroy, wlr_xwayland_set_cursor};
+use wlroots_sys::{pid_t, wl_client, wl_display, wlr_compositor, wlr_seat, wlr_xwayland,
+                  wlr_xwayland_create, wlr_xwayland_destroy, wlr_xwayland_set_cursor,
+                  wlr_xwayland_set_seat};
 
-use xwayland;
+use {seat::Seat, xwayland};
 
 #[allow(dead_code)]
 pub struct Server {
     xwayland: *mut wlr_xwayland,
-    manager: &'static mut xwayland::manager::Manager
+    manager: &'static mut xwayland::manager::Manager,
+    dnd: bool
 }
 
 impl Server {
     pub(crate) unsafe fn new(display: *mut wl_display,
                              compositor: *mut wlr_compositor,
                              builder: xwayland::manager::Builder,
-                             lazy: bool)
-                             -> Self {
+                             lazy: bool,
+                             dnd: bool)
+                             -> Option<Self> {
         let xwayland = wlr_xwayland_create(display, compositor, lazy);
         if xwayland.is_null() {
-            panic!("Could not start XWayland server")
+            wlr_log!(WLR_ERROR, "Could not start XWayland server");
+            return None
         }
         let manager = xwayland::manager::Manager::build(builder);
         wl_signal_add(&mut (*xwayland).events.ready as *mut _ as _,
                       (&mut manager.on_ready_listener) as *mut _ as _);
         wl_signal_add(&mut (*xwayland).events.new_surface as *mut _ as _,
                       (&mut manager.new_surface_listener) as *mut _ as _);
-        Server { xwayland, manager }
+        Some(Server { xwayland, manager, dnd })
     }
 
     /// Get the PID of the XWayland server.
@@ -54,6 +58,32 @@ impl Server {
         unsafe { (*self.xwayland).client }
     }
 
+    /// Bridge the selections and drag and drop of the seat with the X11
+    /// clients.
+    ///
+    /// The window manager of XWayland proxies the clipboard, the primary
+    /// selection and drags between X11 and Wayland clients of this seat on
+    /// its own, so X11 windows take part in drags like any other surface.
+    /// Drags need the data device manager (see
+    /// `compositor::Builder::data_device_manager`).
+    pub fn set_seat(&mut self, seat: &Seat) {
+        unsafe { wlr_xwayland_set_seat(self.xwayland, seat.as_ptr()) }
+    }
+
+    /// Determines if the seat of XWayland is set automatically, see
+    /// `compositor::Builder::xwayland_dnd`.
+    pub fn dnd(&self) -> bool {
+        self.dnd
+    }
+
+    /// Give XWayland the seat if it should bridge drag and drop, and doesn't
+    /// have a seat yet.
+    pub(crate) unsafe fn seat_created(&mut self, seat: *mut wlr_seat) {
+        if self.dnd && (*self.xwayland).seat.is_null() {
+            wlr_xwayland_set_seat(self.xwayland, seat)
+        }
+    }
+
     pub fn set_cursor(&mut self,
                       bytes: &mut [u8],
                       stride: u32,
@@ -1,16 +1,20 @@
-use std::{ptr, cell::Cell, rc::{Rc, Weak}};
+use std::{ptr, slice, cell::Cell, rc::{Rc, Weak}};
 
 use libc::{self, size_t, int16_t, uint16_t};
 
 use wayland_sys::server::WAYLAND_SERVER_HANDLE;
 use wlroots_sys::{pid_t, wl_event_source, wlr_xwayland_surface, xcb_atom_t, xcb_window_t,
-                  wlr_xwayland_surface_configure, wlr_xwayland_surface_activate};
+                  wlr_xwayland_surface_configure, wlr_xwayland_surface_activate,
+                  wlr_xwayland_surface_close, wlr_surface_surface_at};
 
 use {area::{Area, Size, Origin},
      compositor,
+     shell::ShellEvents,
      surface::{self, InternalState},
      xwayland,
      utils::{self, HandleErr, HandleResult, Handleable, c_to_rust_string}};
+#[cfg(feature = "foreign_toplevel_list")]
+use {extensions::foreign_toplevel_list, view::View};
 pub use xwayland::hints::{Hints, SizeHints};
 
 pub type Handle = utils::Handle<(), wlr_xwayland_surface, Surface>;
@@ -97,6 +101,12 @@ pub trait Handler {
                        surface_handle: Option<surface::Handle>,
                        xwayland_surface_handle: Handle) {}
 
+    /// Called when the `WM_HINTS` of the XWayland surface have been set.
+    fn hints_set(&mut self,
+                 compositor_handle: compositor::Handle,
+                 surface_handle: Option<surface::Handle>,
+                 xwayland_surface_handle: Handle) {}
+
     /// Called when the ping request timed out.
     ///
     /// This usually indicates something is wrong with the client.
@@ -104,6 +114,17 @@ pub trait Handler {
                     compositor_handle: compositor::Handle,
                     surface_handle: Option<surface::Handle>,
                     xwayland_surface_handle: Handle) {}
+
+    /// The events this handler is called for.
+    ///
+    /// Only these events are listened to on the surface the handler is
+    /// given to, like `xdg_shell::Handler::events`.
+    ///
+    /// Called once, when the surface appears. By default every event is
+    /// listened to.
+    fn events(&self) -> ShellEvents {
+        ShellEvents::all()
+    }
 }
 
 wayland_listener!(pub(crate) Shell, (Surface, Option<Box<Handler>>), [
@@ -211,6 +232,9 @@ wayland_listener!(pub(crate) Shell, (Surface, Option<Box<Handler>>), [
     };
     map_listener => map_notify: |this: &mut Shell, _data: *mut libc::c_void,|
     unsafe {
+        ::view::urgency::check_hints(this.data.0.shell_surface);
+        #[cfg(feature = "foreign_toplevel_list")]
+        foreign_toplevel_list::view_mapped(View::XWayland(this.data.0.weak_reference()));
         let (ref mut shell_surface, ref mut manager) = match &mut this.data {
             (_, None) => return,
             (ss, Some(manager)) => (ss, manager)
@@ -232,6 +256,8 @@ wayland_listener!(pub(crate) Shell, (Surface, Option<Box<Handler>>), [
     };
     unmap_listener => unmap_notify: |this: &mut Shell, _data: *mut libc::c_void,|
     unsafe {
+        #[cfg(feature = "foreign_toplevel_list")]
+        foreign_toplevel_list::view_unmapped(&View::XWayland(this.data.0.weak_reference()));
         let (ref mut shell_surface, ref mut manager) = match &mut this.data {
             (_, None) => return,
             (ss, Some(manager)) => (ss, manager)
@@ -247,6 +273,8 @@ wayland_listener!(pub(crate) Shell, (Surface, Option<Box<Handler>>), [
     };
     set_title_listener => set_title_notify: |this: &mut Shell, _data: *mut libc::c_void,|
     unsafe {
+        #[cfg(feature = "foreign_toplevel_list")]
+        foreign_toplevel_list::view_changed(&View::XWayland(this.data.0.weak_reference()));
         let (ref mut shell_surface, ref mut manager) = match &mut this.data {
             (_, None) => return,
             (ss, Some(manager)) => (ss, manager)
@@ -262,6 +290,8 @@ wayland_listener!(pub(crate) Shell, (Surface, Option<Box<Handler>>), [
     };
     set_class_listener => set_class_notify: |this: &mut Shell, _data: *mut libc::c_void,|
     unsafe {
+        #[cfg(feature = "foreign_toplevel_list")]
+        foreign_toplevel_list::view_changed(&View::XWayland(this.data.0.weak_reference()));
         let (ref mut shell_surface, ref mut manager) = match &mut this.data {
             (_, None) => return,
             (ss, Some(manager)) => (ss, manager)
@@ -321,6 +351,22 @@ wayland_listener!(pub(crate) Shell, (Surface, Option<Box<Handler>>), [
                        surface,
                        shell_surface.weak_reference());
     };
+    set_hints_listener => set_hints_notify: |this: &mut Shell, _data: *mut libc::c_void,|
+    unsafe {
+        ::view::urgency::check_hints(this.data.0.shell_surface);
+        let (ref mut shell_surface, ref mut manager) = match &mut this.data {
+            (_, None) => return,
+            (ss, Some(manager)) => (ss, manager)
+        };
+        let surface = shell_surface.surface();
+        let compositor = match compositor::handle() {
+            Some(handle) => handle,
+            None => return
+        };
+        manager.hints_set(compositor,
+                          surface,
+                          shell_surface.weak_reference());
+    };
     ping_timeout_listener => ping_timeout_notify: |this: &mut Shell,
                                                    _data: *mut libc::c_void,|
     unsafe {
@@ -392,6 +438,27 @@ impl Surface {
         }
     }
 
+    /// Find the surface of the subsurface tree of the window that takes input
+    /// at the surface-local coordinates, the topmost first.
+    ///
+    /// Returns the surface with the coordinates relative to it, or `None` if
+    /// none accepts input there or the window isn't mapped.
+    pub fn surface_at(&self, sx: f64, sy: f64) -> Option<(surface::Handle, f64, f64)> {
+        let (mut sub_x, mut sub_y) = (0.0, 0.0);
+        unsafe {
+            let surface = (*self.shell_surface).surface;
+            if surface.is_null() {
+                return None
+            }
+            let sub_surface = wlr_surface_surface_at(surface, sx, sy, &mut sub_x, &mut sub_y);
+            if sub_surface.is_null() {
+                None
+            } else {
+                Some((surface::Handle::from_ptr(sub_surface), sub_x, sub_y))
+            }
+        }
+    }
+
     /// Get the coordinates of the window.
     ///
     /// Return format is (x, y)
@@ -472,24 +539,17 @@ impl Surface {
         }
     }
 
-    /// Get the type of the window from xcb.
-    pub unsafe fn window_type(&self) -> *mut xcb_atom_t {
-        (*self.shell_surface).window_type
-    }
-
-    /// Get the length of the window_type ptr
-    pub unsafe fn window_type_len(&self) -> size_t {
-        (*self.shell_surface).window_type_len
-    }
-
-    /// Get the protocols of the client.
-    pub unsafe fn protocols(&self) -> *mut xcb_atom_t {
-        (*self.shell_surface).protocols
+    /// Get the atoms of the `_NET_WM_WINDOW_TYPE` of the window, most
+    /// preferred first.
+    pub fn window_type_atoms(&self) -> &[xcb_atom_t] {
+        unsafe {
+            atoms((*self.shell_surface).window_type, (*self.shell_surface).window_type_len)
+        }
     }
 
-    /// Get the length of the protocols ptr.
-    pub unsafe fn protocols_len(&self) -> size_t {
-        (*self.shell_surface).protocols_len
+    /// Get the atoms of the `WM_PROTOCOLS` the client supports.
+    pub fn protocol_atoms(&self) -> &[xcb_atom_t] {
+        unsafe { atoms((*self.shell_surface).protocols, (*self.shell_surface).protocols_len) }
     }
 
     /// Get the decorations on this XWayland client.
@@ -567,6 +627,12 @@ impl Surface {
     pub fn set_activated(&self, active: bool) {
         unsafe { wlr_xwayland_surface_activate(self.shell_surface, active); }
     }
+
+    /// Ask the window to close, the way window managers do (with
+    /// `WM_DELETE_WINDOW` if the window supports it).
+    pub fn close(&self) {
+        unsafe { wlr_xwayland_surface_close(self.shell_surface) }
+    }
 }
 
 impl Drop for Surface {
@@ -574,6 +640,7 @@ impl Drop for Surface {
         if Rc::strong_count(&self.liveliness) > 1 {
             return
         }
+        utils::user_data::clear(self.shell_surface);
         unsafe {
             Box::from_raw((*self.shell_surface).data as *mut State);
         }
@@ -653,9 +720,23 @@ impl Drop for Shell {
             ffi_dispatch!(WAYLAND_SERVER_HANDLE,
                           wl_list_remove,
                           self.set_window_type_listener() as *mut _ as _);
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_list_remove,
+                          self.set_hints_listener() as *mut _ as _);
             ffi_dispatch!(WAYLAND_SERVER_HANDLE,
                           wl_list_remove,
                           self.ping_timeout_listener() as *mut _ as _);
         }
     }
 }
+
+/// Borrow the atoms of a list the XWayland window manager read from a window
+/// property.
+unsafe fn atoms<'surface>(atoms: *mut xcb_atom_t, len: size_t) -> &'surface [xcb_atom_t] {
+    // NOTE The list is null until the property is set.
+    if atoms.is_null() || len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(atoms, len)
+    }
+}
@@ -0,0 +1,407 @@
+//! A connection of the compositor to the XWayland server, for reading the
+//! properties of X11 windows that wlroots doesn't surface (e.g.
+//! `_NET_WM_STATE`, `_MOTIF_WM_HINTS` or the rest of `WM_HINTS`).
+//!
+//! Requests don't block: the replies are read when the connection becomes
+//! readable on the event loop of the compositor, and passed to the callback
+//! of their request.
+//!
+//! ```rust,no_run,ignore
+//! let mut connection = xcb::Connection::open(compositor, display_name)?;
+//! connection.get_property_named(surface.window_id(), "_NET_WM_STATE", xcb::ATOM_ATOM,
+//!                               Box::new(|property| {
+//!     let states = property.map(|property| property.atoms()).unwrap_or_default();
+//!     // ...
+//! }))?;
+//! ```
+//!
+//! Open the connection once the server is ready (see
+//! `xwayland::manager::ServerReady`).
+
+use std::{error::Error, fmt, mem, panic, ptr, slice};
+
+use libc::{self, c_char, c_int, c_uint, c_void};
+use wayland_sys::server::{wl_event_source, WAYLAND_SERVER_HANDLE};
+use wlroots_sys::{xcb_atom_t, xcb_window_t};
+
+use {compositor::Compositor, utils::{self, safe_as_cstring, WL_EVENT_READABLE}};
+
+/// The most of a property that's read, in 32 bit units (the same limit as the
+/// window manager of wlroots).
+const MAX_PROPERTY_LENGTH: u32 = 2048;
+
+/// Matches properties of any type in `get_property`.
+pub const GET_PROPERTY_TYPE_ANY: xcb_atom_t = 0;
+/// The predefined `ATOM` atom, the type of lists of atoms.
+pub const ATOM_ATOM: xcb_atom_t = 4;
+/// The predefined `CARDINAL` atom, the type of lists of numbers.
+pub const ATOM_CARDINAL: xcb_atom_t = 6;
+/// The predefined `WINDOW` atom, the type of lists of windows.
+pub const ATOM_WINDOW: xcb_atom_t = 33;
+/// The predefined `WM_HINTS` atom.
+pub const ATOM_WM_HINTS: xcb_atom_t = 35;
+
+#[repr(C)]
+#[allow(non_camel_case_types)]
+struct xcb_connection_t {
+    _private: [u8; 0]
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+#[allow(non_camel_case_types)]
+struct xcb_cookie_t {
+    sequence: c_uint
+}
+
+#[repr(C)]
+#[allow(non_camel_case_types, dead_code)]
+struct xcb_intern_atom_reply_t {
+    response_type: u8,
+    pad0: u8,
+    sequence: u16,
+    length: u32,
+    atom: xcb_atom_t
+}
+
+#[repr(C)]
+#[allow(non_camel_case_types, dead_code)]
+struct xcb_get_property_reply_t {
+    response_type: u8,
+    format: u8,
+    sequence: u16,
+    length: u32,
+    type_: xcb_atom_t,
+    bytes_after: u32,
+    value_len: u32,
+    pad0: [u8; 12]
+}
+
+extern "C" {
+    fn xcb_connect(displayname: *const c_char, screenp: *mut c_int) -> *mut xcb_connection_t;
+    fn xcb_disconnect(c: *mut xcb_connection_t);
+    fn xcb_connection_has_error(c: *mut xcb_connection_t) -> c_int;
+    fn xcb_get_file_descriptor(c: *mut xcb_connection_t) -> c_int;
+    fn xcb_flush(c: *mut xcb_connection_t) -> c_int;
+    fn xcb_poll_for_event(c: *mut xcb_connection_t) -> *mut c_void;
+    fn xcb_poll_for_reply(c: *mut xcb_connection_t,
+                          request: c_uint,
+                          reply: *mut *mut c_void,
+                          error: *mut *mut c_void)
+                          -> c_int;
+    fn xcb_intern_atom(c: *mut xcb_connection_t,
+                       only_if_exists: u8,
+                       name_len: u16,
+                       name: *const c_char)
+                       -> xcb_cookie_t;
+    fn xcb_get_property(c: *mut xcb_connection_t,
+                        delete: u8,
+                        window: xcb_window_t,
+                        property: xcb_atom_t,
+                        type_: xcb_atom_t,
+                        long_offset: u32,
+                        long_length: u32)
+                        -> xcb_cookie_t;
+    fn xcb_get_property_value(reply: *const xcb_get_property_reply_t) -> *mut c_void;
+    fn xcb_get_property_value_length(reply: *const xcb_get_property_reply_t) -> c_int;
+}
+
+/// Callback with the interned atom, or `None` if that failed.
+pub type AtomCallback = Box<FnOnce(Option<xcb_atom_t>)>;
+
+/// Callback with the property, or `None` if the window doesn't have it
+/// (or the request failed).
+pub type PropertyCallback = Box<FnOnce(Option<Property>)>;
+
+/// The value of a property of a window.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Property {
+    /// The type of the value, e.g. `ATOM_ATOM`.
+    pub type_: xcb_atom_t,
+    /// The size of the items of the value in bits: 8, 16 or 32.
+    pub format: u8,
+    /// The bytes of the value, in the byte order of the compositor.
+    pub value: Vec<u8>
+}
+
+impl Property {
+    /// Get the value as a list of 32 bit items, e.g. for `CARDINAL` or
+    /// `WM_HINTS`.
+    ///
+    /// Returns an empty list if the items have a different size.
+    pub fn u32s(&self) -> Vec<u32> {
+        if self.format != 32 {
+            return Vec::new()
+        }
+        self.value
+            .chunks(4)
+            .filter(|chunk| chunk.len() == 4)
+            .map(|chunk| {
+                let mut item = [0; 4];
+                item.copy_from_slice(chunk);
+                u32::from_ne_bytes(item)
+            })
+            .collect()
+    }
+
+    /// Get the value as a list of atoms, e.g. for `_NET_WM_STATE`.
+    pub fn atoms(&self) -> Vec<xcb_atom_t> {
+        self.u32s()
+    }
+
+    /// Get the value as a string, e.g. for `UTF8_STRING`.
+    pub fn string(&self) -> Option<String> {
+        if self.format != 8 {
+            return None
+        }
+        String::from_utf8(self.value.clone()).ok()
+    }
+}
+
+/// The name of an atom is longer than the X protocol allows (65535 bytes).
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct NameTooLong;
+
+impl fmt::Display for NameTooLong {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "the atom name is too long")
+    }
+}
+
+impl Error for NameTooLong {
+    fn description(&self) -> &str {
+        "The atom name is too long"
+    }
+}
+
+enum Request {
+    Atom(AtomCallback),
+    Property(PropertyCallback),
+    /// The atom of the name of a property, after which the property is read.
+    NamedProperty {
+        window: xcb_window_t,
+        type_: xcb_atom_t,
+        callback: PropertyCallback
+    }
+}
+
+struct Pending {
+    sequence: c_uint,
+    request: Request
+}
+
+struct State {
+    connection: *mut xcb_connection_t,
+    pending: Vec<Pending>
+}
+
+/// A connection to the XWayland server, driven by the event loop of the
+/// compositor.
+///
+/// Dropping it closes the connection, without calling the callbacks of the
+/// requests that didn't get a reply yet.
+pub struct Connection {
+    state: Box<State>,
+    source: *mut wl_event_source
+}
+
+impl Connection {
+    /// Connect to the X server with the display name (e.g. `":1"`).
+    ///
+    /// Returns `None` if the connection couldn't be opened.
+    pub fn open(compositor: &Compositor, display_name: &str) -> Option<Self> {
+        unsafe {
+            let display_name = safe_as_cstring(display_name);
+            let connection = xcb_connect(display_name.as_ptr(), ptr::null_mut());
+            if xcb_connection_has_error(connection) != 0 {
+                wlr_log!(WLR_ERROR, "Could not connect to the X server");
+                xcb_disconnect(connection);
+                return None
+            }
+            let mut state = Box::new(State { connection,
+                                             pending: Vec::new() });
+            let source = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                       wl_event_loop_add_fd,
+                                       compositor.event_loop,
+                                       xcb_get_file_descriptor(connection),
+                                       WL_EVENT_READABLE,
+                                       connection_notify,
+                                       &mut *state as *mut State as *mut c_void);
+            if source.is_null() {
+                wlr_log!(WLR_ERROR, "Could not add the X connection to the event loop");
+                xcb_disconnect(connection);
+                return None
+            }
+            Some(Connection { state, source })
+        }
+    }
+
+    /// Get the atom with the name, creating it if it doesn't exist yet.
+    ///
+    /// Fails without calling the callback if the name is too long to send.
+    pub fn intern_atom(&mut self, name: &str, callback: AtomCallback) -> Result<(), NameTooLong> {
+        unsafe { self.state.intern_atom(name, Request::Atom(callback)) }
+    }
+
+    /// Read the property of the window, if it has the type (or any type
+    /// with `GET_PROPERTY_TYPE_ANY`).
+    pub fn get_property(&mut self,
+                        window: xcb_window_t,
+                        property: xcb_atom_t,
+                        type_: xcb_atom_t,
+                        callback: PropertyCallback) {
+        unsafe { self.state.get_property(window, property, type_, callback) }
+    }
+
+    /// Read the property with the name of the window, interning the atom of
+    /// the name first.
+    ///
+    /// Fails without calling the callback if the name is too long to send.
+    pub fn get_property_named(&mut self,
+                              window: xcb_window_t,
+                              name: &str,
+                              type_: xcb_atom_t,
+                              callback: PropertyCallback)
+                              -> Result<(), NameTooLong> {
+        unsafe {
+            self.state.intern_atom(name, Request::NamedProperty { window, type_, callback })
+        }
+    }
+}
+
+impl fmt::Debug for Connection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Connection")
+         .field("connection", &self.state.connection)
+         .field("pending", &self.state.pending.len())
+         .finish()
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        unsafe {
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_event_source_remove, self.source);
+            xcb_disconnect(self.state.connection)
+        }
+    }
+}
+
+impl State {
+    unsafe fn intern_atom(&mut self, name: &str, request: Request) -> Result<(), NameTooLong> {
+        if name.len() > u16::max_value() as usize {
+            return Err(NameTooLong)
+        }
+        let cookie = xcb_intern_atom(self.connection,
+                                     0,
+                                     name.len() as u16,
+                                     name.as_ptr() as *const c_char);
+        self.send(cookie, request);
+        Ok(())
+    }
+
+    unsafe fn get_property(&mut self,
+                           window: xcb_window_t,
+                           property: xcb_atom_t,
+                           type_: xcb_atom_t,
+                           callback: PropertyCallback) {
+        let cookie = xcb_get_property(self.connection,
+                                      0,
+                                      window,
+                                      property,
+                                      type_,
+                                      0,
+                                      MAX_PROPERTY_LENGTH);
+        self.send(cookie, Request::Property(callback))
+    }
+
+    unsafe fn send(&mut self, cookie: xcb_cookie_t, request: Request) {
+        self.pending.push(Pending { sequence: cookie.sequence, request });
+        xcb_flush(self.connection);
+    }
+
+    /// Read what the server sent, and answer the requests that got a reply.
+    unsafe fn dispatch(&mut self) {
+        // NOTE Polling for events is what reads from the socket. No events
+        // are selected, so there's nothing of interest in them.
+        loop {
+            let event = xcb_poll_for_event(self.connection);
+            if event.is_null() {
+                break
+            }
+            libc::free(event);
+        }
+        if xcb_connection_has_error(self.connection) != 0 {
+            wlr_log!(WLR_ERROR, "Lost the connection to the X server");
+            for pending in mem::replace(&mut self.pending, Vec::new()) {
+                pending.request.fail()
+            }
+            return
+        }
+        let mut index = 0;
+        while index < self.pending.len() {
+            let mut reply = ptr::null_mut();
+            let mut error = ptr::null_mut();
+            if xcb_poll_for_reply(self.connection,
+                                  self.pending[index].sequence,
+                                  &mut reply,
+                                  &mut error) == 0 {
+                index += 1;
+                continue
+            }
+            libc::free(error);
+            let pending = self.pending.remove(index);
+            if reply.is_null() {
+                pending.request.fail();
+                continue
+            }
+            match pending.request {
+                Request::Atom(callback) => {
+                    callback(Some((*(reply as *mut xcb_intern_atom_reply_t)).atom))
+                },
+                Request::Property(callback) => {
+                    callback(property(reply as *mut xcb_get_property_reply_t))
+                },
+                Request::NamedProperty { window, type_, callback } => {
+                    let atom = (*(reply as *mut xcb_intern_atom_reply_t)).atom;
+                    self.get_property(window, atom, type_, callback)
+                }
+            }
+            libc::free(reply);
+        }
+    }
+}
+
+impl Request {
+    fn fail(self) {
+        match self {
+            Request::Atom(callback) => callback(None),
+            Request::Property(callback) | Request::NamedProperty { callback, .. } => callback(None)
+        }
+    }
+}
+
+/// Copy the value out of the reply, if the window has the property.
+unsafe fn property(reply: *mut xcb_get_property_reply_t) -> Option<Property> {
+    // NOTE `None` is returned as the type of properties that aren't set.
+    if (*reply).type_ == 0 {
+        return None
+    }
+    let length = xcb_get_property_value_length(reply).max(0) as usize;
+    let value = xcb_get_property_value(reply) as *const u8;
+    let value = if value.is_null() || length == 0 {
+        Vec::new()
+    } else {
+        slice::from_raw_parts(value, length).to_vec()
+    };
+    Some(Property { type_: (*reply).type_,
+                    format: (*reply).format,
+                    value })
+}
+
+unsafe extern "C" fn connection_notify(_fd: c_int, _mask: u32, data: *mut c_void) -> c_int {
+    let state = &mut *(data as *mut State);
+    let res = panic::catch_unwind(panic::AssertUnwindSafe(|| state.dispatch()));
+    utils::handle_unwind(res);
+    0
+}
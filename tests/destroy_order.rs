@@ -0,0 +1,107 @@
+//! The order destroy callbacks run in, as documented on
+//! `utils::Handle::on_destroy`.
+//!
+//! Run with `cargo test --features testing --test destroy_order`.
+
+extern crate wlroots;
+
+use std::{cell::RefCell, time::Duration};
+
+use wlroots::{backend::Backend, compositor, output, surface, testing::{self, Harness},
+              utils::HandleErr};
+
+thread_local! {
+    /// What happened to the resources, in order.
+    static EVENTS: RefCell<Vec<&'static str>> = RefCell::new(Vec::new());
+    /// The surfaces the clients created, the latest last.
+    static SURFACES: RefCell<Vec<surface::Handle>> = RefCell::new(Vec::new());
+}
+
+fn log(event: &'static str) {
+    EVENTS.with(|events| events.borrow_mut().push(event))
+}
+
+fn take_events() -> Vec<&'static str> {
+    EVENTS.with(|events| events.borrow_mut().drain(..).collect())
+}
+
+struct TestOutput;
+
+impl output::Handler for TestOutput {
+    fn destroyed(&mut self, _: compositor::Handle, _: output::Handle) {
+        log("output destroyed")
+    }
+}
+
+fn output_added<'output>(_: compositor::Handle,
+                         builder: output::Builder<'output>)
+                         -> Option<output::BuilderResult<'output>> {
+    Some(builder.build_best_mode(TestOutput))
+}
+
+fn output_removed(_: compositor::Handle, _: output::Handle) {
+    log("output removed")
+}
+
+fn surface_added(_: compositor::Handle, surface: surface::Handle) {
+    SURFACES.with(|surfaces| surfaces.borrow_mut().push(surface))
+}
+
+fn remove_output(harness: &mut Harness, output: &output::Handle) {
+    compositor::handle().expect("The compositor is not running")
+                        .run(|compositor| match *compositor.backend() {
+                                 Backend::Headless(ref headless) => {
+                                     headless.remove_output(output)
+                                 },
+                                 _ => false
+                             })
+                        .expect("Could not remove the output");
+    harness.dispatch(Duration::from_millis(0));
+}
+
+/// The callbacks of an output run after its handler and the output manager
+/// were told, in the order they were registered, and can't use the output.
+fn output_callbacks(harness: &mut Harness) {
+    let output = harness.add_output(800, 600).expect("Could not add an output");
+    let upgraded = output.clone();
+    output.on_destroy(move || match upgraded.run(|_| ()) {
+                          Err(HandleErr::AlreadyDropped) => log("first callback"),
+                          _ => log("first callback could upgrade the output")
+                      })
+          .unwrap();
+    output.on_destroy(|| log("second callback")).unwrap();
+    remove_output(harness, &output);
+    assert_eq!(take_events(),
+               ["output destroyed", "output removed", "first callback", "second callback"]);
+    assert_eq!(output.on_destroy(|| log("late callback")),
+               Err(HandleErr::AlreadyDropped));
+    assert!(take_events().is_empty());
+}
+
+/// The callbacks of a surface run once its client destroys it, and only then.
+fn surface_callbacks(harness: &mut Harness) {
+    let mut client = harness.connect();
+    let surface = client.create_surface(harness, 100, 100, 0xff_ff_00_00);
+    let handle = SURFACES.with(|surfaces| surfaces.borrow().last().cloned())
+                         .expect("The surface was not announced");
+    handle.on_destroy(|| log("surface callback")).unwrap();
+    client.roundtrip(harness);
+    assert!(take_events().is_empty());
+    surface.surface.destroy();
+    client.roundtrip(harness);
+    assert_eq!(take_events(), ["surface callback"]);
+    assert_eq!(handle.run(|_| ()).err(), Some(HandleErr::AlreadyDropped));
+}
+
+fn main() {
+    let output_builder = output::manager::Builder::default().output_added(output_added)
+                                                            .output_removed(output_removed);
+    let events = compositor::EventBuilder::default().surface_added(surface_added);
+    let builder = compositor::Builder::new().wl_shm(true)
+                                            .output_manager(output_builder)
+                                            .compositor_events(events);
+    testing::run(builder, (), |harness| {
+        output_callbacks(harness);
+        surface_callbacks(harness);
+    });
+}
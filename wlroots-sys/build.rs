@@ -10,8 +10,9 @@ use std::process::Command;
 
 fn main() {
     meson();
-    let protocol_header_path =
-        generate_protocol_headers().expect("Could not generate header files for wayland protocols");
+    let protocol_header_path = generate_protocol_headers().unwrap_or_else(|err| {
+        panic!("Could not generate header files for wayland protocols: {}", err)
+    });
     let target_dir = env::var("OUT_DIR").expect("$OUT_DIR not set!");
     let mut builder = bindgen::builder()
         .derive_debug(true)
@@ -21,9 +22,12 @@ fn main() {
         .whitelisted_type(r"^wlr_.*$")
         .whitelisted_type(r"^xkb_.*$")
         .whitelisted_type(r"^XKB_.*$")
+        .whitelisted_type(r"^libinput_config_.*$")
         .whitelisted_function(r"^_?pixman_.*$")
         .whitelisted_function(r"^_?wlr_.*$")
         .whitelisted_function(r"^xkb_.*$")
+        .whitelisted_function(r"^libinput_device_config_accel_.*$")
+        .whitelisted_function(r"^libinput_device_config_tap_.*$")
         .ctypes_prefix("libc")
         .clang_arg("-Iwlroots/include")
         .clang_arg("-Iwlroots/include/wlr")
@@ -90,6 +94,8 @@ fn main() {
 
     link_optional_libs();
 
+    println!("cargo:rustc-env=WLROOTS_VERSION={}", wlroots_version());
+
     if !cfg!(feature = "static") {
         println!("cargo:rustc-link-lib=dylib=wlroots");
         println!("cargo:rustc-link-search=native=/usr/local/lib");
@@ -153,52 +159,159 @@ fn generate_protocol_headers() -> io::Result<PathBuf> {
     let output_dir_str = env::var("OUT_DIR").unwrap();
     let out_path: PathBuf = format!("{}/wayland-protocols", output_dir_str).into();
     fs::create_dir(&out_path).ok();
-    let protocols_prefix = pkg_config::get_variable("wayland-protocols", "prefix").unwrap();
-    let protocols = fs::read_dir(format!("{}/share/wayland-protocols/stable", protocols_prefix))?
-        .chain(fs::read_dir(format!("{}/share/wayland-protocols/unstable", protocols_prefix))?);
+    let protocols_dir = protocols_dir()?;
+    let protocols = fs::read_dir(format!("{}/stable", protocols_dir))?
+        .chain(fs::read_dir(format!("{}/unstable", protocols_dir))?);
     for entry in protocols {
         let entry = entry?;
         for entry in fs::read_dir(entry.path())? {
-            let entry = entry?;
-            let path = entry.path();
-            let mut filename = entry.file_name().into_string().unwrap();
-            if filename.ends_with(".xml") {
-                let new_length = filename.len() - 4;
-                filename.truncate(new_length);
-            }
-            filename.push_str("-protocol");
-            Command::new("wayland-scanner").arg("server-header")
-                                           .arg(path.clone())
-                                           .arg(format!("{}/{}.h",
-                                                        out_path.to_str().unwrap(),
-                                                        filename))
-                                           .status()
-                                           .unwrap();
+            generate_protocol_header(&entry?, &out_path);
+        }
+    }
+    // NOTE Some of the headers of wlroots (e.g. wlr_layer_shell.h) include
+    // the headers of the protocols it brings itself.
+    for entry in fs::read_dir("wlroots/protocol")? {
+        let entry = entry?;
+        if entry.file_name().to_string_lossy().ends_with(".xml") {
+            generate_protocol_header(&entry, &out_path);
         }
     }
     Ok(out_path)
 }
 
+/// Gets the directory wayland-protocols installed its XML files in.
+fn protocols_dir() -> io::Result<String> {
+    pkg_config::get_variable("wayland-protocols", "pkgdatadir").map_err(|err| {
+        io::Error::new(io::ErrorKind::NotFound,
+                       format!("Could not find the wayland-protocols XML files: {}", err))
+    })
+}
+
+fn generate_protocol_header(entry: &fs::DirEntry, out_path: &Path) {
+    let path = entry.path();
+    let mut filename = entry.file_name().into_string().unwrap();
+    if filename.ends_with(".xml") {
+        let new_length = filename.len() - 4;
+        filename.truncate(new_length);
+    }
+    filename.push_str("-protocol");
+    Command::new("wayland-scanner").arg("server-header")
+                                   .arg(path.clone())
+                                   .arg(format!("{}/{}.h", out_path.to_str().unwrap(), filename))
+                                   .status()
+                                   .unwrap();
+}
+
 fn generate_protocols() {
     let output_dir_str = env::var("OUT_DIR").unwrap();
 
     let output_dir = Path::new(&output_dir_str);
 
-    let protocols = &[("./wlroots/protocol/server-decoration.xml", "server_decoration")];
+    let protocols_dir = protocols_dir().unwrap_or_else(|err| panic!("{}", err));
+    let fullscreen_shell =
+        format!("{}/unstable/fullscreen-shell/fullscreen-shell-unstable-v1.xml",
+                protocols_dir);
+
+    let xdg_foreign = |version| {
+        format!("{}/unstable/xdg-foreign/xdg-foreign-unstable-v{}.xml", protocols_dir, version)
+    };
+
+    let session_lock =
+        format!("{}/staging/ext-session-lock/ext-session-lock-v1.xml", protocols_dir);
+
+    let content_type =
+        format!("{}/staging/content-type/content-type-v1.xml", protocols_dir);
+
+    let idle_notify =
+        format!("{}/staging/ext-idle-notify/ext-idle-notify-v1.xml", protocols_dir);
+
+    let shortcuts_inhibit =
+        format!("{}/unstable/keyboard-shortcuts-inhibit/\
+                 keyboard-shortcuts-inhibit-unstable-v1.xml",
+                protocols_dir);
+
+    let tearing_control =
+        format!("{}/staging/tearing-control/tearing-control-v1.xml", protocols_dir);
+
+    let xdg_activation =
+        format!("{}/staging/xdg-activation/xdg-activation-v1.xml", protocols_dir);
+
+    let foreign_toplevel_list =
+        format!("{}/staging/ext-foreign-toplevel-list/ext-foreign-toplevel-list-v1.xml",
+                protocols_dir);
 
-    for protocol in protocols {
-        wayland_scanner::generate_c_code(protocol.0,
+    let image_capture_source =
+        format!("{}/staging/ext-image-capture-source/ext-image-capture-source-v1.xml",
+                protocols_dir);
+
+    let mut protocols = vec![("./wlroots/protocol/server-decoration.xml".to_string(),
+                              "server_decoration")];
+    // NOTE Only the protocols of the extensions that are used are required,
+    // as the staging ones are only in recent versions of wayland-protocols.
+    if cfg!(feature = "content_type") {
+        protocols.push((content_type, "content_type"));
+    }
+    if cfg!(feature = "fullscreen_shell") {
+        protocols.push((fullscreen_shell, "fullscreen_shell"));
+    }
+    if cfg!(feature = "xdg_foreign") {
+        protocols.push((xdg_foreign(1), "xdg_foreign_v1"));
+        protocols.push((xdg_foreign(2), "xdg_foreign_v2"));
+    }
+    if cfg!(feature = "idle_notify") {
+        protocols.push((idle_notify, "idle_notify"));
+    }
+    if cfg!(feature = "shortcuts_inhibit") {
+        protocols.push((shortcuts_inhibit, "keyboard_shortcuts_inhibit"));
+    }
+    if cfg!(feature = "tearing_control") {
+        protocols.push((tearing_control, "tearing_control"));
+    }
+    if cfg!(feature = "xdg_activation") {
+        protocols.push((xdg_activation, "xdg_activation"));
+    }
+    if cfg!(feature = "session_lock") {
+        protocols.push((session_lock, "session_lock"));
+    }
+    if cfg!(feature = "foreign_toplevel_list") {
+        protocols.push((foreign_toplevel_list, "foreign_toplevel_list"));
+    }
+    if cfg!(feature = "image_capture_source") {
+        protocols.push((image_capture_source, "image_capture_source"));
+    }
+
+    for protocol in &protocols {
+        wayland_scanner::generate_c_code(&protocol.0,
                                          output_dir.join(format!("{}_server_api.rs", protocol.1)),
                                          wayland_scanner::Side::Server);
-        wayland_scanner::generate_c_code(protocol.0,
+        wayland_scanner::generate_c_code(&protocol.0,
                                          output_dir.join(format!("{}_client_api.rs", protocol.1)),
                                          wayland_scanner::Side::Client);
-        wayland_scanner::generate_c_interfaces(protocol.0,
+        wayland_scanner::generate_c_interfaces(&protocol.0,
                                                output_dir.join(format!("{}_interfaces.rs",
                                                                        protocol.1)));
     }
 }
 
+/// Gets the version of wlroots that is linked against: the one pkg-config
+/// finds, or the one in the submodule when linking statically.
+///
+/// It's "0.0.0" if it can't be found out.
+fn wlroots_version() -> String {
+    let version = if cfg!(feature = "static") {
+        fs::read_to_string("wlroots/meson.build").ok().and_then(|meson| {
+            let start = meson.find("version:")?;
+            meson[start..].split('\'').nth(1).map(String::from)
+        })
+    } else {
+        pkg_config::Config::new().cargo_metadata(false)
+                                 .probe("wlroots")
+                                 .ok()
+                                 .map(|library| library.version)
+    };
+    version.unwrap_or_else(|| "0.0.0".into())
+}
+
 fn link_optional_libs() {
     if cfg!(feature = "libcap") && pkg_config::probe_library("libcap").is_ok() {
         println!("cargo:rustc-link-lib=dylib=cap");
@@ -8,6 +8,9 @@ pub extern crate wayland_sys;
 
 pub use wayland_sys::{*, pid_t, gid_t, uid_t, server::{self, WAYLAND_SERVER_HANDLE}};
 
+/// The version of wlroots this was built against, e.g. "0.2.0".
+pub const WLROOTS_VERSION: &str = env!("WLROOTS_VERSION");
+
 #[allow(non_camel_case_types, non_snake_case, non_upper_case_globals)]
 mod generated {
     use libc;
@@ -35,6 +38,244 @@ mod generated {
                 include!(concat!(env!("OUT_DIR"), "/server_decoration_server_api.rs"));
             }
         }
+
+        #[cfg(feature = "fullscreen_shell")]
+        pub mod fullscreen_shell {
+            #![allow(unused_imports)]
+            #![allow(unused_variables)]
+            mod c_interfaces {
+                use wayland_server::sys::protocol_interfaces::{wl_output_interface,
+                                                               wl_surface_interface};
+                include!(concat!(env!("OUT_DIR"), "/fullscreen_shell_interfaces.rs"));
+            }
+
+            pub mod server {
+                pub(crate) use wayland_server::{NewResource, Resource};
+                pub(crate) use wayland_commons::{AnonymousObject, Interface, MessageGroup,
+                                                 wire::{Argument, ArgumentType, Message, MessageDesc},
+                                                 map::{Object, ObjectMetadata}};
+                pub(crate) use wayland_sys as sys;
+                use wayland_server::{*, protocol::{wl_output, wl_surface}};
+                use wayland_sys::common::{wl_interface, wl_argument};
+                include!(concat!(env!("OUT_DIR"), "/fullscreen_shell_server_api.rs"));
+            }
+        }
+
+        #[cfg(feature = "xdg_foreign")]
+        pub mod xdg_foreign_v1 {
+            #![allow(unused_imports)]
+            #![allow(unused_variables)]
+            mod c_interfaces {
+                use wayland_server::sys::protocol_interfaces::wl_surface_interface;
+                include!(concat!(env!("OUT_DIR"), "/xdg_foreign_v1_interfaces.rs"));
+            }
+
+            pub mod server {
+                pub(crate) use wayland_server::{NewResource, Resource};
+                pub(crate) use wayland_commons::{AnonymousObject, Interface, MessageGroup,
+                                                 wire::{Argument, ArgumentType, Message, MessageDesc},
+                                                 map::{Object, ObjectMetadata}};
+                pub(crate) use wayland_sys as sys;
+                use wayland_server::{*, protocol::wl_surface};
+                use wayland_sys::common::{wl_interface, wl_argument};
+                include!(concat!(env!("OUT_DIR"), "/xdg_foreign_v1_server_api.rs"));
+            }
+        }
+
+        #[cfg(feature = "xdg_foreign")]
+        pub mod xdg_foreign_v2 {
+            #![allow(unused_imports)]
+            #![allow(unused_variables)]
+            mod c_interfaces {
+                use wayland_server::sys::protocol_interfaces::wl_surface_interface;
+                include!(concat!(env!("OUT_DIR"), "/xdg_foreign_v2_interfaces.rs"));
+            }
+
+            pub mod server {
+                pub(crate) use wayland_server::{NewResource, Resource};
+                pub(crate) use wayland_commons::{AnonymousObject, Interface, MessageGroup,
+                                                 wire::{Argument, ArgumentType, Message, MessageDesc},
+                                                 map::{Object, ObjectMetadata}};
+                pub(crate) use wayland_sys as sys;
+                use wayland_server::{*, protocol::wl_surface};
+                use wayland_sys::common::{wl_interface, wl_argument};
+                include!(concat!(env!("OUT_DIR"), "/xdg_foreign_v2_server_api.rs"));
+            }
+        }
+
+        #[cfg(feature = "session_lock")]
+        pub mod session_lock {
+            #![allow(unused_imports)]
+            #![allow(unused_variables)]
+            mod c_interfaces {
+                use wayland_server::sys::protocol_interfaces::{wl_output_interface,
+                                                               wl_surface_interface};
+                include!(concat!(env!("OUT_DIR"), "/session_lock_interfaces.rs"));
+            }
+
+            pub mod server {
+                pub(crate) use wayland_server::{NewResource, Resource};
+                pub(crate) use wayland_commons::{AnonymousObject, Interface, MessageGroup,
+                                                 wire::{Argument, ArgumentType, Message, MessageDesc},
+                                                 map::{Object, ObjectMetadata}};
+                pub(crate) use wayland_sys as sys;
+                use wayland_server::{*, protocol::{wl_output, wl_surface}};
+                use wayland_sys::common::{wl_interface, wl_argument};
+                include!(concat!(env!("OUT_DIR"), "/session_lock_server_api.rs"));
+            }
+        }
+
+        #[cfg(feature = "idle_notify")]
+        pub mod idle_notify {
+            #![allow(unused_imports)]
+            #![allow(unused_variables)]
+            mod c_interfaces {
+                use wayland_server::sys::protocol_interfaces::wl_seat_interface;
+                include!(concat!(env!("OUT_DIR"), "/idle_notify_interfaces.rs"));
+            }
+
+            pub mod server {
+                pub(crate) use wayland_server::{NewResource, Resource};
+                pub(crate) use wayland_commons::{AnonymousObject, Interface, MessageGroup,
+                                                 wire::{Argument, ArgumentType, Message, MessageDesc},
+                                                 map::{Object, ObjectMetadata}};
+                pub(crate) use wayland_sys as sys;
+                use wayland_server::{*, protocol::wl_seat};
+                use wayland_sys::common::{wl_interface, wl_argument};
+                include!(concat!(env!("OUT_DIR"), "/idle_notify_server_api.rs"));
+            }
+        }
+
+        #[cfg(feature = "shortcuts_inhibit")]
+        pub mod keyboard_shortcuts_inhibit {
+            #![allow(unused_imports)]
+            #![allow(unused_variables)]
+            mod c_interfaces {
+                use wayland_server::sys::protocol_interfaces::{wl_seat_interface,
+                                                               wl_surface_interface};
+                include!(concat!(env!("OUT_DIR"), "/keyboard_shortcuts_inhibit_interfaces.rs"));
+            }
+
+            pub mod server {
+                pub(crate) use wayland_server::{NewResource, Resource};
+                pub(crate) use wayland_commons::{AnonymousObject, Interface, MessageGroup,
+                                                 wire::{Argument, ArgumentType, Message, MessageDesc},
+                                                 map::{Object, ObjectMetadata}};
+                pub(crate) use wayland_sys as sys;
+                use wayland_server::{*, protocol::{wl_seat, wl_surface}};
+                use wayland_sys::common::{wl_interface, wl_argument};
+                include!(concat!(env!("OUT_DIR"), "/keyboard_shortcuts_inhibit_server_api.rs"));
+            }
+        }
+
+        #[cfg(feature = "content_type")]
+        pub mod content_type {
+            #![allow(unused_imports)]
+            #![allow(unused_variables)]
+            mod c_interfaces {
+                use wayland_server::sys::protocol_interfaces::wl_surface_interface;
+                include!(concat!(env!("OUT_DIR"), "/content_type_interfaces.rs"));
+            }
+
+            pub mod server {
+                pub(crate) use wayland_server::{NewResource, Resource};
+                pub(crate) use wayland_commons::{AnonymousObject, Interface, MessageGroup,
+                                                 wire::{Argument, ArgumentType, Message, MessageDesc},
+                                                 map::{Object, ObjectMetadata}};
+                pub(crate) use wayland_sys as sys;
+                use wayland_server::{*, protocol::wl_surface};
+                use wayland_sys::common::{wl_interface, wl_argument};
+                include!(concat!(env!("OUT_DIR"), "/content_type_server_api.rs"));
+            }
+        }
+
+        #[cfg(feature = "tearing_control")]
+        pub mod tearing_control {
+            #![allow(unused_imports)]
+            #![allow(unused_variables)]
+            mod c_interfaces {
+                use wayland_server::sys::protocol_interfaces::wl_surface_interface;
+                include!(concat!(env!("OUT_DIR"), "/tearing_control_interfaces.rs"));
+            }
+
+            pub mod server {
+                pub(crate) use wayland_server::{NewResource, Resource};
+                pub(crate) use wayland_commons::{AnonymousObject, Interface, MessageGroup,
+                                                 wire::{Argument, ArgumentType, Message, MessageDesc},
+                                                 map::{Object, ObjectMetadata}};
+                pub(crate) use wayland_sys as sys;
+                use wayland_server::{*, protocol::wl_surface};
+                use wayland_sys::common::{wl_interface, wl_argument};
+                include!(concat!(env!("OUT_DIR"), "/tearing_control_server_api.rs"));
+            }
+        }
+
+        #[cfg(feature = "xdg_activation")]
+        pub mod xdg_activation {
+            #![allow(unused_imports)]
+            #![allow(unused_variables)]
+            mod c_interfaces {
+                use wayland_server::sys::protocol_interfaces::{wl_seat_interface,
+                                                               wl_surface_interface};
+                include!(concat!(env!("OUT_DIR"), "/xdg_activation_interfaces.rs"));
+            }
+
+            pub mod server {
+                pub(crate) use wayland_server::{NewResource, Resource};
+                pub(crate) use wayland_commons::{AnonymousObject, Interface, MessageGroup,
+                                                 wire::{Argument, ArgumentType, Message, MessageDesc},
+                                                 map::{Object, ObjectMetadata}};
+                pub(crate) use wayland_sys as sys;
+                use wayland_server::{*, protocol::{wl_seat, wl_surface}};
+                use wayland_sys::common::{wl_interface, wl_argument};
+                include!(concat!(env!("OUT_DIR"), "/xdg_activation_server_api.rs"));
+            }
+        }
+
+        #[cfg(feature = "foreign_toplevel_list")]
+        pub mod foreign_toplevel_list {
+            #![allow(unused_imports)]
+            #![allow(unused_variables)]
+            // NOTE The capture sources of toplevels refer to these.
+            pub(crate) mod c_interfaces {
+                include!(concat!(env!("OUT_DIR"), "/foreign_toplevel_list_interfaces.rs"));
+            }
+
+            pub mod server {
+                pub(crate) use wayland_server::{NewResource, Resource};
+                pub(crate) use wayland_commons::{AnonymousObject, Interface, MessageGroup,
+                                                 wire::{Argument, ArgumentType, Message, MessageDesc},
+                                                 map::{Object, ObjectMetadata}};
+                pub(crate) use wayland_sys as sys;
+                use wayland_server::*;
+                use wayland_sys::common::{wl_interface, wl_argument};
+                include!(concat!(env!("OUT_DIR"), "/foreign_toplevel_list_server_api.rs"));
+            }
+        }
+
+        #[cfg(feature = "image_capture_source")]
+        pub mod image_capture_source {
+            #![allow(unused_imports)]
+            #![allow(unused_variables)]
+            mod c_interfaces {
+                use wayland_server::sys::protocol_interfaces::wl_output_interface;
+                use super::super::foreign_toplevel_list::c_interfaces::{
+                    ext_foreign_toplevel_handle_v1_interface};
+                include!(concat!(env!("OUT_DIR"), "/image_capture_source_interfaces.rs"));
+            }
+
+            pub mod server {
+                pub(crate) use wayland_server::{NewResource, Resource};
+                pub(crate) use wayland_commons::{AnonymousObject, Interface, MessageGroup,
+                                                 wire::{Argument, ArgumentType, Message, MessageDesc},
+                                                 map::{Object, ObjectMetadata}};
+                pub(crate) use wayland_sys as sys;
+                use wayland_server::{*, protocol::wl_output};
+                use wayland_sys::common::{wl_interface, wl_argument};
+                use super::super::foreign_toplevel_list::server::ext_foreign_toplevel_handle_v1;
+                include!(concat!(env!("OUT_DIR"), "/image_capture_source_server_api.rs"));
+            }
+        }
     }
 }
 pub use self::generated::*;
@@ -22,6 +22,8 @@
 #include <wlr/backend/x11.h>
 #include <wlr/backend/session/interface.h>
 
+#include <wlr/interfaces/wlr_input_device.h>
+
 #include <wlr/render/wlr_renderer.h>
 #include <wlr/render/egl.h>
 #include <wlr/render/gles2.h>
@@ -31,12 +33,16 @@
 #include <wlr/types/wlr_box.h>
 // NOTE this is stable, but it relies on wlr_box.h which isn't
 #include <wlr/types/wlr_matrix.h>
+#include <wlr/types/wlr_buffer.h>
 #include <wlr/types/wlr_compositor.h>
 #include <wlr/types/wlr_cursor.h>
 #include <wlr/types/wlr_data_device.h>
 #include <wlr/types/wlr_gamma_control.h>
+#include <wlr/types/wlr_idle.h>
 #include <wlr/types/wlr_input_device.h>
+#include <wlr/types/wlr_input_inhibitor.h>
 #include <wlr/types/wlr_keyboard.h>
+#include <wlr/types/wlr_layer_shell.h>
 #include <wlr/types/wlr_output.h>
 #include <wlr/types/wlr_output_layout.h>
 #include <wlr/types/wlr_output_damage.h>